@@ -0,0 +1,97 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Node.js bindings for the wassette lifecycle API. All methods return promises and run on a
+//! Tokio runtime, so they don't block the event loop.
+//!
+//! ```js
+//! const { createLifecycleManager } = require("@microsoft/wassette");
+//!
+//! const manager = await createLifecycleManager("./plugins");
+//! const { id } = await manager.loadComponent("oci://ghcr.io/yoshuawuyts/time:latest");
+//! console.log(await manager.executeComponentCall(id, "get-current-time", "{}"));
+//! ```
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use wassette::{LoadResult, UnloadResult};
+
+fn to_napi_err(error: anyhow::Error) -> Error {
+    Error::from_reason(format!("{error:#}"))
+}
+
+/// The outcome of loading a component
+#[napi(object)]
+pub struct LoadedComponent {
+    /// ID of the component
+    pub id: String,
+    /// Whether the component replaced a loaded component with the same ID
+    pub replaced: bool,
+}
+
+/// Loads WebAssembly components and calls their tools in a sandbox
+#[napi]
+pub struct LifecycleManager {
+    inner: wassette::LifecycleManager,
+}
+
+/// Creates a lifecycle manager that keeps its components in `pluginDir`
+#[napi]
+pub async fn create_lifecycle_manager(plugin_dir: String) -> Result<LifecycleManager> {
+    let inner = wassette::LifecycleManager::new(plugin_dir)
+        .await
+        .map_err(to_napi_err)?;
+    Ok(LifecycleManager { inner })
+}
+
+#[napi]
+impl LifecycleManager {
+    /// Loads the component at `uri`
+    #[napi]
+    pub async fn load_component(&self, uri: String) -> Result<LoadedComponent> {
+        let (id, result) = self.inner.load_component(&uri).await.map_err(to_napi_err)?;
+        Ok(LoadedComponent {
+            id,
+            replaced: matches!(result, LoadResult::Replaced(_)),
+        })
+    }
+
+    /// Unloads a component and removes its files from the plugin directory. Resolves to whether
+    /// there was a component to unload.
+    #[napi]
+    pub async fn unload_component(&self, component_id: String) -> Result<bool> {
+        self.inner
+            .unload_component(&component_id)
+            .await
+            .map(|result| result == UnloadResult::Removed)
+            .map_err(to_napi_err)
+    }
+
+    /// Returns the IDs of the loaded components
+    #[napi]
+    pub async fn list_components(&self) -> Vec<String> {
+        self.inner.list_components().await
+    }
+
+    /// Returns the tools of all loaded components as objects with a `name`, `description` and
+    /// `inputSchema`
+    #[napi]
+    pub async fn list_tools(&self) -> Result<serde_json::Value> {
+        serde_json::to_value(self.inner.list_tools().await).map_err(|e| to_napi_err(e.into()))
+    }
+
+    /// Calls a tool of a component with arguments given as a JSON object and returns its result
+    /// as JSON
+    #[napi]
+    pub async fn execute_component_call(
+        &self,
+        component_id: String,
+        function_name: String,
+        parameters: String,
+    ) -> Result<String> {
+        self.inner
+            .execute_component_call(&component_id, &function_name, &parameters)
+            .await
+            .map_err(to_napi_err)
+    }
+}
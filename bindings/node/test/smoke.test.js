@@ -0,0 +1,27 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+const assert = require("node:assert");
+const fs = require("node:fs");
+const os = require("node:os");
+const path = require("node:path");
+const test = require("node:test");
+
+const { createLifecycleManager } = require("..");
+
+function pluginDir() {
+  return fs.mkdtempSync(path.join(os.tmpdir(), "wassette-node-"));
+}
+
+test("an empty plugin directory has no components", async () => {
+  const manager = await createLifecycleManager(pluginDir());
+  assert.deepStrictEqual(await manager.listComponents(), []);
+  assert.ok(Array.isArray(await manager.listTools()));
+});
+
+test("loading a missing component rejects", async () => {
+  const dir = pluginDir();
+  const manager = await createLifecycleManager(dir);
+  await assert.rejects(manager.loadComponent(`file://${dir}/missing.wasm`));
+  assert.strictEqual(await manager.unloadComponent("missing"), false);
+});
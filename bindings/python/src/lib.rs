@@ -0,0 +1,95 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Python bindings for the wassette lifecycle API.
+//!
+//! ```python
+//! import json, wassette
+//!
+//! manager = wassette.LifecycleManager("./plugins")
+//! component_id, _ = manager.load_component("oci://ghcr.io/yoshuawuyts/time:latest")
+//! print(manager.execute_component_call(component_id, "get-current-time", "{}"))
+//! ```
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use wassette::blocking;
+use wassette::{LoadResult, UnloadResult};
+
+fn to_py_err(error: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{error:#}"))
+}
+
+/// Loads WebAssembly components and calls their tools in a sandbox. Calls release the GIL while
+/// they run.
+#[pyclass(module = "wassette", frozen)]
+struct LifecycleManager {
+    inner: blocking::LifecycleManager,
+}
+
+#[pymethods]
+impl LifecycleManager {
+    /// Creates a lifecycle manager that keeps its components in `plugin_dir`
+    #[new]
+    fn new(py: Python<'_>, plugin_dir: std::path::PathBuf) -> PyResult<Self> {
+        let inner = py
+            .allow_threads(|| blocking::LifecycleManager::new(&plugin_dir))
+            .map_err(to_py_err)?;
+        Ok(Self { inner })
+    }
+
+    /// Loads the component at `uri` and returns its ID and whether it was `"new"` or
+    /// `"replaced"` a loaded component
+    fn load_component(&self, py: Python<'_>, uri: &str) -> PyResult<(String, &'static str)> {
+        let (id, result) = py
+            .allow_threads(|| self.inner.load_component(uri))
+            .map_err(to_py_err)?;
+        let result = match result {
+            LoadResult::New => "new",
+            LoadResult::Replaced(_) => "replaced",
+        };
+        Ok((id, result))
+    }
+
+    /// Unloads a component and removes its files from the plugin directory. Returns whether
+    /// there was a component to unload.
+    fn unload_component(&self, py: Python<'_>, component_id: &str) -> PyResult<bool> {
+        py.allow_threads(|| self.inner.unload_component(component_id))
+            .map(|result| result == UnloadResult::Removed)
+            .map_err(to_py_err)
+    }
+
+    /// Returns the IDs of the loaded components
+    fn list_components(&self, py: Python<'_>) -> Vec<String> {
+        py.allow_threads(|| self.inner.list_components())
+    }
+
+    /// Returns the tools of all loaded components as dicts with a `name`, `description` and
+    /// `inputSchema`
+    fn list_tools<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let tools = py.allow_threads(|| self.inner.list_tools());
+        Ok(pythonize::pythonize(py, &tools)?)
+    }
+
+    /// Calls a tool of a component with arguments given as a JSON object and returns its result
+    /// as JSON
+    fn execute_component_call(
+        &self,
+        py: Python<'_>,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+    ) -> PyResult<String> {
+        py.allow_threads(|| {
+            self.inner
+                .execute_component_call(component_id, function_name, parameters)
+        })
+        .map_err(to_py_err)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "wassette")]
+fn wassette_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<LifecycleManager>()
+}
@@ -0,0 +1,19 @@
+# Copyright (c) Microsoft Corporation.
+# Licensed under the MIT license.
+
+import pytest
+
+import wassette
+
+
+def test_empty_plugin_dir(tmp_path):
+    manager = wassette.LifecycleManager(tmp_path)
+    assert manager.list_components() == []
+    assert isinstance(manager.list_tools(), list)
+
+
+def test_load_missing_component_raises(tmp_path):
+    manager = wassette.LifecycleManager(tmp_path)
+    with pytest.raises(RuntimeError):
+        manager.load_component(f"file://{tmp_path}/missing.wasm")
+    assert not manager.unload_component("missing")
@@ -1,11 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT license.
 
-use std::sync::Arc;
-
 use anyhow::Result;
-use wasmtime::component::Component;
-use wasmtime::{Config, Engine};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -15,31 +11,22 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     });
 
-    let mut config = Config::new();
-    config.wasm_component_model(true);
-    config.async_support(true);
-    let engine = Arc::new(Engine::new(&config)?);
-
-    let component = Arc::new(Component::from_file(&engine, path)?);
+    let wasm = tokio::fs::read(path).await?;
+    let document = component2json::extract_tools_from_bytes(&wasm)?;
 
-    let schema = component2json::component_exports_to_json_schema(&component, &engine, true);
-    if let Some(arr) = schema["tools"].as_array() {
-        for t in arr {
-            let name = t["name"].as_str().unwrap_or("<unnamed>").to_string();
-            let description: Option<String> = t["description"].as_str().map(|s| s.to_string());
-            let input_schema = t["inputSchema"].clone(); // already a serde_json::Value
-            let output_schema = t["outputSchema"].clone(); // already a serde_json::Value
-
-            println!("{name}, {description:?}");
-            println!(
-                "input schema: {}",
-                serde_json::to_string_pretty(&input_schema)?
-            );
-            println!(
-                "output schema: {}",
-                serde_json::to_string_pretty(&output_schema)?
-            );
-        }
+    if let Some(world) = &document.world {
+        println!("world: {world}");
+    }
+    for tool in &document.tools {
+        println!("{}, {:?}", tool.name, tool.description);
+        println!(
+            "input schema: {}",
+            serde_json::to_string_pretty(&tool.input_schema)?
+        );
+        println!(
+            "output schema: {}",
+            serde_json::to_string_pretty(&tool.output_schema)?
+        );
     }
     Ok(())
 }
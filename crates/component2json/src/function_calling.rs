@@ -0,0 +1,336 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Emitters for the function-calling tool formats of LLM provider SDKs.
+//!
+//! These formats accept only a subset of JSON Schema, so schemas are rewritten into a flat form:
+//! `oneOf` becomes `anyOf` (or a list of types), `const` becomes a single value `enum`, tuples are
+//! described as arrays, and keywords such as `prefixItems` or `pattern` are dropped.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{json, Map, Value};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+use crate::{component_exports_to_tools, ToolSchema};
+
+/// Keywords that are copied unchanged into simplified schemas
+const KEPT_KEYWORDS: &[&str] = &["type", "description", "enum", "required", "default"];
+
+/// The tool format to emit schemas in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaFormat {
+    /// MCP tool definitions, as generated by [`component_exports_to_tools`]
+    #[default]
+    Mcp,
+    /// OpenAI `tools` entries of type `function`
+    OpenAi,
+    /// Anthropic tool-use definitions
+    Anthropic,
+}
+
+impl FromStr for SchemaFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mcp" => Ok(Self::Mcp),
+            "openai" => Ok(Self::OpenAi),
+            "anthropic" => Ok(Self::Anthropic),
+            other => Err(format!(
+                "unknown schema format '{other}', expected one of: mcp, openai, anthropic"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SchemaFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mcp => f.write_str("mcp"),
+            Self::OpenAi => f.write_str("openai"),
+            Self::Anthropic => f.write_str("anthropic"),
+        }
+    }
+}
+
+/// Rewrites a JSON schema into the flat subset accepted by function-calling APIs.
+pub fn simplify_schema(schema: &Value) -> Value {
+    let Some(obj) = schema.as_object() else {
+        return schema.clone();
+    };
+
+    let mut out = Map::new();
+    for key in KEPT_KEYWORDS {
+        if let Some(value) = obj.get(*key) {
+            out.insert(key.to_string(), value.clone());
+        }
+    }
+
+    if let Some(constant) = obj.get("const") {
+        if let Some(ty) = json_type(constant) {
+            out.entry("type").or_insert_with(|| json!(ty));
+        }
+        out.insert("enum".to_string(), json!([constant]));
+    }
+
+    if let Some(properties) = obj.get("properties").and_then(Value::as_object) {
+        let properties: Map<String, Value> = properties
+            .iter()
+            .map(|(name, property)| (name.clone(), simplify_schema(property)))
+            .collect();
+        out.insert("properties".to_string(), Value::Object(properties));
+    }
+
+    if let Some(items) = obj.get("items") {
+        out.insert("items".to_string(), simplify_schema(items));
+    } else if let Some(prefix_items) = obj.get("prefixItems").and_then(Value::as_array) {
+        let items: Vec<Value> = prefix_items.iter().map(simplify_schema).collect();
+        let description = format!(
+            "A tuple of exactly {} items, in this order: {}",
+            items.len(),
+            items
+                .iter()
+                .map(describe_type)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        out.insert("items".to_string(), merge_alternatives(dedup(items)));
+        append_description(&mut out, &description);
+    }
+
+    if let Some(branches) = obj
+        .get("oneOf")
+        .or_else(|| obj.get("anyOf"))
+        .and_then(Value::as_array)
+    {
+        let branches = dedup(branches.iter().map(simplify_schema).collect());
+        let merged = merge_alternatives(branches);
+        if let Value::Object(merged) = merged {
+            for (key, value) in merged {
+                if key == "description" {
+                    append_description(&mut out, value.as_str().unwrap_or_default());
+                } else {
+                    out.insert(key, value);
+                }
+            }
+        }
+    }
+
+    Value::Object(out)
+}
+
+/// Converts a tool definition into the given format.
+pub fn tool_schema_for_format(tool: &ToolSchema, format: SchemaFormat) -> Value {
+    let name = &tool.name;
+    let description = &tool.description;
+    let parameters = simplify_schema(&tool.input_schema);
+
+    match format {
+        SchemaFormat::Mcp => json!(tool),
+        SchemaFormat::OpenAi => json!({
+            "type": "function",
+            "function": {
+                "name": name,
+                "description": description,
+                "parameters": parameters
+            }
+        }),
+        SchemaFormat::Anthropic => json!({
+            "name": name,
+            "description": description,
+            "input_schema": parameters
+        }),
+    }
+}
+
+/// Given a component and a wasmtime engine, return the tool definitions of the component's
+/// exports in the given format.
+pub fn component_exports_to_function_schemas(
+    component: &Component,
+    engine: &Engine,
+    format: SchemaFormat,
+) -> Vec<Value> {
+    component_exports_to_tools(component, engine, format == SchemaFormat::Mcp)
+        .iter()
+        .map(|tool| tool_schema_for_format(&tool.schema, format))
+        .collect()
+}
+
+/// Combines alternative schemas, preferring a list of types over `anyOf` where possible
+fn merge_alternatives(mut branches: Vec<Value>) -> Value {
+    if branches.len() == 1 {
+        return branches.remove(0);
+    }
+
+    // Nullable values, i.e. `option<T>`
+    let null = json!({ "type": "null" });
+    if branches.len() == 2 && branches.contains(&null) {
+        let mut other = branches
+            .into_iter()
+            .find(|b| b != &null)
+            .unwrap_or_default();
+        if let Some(ty) = other.get("type").and_then(Value::as_str).map(String::from) {
+            other["type"] = json!([ty, "null"]);
+            return other;
+        }
+        return json!({ "anyOf": [other, null] });
+    }
+
+    // Alternatives that only differ by type, e.g. a u64 given as number or string
+    if branches
+        .iter()
+        .all(|b| b.as_object().is_some_and(|o| o.len() == 1))
+    {
+        if let Some(types) = branches
+            .iter()
+            .map(|b| b.get("type").and_then(Value::as_str))
+            .collect::<Option<Vec<_>>>()
+        {
+            return json!({ "type": types });
+        }
+    }
+
+    json!({ "anyOf": branches })
+}
+
+fn dedup(values: Vec<Value>) -> Vec<Value> {
+    let mut unique = Vec::with_capacity(values.len());
+    for value in values {
+        if !unique.contains(&value) {
+            unique.push(value);
+        }
+    }
+    unique
+}
+
+fn json_type(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::String(_) => Some("string"),
+        Value::Bool(_) => Some("boolean"),
+        Value::Number(_) => Some("number"),
+        _ => None,
+    }
+}
+
+fn describe_type(schema: &Value) -> String {
+    match schema.get("type") {
+        Some(Value::String(ty)) => ty.clone(),
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(" or "),
+        _ => "value".to_string(),
+    }
+}
+
+fn append_description(out: &mut Map<String, Value>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    let description = match out.get("description").and_then(Value::as_str) {
+        Some(existing) => format!("{existing}. {text}"),
+        None => text.to_string(),
+    };
+    out.insert("description".to_string(), json!(description));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_format_from_str() {
+        assert_eq!("openai".parse(), Ok(SchemaFormat::OpenAi));
+        assert_eq!("Anthropic".parse(), Ok(SchemaFormat::Anthropic));
+        assert_eq!("mcp".parse(), Ok(SchemaFormat::Mcp));
+        assert!("gemini".parse::<SchemaFormat>().is_err());
+        assert_eq!(SchemaFormat::OpenAi.to_string(), "openai");
+    }
+
+    #[test]
+    fn test_simplify_schema() {
+        let u64_schema = json!({
+            "anyOf": [
+                { "type": "number" },
+                { "type": "string", "pattern": "^[0-9]+$" }
+            ]
+        });
+        assert_eq!(
+            simplify_schema(&u64_schema),
+            json!({ "type": ["number", "string"] })
+        );
+
+        let option = json!({ "anyOf": [{ "type": "null" }, { "type": "string" }] });
+        assert_eq!(
+            simplify_schema(&option),
+            json!({ "type": ["string", "null"] })
+        );
+
+        let tuple = json!({
+            "type": "array",
+            "prefixItems": [{ "type": "string" }, { "type": "number" }],
+            "minItems": 2,
+            "maxItems": 2
+        });
+        assert_eq!(
+            simplify_schema(&tuple),
+            json!({
+                "type": "array",
+                "items": { "type": ["string", "number"] },
+                "description": "A tuple of exactly 2 items, in this order: string, number"
+            })
+        );
+
+        let variant = json!({
+            "oneOf": [
+                {
+                    "type": "object",
+                    "properties": { "tag": { "const": "none" } },
+                    "required": ["tag"]
+                },
+                {
+                    "type": "object",
+                    "properties": { "tag": { "const": "some" }, "val": { "type": "number" } },
+                    "required": ["tag", "val"]
+                }
+            ]
+        });
+        let simplified = simplify_schema(&variant);
+        assert!(simplified.get("oneOf").is_none());
+        assert_eq!(
+            simplified["anyOf"][1]["properties"]["tag"],
+            json!({ "type": "string", "enum": ["some"] })
+        );
+    }
+
+    #[test]
+    fn test_tool_schema_for_format() {
+        let raw = json!({
+            "name": "fetch",
+            "description": "Fetches a URL",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "url": { "type": "string" } },
+                "required": ["url"]
+            },
+            "outputSchema": { "type": "string" }
+        });
+        let tool: ToolSchema = serde_json::from_value(raw.clone()).unwrap();
+
+        let openai = tool_schema_for_format(&tool, SchemaFormat::OpenAi);
+        assert_eq!(openai["type"], "function");
+        assert_eq!(openai["function"]["name"], "fetch");
+        assert_eq!(openai["function"]["parameters"], raw["inputSchema"]);
+
+        let anthropic = tool_schema_for_format(&tool, SchemaFormat::Anthropic);
+        assert_eq!(anthropic["name"], "fetch");
+        assert_eq!(anthropic["description"], "Fetches a URL");
+        assert_eq!(anthropic["input_schema"], raw["inputSchema"]);
+
+        assert_eq!(tool_schema_for_format(&tool, SchemaFormat::Mcp), raw);
+    }
+}
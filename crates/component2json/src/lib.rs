@@ -3,12 +3,22 @@
 
 #![doc = include_str!("../README.md")]
 
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 use thiserror::Error;
 use wasmtime::component::types::{ComponentFunc, ComponentItem};
 use wasmtime::component::{Component, Type, Val};
 use wasmtime::Engine;
 
+mod function_calling;
+pub use function_calling::{
+    component_exports_to_function_schemas, simplify_schema, tool_schema_for_format, SchemaFormat,
+};
+
 /// Function identifier for tools, containing WIT package, WIT interface, and function names.
 #[derive(Debug, Clone, PartialEq)]
 pub struct FunctionIdentifier {
@@ -17,6 +27,84 @@ pub struct FunctionIdentifier {
     pub function_name: String,
 }
 
+impl FunctionIdentifier {
+    /// Returns the unsanitized, fully qualified name of the function, e.g.
+    /// `wasi:cli/run@0.2.0.run`. This is the name a tool would have without any normalization.
+    pub fn qualified_name(&self) -> String {
+        let mut name = String::new();
+        if let Some(pkg) = &self.package_name {
+            name.push_str(pkg);
+            name.push('/');
+        }
+        if let Some(iface) = &self.interface_name {
+            name.push_str(iface);
+            name.push('.');
+        }
+        name.push_str(&self.function_name);
+        name
+    }
+
+    /// Returns the parsed interface name if the function belongs to a WIT interface export such
+    /// as `wasi:cli/run@0.2.0`
+    pub fn interface(&self) -> Option<InterfaceName<'_>> {
+        self.interface_name
+            .as_deref()
+            .and_then(parse_interface_name)
+    }
+
+    /// Returns the `namespace:package` the function's interface belongs to, if any
+    pub fn package(&self) -> Option<String> {
+        self.interface()
+            .map(|iface| format!("{}:{}", iface.namespace, iface.package))
+    }
+
+    /// Returns the version of the function's interface package, if any
+    pub fn version(&self) -> Option<&str> {
+        self.interface().and_then(|iface| iface.version)
+    }
+}
+
+/// The parts of a fully qualified interface name like `wasi:cli/run@0.2.0`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterfaceName<'a> {
+    /// The package namespace, e.g. `wasi`
+    pub namespace: &'a str,
+    /// The package name, e.g. `cli`
+    pub package: &'a str,
+    /// The interface name, e.g. `run`
+    pub interface: &'a str,
+    /// The package version, e.g. `0.2.0`
+    pub version: Option<&'a str>,
+}
+
+impl InterfaceName<'_> {
+    /// Returns the interface name without its version, e.g. `wasi:cli/run`
+    pub fn unversioned(&self) -> String {
+        format!("{}:{}/{}", self.namespace, self.package, self.interface)
+    }
+}
+
+/// Parses an export name of the form `namespace:package/interface[@version]`. Returns `None` for
+/// plain (non-interface) export names.
+pub fn parse_interface_name(name: &str) -> Option<InterfaceName<'_>> {
+    let (path, version) = match name.split_once('@') {
+        Some((path, version)) if !version.is_empty() => (path, Some(version)),
+        Some(_) => return None,
+        None => (name, None),
+    };
+    let (namespace, rest) = path.split_once(':')?;
+    let (package, interface) = rest.rsplit_once('/')?;
+    if namespace.is_empty() || package.is_empty() || interface.is_empty() {
+        return None;
+    }
+    Some(InterfaceName {
+        namespace,
+        package,
+        interface,
+        version,
+    })
+}
+
 /// Metadata for a tool, including its identifier, normalized name, and JSON schema.
 #[derive(Debug, Clone)]
 pub struct ToolMetadata {
@@ -24,8 +112,161 @@ pub struct ToolMetadata {
     pub identifier: FunctionIdentifier,
     /// Normalized tool name that complies with MCP specification. "^[a-zA-Z0-9_-]{1,128}$"
     pub normalized_name: String,
-    /// JSON schema for the tool's function, including input and output types
-    pub schema: Value,
+    /// Tool definition for the function, including input and output types
+    pub schema: ToolSchema,
+    /// Set if the component's metadata marks the tool as deprecated
+    pub deprecation: Option<Deprecation>,
+    /// The plan variant of the function, which describes what a call would do without doing it.
+    /// Calls with [`PREVIEW_ARGUMENT`] set to `true` go to this function instead
+    pub plan: Option<FunctionIdentifier>,
+    /// Set if the component's metadata marks the tool as pure, so its results can be reused
+    pub memoization: Option<Memoization>,
+}
+
+/// Suffix of the exports that are the plan variant of another function, e.g. `delete-file-plan`
+/// for `delete-file`
+pub const PLAN_SUFFIX: &str = "-plan";
+
+/// Argument that tools with a plan variant get, asking for a dry-run description of what the call
+/// would do instead of doing it
+pub const PREVIEW_ARGUMENT: &str = "preview";
+
+/// Deprecation of a tool, declared in the component's tool metadata with `"deprecated": true` or
+/// with an object giving these details
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deprecation {
+    /// Tool that callers should use instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+    /// Date after which the tool may be removed, e.g. `2025-12-31`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sunset: Option<String>,
+    /// Further explanation for callers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Deprecation {
+    /// Reads the value of a tool's `deprecated` metadata, which is either a boolean or an object
+    fn from_metadata(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(deprecated) => deprecated.then(Self::default),
+            Value::Object(_) => serde_json::from_value(value.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns a sentence telling callers that the tool is deprecated and what to do about it
+    pub fn notice(&self, tool: &str) -> String {
+        let mut notice = format!("Tool '{tool}' is deprecated");
+        if let Some(sunset) = &self.sunset {
+            notice.push_str(&format!(" and may be removed after {sunset}"));
+        }
+        if let Some(replacement) = &self.replacement {
+            notice.push_str(&format!(", use '{replacement}' instead"));
+        }
+        notice.push('.');
+        if let Some(message) = &self.message {
+            notice.push(' ');
+            notice.push_str(message);
+        }
+        notice
+    }
+}
+
+/// Number of seconds the result of a pure tool is reused for unless its metadata says otherwise
+pub const DEFAULT_MEMOIZATION_TTL_SECS: u64 = 300;
+
+/// Memoization of a pure tool, whose result only depends on its arguments, declared in the
+/// component's tool metadata with `"pure": true` or with an object giving these details
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Memoization {
+    /// Number of seconds a result is reused for. Defaults to [`DEFAULT_MEMOIZATION_TTL_SECS`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+}
+
+impl Memoization {
+    /// Reads the value of a tool's `pure` metadata, which is either a boolean or an object
+    fn from_metadata(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(pure) => pure.then(Self::default),
+            Value::Object(_) => serde_json::from_value(value.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns how long a result is reused for
+    pub fn ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.ttl_secs.unwrap_or(DEFAULT_MEMOIZATION_TTL_SECS))
+    }
+}
+
+/// The definition of a single tool, as exposed over MCP
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolSchema {
+    /// Name of the tool
+    pub name: String,
+    /// Human readable description of the tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON schema of the tool's arguments
+    #[serde(rename = "inputSchema")]
+    pub input_schema: Value,
+    /// JSON schema of the tool's result, if it returns anything
+    #[serde(
+        rename = "outputSchema",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub output_schema: Option<Value>,
+    /// Hints about the tool's behavior that help clients decide when to ask for confirmation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+/// Hints about the behavior of a tool, as defined by MCP. Clients treat unset hints as unknown.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    /// Human readable title of the tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The tool doesn't modify its environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only_hint: Option<bool>,
+    /// The tool may destroy data, rather than only add to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destructive_hint: Option<bool>,
+    /// Calling the tool again with the same arguments has no further effect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotent_hint: Option<bool>,
+    /// The tool interacts with external entities, such as the network
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_world_hint: Option<bool>,
+}
+
+impl ToolAnnotations {
+    /// Returns these annotations with the unset hints taken from `defaults`
+    pub fn or(self, defaults: &ToolAnnotations) -> Self {
+        Self {
+            title: self.title.or_else(|| defaults.title.clone()),
+            read_only_hint: self.read_only_hint.or(defaults.read_only_hint),
+            destructive_hint: self.destructive_hint.or(defaults.destructive_hint),
+            idempotent_hint: self.idempotent_hint.or(defaults.idempotent_hint),
+            open_world_hint: self.open_world_hint.or(defaults.open_world_hint),
+        }
+    }
+}
+
+/// The tools exported by a component
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolsDocument {
+    /// Tool definitions, one per exported function
+    pub tools: Vec<ToolSchema>,
+    /// Name of the WIT world the component was built from, if the binary records it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub world: Option<String>,
 }
 
 /// Error type for tool name validation
@@ -35,6 +276,10 @@ pub enum ValidationError {
     InvalidToolName(String),
     #[error("Tool name too long: {0} characters (max 128)")]
     ToolNameTooLong(usize),
+    #[error("Invalid tool name pattern: {0}")]
+    InvalidPattern(String),
+    #[error("Invalid maximum tool name length {0}, must be between {MIN_TOOL_NAME_LENGTH} and {MAX_TOOL_NAME_LENGTH}")]
+    InvalidMaxLength(usize),
 }
 
 #[derive(Error, Debug)]
@@ -48,6 +293,14 @@ pub enum ValError {
     #[error("invalid char: {0}")]
     InvalidChar(String),
 
+    /// A number that is not an integer was given for an integer type.
+    #[error("expected an integer for {0}, found {1}")]
+    NotAnInteger(&'static str, String),
+
+    /// A number does not fit into the target type.
+    #[error("value {1} is out of range for {0}")]
+    OutOfRange(&'static str, String),
+
     /// An object had an unexpected shape for a particular conceptual type.
     #[error("expected object shape for {0}, found: {1}")]
     ShapeError(&'static str, String),
@@ -61,9 +314,169 @@ pub enum ValError {
     ResourceError,
 }
 
+/// Maximum length of a tool name allowed by the MCP specification
+pub const MAX_TOOL_NAME_LENGTH: usize = 128;
+
+/// Smallest configurable maximum tool name length. Truncated names keep a hash suffix, so the
+/// limit needs to leave room for it.
+pub const MIN_TOOL_NAME_LENGTH: usize = 16;
+
+/// Characters replaced by the default sanitizer. Names are lowercased before this is applied.
+pub const DEFAULT_INVALID_CHARS: &str = "[^a-z0-9-]";
+
+const HASH_SUFFIX_LENGTH: usize = 9;
+
+static DEFAULT_SANITIZER: LazyLock<ToolNameSanitizer> = LazyLock::new(ToolNameSanitizer::default);
+
+/// Derives MCP compliant tool names from function identifiers.
+///
+/// Each part of the identifier is optionally lowercased and every match of the `invalid_chars`
+/// pattern is replaced. Names longer than `max_length` are truncated and suffixed with a short
+/// hash of the full name so distinct functions keep distinct names. Whatever the configuration,
+/// the final name always satisfies [`validate_tool_name`].
+#[derive(Debug, Clone)]
+pub struct ToolNameSanitizer {
+    invalid_chars: Regex,
+    replacement: String,
+    max_length: usize,
+    lowercase: bool,
+    strip_versions: bool,
+}
+
+impl Default for ToolNameSanitizer {
+    fn default() -> Self {
+        Self {
+            invalid_chars: Regex::new(DEFAULT_INVALID_CHARS).expect("default pattern is valid"),
+            replacement: "_".to_string(),
+            max_length: MAX_TOOL_NAME_LENGTH,
+            lowercase: true,
+            strip_versions: false,
+        }
+    }
+}
+
+impl ToolNameSanitizer {
+    /// Creates a new sanitizer. `invalid_chars` is a regular expression matching the text to
+    /// replace with `replacement`.
+    pub fn new(
+        invalid_chars: &str,
+        replacement: &str,
+        max_length: usize,
+        lowercase: bool,
+    ) -> Result<Self, ValidationError> {
+        let invalid_chars = Regex::new(invalid_chars)
+            .map_err(|e| ValidationError::InvalidPattern(e.to_string()))?;
+        if !(MIN_TOOL_NAME_LENGTH..=MAX_TOOL_NAME_LENGTH).contains(&max_length) {
+            return Err(ValidationError::InvalidMaxLength(max_length));
+        }
+        Ok(Self {
+            invalid_chars,
+            replacement: replacement.to_string(),
+            max_length,
+            lowercase,
+            strip_versions: false,
+        })
+    }
+
+    /// Leave interface package versions out of tool names, so `wasi:cli/run@0.2.0.run` becomes
+    /// `wasi_cli_run_run`. Functions that would only differ by version keep the version (see
+    /// [`component_exports_to_tools_with_sanitizer`]).
+    pub fn with_strip_versions(mut self, strip_versions: bool) -> Self {
+        self.strip_versions = strip_versions;
+        self
+    }
+
+    /// The maximum length of names produced by this sanitizer
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    /// Sanitizes a single part of a tool name
+    pub fn sanitize_component(&self, name: &str) -> String {
+        let name = if self.lowercase {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        };
+        let replaced = self
+            .invalid_chars
+            .replace_all(&name, self.replacement.as_str());
+        // A custom pattern or replacement may still let through characters MCP doesn't allow
+        replaced
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+
+    /// Generates a tool name from a function identifier
+    pub fn tool_name(&self, identifier: &FunctionIdentifier) -> String {
+        self.build_tool_name(identifier, self.strip_versions)
+    }
+
+    /// Generates a tool name from a function identifier, always keeping the interface version
+    pub fn versioned_tool_name(&self, identifier: &FunctionIdentifier) -> String {
+        self.build_tool_name(identifier, false)
+    }
+
+    fn build_tool_name(&self, identifier: &FunctionIdentifier, strip_versions: bool) -> String {
+        let mut parts = Vec::new();
+
+        if let Some(pkg) = &identifier.package_name {
+            parts.push(self.sanitize_component(pkg));
+        }
+
+        match (identifier.interface(), &identifier.interface_name) {
+            (Some(iface), _) if strip_versions => {
+                parts.push(self.sanitize_component(&iface.unversioned()))
+            }
+            (_, Some(iface)) => parts.push(self.sanitize_component(iface)),
+            (_, None) => {}
+        }
+
+        parts.push(self.sanitize_component(&identifier.function_name));
+
+        let mut normalized = parts.join("_");
+        if normalized.is_empty() {
+            normalized.push('_');
+        }
+
+        self.truncate(normalized, &identifier.qualified_name())
+    }
+
+    /// Returns a variant of `name` carrying the numeric `suffix`, used to tell apart different
+    /// functions whose names collide after sanitization.
+    pub fn dedup_name(&self, name: &str, suffix: usize) -> String {
+        let suffix = format!("-{suffix}");
+        let keep = self.max_length.saturating_sub(suffix.len()).min(name.len());
+        format!("{}{suffix}", &name[..keep])
+    }
+
+    fn truncate(&self, name: String, full_name: &str) -> String {
+        if name.len() <= self.max_length {
+            return name;
+        }
+        // Sanitized names are always ASCII, so slicing by byte is safe
+        let keep = self.max_length - HASH_SUFFIX_LENGTH;
+        format!("{}-{:08x}", &name[..keep], fnv1a(full_name))
+    }
+}
+
+/// A small, stable hash used for truncated names so they stay the same across releases
+fn fnv1a(input: &str) -> u32 {
+    input.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x01000193)
+    })
+}
+
 /// Validates a tool name according to MCP specification
 pub fn validate_tool_name(tool_name: &str) -> Result<(), ValidationError> {
-    if tool_name.len() > 128 {
+    if tool_name.len() > MAX_TOOL_NAME_LENGTH {
         return Err(ValidationError::ToolNameTooLong(tool_name.len()));
     }
 
@@ -85,38 +498,9 @@ pub fn validate_tool_name(tool_name: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
-/// Normalizes a tool name component by replacing invalid characters with underscores
-fn normalize_name_component(name: &str) -> String {
-    name.to_lowercase()
-        .chars()
-        .map(|c| match c {
-            ':' | '/' | '.' => '_',
-            c if c.is_ascii_alphanumeric() || c == '-' => c,
-            _ => '_',
-        })
-        .collect()
-}
-
-/// Generates a normalized tool name from a function identifier
+/// Generates a normalized tool name from a function identifier using the default sanitizer
 pub fn normalize_tool_name(identifier: &FunctionIdentifier) -> String {
-    let mut parts = Vec::new();
-
-    if let Some(pkg) = &identifier.package_name {
-        parts.push(normalize_name_component(pkg));
-    }
-
-    if let Some(iface) = &identifier.interface_name {
-        parts.push(normalize_name_component(iface));
-    }
-
-    parts.push(normalize_name_component(&identifier.function_name));
-
-    let normalized = parts.join("_");
-
-    // Validate the result
-    validate_tool_name(&normalized).expect("Internal error: generated tool name failed validation");
-
-    normalized
+    DEFAULT_SANITIZER.tool_name(identifier)
 }
 
 /// Given a component and a wasmtime engine, return structured tool metadata with normalized names.
@@ -127,21 +511,398 @@ pub fn component_exports_to_tools(
     engine: &Engine,
     output: bool,
 ) -> Vec<ToolMetadata> {
-    let mut tools = Vec::new();
+    component_exports_to_tools_with_sanitizer(component, engine, output, &DEFAULT_SANITIZER)
+}
+
+/// Same as [`component_exports_to_tools`], but names tools with the given [`ToolNameSanitizer`].
+///
+/// If two functions of the component end up with the same name, the later ones get a numeric
+/// suffix (see [`ToolNameSanitizer::dedup_name`]).
+pub fn component_exports_to_tools_with_sanitizer(
+    component: &Component,
+    engine: &Engine,
+    output: bool,
+    sanitizer: &ToolNameSanitizer,
+) -> Vec<ToolMetadata> {
+    let mut functions = Vec::new();
 
     for (export_name, export_item) in component.component_type().exports(engine) {
-        gather_exported_functions_with_metadata(
+        gather_exported_functions(
             export_name,
             None,
             None,
             &export_item,
             engine,
-            &mut tools,
-            output,
+            &mut functions,
         );
     }
+    let functions = pair_plan_functions(functions);
+
+    // Names that more than one function maps to once versions are stripped keep their versions,
+    // so different versions of the same interface stay apart
+    let mut name_counts = HashMap::new();
+    for (identifier, _, _) in &functions {
+        *name_counts
+            .entry(sanitizer.tool_name(identifier))
+            .or_insert(0usize) += 1;
+    }
+
+    let mut seen = HashSet::new();
+    functions
+        .into_iter()
+        .map(|(identifier, func, plan)| {
+            let mut base_name = sanitizer.tool_name(&identifier);
+            if name_counts.get(&base_name).copied().unwrap_or_default() > 1 {
+                base_name = sanitizer.versioned_tool_name(&identifier);
+            }
+            let mut normalized_name = base_name.clone();
+            let mut suffix = 2;
+            while !seen.insert(normalized_name.clone()) {
+                normalized_name = sanitizer.dedup_name(&base_name, suffix);
+                suffix += 1;
+            }
+            let mut schema = component_func_to_schema(&normalized_name, &func, output);
+            if plan.is_some() {
+                if let Some(properties) = schema
+                    .input_schema
+                    .get_mut("properties")
+                    .and_then(Value::as_object_mut)
+                {
+                    properties.insert(
+                        PREVIEW_ARGUMENT.to_string(),
+                        json!({
+                            "type": "boolean",
+                            "description": "Describe what the call would do instead of doing it"
+                        }),
+                    );
+                }
+            }
+            ToolMetadata {
+                identifier,
+                normalized_name,
+                schema,
+                deprecation: None,
+                plan,
+                memoization: None,
+            }
+        })
+        .collect()
+}
+
+/// Takes the plan variants out of the exported functions and returns the other functions with
+/// their plan variant, if they have one. The plan variant of a function is the function of the
+/// same interface with [`PLAN_SUFFIX`] appended to its name, and must take the same parameters.
+/// Functions with a parameter named [`PREVIEW_ARGUMENT`] can't have one.
+fn pair_plan_functions(
+    functions: Vec<(FunctionIdentifier, ComponentFunc)>,
+) -> Vec<(
+    FunctionIdentifier,
+    ComponentFunc,
+    Option<FunctionIdentifier>,
+)> {
+    let param_names = |func: &ComponentFunc| -> Vec<String> {
+        func.params().map(|(n, _)| n.to_string()).collect()
+    };
+    let is_plan_of = |(plan_id, plan): &(FunctionIdentifier, ComponentFunc),
+                      (id, func): &(FunctionIdentifier, ComponentFunc)| {
+        plan_id.package_name == id.package_name
+            && plan_id.interface_name == id.interface_name
+            && plan_id.function_name.strip_suffix(PLAN_SUFFIX) == Some(id.function_name.as_str())
+            && param_names(plan) == param_names(func)
+            && !param_names(func)
+                .iter()
+                .any(|name| name == PREVIEW_ARGUMENT)
+    };
+    let plans: Vec<Option<usize>> = functions
+        .iter()
+        .map(|function| functions.iter().position(|plan| is_plan_of(plan, function)))
+        .collect();
+    let paired: HashSet<usize> = plans.iter().flatten().copied().collect();
+    functions
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !paired.contains(i))
+        .map(|(i, (id, func))| {
+            let plan = plans[i].map(|plan| functions[plan].0.clone());
+            (id.clone(), func.clone(), plan)
+        })
+        .collect()
+}
+
+/// Returns the name of the WIT world a component was built from, if the binary records it.
+///
+/// Toolchains don't agree on where (or whether) this is stored, so this is best effort: it looks
+/// for a `component-type` custom section named after the world (as emitted by `wit-bindgen`) and
+/// falls back to the component's own name from the `component-name` section.
+pub fn component_world_name(wasm: &[u8]) -> Option<String> {
+    let mut component_name = None;
+    for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+        let Ok(wasmparser::Payload::CustomSection(section)) = payload else {
+            continue;
+        };
+        if let Some(world) = world_from_component_type_section(section.name()) {
+            return Some(world);
+        }
+        if component_name.is_none() {
+            if let wasmparser::KnownCustom::ComponentName(reader) = section.as_known() {
+                component_name = reader.into_iter().find_map(|name| match name {
+                    Ok(wasmparser::ComponentName::Component { name, .. }) => Some(name.to_string()),
+                    _ => None,
+                });
+            }
+        }
+    }
+    component_name
+}
+
+/// Section names look like `component-type:wit-bindgen:0.41.0:ns:pkg@1.0.0:world:encoded world`
+fn world_from_component_type_section(name: &str) -> Option<String> {
+    let rest = name.strip_prefix("component-type:")?;
+    let rest = rest
+        .strip_suffix(":encoded world")
+        .or_else(|| rest.strip_suffix(":imports and exports"))
+        .unwrap_or(rest);
+    rest.rsplit(':')
+        .next()
+        .filter(|world| !world.is_empty())
+        .map(String::from)
+}
+
+/// Name of the custom section in which a component can embed metadata about its tools
+pub const TOOL_METADATA_SECTION: &str = "wassette-tool-metadata";
+
+/// Returns the tool metadata embedded in a component's [`TOOL_METADATA_SECTION`] custom section.
+///
+/// The section holds a JSON document keyed by tool, where a tool is named either by its
+/// qualified function name (e.g. `wasi:http/handler@0.2.0.handle`) or by its tool name:
+///
+/// ```json
+/// { "tools": { "fetch": { "parameters": { "timeout": { "default": 30, "examples": [5, 60] } } } } }
+/// ```
+///
+/// Returns `None` if the section is missing or is not valid JSON.
+pub fn component_tool_metadata(wasm: &[u8]) -> Option<Value> {
+    wasmparser::Parser::new(0)
+        .parse_all(wasm)
+        .find_map(|payload| match payload {
+            Ok(wasmparser::Payload::CustomSection(section))
+                if section.name() == TOOL_METADATA_SECTION =>
+            {
+                Some(section.data())
+            }
+            _ => None,
+        })
+        .and_then(|data| serde_json::from_slice(data).ok())
+}
+
+/// Merges declared parameter defaults and examples into the `inputSchema` of each tool, as the
+/// `default` and `examples` keywords. Parameters with a default are no longer required, as
+/// [`apply_schema_defaults`] fills them in when a caller omits them.
+///
+/// Tools declared as `deprecated` get their [`Deprecation`], the `deprecated` keyword in their
+/// `inputSchema` and a description that starts with the deprecation notice. Declared
+/// `annotations`, such as `{"readOnlyHint": true}`, become the tool's [`ToolAnnotations`]. Tools
+/// declared as `pure` get their [`Memoization`].
+pub fn apply_tool_metadata(tools: &mut [ToolMetadata], metadata: &Value) {
+    let Some(declared) = metadata.get("tools").and_then(Value::as_object) else {
+        return;
+    };
+    for tool in tools.iter_mut() {
+        let Some(meta) = declared
+            .get(&tool.identifier.qualified_name())
+            .or_else(|| declared.get(&tool.normalized_name))
+        else {
+            continue;
+        };
+        if let Some(deprecation) = meta.get("deprecated").and_then(Deprecation::from_metadata) {
+            let notice = deprecation.notice(&tool.normalized_name);
+            tool.schema.description = Some(match tool.schema.description.take() {
+                Some(description) => format!("{notice} {description}"),
+                None => notice,
+            });
+            if let Some(input_schema) = tool.schema.input_schema.as_object_mut() {
+                input_schema.insert("deprecated".to_string(), Value::Bool(true));
+            }
+            tool.deprecation = Some(deprecation);
+        }
+        if let Some(annotations) = meta
+            .get("annotations")
+            .and_then(|annotations| serde_json::from_value(annotations.clone()).ok())
+        {
+            tool.schema.annotations = Some(annotations);
+        }
+        tool.memoization = meta.get("pure").and_then(Memoization::from_metadata);
+        let Some(parameters) = meta.get("parameters").and_then(Value::as_object) else {
+            continue;
+        };
+        let input_schema = &mut tool.schema.input_schema;
+        for (param, meta) in parameters {
+            let Some(property) = input_schema
+                .get_mut("properties")
+                .and_then(|props| props.get_mut(param))
+                .and_then(Value::as_object_mut)
+            else {
+                continue;
+            };
+            if let Some(examples) = meta.get("examples").filter(|e| e.is_array()) {
+                property.insert("examples".to_string(), examples.clone());
+            }
+            if let Some(default) = meta.get("default") {
+                property.insert("default".to_string(), default.clone());
+                if let Some(required) = input_schema
+                    .get_mut("required")
+                    .and_then(Value::as_array_mut)
+                {
+                    required.retain(|name| name.as_str() != Some(param));
+                }
+            }
+        }
+    }
+}
+
+/// Fills in the `default` of every property in `input_schema` that is missing from `arguments`.
+pub fn apply_schema_defaults(input_schema: &Value, arguments: &mut serde_json::Map<String, Value>) {
+    let Some(properties) = input_schema.get("properties").and_then(Value::as_object) else {
+        return;
+    };
+    for (name, property) in properties {
+        if let Some(default) = property.get("default") {
+            arguments
+                .entry(name.clone())
+                .or_insert_with(|| default.clone());
+        }
+    }
+}
+
+/// How deeply nested the subschemas kept by [`compact_schema`] may be. The parameters of a tool
+/// are at depth 1, the fields of a record parameter and the cases of a variant at depth 2.
+pub const COMPACT_SCHEMA_DEPTH: usize = 2;
+
+/// How many fields or cases a description written by [`compact_schema`] names
+const COMPACT_DESCRIPTION_NAMES: usize = 8;
 
-    tools
+/// Shortens a schema by replacing every record, list, tuple, variant, option or result nested
+/// deeper than `max_depth` with a one-line description of its shape, e.g. `one of: ok, err`.
+/// Returns `None` if there is nothing to shorten.
+pub fn compact_schema(schema: &Value, max_depth: usize) -> Option<Value> {
+    let mut compacted = false;
+    let schema = compact_subschema(schema, 0, max_depth, &mut compacted);
+    compacted.then_some(schema)
+}
+
+fn compact_subschema(
+    schema: &Value,
+    depth: usize,
+    max_depth: usize,
+    compacted: &mut bool,
+) -> Value {
+    let Value::Object(map) = schema else {
+        return schema.clone();
+    };
+    let nested = ["properties", "items", "prefixItems", "oneOf", "anyOf"];
+    if depth > max_depth && nested.iter().any(|key| map.contains_key(*key)) {
+        *compacted = true;
+        let mut short = Map::new();
+        if let Some(ty) = map.get("type") {
+            short.insert("type".to_string(), ty.clone());
+        }
+        let description = match map.get("description").and_then(Value::as_str) {
+            Some(description) => format!("{description} ({})", describe_schema(map)),
+            None => describe_schema(map),
+        };
+        short.insert("description".to_string(), Value::String(description));
+        return Value::Object(short);
+    }
+    let compact_all = |values: &[Value], compacted: &mut bool| {
+        values
+            .iter()
+            .map(|value| compact_subschema(value, depth + 1, max_depth, compacted))
+            .collect::<Vec<_>>()
+    };
+    let mut result = Map::new();
+    for (key, value) in map {
+        let value = match (key.as_str(), value) {
+            ("properties", Value::Object(properties)) => Value::Object(
+                properties
+                    .iter()
+                    .map(|(name, property)| {
+                        let property = compact_subschema(property, depth + 1, max_depth, compacted);
+                        (name.clone(), property)
+                    })
+                    .collect(),
+            ),
+            ("items", items) => compact_subschema(items, depth + 1, max_depth, compacted),
+            ("prefixItems" | "oneOf" | "anyOf", Value::Array(values)) => {
+                Value::Array(compact_all(values, compacted))
+            }
+            _ => value.clone(),
+        };
+        result.insert(key.clone(), value);
+    }
+    Value::Object(result)
+}
+
+/// Describes the shape of a schema in a few words
+fn describe_schema(schema: &Map<String, Value>) -> String {
+    if let Some(Value::Array(cases)) = schema.get("oneOf").or_else(|| schema.get("anyOf")) {
+        let names: Vec<String> = cases.iter().map(case_name).collect();
+        return format!("one of: {}", name_list(&names));
+    }
+    if let Some(Value::Object(properties)) = schema.get("properties") {
+        let names: Vec<String> = properties.keys().cloned().collect();
+        return format!("object with fields {}", name_list(&names));
+    }
+    if let Some(Value::Array(items)) = schema.get("prefixItems") {
+        return format!("tuple of {} values", items.len());
+    }
+    match schema.get("items").and_then(Value::as_object) {
+        Some(items) => match items.get("type").and_then(Value::as_str) {
+            Some("object") => "list of objects".to_string(),
+            Some(ty) => format!("list of {ty}s"),
+            None => "list".to_string(),
+        },
+        None => "value".to_string(),
+    }
+}
+
+/// Names a case of a variant, result or option schema
+fn case_name(case: &Value) -> String {
+    if let Some(tag) = case
+        .pointer("/properties/tag/const")
+        .and_then(Value::as_str)
+    {
+        return tag.to_string();
+    }
+    // Results are objects with a single `ok` or `err` field
+    if let Some([field]) = case
+        .get("required")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+    {
+        if let Some(field) = field.as_str() {
+            return field.to_string();
+        }
+    }
+    case.get("type")
+        .and_then(Value::as_str)
+        .unwrap_or("value")
+        .to_string()
+}
+
+fn name_list(names: &[String]) -> String {
+    let mut list = names
+        .iter()
+        .take(COMPACT_DESCRIPTION_NAMES)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > COMPACT_DESCRIPTION_NAMES {
+        list.push_str(&format!(
+            " and {} more",
+            names.len() - COMPACT_DESCRIPTION_NAMES
+        ));
+    }
+    list
 }
 
 /// Given a component and a wasmtime engine, return a full JSON schema of the component's exports.
@@ -156,6 +917,28 @@ pub fn component_exports_to_json_schema(
     json!({ "tools": tools.into_iter().map(|t| t.schema).collect::<Vec<_>>() })
 }
 
+/// Extracts the tools exported by a component from its binary, without having to set up an
+/// [`Engine`]. Tool metadata embedded in the component is applied to the schemas.
+///
+/// This compiles the component, so it is meant for one-off introspection. Callers that already
+/// have a compiled [`Component`] should use [`component_exports_to_tools`] instead.
+pub fn extract_tools_from_bytes(wasm: &[u8]) -> anyhow::Result<ToolsDocument> {
+    let mut config = wasmtime::Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+    let component = Component::new(&engine, wasm)?;
+
+    let mut tools = component_exports_to_tools(&component, &engine, true);
+    if let Some(metadata) = component_tool_metadata(wasm) {
+        apply_tool_metadata(&mut tools, &metadata);
+    }
+
+    Ok(ToolsDocument {
+        tools: tools.into_iter().map(|tool| tool.schema).collect(),
+        world: component_world_name(wasm),
+    })
+}
+
 /// Converts a slice of component model [`Val`] objects into a JSON representation.
 pub fn vals_to_json(vals: &[Val]) -> Value {
     match vals.len() {
@@ -201,16 +984,30 @@ pub fn create_placeholder_results(results: &[Type]) -> Vec<Val> {
 fn type_to_json_schema(t: &Type) -> Value {
     match t {
         Type::Bool => json!({ "type": "boolean" }),
-        Type::S8
-        | Type::S16
-        | Type::S32
-        | Type::S64
-        | Type::U8
-        | Type::U16
-        | Type::U32
-        | Type::U64
-        | Type::Float32
-        | Type::Float64 => json!({ "type": "number" }),
+        Type::S8 | Type::S16 | Type::S32 | Type::U8 | Type::U16 | Type::U32 => {
+            json!({ "type": "number" })
+        }
+        // Values beyond 2^53 can't be represented exactly by many JSON implementations, so they
+        // may be passed as decimal strings instead
+        Type::S64 => json!({
+            "anyOf": [
+                { "type": "number" },
+                { "type": "string", "pattern": "^-?[0-9]+$" }
+            ]
+        }),
+        Type::U64 => json!({
+            "anyOf": [
+                { "type": "number" },
+                { "type": "string", "pattern": "^[0-9]+$" }
+            ]
+        }),
+        // Non-finite values are passed as strings, the way results carry them
+        Type::Float32 | Type::Float64 => json!({
+            "anyOf": [
+                { "type": "number" },
+                { "type": "string", "enum": ["NaN", "inf", "-inf"] }
+            ]
+        }),
         Type::Char => json!({
             "type": "string",
             "description": "1 unicode codepoint"
@@ -351,7 +1148,7 @@ fn type_to_json_schema(t: &Type) -> Value {
     }
 }
 
-fn component_func_to_schema(name: &str, func: &ComponentFunc, output: bool) -> serde_json::Value {
+fn component_func_to_schema(name: &str, func: &ComponentFunc, output: bool) -> ToolSchema {
     let mut properties = serde_json::Map::new();
     let mut required = Vec::new();
 
@@ -366,17 +1163,9 @@ fn component_func_to_schema(name: &str, func: &ComponentFunc, output: bool) -> s
         "required": required
     });
 
-    let mut tool_obj = serde_json::Map::new();
-    tool_obj.insert("name".to_string(), json!(name));
-    tool_obj.insert(
-        "description".to_string(),
-        json!(format!("Auto-generated schema for function '{name}'")),
-    );
-    tool_obj.insert("inputSchema".to_string(), input_schema);
-
-    if output {
+    let output_schema = if output {
         let mut results_iter = func.results();
-        let output_schema = match results_iter.len() {
+        match results_iter.len() {
             0 => None,
             1 => Some(type_to_json_schema(&results_iter.next().unwrap())),
             _ => {
@@ -386,22 +1175,27 @@ fn component_func_to_schema(name: &str, func: &ComponentFunc, output: bool) -> s
                     "items": schemas
                 }))
             }
-        };
-        if let Some(o) = output_schema {
-            tool_obj.insert("outputSchema".to_string(), o);
         }
+    } else {
+        None
+    };
+
+    ToolSchema {
+        name: name.to_string(),
+        description: Some(format!("Auto-generated schema for function '{name}'")),
+        input_schema,
+        output_schema,
+        annotations: None,
     }
-    json!(tool_obj)
 }
 
-fn gather_exported_functions_with_metadata(
+fn gather_exported_functions(
     export_name: &str,
     previous_name: Option<String>,
     package_name: Option<String>,
     item: &ComponentItem,
     engine: &Engine,
-    results: &mut Vec<ToolMetadata>,
-    output: bool,
+    results: &mut Vec<(FunctionIdentifier, ComponentFunc)>,
 ) {
     match item {
         ComponentItem::ComponentFunc(func) => {
@@ -411,40 +1205,31 @@ fn gather_exported_functions_with_metadata(
                 function_name: export_name.to_string(),
             };
 
-            let normalized_name = normalize_tool_name(&function_id);
-            let schema = component_func_to_schema(&normalized_name, func, output);
-
-            results.push(ToolMetadata {
-                identifier: function_id,
-                normalized_name,
-                schema,
-            });
+            results.push((function_id, func.clone()));
         }
         ComponentItem::Component(sub_component) => {
             let previous_name = Some(export_name.to_string());
             for (export_name, export_item) in sub_component.exports(engine) {
-                gather_exported_functions_with_metadata(
+                gather_exported_functions(
                     export_name,
                     previous_name.clone(),
                     package_name.clone(),
                     &export_item,
                     engine,
                     results,
-                    output,
                 );
             }
         }
         ComponentItem::ComponentInstance(instance) => {
             let previous_name = Some(export_name.to_string());
             for (export_name, export_item) in instance.exports(engine) {
-                gather_exported_functions_with_metadata(
+                gather_exported_functions(
                     export_name,
                     previous_name.clone(),
                     package_name.clone(),
                     &export_item,
                     engine,
                     results,
-                    output,
                 );
             }
         }
@@ -532,88 +1317,31 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
             Value::Bool(b) => Ok(Val::Bool(*b)),
             _ => Err(ValError::ShapeError("bool", format!("{value:?}"))),
         },
-        Type::S8 => match value {
-            Value::Number(n) => n
-                .as_i64()
-                .and_then(|i| i8::try_from(i).ok())
-                .map(Val::S8)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("s8", format!("{value:?}"))),
-        },
-        Type::S16 => match value {
-            Value::Number(n) => n
-                .as_i64()
-                .and_then(|i| i16::try_from(i).ok())
-                .map(Val::S16)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("s16", format!("{value:?}"))),
-        },
-        Type::S32 => match value {
-            Value::Number(n) => n
-                .as_i64()
-                .and_then(|i| i32::try_from(i).ok())
-                .map(Val::S32)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("s32", format!("{value:?}"))),
-        },
-        Type::S64 => match value {
-            Value::Number(n) => n
-                .as_i64()
-                .map(Val::S64)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("s64", format!("{value:?}"))),
-        },
-        Type::U8 => match value {
-            Value::Number(n) => n
-                .as_u64()
-                .and_then(|i| u8::try_from(i).ok())
-                .map(Val::U8)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("u8", format!("{value:?}"))),
-        },
-        Type::U16 => match value {
-            Value::Number(n) => n
-                .as_u64()
-                .and_then(|i| u16::try_from(i).ok())
-                .map(Val::U16)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("u16", format!("{value:?}"))),
-        },
-        Type::U32 => match value {
-            Value::Number(n) => n
-                .as_u64()
-                .and_then(|i| u32::try_from(i).ok())
-                .map(Val::U32)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("u32", format!("{value:?}"))),
-        },
-        Type::U64 => match value {
-            Value::Number(n) => n
-                .as_u64()
-                .map(Val::U64)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("u64", format!("{value:?}"))),
-        },
-        Type::Float32 => match value {
-            Value::Number(n) => n
-                .as_f64()
-                .map(|f| Val::Float32(f as f32))
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("float32", format!("{value:?}"))),
-        },
-        Type::Float64 => match value {
-            Value::Number(n) => n
-                .as_f64()
-                .map(Val::Float64)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("float64", format!("{value:?}"))),
-        },
+        Type::S8 => json_to_int(value, "s8", false).map(Val::S8),
+        Type::S16 => json_to_int(value, "s16", false).map(Val::S16),
+        Type::S32 => json_to_int(value, "s32", false).map(Val::S32),
+        Type::S64 => json_to_int(value, "s64", true).map(Val::S64),
+        Type::U8 => json_to_int(value, "u8", false).map(Val::U8),
+        Type::U16 => json_to_int(value, "u16", false).map(Val::U16),
+        Type::U32 => json_to_int(value, "u32", false).map(Val::U32),
+        Type::U64 => json_to_int(value, "u64", true).map(Val::U64),
+        Type::Float32 => {
+            let f = json_to_float(value, "float32")?;
+            if f.is_finite() && f.abs() > f32::MAX as f64 {
+                return Err(ValError::OutOfRange("float32", f.to_string()));
+            }
+            Ok(Val::Float32(f as f32))
+        }
+        Type::Float64 => json_to_float(value, "float64").map(Val::Float64),
         Type::Char => match value {
             Value::String(s) => {
-                if s.chars().count() == 1 {
-                    Ok(Val::Char(s.chars().next().unwrap()))
-                } else {
-                    Err(ValError::InvalidChar(s.clone()))
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Val::Char(c)),
+                    _ => Err(ValError::InvalidChar(format!(
+                        "expected exactly one Unicode code point, found {} in {s:?}",
+                        s.chars().count()
+                    ))),
                 }
             }
             _ => Err(ValError::ShapeError("char", format!("{value:?}"))),
@@ -741,6 +1469,44 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
     }
 }
 
+/// Converts a JSON value to an integer of type `T`, reporting values that don't fit. 64-bit types
+/// also accept decimal strings, as JSON numbers beyond 2^53 lose precision in many clients.
+fn json_to_int<T: TryFrom<i128>>(
+    value: &Value,
+    ty: &'static str,
+    allow_string: bool,
+) -> Result<T, ValError> {
+    let wide = match value {
+        Value::Number(n) => n
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| n.as_u64().map(i128::from))
+            .ok_or_else(|| ValError::NotAnInteger(ty, n.to_string()))?,
+        Value::String(s) if allow_string => s
+            .parse::<i128>()
+            .map_err(|_| ValError::NotAnInteger(ty, format!("{s:?}")))?,
+        _ => return Err(ValError::ShapeError(ty, format!("{value:?}"))),
+    };
+    T::try_from(wide).map_err(|_| ValError::OutOfRange(ty, wide.to_string()))
+}
+
+/// Converts a JSON value to a float. Non-finite values are accepted in the string form produced
+/// by [`vals_to_json`] (`"NaN"`, `"inf"` and `"-inf"`).
+fn json_to_float(value: &Value, ty: &'static str) -> Result<f64, ValError> {
+    match value {
+        Value::Number(n) => n
+            .as_f64()
+            .ok_or_else(|| ValError::NumberError(n.to_string())),
+        Value::String(s) => match s.as_str() {
+            "NaN" => Ok(f64::NAN),
+            "inf" => Ok(f64::INFINITY),
+            "-inf" => Ok(f64::NEG_INFINITY),
+            _ => Err(ValError::ShapeError(ty, format!("{value:?}"))),
+        },
+        _ => Err(ValError::ShapeError(ty, format!("{value:?}"))),
+    }
+}
+
 fn default_val_for_type(ty: &Type) -> Val {
     match ty {
         Type::Bool => Val::Bool(false),
@@ -1263,9 +2029,10 @@ mod tests {
             .as_array()
             .unwrap();
         assert_eq!(prefix_items.len(), 4);
-        for item in prefix_items {
+        for item in &prefix_items[..3] {
             assert_eq!(item.get("type").unwrap(), "number");
         }
+        assert_eq!(prefix_items[3], type_to_json_schema(&Type::S64));
 
         // Test foo namespace functions
         let foo_a = find_tool(tools, "foo_a").unwrap();
@@ -1339,14 +2106,8 @@ mod tests {
                 "c"
             );
             assert_eq!(
-                case_c
-                    .get("properties")
-                    .unwrap()
-                    .get("val")
-                    .unwrap()
-                    .get("type")
-                    .unwrap(),
-                "number"
+                case_c.get("properties").unwrap().get("val").unwrap(),
+                &type_to_json_schema(&Type::S64)
             );
         }
 
@@ -1459,6 +2220,100 @@ mod tests {
         assert!(json_to_val(&overflow_val, &s8_ty).is_err());
     }
 
+    #[test]
+    fn test_json_to_val_integer_fidelity() {
+        assert_eq!(
+            json_to_val(&json!(u64::MAX), &Type::U64).unwrap(),
+            Val::U64(u64::MAX)
+        );
+        assert_eq!(
+            json_to_val(&json!("18446744073709551615"), &Type::U64).unwrap(),
+            Val::U64(u64::MAX)
+        );
+        assert_eq!(
+            json_to_val(&json!("-9223372036854775808"), &Type::S64).unwrap(),
+            Val::S64(i64::MIN)
+        );
+
+        let err = json_to_val(&json!("18446744073709551616"), &Type::U64).unwrap_err();
+        assert!(matches!(err, ValError::OutOfRange("u64", _)));
+        let err = json_to_val(&json!(-1), &Type::U8).unwrap_err();
+        assert_eq!(err.to_string(), "value -1 is out of range for u8");
+        let err = json_to_val(&json!(1.5), &Type::U32).unwrap_err();
+        assert!(matches!(err, ValError::NotAnInteger("u32", _)));
+
+        // Only 64-bit integers accept strings
+        assert!(json_to_val(&json!("12"), &Type::U32).is_err());
+        assert!(json_to_val(&json!("abc"), &Type::U64).is_err());
+    }
+
+    #[test]
+    fn test_schema_admits_string_numbers() {
+        // Every type whose conversion accepts strings has to admit them in its schema, or input
+        // validation rejects arguments that would convert fine
+        for (ty, input) in [
+            (Type::S64, "-9223372036854775808"),
+            (Type::U64, "18446744073709551615"),
+            (Type::Float32, "NaN"),
+            (Type::Float64, "-inf"),
+        ] {
+            assert!(json_to_val(&json!(input), &ty).is_ok());
+            let schema = type_to_json_schema(&ty);
+            let string = schema["anyOf"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|branch| branch["type"] == "string")
+                .unwrap();
+            let admitted = match &string["enum"] {
+                Value::Array(values) => values.contains(&json!(input)),
+                _ => string["pattern"].is_string(),
+            };
+            assert!(admitted, "{schema} doesn't admit {input}");
+        }
+    }
+
+    #[test]
+    fn test_json_to_val_float_fidelity() {
+        assert_eq!(
+            json_to_val(&json!(1.5), &Type::Float32).unwrap(),
+            Val::Float32(1.5)
+        );
+        let err = json_to_val(&json!(1e39), &Type::Float32).unwrap_err();
+        assert!(matches!(err, ValError::OutOfRange("float32", _)));
+        assert!(json_to_val(&json!(1e39), &Type::Float64).is_ok());
+
+        // Non-finite values roundtrip through their string form
+        for val in [Val::Float32(f32::INFINITY), Val::Float64(f64::NEG_INFINITY)] {
+            let ty = match val {
+                Val::Float32(_) => Type::Float32,
+                _ => Type::Float64,
+            };
+            assert_eq!(json_to_val(&val_to_json(&val), &ty).unwrap(), val);
+        }
+        match json_to_val(&json!("NaN"), &Type::Float64).unwrap() {
+            Val::Float64(f) => assert!(f.is_nan()),
+            other => panic!("unexpected value {other:?}"),
+        }
+        assert!(json_to_val(&json!("1.5"), &Type::Float64).is_err());
+    }
+
+    #[test]
+    fn test_json_to_val_char() {
+        assert_eq!(
+            json_to_val(&json!("é"), &Type::Char).unwrap(),
+            Val::Char('é')
+        );
+        assert_eq!(
+            json_to_val(&json!("🦀"), &Type::Char).unwrap(),
+            Val::Char('🦀')
+        );
+        let err = json_to_val(&json!("ab"), &Type::Char).unwrap_err();
+        assert!(err.to_string().contains("found 2"));
+        assert!(json_to_val(&json!(""), &Type::Char).is_err());
+        assert!(json_to_val(&json!(97), &Type::Char).is_err());
+    }
+
     #[test]
     fn test_json_to_vals_errors() {
         let types = vec![
@@ -1680,6 +2535,10 @@ mod tests {
         assert!(validate_tool_name(&max_name).is_ok());
     }
 
+    fn normalize_name_component(name: &str) -> String {
+        DEFAULT_SANITIZER.sanitize_component(name)
+    }
+
     #[test]
     fn test_normalize_name_component() {
         assert_eq!(normalize_name_component("get-weather"), "get-weather");
@@ -1753,6 +2612,422 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sanitizer_custom_pattern() {
+        let sanitizer = ToolNameSanitizer::new("[^a-zA-Z0-9]", "", 64, false).unwrap();
+        let identifier = FunctionIdentifier {
+            package_name: None,
+            interface_name: Some("wasi:cli/run@0.2.0".to_string()),
+            function_name: "Run".to_string(),
+        };
+        assert_eq!(sanitizer.tool_name(&identifier), "wasiclirun020_Run");
+        assert_eq!(identifier.qualified_name(), "wasi:cli/run@0.2.0.Run");
+
+        // Replacements that MCP doesn't allow are still cleaned up
+        let sanitizer = ToolNameSanitizer::new("-", ".", 64, true).unwrap();
+        assert_eq!(sanitizer.sanitize_component("get-time"), "get_time");
+    }
+
+    #[test]
+    fn test_sanitizer_rejects_invalid_config() {
+        assert!(matches!(
+            ToolNameSanitizer::new("[", "_", 64, true),
+            Err(ValidationError::InvalidPattern(_))
+        ));
+        assert!(matches!(
+            ToolNameSanitizer::new(DEFAULT_INVALID_CHARS, "_", 4, true),
+            Err(ValidationError::InvalidMaxLength(4))
+        ));
+        assert!(matches!(
+            ToolNameSanitizer::new(DEFAULT_INVALID_CHARS, "_", 129, true),
+            Err(ValidationError::InvalidMaxLength(129))
+        ));
+    }
+
+    #[test]
+    fn test_sanitizer_truncates_long_names() {
+        let sanitizer = ToolNameSanitizer::new(DEFAULT_INVALID_CHARS, "_", 32, true).unwrap();
+        let first = FunctionIdentifier {
+            package_name: None,
+            interface_name: Some("very:long-package/interface-name".to_string()),
+            function_name: "first-function".to_string(),
+        };
+        let second = FunctionIdentifier {
+            function_name: "second-function".to_string(),
+            ..first.clone()
+        };
+
+        let first_name = sanitizer.tool_name(&first);
+        let second_name = sanitizer.tool_name(&second);
+        assert_eq!(first_name.len(), 32);
+        assert_eq!(second_name.len(), 32);
+        assert_ne!(first_name, second_name);
+        assert_eq!(first_name, sanitizer.tool_name(&first));
+        assert!(validate_tool_name(&first_name).is_ok());
+
+        // The default limit is the MCP maximum, so long names no longer fail validation
+        let huge = FunctionIdentifier {
+            package_name: None,
+            interface_name: None,
+            function_name: "a".repeat(300),
+        };
+        let name = normalize_tool_name(&huge);
+        assert_eq!(name.len(), MAX_TOOL_NAME_LENGTH);
+        assert!(validate_tool_name(&name).is_ok());
+    }
+
+    #[test]
+    fn test_sanitizer_dedup_name() {
+        let sanitizer = ToolNameSanitizer::new(DEFAULT_INVALID_CHARS, "_", 16, true).unwrap();
+        assert_eq!(sanitizer.dedup_name("run", 2), "run-2");
+        let deduped = sanitizer.dedup_name("abcdefghijklmnop", 12);
+        assert_eq!(deduped, "abcdefghijklm-12");
+        assert_eq!(deduped.len(), 16);
+    }
+
+    #[test]
+    fn test_colliding_tool_names_are_deduplicated() -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model(true);
+        let engine = wasmtime::Engine::new(&config)?;
+
+        let wat = r#"(component
+            (core module $m
+                (func (export "f"))
+            )
+            (core instance $i (instantiate $m))
+            (func $f (canon lift (core func $i "f")))
+            (export "get-time" (func $f))
+            (export "gettime" (func $f))
+        )"#;
+
+        // Dropping dashes makes "get-time" and "gettime" collide
+        let sanitizer = ToolNameSanitizer::new("-", "", 64, true)?;
+        let component = wasmtime::component::Component::new(&engine, wat)?;
+        let tools =
+            component_exports_to_tools_with_sanitizer(&component, &engine, false, &sanitizer);
+        let names: Vec<_> = tools.iter().map(|t| t.normalized_name.as_str()).collect();
+        assert_eq!(names, vec!["gettime", "gettime-2"]);
+        for tool in &tools {
+            assert_eq!(tool.schema.name, tool.normalized_name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_interface_name() {
+        let iface = parse_interface_name("wasi:cli/run@0.2.0").unwrap();
+        assert_eq!(iface.namespace, "wasi");
+        assert_eq!(iface.package, "cli");
+        assert_eq!(iface.interface, "run");
+        assert_eq!(iface.version, Some("0.2.0"));
+        assert_eq!(iface.unversioned(), "wasi:cli/run");
+
+        let iface = parse_interface_name("foo:foo/foo").unwrap();
+        assert_eq!(iface.version, None);
+
+        assert!(parse_interface_name("generate").is_none());
+        assert!(parse_interface_name("foo:bar").is_none());
+        assert!(parse_interface_name("foo:bar/baz@").is_none());
+
+        let identifier = FunctionIdentifier {
+            package_name: None,
+            interface_name: Some("wasi:http/handler@0.2.3".to_string()),
+            function_name: "handle".to_string(),
+        };
+        assert_eq!(identifier.package().as_deref(), Some("wasi:http"));
+        assert_eq!(identifier.version(), Some("0.2.3"));
+    }
+
+    #[test]
+    fn test_strip_versions() {
+        let sanitizer = ToolNameSanitizer::default().with_strip_versions(true);
+        let identifier = FunctionIdentifier {
+            package_name: None,
+            interface_name: Some("wasi:cli/run@0.2.0".to_string()),
+            function_name: "run".to_string(),
+        };
+        assert_eq!(sanitizer.tool_name(&identifier), "wasi_cli_run_run");
+        assert_eq!(
+            sanitizer.versioned_tool_name(&identifier),
+            "wasi_cli_run_0_2_0_run"
+        );
+        assert_eq!(normalize_tool_name(&identifier), "wasi_cli_run_0_2_0_run");
+    }
+
+    #[test]
+    fn test_versioned_interfaces_do_not_collide() -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model(true);
+        let engine = wasmtime::Engine::new(&config)?;
+
+        let wat = r#"(component
+            (type (component
+                (type (instance
+                    (type (func (result string)))
+                    (export "get" (func (type 0)))
+                ))
+                (export "foo:bar/baz@1.0.0" (instance (type 0)))
+                (export "foo:bar/baz@2.0.0" (instance (type 0)))
+                (export "foo:bar/qux@1.0.0" (instance (type 0)))
+            ))
+            (export "foo" (type 0))
+        )"#;
+        let component = wasmtime::component::Component::new(&engine, wat)?;
+
+        let sanitizer = ToolNameSanitizer::default().with_strip_versions(true);
+        let tools =
+            component_exports_to_tools_with_sanitizer(&component, &engine, false, &sanitizer);
+        let mut names: Vec<_> = tools.iter().map(|t| t.normalized_name.as_str()).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "foo_bar_baz_1_0_0_get",
+                "foo_bar_baz_2_0_0_get",
+                "foo_bar_qux_get"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_world_name() {
+        assert_eq!(
+            world_from_component_type_section(
+                "component-type:wit-bindgen:0.41.0:component:fetch-rs:fetch:encoded world"
+            )
+            .as_deref(),
+            Some("fetch")
+        );
+        assert_eq!(world_from_component_type_section("producers"), None);
+
+        let wasm = wat::parse_str(r#"(component $my-world)"#).unwrap();
+        assert_eq!(component_world_name(&wasm).as_deref(), Some("my-world"));
+        let wasm = wat::parse_str("(component)").unwrap();
+        assert_eq!(component_world_name(&wasm), None);
+    }
+
+    #[test]
+    fn test_tool_metadata_defaults_and_examples() -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model(true);
+        let engine = wasmtime::Engine::new(&config)?;
+
+        let wat = r#"(component
+            (type (component
+                (type (instance
+                    (type (func (param "name" string) (param "count" u32) (result string)))
+                    (export "hello" (func (type 0)))
+                ))
+                (export "foo:bar/greet" (instance (type 0)))
+            ))
+            (export "foo" (type 0))
+            (@custom "wassette-tool-metadata" "{\"tools\": {\"foo_bar_greet_hello\": {\"parameters\": {\"count\": {\"default\": 1, \"examples\": [1, 3]}, \"name\": {\"examples\": [\"world\"]}}}}}")
+        )"#;
+        let wasm = wat::parse_str(wat)?;
+        let component = wasmtime::component::Component::new(&engine, &wasm)?;
+        let metadata = component_tool_metadata(&wasm).expect("metadata section should be read");
+
+        let mut tools = component_exports_to_tools(&component, &engine, false);
+        apply_tool_metadata(&mut tools, &metadata);
+        let input_schema = &tools[0].schema.input_schema;
+        assert_eq!(input_schema["properties"]["count"]["default"], json!(1));
+        assert_eq!(
+            input_schema["properties"]["count"]["examples"],
+            json!([1, 3])
+        );
+        assert_eq!(
+            input_schema["properties"]["name"]["examples"],
+            json!(["world"])
+        );
+        assert!(input_schema["properties"]["name"].get("default").is_none());
+        assert_eq!(input_schema["required"], json!(["name"]));
+
+        let mut args = serde_json::Map::new();
+        args.insert("name".to_string(), json!("wassette"));
+        apply_schema_defaults(input_schema, &mut args);
+        assert_eq!(json!(args), json!({ "name": "wassette", "count": 1 }));
+
+        // Explicit values take precedence over defaults
+        let mut args = serde_json::Map::new();
+        args.insert("count".to_string(), json!(5));
+        apply_schema_defaults(input_schema, &mut args);
+        assert_eq!(args["count"], json!(5));
+
+        assert!(component_tool_metadata(&wat::parse_str("(component)")?).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_variants_become_preview_argument() -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model(true);
+        let engine = wasmtime::Engine::new(&config)?;
+
+        let wat = r#"(component
+            (type (component
+                (type (instance
+                    (type (func (param "path" string) (result string)))
+                    (type (func (param "path" string) (param "force" bool) (result string)))
+                    (export "delete-file" (func (type 0)))
+                    (export "delete-file-plan" (func (type 0)))
+                    (export "move-file" (func (type 0)))
+                    (export "move-file-plan" (func (type 1)))
+                ))
+                (export "foo:bar/files" (instance (type 0)))
+            ))
+            (export "foo" (type 0))
+        )"#;
+        let component = wasmtime::component::Component::new(&engine, wat::parse_str(wat)?)?;
+        let tools = component_exports_to_tools(&component, &engine, false);
+        let names: Vec<_> = tools.iter().map(|t| t.normalized_name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "foo_bar_files_delete-file",
+                "foo_bar_files_move-file",
+                "foo_bar_files_move-file-plan"
+            ]
+        );
+
+        let delete = &tools[0];
+        assert_eq!(
+            delete.plan.as_ref().map(|plan| plan.function_name.as_str()),
+            Some("delete-file-plan")
+        );
+        assert_eq!(
+            delete.schema.input_schema["properties"][PREVIEW_ARGUMENT]["type"],
+            "boolean"
+        );
+        assert_eq!(delete.schema.input_schema["required"], json!(["path"]));
+
+        // The parameters differ, so move-file-plan stays a tool of its own
+        assert!(tools[1].plan.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tool_metadata_deprecation_and_annotations() -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model(true);
+        let engine = wasmtime::Engine::new(&config)?;
+
+        let wat = r#"(component
+            (type (component
+                (type (instance
+                    (type (func (param "name" string) (result string)))
+                    (export "hello" (func (type 0)))
+                    (export "hi" (func (type 0)))
+                ))
+                (export "foo:bar/greet" (instance (type 0)))
+            ))
+            (export "foo" (type 0))
+        )"#;
+        let component = wasmtime::component::Component::new(&engine, wat::parse_str(wat)?)?;
+        let metadata = json!({
+            "tools": {
+                "foo_bar_greet_hello": {
+                    "deprecated": { "replacement": "foo_bar_greet_hi", "sunset": "2025-12-31" }
+                },
+                "foo_bar_greet_hi": {
+                    "deprecated": false,
+                    "pure": { "ttl_secs": 60 },
+                    "annotations": { "readOnlyHint": true, "title": "Say hi" }
+                }
+            }
+        });
+
+        let mut tools = component_exports_to_tools(&component, &engine, false);
+        apply_tool_metadata(&mut tools, &metadata);
+        let hello = tools
+            .iter()
+            .find(|t| t.normalized_name == "foo_bar_greet_hello")
+            .unwrap();
+        assert_eq!(
+            hello
+                .deprecation
+                .as_ref()
+                .and_then(|d| d.replacement.as_deref()),
+            Some("foo_bar_greet_hi")
+        );
+        assert_eq!(hello.schema.input_schema["deprecated"], json!(true));
+        assert!(hello
+            .schema
+            .description
+            .as_deref()
+            .unwrap()
+            .starts_with("Tool 'foo_bar_greet_hello' is deprecated and may be removed after 2025-12-31, use 'foo_bar_greet_hi' instead."));
+
+        let hi = tools
+            .iter()
+            .find(|t| t.normalized_name == "foo_bar_greet_hi")
+            .unwrap();
+        assert!(hi.deprecation.is_none());
+        assert!(hi.schema.input_schema.get("deprecated").is_none());
+        let annotations = hi.schema.annotations.clone().unwrap();
+        assert_eq!(annotations.read_only_hint, Some(true));
+        assert_eq!(annotations.title.as_deref(), Some("Say hi"));
+        assert!(hello.schema.annotations.is_none());
+        assert_eq!(
+            hi.memoization.as_ref().map(Memoization::ttl),
+            Some(std::time::Duration::from_secs(60))
+        );
+        assert!(hello.memoization.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_tools_from_bytes() -> Result<(), Box<dyn std::error::Error>> {
+        let wasm = wat::parse_str(
+            r#"(component $greeter
+                (type (component
+                    (type (instance
+                        (type (func (param "name" string) (result string)))
+                        (export "hello" (func (type 0)))
+                    ))
+                    (export "foo:bar/greet" (instance (type 0)))
+                ))
+                (export "foo" (type 0))
+            )"#,
+        )?;
+
+        let document = extract_tools_from_bytes(&wasm)?;
+        assert_eq!(document.world.as_deref(), Some("greeter"));
+        assert_eq!(document.tools.len(), 1);
+        let tool = &document.tools[0];
+        assert_eq!(tool.name, "foo_bar_greet_hello");
+        assert_eq!(tool.input_schema["required"], json!(["name"]));
+        assert_eq!(tool.output_schema, Some(json!({ "type": "string" })));
+
+        assert!(extract_tools_from_bytes(b"not wasm").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tools_document_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let raw = json!({
+            "tools": [{
+                "name": "fetch",
+                "description": "Fetches a URL",
+                "inputSchema": { "type": "object", "properties": {}, "required": [] },
+                "outputSchema": { "type": "string" }
+            }, {
+                "name": "ping",
+                "inputSchema": { "type": "object" }
+            }]
+        });
+        let document: ToolsDocument = serde_json::from_value(raw.clone())?;
+        assert_eq!(
+            document.tools[0].description.as_deref(),
+            Some("Fetches a URL")
+        );
+        assert_eq!(document.tools[1].output_schema, None);
+        assert_eq!(document.world, None);
+        assert_eq!(serde_json::to_value(&document)?, raw);
+        Ok(())
+    }
+
     #[test]
     fn test_function_identifier_equality() {
         let id1 = FunctionIdentifier {
@@ -1834,10 +3109,7 @@ mod tests {
         assert!(validate_tool_name(&tool.normalized_name).is_ok());
 
         // The schema should have the normalized name
-        assert_eq!(
-            tool.schema["name"].as_str().unwrap(),
-            "foo_foo_foo_generate"
-        );
+        assert_eq!(tool.schema.name, "foo_foo_foo_generate");
 
         // The function identifier should preserve the original structure
         assert_eq!(
@@ -1848,4 +3120,50 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_compact_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "shape": {
+                    "oneOf": [
+                        {
+                            "type": "object",
+                            "properties": {
+                                "tag": {"const": "polygon"},
+                                "val": {"type": "array", "items": {"type": "object", "properties": {"x": {"type": "number"}}}}
+                            },
+                            "required": ["tag", "val"]
+                        },
+                        {
+                            "type": "object",
+                            "properties": {"tag": {"const": "empty"}},
+                            "required": ["tag"]
+                        }
+                    ]
+                }
+            },
+            "required": ["name", "shape"]
+        });
+
+        let compact = compact_schema(&schema, 2).unwrap();
+        assert_eq!(compact["properties"]["name"], json!({"type": "string"}));
+        let cases = compact["properties"]["shape"]["oneOf"].as_array().unwrap();
+        assert_eq!(cases[0]["properties"]["tag"], json!({"const": "polygon"}));
+        assert_eq!(
+            cases[0]["properties"]["val"],
+            json!({"type": "array", "description": "list of objects"})
+        );
+        assert_eq!(compact["required"], schema["required"]);
+
+        let compact = compact_schema(&schema, 0).unwrap();
+        assert_eq!(
+            compact["properties"]["shape"],
+            json!({"description": "one of: polygon, empty"})
+        );
+
+        assert!(compact_schema(&schema, 4).is_none());
+    }
 }
@@ -0,0 +1,356 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! An access log of the HTTP transport, so that network activity can be traced to the MCP
+//! sessions and tool calls it belongs to.
+
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderMap, Uri};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Name of the access log in the [`APPROVALS_DIR`](wassette::APPROVALS_DIR) of the plugin
+/// directory, next to the approval audit log
+pub const ACCESS_LOG_FILE: &str = "access.jsonl";
+
+/// How long the entry of an event stream waits for the first event to learn the session ID
+const FIRST_EVENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A request in the access log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessLogEntry {
+    /// When the request was received, in milliseconds since the Unix epoch
+    pub time_ms: u64,
+    /// HTTP method of the request
+    pub method: String,
+    /// Path of the request, without its query
+    pub path: String,
+    /// ID of the MCP session the request belongs to, if it names one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Status code of the response
+    pub status: u16,
+    /// Time until the response started, in milliseconds. Event streams stay open much longer
+    pub duration_ms: u64,
+    /// Address of the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer: Option<IpAddr>,
+    /// Address of the trusted proxy that forwarded the request, if it was forwarded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarded_by: Option<IpAddr>,
+}
+
+/// Writes the requests to an HTTP router to an access log
+#[derive(Clone)]
+pub struct AccessLog {
+    file: Arc<Mutex<tokio::fs::File>>,
+    trusted_proxies: Arc<[IpAddr]>,
+}
+
+impl AccessLog {
+    /// Opens the access log at `path` for appending, creating it and its directory if needed.
+    /// `X-Forwarded-For` is only honored for requests from `trusted_proxies`.
+    pub async fn open(path: impl AsRef<Path>, trusted_proxies: Vec<IpAddr>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .context("Failed to create access log directory")?;
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Failed to open access log: {}", path.display()))?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            trusted_proxies: trusted_proxies.into(),
+        })
+    }
+
+    /// Returns the path of the access log in a plugin directory
+    pub fn path_in(plugin_dir: impl AsRef<Path>) -> PathBuf {
+        plugin_dir
+            .as_ref()
+            .join(wassette::APPROVALS_DIR)
+            .join(ACCESS_LOG_FILE)
+    }
+
+    /// Logs every request to `router`. The peer address is only known if the router is served
+    /// with [`into_make_service_with_connect_info`](Router::into_make_service_with_connect_info).
+    pub fn layer(self, router: Router) -> Router {
+        router.layer(middleware::from_fn_with_state(self, log_request))
+    }
+
+    async fn append(&self, entry: &AccessLogEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+async fn log_request(State(log): State<AccessLog>, request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let time_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let mut session_id = session_id(request.uri());
+    let (peer, forwarded_by) = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => {
+            client_address(addr.ip(), request.headers(), &log.trusted_proxies)
+        }
+        None => (None, None),
+    };
+
+    let mut response = next.run(request).await;
+    if session_id.is_none() && is_event_stream(response.headers()) {
+        let (parts, body) = response.into_parts();
+        let (first_event, body) = peek_first_event(body).await;
+        session_id = first_event.as_deref().and_then(endpoint_session_id);
+        response = Response::from_parts(parts, body);
+    }
+
+    let entry = AccessLogEntry {
+        time_ms,
+        method,
+        path,
+        session_id,
+        status: response.status().as_u16(),
+        duration_ms: start.elapsed().as_millis() as u64,
+        peer,
+        forwarded_by,
+    };
+    if let Err(e) = log.append(&entry).await {
+        warn!(error = %e, "Failed to write access log");
+    }
+    response
+}
+
+/// Returns the MCP session a request names in its `sessionId` query parameter
+pub(crate) fn session_id(uri: &Uri) -> Option<String> {
+    uri.query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("sessionId="))
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+/// Reads the first event of a stream, and returns it with a body that still starts with it.
+/// Gives up after [`FIRST_EVENT_TIMEOUT`] so that quiet streams aren't held back.
+async fn peek_first_event(body: Body) -> (Option<Vec<u8>>, Body) {
+    let mut stream = body.into_data_stream();
+    match tokio::time::timeout(FIRST_EVENT_TIMEOUT, stream.next()).await {
+        Ok(Some(Ok(first))) => (
+            Some(first.to_vec()),
+            Body::from_stream(futures::stream::once(async move { Ok(first) }).chain(stream)),
+        ),
+        Ok(Some(Err(e))) => (
+            None,
+            Body::from_stream(futures::stream::once(async move { Err(e) }).chain(stream)),
+        ),
+        Ok(None) | Err(_) => (None, Body::from_stream(stream)),
+    }
+}
+
+/// Returns the session that the `endpoint` event of the SSE transport tells its client to post to
+fn endpoint_session_id(event: &[u8]) -> Option<String> {
+    let event = std::str::from_utf8(event).ok()?;
+    let (_, rest) = event.split_once("sessionId=")?;
+    let id = rest
+        .split(|c: char| c == '&' || c.is_whitespace())
+        .next()
+        .unwrap_or_default();
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+/// Returns the address of the client that made a request from `peer`, and the proxy that
+/// forwarded it if `peer` is a trusted proxy
+fn client_address(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpAddr],
+) -> (Option<IpAddr>, Option<IpAddr>) {
+    if !trusted_proxies.contains(&peer) {
+        return (Some(peer), None);
+    }
+    let forwarded: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    // The hop nearest to the client that a trusted proxy vouched for
+    let mut client = None;
+    for hop in forwarded.iter().rev() {
+        let Ok(address) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = Some(address);
+        if !trusted_proxies.contains(&address) {
+            break;
+        }
+    }
+    match client {
+        Some(client) => (Some(client), Some(peer)),
+        None => (Some(peer), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use axum::http::HeaderValue;
+    use axum::response::sse::{Event, Sse};
+    use axum::routing::get;
+
+    use super::*;
+
+    #[test]
+    fn test_session_id() {
+        let uri: Uri = "/message?sessionId=abc-123".parse().unwrap();
+        assert_eq!(session_id(&uri).as_deref(), Some("abc-123"));
+        let uri: Uri = "/message?other=1&sessionId=abc".parse().unwrap();
+        assert_eq!(session_id(&uri).as_deref(), Some("abc"));
+        let uri: Uri = "/sse".parse().unwrap();
+        assert_eq!(session_id(&uri), None);
+    }
+
+    #[test]
+    fn test_client_address_honors_only_trusted_proxies() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.1, 203.0.113.7, 10.0.0.2"),
+        );
+        let trusted = [proxy, "10.0.0.2".parse().unwrap()];
+
+        // The spoofable first entry is skipped for the last one added by an untrusted hop
+        assert_eq!(
+            client_address(proxy, &headers, &trusted),
+            (Some(client), Some(proxy))
+        );
+        // Anyone else can't pretend to be someone
+        assert_eq!(
+            client_address(client, &headers, &trusted),
+            (Some(client), None)
+        );
+        assert_eq!(
+            client_address(proxy, &HeaderMap::new(), &trusted),
+            (Some(proxy), None)
+        );
+
+        // Hops left of one that isn't an address weren't added by a trusted proxy
+        let proxy_hop: IpAddr = "10.0.0.2".parse().unwrap();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.1, garbage, 10.0.0.2"),
+        );
+        assert_eq!(
+            client_address(proxy, &headers, &trusted),
+            (Some(proxy_hop), Some(proxy))
+        );
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.1, garbage"),
+        );
+        assert_eq!(
+            client_address(proxy, &headers, &trusted),
+            (Some(proxy), None)
+        );
+    }
+
+    #[test]
+    fn test_endpoint_session_id() {
+        let event = b"event: endpoint\ndata: /mcp/message?sessionId=abc-123\n\n";
+        assert_eq!(endpoint_session_id(event).as_deref(), Some("abc-123"));
+        assert_eq!(endpoint_session_id(b"data: /message\n\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_logged() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = AccessLog::path_in(tempdir.path());
+        let log = AccessLog::open(&path, Vec::new()).await?;
+        let app = log.layer(
+            Router::new()
+                .route("/message", get(|| async { "ok" }))
+                .route(
+                    "/sse",
+                    get(|| async {
+                        // Announces the session like the SSE transport, then stays open
+                        let endpoint = Event::default()
+                            .event("endpoint")
+                            .data("/message?sessionId=s2");
+                        Sse::new(
+                            futures::stream::once(async move { Ok::<_, Infallible>(endpoint) })
+                                .chain(futures::stream::pending()),
+                        )
+                    }),
+                ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+
+        reqwest::get(format!("http://{addr}/message?sessionId=s1")).await?;
+        reqwest::Client::new()
+            .post(format!("http://{addr}/message"))
+            .send()
+            .await?;
+        let _events = reqwest::get(format!("http://{addr}/sse")).await?;
+
+        let entries: Vec<AccessLogEntry> = tokio::fs::read_to_string(&path)
+            .await?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].method, "GET");
+        assert_eq!(entries[0].path, "/message");
+        assert_eq!(entries[0].session_id.as_deref(), Some("s1"));
+        assert_eq!(entries[0].status, 200);
+        assert_eq!(entries[0].peer, Some(addr.ip()));
+        assert_eq!(entries[1].method, "POST");
+        assert_eq!(entries[1].status, 405);
+        assert_eq!(entries[1].session_id, None);
+        assert_eq!(entries[2].path, "/sse");
+        assert_eq!(entries[2].session_id.as_deref(), Some("s2"));
+        Ok(())
+    }
+}
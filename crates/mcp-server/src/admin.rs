@@ -0,0 +1,369 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! A REST management API as an [`axum::Router`], served on its own listener and guarded by the
+//! admin token.
+
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+use wassette::{
+    LifecycleManager, LoadResult, ProvisionManifest, ProvisionReport, ServerStatus, UnloadOptions,
+    UsageStats,
+};
+
+#[derive(Clone)]
+struct AdminState {
+    lifecycle_manager: LifecycleManager,
+    token: Arc<str>,
+}
+
+/// Returns a router serving the management API, which rejects requests without `token`
+pub fn router(lifecycle_manager: LifecycleManager, token: impl Into<Arc<str>>) -> Router {
+    let state = AdminState {
+        lifecycle_manager,
+        token: token.into(),
+    };
+    Router::new()
+        .route("/components", get(list_components).post(load_component))
+        .route("/components/{id}", delete(unload_component))
+        .route(
+            "/components/{id}/policy",
+            put(attach_policy).delete(detach_policy),
+        )
+        .route("/manifests", post(apply_manifest))
+        .route("/stats", get(stats))
+        .route("/status", get(status))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+/// An error answered with its status code and `{"error": "..."}`
+struct ApiError(StatusCode, anyhow::Error);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({"error": format!("{:#}", self.1)}))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Compares tokens in constant time, so their contents can't be guessed from response times
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn require_token(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| tokens_match(&state.token, token));
+    if !authorized {
+        return ApiError(
+            StatusCode::UNAUTHORIZED,
+            anyhow::anyhow!("Missing or wrong admin token"),
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+/// Resolves an ID or name from a path to the ID of a loaded component
+async fn resolve(lifecycle_manager: &LifecycleManager, id: &str) -> ApiResult<String> {
+    let id = lifecycle_manager
+        .resolve_component_id(id)
+        .await
+        .map_err(|e| ApiError(StatusCode::CONFLICT, e))?;
+    if lifecycle_manager.get_component(&id).await.is_none() {
+        return Err(ApiError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Component not found: {id}"),
+        ));
+    }
+    Ok(id)
+}
+
+#[derive(Deserialize)]
+struct SourceBody {
+    uri: String,
+}
+
+async fn list_components(State(state): State<AdminState>) -> Json<Vec<Value>> {
+    let lifecycle_manager = &state.lifecycle_manager;
+    let mut components = Vec::new();
+    for id in lifecycle_manager.list_components().await {
+        let tools: Vec<String> = lifecycle_manager
+            .get_component_schema(&id)
+            .await
+            .map(|schema| schema.tools.into_iter().map(|tool| tool.name).collect())
+            .unwrap_or_default();
+        let labels = lifecycle_manager
+            .get_component_labels(&id)
+            .await
+            .unwrap_or_default();
+        let policy = lifecycle_manager
+            .get_policy_info(&id)
+            .await
+            .map(|info| info.source_uri);
+        components.push(json!({
+            "id": id,
+            "labels": labels,
+            "tools": tools,
+            "policy": policy,
+        }));
+    }
+    Json(components)
+}
+
+async fn load_component(
+    State(state): State<AdminState>,
+    Json(body): Json<SourceBody>,
+) -> ApiResult<(StatusCode, Json<Value>)> {
+    info!(uri = %body.uri, "Loading component through the management API");
+    let (id, load_result) = state
+        .lifecycle_manager
+        .load_component(&body.uri)
+        .await
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e))?;
+    Ok(match load_result {
+        LoadResult::New => (StatusCode::CREATED, Json(json!({"id": id}))),
+        LoadResult::Replaced(diff) => {
+            (StatusCode::OK, Json(json!({"id": id, "schema_diff": diff})))
+        }
+    })
+}
+
+#[derive(Deserialize)]
+struct UnloadQuery {
+    #[serde(default)]
+    keep_policy: bool,
+    #[serde(default)]
+    keep_data: bool,
+}
+
+async fn unload_component(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Query(query): Query<UnloadQuery>,
+) -> ApiResult<StatusCode> {
+    let id = resolve(&state.lifecycle_manager, &id).await?;
+    info!(component_id = %id, "Unloading component through the management API");
+    state
+        .lifecycle_manager
+        .unload_component_with_options(
+            &id,
+            &UnloadOptions {
+                keep_policy: query.keep_policy,
+                keep_data: query.keep_data,
+            },
+        )
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn attach_policy(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Json(body): Json<SourceBody>,
+) -> ApiResult<StatusCode> {
+    let id = resolve(&state.lifecycle_manager, &id).await?;
+    state
+        .lifecycle_manager
+        .attach_policy(&id, &body.uri)
+        .await
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn detach_policy(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+) -> ApiResult<StatusCode> {
+    let id = resolve(&state.lifecycle_manager, &id).await?;
+    state
+        .lifecycle_manager
+        .detach_policy(&id)
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Reconciles the components with a manifest, in the classic form or made of Kubernetes-style
+/// resources. Components that fail are reported in the body rather than failing the request.
+async fn apply_manifest(
+    State(state): State<AdminState>,
+    body: String,
+) -> ApiResult<Json<ProvisionReport>> {
+    let manifest =
+        ProvisionManifest::parse(&body).map_err(|e| ApiError(StatusCode::BAD_REQUEST, e))?;
+    let report = state.lifecycle_manager.provision(&manifest).await;
+    info!(%report, "Applied a manifest through the management API");
+    Ok(Json(report))
+}
+
+#[derive(Deserialize)]
+struct StatsQuery {
+    component: Option<String>,
+}
+
+async fn stats(
+    State(state): State<AdminState>,
+    Query(query): Query<StatsQuery>,
+) -> ApiResult<Json<UsageStats>> {
+    let component_id = match &query.component {
+        Some(id) => Some(resolve(&state.lifecycle_manager, id).await?),
+        None => None,
+    };
+    Ok(Json(
+        state
+            .lifecycle_manager
+            .get_tool_stats(component_id.as_deref())
+            .await,
+    ))
+}
+
+async fn status(State(state): State<AdminState>) -> Json<ServerStatus> {
+    Json(state.lifecycle_manager.server_status().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "s3cret";
+
+    async fn serve() -> anyhow::Result<(tempfile::TempDir, String)> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = LifecycleManager::new(tempdir.path()).await?;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, router(lifecycle_manager, TOKEN)).await });
+        Ok((tempdir, format!("http://{addr}")))
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3cret", "s3crex"));
+        assert!(!tokens_match("s3cret", "s3cre"));
+    }
+
+    #[tokio::test]
+    async fn test_requires_token() -> anyhow::Result<()> {
+        let (_tempdir, base) = serve().await?;
+        let client = reqwest::Client::new();
+
+        let response = client.get(format!("{base}/components")).send().await?;
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = client
+            .get(format!("{base}/components"))
+            .bearer_auth("wrong")
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .get(format!("{base}/components"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.json::<Value>().await?, json!([]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_management_endpoints() -> anyhow::Result<()> {
+        let (_tempdir, base) = serve().await?;
+        let client = reqwest::Client::new();
+
+        let status: Value = client
+            .get(format!("{base}/status"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await?
+            .json()
+            .await?;
+        assert_eq!(status["loaded_components"], 0);
+
+        let stats: Value = client
+            .get(format!("{base}/stats"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await?
+            .json()
+            .await?;
+        assert_eq!(stats["components"], json!({}));
+
+        let response = client
+            .delete(format!("{base}/components/local%2Fmissing"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let response = client
+            .post(format!("{base}/components"))
+            .bearer_auth(TOKEN)
+            .json(&json!({"uri": "file:///does/not/exist.wasm"}))
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert!(response.json::<Value>().await?["error"].is_string());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_apply_manifest() -> anyhow::Result<()> {
+        let (_tempdir, base) = serve().await?;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .post(format!("{base}/manifests"))
+            .bearer_auth(TOKEN)
+            .body("apiVersion: wassette.microsoft.com/v1alpha1\nkind: Deployment\n")
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let report: Value = client
+            .post(format!("{base}/manifests"))
+            .bearer_auth(TOKEN)
+            .body(
+                "apiVersion: wassette.microsoft.com/v1alpha1
+kind: Component
+metadata:
+  name: missing
+spec:
+  uri: file:///does/not/exist.wasm
+",
+            )
+            .send()
+            .await?
+            .json()
+            .await?;
+        assert_eq!(report["installed"], json!([]));
+        assert_eq!(
+            report["failed"][0]["component"],
+            "file:///does/not/exist.wasm"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The `execute-batch` builtin, which runs several component tool calls in one request.
+//!
+//! Steps run in order. An argument of a step may take the result of an earlier step with a
+//! reference like `{"$step": "search", "pointer": "/items/0/url"}`, where `pointer` is an
+//! optional JSON pointer into the result.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use rmcp::model::{CallToolRequestParam, CallToolResult, Content};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tracing::{info, instrument, warn};
+use wassette::{CorrelationIds, LifecycleManager};
+
+use crate::components::{extract_args_from_request, handle_component_call};
+use crate::i18n::missing_argument;
+use crate::logging::McpLogger;
+
+/// The key of a reference to the result of an earlier step
+const STEP_REFERENCE_KEY: &str = "$step";
+
+/// The key of the JSON pointer into the referenced result
+const POINTER_KEY: &str = "pointer";
+
+/// Most steps a single batch may have
+const MAX_BATCH_STEPS: usize = 32;
+
+#[derive(Debug, Deserialize)]
+struct BatchStep {
+    /// Name other steps use to reference the result of this one, defaults to its index
+    id: Option<String>,
+    tool: String,
+    #[serde(default)]
+    arguments: Map<String, Value>,
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn handle_execute_batch(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    logger: &McpLogger,
+    correlation: CorrelationIds,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let steps: Vec<BatchStep> = serde_json::from_value(
+        args.get("steps")
+            .cloned()
+            .ok_or_else(|| missing_argument("steps"))?,
+    )
+    .context("Argument 'steps' must be a list of {\"tool\", \"arguments\"} objects")?;
+    if steps.len() > MAX_BATCH_STEPS {
+        bail!(
+            "A batch may have at most {MAX_BATCH_STEPS} steps, got {}",
+            steps.len()
+        );
+    }
+    let stop_on_error = args
+        .get("stop_on_error")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+
+    info!(steps = steps.len(), stop_on_error, "Executing batch");
+
+    let mut results: HashMap<String, Value> = HashMap::new();
+    let mut reports = Vec::with_capacity(steps.len());
+    let mut failed = false;
+    for (index, step) in steps.into_iter().enumerate() {
+        let id = step.id.unwrap_or_else(|| index.to_string());
+        if failed && stop_on_error {
+            reports.push(json!({"id": id, "tool": step.tool, "status": "skipped"}));
+            continue;
+        }
+        match run_step(
+            &step.tool,
+            step.arguments,
+            &results,
+            lifecycle_manager,
+            logger,
+            correlation.clone(),
+        )
+        .await
+        {
+            Ok(result) => {
+                reports
+                    .push(json!({"id": id, "tool": step.tool, "status": "ok", "result": result}));
+                results.insert(id, result);
+            }
+            Err(e) => {
+                warn!(step = %id, tool = %step.tool, error = %e, "Batch step failed");
+                failed = true;
+                reports.push(json!({
+                    "id": id,
+                    "tool": step.tool,
+                    "status": "error",
+                    "error": format!("{e:#}")
+                }));
+            }
+        }
+    }
+
+    let status_text = serde_json::to_string(&json!({
+        "status": if failed { "failed" } else { "completed" },
+        "steps": reports,
+    }))?;
+
+    Ok(CallToolResult {
+        content: vec![Content::text(status_text)],
+        is_error: failed.then_some(true),
+    })
+}
+
+/// Calls the tool of a single step, returning its result as JSON if it is JSON and as a string
+/// otherwise
+async fn run_step(
+    tool: &str,
+    arguments: Map<String, Value>,
+    results: &HashMap<String, Value>,
+    lifecycle_manager: &LifecycleManager,
+    logger: &McpLogger,
+    correlation: CorrelationIds,
+) -> Result<Value> {
+    if tool == "execute-batch" {
+        bail!("Batches can't be nested");
+    }
+    let Value::Object(arguments) = resolve_references(Value::Object(arguments), results)? else {
+        bail!("The arguments of a step must be an object");
+    };
+    let req = CallToolRequestParam {
+        name: tool.to_string().into(),
+        arguments: Some(arguments),
+    };
+    let result = handle_component_call(&req, lifecycle_manager, logger, correlation).await?;
+    let text = result
+        .content
+        .first()
+        .and_then(|content| content.as_text())
+        .map(|text| text.text.clone())
+        .unwrap_or_default();
+    if result.is_error == Some(true) {
+        bail!("{text}");
+    }
+    Ok(serde_json::from_str(&text).unwrap_or(Value::String(text)))
+}
+
+/// Replaces every step reference in `value` with the result it points to
+fn resolve_references(value: Value, results: &HashMap<String, Value>) -> Result<Value> {
+    match value {
+        Value::Object(map) => {
+            if let Some(step) = map.get(STEP_REFERENCE_KEY) {
+                let step = step
+                    .as_str()
+                    .with_context(|| format!("'{STEP_REFERENCE_KEY}' must name a step"))?;
+                let result = results.get(step).with_context(|| {
+                    format!("Step '{step}' is not an earlier step that succeeded")
+                })?;
+                return match map.get(POINTER_KEY).and_then(|p| p.as_str()) {
+                    Some(pointer) => result.pointer(pointer).cloned().with_context(|| {
+                        format!("The result of step '{step}' has nothing at '{pointer}'")
+                    }),
+                    None => Ok(result.clone()),
+                };
+            }
+            map.into_iter()
+                .map(|(key, value)| Ok((key, resolve_references(value, results)?)))
+                .collect::<Result<Map<_, _>>>()
+                .map(Value::Object)
+        }
+        Value::Array(values) => values
+            .into_iter()
+            .map(|value| resolve_references(value, results))
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array),
+        other => Ok(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_references() -> Result<()> {
+        let results = HashMap::from([(
+            "search".to_string(),
+            json!({"items": [{"url": "https://example.com"}]}),
+        )]);
+
+        let resolved = resolve_references(
+            json!({
+                "url": {"$step": "search", "pointer": "/items/0/url"},
+                "all": [{"$step": "search"}],
+                "limit": 3
+            }),
+            &results,
+        )?;
+        assert_eq!(
+            resolved,
+            json!({
+                "url": "https://example.com",
+                "all": [{"items": [{"url": "https://example.com"}]}],
+                "limit": 3
+            })
+        );
+
+        assert!(resolve_references(json!({"a": {"$step": "missing"}}), &results).is_err());
+        assert!(resolve_references(
+            json!({"a": {"$step": "search", "pointer": "/nothing"}}),
+            &results
+        )
+        .is_err());
+        Ok(())
+    }
+}
@@ -6,76 +6,108 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use futures::stream::{self, StreamExt};
-use rmcp::model::{CallToolRequestParam, CallToolResult, Content, Tool};
-use rmcp::{Peer, RoleServer};
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, LoggingLevel, Tool, ToolAnnotations,
+};
 use serde_json::{json, Value};
-use tracing::{debug, error, info, instrument};
-use wassette::LifecycleManager;
+use tracing::{debug, error, info, instrument, warn};
+use wassette::{
+    CallOptions, CoreDump, CorrelationIds, LabelSelector, LifecycleManager, LoadResult,
+    Permissions, RecordedTrace, ToolSchema, UnloadOptions, UnloadResult, UnsupportedImports,
+    CAPABILITIES_ARGUMENT_KEY, DEFAULT_LOAD_CONCURRENCY, PROFILE_ARGUMENT_KEY, RECORD_ARGUMENT_KEY,
+};
+
+use crate::i18n::{missing_argument, unknown_tool};
+use crate::logging::{McpLogger, HOST_LOGGER};
+use crate::notifications::{Notification, NotificationQueue};
+use crate::validation::{validate_arguments, validation_report};
 
 #[instrument(skip(lifecycle_manager))]
 pub(crate) async fn get_component_tools(lifecycle_manager: &LifecycleManager) -> Result<Vec<Tool>> {
-    debug!("Listing components");
-    let component_ids = lifecycle_manager.list_components().await;
-
-    info!(count = component_ids.len(), "Found components");
-    let mut tools = Vec::new();
-
-    for id in component_ids {
-        debug!(component_id = %id, "Getting component details");
-        if let Some(schema) = lifecycle_manager.get_component_schema(&id).await {
-            if let Some(arr) = schema.get("tools").and_then(|v| v.as_array()) {
-                let tool_count = arr.len();
-                debug!(component_id = %id, tool_count, "Found tools in component");
-                for tool_json in arr {
-                    if let Some(tool) = parse_tool_schema(tool_json) {
-                        tools.push(tool);
-                    }
-                }
-            }
-        }
-    }
+    debug!("Listing component tools");
+    // The lifecycle manager lists tools in a stable order, which clients rely on for pagination
+    let tools: Vec<Tool> = lifecycle_manager
+        .list_visible_tools()
+        .await
+        .iter()
+        .map(tool_from_schema)
+        .collect();
     info!(total_tools = tools.len(), "Total tools collected");
     Ok(tools)
 }
 
-#[instrument(skip(lifecycle_manager))]
+/// Describes a loaded component to the agent: whether it replaced a component, the tools that
+/// became available, and whether a policy grants what the component needs
+async fn load_status(
+    lifecycle_manager: &LifecycleManager,
+    id: &str,
+    load_result: &LoadResult,
+) -> Value {
+    let mut status = json!({
+        "status": "component loaded",
+        "id": id,
+    });
+    let new_tools = match load_result {
+        LoadResult::New => {
+            status["load_result"] = json!("new");
+            lifecycle_manager
+                .get_component_schema(id)
+                .await
+                .map(|schema| schema.tools.into_iter().map(|tool| tool.name).collect())
+                .unwrap_or_default()
+        }
+        LoadResult::Replaced(diff) => {
+            // Tell the agent how the tools of the component it replaced changed
+            status["load_result"] = json!("replaced");
+            status["schema_diff"] = json!(diff);
+            diff.added.clone()
+        }
+    };
+    status["new_tools"] = json!(new_tools);
+    let policy = lifecycle_manager.get_policy_info(id).await;
+    status["policy"] = json!({
+        "attached": policy.is_some(),
+        "source_uri": policy.map(|info| info.source_uri),
+        "required_capabilities": lifecycle_manager
+            .get_required_capabilities(id)
+            .await
+            .unwrap_or_default(),
+        "missing_capabilities": lifecycle_manager
+            .get_capability_gaps(id)
+            .await
+            .unwrap_or_default(),
+    });
+    status
+}
+
+#[instrument(skip(lifecycle_manager, notifications))]
 pub(crate) async fn handle_load_component(
     req: &CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
-    server_peer: Peer<RoleServer>,
+    notifications: &NotificationQueue,
 ) -> Result<CallToolResult> {
     let args = extract_args_from_request(req)?;
     let path = args
         .get("path")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'path'"))?;
+        .ok_or_else(|| missing_argument("path"))?;
 
     info!(path, "Loading component");
 
     let result = lifecycle_manager.load_component(path).await;
 
     match result {
-        Ok((id, _load_result)) => {
-            let status_text = serde_json::to_string(&json!({
-                "status": "component loaded",
-                "id": id
-            }))?;
+        Ok((id, load_result)) => {
+            let status = load_status(lifecycle_manager, &id, &load_result).await;
+            let status_text = serde_json::to_string(&status)?;
 
             let contents = vec![Content::text(status_text)];
 
             info!(
                 component_id = %id,
-                "Notifying server peer about tool list change after loading component"
+                "Queued tool list changed notification after loading component"
             );
-            // Notify the server peer about the tool list change
-            if let Err(e) = server_peer.notify_tool_list_changed().await {
-                error!(error = %e, "Failed to send tool list change notification");
-            } else {
-                info!(
-                    component_id = %id,
-                    "Sent tool list changed notification after loading component"
-                );
-            }
+            notifications.push(Notification::ToolListChanged);
 
             Ok(CallToolResult {
                 content: contents,
@@ -84,6 +116,17 @@ pub(crate) async fn handle_load_component(
         }
         Err(e) => {
             error!(error = %e, path, "Failed to load component");
+            if let Some(unsupported) = e.downcast_ref::<UnsupportedImports>() {
+                let report = json!({
+                    "status": "error",
+                    "error": "Component imports interfaces that wassette doesn't provide",
+                    "unsupported_imports": unsupported.imports,
+                });
+                return Ok(CallToolResult {
+                    content: vec![Content::text(serde_json::to_string(&report)?)],
+                    is_error: Some(true),
+                });
+            }
             Err(anyhow::anyhow!(
                 "Failed to load component: {}. Error: {}",
                 path,
@@ -93,38 +136,135 @@ pub(crate) async fn handle_load_component(
     }
 }
 
-#[instrument(skip(lifecycle_manager))]
+/// Largest number of components `load-components` loads at once
+const MAX_LOAD_CONCURRENCY: usize = 16;
+
+#[instrument(skip(lifecycle_manager, notifications))]
+pub(crate) async fn handle_load_components(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    notifications: &NotificationQueue,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+    let paths = args
+        .get("paths")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| missing_argument("paths"))?
+        .iter()
+        .map(|path| {
+            path.as_str()
+                .map(String::from)
+                .ok_or_else(|| anyhow::anyhow!("Every entry of 'paths' must be a string"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let concurrency = args
+        .get("concurrency")
+        .and_then(|v| v.as_u64())
+        .map(|n| (n as usize).clamp(1, MAX_LOAD_CONCURRENCY))
+        .unwrap_or(DEFAULT_LOAD_CONCURRENCY);
+
+    info!(count = paths.len(), concurrency, "Loading components");
+    let results = lifecycle_manager
+        .load_components(&paths, concurrency)
+        .await
+        .into_iter()
+        .map(|(path, result)| match result {
+            Ok((id, load_result)) => {
+                let mut report = json!({"path": path, "status": "loaded", "id": id});
+                if let LoadResult::Replaced(diff) = &load_result {
+                    report["schema_diff"] = json!(diff);
+                }
+                report
+            }
+            Err(e) => {
+                error!(error = %e, path, "Failed to load component");
+                let mut report = json!({"path": path, "status": "error", "error": e.to_string()});
+                if let Some(unsupported) = e.downcast_ref::<UnsupportedImports>() {
+                    report["unsupported_imports"] = json!(unsupported.imports);
+                }
+                report
+            }
+        })
+        .collect::<Vec<_>>();
+    let loaded = results.iter().filter(|r| r["status"] == "loaded").count();
+    let failed = results.len() - loaded;
+
+    if loaded > 0 {
+        notifications.push(Notification::ToolListChanged);
+    }
+
+    let status_text = serde_json::to_string(&json!({
+        "status": if failed == 0 { "components loaded" } else { "some components failed to load" },
+        "loaded": loaded,
+        "failed": failed,
+        "results": results,
+    }))?;
+    Ok(CallToolResult {
+        content: vec![Content::text(status_text)],
+        is_error: (loaded == 0 && failed > 0).then_some(true),
+    })
+}
+
+#[instrument(skip(lifecycle_manager, notifications))]
 pub(crate) async fn handle_unload_component(
     req: &CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
-    server_peer: Peer<RoleServer>,
+    notifications: &NotificationQueue,
 ) -> Result<CallToolResult> {
     let args = extract_args_from_request(req)?;
 
     let id = args
         .get("id")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing 'id' in arguments"))?;
-
-    info!(component_id = %id, "Unloading component");
-
-    match lifecycle_manager.unload_component(id).await {
-        Ok(()) => {
+        .ok_or_else(|| missing_argument("id"))?;
+    let id = &lifecycle_manager.resolve_component_id(id).await?;
+    let options = UnloadOptions {
+        keep_policy: args
+            .get("keep_policy")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+        keep_data: args
+            .get("keep_data")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+    };
+
+    info!(
+        component_id = %id,
+        keep_policy = options.keep_policy,
+        keep_data = options.keep_data,
+        "Unloading component"
+    );
+
+    match lifecycle_manager
+        .unload_component_with_options(id, &options)
+        .await
+    {
+        Ok(UnloadResult::NotFound) => {
+            let status_text = serde_json::to_string(&json!({
+                "status": "component not found",
+                "id": id,
+                "removed": false
+            }))?;
+            Ok(CallToolResult {
+                content: vec![Content::text(status_text)],
+                is_error: None,
+            })
+        }
+        Ok(UnloadResult::Removed) => {
             let status_text = serde_json::to_string(&json!({
                 "status": "component unloaded successfully",
-                "id": id
+                "id": id,
+                "removed": true
             }))?;
 
             let contents = vec![Content::text(status_text)];
 
-            if let Err(e) = server_peer.notify_tool_list_changed().await {
-                error!(error = %e, "Failed to send tool list change notification");
-            } else {
-                info!(
-                    component_id = %id,
-                    "Sent tool list changed notification after unloading component"
-                );
-            }
+            notifications.push(Notification::ToolListChanged);
+            info!(
+                component_id = %id,
+                "Queued tool list changed notification after unloading component"
+            );
 
             Ok(CallToolResult {
                 content: contents,
@@ -149,16 +289,39 @@ pub(crate) async fn handle_unload_component(
     }
 }
 
-#[instrument(skip(lifecycle_manager))]
+#[instrument(skip(lifecycle_manager, logger))]
 pub(crate) async fn handle_component_call(
     req: &CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
+    logger: &McpLogger,
+    correlation: CorrelationIds,
 ) -> Result<CallToolResult> {
-    let args = extract_args_from_request(req)?;
+    let mut args = extract_args_from_request(req)?;
+    // Not arguments of the tool, but requests to profile, record or narrow this call
+    let profile = args
+        .remove(PROFILE_ARGUMENT_KEY)
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let record = args
+        .remove(RECORD_ARGUMENT_KEY)
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let capabilities = args
+        .remove(CAPABILITIES_ARGUMENT_KEY)
+        .map(serde_json::from_value::<Permissions>)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("Invalid '{CAPABILITIES_ARGUMENT_KEY}' argument: {e}"))?;
 
     let method_name = req.name.to_string();
-    info!(function_name = %method_name, "Calling function");
+    info!(function_name = %method_name, profile, record, "Calling function");
 
+    let Some(input_schema) = lifecycle_manager
+        .get_tool_schema(&method_name)
+        .await
+        .map(|schema| schema.input_schema)
+    else {
+        return Err(unknown_tool(&method_name));
+    };
     let component_id = lifecycle_manager
         .get_component_id_for_tool(&method_name)
         .await
@@ -166,14 +329,99 @@ pub(crate) async fn handle_component_call(
             anyhow::anyhow!("Failed to find component for tool '{}': {}", method_name, e)
         })?;
 
+    let args = Value::Object(args);
+    match validate_arguments(&input_schema, &args) {
+        Ok(violations) if !violations.is_empty() => {
+            warn!(
+                function_name = %method_name,
+                violations = violations.len(),
+                "Rejecting call with invalid arguments"
+            );
+            let report = validation_report(&method_name, &violations);
+            return Ok(CallToolResult {
+                content: vec![Content::text(serde_json::to_string(&report)?)],
+                is_error: Some(true),
+            });
+        }
+        Ok(_) => {}
+        Err(e) => {
+            warn!(function_name = %method_name, error = %e, "Skipping argument validation");
+            logger.log(
+                LoggingLevel::Warning,
+                HOST_LOGGER,
+                format!("Skipping argument validation of '{method_name}': {e}"),
+            );
+        }
+    }
+
+    let parameters = serde_json::to_string(&args)?;
+    if lifecycle_manager
+        .requires_approval(&component_id, &method_name)
+        .await
+        && !lifecycle_manager
+            .is_preview_call(&method_name, &parameters)
+            .await
+    {
+        logger
+            .log(
+                LoggingLevel::Notice,
+                HOST_LOGGER,
+                format!(
+                    "'{method_name}' is destructive and waits for approval, list waiting calls \
+                     with `wassette approval list` and approve one with `wassette approval approve <id>`"
+                ),
+            );
+    }
+
+    let (stderr, forwarder) = logger.forward_component_logs(&component_id);
+    let (events, event_forwarder) = logger.forward_component_events(&component_id);
     let result = lifecycle_manager
-        .execute_component_call(&component_id, &method_name, &serde_json::to_string(&args)?)
+        .execute_component_call_with_options(
+            &component_id,
+            &method_name,
+            &parameters,
+            CallOptions {
+                profile,
+                record,
+                stderr: Some(stderr),
+                events: Some(events),
+                correlation,
+                capabilities,
+                class: None,
+            },
+        )
         .await;
+    // Deliver the component's log messages and events before the result
+    if let Err(e) = forwarder.await {
+        warn!(error = %e, "Failed to forward component logs");
+    }
+    if let Err(e) = event_forwarder.await {
+        warn!(error = %e, "Failed to forward component events");
+    }
 
     match result {
-        Ok(result_str) => {
+        Ok(output) => {
             debug!("Component call successful");
-            let contents = vec![Content::text(result_str)];
+            let mut contents = vec![Content::text(output.result)];
+            if let Some(path) = output.profile_path {
+                contents.push(Content::text(format!(
+                    "Profile written to file://{} (open it with https://profiler.firefox.com)",
+                    path.display()
+                )));
+            }
+            if let Some(path) = output.trace_path {
+                contents.push(Content::text(format!(
+                    "Trace recorded to file://{} (replay it with `wassette replay`)",
+                    path.display()
+                )));
+            }
+            // Advise the caller to move off deprecated tools while they still work
+            if let Some(deprecation) = lifecycle_manager.get_tool_deprecation(&method_name).await {
+                let notice = deprecation.notice(&method_name);
+                warn!(function_name = %method_name, "Called deprecated tool");
+                logger.log(LoggingLevel::Warning, HOST_LOGGER, notice.clone());
+                contents.push(Content::text(format!("Warning: {notice}")));
+            }
 
             Ok(CallToolResult {
                 content: contents,
@@ -182,6 +430,27 @@ pub(crate) async fn handle_component_call(
         }
         Err(e) => {
             error!(error = %e, "Component call failed");
+            let dump = e.downcast_ref::<CoreDump>();
+            let trace = e.downcast_ref::<RecordedTrace>();
+            let message = dump
+                .map(|dump| &dump.message)
+                .or(trace.map(|trace| &trace.message));
+            if let Some(message) = message {
+                let mut report = json!({
+                    "status": "error",
+                    "error": message,
+                });
+                if let Some(dump) = dump {
+                    report["coredump"] = json!(format!("file://{}", dump.path.display()));
+                }
+                if let Some(trace) = trace {
+                    report["trace"] = json!(format!("file://{}", trace.path.display()));
+                }
+                return Ok(CallToolResult {
+                    content: vec![Content::text(serde_json::to_string(&report)?)],
+                    is_error: Some(true),
+                });
+            }
             Err(anyhow::anyhow!(e.to_string()))
         }
     }
@@ -189,36 +458,56 @@ pub(crate) async fn handle_component_call(
 
 #[instrument(skip(lifecycle_manager))]
 pub(crate) async fn handle_list_components(
+    req: &CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
 ) -> Result<CallToolResult> {
-    info!("Listing loaded components");
+    // All arguments are optional, so a request without any is fine
+    let args = extract_args_from_request(req).unwrap_or_default();
+    let selector = args
+        .get("labels")
+        .and_then(|v| v.as_str())
+        .map(str::parse::<LabelSelector>)
+        .transpose()?;
+
+    info!(selector = ?selector.as_ref().map(ToString::to_string), "Listing loaded components");
 
-    let component_ids = lifecycle_manager.list_components().await;
+    let component_ids = match &selector {
+        Some(selector) => lifecycle_manager.list_components_matching(selector).await,
+        None => lifecycle_manager.list_components().await,
+    };
 
     let components_info = stream::iter(component_ids)
         .map(|id| async move {
             debug!(component_id = %id, "Getting component details");
+            let capability_gaps = lifecycle_manager
+                .get_capability_gaps(&id)
+                .await
+                .unwrap_or_default();
+            let labels = lifecycle_manager
+                .get_component_labels(&id)
+                .await
+                .unwrap_or_default();
             if let Some(schema) = lifecycle_manager.get_component_schema(&id).await {
-                let tools_count = schema
-                    .get("tools")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| arr.len())
-                    .unwrap_or(0);
-
                 json!({
                     "id": id,
-                    "tools_count": tools_count,
-                    "schema": schema
+                    "labels": labels,
+                    "tools_count": schema.tools.len(),
+                    "schema": schema,
+                    "exceeds_policy": !capability_gaps.is_empty(),
+                    "capability_gaps": capability_gaps,
                 })
             } else {
                 json!({
                     "id": id,
+                    "labels": labels,
                     "tools_count": 0,
-                    "schema": null
+                    "schema": null,
+                    "exceeds_policy": !capability_gaps.is_empty(),
+                    "capability_gaps": capability_gaps,
                 })
             }
         })
-        .buffer_unordered(50)
+        .buffered(50)
         .collect::<Vec<_>>()
         .await;
 
@@ -248,28 +537,27 @@ pub(crate) fn extract_args_from_request(
     }
 }
 
-#[instrument]
-fn parse_tool_schema(tool_json: &Value) -> Option<Tool> {
-    let name = tool_json
-        .get("name")
-        .and_then(|v| v.as_str())
-        .unwrap_or("<unnamed>");
-
-    let description = tool_json
-        .get("description")
-        .and_then(|v| v.as_str())
+#[instrument(skip_all, fields(tool_name = %schema.name))]
+fn tool_from_schema(schema: &ToolSchema) -> Tool {
+    let description = schema
+        .description
+        .as_deref()
         .unwrap_or("No description available");
 
-    let input_schema = tool_json.get("inputSchema").cloned().unwrap_or(json!({}));
-
-    debug!(tool_name = %name, "Parsed tool schema");
-
-    Some(Tool {
-        name: Cow::Owned(name.to_string()),
+    Tool {
+        name: Cow::Owned(schema.name.clone()),
         description: Some(Cow::Owned(description.to_string())),
-        input_schema: Arc::new(serde_json::from_value(input_schema).unwrap_or_default()),
-        annotations: None,
-    })
+        input_schema: Arc::new(
+            serde_json::from_value(schema.input_schema.clone()).unwrap_or_default(),
+        ),
+        annotations: schema.annotations.as_ref().map(|a| ToolAnnotations {
+            title: a.title.clone(),
+            read_only_hint: a.read_only_hint,
+            destructive_hint: a.destructive_hint,
+            idempotent_hint: a.idempotent_hint,
+            open_world_hint: a.open_world_hint,
+        }),
+    }
 }
 
 #[cfg(test)]
@@ -277,8 +565,8 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_parse_tool_schema() {
-        let tool_json = json!({
+    fn test_tool_from_schema() {
+        let schema: ToolSchema = serde_json::from_value(json!({
             "name": "test-tool",
             "description": "Test tool description",
             "inputSchema": {
@@ -287,9 +575,10 @@ mod tests {
                     "test": {"type": "string"}
                 }
             }
-        });
+        }))
+        .unwrap();
 
-        let tool = parse_tool_schema(&tool_json).unwrap();
+        let tool = tool_from_schema(&schema);
 
         assert_eq!(tool.name, "test-tool");
         assert_eq!(tool.description, Some("Test tool description".into()));
@@ -0,0 +1,426 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The MCP endpoints over HTTP as an [`axum::Router`], for applications that embed wassette in
+//! their own axum server and want to share its listener, middleware, authentication and TLS.
+//!
+//! ```no_run
+//! # async fn run(lifecycle_manager: mcp_server::LifecycleManager) -> anyhow::Result<()> {
+//! let mcp = mcp_server::http::router(mcp_server::McpServer::new(lifecycle_manager));
+//! let app = axum::Router::new().nest("/mcp", mcp);
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+//! axum::serve(listener, app).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use futures::{SinkExt, StreamExt};
+use rmcp::model::{JsonRpcBatchRequestItem, JsonRpcBatchResponseItem, JsonRpcMessage};
+use rmcp::service::{RxJsonRpcMessage, TxJsonRpcMessage};
+use rmcp::transport::sse_server::SseServerConfig;
+use rmcp::transport::SseServer;
+use rmcp::{RoleServer, ServiceExt};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::{LifecycleManager, McpServer};
+
+/// Path of the endpoint that opens the event stream of a session
+pub const SSE_PATH: &str = "/sse";
+
+/// Path of the endpoint that clients post their messages to
+pub const POST_PATH: &str = "/message";
+
+/// Seconds a client turned away for too many open sessions is asked to wait before retrying
+const RETRY_AFTER_SECS: u64 = 5;
+
+/// Returns a router serving the MCP SSE transport at [`SSE_PATH`] and [`POST_PATH`]. Every
+/// session gets its own server with the lifecycle manager of `server`. Sessions end when their
+/// client disconnects, when they stay idle longer than the configured
+/// [`idle_timeout_secs`](wassette::SessionConfig::idle_timeout_secs) or when the router is
+/// dropped. Once [`max_sessions`](wassette::SessionConfig::max_sessions) sessions are open,
+/// further clients get `503 Service Unavailable`. The router also serves a human-readable
+/// report on each component at `/inspect/{id}`.
+pub fn router(server: McpServer) -> axum::Router {
+    router_with_cancellation(server, CancellationToken::new())
+}
+
+/// Same as [`router`], but all sessions are closed when `ct` is cancelled, e.g. before a
+/// graceful shutdown that would otherwise wait for the open event streams
+pub fn router_with_cancellation(server: McpServer, ct: CancellationToken) -> axum::Router {
+    let (mut sse_server, router) = SseServer::new(SseServerConfig {
+        // Only used in log messages, since the router doesn't bind a listener itself
+        bind: ([0, 0, 0, 0], 0).into(),
+        sse_path: SSE_PATH.to_string(),
+        post_path: POST_PATH.to_string(),
+        ct: ct.clone(),
+        sse_keep_alive: None,
+    });
+    let lifecycle_manager = server.lifecycle_manager().clone();
+    let inspector = crate::inspect::router(lifecycle_manager.clone());
+    let sessions = OpenSessions {
+        lifecycle_manager: lifecycle_manager.clone(),
+        open: Arc::default(),
+    };
+    let router = router.layer(middleware::from_fn_with_state(
+        sessions.clone(),
+        limit_sessions,
+    ));
+    // Like `SseServer::with_service`, but each session can be closed on its own when it is idle
+    // or its client falls behind on notifications
+    tokio::spawn(async move {
+        while let Some(transport) = sse_server.next_transport().await {
+            let config = lifecycle_manager.session_config().await;
+            // Clients that got past the check of `limit_sessions` at the same time
+            if config
+                .max_sessions
+                .is_some_and(|max| sessions.count() >= max)
+            {
+                warn!("Closing session beyond the maximum number of sessions");
+                continue;
+            }
+            let guard = sessions.open();
+            let session_ct = ct.child_token();
+            let activity = Arc::new(SessionActivity::new());
+            if let Some(timeout) = config.idle_timeout() {
+                tokio::spawn(close_when_idle(
+                    activity.clone(),
+                    timeout,
+                    session_ct.clone(),
+                ));
+            }
+            let (sink, stream) = transport.split();
+            let sent = activity.clone();
+            let sink = sink.with(move |message: TxJsonRpcMessage<RoleServer>| {
+                sent.sent(&message);
+                futures::future::ready(Ok::<_, std::io::Error>(message))
+            });
+            let stream = stream.inspect(move |message| activity.received(message));
+            let server =
+                McpServer::new(lifecycle_manager.clone()).with_disconnect(session_ct.clone());
+            tokio::spawn(async move {
+                let _guard = guard;
+                match server
+                    .serve_with_ct((sink, stream), session_ct.clone())
+                    .await
+                {
+                    Ok(service) => {
+                        if let Err(e) = service.waiting().await {
+                            debug!(error = %e, "Session ended with an error");
+                        }
+                    }
+                    Err(e) => debug!(error = %e, "Failed to start session"),
+                }
+                // Stops the idle timer of the session
+                session_ct.cancel();
+            });
+        }
+    });
+    router.merge(inspector)
+}
+
+/// Counts the sessions open over HTTP
+#[derive(Clone)]
+struct OpenSessions {
+    lifecycle_manager: LifecycleManager,
+    open: Arc<AtomicUsize>,
+}
+
+impl OpenSessions {
+    fn count(&self) -> usize {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// Counts a session as open until the returned guard is dropped
+    fn open(&self) -> OpenSessionGuard {
+        self.open.fetch_add(1, Ordering::Relaxed);
+        OpenSessionGuard(self.open.clone())
+    }
+}
+
+struct OpenSessionGuard(Arc<AtomicUsize>);
+
+impl Drop for OpenSessionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Turns away clients opening a session while the maximum number of sessions is open
+async fn limit_sessions(
+    State(sessions): State<OpenSessions>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.uri().path() == SSE_PATH {
+        let max_sessions = sessions
+            .lifecycle_manager
+            .session_config()
+            .await
+            .max_sessions;
+        if max_sessions.is_some_and(|max| sessions.count() >= max) {
+            warn!(
+                max_sessions,
+                "Turning away client, the maximum number of sessions is open"
+            );
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())],
+                "Too many open sessions, try again later",
+            )
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// When a session last exchanged a message with its client, and how many of the client's
+/// requests it is still working on
+struct SessionActivity {
+    last_message: Mutex<Instant>,
+    in_flight: AtomicUsize,
+}
+
+impl SessionActivity {
+    fn new() -> Self {
+        Self {
+            last_message: Mutex::new(Instant::now()),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    fn touch(&self) {
+        *self.last_message.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    /// Records a message from the client
+    fn received(&self, message: &RxJsonRpcMessage<RoleServer>) {
+        let requests = match message {
+            JsonRpcMessage::Request(_) => 1,
+            JsonRpcMessage::BatchRequest(items) => items
+                .iter()
+                .filter(|item| matches!(item, JsonRpcBatchRequestItem::Request(_)))
+                .count(),
+            _ => 0,
+        };
+        self.in_flight.fetch_add(requests, Ordering::Relaxed);
+        self.touch();
+    }
+
+    /// Records a response or notification to the client
+    fn sent(&self, message: &TxJsonRpcMessage<RoleServer>) {
+        let responses = match message {
+            JsonRpcMessage::Response(_) | JsonRpcMessage::Error(_) => 1,
+            JsonRpcMessage::BatchResponse(items) => items
+                .iter()
+                .filter(|item| {
+                    matches!(
+                        item,
+                        JsonRpcBatchResponseItem::Response(_) | JsonRpcBatchResponseItem::Error(_)
+                    )
+                })
+                .count(),
+            _ => 0,
+        };
+        // Errors for messages that weren't requests, like ones that failed to parse, don't
+        // answer anything in flight
+        let _ = self
+            .in_flight
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                Some(n.saturating_sub(responses))
+            });
+        self.touch();
+    }
+
+    /// Returns when the session became idle, or `None` while a request is in flight
+    fn idle_since(&self) -> Option<Instant> {
+        if self.in_flight.load(Ordering::Relaxed) > 0 {
+            return None;
+        }
+        Some(*self.last_message.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Closes a session by cancelling `ct` once it has gone `timeout` without exchanging a message
+/// with its client or working on one of its requests
+async fn close_when_idle(activity: Arc<SessionActivity>, timeout: Duration, ct: CancellationToken) {
+    loop {
+        let deadline = match activity.idle_since() {
+            Some(since) => since + timeout,
+            // Checks again later, the response restarts the timer
+            None => Instant::now() + timeout,
+        };
+        if deadline <= Instant::now() {
+            info!(
+                idle_timeout_secs = timeout.as_secs(),
+                "Closing idle session"
+            );
+            ct.cancel();
+            return;
+        }
+        tokio::select! {
+            _ = ct.cancelled() => return,
+            _ = tokio::time::sleep_until(deadline) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_router_serves_event_stream() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = crate::LifecycleManager::new(tempdir.path()).await?;
+        let app = axum::Router::new().nest("/mcp", router(McpServer::new(lifecycle_manager)));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut response = reqwest::get(format!("http://{addr}/mcp{SSE_PATH}")).await?;
+        assert!(response.status().is_success());
+        assert_eq!(
+            response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok()),
+            Some("text/event-stream")
+        );
+        // The endpoint announced to the client includes the prefix the router is nested under
+        let event = response.chunk().await?.unwrap_or_default();
+        let event = String::from_utf8_lossy(&event);
+        assert!(
+            event.contains(&format!("data: /mcp{POST_PATH}?sessionId=")),
+            "unexpected event: {event}"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_sessions() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let config = wassette::LifecycleConfig {
+            sessions: wassette::SessionConfig {
+                max_sessions: Some(1),
+                idle_timeout_secs: None,
+            },
+            ..Default::default()
+        };
+        let lifecycle_manager = LifecycleManager::new_with_config(tempdir.path(), config).await?;
+        let app = router(McpServer::new(lifecycle_manager));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let url = format!("http://{addr}{SSE_PATH}");
+
+        let mut first = reqwest::get(&url).await?;
+        assert!(first.status().is_success());
+        first.chunk().await?;
+        let mut turned_away = None;
+        for _ in 0..50 {
+            let response = reqwest::get(&url).await?;
+            if response.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+                turned_away = Some(response);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let turned_away = turned_away.expect("second session wasn't turned away");
+        assert!(turned_away
+            .headers()
+            .contains_key(reqwest::header::RETRY_AFTER));
+
+        // Another client gets in once the first one leaves
+        drop(first);
+        let mut admitted = false;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            if reqwest::get(&url).await?.status().is_success() {
+                admitted = true;
+                break;
+            }
+        }
+        assert!(
+            admitted,
+            "no session was admitted after the first one closed"
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_close_when_idle() {
+        let activity = Arc::new(SessionActivity::new());
+        let ct = CancellationToken::new();
+        let timer = tokio::spawn(close_when_idle(
+            activity.clone(),
+            Duration::from_secs(60),
+            ct.clone(),
+        ));
+
+        tokio::time::sleep(Duration::from_secs(45)).await;
+        activity.touch();
+        tokio::time::sleep(Duration::from_secs(45)).await;
+        assert!(
+            !ct.is_cancelled(),
+            "session closed despite a recent message"
+        );
+
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert!(ct.is_cancelled());
+        timer.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_in_flight_keep_sessions_open() {
+        let activity = Arc::new(SessionActivity::new());
+        let ct = CancellationToken::new();
+        let timer = tokio::spawn(close_when_idle(
+            activity.clone(),
+            Duration::from_secs(60),
+            ct.clone(),
+        ));
+        let request: RxJsonRpcMessage<RoleServer> = serde_json::from_value(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {"name": "slow-tool", "arguments": {}}
+        }))
+        .unwrap();
+        let response: TxJsonRpcMessage<RoleServer> = serde_json::from_value(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": {"content": []}
+        }))
+        .unwrap();
+
+        // A tool call that runs longer than the timeout
+        activity.received(&request);
+        tokio::time::sleep(Duration::from_secs(150)).await;
+        assert!(
+            !ct.is_cancelled(),
+            "session closed while a request was in flight"
+        );
+
+        // The response counts as activity too
+        activity.sent(&response);
+        tokio::time::sleep(Duration::from_secs(45)).await;
+        assert!(
+            !ct.is_cancelled(),
+            "session closed right after its response"
+        );
+
+        tokio::time::sleep(Duration::from_secs(80)).await;
+        assert!(ct.is_cancelled());
+        timer.await.unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Translations of the builtin tool descriptions and of common error messages.
+//!
+//! English text lives in the code, and the catalogs in `locales/` translate it by tool name and
+//! error key. Text missing from a catalog stays in English. The language comes from the session,
+//! if its client declared one when it connected, and otherwise from the server configuration.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::LazyLock;
+
+use rmcp::model::ClientCapabilities;
+use serde::Deserialize;
+
+/// Languages that builtin tool descriptions and errors are available in
+pub const SUPPORTED_LANGUAGES: &[&str] = &["en", "de", "es", "fr", "ja"];
+
+/// The experimental client capability in which a client declares its language, e.g.
+/// `{"locale": {"language": "de"}}`
+pub const LOCALE_CAPABILITY: &str = "locale";
+
+#[derive(Debug, Default, Deserialize)]
+struct Catalog {
+    #[serde(default)]
+    tools: HashMap<String, String>,
+    #[serde(default)]
+    errors: HashMap<String, String>,
+}
+
+static CATALOGS: LazyLock<HashMap<&'static str, Catalog>> = LazyLock::new(|| {
+    [
+        ("de", include_str!("../locales/de.json")),
+        ("es", include_str!("../locales/es.json")),
+        ("fr", include_str!("../locales/fr.json")),
+        ("ja", include_str!("../locales/ja.json")),
+    ]
+    .into_iter()
+    .map(|(language, catalog)| {
+        let catalog = serde_json::from_str(catalog).expect("bundled catalogs are valid");
+        (language, catalog)
+    })
+    .collect()
+});
+
+/// The messages of one language
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Messages {
+    catalog: Option<&'static Catalog>,
+}
+
+impl Messages {
+    /// Returns the messages of a language tag such as `de` or `fr-CA`, which are English if the
+    /// language isn't supported
+    pub(crate) fn for_language(language: Option<&str>) -> Self {
+        let catalog = language.and_then(|tag| {
+            let primary = tag.split(['-', '_']).next().unwrap_or(tag);
+            CATALOGS.get(primary.to_ascii_lowercase().as_str())
+        });
+        Self { catalog }
+    }
+
+    /// Returns the translated description of a builtin tool, if there is one
+    pub(crate) fn tool_description(&self, tool: &str) -> Option<&'static str> {
+        self.catalog?.tools.get(tool).map(String::as_str)
+    }
+
+    fn error_template(&self, key: &str) -> Option<&'static str> {
+        self.catalog?.errors.get(key).map(String::as_str)
+    }
+
+    /// Renders the text of a failed tool call
+    pub(crate) fn error_text(&self, error: &anyhow::Error) -> String {
+        let message = match error.downcast_ref::<BuiltinError>() {
+            Some(builtin) => match self.error_template(builtin.key()) {
+                Some(template) => template.replace("{name}", builtin.name()),
+                None => builtin.to_string(),
+            },
+            None => error.to_string(),
+        };
+        match self.error_template("error") {
+            Some(template) => template.replace("{message}", &message),
+            None => format!("Error: {message}"),
+        }
+    }
+}
+
+/// Returns the language that a client declared in its capabilities when it connected
+pub(crate) fn client_language(capabilities: &ClientCapabilities) -> Option<String> {
+    capabilities
+        .experimental
+        .as_ref()?
+        .get(LOCALE_CAPABILITY)?
+        .get("language")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Errors of builtin tools whose messages are translated
+#[derive(Debug)]
+pub(crate) enum BuiltinError {
+    MissingArgument(String),
+    UnknownTool(String),
+}
+
+impl BuiltinError {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::MissingArgument(_) => "missing_argument",
+            Self::UnknownTool(_) => "unknown_tool",
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::MissingArgument(name) | Self::UnknownTool(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for BuiltinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingArgument(name) => write!(f, "Missing required argument: '{name}'"),
+            Self::UnknownTool(name) => write!(f, "Unknown tool: '{name}'"),
+        }
+    }
+}
+
+impl std::error::Error for BuiltinError {}
+
+/// Returns the error of a call that lacks a required argument
+pub(crate) fn missing_argument(name: &str) -> anyhow::Error {
+    BuiltinError::MissingArgument(name.to_string()).into()
+}
+
+/// Returns the error of a call to a tool that doesn't exist
+pub(crate) fn unknown_tool(name: &str) -> anyhow::Error {
+    BuiltinError::UnknownTool(name.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalogs_translate_every_key() {
+        let english = Messages::default();
+        assert!(english.tool_description("load-component").is_none());
+        for language in SUPPORTED_LANGUAGES.iter().filter(|l| **l != "en") {
+            let catalog = &CATALOGS[language];
+            for key in ["error", "missing_argument", "unknown_tool"] {
+                assert!(catalog.errors.contains_key(key), "{language} lacks {key}");
+            }
+            assert!(catalog.tools.contains_key("load-component"));
+        }
+    }
+
+    #[test]
+    fn test_error_text() {
+        let error = missing_argument("path");
+        assert_eq!(
+            Messages::default().error_text(&error),
+            "Error: Missing required argument: 'path'"
+        );
+        assert_eq!(
+            Messages::for_language(Some("de-AT")).error_text(&error),
+            "Fehler: Fehlendes Pflichtargument: 'path'"
+        );
+        assert_eq!(
+            Messages::for_language(Some("xx")).error_text(&anyhow::anyhow!("boom")),
+            "Error: boom"
+        );
+    }
+}
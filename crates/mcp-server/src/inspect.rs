@@ -0,0 +1,317 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! A report on a single component, served next to the MCP endpoints at `/inspect/{id}`, so that
+//! operators can look at a component in a browser without an MCP client. The report lists the
+//! tools of the component with their schemas, the policy attached to it and the permissions it
+//! grants, the usage statistics of its tools and its most recent errors.
+//!
+//! The report is HTML, or JSON if the request accepts `application/json` or adds
+//! `?format=json`. As with the management API, a `/` in a component ID has to be percent-encoded,
+//! and component names are accepted too.
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use wassette::LifecycleManager;
+
+/// Path of the inspector, with the component ID or name as its last segment
+pub(crate) const INSPECT_PATH: &str = "/inspect/{id}";
+
+/// Returns a router serving the inspector
+pub(crate) fn router(lifecycle_manager: LifecycleManager) -> Router {
+    Router::new()
+        .route(INSPECT_PATH, get(inspect))
+        .with_state(lifecycle_manager)
+}
+
+#[derive(Deserialize)]
+struct InspectQuery {
+    format: Option<String>,
+}
+
+async fn inspect(
+    State(lifecycle_manager): State<LifecycleManager>,
+    Path(id): Path<String>,
+    Query(query): Query<InspectQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let as_json = match query.format.as_deref() {
+        Some(format) => format == "json",
+        None => headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|accept| accept.contains("application/json")),
+    };
+    match report(&lifecycle_manager, &id).await {
+        Ok(report) if as_json => Json(report).into_response(),
+        Ok(report) => Html(render(&report)).into_response(),
+        Err((status, error)) if as_json => {
+            (status, Json(json!({"error": format!("{error:#}")}))).into_response()
+        }
+        Err((status, error)) => (
+            status,
+            Html(page(
+                "Component not available",
+                &format!("<p>{}</p>", escape(&format!("{error:#}"))),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+/// Collects the report on a component as JSON
+async fn report(
+    lifecycle_manager: &LifecycleManager,
+    id: &str,
+) -> Result<Value, (StatusCode, anyhow::Error)> {
+    let id = lifecycle_manager
+        .resolve_component_id(id)
+        .await
+        .map_err(|e| (StatusCode::CONFLICT, e))?;
+    let Some(schema) = lifecycle_manager.get_component_schema(&id).await else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Component not found: {id}"),
+        ));
+    };
+    let labels = lifecycle_manager
+        .get_component_labels(&id)
+        .await
+        .unwrap_or_default();
+    let policy = lifecycle_manager.get_policy_info(&id).await.map(|info| {
+        json!({
+            "source_uri": info.source_uri,
+            "local_path": info.local_path,
+        })
+    });
+    let permissions = match lifecycle_manager.get_policy_permissions(&id).await {
+        Ok(Some(permissions)) => json!({"status": "granted by policy", "granted": permissions}),
+        Ok(None) => json!({"status": "no policy attached, nothing is granted"}),
+        Err(e) => json!({"status": "policy can't be read", "error": format!("{e:#}")}),
+    };
+    let stats = lifecycle_manager
+        .get_tool_stats(Some(&id))
+        .await
+        .components
+        .remove(&id)
+        .unwrap_or_default();
+    let recent_errors = lifecycle_manager.get_recent_errors(&id).await;
+    Ok(json!({
+        "id": id,
+        "world": schema.world,
+        "labels": labels,
+        "tools": schema.tools,
+        "policy": policy,
+        "permissions": permissions,
+        "stats": stats,
+        "recent_errors": recent_errors,
+    }))
+}
+
+/// Renders a report collected by [`report`] as an HTML page
+fn render(report: &Value) -> String {
+    let id = report["id"].as_str().unwrap_or_default();
+    let mut body = String::new();
+
+    if let Some(world) = report["world"].as_str() {
+        body.push_str(&format!("<p>World: <code>{}</code></p>", escape(world)));
+    }
+    if let Some(labels) = report["labels"].as_object().filter(|l| !l.is_empty()) {
+        body.push_str("<p>Labels: ");
+        for (key, value) in labels {
+            let value = value
+                .as_str()
+                .map_or_else(|| value.to_string(), String::from);
+            body.push_str(&format!("<code>{}={}</code> ", escape(key), escape(&value)));
+        }
+        body.push_str("</p>");
+    }
+
+    body.push_str("<h2>Tools</h2>");
+    for tool in report["tools"].as_array().into_iter().flatten() {
+        let name = tool["name"].as_str().unwrap_or_default();
+        body.push_str(&format!("<h3><code>{}</code></h3>", escape(name)));
+        if let Some(description) = tool["description"].as_str() {
+            body.push_str(&format!("<p>{}</p>", escape(description)));
+        }
+        body.push_str(&format!(
+            "<details><summary>Input schema</summary>{}</details>",
+            pre(&tool["inputSchema"])
+        ));
+        if !tool["outputSchema"].is_null() {
+            body.push_str(&format!(
+                "<details><summary>Output schema</summary>{}</details>",
+                pre(&tool["outputSchema"])
+            ));
+        }
+    }
+
+    body.push_str("<h2>Policy</h2>");
+    match report["policy"]["source_uri"].as_str() {
+        Some(source) => body.push_str(&format!(
+            "<p>Attached from <code>{}</code></p>",
+            escape(source)
+        )),
+        None => body.push_str("<p>No policy attached</p>"),
+    }
+    let permissions = &report["permissions"];
+    body.push_str(&format!(
+        "<p>Permissions: {}</p>",
+        escape(permissions["status"].as_str().unwrap_or_default())
+    ));
+    if let Some(error) = permissions["error"].as_str() {
+        body.push_str(&format!("<p>{}</p>", escape(error)));
+    }
+    if !permissions["granted"].is_null() {
+        body.push_str(&pre(&permissions["granted"]));
+    }
+
+    body.push_str("<h2>Statistics</h2>");
+    match report["stats"].as_object().filter(|s| !s.is_empty()) {
+        Some(stats) => {
+            body.push_str(
+                "<table><tr><th>Tool</th><th>Calls</th><th>Failures</th><th>Average ms</th><th>Peak memory</th></tr>",
+            );
+            for (tool, stats) in stats {
+                let invocations = stats["invocations"].as_u64().unwrap_or_default();
+                let average = match invocations {
+                    0 => "-".to_string(),
+                    n => format!(
+                        "{:.1}",
+                        stats["total_duration_ms"].as_u64().unwrap_or_default() as f64 / n as f64
+                    ),
+                };
+                body.push_str(&format!(
+                    "<tr><td><code>{}</code></td><td>{invocations}</td><td>{}</td><td>{average}</td><td>{}</td></tr>",
+                    escape(tool),
+                    stats["failures"],
+                    stats["peak_memory_bytes"],
+                ));
+            }
+            body.push_str("</table>");
+        }
+        None => body.push_str("<p>No calls yet</p>"),
+    }
+
+    body.push_str("<h2>Recent errors</h2>");
+    match report["recent_errors"].as_array().filter(|e| !e.is_empty()) {
+        Some(errors) => {
+            body.push_str("<ul>");
+            for error in errors.iter().rev() {
+                body.push_str(&format!(
+                    "<li><code>{}</code> at {}: {}</li>",
+                    escape(error["tool"].as_str().unwrap_or_default()),
+                    error["time"],
+                    escape(error["message"].as_str().unwrap_or_default())
+                ));
+            }
+            body.push_str("</ul>");
+        }
+        None => body.push_str("<p>No errors since the server started</p>"),
+    }
+
+    page(id, &body)
+}
+
+/// Wraps the body of a page in a document titled `title`
+fn page(title: &str, body: &str) -> String {
+    let title = escape(title);
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <style>body{{font-family:sans-serif;max-width:60em;margin:auto}}\
+         pre{{background:#f4f4f4;padding:.5em;overflow:auto}}\
+         td,th{{padding:0 .8em;text-align:left}}</style></head>\
+         <body><h1>{title}</h1>{body}</body></html>"
+    )
+}
+
+/// Renders a JSON value in a preformatted block
+fn pre(value: &Value) -> String {
+    format!(
+        "<pre>{}</pre>",
+        escape(&serde_json::to_string_pretty(value).unwrap_or_default())
+    )
+}
+
+/// Escapes text for use in HTML content and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<script>alert('x & y')</script>"),
+            "&lt;script&gt;alert(&#39;x &amp; y&#39;)&lt;/script&gt;"
+        );
+    }
+
+    #[test]
+    fn test_render_escapes_component_data() {
+        let report = json!({
+            "id": "local/fetch",
+            "world": null,
+            "labels": {},
+            "tools": [{
+                "name": "fetch",
+                "description": "Fetches <b>URLs</b>",
+                "inputSchema": {"type": "object"},
+            }],
+            "policy": null,
+            "permissions": {"status": "no policy attached, nothing is granted"},
+            "stats": {"fetch": {"invocations": 2, "failures": 1, "total_duration_ms": 30, "peak_memory_bytes": 65536}},
+            "recent_errors": [{"tool": "fetch", "message": "<oops>", "time": 1}],
+        });
+        let html = render(&report);
+        assert!(html.contains("<title>local/fetch</title>"));
+        assert!(html.contains("Fetches &lt;b&gt;URLs&lt;/b&gt;"));
+        assert!(html.contains("<td>15.0</td>"));
+        assert!(html.contains("&lt;oops&gt;"));
+        assert!(!html.contains("<oops>"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_component() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = LifecycleManager::new(tempdir.path()).await?;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, router(lifecycle_manager)).await });
+        let url = format!("http://{addr}/inspect/local%2Fmissing");
+
+        let response = reqwest::get(&url).await?;
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        assert!(response
+            .text()
+            .await?
+            .contains("Component not found: local/missing"));
+
+        let response = reqwest::get(format!("{url}?format=json")).await?;
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.json::<Value>().await?,
+            json!({"error": "Component not found: local/missing"})
+        );
+        Ok(())
+    }
+}
@@ -3,11 +3,29 @@
 
 pub use wassette::LifecycleManager;
 
+pub mod access_log;
+pub mod admin;
+mod batch;
 mod components;
+pub mod http;
+mod i18n;
+mod inspect;
+mod logging;
+mod notifications;
 mod prompts;
 mod resources;
+mod select;
+mod server;
 mod tools;
+mod uploads;
+mod validation;
+mod variables;
 
+pub use i18n::{LOCALE_CAPABILITY, SUPPORTED_LANGUAGES};
+pub use logging::DEFAULT_LOG_LEVEL;
 pub use prompts::handle_prompts_list;
-pub use resources::handle_resources_list;
-pub use tools::{handle_tools_call, handle_tools_list};
+pub use resources::{handle_resources_list, handle_resources_read};
+pub use select::SELECT_ARGUMENT_KEY;
+pub use server::McpServer;
+pub use tools::{handle_tools_call, handle_tools_list, ToolCallContext};
+pub use variables::{SessionVariables, SAVE_AS_ARGUMENT_KEY};
@@ -0,0 +1,152 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Forwarding of component stderr, the events components publish and host-side diagnostics to
+//! the client through MCP `notifications/message`, filtered by the level the session set with
+//! `logging/setLevel`. Messages are sent through the session's
+//! [notification queue](crate::notifications).
+
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use wassette::{
+    ComponentLog, ComponentLogLevel, ComponentLogSink, PublishedEvent, PublishedEventSink,
+};
+
+use crate::notifications::{Notification, NotificationQueue};
+
+/// Logger name of messages that come from the server rather than a component
+pub(crate) const HOST_LOGGER: &str = "wassette";
+
+/// Level of the messages sent to a session that hasn't set one
+pub const DEFAULT_LOG_LEVEL: LoggingLevel = LoggingLevel::Info;
+
+/// Sends log messages to the client of a session
+#[derive(Clone)]
+pub(crate) struct McpLogger {
+    notifications: NotificationQueue,
+    min_level: LoggingLevel,
+}
+
+impl McpLogger {
+    pub(crate) fn new(notifications: NotificationQueue, min_level: LoggingLevel) -> Self {
+        Self {
+            notifications,
+            min_level,
+        }
+    }
+
+    fn enabled(&self, level: LoggingLevel) -> bool {
+        severity(level) >= severity(self.min_level)
+    }
+
+    /// Queues a message if its level is at least the session's level
+    pub(crate) fn log(&self, level: LoggingLevel, logger: &str, message: impl Into<String>) {
+        self.log_data(level, logger, Value::String(message.into()));
+    }
+
+    /// Queues a message with structured data if its level is at least the session's level
+    pub(crate) fn log_data(&self, level: LoggingLevel, logger: &str, data: Value) {
+        if !self.enabled(level) {
+            return;
+        }
+        self.notifications
+            .push(Notification::Log(LoggingMessageNotificationParam {
+                level,
+                logger: Some(logger.to_string()),
+                data,
+            }));
+    }
+
+    /// Returns a sink for the stderr of a component call, and the task that forwards what it
+    /// receives. The task finishes once the call is done and every line has been queued.
+    pub(crate) fn forward_component_logs(
+        &self,
+        component_id: &str,
+    ) -> (ComponentLogSink, JoinHandle<()>) {
+        let (sink, mut receiver) = mpsc::unbounded_channel::<ComponentLog>();
+        let logger = self.clone();
+        let component_id = component_id.to_string();
+        let forwarder = tokio::spawn(async move {
+            while let Some(line) = receiver.recv().await {
+                logger.log(to_logging_level(line.level), &component_id, line.message);
+            }
+        });
+        (sink, forwarder)
+    }
+
+    /// Returns a sink for the events a component publishes during a call, and the task that
+    /// sends them as messages of the logger `<component_id>/events`. The task finishes once the
+    /// call is done and every event has been queued.
+    pub(crate) fn forward_component_events(
+        &self,
+        component_id: &str,
+    ) -> (PublishedEventSink, JoinHandle<()>) {
+        let (sink, mut receiver) = mpsc::unbounded_channel::<PublishedEvent>();
+        let logger = self.clone();
+        let name = format!("{component_id}/events");
+        let forwarder = tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                logger.log_data(LoggingLevel::Info, &name, event_data(event));
+            }
+        });
+        (sink, forwarder)
+    }
+}
+
+/// The data of the message an event is sent as
+fn event_data(event: PublishedEvent) -> Value {
+    json!({"topic": event.topic, "data": event.data})
+}
+
+fn to_logging_level(level: ComponentLogLevel) -> LoggingLevel {
+    match level {
+        ComponentLogLevel::Debug => LoggingLevel::Debug,
+        ComponentLogLevel::Info => LoggingLevel::Info,
+        ComponentLogLevel::Warning => LoggingLevel::Warning,
+        ComponentLogLevel::Error => LoggingLevel::Error,
+    }
+}
+
+/// Orders the levels from least to most severe, as `LoggingLevel` doesn't implement `Ord`
+fn severity(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_severity_order() {
+        assert!(severity(LoggingLevel::Warning) > severity(DEFAULT_LOG_LEVEL));
+        assert!(severity(LoggingLevel::Debug) < severity(DEFAULT_LOG_LEVEL));
+        assert_eq!(
+            to_logging_level(ComponentLogLevel::Warning),
+            LoggingLevel::Warning
+        );
+    }
+
+    #[test]
+    fn test_event_data() {
+        let event = PublishedEvent {
+            component_id: "local/scanner".to_string(),
+            topic: "scan.finding".to_string(),
+            data: json!({"severity": "high"}),
+        };
+        assert_eq!(
+            event_data(event),
+            json!({"topic": "scan.finding", "data": {"severity": "high"}})
+        );
+    }
+}
@@ -0,0 +1,307 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The bounded queue of notifications that a session sends to its client.
+//!
+//! Log messages and tool list changes are queued and sent by a task of their own, so a client
+//! that reads slowly holds up neither tool calls nor other sessions. The queue holds at most
+//! [`capacity`](wassette::NotificationQueueConfig::capacity) notifications. A tool list change is
+//! only queued if none is waiting already, since the client lists all tools again either way.
+//! When the queue is full, the [overflow policy](wassette::NotificationOverflow) either drops the
+//! oldest log message or closes the session. The number of queued and dropped notifications is
+//! reported in the server status.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rmcp::model::LoggingMessageNotificationParam;
+use rmcp::{Peer, RoleServer};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+use wassette::{NotificationOverflow, NotificationQueueConfig, SessionGuard};
+
+/// A notification waiting to be sent
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Notification {
+    Log(LoggingMessageNotificationParam),
+    ToolListChanged,
+}
+
+#[derive(Default)]
+struct Queue {
+    items: VecDeque<Notification>,
+    closed: bool,
+    /// Whether a notification was dropped, so that only the first drop is logged
+    dropped: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    ready: Notify,
+    /// Reports the depth of the queue and dropped notifications in the server status
+    session: Option<Arc<SessionGuard>>,
+}
+
+impl Shared {
+    fn new(session: Option<Arc<SessionGuard>>) -> Self {
+        Self {
+            queue: Mutex::new(Queue::default()),
+            ready: Notify::new(),
+            session,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the depth of `queue` and `dropped` notifications, with the queue still locked so
+    /// that depths are recorded in the order they changed
+    fn report(&self, queue: &Queue, dropped: u64) {
+        if let Some(session) = &self.session {
+            session.set_queue_depth(queue.items.len());
+            if dropped > 0 {
+                session.record_dropped_notifications(dropped);
+            }
+        }
+    }
+
+    /// Stops accepting notifications. Unless `discard` is set, those already queued are still
+    /// sent.
+    fn close(&self, discard: bool) {
+        let mut queue = self.lock();
+        queue.closed = true;
+        if discard {
+            queue.items.clear();
+            self.report(&queue, 0);
+        }
+        drop(queue);
+        self.ready.notify_one();
+    }
+}
+
+/// Stops the sending task once the last handle of a queue is gone and what was queued is sent
+struct Closer(Arc<Shared>);
+
+impl Drop for Closer {
+    fn drop(&mut self) {
+        self.0.close(false);
+    }
+}
+
+/// The notifications waiting to be sent to the client of a session
+#[derive(Clone)]
+pub(crate) struct NotificationQueue {
+    shared: Arc<Shared>,
+    capacity: usize,
+    overflow: NotificationOverflow,
+    /// Closes the session if the queue overflows and the policy says so
+    disconnect: Option<CancellationToken>,
+    _closer: Arc<Closer>,
+}
+
+impl NotificationQueue {
+    /// Creates a queue and starts sending what is pushed to it to `peer`. The depth of the queue
+    /// and the notifications it drops are counted for `session` in the server status.
+    pub(crate) fn start(
+        peer: Peer<RoleServer>,
+        config: &NotificationQueueConfig,
+        session: Option<Arc<SessionGuard>>,
+    ) -> Self {
+        let queue = Self::new(config, session);
+        tokio::spawn(send_notifications(peer, queue.shared.clone()));
+        queue
+    }
+
+    fn new(config: &NotificationQueueConfig, session: Option<Arc<SessionGuard>>) -> Self {
+        let shared = Arc::new(Shared::new(session));
+        Self {
+            _closer: Arc::new(Closer(shared.clone())),
+            shared,
+            capacity: config.capacity(),
+            overflow: config.overflow,
+            disconnect: None,
+        }
+    }
+
+    /// Lets the queue close the session by cancelling `ct` when it overflows
+    pub(crate) fn with_disconnect(mut self, ct: Option<CancellationToken>) -> Self {
+        self.disconnect = ct;
+        self
+    }
+
+    /// Queues a notification, applying the overflow policy if the queue is full
+    pub(crate) fn push(&self, notification: Notification) {
+        let mut queue = self.shared.lock();
+        if queue.closed {
+            return;
+        }
+        if notification == Notification::ToolListChanged
+            && queue.items.contains(&Notification::ToolListChanged)
+        {
+            return;
+        }
+        if queue.items.len() >= self.capacity {
+            if let (NotificationOverflow::Disconnect, Some(ct)) = (self.overflow, &self.disconnect)
+            {
+                warn!(
+                    capacity = self.capacity,
+                    "Closing session whose client doesn't keep up with its notifications"
+                );
+                let dropped = queue.items.len() as u64 + 1;
+                queue.closed = true;
+                queue.items.clear();
+                self.shared.report(&queue, dropped);
+                drop(queue);
+                self.shared.ready.notify_one();
+                ct.cancel();
+                return;
+            }
+            // Tool list changes are kept, as dropping one would leave the client with stale tools
+            let oldest = queue
+                .items
+                .iter()
+                .position(|item| matches!(item, Notification::Log(_)))
+                .unwrap_or(0);
+            queue.items.remove(oldest);
+            if !queue.dropped {
+                queue.dropped = true;
+                warn!(
+                    capacity = self.capacity,
+                    "Client doesn't keep up with its notifications, dropping the oldest"
+                );
+            }
+            queue.items.push_back(notification);
+            self.shared.report(&queue, 1);
+        } else {
+            queue.items.push_back(notification);
+            self.shared.report(&queue, 0);
+        }
+        drop(queue);
+        self.shared.ready.notify_one();
+    }
+
+    /// Returns the number of notifications waiting to be sent
+    #[cfg(test)]
+    fn depth(&self) -> usize {
+        self.shared.lock().items.len()
+    }
+}
+
+/// Sends the notifications of a queue to its client, one at a time, until the queue is closed
+/// and empty
+async fn send_notifications(peer: Peer<RoleServer>, shared: Arc<Shared>) {
+    loop {
+        let next = {
+            let mut queue = shared.lock();
+            let next = queue.items.pop_front();
+            if next.is_none() && queue.closed {
+                return;
+            }
+            shared.report(&queue, 0);
+            next
+        };
+        let Some(notification) = next else {
+            shared.ready.notified().await;
+            continue;
+        };
+        let sent = match notification {
+            Notification::Log(params) => peer.notify_logging_message(params).await,
+            Notification::ToolListChanged => peer.notify_tool_list_changed().await,
+        };
+        if let Err(e) = sent {
+            debug!(error = %e, "Failed to send notification, the client is gone");
+            shared.close(true);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::LoggingLevel;
+    use serde_json::json;
+
+    use super::*;
+
+    fn log(message: &str) -> Notification {
+        Notification::Log(LoggingMessageNotificationParam {
+            level: LoggingLevel::Info,
+            logger: None,
+            data: json!(message),
+        })
+    }
+
+    /// Creates a queue whose items aren't sent, as if its client never read them
+    fn stalled_queue(capacity: usize, overflow: NotificationOverflow) -> NotificationQueue {
+        let config = NotificationQueueConfig {
+            capacity: Some(capacity),
+            overflow,
+        };
+        NotificationQueue::new(&config, None)
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_tool_list_changes() {
+        let queue = stalled_queue(3, NotificationOverflow::DropOldest);
+        queue.push(Notification::ToolListChanged);
+        queue.push(log("first"));
+        queue.push(Notification::ToolListChanged);
+        queue.push(log("second"));
+        queue.push(log("third"));
+        assert_eq!(
+            Vec::from(queue.shared.lock().items.clone()),
+            vec![Notification::ToolListChanged, log("second"), log("third")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_is_reported_in_status() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = wassette::LifecycleManager::new(tempdir.path()).await?;
+        let config = NotificationQueueConfig {
+            capacity: Some(2),
+            overflow: NotificationOverflow::DropOldest,
+        };
+        let queue = NotificationQueue::new(&config, Some(Arc::new(manager.session_guard())));
+        queue.push(log("first"));
+        queue.push(log("second"));
+        queue.push(log("third"));
+        let status = manager.server_status().await;
+        assert_eq!(status.queued_notifications, 2);
+        assert_eq!(status.dropped_notifications, 1);
+
+        drop(queue);
+        assert_eq!(manager.server_status().await.queued_notifications, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disconnect_on_overflow() {
+        let ct = CancellationToken::new();
+        let queue =
+            stalled_queue(2, NotificationOverflow::Disconnect).with_disconnect(Some(ct.clone()));
+        queue.push(log("first"));
+        queue.push(log("second"));
+        assert!(!ct.is_cancelled());
+        queue.push(log("third"));
+        assert!(ct.is_cancelled());
+        assert_eq!(queue.depth(), 0);
+
+        // Nothing is queued for a closed session
+        queue.push(log("fourth"));
+        assert_eq!(queue.depth(), 0);
+    }
+
+    #[test]
+    fn test_disconnect_without_token_drops_oldest() {
+        let queue = stalled_queue(1, NotificationOverflow::Disconnect);
+        queue.push(log("first"));
+        queue.push(log("second"));
+        assert_eq!(
+            Vec::from(queue.shared.lock().items.clone()),
+            vec![log("second")]
+        );
+    }
+}
@@ -2,13 +2,62 @@
 // Licensed under the MIT license.
 
 use anyhow::Result;
-use rmcp::model::{ListResourcesRequest, ListResourcesResult};
+use rmcp::model::{
+    AnnotateAble, ListResourcesResult, RawResource, ReadResourceRequestParam, ReadResourceResult,
+    ResourceContents,
+};
+use tracing::{debug, instrument};
+use wassette::LifecycleManager;
+
+/// Handles a request to list resources, which are the stored tool call transcripts.
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_resources_list(
+    lifecycle_manager: &LifecycleManager,
+) -> Result<serde_json::Value> {
+    let resources = lifecycle_manager
+        .list_transcripts()
+        .await
+        .into_iter()
+        .map(|entry| {
+            let mut resource = RawResource::new(
+                entry.uri(),
+                format!(
+                    "{} transcript {}",
+                    entry.tool,
+                    entry.digest.get(..12).unwrap_or(&entry.digest)
+                ),
+            );
+            resource.description = Some(format!(
+                "Transcript of a call to tool '{}' of component '{}'",
+                entry.tool, entry.component_id
+            ));
+            resource.mime_type = Some("application/json".to_string());
+            resource.size = u32::try_from(entry.size).ok();
+            resource.no_annotation()
+        })
+        .collect::<Vec<_>>();
+    debug!(num_resources = resources.len(), "Listing resources");
 
-pub async fn handle_resources_list(req: serde_json::Value) -> Result<serde_json::Value> {
-    let _parsed_req: ListResourcesRequest = serde_json::from_value(req)?;
     let response = ListResourcesResult {
-        resources: vec![],
+        resources,
         next_cursor: None,
     };
     Ok(serde_json::to_value(response)?)
 }
+
+/// Handles a request to read a resource.
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_resources_read(
+    req: ReadResourceRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<serde_json::Value> {
+    let transcript = lifecycle_manager.read_transcript(&req.uri).await?;
+    let response = ReadResourceResult {
+        contents: vec![ResourceContents::TextResourceContents {
+            uri: req.uri,
+            mime_type: Some("application/json".to_string()),
+            text: transcript,
+        }],
+    };
+    Ok(serde_json::to_value(response)?)
+}
@@ -0,0 +1,404 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Server-side selection of the parts of a tool result that the caller needs.
+//!
+//! A call passes a jq-style expression in the `$select` argument, and only what the expression
+//! selects from the JSON result is returned. The expression is a pipeline of stages separated by
+//! `|`, each either a path like `.items[0].name`, `.["content-type"]` or `.items[]`, or an
+//! object built from paths like `{name, url: .links.self}`. `[]` iterates over an array or the
+//! values of an object, and an expression that iterates returns an array of what it selected.
+//! Missing fields select `null`.
+
+use anyhow::{bail, Context, Result};
+use rmcp::model::{CallToolRequestParam, CallToolResult, Content};
+use serde_json::{Map, Value};
+
+/// The argument that holds the expression selecting what to return from a call's result
+pub const SELECT_ARGUMENT_KEY: &str = "$select";
+
+/// Longest expression accepted, which keeps parsing cheap
+const MAX_EXPRESSION_LENGTH: usize = 1024;
+
+/// A parsed `$select` expression
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Selector {
+    stages: Vec<Stage>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Stage {
+    Path(Vec<Segment>),
+    Object(Vec<(String, Vec<Segment>)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(i64),
+    Iterate,
+}
+
+impl Selector {
+    /// Parses an expression
+    pub(crate) fn parse(expression: &str) -> Result<Self> {
+        if expression.len() > MAX_EXPRESSION_LENGTH {
+            bail!(
+                "'{SELECT_ARGUMENT_KEY}' expressions may be at most {MAX_EXPRESSION_LENGTH} \
+                 characters"
+            );
+        }
+        let mut parser = Parser {
+            chars: expression.chars().collect(),
+            pos: 0,
+        };
+        let mut stages = vec![parser.stage()?];
+        while parser.eat('|') {
+            stages.push(parser.stage()?);
+        }
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            bail!(
+                "Unexpected '{}' at position {} of '{expression}'",
+                parser.chars[parser.pos],
+                parser.pos
+            );
+        }
+        Ok(Self { stages })
+    }
+
+    fn iterates(&self) -> bool {
+        self.stages.iter().any(|stage| match stage {
+            Stage::Path(path) => path.contains(&Segment::Iterate),
+            Stage::Object(_) => false,
+        })
+    }
+
+    /// Applies the expression to a value
+    pub(crate) fn apply(&self, value: Value) -> Result<Value> {
+        let mut values = vec![value];
+        for stage in &self.stages {
+            let mut next = Vec::new();
+            for value in values {
+                match stage {
+                    Stage::Path(path) => next.extend(select_path(&value, path)?),
+                    Stage::Object(entries) => {
+                        let mut object = Map::new();
+                        for (key, path) in entries {
+                            let mut selected = select_path(&value, path)?;
+                            let selected = if path.contains(&Segment::Iterate) {
+                                Value::Array(selected)
+                            } else {
+                                selected.pop().unwrap_or(Value::Null)
+                            };
+                            object.insert(key.clone(), selected);
+                        }
+                        next.push(Value::Object(object));
+                    }
+                }
+            }
+            values = next;
+        }
+        if self.iterates() {
+            Ok(Value::Array(values))
+        } else {
+            Ok(values.pop().unwrap_or(Value::Null))
+        }
+    }
+}
+
+fn select_path(value: &Value, path: &[Segment]) -> Result<Vec<Value>> {
+    let Some((segment, rest)) = path.split_first() else {
+        return Ok(vec![value.clone()]);
+    };
+    match (segment, value) {
+        (Segment::Field(field), Value::Object(map)) => {
+            select_path(map.get(field).unwrap_or(&Value::Null), rest)
+        }
+        (Segment::Index(index), Value::Array(items)) => {
+            let index = if *index < 0 {
+                items.len().checked_sub(index.unsigned_abs() as usize)
+            } else {
+                Some(*index as usize)
+            };
+            let item = index.and_then(|i| items.get(i)).unwrap_or(&Value::Null);
+            select_path(item, rest)
+        }
+        (Segment::Iterate, Value::Array(items)) => items
+            .iter()
+            .map(|item| select_path(item, rest))
+            .collect::<Result<Vec<_>>>()
+            .map(|selected| selected.into_iter().flatten().collect()),
+        (Segment::Iterate, Value::Object(map)) => map
+            .values()
+            .map(|item| select_path(item, rest))
+            .collect::<Result<Vec<_>>>()
+            .map(|selected| selected.into_iter().flatten().collect()),
+        (Segment::Field(_) | Segment::Index(_), Value::Null) => select_path(&Value::Null, rest),
+        (Segment::Iterate, Value::Null) => Ok(Vec::new()),
+        (Segment::Field(field), other) => bail!("Cannot select '{field}' of {}", kind(other)),
+        (Segment::Index(index), other) => bail!("Cannot select [{index}] of {}", kind(other)),
+        (Segment::Iterate, other) => bail!("Cannot iterate over {}", kind(other)),
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        if !self.eat(expected) {
+            bail!("Expected '{expected}' at position {}", self.pos);
+        }
+        Ok(())
+    }
+
+    fn stage(&mut self) -> Result<Stage> {
+        if self.eat('{') {
+            let mut entries = Vec::new();
+            loop {
+                let key = match self.peek() {
+                    Some('"') => self.string()?,
+                    _ => self.identifier()?,
+                };
+                let path = if self.eat(':') {
+                    self.path()?
+                } else {
+                    vec![Segment::Field(key.clone())]
+                };
+                entries.push((key, path));
+                if !self.eat(',') {
+                    break;
+                }
+            }
+            self.expect('}')?;
+            Ok(Stage::Object(entries))
+        } else {
+            Ok(Stage::Path(self.path()?))
+        }
+    }
+
+    fn path(&mut self) -> Result<Vec<Segment>> {
+        self.expect('.')?;
+        let mut segments = Vec::new();
+        // The field right after the leading dot, as in `.items`
+        if self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_alphabetic() || *c == '_')
+        {
+            segments.push(Segment::Field(self.identifier()?));
+        } else if self.chars.get(self.pos) == Some(&'"') {
+            segments.push(Segment::Field(self.string()?));
+        }
+        loop {
+            match self.chars.get(self.pos) {
+                Some('.') => {
+                    self.pos += 1;
+                    match self.chars.get(self.pos) {
+                        Some('"') => segments.push(Segment::Field(self.string()?)),
+                        Some('[') => {}
+                        _ => segments.push(Segment::Field(self.identifier()?)),
+                    }
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(']') => segments.push(Segment::Iterate),
+                        Some('"') => segments.push(Segment::Field(self.string()?)),
+                        _ => segments.push(Segment::Index(self.integer()?)),
+                    }
+                    self.expect(']')?;
+                }
+                _ => return Ok(segments),
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            bail!("Expected a field name at position {start}");
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.chars.get(self.pos) {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some('\\') => {
+                    let escaped = self
+                        .chars
+                        .get(self.pos + 1)
+                        .context("Unterminated string")?;
+                    value.push(*escaped);
+                    self.pos += 2;
+                }
+                Some(c) => {
+                    value.push(*c);
+                    self.pos += 1;
+                }
+                None => bail!("Unterminated string"),
+            }
+        }
+    }
+
+    fn integer(&mut self) -> Result<i64> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if self.chars.get(self.pos) == Some(&'-') {
+            self.pos += 1;
+        }
+        while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse()
+            .with_context(|| format!("Expected an index at position {start}"))
+    }
+}
+
+/// Removes the `$select` argument from a call, parsing its expression
+pub(crate) fn take_selector(req: &mut CallToolRequestParam) -> Result<Option<Selector>> {
+    let Some(expression) = req
+        .arguments
+        .as_mut()
+        .and_then(|arguments| arguments.remove(SELECT_ARGUMENT_KEY))
+    else {
+        return Ok(None);
+    };
+    let expression = expression
+        .as_str()
+        .with_context(|| format!("Argument '{SELECT_ARGUMENT_KEY}' must be a string"))?;
+    Selector::parse(expression)
+        .map(Some)
+        .with_context(|| format!("Invalid '{SELECT_ARGUMENT_KEY}' expression"))
+}
+
+/// Replaces the JSON result of a successful call with what the selector selects from it
+pub(crate) fn apply_selector(
+    selector: &Selector,
+    result: CallToolResult,
+) -> Result<CallToolResult> {
+    if result.is_error == Some(true) {
+        return Ok(result);
+    }
+    let text = result
+        .content
+        .first()
+        .and_then(|content| content.as_text())
+        .map(|text| text.text.as_str())
+        .unwrap_or_default();
+    let value: Value = serde_json::from_str(text)
+        .with_context(|| format!("'{SELECT_ARGUMENT_KEY}' needs a JSON result"))?;
+    let selected = selector.apply(value)?;
+    // Notes that follow the result, such as where a profile was written, are kept
+    let mut content = vec![Content::text(serde_json::to_string(&selected)?)];
+    content.extend(result.content.into_iter().skip(1));
+    Ok(CallToolResult {
+        content,
+        is_error: result.is_error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn select(expression: &str, value: Value) -> Result<Value> {
+        Selector::parse(expression)?.apply(value)
+    }
+
+    #[test]
+    fn test_paths() -> Result<()> {
+        let value = json!({
+            "items": [{"name": "a", "tags": ["x"]}, {"name": "b", "tags": []}],
+            "content-type": "json"
+        });
+        assert_eq!(select(".", value.clone())?, value);
+        assert_eq!(select(".items[0].name", value.clone())?, json!("a"));
+        assert_eq!(select(".items[-1].name", value.clone())?, json!("b"));
+        assert_eq!(select(".items[].name", value.clone())?, json!(["a", "b"]));
+        assert_eq!(select(".[\"content-type\"]", value.clone())?, json!("json"));
+        assert_eq!(select(".missing.field", value.clone())?, Value::Null);
+        assert!(select(".items.name", value).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipes_and_objects() -> Result<()> {
+        let value = json!({
+            "data": {"users": [
+                {"id": 1, "login": "ana", "profile": {"url": "u1"}, "bio": "long"},
+                {"id": 2, "login": "bo", "profile": {"url": "u2"}, "bio": "longer"}
+            ]}
+        });
+        assert_eq!(
+            select(".data.users[] | {id, url: .profile.url}", value.clone())?,
+            json!([{"id": 1, "url": "u1"}, {"id": 2, "url": "u2"}])
+        );
+        assert_eq!(
+            select(".data | {logins: .users[].login}", value)?,
+            json!({"logins": ["ana", "bo"]})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!(Selector::parse("items").is_err());
+        assert!(Selector::parse(".items[").is_err());
+        assert!(Selector::parse(".a | ").is_err());
+        assert!(Selector::parse("{a, b").is_err());
+        assert!(Selector::parse(&format!(".{}", "a".repeat(MAX_EXPRESSION_LENGTH))).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The MCP server handler that serves the tools, prompts and resources of a lifecycle manager.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, ErrorData, ListPromptsResult, ListResourcesResult,
+    ListToolsResult, LoggingLevel, PaginatedRequestParam, ReadResourceRequestParam,
+    ReadResourceResult, ResourcesCapability, ServerCapabilities, ServerInfo, SetLevelRequestParam,
+    ToolsCapability,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::Peer;
+use rmcp::ServerHandler;
+use tokio_util::sync::CancellationToken;
+use wassette::SessionGuard;
+
+use crate::access_log::session_id;
+use crate::i18n::client_language;
+use crate::notifications::NotificationQueue;
+use crate::tools::call_tool;
+use crate::{
+    handle_prompts_list, handle_resources_list, handle_resources_read, handle_tools_list,
+    LifecycleManager, SessionVariables, ToolCallContext, DEFAULT_LOG_LEVEL,
+};
+
+/// A security-oriented runtime that runs WebAssembly Components via MCP.
+#[derive(Clone)]
+pub struct McpServer {
+    lifecycle_manager: LifecycleManager,
+    /// Minimum level of the log messages sent to the client, set with `logging/setLevel`
+    log_level: Arc<Mutex<LoggingLevel>>,
+    /// Identifies the session in server logs and in the components it calls
+    session_id: String,
+    /// Variables that the session's tool calls set and reference
+    variables: SessionVariables,
+    /// Counts the session as active in the server status while any clone of the server is alive
+    session: Arc<SessionGuard>,
+    /// Notifications waiting to be sent to the client, created with the first tool call
+    notifications: Arc<OnceLock<NotificationQueue>>,
+    /// Closes the session, if the transport allows it, when its client falls too far behind
+    disconnect: Option<CancellationToken>,
+}
+
+impl McpServer {
+    /// Creates a new MCP server instance with the given lifecycle manager. Each session should
+    /// get its own instance, so that log levels are set per session.
+    ///
+    /// # Arguments
+    /// * `lifecycle_manager` - The lifecycle manager for handling component operations
+    pub fn new(lifecycle_manager: LifecycleManager) -> Self {
+        Self {
+            log_level: Arc::new(Mutex::new(DEFAULT_LOG_LEVEL)),
+            session_id: new_session_id(),
+            variables: SessionVariables::default(),
+            session: Arc::new(lifecycle_manager.session_guard()),
+            notifications: Arc::default(),
+            disconnect: None,
+            lifecycle_manager,
+        }
+    }
+
+    /// Closes the session by cancelling `ct` if its client doesn't keep up with notifications
+    /// and the [overflow policy](wassette::NotificationOverflow) says so
+    pub(crate) fn with_disconnect(mut self, ct: CancellationToken) -> Self {
+        self.disconnect = Some(ct);
+        self
+    }
+
+    /// Returns the lifecycle manager that the server runs components with
+    pub fn lifecycle_manager(&self) -> &LifecycleManager {
+        &self.lifecycle_manager
+    }
+
+    /// Returns the ID of the session a request belongs to. Over the SSE transport, that is the
+    /// transport's session ID, so that calls can be found in the access log
+    fn session_id(&self, ctx: &RequestContext<RoleServer>) -> String {
+        ctx.extensions
+            .get::<axum::http::request::Parts>()
+            .and_then(|parts| session_id(&parts.uri))
+            .unwrap_or_else(|| self.session_id.clone())
+    }
+
+    /// Returns the notification queue of the session, starting it on first use
+    async fn notifications(&self, peer: &Peer<RoleServer>) -> NotificationQueue {
+        if let Some(notifications) = self.notifications.get() {
+            return notifications.clone();
+        }
+        let config = self.lifecycle_manager.notification_queue_config().await;
+        self.notifications
+            .get_or_init(|| {
+                NotificationQueue::start(peer.clone(), &config, Some(self.session.clone()))
+                    .with_disconnect(self.disconnect.clone())
+            })
+            .clone()
+    }
+
+    fn log_level(&self) -> LoggingLevel {
+        *self.log_level.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the language the client declared when it connected, or else the configured one
+    async fn language(&self, peer: &Peer<RoleServer>) -> Option<String> {
+        match peer
+            .peer_info()
+            .and_then(|info| client_language(&info.capabilities))
+        {
+            Some(language) => Some(language),
+            None => self.lifecycle_manager.language().await,
+        }
+    }
+}
+
+/// Returns an ID for a new session, made of the time it started and a per-process counter
+fn new_session_id() -> String {
+    static NEXT_SESSION: AtomicU64 = AtomicU64::new(0);
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    format!(
+        "{started:x}-{:x}",
+        NEXT_SESSION.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[allow(refining_impl_trait_reachable)]
+impl ServerHandler for McpServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities {
+                tools: Some(ToolsCapability {
+                    list_changed: Some(true),
+                }),
+                logging: Some(Default::default()),
+                resources: Some(ResourcesCapability::default()),
+                ..Default::default()
+            },
+            instructions: Some(
+                r#"This server runs tools in sandboxed WebAssembly environments with no default access to host resources.
+
+Key points:
+- Tools must be loaded before use: "Load component from oci://registry/tool:version" or "file:///path/to/tool.wasm"
+- When the server starts, it will load all tools present in the plugin directory.
+- You can list loaded tools with 'list-components' tool.
+- Each tool only accesses resources explicitly granted by a policy file (filesystem paths, network domains, etc.)
+- You MUST never modify the policy file directly, use tools to grant permissions instead.
+- Tools needs permission for that resource
+- If access is denied, suggest alternatives within allowed permissions or propose to grant permission"#.to_string(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn call_tool<'a>(
+        &'a self,
+        params: CallToolRequestParam,
+        ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<CallToolResult, ErrorData>> + Send + 'a>> {
+        let mut context = ToolCallContext {
+            log_level: self.log_level(),
+            request_id: Some(ctx.id.to_string()),
+            session_id: Some(self.session_id(&ctx)),
+            variables: self.variables.clone(),
+            language: None,
+        };
+
+        Box::pin(async move {
+            context.language = self.language(&ctx.peer).await;
+            let notifications = self.notifications(&ctx.peer).await;
+            let result = call_tool(params, &self.lifecycle_manager, &notifications, context).await;
+            match result {
+                Ok(value) => serde_json::from_value(value).map_err(|e| {
+                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
+                }),
+                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
+            }
+        })
+    }
+
+    fn set_level<'a>(
+        &'a self,
+        params: SetLevelRequestParam,
+        _ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), ErrorData>> + Send + 'a>> {
+        Box::pin(async move {
+            tracing::debug!(level = ?params.level, "Setting log level of session");
+            *self.log_level.lock().unwrap_or_else(|e| e.into_inner()) = params.level;
+            Ok(())
+        })
+    }
+
+    fn list_tools<'a>(
+        &'a self,
+        _params: Option<PaginatedRequestParam>,
+        ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<ListToolsResult, ErrorData>> + Send + 'a>> {
+        Box::pin(async move {
+            let language = self.language(&ctx.peer).await;
+            let result = handle_tools_list(&self.lifecycle_manager, language.as_deref()).await;
+            match result {
+                Ok(value) => serde_json::from_value(value).map_err(|e| {
+                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
+                }),
+                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
+            }
+        })
+    }
+
+    fn list_prompts<'a>(
+        &'a self,
+        _params: Option<PaginatedRequestParam>,
+        _ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<ListPromptsResult, ErrorData>> + Send + 'a>> {
+        Box::pin(async move {
+            let result = handle_prompts_list(serde_json::Value::Null).await;
+            match result {
+                Ok(value) => serde_json::from_value(value).map_err(|e| {
+                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
+                }),
+                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
+            }
+        })
+    }
+
+    fn list_resources<'a>(
+        &'a self,
+        _params: Option<PaginatedRequestParam>,
+        _ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<ListResourcesResult, ErrorData>> + Send + 'a>> {
+        Box::pin(async move {
+            let result = handle_resources_list(&self.lifecycle_manager).await;
+            match result {
+                Ok(value) => serde_json::from_value(value).map_err(|e| {
+                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
+                }),
+                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
+            }
+        })
+    }
+
+    fn read_resource<'a>(
+        &'a self,
+        params: ReadResourceRequestParam,
+        _ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<ReadResourceResult, ErrorData>> + Send + 'a>> {
+        Box::pin(async move {
+            let uri = params.uri.clone();
+            let result = handle_resources_read(params, &self.lifecycle_manager).await;
+            match result {
+                Ok(value) => serde_json::from_value(value).map_err(|e| {
+                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
+                }),
+                Err(err) => Err(ErrorData::resource_not_found(
+                    format!("{err:#}"),
+                    Some(serde_json::json!({ "uri": uri })),
+                )),
+            }
+        })
+    }
+}
@@ -5,24 +5,37 @@ use std::borrow::Cow;
 use std::sync::Arc;
 
 use anyhow::Result;
-use rmcp::model::{CallToolRequestParam, CallToolResult, Content, Tool};
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, LoggingLevel, Tool, ToolAnnotations,
+};
 use rmcp::{Peer, RoleServer};
 use serde_json::{json, Value};
 use tracing::{debug, error, info, instrument};
-use wassette::LifecycleManager;
+use wassette::{CorrelationIds, LifecycleManager};
 
+use crate::batch::handle_execute_batch;
 use crate::components::{
     extract_args_from_request, get_component_tools, handle_component_call, handle_list_components,
-    handle_load_component, handle_unload_component,
+    handle_load_component, handle_load_components, handle_unload_component,
 };
-
-/// Handles a request to list available tools.
+use crate::i18n::{missing_argument, unknown_tool, Messages};
+use crate::logging::{McpLogger, DEFAULT_LOG_LEVEL, HOST_LOGGER};
+use crate::notifications::NotificationQueue;
+use crate::select::{apply_selector, take_selector};
+use crate::uploads::{handle_append_chunk, handle_begin_upload, handle_call_with_upload};
+use crate::variables::{handle_get_variable, handle_set_variable, save_result, SessionVariables};
+
+/// Handles a request to list available tools. The descriptions of the builtin tools are in
+/// `language` if it is one of the [`SUPPORTED_LANGUAGES`](crate::SUPPORTED_LANGUAGES).
 #[instrument(skip(lifecycle_manager))]
-pub async fn handle_tools_list(lifecycle_manager: &LifecycleManager) -> Result<Value> {
+pub async fn handle_tools_list(
+    lifecycle_manager: &LifecycleManager,
+    language: Option<&str>,
+) -> Result<Value> {
     debug!("Handling tools list request");
 
     let mut tools = get_component_tools(lifecycle_manager).await?;
-    tools.extend(get_builtin_tools());
+    tools.extend(localized_builtin_tools(Messages::for_language(language)));
     debug!(num_tools = %tools.len(), "Retrieved tools");
 
     let response = rmcp::model::ListToolsResult {
@@ -33,40 +46,113 @@ pub async fn handle_tools_list(lifecycle_manager: &LifecycleManager) -> Result<V
     Ok(serde_json::to_value(response)?)
 }
 
-/// Handles a tool call request.
-#[instrument(skip_all, fields(method_name = %req.name))]
+/// Details of a tool call request that come from its MCP session
+#[derive(Debug, Clone)]
+pub struct ToolCallContext {
+    /// Minimum level of the log messages sent to the client
+    pub log_level: LoggingLevel,
+    /// ID of the MCP request, passed to components to correlate their logs
+    pub request_id: Option<String>,
+    /// ID of the MCP session, passed to components to correlate their logs
+    pub session_id: Option<String>,
+    /// Variables of the MCP session, which tool arguments can reference
+    pub variables: SessionVariables,
+    /// Language of the error messages sent to the client
+    pub language: Option<String>,
+}
+
+impl Default for ToolCallContext {
+    fn default() -> Self {
+        Self {
+            log_level: DEFAULT_LOG_LEVEL,
+            request_id: None,
+            session_id: None,
+            variables: SessionVariables::default(),
+            language: None,
+        }
+    }
+}
+
+/// Handles a tool call request. Component stderr and host-side diagnostics of the call are sent
+/// to the client as log messages of at least the context's log level. Variable references in the
+/// arguments are replaced with the values of the session's variables. The result is narrowed to
+/// what the `$select` argument selects, and saved in a variable instead of being returned if the
+/// call asks for it with `$save_as`.
+///
+/// Notifications are sent through a queue of their own for this call, bounded as configured in
+/// the lifecycle manager.
 pub async fn handle_tools_call(
     req: CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
     server_peer: Peer<RoleServer>,
+    context: ToolCallContext,
+) -> Result<Value> {
+    let config = lifecycle_manager.notification_queue_config().await;
+    let notifications = NotificationQueue::start(server_peer, &config, None);
+    call_tool(req, lifecycle_manager, &notifications, context).await
+}
+
+/// Handles a tool call request like [`handle_tools_call`], queueing notifications in the
+/// session's queue
+#[instrument(skip_all, fields(
+    method_name = %req.name,
+    request_id = context.request_id.as_deref(),
+    session_id = context.session_id.as_deref(),
+))]
+pub(crate) async fn call_tool(
+    mut req: CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    notifications: &NotificationQueue,
+    context: ToolCallContext,
 ) -> Result<Value> {
     info!("Handling tool call");
+    let logger = McpLogger::new(notifications.clone(), context.log_level);
+    let messages = Messages::for_language(context.language.as_deref());
+    let correlation = CorrelationIds {
+        request_id: context.request_id,
+        session_id: context.session_id,
+    };
 
-    let result = match req.name.as_ref() {
-        "load-component" => handle_load_component(&req, lifecycle_manager, server_peer).await,
-        "unload-component" => handle_unload_component(&req, lifecycle_manager, server_peer).await,
-        "list-components" => handle_list_components(lifecycle_manager).await,
-        "get-policy" => handle_get_policy(&req, lifecycle_manager).await,
-        "grant-storage-permission" => {
-            handle_grant_storage_permission(&req, lifecycle_manager).await
-        }
-        "grant-network-permission" => {
-            handle_grant_network_permission(&req, lifecycle_manager).await
-        }
-        "grant-environment-variable-permission" => {
-            handle_grant_environment_variable_permission(&req, lifecycle_manager).await
+    let variables = context.variables;
+    let prepared = variables
+        .prepare_call(&mut req)
+        .and_then(|save_as| Ok((save_as, take_selector(&mut req)?)));
+    let result = match prepared {
+        Ok((save_as, selector)) => {
+            let result = dispatch_tool_call(
+                &req,
+                lifecycle_manager,
+                notifications,
+                &logger,
+                &variables,
+                correlation,
+            )
+            .await;
+            let result = match selector {
+                Some(selector) => result.and_then(|result| apply_selector(&selector, result)),
+                None => result,
+            };
+            match save_as {
+                Some(name) => result.and_then(|result| save_result(&name, result, &variables)),
+                None => result,
+            }
         }
-        _ => handle_component_call(&req, lifecycle_manager).await,
+        Err(e) => Err(e),
     };
 
     if let Err(ref e) = result {
         error!(error = ?e, "Tool call failed");
+        logger.log(
+            LoggingLevel::Error,
+            HOST_LOGGER,
+            format!("Tool call '{}' failed: {e}", req.name),
+        );
     }
 
     match result {
         Ok(result) => Ok(serde_json::to_value(result)?),
         Err(e) => {
-            let error_text = format!("Error: {e}");
+            let error_text = messages.error_text(&e);
             let contents = vec![Content::text(error_text)];
 
             let error_result = CallToolResult {
@@ -78,6 +164,75 @@ pub async fn handle_tools_call(
     }
 }
 
+/// Runs a tool call with its variable references already replaced
+async fn dispatch_tool_call(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    notifications: &NotificationQueue,
+    logger: &McpLogger,
+    variables: &SessionVariables,
+    correlation: CorrelationIds,
+) -> Result<CallToolResult> {
+    match req.name.as_ref() {
+        "load-component" => handle_load_component(req, lifecycle_manager, notifications).await,
+        "load-components" => handle_load_components(req, lifecycle_manager, notifications).await,
+        "unload-component" => handle_unload_component(req, lifecycle_manager, notifications).await,
+        "list-components" => handle_list_components(req, lifecycle_manager).await,
+        "get-policy" => handle_get_policy(req, lifecycle_manager).await,
+        "get-tool-stats" => handle_get_tool_stats(req, lifecycle_manager).await,
+        "get-tool-schema" => handle_get_tool_schema(req, lifecycle_manager).await,
+        "server-status" => handle_server_status(lifecycle_manager).await,
+        "query-audit-log" => handle_query_audit_log(req, lifecycle_manager).await,
+        "grant-storage-permission" => handle_grant_storage_permission(req, lifecycle_manager).await,
+        "grant-network-permission" => handle_grant_network_permission(req, lifecycle_manager).await,
+        "grant-environment-variable-permission" => {
+            handle_grant_environment_variable_permission(req, lifecycle_manager).await
+        }
+        "set-component-config" => handle_set_component_config(req, lifecycle_manager).await,
+        "get-component-config" => handle_get_component_config(req, lifecycle_manager).await,
+        "begin-upload" => {
+            handle_begin_upload(lifecycle_manager, correlation.session_id.as_deref()).await
+        }
+        "append-chunk" => {
+            handle_append_chunk(req, lifecycle_manager, correlation.session_id.as_deref()).await
+        }
+        "call-with-upload" => {
+            handle_call_with_upload(req, lifecycle_manager, logger, correlation).await
+        }
+        "execute-batch" => handle_execute_batch(req, lifecycle_manager, logger, correlation).await,
+        "set-variable" => handle_set_variable(req, variables).await,
+        "get-variable" => handle_get_variable(req, variables).await,
+        _ => handle_component_call(req, lifecycle_manager, logger, correlation).await,
+    }
+}
+
+/// Annotations of a builtin tool. Only loading components reaches outside of the server.
+fn annotations(
+    read_only: bool,
+    destructive: bool,
+    idempotent: bool,
+    open_world: bool,
+) -> Option<ToolAnnotations> {
+    Some(ToolAnnotations {
+        title: None,
+        read_only_hint: Some(read_only),
+        destructive_hint: Some(destructive),
+        idempotent_hint: Some(idempotent),
+        open_world_hint: Some(open_world),
+    })
+}
+
+/// Returns the builtin tools with their descriptions translated where the messages have them
+fn localized_builtin_tools(messages: Messages) -> Vec<Tool> {
+    let mut tools = get_builtin_tools();
+    for tool in &mut tools {
+        if let Some(description) = messages.tool_description(&tool.name) {
+            tool.description = Some(Cow::Borrowed(description));
+        }
+    }
+    tools
+}
+
 fn get_builtin_tools() -> Vec<Tool> {
     debug!("Getting builtin tools");
     vec![
@@ -96,39 +251,79 @@ fn get_builtin_tools() -> Vec<Tool> {
                 }))
                 .unwrap_or_default(),
             ),
-            annotations: None,
+            annotations: annotations(false, false, true, true),
+        },
+        Tool {
+            name: Cow::Borrowed("load-components"),
+            description: Some(Cow::Borrowed(
+                "Loads several components at once, downloading and compiling them concurrently. Returns whether each one loaded.",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "paths": {
+                            "type": "array",
+                            "items": {"type": "string"},
+                            "description": "URIs of the components, as accepted by load-component"
+                        },
+                        "concurrency": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "maximum": 16,
+                            "description": "How many components to load at once. Defaults to 4"
+                        }
+                    },
+                    "required": ["paths"]
+                }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(false, false, true, true),
         },
         Tool {
             name: Cow::Borrowed("unload-component"),
             description: Some(Cow::Borrowed(
-                "Unloads a tool or component.",
+                "Unloads a tool or component and removes its files, including its policy unless keep_policy is set and its blob containers unless keep_data is set. Reports in `removed` whether there was a component to unload.",
             )),
             input_schema: Arc::new(
                 serde_json::from_value(json!({
                     "type": "object",
                     "properties": {
-                        "id": {"type": "string"}
+                        "id": {"type": "string"},
+                        "keep_policy": {
+                            "type": "boolean",
+                            "description": "Keep the component's policy, so that it applies again when the component is loaded again"
+                        },
+                        "keep_data": {
+                            "type": "boolean",
+                            "description": "Keep the component's blob containers and the objects in them"
+                        }
                     },
                     "required": ["id"]
                 }))
                 .unwrap_or_default(),
             ),
-            annotations: None,
+            annotations: annotations(false, true, true, false),
         },
         Tool {
             name: Cow::Borrowed("list-components"),
             description: Some(Cow::Borrowed(
-                "Lists all currently loaded components or tools.",
+                "Lists all currently loaded components or tools, optionally only those whose labels match a selector.",
             )),
             input_schema: Arc::new(
                 serde_json::from_value(json!({
                     "type": "object",
-                    "properties": {},
+                    "properties": {
+                        "labels": {
+                            "type": "string",
+                            "description": "Label selector, a comma-separated list of key=value, key!=value, key or !key conditions that must all hold, e.g. team=infra,risk!=high"
+                        }
+                    },
                     "required": []
                 }))
                 .unwrap_or_default(),
             ),
-            annotations: None,
+            annotations: annotations(true, false, true, false),
         },
         Tool {
             name: Cow::Borrowed("get-policy"),
@@ -148,7 +343,109 @@ fn get_builtin_tools() -> Vec<Tool> {
                 }))
                 .unwrap_or_default(),
             ),
-            annotations: None,
+            annotations: annotations(true, false, true, false),
+        },
+        Tool {
+            name: Cow::Borrowed("get-tool-stats"),
+            description: Some(Cow::Borrowed(
+                "Gets usage statistics for component tools: how often each tool was called, its success rate, its average latency and the most memory a call used",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "component_id": {
+                            "type": "string",
+                            "description": "Only return statistics for the tools of this component"
+                        }
+                    },
+                    "required": []
+                }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(true, false, true, false),
+        },
+        Tool {
+            name: Cow::Borrowed("get-tool-schema"),
+            description: Some(Cow::Borrowed(
+                "Gets the full definition of a tool, including the parts of its input and output schemas that the tool list abbreviates",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "Name of the tool"
+                        }
+                    },
+                    "required": ["name"]
+                }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(true, false, true, false),
+        },
+        Tool {
+            name: Cow::Borrowed("server-status"),
+            description: Some(Cow::Borrowed(
+                "Gets the resource usage of the server: process memory, live component instances, staged uploads, the size and hit rate of the compilation and result caches, and the estimated memory use of each loaded component",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {},
+                    "required": []
+                }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(true, false, true, false),
+        },
+        Tool {
+            name: Cow::Borrowed("query-audit-log"),
+            description: Some(Cow::Borrowed(
+                "Searches the audit log of approval decisions on destructive tool calls by component, tool, time range and outcome. Returns the matching decisions oldest first, a page at a time, with a cursor for the next page if there are more",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "component_id": {
+                            "type": "string",
+                            "description": "Only decisions on calls to this component"
+                        },
+                        "tool": {
+                            "type": "string",
+                            "description": "Only decisions on calls to this tool"
+                        },
+                        "since": {
+                            "type": ["string", "integer"],
+                            "description": "Only decisions made at or after this time: an RFC 3339 timestamp, milliseconds since the Unix epoch or a duration before now such as 12h or 7d"
+                        },
+                        "until": {
+                            "type": ["string", "integer"],
+                            "description": "Only decisions made before this time, in the same formats as since"
+                        },
+                        "outcome": {
+                            "type": "string",
+                            "enum": ["approved", "denied", "expired"],
+                            "description": "Only decisions with this outcome"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "maximum": wassette::MAX_AUDIT_PAGE_SIZE,
+                            "description": "Largest number of decisions to return, 50 by default"
+                        },
+                        "cursor": {
+                            "type": "string",
+                            "description": "Cursor of the page to return, from next_cursor of the previous page"
+                        }
+                    },
+                    "required": []
+                }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(true, false, true, false),
         },
         Tool {
             name: Cow::Borrowed("grant-storage-permission"),
@@ -187,7 +484,7 @@ fn get_builtin_tools() -> Vec<Tool> {
                   }))
                 .unwrap_or_default(),
             ),
-            annotations: None,
+            annotations: annotations(false, false, true, false),
         },
         Tool {
             name: Cow::Borrowed("grant-network-permission"),
@@ -218,7 +515,7 @@ fn get_builtin_tools() -> Vec<Tool> {
                   }))
                 .unwrap_or_default(),
             ),
-            annotations: None,
+            annotations: annotations(false, false, true, false),
         },
         Tool {
             name: Cow::Borrowed("grant-environment-variable-permission"),
@@ -249,8 +546,208 @@ fn get_builtin_tools() -> Vec<Tool> {
                   }))
                 .unwrap_or_default(),
             ),
+            annotations: annotations(false, false, true, false),
+        },
+        Tool {
+            name: Cow::Borrowed("set-component-config"),
+            description: Some(Cow::Borrowed(
+                "Sets a setting of a component, such as a feature flag, that the component reads through wasi:config. The setting is stored in the config section of the component's policy and applies from the next call on. Omit the value to remove the setting."
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                      "component_id": {
+                        "type": "string",
+                        "description": "ID of the component to configure"
+                      },
+                      "key": {
+                        "type": "string",
+                        "description": "Name of the setting, made of letters, digits, '_', '-' and '.'"
+                      },
+                      "value": {
+                        "type": "string",
+                        "description": "Value of the setting. Omit it to remove the setting"
+                      }
+                    },
+                    "required": ["component_id", "key"]
+                  }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(false, false, true, false),
+        },
+        Tool {
+            name: Cow::Borrowed("get-component-config"),
+            description: Some(Cow::Borrowed(
+                "Returns the settings of a component from the config section of its policy, which the component reads through wasi:config."
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                      "component_id": {
+                        "type": "string",
+                        "description": "ID of the component whose settings to return"
+                      }
+                    },
+                    "required": ["component_id"]
+                  }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(true, false, true, false),
+        },
+        Tool {
+            name: Cow::Borrowed("begin-upload"),
+            description: Some(Cow::Borrowed(
+                "Starts a chunked upload for a large binary parameter. Returns an upload ID to pass to append-chunk and to reference as {\"$upload\": \"<upload_id>\"} in call-with-upload."
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {},
+                    "required": []
+                }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(false, false, false, false),
+        },
+        Tool {
+            name: Cow::Borrowed("append-chunk"),
+            description: Some(Cow::Borrowed(
+                "Appends a base64 encoded chunk of data to an upload started with begin-upload."
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                      "upload_id": {
+                        "type": "string",
+                        "description": "ID of the upload returned by begin-upload"
+                      },
+                      "data": {
+                        "type": "string",
+                        "description": "Base64 encoded chunk of data"
+                      }
+                    },
+                    "required": ["upload_id", "data"]
+                  }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(false, false, false, false),
+        },
+        Tool {
+            name: Cow::Borrowed("call-with-upload"),
+            description: Some(Cow::Borrowed(
+                "Calls a component tool, replacing every {\"$upload\": \"<upload_id>\"} value in the arguments with the bytes staged for that upload. Uploads are consumed by the call."
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                      "tool": {
+                        "type": "string",
+                        "description": "Name of the tool to call"
+                      },
+                      "arguments": {
+                        "type": "object",
+                        "description": "Arguments for the tool, which may contain upload references"
+                      }
+                    },
+                    "required": ["tool"]
+                  }))
+                .unwrap_or_default(),
+            ),
+            annotations: None,
+        },
+        Tool {
+            name: Cow::Borrowed("execute-batch"),
+            description: Some(Cow::Borrowed(
+                "Calls several component tools in order in a single request and returns the result of each step. An argument can take the result of an earlier step as {\"$step\": \"<step id>\", \"pointer\": \"/json/pointer\"}, where the pointer is optional."
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                      "steps": {
+                        "type": "array",
+                        "maxItems": 32,
+                        "items": {
+                          "type": "object",
+                          "properties": {
+                            "id": {
+                              "type": "string",
+                              "description": "Name that later steps use to reference the result of this step. Defaults to the step's index"
+                            },
+                            "tool": {
+                              "type": "string",
+                              "description": "Name of the tool to call"
+                            },
+                            "arguments": {
+                              "type": "object",
+                              "description": "Arguments for the tool, which may contain step references"
+                            }
+                          },
+                          "required": ["tool"]
+                        }
+                      },
+                      "stop_on_error": {
+                        "type": "boolean",
+                        "description": "Skip the remaining steps once a step fails. Defaults to true"
+                      }
+                    },
+                    "required": ["steps"]
+                  }))
+                .unwrap_or_default(),
+            ),
             annotations: None,
         },
+        Tool {
+            name: Cow::Borrowed("set-variable"),
+            description: Some(Cow::Borrowed(
+                "Sets a variable of this session. The arguments of later tool calls can use it as \"${name}\", and any call can save its result in a variable instead of returning it by passing \"$save_as\": \"<name>\"."
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                      "name": {
+                        "type": "string",
+                        "description": "Name of the variable, made of letters, digits, '_' and '-'"
+                      },
+                      "value": {
+                        "description": "Value of the variable, of any type"
+                      }
+                    },
+                    "required": ["name", "value"]
+                  }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(false, false, true, false),
+        },
+        Tool {
+            name: Cow::Borrowed("get-variable"),
+            description: Some(Cow::Borrowed(
+                "Gets the value of a variable of this session, or the part of it at a JSON pointer."
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                      "name": {
+                        "type": "string",
+                        "description": "Name of the variable"
+                      },
+                      "pointer": {
+                        "type": "string",
+                        "description": "JSON pointer to the part of the value to return, e.g. /items/0"
+                      }
+                    },
+                    "required": ["name"]
+                  }))
+                .unwrap_or_default(),
+            ),
+            annotations: annotations(true, false, true, false),
+        },
     ]
 }
 
@@ -264,7 +761,8 @@ async fn handle_get_policy(
     let component_id = args
         .get("component_id")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'component_id'"))?;
+        .ok_or_else(|| missing_argument("component_id"))?;
+    let component_id = &lifecycle_manager.resolve_component_id(component_id).await?;
 
     info!("Getting policy for component {}", component_id);
 
@@ -297,6 +795,129 @@ async fn handle_get_policy(
     })
 }
 
+#[instrument(skip(lifecycle_manager))]
+async fn handle_get_tool_stats(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    // All arguments are optional, so a request without any is fine
+    let args = extract_args_from_request(req).unwrap_or_default();
+    let component_id = match args.get("component_id").and_then(|v| v.as_str()) {
+        Some(id) => Some(lifecycle_manager.resolve_component_id(id).await?),
+        None => None,
+    };
+    let component_id = component_id.as_deref();
+
+    info!(?component_id, "Getting tool usage statistics");
+
+    let usage = lifecycle_manager.get_tool_stats(component_id).await;
+    let tools: Vec<Value> = usage
+        .iter()
+        .map(|(component_id, tool, stats)| {
+            json!({
+                "component_id": component_id,
+                "tool": tool,
+                "invocations": stats.invocations,
+                "successes": stats.successes,
+                "failures": stats.failures,
+                "success_rate": stats.success_rate(),
+                "average_latency_ms": stats.average_latency_ms(),
+                "peak_memory_bytes": stats.peak_memory_bytes,
+                "peak_table_elements": stats.peak_table_elements,
+                "last_called": stats.last_called
+            })
+        })
+        .collect();
+
+    let status_text = serde_json::to_string(&json!({
+        "tools": tools,
+        "total_invocations": usage.iter().map(|(_, _, stats)| stats.invocations).sum::<u64>()
+    }))?;
+
+    Ok(CallToolResult {
+        content: vec![Content::text(status_text)],
+        is_error: None,
+    })
+}
+
+#[instrument(skip(lifecycle_manager))]
+async fn handle_get_tool_schema(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let name = args
+        .get("name")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| missing_argument("name"))?;
+
+    let schema = lifecycle_manager
+        .get_tool_schema(name)
+        .await
+        .ok_or_else(|| unknown_tool(name))?;
+
+    Ok(CallToolResult {
+        content: vec![Content::text(serde_json::to_string(&schema)?)],
+        is_error: None,
+    })
+}
+
+#[instrument(skip(lifecycle_manager))]
+async fn handle_server_status(lifecycle_manager: &LifecycleManager) -> Result<CallToolResult> {
+    info!("Getting server status");
+
+    let status = lifecycle_manager.server_status().await;
+    let status_text = serde_json::to_string(&status)?;
+
+    Ok(CallToolResult {
+        content: vec![Content::text(status_text)],
+        is_error: None,
+    })
+}
+
+#[instrument(skip(lifecycle_manager))]
+async fn handle_query_audit_log(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    // All arguments are optional, so a request without any is fine
+    let args = extract_args_from_request(req).unwrap_or_default();
+    let string = |name: &str| args.get(name).and_then(|v| v.as_str()).map(String::from);
+    let time = |name: &str| -> Result<Option<u64>> {
+        match args.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(time)) => wassette::parse_audit_time(time).map(Some),
+            Some(time) => time
+                .as_u64()
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("Invalid {name}: {time}")),
+        }
+    };
+    let query = wassette::AuditQuery {
+        component: string("component_id"),
+        tool: string("tool"),
+        since_ms: time("since")?,
+        until_ms: time("until")?,
+        outcome: string("outcome").map(|o| o.parse()).transpose()?,
+        limit: args
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .map(|limit| limit as usize),
+        cursor: string("cursor"),
+    };
+
+    info!(?query, "Querying audit log");
+
+    let page = lifecycle_manager.query_audit_log(&query).await?;
+    let page_text = serde_json::to_string(&page)?;
+
+    Ok(CallToolResult {
+        content: vec![Content::text(page_text)],
+        is_error: None,
+    })
+}
+
 #[instrument(skip(lifecycle_manager))]
 async fn handle_grant_storage_permission(
     req: &CallToolRequestParam,
@@ -307,11 +928,12 @@ async fn handle_grant_storage_permission(
     let component_id = args
         .get("component_id")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'component_id'"))?;
+        .ok_or_else(|| missing_argument("component_id"))?;
+    let component_id = &lifecycle_manager.resolve_component_id(component_id).await?;
 
     let details = args
         .get("details")
-        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'details'"))?;
+        .ok_or_else(|| missing_argument("details"))?;
 
     info!("Granting storage permission to component {}", component_id);
 
@@ -356,11 +978,12 @@ async fn handle_grant_network_permission(
     let component_id = args
         .get("component_id")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'component_id'"))?;
+        .ok_or_else(|| missing_argument("component_id"))?;
+    let component_id = &lifecycle_manager.resolve_component_id(component_id).await?;
 
     let details = args
         .get("details")
-        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'details'"))?;
+        .ok_or_else(|| missing_argument("details"))?;
 
     info!("Granting network permission to component {}", component_id);
 
@@ -405,11 +1028,12 @@ async fn handle_grant_environment_variable_permission(
     let component_id = args
         .get("component_id")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'component_id'"))?;
+        .ok_or_else(|| missing_argument("component_id"))?;
+    let component_id = &lifecycle_manager.resolve_component_id(component_id).await?;
 
     let details = args
         .get("details")
-        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'details'"))?;
+        .ok_or_else(|| missing_argument("details"))?;
 
     info!(
         "Granting environment variable permission to component {}",
@@ -447,6 +1071,71 @@ async fn handle_grant_environment_variable_permission(
     }
 }
 
+#[instrument(skip(lifecycle_manager))]
+async fn handle_set_component_config(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let component_id = args
+        .get("component_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| missing_argument("component_id"))?;
+    let component_id = &lifecycle_manager.resolve_component_id(component_id).await?;
+    let key = args
+        .get("key")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| missing_argument("key"))?;
+    let value = args.get("value").and_then(|v| v.as_str());
+
+    info!("Setting config key {} of component {}", key, component_id);
+
+    lifecycle_manager
+        .set_component_config(component_id, key, value)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!("Failed to set config of component {}: {}", component_id, e)
+        })?;
+
+    let status_text = serde_json::to_string(&json!({
+        "status": if value.is_some() { "config set" } else { "config removed" },
+        "component_id": component_id,
+        "key": key,
+        "value": value
+    }))?;
+
+    Ok(CallToolResult {
+        content: vec![Content::text(status_text)],
+        is_error: None,
+    })
+}
+
+#[instrument(skip(lifecycle_manager))]
+async fn handle_get_component_config(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let component_id = args
+        .get("component_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| missing_argument("component_id"))?;
+    let component_id = &lifecycle_manager.resolve_component_id(component_id).await?;
+
+    let config = lifecycle_manager.get_component_config(component_id).await?;
+    let status_text = serde_json::to_string(&json!({
+        "component_id": component_id,
+        "config": config
+    }))?;
+
+    Ok(CallToolResult {
+        content: vec![Content::text(status_text)],
+        is_error: None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -454,16 +1143,89 @@ mod tests {
     #[test]
     fn test_get_builtin_tools() {
         let tools = get_builtin_tools();
-        assert_eq!(tools.len(), 7);
+        assert_eq!(tools.len(), 20);
         assert!(tools.iter().any(|t| t.name == "load-component"));
+        assert!(tools.iter().any(|t| t.name == "load-components"));
         assert!(tools.iter().any(|t| t.name == "unload-component"));
         assert!(tools.iter().any(|t| t.name == "list-components"));
         assert!(tools.iter().any(|t| t.name == "get-policy"));
+        assert!(tools.iter().any(|t| t.name == "get-tool-stats"));
+        assert!(tools.iter().any(|t| t.name == "get-tool-schema"));
+        assert!(tools.iter().any(|t| t.name == "server-status"));
+        assert!(tools.iter().any(|t| t.name == "query-audit-log"));
         assert!(tools.iter().any(|t| t.name == "grant-storage-permission"));
         assert!(tools.iter().any(|t| t.name == "grant-network-permission"));
         assert!(tools
             .iter()
             .any(|t| t.name == "grant-environment-variable-permission"));
+        assert!(tools.iter().any(|t| t.name == "set-component-config"));
+        assert!(tools.iter().any(|t| t.name == "get-component-config"));
+        assert!(tools.iter().any(|t| t.name == "begin-upload"));
+        assert!(tools.iter().any(|t| t.name == "append-chunk"));
+        assert!(tools.iter().any(|t| t.name == "call-with-upload"));
+        assert!(tools.iter().any(|t| t.name == "execute-batch"));
+        assert!(tools.iter().any(|t| t.name == "set-variable"));
+        assert!(tools.iter().any(|t| t.name == "get-variable"));
+    }
+
+    #[test]
+    fn test_localized_builtin_tools() {
+        let tools = localized_builtin_tools(Messages::for_language(Some("de")));
+        let load = tools.iter().find(|t| t.name == "load-component").unwrap();
+        assert!(load.description.as_deref().unwrap().starts_with("Lädt"));
+        assert_eq!(tools.len(), get_builtin_tools().len());
+    }
+
+    #[tokio::test]
+    async fn test_get_tool_stats_without_calls() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = wassette::LifecycleManager::new(&tempdir).await?;
+
+        let req = CallToolRequestParam {
+            name: "get-tool-stats".into(),
+            arguments: None,
+        };
+        let result = handle_get_tool_stats(&req, &lifecycle_manager).await?;
+        let text = &result.content[0].as_text().unwrap().text;
+        let stats: Value = serde_json::from_str(text)?;
+        assert_eq!(stats["tools"], json!([]));
+        assert_eq!(stats["total_invocations"], 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_tool_schema_of_unknown_tool() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = wassette::LifecycleManager::new(&tempdir).await?;
+
+        let mut args = serde_json::Map::new();
+        args.insert("name".to_string(), json!("missing-tool"));
+        let req = CallToolRequestParam {
+            name: "get-tool-schema".into(),
+            arguments: Some(args),
+        };
+        let result = handle_get_tool_schema(&req, &lifecycle_manager).await;
+        assert!(result.unwrap_err().to_string().contains("Unknown tool"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_status() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = wassette::LifecycleManager::new(&tempdir).await?;
+
+        let result = handle_server_status(&lifecycle_manager).await?;
+        let text = &result.content[0].as_text().unwrap().text;
+        let status: Value = serde_json::from_str(text)?;
+        assert_eq!(status["loaded_components"], 0);
+        assert_eq!(status["live_stores"], 0);
+        assert_eq!(status["components"], json!({}));
+
+        let session = crate::McpServer::new(lifecycle_manager.clone());
+        assert_eq!(lifecycle_manager.server_status().await.active_sessions, 1);
+        drop(session);
+        assert_eq!(lifecycle_manager.server_status().await.active_sessions, 0);
+        Ok(())
     }
 
     #[tokio::test]
@@ -0,0 +1,172 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+use anyhow::{Context, Result};
+use base64::Engine as _;
+use rmcp::model::{CallToolRequestParam, CallToolResult, Content};
+use serde_json::{json, Value};
+use tracing::{info, instrument};
+use wassette::{CorrelationIds, LifecycleManager, MAX_UPLOAD_SIZE, UPLOAD_REFERENCE_KEY};
+
+use crate::components::{extract_args_from_request, handle_component_call};
+use crate::i18n::missing_argument;
+use crate::logging::McpLogger;
+
+#[instrument(skip(lifecycle_manager))]
+pub(crate) async fn handle_begin_upload(
+    lifecycle_manager: &LifecycleManager,
+    session_id: Option<&str>,
+) -> Result<CallToolResult> {
+    let upload_id = lifecycle_manager.begin_upload(session_id).await?;
+
+    info!(upload_id = %upload_id, "Started chunked upload");
+
+    let status_text = serde_json::to_string(&json!({
+        "status": "upload started",
+        "upload_id": upload_id,
+        "max_size": MAX_UPLOAD_SIZE,
+        "reference": { UPLOAD_REFERENCE_KEY: upload_id }
+    }))?;
+
+    Ok(CallToolResult {
+        content: vec![Content::text(status_text)],
+        is_error: None,
+    })
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn handle_append_chunk(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    session_id: Option<&str>,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let upload_id = args
+        .get("upload_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| missing_argument("upload_id"))?;
+
+    let data = args
+        .get("data")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| missing_argument("data"))?;
+
+    let chunk = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .context("Chunk data must be base64 encoded")?;
+
+    let size = lifecycle_manager
+        .append_upload_chunk(session_id, upload_id, &chunk)
+        .await?;
+
+    let status_text = serde_json::to_string(&json!({
+        "status": "chunk appended",
+        "upload_id": upload_id,
+        "size": size
+    }))?;
+
+    Ok(CallToolResult {
+        content: vec![Content::text(status_text)],
+        is_error: None,
+    })
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn handle_call_with_upload(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    logger: &McpLogger,
+    correlation: CorrelationIds,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let tool = args
+        .get("tool")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| missing_argument("tool"))?;
+
+    let arguments = args.get("arguments").cloned().unwrap_or_else(|| json!({}));
+
+    info!(tool, "Calling tool with staged uploads");
+
+    let arguments = match lifecycle_manager
+        .resolve_upload_references(correlation.session_id.as_deref(), arguments)
+        .await?
+    {
+        Value::Object(map) => map,
+        _ => anyhow::bail!("Argument 'arguments' must be an object"),
+    };
+
+    let inner_req = CallToolRequestParam {
+        name: tool.to_string().into(),
+        arguments: Some(arguments),
+    };
+
+    handle_component_call(&inner_req, lifecycle_manager, logger, correlation).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION: Option<&str> = Some("session-1");
+
+    fn text_of(result: &CallToolResult) -> Value {
+        let text = result.content[0].as_text().unwrap().text.clone();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_chunked_upload_flow() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = LifecycleManager::new(&tempdir).await?;
+
+        let started = text_of(&handle_begin_upload(&lifecycle_manager, SESSION).await?);
+        let upload_id = started["upload_id"].as_str().unwrap().to_string();
+
+        for chunk in ["aGVsbG8g", "d29ybGQ="] {
+            let mut args = serde_json::Map::new();
+            args.insert("upload_id".to_string(), json!(upload_id));
+            args.insert("data".to_string(), json!(chunk));
+            let req = CallToolRequestParam {
+                name: "append-chunk".into(),
+                arguments: Some(args),
+            };
+            handle_append_chunk(&req, &lifecycle_manager, SESSION).await?;
+        }
+
+        // Another session can't use the upload
+        let reference = json!({ "data": { "$upload": upload_id } });
+        assert!(lifecycle_manager
+            .resolve_upload_references(Some("other-session"), reference.clone())
+            .await
+            .is_err());
+
+        let resolved = lifecycle_manager
+            .resolve_upload_references(SESSION, reference)
+            .await?;
+        assert_eq!(resolved, json!({ "data": b"hello world".to_vec() }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_append_chunk_rejects_invalid_base64() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = LifecycleManager::new(&tempdir).await?;
+        let upload_id = lifecycle_manager.begin_upload(SESSION).await?;
+
+        let mut args = serde_json::Map::new();
+        args.insert("upload_id".to_string(), json!(upload_id));
+        args.insert("data".to_string(), json!("not base64!"));
+        let req = CallToolRequestParam {
+            name: "append-chunk".into(),
+            arguments: Some(args),
+        };
+
+        let result = handle_append_chunk(&req, &lifecycle_manager, SESSION).await;
+        assert!(result.unwrap_err().to_string().contains("base64"));
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// A single constraint of a tool's input schema that the call arguments violate
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Violation {
+    /// JSON pointer to the offending value within the arguments
+    pub path: String,
+    /// JSON pointer to the violated constraint within the input schema
+    pub schema_path: String,
+    /// Human readable description of the violation
+    pub message: String,
+}
+
+/// Validates tool call arguments against the tool's input schema and returns every violated
+/// constraint. An error is only returned if the schema itself cannot be compiled.
+pub(crate) fn validate_arguments(
+    input_schema: &Value,
+    arguments: &Value,
+) -> Result<Vec<Violation>> {
+    let validator = jsonschema::validator_for(input_schema)
+        .map_err(|e| anyhow!("Invalid input schema: {e}"))?;
+
+    Ok(validator
+        .iter_errors(arguments)
+        .map(|error| Violation {
+            path: error.instance_path.to_string(),
+            schema_path: error.schema_path.to_string(),
+            message: error.to_string(),
+        })
+        .collect())
+}
+
+/// Builds the report returned to the client when call arguments fail validation
+pub(crate) fn validation_report(tool_name: &str, violations: &[Violation]) -> Value {
+    json!({
+        "status": "error",
+        "error": format!(
+            "Invalid arguments for tool '{tool_name}': {} constraint(s) violated",
+            violations.len()
+        ),
+        "violations": violations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "count": { "type": "number" },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["name", "count"]
+        })
+    }
+
+    #[test]
+    fn test_valid_arguments() -> Result<()> {
+        let violations = validate_arguments(&schema(), &json!({ "name": "a", "count": 1 }))?;
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reports_every_violation() -> Result<()> {
+        let violations =
+            validate_arguments(&schema(), &json!({ "count": "one", "tags": ["ok", 2] }))?;
+        assert_eq!(violations.len(), 3);
+
+        let paths: Vec<_> = violations.iter().map(|v| v.path.as_str()).collect();
+        assert!(paths.contains(&""));
+        assert!(paths.contains(&"/count"));
+        assert!(paths.contains(&"/tags/1"));
+
+        let report = validation_report("my-tool", &violations);
+        assert_eq!(report["status"], "error");
+        assert_eq!(report["violations"].as_array().unwrap().len(), 3);
+        assert!(report["violations"][0]["schema_path"].is_string());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let result = validate_arguments(&json!({ "type": 12 }), &json!({}));
+        assert!(result.is_err());
+    }
+}
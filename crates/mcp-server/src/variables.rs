@@ -0,0 +1,350 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Variables of an MCP session, which let an agent pass the result of one tool call to another
+//! without copying it through its context.
+//!
+//! A call saves its result in a variable with the `$save_as` argument, or the `set-variable`
+//! tool sets one. Later calls use it by writing `${name}` in their arguments: a string that is
+//! only a reference is replaced by the variable's value, whatever its type, and a reference
+//! within a longer string by the value's text. References to variables that aren't set are left
+//! as they are, so arguments that happen to contain `${`, such as shell scripts, pass through
+//! unchanged. `$${` stands for a literal `${`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use rmcp::model::{CallToolRequestParam, CallToolResult, Content};
+use serde_json::{json, Map, Value};
+use tracing::{info, instrument};
+
+use crate::components::extract_args_from_request;
+use crate::i18n::missing_argument;
+
+/// The argument that saves the result of a call in a session variable instead of returning it
+pub const SAVE_AS_ARGUMENT_KEY: &str = "$save_as";
+
+/// Most variables a session may hold
+const MAX_VARIABLES: usize = 64;
+
+/// Most bytes the values of a session's variables may take together, as serialized JSON
+const MAX_TOTAL_SIZE: usize = 16 * 1024 * 1024;
+
+/// Longest variable name
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The variables of a single MCP session. Clones share the same variables.
+#[derive(Debug, Clone, Default)]
+pub struct SessionVariables {
+    values: Arc<Mutex<HashMap<String, StoredValue>>>,
+}
+
+#[derive(Debug)]
+struct StoredValue {
+    value: Value,
+    size: usize,
+}
+
+impl SessionVariables {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, StoredValue>> {
+        self.values.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sets a variable, returning the size of its value
+    pub fn set(&self, name: &str, value: Value) -> Result<usize> {
+        validate_name(name)?;
+        let size = serde_json::to_string(&value)?.len();
+        let mut values = self.lock();
+        if !values.contains_key(name) && values.len() >= MAX_VARIABLES {
+            bail!("A session may hold at most {MAX_VARIABLES} variables");
+        }
+        let others: usize = values
+            .iter()
+            .filter(|(key, _)| key.as_str() != name)
+            .map(|(_, stored)| stored.size)
+            .sum();
+        if others + size > MAX_TOTAL_SIZE {
+            bail!("The variables of a session may take at most {MAX_TOTAL_SIZE} bytes");
+        }
+        values.insert(name.to_string(), StoredValue { value, size });
+        Ok(size)
+    }
+
+    /// Returns the value of a variable
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.lock().get(name).map(|stored| stored.value.clone())
+    }
+
+    /// Prepares a tool call for execution, replacing the variable references in its arguments
+    /// and removing the name of the variable its result is saved in, if there is one
+    pub(crate) fn prepare_call(&self, req: &mut CallToolRequestParam) -> Result<Option<String>> {
+        let Some(arguments) = req.arguments.take() else {
+            return Ok(None);
+        };
+        let mut arguments = self.substitute(arguments);
+        let save_as = match arguments.remove(SAVE_AS_ARGUMENT_KEY) {
+            Some(Value::String(name)) => {
+                validate_name(&name)?;
+                Some(name)
+            }
+            Some(_) => bail!("Argument '{SAVE_AS_ARGUMENT_KEY}' must be a variable name"),
+            None => None,
+        };
+        req.arguments = Some(arguments);
+        Ok(save_as)
+    }
+
+    /// Replaces the references to variables that are set in the arguments of a tool call
+    pub fn substitute(&self, arguments: Map<String, Value>) -> Map<String, Value> {
+        let values = self.lock();
+        arguments
+            .into_iter()
+            .map(|(key, value)| (key, substitute_value(value, &values)))
+            .collect()
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        bail!(
+            "Invalid variable name '{name}': names are up to {MAX_NAME_LENGTH} letters, digits, \
+             '_' or '-' and don't start with a digit"
+        );
+    }
+    Ok(())
+}
+
+fn substitute_value(value: Value, values: &HashMap<String, StoredValue>) -> Value {
+    match value {
+        Value::String(text) => substitute_string(&text, values),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| substitute_value(item, values))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, substitute_value(value, values)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+fn lookup<'a>(name: &str, values: &'a HashMap<String, StoredValue>) -> Option<&'a Value> {
+    values.get(name).map(|stored| &stored.value)
+}
+
+fn substitute_string(text: &str, values: &HashMap<String, StoredValue>) -> Value {
+    // A string that is nothing but a reference takes the value with its type
+    if let Some(value) = text
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .filter(|name| !name.contains('}'))
+        .and_then(|name| lookup(name, values))
+    {
+        return value.clone();
+    }
+    if !text.contains("${") {
+        return Value::String(text.to_string());
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            result.push_str(&rest[..start - 1]);
+            result.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let name = &rest[start + 2..start + end];
+        match lookup(name, values) {
+            Some(Value::String(value)) => result.push_str(value),
+            Some(value) => result.push_str(&value.to_string()),
+            None => result.push_str(&rest[start..start + end + 1]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Value::String(result)
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn handle_set_variable(
+    req: &CallToolRequestParam,
+    variables: &SessionVariables,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let name = args
+        .get("name")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| missing_argument("name"))?;
+    let value = args
+        .get("value")
+        .cloned()
+        .ok_or_else(|| missing_argument("value"))?;
+
+    let size = variables.set(name, value)?;
+    info!(name, size, "Set session variable");
+
+    saved_result(name, size)
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn handle_get_variable(
+    req: &CallToolRequestParam,
+    variables: &SessionVariables,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let name = args
+        .get("name")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| missing_argument("name"))?;
+    let value = variables
+        .get(name)
+        .with_context(|| format!("Unknown variable '{name}'"))?;
+    let value = match args.get("pointer").and_then(|v| v.as_str()) {
+        Some(pointer) => value
+            .pointer(pointer)
+            .cloned()
+            .with_context(|| format!("Variable '{name}' has nothing at '{pointer}'"))?,
+        None => value,
+    };
+
+    let text = match value {
+        Value::String(text) => text,
+        value => serde_json::to_string(&value)?,
+    };
+    Ok(CallToolResult {
+        content: vec![Content::text(text)],
+        is_error: None,
+    })
+}
+
+/// Saves the result of a successful call in a variable and replaces it with a short summary
+pub(crate) fn save_result(
+    name: &str,
+    result: CallToolResult,
+    variables: &SessionVariables,
+) -> Result<CallToolResult> {
+    if result.is_error == Some(true) {
+        return Ok(result);
+    }
+    let text = result
+        .content
+        .first()
+        .and_then(|content| content.as_text())
+        .map(|text| text.text.clone())
+        .unwrap_or_default();
+    let value = serde_json::from_str(&text).unwrap_or(Value::String(text));
+    let size = variables.set(name, value)?;
+    info!(name, size, "Saved call result in session variable");
+    saved_result(name, size)
+}
+
+fn saved_result(name: &str, size: usize) -> Result<CallToolResult> {
+    let status_text = serde_json::to_string(&json!({
+        "status": "variable set",
+        "name": name,
+        "size": size,
+        "reference": format!("${{{name}}}")
+    }))?;
+    Ok(CallToolResult {
+        content: vec![Content::text(status_text)],
+        is_error: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute() -> Result<()> {
+        let variables = SessionVariables::default();
+        variables.set("page", json!({"title": "Wasm", "links": 3}))?;
+        variables.set("url", json!("https://example.com"))?;
+        variables.set("count", json!(3))?;
+
+        let arguments = json!({
+            "document": "${page}",
+            "message": "Fetched ${url} ${count} times",
+            "list": ["${url}"],
+            "literal": "costs $${url}",
+            "count": 1
+        });
+        let Value::Object(arguments) = arguments else {
+            unreachable!()
+        };
+        let substituted = variables.substitute(arguments);
+        assert_eq!(
+            Value::Object(substituted),
+            json!({
+                "document": {"title": "Wasm", "links": 3},
+                "message": "Fetched https://example.com 3 times",
+                "list": ["https://example.com"],
+                "literal": "costs ${url}",
+                "count": 1
+            })
+        );
+
+        // References to variables that aren't set, and unterminated ones, stay as they are
+        let Value::Object(unknown) = json!({
+            "a": "${missing}",
+            "script": "echo ${HOME} ${url}",
+            "open": "${url} ${"
+        }) else {
+            unreachable!()
+        };
+        assert_eq!(
+            Value::Object(variables.substitute(unknown)),
+            json!({
+                "a": "${missing}",
+                "script": "echo ${HOME} https://example.com",
+                "open": "https://example.com ${"
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_variable_limits() {
+        let variables = SessionVariables::default();
+        assert!(variables.set("1st", json!(1)).is_err());
+        assert!(variables.set("a.b", json!(1)).is_err());
+        assert!(variables.set("ok_name-2", json!(1)).is_ok());
+        let big = "x".repeat(MAX_TOTAL_SIZE);
+        assert!(variables.set("big", json!(big)).is_err());
+        assert_eq!(variables.get("ok_name-2"), Some(json!(1)));
+        assert_eq!(variables.get("big"), None);
+    }
+
+    #[test]
+    fn test_save_result() -> Result<()> {
+        let variables = SessionVariables::default();
+        let result = CallToolResult {
+            content: vec![Content::text(r#"{"items": [1, 2]}"#)],
+            is_error: None,
+        };
+        let summary = save_result("items", result, &variables)?;
+        let summary: Value = serde_json::from_str(&summary.content[0].as_text().unwrap().text)?;
+        assert_eq!(summary["reference"], "${items}");
+        assert_eq!(variables.get("items"), Some(json!({"items": [1, 2]})));
+        Ok(())
+    }
+}
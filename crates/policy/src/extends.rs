@@ -0,0 +1,438 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Composition of policy documents with `extends`, which merges base documents into the document
+//! that lists them.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    AiPermissions, Approval, BlobstorePermissions, Budget, EnvironmentPermissions,
+    EventPermissions, Locale, PermissionList, Permissions, PolicyDocument, PolicyParser,
+    PolicyResult, ProcessPermissions, ResourceLimits, Schedule,
+};
+
+/// Most levels of bases a document can have
+pub const MAX_EXTENDS_DEPTH: usize = 8;
+
+impl PolicyDocument {
+    /// Merges the bases listed in `extends` into the document, reading them relative to `dir`.
+    /// Returns the document as it is if it extends nothing.
+    pub fn resolve_extends(self, dir: &Path) -> PolicyResult<PolicyDocument> {
+        self.resolve_extends_with_bases(dir)
+            .map(|(document, _)| document)
+    }
+
+    /// Same as [`PolicyDocument::resolve_extends`], but also returns the paths of the bases that
+    /// were merged in, nested ones included, in the order they were read
+    pub fn resolve_extends_with_bases(
+        self,
+        dir: &Path,
+    ) -> PolicyResult<(PolicyDocument, Vec<PathBuf>)> {
+        let mut bases = Vec::new();
+        let document = resolve(self, dir, &mut Vec::new(), &mut bases)?;
+        Ok((document, bases))
+    }
+
+    /// Makes the relative paths in `extends` absolute, relative to `dir`, so that the document
+    /// can be stored elsewhere
+    pub fn absolutize_extends(&mut self, dir: &Path) {
+        for base in &mut self.extends {
+            if Path::new(base).is_relative() {
+                *base = dir.join(&*base).to_string_lossy().into_owned();
+            }
+        }
+    }
+}
+
+fn resolve(
+    document: PolicyDocument,
+    dir: &Path,
+    chain: &mut Vec<PathBuf>,
+    bases: &mut Vec<PathBuf>,
+) -> PolicyResult<PolicyDocument> {
+    if document.extends.is_empty() {
+        return Ok(document);
+    }
+    if chain.len() >= MAX_EXTENDS_DEPTH {
+        bail!("Policy extends more than {MAX_EXTENDS_DEPTH} levels of bases");
+    }
+
+    let mut merged = Permissions::default();
+    let mut settings = Settings::default();
+    for base in &document.extends {
+        let path = dir.join(base);
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to read base policy {}", path.display()))?;
+        if chain.contains(&path) {
+            bail!("Base policy {} extends itself", path.display());
+        }
+        let parsed = PolicyParser::parse_file(&path)
+            .with_context(|| format!("Invalid base policy {}", path.display()))?;
+        bases.push(path.clone());
+        chain.push(path.clone());
+        let parsed = resolve(parsed, path.parent().unwrap_or(dir), chain, bases)?;
+        chain.pop();
+        settings.source = path.display().to_string();
+        merge(&mut merged, &parsed.permissions, &mut settings, false);
+    }
+    merge(&mut merged, &document.permissions, &mut settings, true);
+    if let Some((setting, first, second)) = settings.conflicts.first() {
+        bail!(
+            "Base policies {first} and {second} set {setting} to different values, set it in the \
+             extending policy to resolve the conflict"
+        );
+    }
+
+    let merged = PolicyDocument {
+        version: document.version,
+        description: document.description,
+        extends: Vec::new(),
+        permissions: merged,
+    };
+    merged
+        .validate()
+        .context("Policy is invalid once its bases are merged")?;
+    Ok(merged)
+}
+
+/// Where the settings merged so far came from, and the conflicts found between bases
+#[derive(Default)]
+struct Settings {
+    /// The base being merged
+    source: String,
+    /// The base each setting was taken from
+    sources: Vec<(String, String)>,
+    /// Settings that two bases set differently, with the two bases
+    conflicts: Vec<(String, String, String)>,
+}
+
+impl Settings {
+    fn set<T: PartialEq + Clone>(
+        &mut self,
+        name: &str,
+        target: &mut Option<T>,
+        value: &Option<T>,
+        overriding: bool,
+    ) {
+        let Some(value) = value else {
+            return;
+        };
+        if overriding {
+            self.conflicts.retain(|(setting, _, _)| setting != name);
+        } else if target.as_ref().is_some_and(|current| current != value) {
+            let first = self
+                .sources
+                .iter()
+                .find(|(setting, _)| setting == name)
+                .map(|(_, source)| source.clone())
+                .unwrap_or_default();
+            self.conflicts
+                .push((name.to_string(), first, self.source.clone()));
+            return;
+        } else {
+            self.sources.push((name.to_string(), self.source.clone()));
+        }
+        *target = Some(value.clone());
+    }
+}
+
+/// Appends the entries of `items` that `target` doesn't have yet
+fn combine<T: PartialEq + Clone>(target: &mut Option<Vec<T>>, items: &Option<Vec<T>>) {
+    let Some(items) = items else {
+        return;
+    };
+    let target = target.get_or_insert_with(Vec::new);
+    for item in items {
+        if !target.contains(item) {
+            target.push(item.clone());
+        }
+    }
+}
+
+fn combine_lists<T: PartialEq + Clone>(
+    target: &mut Option<PermissionList<T>>,
+    list: &Option<PermissionList<T>>,
+) {
+    if let Some(list) = list {
+        let target = target.get_or_insert_with(PermissionList::default);
+        combine(&mut target.allow, &list.allow);
+        combine(&mut target.deny, &list.deny);
+    }
+}
+
+fn merge(
+    target: &mut Permissions,
+    source: &Permissions,
+    settings: &mut Settings,
+    overriding: bool,
+) {
+    combine_lists(&mut target.storage, &source.storage);
+    combine_lists(&mut target.network, &source.network);
+    combine_lists(&mut target.ipc, &source.ipc);
+    if let Some(environment) = &source.environment {
+        let target = target
+            .environment
+            .get_or_insert_with(EnvironmentPermissions::default);
+        combine(&mut target.allow, &environment.allow);
+    }
+    if let Some(ai) = &source.ai {
+        combine(
+            &mut target.ai.get_or_insert_with(AiPermissions::default).allow,
+            &ai.allow,
+        );
+    }
+    if let Some(blobstore) = &source.blobstore {
+        let target = target
+            .blobstore
+            .get_or_insert_with(BlobstorePermissions::default);
+        combine(&mut target.allow, &blobstore.allow);
+    }
+    if let Some(events) = &source.events {
+        let target = target.events.get_or_insert_with(EventPermissions::default);
+        combine(&mut target.allow, &events.allow);
+    }
+    if let Some(schedule) = &source.schedule {
+        let target = target.schedule.get_or_insert_with(Schedule::default);
+        let mut windows = Some(std::mem::take(&mut target.windows));
+        combine(&mut windows, &Some(schedule.windows.clone()));
+        target.windows = windows.unwrap_or_default();
+    }
+
+    if let Some(approval) = &source.approval {
+        let target = target.approval.get_or_insert_with(Approval::default);
+        target.destructive |= approval.destructive;
+    }
+    if let Some(process) = &source.process {
+        let target = target
+            .process
+            .get_or_insert_with(ProcessPermissions::default);
+        target.args |= process.args;
+        target.stdin |= process.stdin;
+        target.wall_clock |= process.wall_clock;
+    }
+
+    settings.set("runtime", &mut target.runtime, &source.runtime, overriding);
+    if let Some(resources) = &source.resources {
+        let target = target.resources.get_or_insert_with(ResourceLimits::default);
+        settings.set("resources.cpu", &mut target.cpu, &resources.cpu, overriding);
+        settings.set(
+            "resources.memory",
+            &mut target.memory,
+            &resources.memory,
+            overriding,
+        );
+        settings.set("resources.io", &mut target.io, &resources.io, overriding);
+    }
+    if let Some(budget) = &source.budget {
+        let target = target.budget.get_or_insert_with(Budget::default);
+        for (name, target, value) in [
+            (
+                "budget.max_calls_per_hour",
+                &mut target.max_calls_per_hour,
+                budget.max_calls_per_hour,
+            ),
+            (
+                "budget.max_calls_per_day",
+                &mut target.max_calls_per_day,
+                budget.max_calls_per_day,
+            ),
+            (
+                "budget.max_wall_clock_seconds_per_day",
+                &mut target.max_wall_clock_seconds_per_day,
+                budget.max_wall_clock_seconds_per_day,
+            ),
+            (
+                "budget.max_sleep_seconds_per_call",
+                &mut target.max_sleep_seconds_per_call,
+                budget.max_sleep_seconds_per_call,
+            ),
+        ] {
+            settings.set(name, target, &value, overriding);
+        }
+    }
+    if let Some(locale) = &source.locale {
+        let target = target.locale.get_or_insert_with(Locale::default);
+        settings.set(
+            "locale.timezone",
+            &mut target.timezone,
+            &locale.timezone,
+            overriding,
+        );
+        settings.set("locale.lang", &mut target.lang, &locale.lang, overriding);
+        for (key, value) in &locale.variables {
+            let mut current = target.variables.get(key).cloned();
+            settings.set(
+                &format!("locale.variables.{key}"),
+                &mut current,
+                &Some(value.clone()),
+                overriding,
+            );
+            if let Some(current) = current {
+                target.variables.insert(key.clone(), current);
+            }
+        }
+    }
+    if let Some(config) = &source.config {
+        let target = target.config.get_or_insert_with(Default::default);
+        for (key, value) in config {
+            let mut current = target.get(key).cloned();
+            settings.set(
+                &format!("config.{key}"),
+                &mut current,
+                &Some(value.clone()),
+                overriding,
+            );
+            if let Some(current) = current {
+                target.insert(key.clone(), current);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetworkPermission;
+
+    fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_bases_are_merged_in_order() -> PolicyResult<()> {
+        let dir = tempfile::tempdir()?;
+        write(
+            dir.path(),
+            "org.yaml",
+            r#"
+version: "1.6"
+permissions:
+  network:
+    allow:
+      - host: "api.github.com"
+  budget:
+    max_calls_per_day: 1000
+  approval:
+    destructive: true
+"#,
+        );
+        std::fs::create_dir(dir.path().join("teams"))?;
+        write(
+            &dir.path().join("teams"),
+            "search.yaml",
+            r#"
+version: "1.6"
+extends: ["../org.yaml"]
+permissions:
+  network:
+    allow:
+      - host: "api.github.com"
+      - host: "search.example.com"
+  config:
+    region: eu
+"#,
+        );
+        let component = write(
+            dir.path(),
+            "component.yaml",
+            r#"
+version: "1.6"
+description: "Search tool"
+extends: ["teams/search.yaml"]
+permissions:
+  budget:
+    max_calls_per_day: 50
+"#,
+        );
+
+        let (policy, bases) =
+            PolicyParser::parse_file(&component)?.resolve_extends_with_bases(dir.path())?;
+        assert_eq!(
+            bases,
+            [
+                dir.path().join("teams/search.yaml").canonicalize()?,
+                dir.path().join("org.yaml").canonicalize()?,
+            ]
+        );
+        assert!(policy.extends.is_empty());
+        assert_eq!(policy.description.as_deref(), Some("Search tool"));
+        let hosts: Vec<_> = policy
+            .permissions
+            .network
+            .unwrap()
+            .allow
+            .unwrap()
+            .into_iter()
+            .map(|permission| match permission {
+                NetworkPermission::Host(host) => host.host,
+                NetworkPermission::Cidr(cidr) => cidr.cidr,
+            })
+            .collect();
+        assert_eq!(hosts, ["api.github.com", "search.example.com"]);
+        assert_eq!(
+            policy.permissions.budget.unwrap().max_calls_per_day,
+            Some(50)
+        );
+        assert!(policy.permissions.approval.unwrap().destructive);
+        assert_eq!(policy.permissions.config.unwrap()["region"], "eu");
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflicting_bases() -> PolicyResult<()> {
+        let dir = tempfile::tempdir()?;
+        for (name, limit) in [("a.yaml", 10), ("b.yaml", 20)] {
+            write(
+                dir.path(),
+                name,
+                &format!(
+                    "version: \"1.6\"\npermissions:\n  budget:\n    max_calls_per_hour: {limit}\n"
+                ),
+            );
+        }
+        let conflicting = PolicyParser::parse_str(
+            "version: \"1.6\"\nextends: [a.yaml, b.yaml]\npermissions: {}",
+        )?;
+        let error = conflicting.resolve_extends(dir.path()).unwrap_err();
+        assert!(
+            error.to_string().contains("budget.max_calls_per_hour"),
+            "{error:#}"
+        );
+
+        let resolved = PolicyParser::parse_str(
+            "version: \"1.6\"\nextends: [a.yaml, b.yaml]\npermissions:\n  budget:\n    max_calls_per_hour: 5\n",
+        )?
+        .resolve_extends(dir.path())?;
+        assert_eq!(
+            resolved.permissions.budget.unwrap().max_calls_per_hour,
+            Some(5)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cyclic_bases() -> PolicyResult<()> {
+        let dir = tempfile::tempdir()?;
+        write(
+            dir.path(),
+            "a.yaml",
+            "version: \"1.6\"\nextends: [b.yaml]\npermissions: {}",
+        );
+        write(
+            dir.path(),
+            "b.yaml",
+            "version: \"1.6\"\nextends: [a.yaml]\npermissions: {}",
+        );
+        let policy =
+            PolicyParser::parse_str("version: \"1.6\"\nextends: [a.yaml]\npermissions: {}")?;
+        let error = policy.resolve_extends(dir.path()).unwrap_err();
+        assert!(error.to_string().contains("extends itself"), "{error:#}");
+        Ok(())
+    }
+}
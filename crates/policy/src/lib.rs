@@ -9,11 +9,15 @@
 use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
 
+pub mod extends;
 pub mod parser;
 pub mod types;
+pub mod version;
 
+pub use extends::MAX_EXTENDS_DEPTH;
 pub use parser::PolicyParser;
 pub use types::*;
+pub use version::{PolicyWarning, CURRENT_VERSION};
 
 /// Policy document structure
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
@@ -24,6 +28,10 @@ pub struct PolicyDocument {
     /// Human-readable description of the policy
     pub description: Option<String>,
 
+    /// Base policies merged into this one, see [`extends`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
+
     /// Permission definitions
     pub permissions: Permissions,
 }
@@ -31,7 +39,8 @@ pub struct PolicyDocument {
 impl PolicyDocument {
     /// Validate the policy document
     pub fn validate(&self) -> Result<()> {
-        // Only supporting v1.x for now - will add v2 when we know what it looks like
+        // Only supporting v1.x for now - will add v2 when we know what it looks like. Minor
+        // versions are handled in `version`
         if !self.version.starts_with("1.") {
             bail!("Unsupported version: {}", self.version);
         }
@@ -62,6 +71,7 @@ mod tests {
         let policy = PolicyDocument {
             version: "1.0".to_string(),
             description: Some("Test policy".to_string()),
+            extends: Vec::new(),
             permissions: Permissions::default(),
         };
 
@@ -85,6 +95,7 @@ mod tests {
         let policy = PolicyDocument {
             version: "2.0".to_string(),
             description: None,
+            extends: Vec::new(),
             permissions: Permissions::default(),
         };
 
@@ -77,6 +77,7 @@ impl PolicyParser {
     /// let policy = PolicyDocument {
     ///     version: "1.0".to_string(),
     ///     description: Some("Test policy".to_string()),
+    ///     extends: Vec::new(),
     ///     permissions: Permissions::default(),
     /// };
     ///
@@ -141,6 +142,95 @@ permissions:
         );
     }
 
+    #[test]
+    fn test_parse_str_budget() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  budget:
+    max_calls_per_hour: 20
+    max_wall_clock_seconds_per_day: 600
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let budget = policy.permissions.budget.unwrap();
+        assert_eq!(budget.max_calls_per_hour, Some(20));
+        assert_eq!(budget.max_calls_per_day, None);
+        assert_eq!(budget.max_wall_clock_seconds_per_day, Some(600));
+    }
+
+    #[test]
+    fn test_parse_str_approval() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  approval:
+    destructive: true
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        assert!(policy.permissions.approval.unwrap().destructive);
+    }
+
+    #[test]
+    fn test_parse_str_process() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  process:
+    wall_clock: true
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let process = policy.permissions.process.unwrap();
+        assert!(process.wall_clock);
+        assert!(!process.args);
+        assert!(!process.stdin);
+    }
+
+    #[test]
+    fn test_parse_str_locale() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  locale:
+    timezone: UTC
+    lang: C.UTF-8
+    variables:
+      LC_NUMERIC: de_DE.UTF-8
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let locale = policy.permissions.locale.unwrap();
+        assert_eq!(locale.timezone.as_deref(), Some("UTC"));
+        assert_eq!(locale.lang.as_deref(), Some("C.UTF-8"));
+        assert_eq!(locale.variables["LC_NUMERIC"], "de_DE.UTF-8");
+    }
+
+    #[test]
+    fn test_parse_str_ai() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  ai:
+    allow:
+      - model: mobilenet
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let ai = policy.permissions.ai.unwrap();
+        assert_eq!(ai.allow.unwrap()[0].model, "mobilenet");
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  ai:
+    allow:
+      - model: ""
+"#;
+        assert!(PolicyParser::parse_str(yaml_content).is_err());
+    }
+
     #[test]
     fn test_parse_str_invalid_version() {
         let yaml_content = r#"
@@ -181,6 +271,7 @@ invalid: yaml: content
         let original = PolicyDocument {
             version: "1.0".to_string(),
             description: Some("Test policy".to_string()),
+            extends: Vec::new(),
             permissions,
         };
 
@@ -223,6 +314,7 @@ permissions:
         let policy = PolicyDocument {
             version: "1.0".to_string(),
             description: Some("Write test policy".to_string()),
+            extends: Vec::new(),
             permissions,
         };
 
@@ -3,7 +3,7 @@
 
 //! Type definitions
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
 
 use anyhow::bail;
@@ -120,13 +120,90 @@ pub struct HyperlightRuntime {
 }
 
 /// Resource limits configuration (future/TODO)
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct ResourceLimits {
     pub cpu: Option<f64>,
+    /// Largest combined size of the linear memories of a tool call, in MiB
     pub memory: Option<u64>,
     pub io: Option<u64>,
 }
 
+/// Usage budget for a component's tools, e.g. to protect rate-limited downstream APIs
+///
+/// Hourly limits reset at the start of every hour and daily limits at midnight UTC.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Budget {
+    /// Maximum number of tool calls per hour
+    pub max_calls_per_hour: Option<u64>,
+    /// Maximum number of tool calls per day
+    pub max_calls_per_day: Option<u64>,
+    /// Maximum combined wall-clock time of all tool calls per day, in seconds
+    pub max_wall_clock_seconds_per_day: Option<u64>,
+    /// Maximum combined duration of the timers a single tool call may wait on, in seconds
+    pub max_sleep_seconds_per_call: Option<u64>,
+}
+
+/// Human confirmation required before a component's tools run, so that tools which can change
+/// data don't run unattended
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Approval {
+    /// Require approval of every call to a tool annotated as destructive
+    pub destructive: bool,
+}
+
+/// Access to the state of the server process, which components don't get unless it is granted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ProcessPermissions {
+    /// Pass the server's command line arguments to the component
+    pub args: bool,
+    /// Connect the component's stdin to the server's stdin
+    pub stdin: bool,
+    /// Give the component the host's wall clock instead of one that reads as the Unix epoch
+    pub wall_clock: bool,
+}
+
+/// Locale environment variables besides `TZ` and `LANG` that a policy may set in its `locale`
+/// section
+pub const LOCALE_VARIABLES: &[&str] = &[
+    "LANGUAGE",
+    "LC_ALL",
+    "LC_COLLATE",
+    "LC_CTYPE",
+    "LC_MESSAGES",
+    "LC_MONETARY",
+    "LC_NUMERIC",
+    "LC_TIME",
+];
+
+/// Time zone and locale of the component, set as environment variables inside the sandbox rather
+/// than taken from the host, so that the component behaves the same on every machine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Locale {
+    /// Time zone set as `TZ`, e.g. `Europe/Berlin`
+    pub timezone: Option<String>,
+    /// Locale set as `LANG`, e.g. `de_DE.UTF-8`
+    pub lang: Option<String>,
+    /// Other locale variables, which must be listed in [`LOCALE_VARIABLES`]
+    pub variables: BTreeMap<String, String>,
+}
+
+impl Locale {
+    /// Returns the environment variables the locale sets
+    pub fn env_vars(&self) -> BTreeMap<String, String> {
+        let mut vars = self.variables.clone();
+        if let Some(timezone) = &self.timezone {
+            vars.insert("TZ".to_string(), timezone.clone());
+        }
+        if let Some(lang) = &self.lang {
+            vars.insert("LANG".to_string(), lang.clone());
+        }
+        vars
+    }
+}
+
 /// IPC permission configuration (future/TODO)
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IpcPermission {
@@ -158,6 +235,197 @@ impl<T> Default for PermissionList<T> {
     }
 }
 
+/// A model the server loaded for `wasi:nn` inference that a component may use
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AiModelPermission {
+    /// Name of the model in the server configuration, or `*` for every model
+    pub model: String,
+}
+
+/// Access to local model inference through `wasi:nn` (allow-only). Components without this
+/// section can't use `wasi:nn`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct AiPermissions {
+    pub allow: Option<Vec<AiModelPermission>>,
+}
+
+/// A `wasi:blobstore` container that a component may use
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlobContainerPermission {
+    /// Name of the container, or `*` for every container of the component
+    pub container: String,
+    /// Access types allowed. Creating, deleting and writing to a container needs `write`
+    pub access: Vec<AccessType>,
+}
+
+/// Access to blob containers through `wasi:blobstore` (allow-only). Containers belong to the
+/// component that created them, and components without this section can't use `wasi:blobstore`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct BlobstorePermissions {
+    pub allow: Option<Vec<BlobContainerPermission>>,
+}
+
+/// A topic that a component may publish events to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventTopicPermission {
+    /// Name of the topic, a prefix ending in `.*` such as `scan.*`, or `*` for every topic
+    pub topic: String,
+}
+
+/// Publishing events through `wassette:events` (allow-only). Components without this section
+/// can't publish events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct EventPermissions {
+    pub allow: Option<Vec<EventTopicPermission>>,
+}
+
+impl EventTopicPermission {
+    /// Returns whether the permission covers `topic`
+    pub fn allows(&self, topic: &str) -> bool {
+        match self.topic.strip_suffix('*') {
+            Some("") => true,
+            Some(prefix) => topic.starts_with(prefix) && topic.len() > prefix.len(),
+            None => self.topic == topic,
+        }
+    }
+
+    /// Returns whether the permission covers every topic `other` allows
+    pub fn covers(&self, other: &EventTopicPermission) -> bool {
+        match (self.topic.strip_suffix('*'), other.topic.strip_suffix('*')) {
+            (Some(""), _) => true,
+            (Some(prefix), Some(other_prefix)) => other_prefix.starts_with(prefix),
+            (Some(_), None) => self.allows(&other.topic),
+            (None, _) => self.topic == other.topic,
+        }
+    }
+}
+
+/// A day of the week
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    /// The days of the week, starting on Monday
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
+
+    /// Returns the day before this one
+    pub fn previous(self) -> Self {
+        let index = Self::ALL.iter().position(|day| *day == self).unwrap_or(0);
+        Self::ALL[(index + 6) % 7]
+    }
+}
+
+/// A time of day during which some grants of the policy apply. Outside of all the windows that
+/// list it, a grant doesn't apply.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeWindow {
+    /// Name of the window, used in messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// IANA time zone of `start` and `end`, e.g. `Europe/Berlin`. UTC unless set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Days on which the window opens, every day unless set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days: Option<Vec<Weekday>>,
+    /// Time the window opens, as `HH:MM`
+    pub start: String,
+    /// Time the window closes, as `HH:MM`. A window that closes before it opens ends on the next
+    /// day.
+    pub end: String,
+    /// Hosts granted by `network` that only apply during the window
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    /// URIs granted by `storage` that only apply during the window
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub storage: Vec<String>,
+}
+
+/// Minutes in a day
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+impl TimeWindow {
+    /// Returns the start and end of the window, in minutes after midnight
+    pub fn bounds(&self) -> PolicyResult<(u32, u32)> {
+        let parse = |time: &str| -> PolicyResult<u32> {
+            let minutes = time.split_once(':').and_then(|(hours, minutes)| {
+                if hours.len() != 2 || minutes.len() != 2 {
+                    return None;
+                }
+                let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+                (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+            });
+            match minutes {
+                Some(minutes) => Ok(minutes),
+                None if time == "24:00" => Ok(MINUTES_PER_DAY),
+                None => bail!("Invalid time of day {time:?}, expected HH:MM"),
+            }
+        };
+        let (start, end) = (parse(&self.start)?, parse(&self.end)?);
+        if start == end || start == MINUTES_PER_DAY {
+            bail!("Time window {} is empty", self.label());
+        }
+        Ok((start, end))
+    }
+
+    /// Returns how many minutes the window stays open from `minute` after midnight on `day`,
+    /// or `None` if it is closed then
+    pub fn open_for(&self, day: Weekday, minute: u32) -> Option<u32> {
+        let (start, end) = self.bounds().ok()?;
+        let opens_on = |day: Weekday| self.days.as_ref().is_none_or(|days| days.contains(&day));
+        if start < end {
+            (opens_on(day) && start <= minute && minute < end).then(|| end - minute)
+        } else if minute >= start && opens_on(day) {
+            Some(MINUTES_PER_DAY - minute + end)
+        } else if minute < end && opens_on(day.previous()) {
+            Some(end - minute)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name of the window, or its times if it has none
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!(
+                "{}-{} {}",
+                self.start,
+                self.end,
+                self.timezone.as_deref().unwrap_or("UTC")
+            ),
+        }
+    }
+}
+
+/// Time windows that restrict when grants apply, e.g. to allow a production API only during
+/// business hours
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Schedule {
+    pub windows: Vec<TimeWindow>,
+}
+
+/// Prefix of the `wasi:config` variables that wassette sets itself, which a policy's `config`
+/// section can't set
+pub const RESERVED_CONFIG_PREFIX: &str = "WASSETTE_";
+
 /// Environment permissions (allow-only for security)
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct EnvironmentPermissions {
@@ -173,6 +441,16 @@ pub struct Permissions {
     pub runtime: Option<Runtime>,
     pub resources: Option<ResourceLimits>,
     pub ipc: Option<PermissionList<IpcPermission>>,
+    pub budget: Option<Budget>,
+    pub ai: Option<AiPermissions>,
+    pub approval: Option<Approval>,
+    pub process: Option<ProcessPermissions>,
+    pub locale: Option<Locale>,
+    pub blobstore: Option<BlobstorePermissions>,
+    pub events: Option<EventPermissions>,
+    pub schedule: Option<Schedule>,
+    /// Settings of the component, such as feature flags, passed to it as `wasi:config` variables
+    pub config: Option<BTreeMap<String, String>>,
 }
 
 impl Permissions {
@@ -239,6 +517,125 @@ impl Permissions {
         Ok(())
     }
 
+    fn validate_event_topic(topic: &str) -> PolicyResult<()> {
+        let name = match topic.strip_suffix(".*") {
+            _ if topic == "*" => return Ok(()),
+            Some(prefix) => prefix,
+            None => topic,
+        };
+        if name.is_empty()
+            || name.split('.').any(str::is_empty)
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        {
+            bail!("Invalid event topic: {topic:?}");
+        }
+        Ok(())
+    }
+
+    fn validate_schedule(&self, schedule: &Schedule) -> PolicyResult<()> {
+        for window in &schedule.windows {
+            window.bounds()?;
+            if let Some(timezone) = &window.timezone {
+                if timezone.is_empty() || timezone.contains(char::is_whitespace) {
+                    bail!("Invalid time zone: {timezone:?}");
+                }
+            }
+            if window.days.as_ref().is_some_and(Vec::is_empty) {
+                bail!("Time window {} has no days", window.label());
+            }
+            if window.hosts.is_empty() && window.storage.is_empty() {
+                bail!(
+                    "Time window {} names no hosts or storage URIs",
+                    window.label()
+                );
+            }
+            for host in &window.hosts {
+                let mut granted = self.network.iter().flat_map(|n| n.allow.iter().flatten());
+                if !granted
+                    .any(|perm| matches!(perm, NetworkPermission::Host(h) if h.host == *host))
+                {
+                    bail!(
+                        "Time window {} names host {host}, which network doesn't allow",
+                        window.label()
+                    );
+                }
+            }
+            for uri in &window.storage {
+                let mut granted = self.storage.iter().flat_map(|s| s.allow.iter().flatten());
+                if !granted.any(|perm| perm.uri == *uri) {
+                    bail!(
+                        "Time window {} names storage URI {uri}, which storage doesn't allow",
+                        window.label()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_config(&self, config: &BTreeMap<String, String>) -> PolicyResult<()> {
+        for key in config.keys() {
+            if key.is_empty()
+                || !key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            {
+                bail!("Invalid config key: {key:?}");
+            }
+            if key.starts_with(RESERVED_CONFIG_PREFIX) {
+                bail!("Config keys starting with {RESERVED_CONFIG_PREFIX} are reserved: {key}");
+            }
+            let mut granted = self
+                .environment
+                .iter()
+                .flat_map(|e| e.allow.iter().flatten());
+            if granted.any(|perm| perm.key == *key) {
+                bail!("{key} is both set in config and granted by environment");
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_blob_container(container: &str) -> PolicyResult<()> {
+        if container == "*" {
+            return Ok(());
+        }
+        if container.is_empty()
+            || container == "."
+            || container == ".."
+            || !container
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        {
+            bail!("Invalid blob container name: {container:?}");
+        }
+        Ok(())
+    }
+
+    fn validate_locale(locale: &Locale) -> PolicyResult<()> {
+        for key in locale.variables.keys() {
+            if !LOCALE_VARIABLES.contains(&key.as_str()) {
+                bail!(
+                    "Locale can't set {key}, only timezone, lang and {}",
+                    LOCALE_VARIABLES.join(", ")
+                );
+            }
+        }
+        let values = locale
+            .timezone
+            .iter()
+            .chain(&locale.lang)
+            .chain(locale.variables.values());
+        for value in values {
+            if value.is_empty() || value.contains(|c: char| c == '\0' || c.is_whitespace()) {
+                bail!("Invalid locale value: {value:?}");
+            }
+        }
+        Ok(())
+    }
+
     /// Validate the permissions structure
     pub fn validate(&self) -> PolicyResult<()> {
         if let Some(storage) = &self.storage {
@@ -305,6 +702,55 @@ impl Permissions {
             }
         }
 
+        if let Some(locale) = &self.locale {
+            Self::validate_locale(locale)?;
+        }
+
+        if let Some(ai) = &self.ai {
+            if let Some(allow_list) = &ai.allow {
+                for perm in allow_list {
+                    if perm.model.is_empty() {
+                        bail!("AI model name can't be empty");
+                    }
+                }
+            }
+        }
+
+        if let Some(blobstore) = &self.blobstore {
+            for perm in blobstore.allow.iter().flatten() {
+                Self::validate_blob_container(&perm.container)?;
+                if perm.access.is_empty() {
+                    bail!("Blob containers need some access permissions");
+                }
+            }
+        }
+
+        if let Some(events) = &self.events {
+            for perm in events.allow.iter().flatten() {
+                Self::validate_event_topic(&perm.topic)?;
+            }
+        }
+
+        if let Some(schedule) = &self.schedule {
+            self.validate_schedule(schedule)?;
+        }
+
+        if let Some(config) = &self.config {
+            self.validate_config(config)?;
+        }
+
+        if let Some(budget) = &self.budget {
+            let limits = [
+                budget.max_calls_per_hour,
+                budget.max_calls_per_day,
+                budget.max_wall_clock_seconds_per_day,
+                budget.max_sleep_seconds_per_call,
+            ];
+            if limits.contains(&Some(0)) {
+                bail!("Budget limits must be greater than zero");
+            }
+        }
+
         Ok(())
     }
 }
@@ -360,6 +806,129 @@ mod tests {
         assert!(permissions.validate().is_ok());
     }
 
+    #[test]
+    fn test_blob_container_validation() {
+        assert!(Permissions::validate_blob_container("artifacts").is_ok());
+        assert!(Permissions::validate_blob_container("build-2.cache").is_ok());
+        assert!(Permissions::validate_blob_container("*").is_ok());
+
+        assert!(Permissions::validate_blob_container("").is_err());
+        assert!(Permissions::validate_blob_container("..").is_err());
+        assert!(Permissions::validate_blob_container("a/b").is_err());
+        assert!(Permissions::validate_blob_container("art*").is_err());
+    }
+
+    #[test]
+    fn test_event_topic_validation() {
+        assert!(Permissions::validate_event_topic("scan.finding").is_ok());
+        assert!(Permissions::validate_event_topic("scan.*").is_ok());
+        assert!(Permissions::validate_event_topic("*").is_ok());
+
+        assert!(Permissions::validate_event_topic("").is_err());
+        assert!(Permissions::validate_event_topic(".*").is_err());
+        assert!(Permissions::validate_event_topic("scan..finding").is_err());
+        assert!(Permissions::validate_event_topic("scan*").is_err());
+        assert!(Permissions::validate_event_topic("scan finding").is_err());
+
+        let prefix = EventTopicPermission {
+            topic: "scan.*".to_string(),
+        };
+        assert!(prefix.allows("scan.finding"));
+        assert!(!prefix.allows("scan."));
+        assert!(!prefix.allows("scanner.finding"));
+        let exact = EventTopicPermission {
+            topic: "scan".to_string(),
+        };
+        assert!(exact.allows("scan") && !exact.allows("scan.finding"));
+        assert!(prefix.covers(&EventTopicPermission {
+            topic: "scan.deep.*".to_string(),
+        }));
+        assert!(!prefix.covers(&EventTopicPermission {
+            topic: "*".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_time_window() {
+        let window = TimeWindow {
+            name: None,
+            timezone: None,
+            days: Some(vec![Weekday::Mon, Weekday::Fri]),
+            start: "22:00".to_string(),
+            end: "06:30".to_string(),
+            hosts: vec!["api.example.com".to_string()],
+            storage: Vec::new(),
+        };
+        assert_eq!(window.bounds().unwrap(), (22 * 60, 6 * 60 + 30));
+        // Opens on Friday night and stays open into Saturday morning
+        assert_eq!(window.open_for(Weekday::Fri, 23 * 60), Some(7 * 60 + 30));
+        assert_eq!(window.open_for(Weekday::Sat, 6 * 60), Some(30));
+        assert_eq!(window.open_for(Weekday::Sat, 23 * 60), None);
+        assert_eq!(window.open_for(Weekday::Mon, 6 * 60), None);
+        assert_eq!(window.open_for(Weekday::Tue, 60), Some(5 * 60 + 30));
+
+        let business_hours = TimeWindow {
+            start: "09:00".to_string(),
+            end: "17:00".to_string(),
+            days: None,
+            ..window.clone()
+        };
+        assert_eq!(business_hours.open_for(Weekday::Sun, 16 * 60), Some(60));
+        assert_eq!(business_hours.open_for(Weekday::Sun, 17 * 60), None);
+
+        for (start, end) in [("9:00", "17:00"), ("09:00", "25:00"), ("10:00", "10:00")] {
+            let invalid = TimeWindow {
+                start: start.to_string(),
+                end: end.to_string(),
+                ..window.clone()
+            };
+            assert!(invalid.bounds().is_err(), "{start}-{end}");
+        }
+    }
+
+    #[test]
+    fn test_schedule_validation() {
+        let policy = |hosts: &str| {
+            crate::PolicyParser::parse_str(format!(
+                r#"
+version: "1.4"
+permissions:
+  network:
+    allow:
+      - host: "api.example.com"
+  schedule:
+    windows:
+      - start: "09:00"
+        end: "17:00"
+        hosts: [{hosts}]
+"#
+            ))
+        };
+        assert!(policy("\"api.example.com\"").is_ok());
+        assert!(policy("\"other.example.com\"").is_err());
+        assert!(policy("").is_err());
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let permissions = |key: &str| Permissions {
+            environment: Some(EnvironmentPermissions {
+                allow: Some(vec![EnvironmentPermission {
+                    key: "API_KEY".to_string(),
+                }]),
+            }),
+            config: Some(BTreeMap::from([(key.to_string(), "on".to_string())])),
+            ..Default::default()
+        };
+        assert!(permissions("beta-search").validate().is_ok());
+        assert!(permissions("LOG_LEVEL").validate().is_ok());
+
+        assert!(permissions("").validate().is_err());
+        assert!(permissions("two words").validate().is_err());
+        assert!(permissions("WASSETTE_REQUEST_ID").validate().is_err());
+        assert!(permissions("API_KEY").validate().is_err());
+    }
+
     #[test]
     fn test_storage_uri_wildcard_validation() {
         assert!(Permissions::validate_storage_uri("fs://work/agent/**").is_ok());
@@ -390,6 +959,26 @@ mod tests {
         assert!(Permissions::validate_network_host("*.example.").is_err());
     }
 
+    #[test]
+    fn test_budget_validation() {
+        let mut permissions = Permissions {
+            budget: Some(Budget {
+                max_calls_per_hour: Some(10),
+                max_calls_per_day: Some(100),
+                max_wall_clock_seconds_per_day: None,
+                max_sleep_seconds_per_call: Some(5),
+            }),
+            ..Default::default()
+        };
+        assert!(permissions.validate().is_ok());
+
+        permissions.budget = Some(Budget {
+            max_calls_per_day: Some(0),
+            ..Default::default()
+        });
+        assert!(permissions.validate().is_err());
+    }
+
     #[test]
     fn test_environment_key_validation() {
         assert!(Permissions::validate_environment_key("PATH").is_ok());
@@ -490,4 +1079,38 @@ mod tests {
         });
         assert!(permissions.validate().is_err());
     }
+
+    #[test]
+    fn test_locale_validation() {
+        let mut permissions = Permissions {
+            locale: Some(Locale {
+                timezone: Some("Europe/Berlin".to_string()),
+                lang: Some("de_DE.UTF-8".to_string()),
+                variables: BTreeMap::from([("LC_TIME".to_string(), "en_GB.UTF-8".to_string())]),
+            }),
+            ..Default::default()
+        };
+        assert!(permissions.validate().is_ok());
+        assert_eq!(
+            permissions.locale.as_ref().unwrap().env_vars(),
+            BTreeMap::from([
+                ("LANG".to_string(), "de_DE.UTF-8".to_string()),
+                ("LC_TIME".to_string(), "en_GB.UTF-8".to_string()),
+                ("TZ".to_string(), "Europe/Berlin".to_string()),
+            ])
+        );
+
+        // Only locale variables can be set
+        permissions.locale = Some(Locale {
+            variables: BTreeMap::from([("API_KEY".to_string(), "secret".to_string())]),
+            ..Default::default()
+        });
+        assert!(permissions.validate().is_err());
+
+        permissions.locale = Some(Locale {
+            timezone: Some(String::new()),
+            ..Default::default()
+        });
+        assert!(permissions.validate().is_err());
+    }
 }
@@ -0,0 +1,268 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Versions of the policy file format
+//!
+//! Documents of an older minor version of the current format are read as they are and
+//! [`PolicyDocument::migrate`] upgrades them to [`CURRENT_VERSION`]. A newer minor version is
+//! read as well, ignoring settings this version doesn't know. Other major versions are rejected.
+
+use std::fmt;
+
+use crate::PolicyDocument;
+
+/// Version of the policy format written by this version
+pub const CURRENT_VERSION: &str = "1.6";
+
+const CURRENT_MAJOR: u32 = 1;
+const CURRENT_MINOR: u32 = 6;
+
+/// Migrations of the current major version, where the one at index `n` upgrades `1.n` to
+/// `1.n+1`
+const MIGRATIONS: &[fn(&mut PolicyDocument)] =
+    &[from_1_0, from_1_1, from_1_2, from_1_3, from_1_4, from_1_5];
+
+/// 1.1 only added the `budget`, `ai`, `approval`, `process` and `locale` sections, so 1.0
+/// documents mean the same in 1.1
+fn from_1_0(_document: &mut PolicyDocument) {}
+
+/// 1.2 only added the `blobstore` section
+fn from_1_1(_document: &mut PolicyDocument) {}
+
+/// 1.3 only added the `events` section
+fn from_1_2(_document: &mut PolicyDocument) {}
+
+/// 1.4 only added the `schedule` section
+fn from_1_3(_document: &mut PolicyDocument) {}
+
+/// 1.5 only added the `config` section
+fn from_1_4(_document: &mut PolicyDocument) {}
+
+/// 1.6 only added `extends`
+fn from_1_5(_document: &mut PolicyDocument) {}
+
+/// Minor version that added `extends`
+const EXTENDS_SINCE: u32 = 6;
+
+/// Sections added after 1.0, with the minor version that added them
+const ADDED_SECTIONS: &[(&str, u32)] = &[
+    ("budget", 1),
+    ("ai", 1),
+    ("approval", 1),
+    ("process", 1),
+    ("locale", 1),
+    ("blobstore", 2),
+    ("events", 3),
+    ("schedule", 4),
+    ("config", 5),
+];
+
+/// Something in a policy document that still works but should be changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyWarning {
+    /// Where in the document, e.g. `permissions.runtime`
+    pub path: String,
+    /// What is wrong and what to do about it
+    pub message: String,
+}
+
+impl fmt::Display for PolicyWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl PolicyWarning {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Returns the major and minor version of a format version like `1.0`, or `None` if it isn't one
+pub fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().unwrap_or("0").parse().ok()?;
+    Some((major, minor))
+}
+
+impl PolicyDocument {
+    /// Returns the deprecated settings of the document, including an outdated format version
+    pub fn warnings(&self) -> Vec<PolicyWarning> {
+        let mut warnings = Vec::new();
+        match parse_version(&self.version) {
+            Some((CURRENT_MAJOR, minor)) if minor < CURRENT_MINOR => {
+                warnings.push(PolicyWarning::new(
+                    "version",
+                    format!(
+                        "policy format {} is deprecated, `wassette policy migrate` upgrades it \
+                         to {CURRENT_VERSION}",
+                        self.version
+                    ),
+                ));
+                for (section, since) in ADDED_SECTIONS
+                    .iter()
+                    .filter(|(section, since)| *since > minor && self.has_section(section))
+                {
+                    warnings.push(PolicyWarning::new(
+                        format!("permissions.{section}"),
+                        format!(
+                            "was added in policy format 1.{since}, declare `version: \"1.{since}\"`"
+                        ),
+                    ));
+                }
+                if minor < EXTENDS_SINCE && !self.extends.is_empty() {
+                    warnings.push(PolicyWarning::new(
+                        "extends",
+                        format!(
+                            "was added in policy format 1.{EXTENDS_SINCE}, declare \
+                             `version: \"1.{EXTENDS_SINCE}\"`"
+                        ),
+                    ));
+                }
+            }
+            Some((CURRENT_MAJOR, minor)) if minor > CURRENT_MINOR => {
+                warnings.push(PolicyWarning::new(
+                    "version",
+                    format!(
+                        "policy format {} is newer than {CURRENT_VERSION}, settings it added are \
+                         ignored",
+                        self.version
+                    ),
+                ));
+            }
+            _ => {}
+        }
+
+        let permissions = &self.permissions;
+        if permissions.runtime.is_some() {
+            warnings.push(PolicyWarning::new(
+                "permissions.runtime",
+                "is deprecated and not enforced, components always run in the WebAssembly sandbox",
+            ));
+        }
+        if permissions.ipc.is_some() {
+            warnings.push(PolicyWarning::new(
+                "permissions.ipc",
+                "is deprecated and not enforced",
+            ));
+        }
+        if let Some(resources) = &permissions.resources {
+            if resources.cpu.is_some() {
+                warnings.push(PolicyWarning::new(
+                    "permissions.resources.cpu",
+                    "is deprecated and not enforced",
+                ));
+            }
+            if resources.io.is_some() {
+                warnings.push(PolicyWarning::new(
+                    "permissions.resources.io",
+                    "is deprecated and not enforced",
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// Upgrades a document of an older minor version to [`CURRENT_VERSION`]. Returns whether the
+    /// document changed.
+    pub fn migrate(&mut self) -> bool {
+        let Some((CURRENT_MAJOR, minor)) = parse_version(&self.version) else {
+            return false;
+        };
+        if minor >= CURRENT_MINOR {
+            return false;
+        }
+        for migration in &MIGRATIONS[minor as usize..] {
+            migration(self);
+        }
+        self.version = CURRENT_VERSION.to_string();
+        true
+    }
+
+    fn has_section(&self, section: &str) -> bool {
+        let permissions = &self.permissions;
+        match section {
+            "budget" => permissions.budget.is_some(),
+            "ai" => permissions.ai.is_some(),
+            "approval" => permissions.approval.is_some(),
+            "process" => permissions.process.is_some(),
+            "locale" => permissions.locale.is_some(),
+            "blobstore" => permissions.blobstore.is_some(),
+            "events" => permissions.events.is_some(),
+            "schedule" => permissions.schedule.is_some(),
+            "config" => permissions.config.is_some(),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PolicyParser;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("1.0"), Some((1, 0)));
+        assert_eq!(parse_version("1"), Some((1, 0)));
+        assert_eq!(parse_version("1.12"), Some((1, 12)));
+        assert_eq!(parse_version("one"), None);
+    }
+
+    #[test]
+    fn test_migrate_1_0() {
+        let mut policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+permissions:
+  budget:
+    max_calls_per_hour: 10
+  runtime:
+    docker: {}
+"#,
+        )
+        .unwrap();
+        let paths: Vec<_> = policy.warnings().into_iter().map(|w| w.path).collect();
+        assert_eq!(
+            paths,
+            ["version", "permissions.budget", "permissions.runtime"]
+        );
+
+        assert!(policy.migrate());
+        assert_eq!(policy.version, CURRENT_VERSION);
+        assert!(!policy.migrate());
+        // Settings that aren't enforced can't be migrated away
+        let paths: Vec<_> = policy.warnings().into_iter().map(|w| w.path).collect();
+        assert_eq!(paths, ["permissions.runtime"]);
+    }
+
+    #[test]
+    fn test_section_newer_than_version() {
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.1"
+permissions:
+  blobstore:
+    allow:
+      - container: "artifacts"
+        access: ["read"]
+"#,
+        )
+        .unwrap();
+        let warnings = policy.warnings();
+        assert_eq!(warnings[1].path, "permissions.blobstore");
+        assert!(warnings[1].message.contains("1.2"));
+    }
+
+    #[test]
+    fn test_newer_minor_version() {
+        let mut policy = PolicyParser::parse_str("version: \"1.7\"\npermissions: {}").unwrap();
+        assert!(policy.warnings()[0].message.contains("newer"));
+        assert!(!policy.migrate());
+        assert_eq!(policy.version, "1.7");
+    }
+}
@@ -0,0 +1,59 @@
+/*
+ * Copyright (c) Microsoft Corporation.
+ * Licensed under the MIT license.
+ *
+ * C interface of the wassette runtime. Link against libwassette_ffi.
+ *
+ * Strings are NUL-terminated UTF-8. Strings returned by these functions are owned by the caller
+ * and must be released with wassette_string_free. Functions that can fail return NULL or a
+ * non-zero status and, if `error` is not NULL, store a message in `*error` that must be released
+ * with wassette_string_free as well.
+ *
+ * A manager may be used from several threads at once. Each call blocks until it completes.
+ */
+
+#ifndef WASSETTE_H
+#define WASSETTE_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Version of this interface. Incremented whenever a function changes incompatibly. */
+#define WASSETTE_ABI_VERSION 1
+
+typedef struct WassetteManager WassetteManager;
+
+/* Returns the version of the interface implemented by the library. */
+uint32_t wassette_abi_version(void);
+
+/* Creates a manager that keeps its components in `plugin_dir`. */
+WassetteManager *wassette_manager_new(const char *plugin_dir, char **error);
+
+/* Destroys a manager. Does nothing if `manager` is NULL. */
+void wassette_manager_free(WassetteManager *manager);
+
+/* Loads the component at `uri` and returns its ID. */
+char *wassette_load_component(WassetteManager *manager, const char *uri, char **error);
+
+/* Unloads a component and removes its files. Returns 0 on success. */
+int32_t wassette_unload_component(WassetteManager *manager, const char *component_id,
+                                  char **error);
+
+/* Returns the tools of all loaded components as a JSON array. */
+char *wassette_list_tools(WassetteManager *manager, char **error);
+
+/* Calls a tool with arguments given as a JSON object and returns its result as JSON. */
+char *wassette_call_tool(WassetteManager *manager, const char *component_id,
+                         const char *function_name, const char *parameters, char **error);
+
+/* Releases a string returned by this library. Does nothing if `string` is NULL. */
+void wassette_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* WASSETTE_H */
@@ -0,0 +1,284 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! A C interface to the wassette runtime, so that C, C++, Go and .NET hosts can load components
+//! and call their tools. The functions are declared in `include/wassette.h`.
+//!
+//! Tool arguments and results cross the boundary as JSON strings. Errors are returned as
+//! messages through an optional out parameter, and panics are turned into errors instead of
+//! unwinding into the host.
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use anyhow::{anyhow, Context, Result};
+use wassette::blocking::LifecycleManager;
+
+/// Version of the interface, incremented whenever a function changes incompatibly
+pub const WASSETTE_ABI_VERSION: u32 = 1;
+
+/// A lifecycle manager owned by the host
+pub struct WassetteManager {
+    inner: LifecycleManager,
+}
+
+/// Runs `f`, storing its error or panic in `error` if that isn't null
+///
+/// # Safety
+///
+/// `error` must be null or valid for writes
+unsafe fn ffi_call<T>(error: *mut *mut c_char, f: impl FnOnce() -> Result<T>) -> Option<T> {
+    let result = catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(anyhow!("wassette panicked, see its log for details")));
+    match result {
+        Ok(value) => {
+            if !error.is_null() {
+                *error = ptr::null_mut();
+            }
+            Some(value)
+        }
+        Err(e) => {
+            if !error.is_null() {
+                *error = into_c_string(format!("{e:#}"));
+            }
+            None
+        }
+    }
+}
+
+/// Reads a string argument
+///
+/// # Safety
+///
+/// `value` must be null or point to a NUL-terminated string
+unsafe fn read_str<'a>(value: *const c_char, name: &str) -> Result<&'a str> {
+    if value.is_null() {
+        return Err(anyhow!("{name} is null"));
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .with_context(|| format!("{name} is not valid UTF-8"))
+}
+
+/// Reads the manager argument
+///
+/// # Safety
+///
+/// `manager` must be null or returned by [`wassette_manager_new`] and not yet freed
+unsafe fn read_manager<'a>(manager: *mut WassetteManager) -> Result<&'a LifecycleManager> {
+    manager
+        .as_ref()
+        .map(|manager| &manager.inner)
+        .context("manager is null")
+}
+
+/// Hands a string to the host. Interior NUL bytes, which JSON and error messages don't contain,
+/// are dropped.
+fn into_c_string(value: String) -> *mut c_char {
+    let value = CString::new(value).unwrap_or_else(|e| {
+        let mut bytes = e.into_vec();
+        bytes.retain(|byte| *byte != 0);
+        CString::new(bytes).expect("NUL bytes were removed")
+    });
+    value.into_raw()
+}
+
+/// Returns the version of the interface implemented by the library
+#[no_mangle]
+pub extern "C" fn wassette_abi_version() -> u32 {
+    WASSETTE_ABI_VERSION
+}
+
+/// Creates a manager that keeps its components in `plugin_dir`. Returns null on failure.
+///
+/// # Safety
+///
+/// `plugin_dir` must be a NUL-terminated string and `error` null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn wassette_manager_new(
+    plugin_dir: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut WassetteManager {
+    ffi_call(error, || {
+        let plugin_dir = read_str(plugin_dir, "plugin_dir")?;
+        let inner = LifecycleManager::new(plugin_dir)?;
+        Ok(Box::into_raw(Box::new(WassetteManager { inner })))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Destroys a manager. Does nothing if `manager` is null.
+///
+/// # Safety
+///
+/// `manager` must be null or returned by [`wassette_manager_new`] and not yet freed, and no other
+/// thread may use it
+#[no_mangle]
+pub unsafe extern "C" fn wassette_manager_free(manager: *mut WassetteManager) {
+    if !manager.is_null() {
+        drop(Box::from_raw(manager));
+    }
+}
+
+/// Loads the component at `uri` and returns its ID. Returns null on failure.
+///
+/// # Safety
+///
+/// `manager` must be returned by [`wassette_manager_new`], `uri` a NUL-terminated string and
+/// `error` null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn wassette_load_component(
+    manager: *mut WassetteManager,
+    uri: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    ffi_call(error, || {
+        let manager = read_manager(manager)?;
+        let (id, _) = manager.load_component(read_str(uri, "uri")?)?;
+        Ok(into_c_string(id))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Unloads a component and removes its files. Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `manager` must be returned by [`wassette_manager_new`], `component_id` a NUL-terminated string
+/// and `error` null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn wassette_unload_component(
+    manager: *mut WassetteManager,
+    component_id: *const c_char,
+    error: *mut *mut c_char,
+) -> i32 {
+    ffi_call(error, || {
+        let manager = read_manager(manager)?;
+        manager.unload_component(read_str(component_id, "component_id")?)
+    })
+    .map_or(-1, |_| 0)
+}
+
+/// Returns the tools of all loaded components as a JSON array. Returns null on failure.
+///
+/// # Safety
+///
+/// `manager` must be returned by [`wassette_manager_new`] and `error` null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn wassette_list_tools(
+    manager: *mut WassetteManager,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    ffi_call(error, || {
+        let manager = read_manager(manager)?;
+        Ok(into_c_string(serde_json::to_string(&manager.list_tools())?))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Calls a tool with arguments given as a JSON object and returns its result as JSON. Returns
+/// null on failure.
+///
+/// # Safety
+///
+/// `manager` must be returned by [`wassette_manager_new`], the other arguments NUL-terminated
+/// strings and `error` null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn wassette_call_tool(
+    manager: *mut WassetteManager,
+    component_id: *const c_char,
+    function_name: *const c_char,
+    parameters: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    ffi_call(error, || {
+        let manager = read_manager(manager)?;
+        let result = manager.execute_component_call(
+            read_str(component_id, "component_id")?,
+            read_str(function_name, "function_name")?,
+            read_str(parameters, "parameters")?,
+        )?;
+        Ok(into_c_string(result))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Releases a string returned by this library. Does nothing if `string` is null.
+///
+/// # Safety
+///
+/// `string` must be null or returned by this library and not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn wassette_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn take_string(value: *mut c_char) -> String {
+        let string = CStr::from_ptr(value).to_string_lossy().into_owned();
+        wassette_string_free(value);
+        string
+    }
+
+    #[test]
+    fn test_manager_round_trip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let plugin_dir = CString::new(tempdir.path().to_str().unwrap()).unwrap();
+        let mut error = ptr::null_mut();
+
+        unsafe {
+            let manager = wassette_manager_new(plugin_dir.as_ptr(), &mut error);
+            assert!(!manager.is_null());
+            assert!(error.is_null());
+
+            // Only the tools the host implements itself, as no component is loaded
+            let tools: serde_json::Value =
+                serde_json::from_str(&take_string(wassette_list_tools(manager, &mut error)))
+                    .unwrap();
+            let names: Vec<_> = tools
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|tool| tool["name"].as_str().unwrap())
+                .collect();
+            assert_eq!(names, ["fetch-url", "read-file"]);
+
+            let component_id = CString::new("missing").unwrap();
+            let function_name = CString::new("fetch").unwrap();
+            let parameters = CString::new("{}").unwrap();
+            let result = wassette_call_tool(
+                manager,
+                component_id.as_ptr(),
+                function_name.as_ptr(),
+                parameters.as_ptr(),
+                &mut error,
+            );
+            assert!(result.is_null());
+            assert!(!error.is_null());
+            assert!(!take_string(error).is_empty());
+
+            wassette_manager_free(manager);
+        }
+    }
+
+    #[test]
+    fn test_null_arguments_are_errors() {
+        let mut error = ptr::null_mut();
+        unsafe {
+            assert!(wassette_manager_new(ptr::null(), &mut error).is_null());
+            assert_eq!(take_string(error), "plugin_dir is null");
+            assert_eq!(
+                wassette_unload_component(ptr::null_mut(), ptr::null(), ptr::null_mut()),
+                -1
+            );
+            wassette_manager_free(ptr::null_mut());
+            wassette_string_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Adapting WASI preview 1 core modules into components.
+//!
+//! Core modules that only import `wasi_snapshot_preview1` are wrapped with the preview 1
+//! adapter from Wasmtime, which implements the preview 1 functions on top of the preview 2
+//! interfaces that the component policies govern. Modules that export `_start` become commands
+//! exporting `wasi:cli/run`, the others become reactors.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+use tracing::info;
+use wasi_preview1_component_adapter_provider::{
+    WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME, WASI_SNAPSHOT_PREVIEW1_COMMAND_ADAPTER,
+    WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
+};
+use wasmparser::{Parser, Payload};
+
+use crate::imports::{Capability, RequiredCapability};
+
+/// Returns whether the binary is a core module rather than a component
+pub(crate) fn is_core_module(wasm: &[u8]) -> bool {
+    Parser::is_core_wasm(wasm)
+}
+
+/// A component binary, ready to compile
+pub(crate) struct Adapted {
+    pub(crate) wasm: Vec<u8>,
+    /// The capabilities a preview 1 module needs, derived from its imports, or `None` if the
+    /// binary already was a component. The adapter imports every preview 2 interface, so the
+    /// imports of the adapted component would overstate them.
+    pub(crate) preview1_capabilities: Option<Vec<RequiredCapability>>,
+}
+
+/// Returns the binary unchanged if it is a component, or adapts it if it is a preview 1 module
+pub(crate) fn adapt_if_module(wasm: Vec<u8>) -> Result<Adapted> {
+    if !is_core_module(&wasm) {
+        return Ok(Adapted {
+            wasm,
+            preview1_capabilities: None,
+        });
+    }
+    let capabilities = preview1_capabilities(&wasm)?;
+    info!(
+        capabilities = ?capabilities.iter().map(|c| c.capability).collect::<Vec<_>>(),
+        "Adapting WASI preview 1 module into a component; grant these permissions in its policy"
+    );
+    Ok(Adapted {
+        wasm: componentize(&wasm)?,
+        preview1_capabilities: Some(capabilities),
+    })
+}
+
+/// Returns the policy permissions a preview 1 module needs, derived from the functions it
+/// imports:
+///
+/// - `path_*` and the functions listing preopened directories need `storage`
+/// - `sock_*` need `network`
+/// - `environ_*` need `environment`
+///
+/// Clocks, random numbers, arguments and stdio are always available.
+pub(crate) fn preview1_capabilities(wasm: &[u8]) -> Result<Vec<RequiredCapability>> {
+    let mut required: BTreeMap<Capability, Vec<String>> = BTreeMap::new();
+    for (module, name) in imports(wasm)? {
+        if module != WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME {
+            continue;
+        }
+        let capability = if name.starts_with("path_")
+            || name == "fd_prestat_get"
+            || name == "fd_prestat_dir_name"
+            || name == "fd_readdir"
+        {
+            Capability::Storage
+        } else if name.starts_with("sock_") {
+            Capability::Network
+        } else if name.starts_with("environ_") {
+            Capability::Environment
+        } else {
+            continue;
+        };
+        required
+            .entry(capability)
+            .or_default()
+            .push(format!("{module}::{name}"));
+    }
+    Ok(required
+        .into_iter()
+        .map(|(capability, imports)| RequiredCapability {
+            capability,
+            imports,
+        })
+        .collect())
+}
+
+/// Wraps a preview 1 core module into a component. Fails if the module imports anything besides
+/// `wasi_snapshot_preview1`, since there is nothing to provide those imports.
+pub(crate) fn componentize(wasm: &[u8]) -> Result<Vec<u8>> {
+    if let Some((module, name)) = imports(wasm)?
+        .into_iter()
+        .find(|(module, _)| module != WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME)
+    {
+        bail!(
+            "Core module imports `{module}::{name}`, but only modules importing `{WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME}` can be adapted into components"
+        );
+    }
+    let adapter = if exports(wasm)?.iter().any(|name| name == "_start") {
+        WASI_SNAPSHOT_PREVIEW1_COMMAND_ADAPTER
+    } else {
+        WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER
+    };
+    wit_component::ComponentEncoder::default()
+        .module(wasm)
+        .context("Failed to read core module")?
+        .adapter(WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME, adapter)
+        .context("Failed to add the WASI preview 1 adapter")?
+        .validate(true)
+        .encode()
+        .context("Failed to adapt core module into a component")
+}
+
+fn imports(wasm: &[u8]) -> Result<Vec<(String, String)>> {
+    let mut imports = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::ImportSection(reader) = payload? {
+            for import in reader {
+                let import = import?;
+                imports.push((import.module.to_string(), import.name.to_string()));
+            }
+        }
+    }
+    Ok(imports)
+}
+
+fn exports(wasm: &[u8]) -> Result<Vec<String>> {
+    let mut exports = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::ExportSection(reader) = payload? {
+            for export in reader {
+                exports.push(export?.name.to_string());
+            }
+        }
+    }
+    Ok(exports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMAND: &str = r#"(module
+        (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "path_open"
+            (func (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "environ_sizes_get" (func (param i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (func (export "_start"))
+    )"#;
+
+    #[test]
+    fn test_componentize_command() -> Result<()> {
+        let module = wat::parse_str(COMMAND)?;
+        assert!(is_core_module(&module));
+        assert_eq!(
+            preview1_capabilities(&module)?,
+            [
+                RequiredCapability {
+                    capability: Capability::Storage,
+                    imports: vec!["wasi_snapshot_preview1::path_open".to_string()],
+                },
+                RequiredCapability {
+                    capability: Capability::Environment,
+                    imports: vec!["wasi_snapshot_preview1::environ_sizes_get".to_string()],
+                },
+            ]
+        );
+
+        let component = componentize(&module)?;
+        assert!(!is_core_module(&component));
+        assert!(Parser::is_component(&component));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_preview1_module() -> Result<()> {
+        let manager = crate::tests::create_test_manager().await?;
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("hello.wasm");
+        std::fs::write(&path, wat::parse_str(COMMAND)?)?;
+
+        let (id, _) = manager
+            .load_component(&format!("file://{}", path.display()))
+            .await?;
+        assert_eq!(id, "local/hello");
+        assert_eq!(
+            manager.list_components().await,
+            vec!["local/hello".to_string()]
+        );
+        // The mapping from its preview 1 imports is reported, not the imports of the adapter
+        let required: Vec<_> = manager
+            .get_required_capabilities(&id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|required| required.capability)
+            .collect();
+        assert_eq!(required, [Capability::Storage, Capability::Environment]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_componentize_rejects_other_imports() -> Result<()> {
+        let module = wat::parse_str(
+            r#"(module (import "env" "host_log" (func (param i32))) (memory (export "memory") 1))"#,
+        )?;
+        let error = componentize(&module).unwrap_err().to_string();
+        assert!(error.contains("env::host_log"), "unexpected error: {error}");
+        Ok(())
+    }
+}
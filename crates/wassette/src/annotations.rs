@@ -0,0 +1,99 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Default MCP tool annotations, inferred from what a component's policy lets it do.
+//!
+//! A component without storage or network grants can't change anything outside of the call, so
+//! its tools are read-only. Write access to storage makes them destructive, and network access
+//! makes them open-world. Annotations declared in the component's tool metadata take precedence.
+
+use component2json::{ToolAnnotations, ToolSchema};
+
+use crate::WasiStateTemplate;
+
+/// Returns the annotations implied by the permissions of a component's policy
+pub(crate) fn inferred_annotations(template: &WasiStateTemplate) -> ToolAnnotations {
+    let writes = template.preopened_dirs.iter().any(|dir| {
+        dir.file_perms.contains(wasmtime_wasi::FilePerms::WRITE)
+            || dir.dir_perms.contains(wasmtime_wasi::DirPerms::MUTATE)
+    });
+    let network = !template.allowed_hosts.is_empty()
+        || template.network_perms.allow_tcp
+        || template.network_perms.allow_udp;
+    ToolAnnotations {
+        read_only_hint: Some(!writes && !network),
+        // Requests to allowed hosts may or may not destroy data, so that is left unknown
+        destructive_hint: if writes {
+            Some(true)
+        } else if network {
+            None
+        } else {
+            Some(false)
+        },
+        open_world_hint: Some(network),
+        ..Default::default()
+    }
+}
+
+/// Returns the schema of a tool with the unset annotations inferred from its component's policy
+pub(crate) fn annotate(schema: &ToolSchema, template: &WasiStateTemplate) -> ToolSchema {
+    let inferred = inferred_annotations(template);
+    let mut schema = schema.clone();
+    schema.annotations = Some(match schema.annotations.take() {
+        Some(declared) => declared.or(&inferred),
+        None => inferred,
+    });
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::wasistate::PreopenedDir;
+
+    #[test]
+    fn test_inferred_annotations() {
+        let mut template = WasiStateTemplate::default();
+        let annotations = inferred_annotations(&template);
+        assert_eq!(annotations.read_only_hint, Some(true));
+        assert_eq!(annotations.destructive_hint, Some(false));
+        assert_eq!(annotations.open_world_hint, Some(false));
+
+        template.allowed_hosts.insert("api.example.com".to_string());
+        let annotations = inferred_annotations(&template);
+        assert_eq!(annotations.read_only_hint, Some(false));
+        assert_eq!(annotations.destructive_hint, None);
+        assert_eq!(annotations.open_world_hint, Some(true));
+
+        template.preopened_dirs.push(PreopenedDir {
+            host_path: PathBuf::from("/tmp/data"),
+            guest_path: "/data".to_string(),
+            dir_perms: wasmtime_wasi::DirPerms::READ | wasmtime_wasi::DirPerms::MUTATE,
+            file_perms: wasmtime_wasi::FilePerms::READ | wasmtime_wasi::FilePerms::WRITE,
+        });
+        assert_eq!(inferred_annotations(&template).destructive_hint, Some(true));
+    }
+
+    #[test]
+    fn test_declared_annotations_take_precedence() {
+        let schema = ToolSchema {
+            name: "delete".to_string(),
+            description: None,
+            input_schema: serde_json::json!({"type": "object"}),
+            output_schema: None,
+            annotations: Some(ToolAnnotations {
+                read_only_hint: Some(false),
+                idempotent_hint: Some(true),
+                ..Default::default()
+            }),
+        };
+        let annotations = annotate(&schema, &WasiStateTemplate::default())
+            .annotations
+            .unwrap();
+        assert_eq!(annotations.read_only_hint, Some(false));
+        assert_eq!(annotations.idempotent_hint, Some(true));
+        assert_eq!(annotations.destructive_hint, Some(false));
+    }
+}
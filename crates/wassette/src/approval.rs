@@ -0,0 +1,484 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Human approval of tool calls.
+//!
+//! If the component's policy sets `approval.destructive`, calls to tools that its grants make
+//! destructive only run once someone approved them. Write access to storage and network access
+//! count as destructive, and a tool can declare itself destructive but not harmless. Waiting calls
+//! are written to the `approvals/pending` directory of the plugin directory, where `wassette
+//! approval` lists them and records decisions next to them, so that calls can also be approved
+//! when the server runs as a service. Every decision, including calls that weren't approved in
+//! time, is appended to `approvals/audit.jsonl` along with who made it.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::annotations::inferred_annotations;
+use crate::{CorrelationIds, LifecycleEvent, LifecycleManager, WasiStateTemplate};
+
+/// Directory in the plugin directory that approval requests and the audit log are written to
+pub const APPROVALS_DIR: &str = "approvals";
+
+/// Number of seconds a call waits for approval unless
+/// [`approval_timeout_secs`](crate::LifecycleConfig::approval_timeout_secs) is set
+pub const DEFAULT_APPROVAL_TIMEOUT_SECS: u64 = 300;
+
+const PENDING_DIR: &str = "pending";
+const AUDIT_LOG_FILE: &str = "audit.jsonl";
+const DECISION_SUFFIX: &str = ".decision.json";
+
+/// How often a waiting call checks whether it was decided on
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A tool call waiting for approval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    /// ID that the call is approved or denied by
+    pub id: String,
+    /// ID of the called component
+    pub component_id: String,
+    /// Name of the called tool
+    pub tool: String,
+    /// The arguments of the call
+    pub arguments: Value,
+    /// When approval was requested, in milliseconds since the Unix epoch
+    pub requested_at_ms: u64,
+    /// ID of the request that made the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// ID of the session that made the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+}
+
+/// How a request for approval ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalOutcome {
+    /// The call was approved and ran
+    Approved,
+    /// The call was denied
+    Denied,
+    /// Nobody decided on the call before it timed out
+    Expired,
+}
+
+impl std::str::FromStr for ApprovalOutcome {
+    type Err = anyhow::Error;
+
+    fn from_str(outcome: &str) -> Result<Self> {
+        match outcome {
+            "approved" => Ok(Self::Approved),
+            "denied" => Ok(Self::Denied),
+            "expired" => Ok(Self::Expired),
+            _ => bail!("Invalid outcome, expected approved, denied or expired: {outcome}"),
+        }
+    }
+}
+
+/// A decision on a request for approval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalDecision {
+    /// Whether the call may run
+    pub outcome: ApprovalOutcome,
+    /// Who decided, unless the request expired
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decided_by: Option<String>,
+    /// Why the call was approved or denied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// When the decision was made, in milliseconds since the Unix epoch
+    pub decided_at_ms: u64,
+}
+
+/// An entry of the approval audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalRecord {
+    /// The call that needed approval
+    pub request: ApprovalRequest,
+    /// What was decided about it
+    pub decision: ApprovalDecision,
+}
+
+/// Returns the path of the approval audit log in a plugin directory
+pub(crate) fn audit_log_path(plugin_dir: &Path) -> PathBuf {
+    plugin_dir.join(APPROVALS_DIR).join(AUDIT_LOG_FILE)
+}
+
+fn pending_dir(plugin_dir: &Path) -> PathBuf {
+    plugin_dir.join(APPROVALS_DIR).join(PENDING_DIR)
+}
+
+fn request_path(plugin_dir: &Path, id: &str) -> PathBuf {
+    pending_dir(plugin_dir).join(format!("{id}.json"))
+}
+
+fn decision_path(plugin_dir: &Path, id: &str) -> PathBuf {
+    pending_dir(plugin_dir).join(format!("{id}{DECISION_SUFFIX}"))
+}
+
+fn unix_now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn new_approval_id() -> String {
+    let mut bytes = [0u8; 6];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Returns the calls waiting for approval, oldest first
+pub async fn pending_approvals(plugin_dir: &Path) -> Result<Vec<ApprovalRequest>> {
+    let mut entries = match tokio::fs::read_dir(pending_dir(plugin_dir)).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to read pending approvals"),
+    };
+    let mut requests = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(id) = name.strip_suffix(".json") else {
+            continue;
+        };
+        if name.ends_with(DECISION_SUFFIX) || decision_path(plugin_dir, id).exists() {
+            continue;
+        }
+        let path = entry.path();
+        match read_request(&path).await {
+            Ok(request) => requests.push(request),
+            Err(e) => warn!(
+                error = %e,
+                path = %path.display(),
+                "Skipping unreadable approval request"
+            ),
+        }
+    }
+    requests.sort_by_key(|request| request.requested_at_ms);
+    Ok(requests)
+}
+
+async fn read_request(path: &Path) -> Result<ApprovalRequest> {
+    let data = tokio::fs::read(path).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Approves or denies a call waiting for approval. The waiting call picks the decision up and
+/// records it in the audit log. Returns the call that was decided on.
+pub async fn decide_approval(
+    plugin_dir: &Path,
+    id: &str,
+    approve: bool,
+    decided_by: &str,
+    reason: Option<String>,
+) -> Result<ApprovalRequest> {
+    // IDs are hex digits, which also keeps them from escaping the pending directory
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Invalid approval ID: {id}");
+    }
+    let request = match read_request(&request_path(plugin_dir, id)).await {
+        Ok(request) => request,
+        Err(_) => bail!("No call is waiting for approval {id}"),
+    };
+    let decision = ApprovalDecision {
+        outcome: if approve {
+            ApprovalOutcome::Approved
+        } else {
+            ApprovalOutcome::Denied
+        },
+        decided_by: Some(decided_by.to_string()),
+        reason,
+        decided_at_ms: unix_now_ms(),
+    };
+    let mut file = match tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(decision_path(plugin_dir, id))
+        .await
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            bail!("Approval {id} was already decided on")
+        }
+        Err(e) => return Err(e).context("Failed to record approval decision"),
+    };
+    file.write_all(&serde_json::to_vec_pretty(&decision)?)
+        .await
+        .context("Failed to record approval decision")?;
+    Ok(request)
+}
+
+/// Returns the entries of the approval audit log, oldest first
+pub async fn approval_log(plugin_dir: &Path) -> Result<Vec<ApprovalRecord>> {
+    let data = match tokio::fs::read_to_string(audit_log_path(plugin_dir)).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to read approval audit log"),
+    };
+    data.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).context("Invalid entry in approval audit log"))
+        .collect()
+}
+
+async fn append_to_log(plugin_dir: &Path, record: &ApprovalRecord) -> Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(audit_log_path(plugin_dir))
+        .await
+        .context("Failed to open approval audit log")?;
+    file.write_all(&line)
+        .await
+        .context("Failed to write approval audit log")
+}
+
+/// Writes a request for approval and waits until it is decided on or the timeout passes
+async fn request_approval(
+    plugin_dir: &Path,
+    request: &ApprovalRequest,
+    timeout: Duration,
+) -> Result<ApprovalDecision> {
+    tokio::fs::create_dir_all(pending_dir(plugin_dir))
+        .await
+        .context("Failed to create approvals directory")?;
+    let request_path = request_path(plugin_dir, &request.id);
+    tokio::fs::write(&request_path, serde_json::to_vec_pretty(request)?)
+        .await
+        .context("Failed to write approval request")?;
+
+    let decision_path = decision_path(plugin_dir, &request.id);
+    let deadline = tokio::time::Instant::now() + timeout;
+    let decision = loop {
+        if let Ok(data) = tokio::fs::read(&decision_path).await {
+            // The decision may be read while it is still being written
+            if let Ok(decision) = serde_json::from_slice(&data) {
+                break decision;
+            }
+        }
+        if tokio::time::Instant::now() >= deadline {
+            break ApprovalDecision {
+                outcome: ApprovalOutcome::Expired,
+                decided_by: None,
+                reason: None,
+                decided_at_ms: unix_now_ms(),
+            };
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    };
+
+    for path in [&request_path, &decision_path] {
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!(error = %e, path = %path.display(), "Failed to remove approval file");
+            }
+        }
+    }
+    let record = ApprovalRecord {
+        request: request.clone(),
+        decision: decision.clone(),
+    };
+    if let Err(e) = append_to_log(plugin_dir, &record).await {
+        warn!(error = %e, "Failed to record approval decision in the audit log");
+    }
+    Ok(decision)
+}
+
+/// Whether a tool is destructive for the purpose of approval. What the policy grants decides,
+/// and a tool that may or may not be destructive counts as destructive. The tool's own
+/// `destructive_hint` can only add to that, since a component could otherwise exempt itself from
+/// approval by declaring its tools harmless.
+fn is_destructive(policy: &WasiStateTemplate, declared_hint: Option<bool>) -> bool {
+    inferred_annotations(policy)
+        .destructive_hint
+        .unwrap_or(true)
+        || declared_hint == Some(true)
+}
+
+impl LifecycleManager {
+    /// Returns whether calls to a tool of a component only run once they are approved, because
+    /// the component's policy requires approval for destructive tools and the tool may be
+    /// destructive
+    pub async fn requires_approval(&self, component_id: &str, tool_name: &str) -> bool {
+        let policy = self.policy_template(component_id).await;
+        if !policy
+            .approval
+            .as_ref()
+            .is_some_and(|approval| approval.destructive)
+        {
+            return false;
+        }
+        let declared_hint = self
+            .get_tool_schema(tool_name)
+            .await
+            .and_then(|schema| schema.annotations)
+            .and_then(|annotations| annotations.destructive_hint);
+        is_destructive(&policy, declared_hint)
+    }
+
+    /// Waits for a call to be approved if it needs approval, and fails if it was denied or
+    /// wasn't approved in time
+    pub(crate) async fn await_approval(
+        &self,
+        component_id: &str,
+        tool_name: &str,
+        parameters: &str,
+        correlation: &CorrelationIds,
+    ) -> Result<()> {
+        // Previews run read-only and without network access, so they never need approval
+        if !self.requires_approval(component_id, tool_name).await
+            || self.is_preview_call(tool_name, parameters).await
+        {
+            return Ok(());
+        }
+        let timeout = self.config.read().await.approval_timeout();
+        let request = ApprovalRequest {
+            id: new_approval_id(),
+            component_id: component_id.to_string(),
+            tool: tool_name.to_string(),
+            arguments: serde_json::from_str(parameters)
+                .unwrap_or_else(|_| Value::String(parameters.to_string())),
+            requested_at_ms: unix_now_ms(),
+            request_id: correlation.request_id.clone(),
+            session_id: correlation.session_id.clone(),
+        };
+        info!(
+            approval_id = %request.id,
+            component_id,
+            tool = tool_name,
+            "Waiting for approval, run `wassette approval approve {}` to approve the call",
+            request.id
+        );
+        self.emit(LifecycleEvent::ApprovalRequested {
+            approval_id: request.id.clone(),
+            component_id: component_id.to_string(),
+            tool: tool_name.to_string(),
+            request_id: request.request_id.clone(),
+        });
+
+        let decision = request_approval(&self.plugin_dir, &request, timeout).await?;
+        let reason = decision
+            .reason
+            .as_deref()
+            .map(|reason| format!(": {reason}"))
+            .unwrap_or_default();
+        let decided_by = decision.decided_by.as_deref().unwrap_or("unknown");
+        match decision.outcome {
+            ApprovalOutcome::Approved => {
+                info!(approval_id = %request.id, decided_by, "Call approved");
+                Ok(())
+            }
+            ApprovalOutcome::Denied => {
+                bail!("Call to '{tool_name}' was denied by {decided_by}{reason}")
+            }
+            ApprovalOutcome::Expired => bail!(
+                "Call to '{tool_name}' was not approved within {} seconds",
+                timeout.as_secs()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: &str) -> ApprovalRequest {
+        ApprovalRequest {
+            id: id.to_string(),
+            component_id: "local/files".to_string(),
+            tool: "delete-file".to_string(),
+            arguments: serde_json::json!({"path": "/data/report.csv"}),
+            requested_at_ms: unix_now_ms(),
+            request_id: None,
+            session_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_approval_queue() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let plugin_dir = dir.path().to_path_buf();
+
+        let waiting = {
+            let plugin_dir = plugin_dir.clone();
+            tokio::spawn(async move {
+                request_approval(&plugin_dir, &request("0a1b"), Duration::from_secs(30)).await
+            })
+        };
+        let pending = loop {
+            let pending = pending_approvals(&plugin_dir).await?;
+            if !pending.is_empty() {
+                break pending;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(pending[0].id, "0a1b");
+
+        decide_approval(
+            &plugin_dir,
+            "0a1b",
+            false,
+            "alice",
+            Some("wrong file".into()),
+        )
+        .await?;
+        assert!(decide_approval(&plugin_dir, "0a1b", true, "bob", None)
+            .await
+            .is_err());
+        let decision = waiting.await??;
+        assert_eq!(decision.outcome, ApprovalOutcome::Denied);
+        assert_eq!(decision.decided_by.as_deref(), Some("alice"));
+
+        assert!(pending_approvals(&plugin_dir).await?.is_empty());
+        let log = approval_log(&plugin_dir).await?;
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].request.tool, "delete-file");
+        assert_eq!(log[0].decision.reason.as_deref(), Some("wrong file"));
+
+        assert!(decide_approval(&plugin_dir, "../x", true, "bob", None)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_destructive_fails_closed() {
+        // Without grants, a tool can't change anything, unless it says it does
+        let mut policy = WasiStateTemplate::default();
+        assert!(!is_destructive(&policy, None));
+        assert!(!is_destructive(&policy, Some(false)));
+        assert!(is_destructive(&policy, Some(true)));
+
+        // Network access may be destructive, and a tool can't declare otherwise
+        policy.allowed_hosts.insert("api.example.com".to_string());
+        assert!(is_destructive(&policy, None));
+        assert!(is_destructive(&policy, Some(false)));
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_approval_expires() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let decision =
+            request_approval(dir.path(), &request("ff00"), Duration::from_millis(10)).await?;
+        assert_eq!(decision.outcome, ApprovalOutcome::Expired);
+        assert!(pending_approvals(dir.path()).await?.is_empty());
+        assert_eq!(
+            approval_log(dir.path()).await?[0].decision.outcome,
+            ApprovalOutcome::Expired
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Queries over the approval audit log, so that operators can look up past decisions by
+//! component, tool, time and outcome instead of searching the JSONL file by hand.
+//!
+//! Results come oldest first, in pages. A page that doesn't hold every match carries a cursor,
+//! which is passed back to get the next page. Cursors are positions in the log, so they stay
+//! valid while new decisions are appended to it.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::approval::{audit_log_path, ApprovalOutcome, ApprovalRecord};
+use crate::LifecycleManager;
+
+/// Number of records in a page unless [`AuditQuery::limit`] is set
+pub const DEFAULT_AUDIT_PAGE_SIZE: usize = 50;
+
+/// Largest number of records in a page
+pub const MAX_AUDIT_PAGE_SIZE: usize = 1000;
+
+/// Which records of the approval audit log to return
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditQuery {
+    /// Only records of calls to this component, given by ID or name
+    pub component: Option<String>,
+    /// Only records of calls to this tool
+    pub tool: Option<String>,
+    /// Only records decided at or after this time, in milliseconds since the Unix epoch
+    pub since_ms: Option<u64>,
+    /// Only records decided before this time, in milliseconds since the Unix epoch
+    pub until_ms: Option<u64>,
+    /// Only records with this outcome
+    pub outcome: Option<ApprovalOutcome>,
+    /// Largest number of records to return, [`DEFAULT_AUDIT_PAGE_SIZE`] if unset
+    pub limit: Option<usize>,
+    /// Cursor of the page to return, from [`AuditPage::next_cursor`] of the previous page
+    pub cursor: Option<String>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &ApprovalRecord) -> bool {
+        let request = &record.request;
+        let decided_at = record.decision.decided_at_ms;
+        self.component
+            .as_deref()
+            .is_none_or(|id| crate::component_id::id_matches(id, &request.component_id))
+            && self.tool.as_deref().is_none_or(|tool| request.tool == tool)
+            && self.since_ms.is_none_or(|since| decided_at >= since)
+            && self.until_ms.is_none_or(|until| decided_at < until)
+            && self
+                .outcome
+                .is_none_or(|outcome| record.decision.outcome == outcome)
+    }
+}
+
+/// A page of the records that match an [`AuditQuery`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditPage {
+    /// The matching records, oldest first
+    pub records: Vec<ApprovalRecord>,
+    /// Cursor of the next page, if there are more matching records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Returns the page of the approval audit log in `plugin_dir` that `query` asks for
+pub async fn query_audit_log(plugin_dir: &Path, query: &AuditQuery) -> Result<AuditPage> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_PAGE_SIZE)
+        .clamp(1, MAX_AUDIT_PAGE_SIZE);
+    let start = match &query.cursor {
+        Some(cursor) => cursor
+            .parse::<usize>()
+            .with_context(|| format!("Invalid cursor: {cursor}"))?,
+        None => 0,
+    };
+    let file = match tokio::fs::File::open(audit_log_path(plugin_dir)).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(AuditPage {
+                records: Vec::new(),
+                next_cursor: None,
+            })
+        }
+        Err(e) => return Err(e).context("Failed to read approval audit log"),
+    };
+
+    let mut lines = BufReader::new(file).lines();
+    let mut records = Vec::new();
+    let mut line_number = 0;
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+        if line_number <= start || line.trim().is_empty() {
+            continue;
+        }
+        let record: ApprovalRecord = serde_json::from_str(&line).with_context(|| {
+            format!("Invalid entry on line {line_number} of approval audit log")
+        })?;
+        if !query.matches(&record) {
+            continue;
+        }
+        if records.len() == limit {
+            // The next page starts at the line of this record
+            return Ok(AuditPage {
+                records,
+                next_cursor: Some((line_number - 1).to_string()),
+            });
+        }
+        records.push(record);
+    }
+    Ok(AuditPage {
+        records,
+        next_cursor: None,
+    })
+}
+
+impl LifecycleManager {
+    /// Returns the page of this server's approval audit log that `query` asks for
+    pub async fn query_audit_log(&self, query: &AuditQuery) -> Result<AuditPage> {
+        query_audit_log(&self.plugin_dir, query).await
+    }
+}
+
+/// Parses a point in time for an [`AuditQuery`] into milliseconds since the Unix epoch: an RFC
+/// 3339 timestamp such as `2025-10-01T12:00:00Z`, a number of milliseconds, or a duration before
+/// now such as `30m`, `12h` or `7d`
+pub fn parse_audit_time(time: &str) -> Result<u64> {
+    if let Ok(ms) = time.parse::<u64>() {
+        return Ok(ms);
+    }
+    if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(time) {
+        return u64::try_from(timestamp.timestamp_millis())
+            .with_context(|| format!("Time before 1970: {time}"));
+    }
+    let unit_start = time.len() - time.chars().last().map_or(0, char::len_utf8);
+    let (amount, unit) = time.split_at(unit_start);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!(
+            "Invalid time, expected an RFC 3339 timestamp, milliseconds or a duration such as \
+             12h: {time}"
+        ),
+    };
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("Invalid duration: {time}"))?;
+    let ago = Duration::from_secs(amount.saturating_mul(seconds));
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    Ok(now.saturating_sub(ago).as_millis() as u64)
+}
+
+/// Formats a time of the audit log, in milliseconds since the Unix epoch, as an RFC 3339
+/// timestamp in UTC
+pub fn format_audit_time(ms: u64) -> String {
+    i64::try_from(ms)
+        .ok()
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .map_or_else(
+            || ms.to_string(),
+            |time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::approval::{ApprovalDecision, ApprovalRequest, APPROVALS_DIR};
+
+    fn record(component_id: &str, tool: &str, outcome: ApprovalOutcome, at: u64) -> String {
+        let record = ApprovalRecord {
+            request: ApprovalRequest {
+                id: format!("{at}"),
+                component_id: component_id.to_string(),
+                tool: tool.to_string(),
+                arguments: json!({}),
+                requested_at_ms: at,
+                request_id: None,
+                session_id: None,
+            },
+            decision: ApprovalDecision {
+                outcome,
+                decided_by: Some("ops".to_string()),
+                reason: None,
+                decided_at_ms: at,
+            },
+        };
+        serde_json::to_string(&record).unwrap()
+    }
+
+    async fn write_log(plugin_dir: &Path) -> Result<()> {
+        let log = [
+            record("local/files", "delete", ApprovalOutcome::Approved, 1000),
+            record("local/files", "delete", ApprovalOutcome::Denied, 2000),
+            record("local/db", "drop-table", ApprovalOutcome::Expired, 3000),
+            record("local/files", "move", ApprovalOutcome::Denied, 4000),
+            record("local/files", "delete", ApprovalOutcome::Denied, 5000),
+        ]
+        .join("\n");
+        tokio::fs::create_dir_all(plugin_dir.join(APPROVALS_DIR)).await?;
+        tokio::fs::write(audit_log_path(plugin_dir), log).await?;
+        Ok(())
+    }
+
+    fn times(page: &AuditPage) -> Vec<u64> {
+        page.records
+            .iter()
+            .map(|r| r.decision.decided_at_ms)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_query_filters() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        write_log(tempdir.path()).await?;
+
+        let query = AuditQuery {
+            component: Some("files".to_string()),
+            outcome: Some(ApprovalOutcome::Denied),
+            ..Default::default()
+        };
+        assert_eq!(
+            times(&query_audit_log(tempdir.path(), &query).await?),
+            [2000, 4000, 5000]
+        );
+
+        let query = AuditQuery {
+            tool: Some("delete".to_string()),
+            since_ms: Some(2000),
+            until_ms: Some(5000),
+            ..Default::default()
+        };
+        assert_eq!(
+            times(&query_audit_log(tempdir.path(), &query).await?),
+            [2000]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_pages() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let empty = query_audit_log(tempdir.path(), &AuditQuery::default()).await?;
+        assert!(empty.records.is_empty());
+        write_log(tempdir.path()).await?;
+
+        let mut query = AuditQuery {
+            component: Some("local/files".to_string()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let first = query_audit_log(tempdir.path(), &query).await?;
+        assert_eq!(times(&first), [1000, 2000]);
+        query.cursor = first.next_cursor;
+        let second = query_audit_log(tempdir.path(), &query).await?;
+        assert_eq!(times(&second), [4000, 5000]);
+        assert_eq!(second.next_cursor, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_audit_time() -> Result<()> {
+        assert_eq!(parse_audit_time("1500")?, 1500);
+        assert_eq!(parse_audit_time("2025-10-01T12:00:00Z")?, 1_759_320_000_000);
+        let day_ago = parse_audit_time("1d")?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        assert!((now - day_ago).abs_diff(24 * 60 * 60 * 1000) < 60_000);
+        assert!(parse_audit_time("yesterday").is_err());
+        assert_eq!(format_audit_time(1_759_320_000_000), "2025-10-01T12:00:00Z");
+        Ok(())
+    }
+}
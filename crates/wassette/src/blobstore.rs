@@ -0,0 +1,917 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Blob storage for components through `wasi:blobstore`.
+//!
+//! Tools that produce or consume artifacts too large for JSON arguments and results, such as
+//! archives, images or build outputs, store them as objects in containers. Every component has
+//! its own containers, kept in the backend set in [`BlobstoreConfig`]: the [`BLOBS_DIR`]
+//! directory in the plugin directory unless an S3 bucket or an Azure storage container is
+//! configured. A component may only use the containers that the `blobstore` section of its policy
+//! allows, with the access it allows, and components without that section can't use the
+//! interface at all.
+//!
+//! Containers are recorded as empty marker objects under `containers/<component ID>/` and their
+//! objects are stored under `objects/<component ID>/<container>/`, so that object names can't
+//! collide with markers. The components of a [sandbox group](crate::sandbox_groups) share their
+//! containers, which are kept under `groups/<group>/` instead. Object names are stored as a
+//! single path segment, so they can't escape their container.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use object_store::local::LocalFileSystem;
+use object_store::path::{Path as ObjectPath, PathPart};
+use object_store::{ObjectMeta, ObjectStore, PutPayload};
+use policy::{AccessType, BlobContainerPermission};
+use wasmtime::component::{Linker, Resource};
+use wasmtime_wasi::p2::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::p2::{DynInputStream, DynOutputStream};
+use wasmtime_wasi::ResourceTable;
+
+use crate::{BlobstoreBackend, BlobstoreConfig, WasiState, WassetteWasiState};
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        path: "wit/blobstore",
+        world: "wasi:blobstore/imports",
+        async: true,
+        trappable_imports: true,
+        with: {
+            "wasi:io": wasmtime_wasi::p2::bindings::io,
+            "wasi:blobstore/container/container": super::ContainerHandle,
+            "wasi:blobstore/container/stream-object-names": super::ObjectNames,
+            "wasi:blobstore/types/outgoing-value": super::OutgoingValue,
+            "wasi:blobstore/types/incoming-value": super::IncomingValue,
+        },
+    });
+}
+
+use bindings::wasi::blobstore::types::{ContainerMetadata, ObjectId, ObjectMetadata};
+use bindings::wasi::blobstore::{blobstore, container, types};
+
+/// Directory in the plugin directory that blobs are stored in by default
+pub const BLOBS_DIR: &str = "blobs";
+
+/// Largest blob a component may write unless
+/// [`BlobstoreConfig::max_object_size`](crate::BlobstoreConfig::max_object_size) is set
+pub const DEFAULT_MAX_BLOB_SIZE: u64 = 256 * 1024 * 1024;
+
+/// The `blobstore` policy entry that allows every container
+const ANY_CONTAINER: &str = "*";
+
+/// The result of a `wasi:blobstore` function, whose errors are strings
+type BlobResult<T> = wasmtime::Result<Result<T, String>>;
+
+/// The backend that components' blobs are stored in
+pub(crate) struct Blobs {
+    store: Arc<dyn ObjectStore>,
+    max_object_size: u64,
+}
+
+impl Blobs {
+    /// Opens the configured backend
+    pub(crate) fn open(config: &BlobstoreConfig, plugin_dir: &Path) -> Result<Self> {
+        let store: Arc<dyn ObjectStore> = match &config.backend {
+            BlobstoreBackend::Scratch => {
+                let dir = plugin_dir.join(BLOBS_DIR);
+                std::fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+                Arc::new(LocalFileSystem::new_with_prefix(&dir)?.with_automatic_cleanup(true))
+            }
+            #[cfg(feature = "blobstore-s3")]
+            BlobstoreBackend::S3 { bucket, prefix } => with_prefix(
+                object_store::aws::AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()
+                    .context("Failed to configure the S3 blobstore backend")?,
+                prefix.as_deref(),
+            ),
+            #[cfg(not(feature = "blobstore-s3"))]
+            BlobstoreBackend::S3 { .. } => anyhow::bail!(
+                "The S3 blobstore backend needs wassette to be built with the `blobstore-s3` feature"
+            ),
+            #[cfg(feature = "blobstore-azure")]
+            BlobstoreBackend::Azure {
+                account,
+                container,
+                prefix,
+            } => with_prefix(
+                object_store::azure::MicrosoftAzureBuilder::from_env()
+                    .with_account(account)
+                    .with_container_name(container)
+                    .build()
+                    .context("Failed to configure the Azure blobstore backend")?,
+                prefix.as_deref(),
+            ),
+            #[cfg(not(feature = "blobstore-azure"))]
+            BlobstoreBackend::Azure { .. } => anyhow::bail!(
+                "The Azure blobstore backend needs wassette to be built with the `blobstore-azure` feature"
+            ),
+        };
+        Ok(Self {
+            store,
+            max_object_size: config.max_object_size.unwrap_or(DEFAULT_MAX_BLOB_SIZE),
+        })
+    }
+
+    /// Builds the `wasi:blobstore` context of a call to a component. `grants` lists the
+    /// containers the component's policy allows, and is `None` if it doesn't allow blob storage.
+    pub(crate) fn ctx_for(
+        &self,
+        component_id: &str,
+        grants: Option<&[BlobContainerPermission]>,
+    ) -> BlobstoreCtx {
+        self.ctx(BlobOwner::Component(component_id.to_string()), grants)
+    }
+
+    /// Builds the `wasi:blobstore` context of a call to a component of a sandbox group, which
+    /// uses the containers of the group
+    pub(crate) fn ctx_for_group(
+        &self,
+        group: &str,
+        grants: Option<&[BlobContainerPermission]>,
+    ) -> BlobstoreCtx {
+        self.ctx(BlobOwner::Group(group.to_string()), grants)
+    }
+
+    /// Deletes the containers of a component and the objects in them. The containers of its
+    /// sandbox group are shared, so they are kept.
+    pub(crate) async fn remove_component(&self, component_id: &str) -> Result<()> {
+        let owner = BlobOwner::Component(component_id.to_string());
+        for kind in ["objects", "containers"] {
+            delete_all(self.store.as_ref(), &owner.path(kind, &[]))
+                .await
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("Failed to delete the blobs of {component_id}"))?;
+        }
+        Ok(())
+    }
+
+    fn ctx(&self, owner: BlobOwner, grants: Option<&[BlobContainerPermission]>) -> BlobstoreCtx {
+        match grants {
+            Some(grants) => BlobstoreCtx {
+                store: Some(self.store.clone()),
+                owner,
+                grants: grants.to_vec(),
+                max_object_size: self.max_object_size,
+            },
+            None => BlobstoreCtx::default(),
+        }
+    }
+}
+
+#[cfg(any(feature = "blobstore-s3", feature = "blobstore-azure"))]
+fn with_prefix(store: impl ObjectStore, prefix: Option<&str>) -> Arc<dyn ObjectStore> {
+    match prefix {
+        Some(prefix) => Arc::new(object_store::prefix::PrefixStore::new(store, prefix)),
+        None => Arc::new(store),
+    }
+}
+
+/// The blob storage a call may use
+#[derive(Default)]
+pub(crate) struct BlobstoreCtx {
+    /// The backend, or `None` if the component's policy doesn't allow blob storage
+    store: Option<Arc<dyn ObjectStore>>,
+    owner: BlobOwner,
+    grants: Vec<BlobContainerPermission>,
+    max_object_size: u64,
+}
+
+/// Whose containers a call uses
+#[derive(Debug, Clone)]
+enum BlobOwner {
+    Component(String),
+    Group(String),
+}
+
+impl Default for BlobOwner {
+    fn default() -> Self {
+        Self::Component(String::new())
+    }
+}
+
+impl BlobOwner {
+    /// Returns the path of `parts` among the markers or objects, as `kind` says, of the owner
+    fn path(&self, kind: &str, parts: &[&str]) -> ObjectPath {
+        let owner: Vec<&str> = match self {
+            Self::Component(component_id) => std::iter::once(kind)
+                .chain(component_id.split('/'))
+                .collect(),
+            Self::Group(group) => vec!["groups", group, kind],
+        };
+        owner
+            .into_iter()
+            .chain(parts.iter().copied())
+            .map(PathPart::from)
+            .collect()
+    }
+}
+
+/// A container a component opened
+pub struct ContainerHandle {
+    name: String,
+}
+
+/// The names of the objects of a container, as they are read by the component
+pub struct ObjectNames {
+    names: VecDeque<String>,
+}
+
+/// A blob that a component writes
+pub struct OutgoingValue {
+    body: MemoryOutputPipe,
+    body_taken: bool,
+}
+
+/// A blob, or part of one, that a component reads
+pub struct IncomingValue {
+    data: Bytes,
+}
+
+impl BlobstoreCtx {
+    /// Returns the backend if the policy allows `access` to the container, or any access if
+    /// `access` is `None`
+    fn check(
+        &self,
+        container: &str,
+        access: Option<AccessType>,
+    ) -> Result<&Arc<dyn ObjectStore>, String> {
+        let Some(store) = &self.store else {
+            return Err("The component's policy doesn't allow wasi:blobstore".to_string());
+        };
+        if !is_valid_container_name(container) {
+            return Err(format!("Invalid container name '{container}'"));
+        }
+        let granted = self.grants.iter().any(|grant| {
+            (grant.container == ANY_CONTAINER || grant.container == container)
+                && access
+                    .as_ref()
+                    .is_none_or(|access| grant.access.contains(access))
+        });
+        if !granted {
+            let access = match access {
+                Some(AccessType::Read) => "Read access",
+                Some(AccessType::Write) => "Write access",
+                None => "Access",
+            };
+            return Err(format!(
+                "{access} to container '{container}' is not allowed by the component's policy"
+            ));
+        }
+        Ok(store)
+    }
+
+    fn marker_path(&self, container: &str) -> ObjectPath {
+        self.owner.path("containers", &[container])
+    }
+
+    fn objects_path(&self, container: &str) -> ObjectPath {
+        self.owner.path("objects", &[container])
+    }
+
+    fn object_path(&self, container: &str, object: &str) -> Result<ObjectPath, String> {
+        if object.is_empty() {
+            return Err("Object names can't be empty".to_string());
+        }
+        Ok(self.owner.path("objects", &[container, object]))
+    }
+
+    async fn ensure_container(
+        &self,
+        store: &dyn ObjectStore,
+        container: &str,
+    ) -> Result<(), String> {
+        if exists(store, &self.marker_path(container)).await? {
+            Ok(())
+        } else {
+            Err(format!("Container '{container}' doesn't exist"))
+        }
+    }
+
+    async fn create_container(&self, name: &str) -> Result<(), String> {
+        let store = self.check(name, Some(AccessType::Write))?;
+        let marker = self.marker_path(name);
+        if exists(store.as_ref(), &marker).await? {
+            return Err(format!("Container '{name}' already exists"));
+        }
+        store
+            .put(&marker, PutPayload::new())
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    async fn get_container(&self, name: &str) -> Result<(), String> {
+        let store = self.check(name, None)?;
+        self.ensure_container(store.as_ref(), name).await
+    }
+
+    async fn container_exists(&self, name: &str) -> Result<bool, String> {
+        let store = self.check(name, None)?;
+        exists(store.as_ref(), &self.marker_path(name)).await
+    }
+
+    async fn delete_container(&self, name: &str) -> Result<(), String> {
+        let store = self.check(name, Some(AccessType::Write))?;
+        delete_all(store.as_ref(), &self.objects_path(name)).await?;
+        match store.delete(&self.marker_path(name)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    async fn container_info(&self, name: &str) -> Result<ContainerMetadata, String> {
+        let store = self.check(name, None)?;
+        match store.head(&self.marker_path(name)).await {
+            Ok(meta) => Ok(ContainerMetadata {
+                name: name.to_string(),
+                created_at: timestamp(&meta),
+            }),
+            Err(object_store::Error::NotFound { .. }) => {
+                Err(format!("Container '{name}' doesn't exist"))
+            }
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Reads the bytes from `start` to `end` of an object, both inclusive
+    async fn get_data(
+        &self,
+        container: &str,
+        name: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<Bytes, String> {
+        let store = self.check(container, Some(AccessType::Read))?;
+        let path = self.object_path(container, name)?;
+        let size = head(store.as_ref(), &path, container, name).await?.size;
+        let end = end.saturating_add(1).min(size);
+        if start >= end {
+            return Ok(Bytes::new());
+        }
+        if end - start > self.max_object_size {
+            return Err(format!(
+                "Reads are limited to {} bytes at a time",
+                self.max_object_size
+            ));
+        }
+        store
+            .get_range(&path, start..end)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn write_data(&self, container: &str, name: &str, data: Bytes) -> Result<(), String> {
+        let store = self.check(container, Some(AccessType::Write))?;
+        let path = self.object_path(container, name)?;
+        self.ensure_container(store.as_ref(), container).await?;
+        store
+            .put(&path, PutPayload::from(data))
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    async fn list_objects(&self, container: &str) -> Result<VecDeque<String>, String> {
+        let store = self.check(container, Some(AccessType::Read))?;
+        self.ensure_container(store.as_ref(), container).await?;
+        store
+            .list(Some(&self.objects_path(container)))
+            .map_ok(|meta| object_name(&meta))
+            .try_collect()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn delete_objects(&self, container: &str, names: &[String]) -> Result<(), String> {
+        let store = self.check(container, Some(AccessType::Write))?;
+        for name in names {
+            match store.delete(&self.object_path(container, name)?).await {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn has_object(&self, container: &str, name: &str) -> Result<bool, String> {
+        let store = self.check(container, Some(AccessType::Read))?;
+        exists(store.as_ref(), &self.object_path(container, name)?).await
+    }
+
+    async fn object_info(&self, container: &str, name: &str) -> Result<ObjectMetadata, String> {
+        let store = self.check(container, Some(AccessType::Read))?;
+        let meta = head(
+            store.as_ref(),
+            &self.object_path(container, name)?,
+            container,
+            name,
+        )
+        .await?;
+        Ok(ObjectMetadata {
+            name: name.to_string(),
+            container: container.to_string(),
+            created_at: timestamp(&meta),
+            size: meta.size,
+        })
+    }
+
+    async fn clear(&self, container: &str) -> Result<(), String> {
+        let store = self.check(container, Some(AccessType::Write))?;
+        self.ensure_container(store.as_ref(), container).await?;
+        delete_all(store.as_ref(), &self.objects_path(container)).await
+    }
+
+    /// Copies an object, and deletes the source if `remove_source` is set
+    async fn copy_object(
+        &self,
+        src: &ObjectId,
+        dest: &ObjectId,
+        remove_source: bool,
+    ) -> Result<(), String> {
+        let source_access = if remove_source {
+            AccessType::Write
+        } else {
+            AccessType::Read
+        };
+        let store = self.check(&src.container, Some(source_access))?;
+        self.check(&dest.container, Some(AccessType::Write))?;
+        self.ensure_container(store.as_ref(), &dest.container)
+            .await?;
+        let from = self.object_path(&src.container, &src.object)?;
+        let to = self.object_path(&dest.container, &dest.object)?;
+        let result = if remove_source {
+            store.rename(&from, &to).await
+        } else {
+            store.copy(&from, &to).await
+        };
+        result.map_err(|e| match e {
+            object_store::Error::NotFound { .. } => format!(
+                "Object '{}' doesn't exist in container '{}'",
+                src.object, src.container
+            ),
+            e => e.to_string(),
+        })
+    }
+}
+
+/// Returns whether a name can be used for a container, which is what the policy accepts
+fn is_valid_container_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+async fn exists(store: &dyn ObjectStore, path: &ObjectPath) -> Result<bool, String> {
+    match store.head(path).await {
+        Ok(_) => Ok(true),
+        Err(object_store::Error::NotFound { .. }) => Ok(false),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+async fn head(
+    store: &dyn ObjectStore,
+    path: &ObjectPath,
+    container: &str,
+    name: &str,
+) -> Result<ObjectMeta, String> {
+    store.head(path).await.map_err(|e| match e {
+        object_store::Error::NotFound { .. } => {
+            format!("Object '{name}' doesn't exist in container '{container}'")
+        }
+        e => e.to_string(),
+    })
+}
+
+async fn delete_all(store: &dyn ObjectStore, prefix: &ObjectPath) -> Result<(), String> {
+    let locations = store
+        .list(Some(prefix))
+        .map_ok(|meta| meta.location)
+        .boxed();
+    store
+        .delete_stream(locations)
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Returns the name of an object, which is the percent-encoded last segment of its path
+fn object_name(meta: &ObjectMeta) -> String {
+    let encoded = meta.location.filename().unwrap_or_default();
+    percent_encoding::percent_decode_str(encoded)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+fn timestamp(meta: &ObjectMeta) -> u64 {
+    meta.last_modified.timestamp().try_into().unwrap_or(0)
+}
+
+/// The `wasi:blobstore` host of a call
+pub(crate) struct WasiBlobstore<'a> {
+    ctx: &'a BlobstoreCtx,
+    table: &'a mut ResourceTable,
+}
+
+impl WasiBlobstore<'_> {
+    fn container_name(&self, container: &Resource<ContainerHandle>) -> wasmtime::Result<String> {
+        Ok(self.table.get(container)?.name.clone())
+    }
+
+    fn open_container(
+        &mut self,
+        name: String,
+        result: Result<(), String>,
+    ) -> BlobResult<Resource<ContainerHandle>> {
+        match result {
+            Ok(()) => Ok(Ok(self.table.push(ContainerHandle { name })?)),
+            Err(e) => Ok(Err(e)),
+        }
+    }
+}
+
+impl blobstore::Host for WasiBlobstore<'_> {
+    async fn create_container(&mut self, name: String) -> BlobResult<Resource<ContainerHandle>> {
+        let result = self.ctx.create_container(&name).await;
+        self.open_container(name, result)
+    }
+
+    async fn get_container(&mut self, name: String) -> BlobResult<Resource<ContainerHandle>> {
+        let result = self.ctx.get_container(&name).await;
+        self.open_container(name, result)
+    }
+
+    async fn delete_container(&mut self, name: String) -> BlobResult<()> {
+        Ok(self.ctx.delete_container(&name).await)
+    }
+
+    async fn container_exists(&mut self, name: String) -> BlobResult<bool> {
+        Ok(self.ctx.container_exists(&name).await)
+    }
+
+    async fn copy_object(&mut self, src: ObjectId, dest: ObjectId) -> BlobResult<()> {
+        Ok(self.ctx.copy_object(&src, &dest, false).await)
+    }
+
+    async fn move_object(&mut self, src: ObjectId, dest: ObjectId) -> BlobResult<()> {
+        Ok(self.ctx.copy_object(&src, &dest, true).await)
+    }
+}
+
+impl container::Host for WasiBlobstore<'_> {}
+
+impl container::HostContainer for WasiBlobstore<'_> {
+    async fn name(&mut self, container: Resource<ContainerHandle>) -> BlobResult<String> {
+        Ok(Ok(self.container_name(&container)?))
+    }
+
+    async fn info(
+        &mut self,
+        container: Resource<ContainerHandle>,
+    ) -> BlobResult<ContainerMetadata> {
+        let container = self.container_name(&container)?;
+        Ok(self.ctx.container_info(&container).await)
+    }
+
+    async fn get_data(
+        &mut self,
+        container: Resource<ContainerHandle>,
+        name: String,
+        start: u64,
+        end: u64,
+    ) -> BlobResult<Resource<IncomingValue>> {
+        let container = self.container_name(&container)?;
+        match self.ctx.get_data(&container, &name, start, end).await {
+            Ok(data) => Ok(Ok(self.table.push(IncomingValue { data })?)),
+            Err(e) => Ok(Err(e)),
+        }
+    }
+
+    async fn write_data(
+        &mut self,
+        container: Resource<ContainerHandle>,
+        name: String,
+        data: Resource<OutgoingValue>,
+    ) -> BlobResult<()> {
+        let container = self.container_name(&container)?;
+        let data = self.table.get(&data)?.body.contents();
+        Ok(self.ctx.write_data(&container, &name, data).await)
+    }
+
+    async fn list_objects(
+        &mut self,
+        container: Resource<ContainerHandle>,
+    ) -> BlobResult<Resource<ObjectNames>> {
+        let container = self.container_name(&container)?;
+        match self.ctx.list_objects(&container).await {
+            Ok(names) => Ok(Ok(self.table.push(ObjectNames { names })?)),
+            Err(e) => Ok(Err(e)),
+        }
+    }
+
+    async fn delete_object(
+        &mut self,
+        container: Resource<ContainerHandle>,
+        name: String,
+    ) -> BlobResult<()> {
+        let container = self.container_name(&container)?;
+        Ok(self.ctx.delete_objects(&container, &[name]).await)
+    }
+
+    async fn delete_objects(
+        &mut self,
+        container: Resource<ContainerHandle>,
+        names: Vec<String>,
+    ) -> BlobResult<()> {
+        let container = self.container_name(&container)?;
+        Ok(self.ctx.delete_objects(&container, &names).await)
+    }
+
+    async fn has_object(
+        &mut self,
+        container: Resource<ContainerHandle>,
+        name: String,
+    ) -> BlobResult<bool> {
+        let container = self.container_name(&container)?;
+        Ok(self.ctx.has_object(&container, &name).await)
+    }
+
+    async fn object_info(
+        &mut self,
+        container: Resource<ContainerHandle>,
+        name: String,
+    ) -> BlobResult<ObjectMetadata> {
+        let container = self.container_name(&container)?;
+        Ok(self.ctx.object_info(&container, &name).await)
+    }
+
+    async fn clear(&mut self, container: Resource<ContainerHandle>) -> BlobResult<()> {
+        let container = self.container_name(&container)?;
+        Ok(self.ctx.clear(&container).await)
+    }
+
+    async fn drop(&mut self, container: Resource<ContainerHandle>) -> wasmtime::Result<()> {
+        self.table.delete(container)?;
+        Ok(())
+    }
+}
+
+impl container::HostStreamObjectNames for WasiBlobstore<'_> {
+    async fn read_stream_object_names(
+        &mut self,
+        stream: Resource<ObjectNames>,
+        len: u64,
+    ) -> BlobResult<(Vec<String>, bool)> {
+        let names = &mut self.table.get_mut(&stream)?.names;
+        let count = usize::try_from(len).unwrap_or(usize::MAX).min(names.len());
+        let read = names.drain(..count).collect();
+        Ok(Ok((read, names.is_empty())))
+    }
+
+    async fn skip_stream_object_names(
+        &mut self,
+        stream: Resource<ObjectNames>,
+        num: u64,
+    ) -> BlobResult<(u64, bool)> {
+        let names = &mut self.table.get_mut(&stream)?.names;
+        let count = usize::try_from(num).unwrap_or(usize::MAX).min(names.len());
+        names.drain(..count);
+        Ok(Ok((count as u64, names.is_empty())))
+    }
+
+    async fn drop(&mut self, stream: Resource<ObjectNames>) -> wasmtime::Result<()> {
+        self.table.delete(stream)?;
+        Ok(())
+    }
+}
+
+impl types::Host for WasiBlobstore<'_> {}
+
+impl types::HostOutgoingValue for WasiBlobstore<'_> {
+    async fn new_outgoing_value(&mut self) -> wasmtime::Result<Resource<OutgoingValue>> {
+        let capacity = usize::try_from(self.ctx.max_object_size).unwrap_or(usize::MAX);
+        Ok(self.table.push(OutgoingValue {
+            body: MemoryOutputPipe::new(capacity),
+            body_taken: false,
+        })?)
+    }
+
+    async fn outgoing_value_write_body(
+        &mut self,
+        value: Resource<OutgoingValue>,
+    ) -> wasmtime::Result<Result<Resource<DynOutputStream>, ()>> {
+        let value = self.table.get_mut(&value)?;
+        if value.body_taken {
+            return Ok(Err(()));
+        }
+        value.body_taken = true;
+        // Writing more than the largest blob fails the write with an error
+        let body: DynOutputStream = Box::new(value.body.clone());
+        Ok(Ok(self.table.push(body)?))
+    }
+
+    async fn finish(&mut self, value: Resource<OutgoingValue>) -> BlobResult<()> {
+        self.table.delete(value)?;
+        Ok(Ok(()))
+    }
+
+    async fn drop(&mut self, value: Resource<OutgoingValue>) -> wasmtime::Result<()> {
+        self.table.delete(value)?;
+        Ok(())
+    }
+}
+
+impl types::HostIncomingValue for WasiBlobstore<'_> {
+    async fn incoming_value_consume_sync(
+        &mut self,
+        value: Resource<IncomingValue>,
+    ) -> BlobResult<Vec<u8>> {
+        let value = self.table.delete(value)?;
+        Ok(Ok(value.data.to_vec()))
+    }
+
+    async fn incoming_value_consume_async(
+        &mut self,
+        value: Resource<IncomingValue>,
+    ) -> BlobResult<Resource<DynInputStream>> {
+        let value = self.table.delete(value)?;
+        let body: DynInputStream = Box::new(MemoryInputPipe::new(value.data));
+        Ok(Ok(self.table.push(body)?))
+    }
+
+    async fn size(&mut self, value: Resource<IncomingValue>) -> wasmtime::Result<u64> {
+        Ok(self.table.get(&value)?.data.len() as u64)
+    }
+
+    async fn drop(&mut self, value: Resource<IncomingValue>) -> wasmtime::Result<()> {
+        self.table.delete(value)?;
+        Ok(())
+    }
+}
+
+fn blobstore_view(state: &mut WassetteWasiState<WasiState>) -> WasiBlobstore<'_> {
+    let state = &mut state.inner;
+    WasiBlobstore {
+        ctx: &state.blobstore,
+        table: &mut state.table,
+    }
+}
+
+/// Adds the `wasi:blobstore` interfaces to the linker
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    types::add_to_linker_get_host(linker, blobstore_view)?;
+    container::add_to_linker_get_host(linker, blobstore_view)?;
+    blobstore::add_to_linker_get_host(linker, blobstore_view)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx(dir: &Path, grants: &[BlobContainerPermission]) -> Result<BlobstoreCtx> {
+        let blobs = Blobs::open(&BlobstoreConfig::default(), dir)?;
+        Ok(blobs.ctx_for("local/builder", Some(grants)))
+    }
+
+    fn grant(container: &str, access: &[AccessType]) -> BlobContainerPermission {
+        BlobContainerPermission {
+            container: container.to_string(),
+            access: access.to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scratch_blobstore() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ctx = ctx(
+            dir.path(),
+            &[grant("artifacts", &[AccessType::Read, AccessType::Write])],
+        )?;
+        ctx.create_container("artifacts").await.unwrap();
+        assert!(ctx.create_container("artifacts").await.is_err());
+        ctx.write_data(
+            "artifacts",
+            "out/app.tar",
+            Bytes::from_static(b"0123456789"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            ctx.list_objects("artifacts").await.unwrap(),
+            ["out/app.tar"]
+        );
+        assert_eq!(
+            ctx.get_data("artifacts", "out/app.tar", 2, 4)
+                .await
+                .unwrap(),
+            Bytes::from_static(b"234")
+        );
+        assert_eq!(
+            ctx.get_data("artifacts", "out/app.tar", 8, u64::MAX)
+                .await
+                .unwrap(),
+            Bytes::from_static(b"89")
+        );
+        assert_eq!(
+            ctx.object_info("artifacts", "out/app.tar")
+                .await
+                .unwrap()
+                .size,
+            10
+        );
+
+        // Object names stay in their container
+        ctx.write_data("artifacts", "../../escape", Bytes::from_static(b"x"))
+            .await
+            .unwrap();
+        assert!(ctx.has_object("artifacts", "../../escape").await.unwrap());
+        assert!(dir.path().join(BLOBS_DIR).join("objects").exists());
+        assert!(!dir.path().join("escape").exists());
+
+        ctx.delete_container("artifacts").await.unwrap();
+        assert!(!ctx.container_exists("artifacts").await.unwrap());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blobstore_policy() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let writer = ctx(dir.path(), &[grant("*", &[AccessType::Write])])?;
+        writer.create_container("inbox").await.unwrap();
+        writer
+            .write_data("inbox", "report.pdf", Bytes::from_static(b"%PDF"))
+            .await
+            .unwrap();
+        let error = writer
+            .get_data("inbox", "report.pdf", 0, 3)
+            .await
+            .unwrap_err();
+        assert!(error.contains("Read access to container 'inbox'"));
+
+        let reader = ctx(dir.path(), &[grant("inbox", &[AccessType::Read])])?;
+        assert!(reader.has_object("inbox", "report.pdf").await.unwrap());
+        assert!(reader.delete_container("inbox").await.is_err());
+        assert!(reader.container_exists("outbox").await.is_err());
+
+        // Containers belong to the component that created them
+        let blobs = Blobs::open(&BlobstoreConfig::default(), dir.path())?;
+        let other = blobs.ctx_for("local/other", Some(&[grant("*", &[AccessType::Read])]));
+        assert!(!other.container_exists("inbox").await.unwrap());
+
+        let denied = blobs.ctx_for("local/builder", None);
+        assert!(denied.container_exists("inbox").await.is_err());
+
+        // The components of a sandbox group share their containers, and only them
+        let all = [grant("*", &[AccessType::Read, AccessType::Write])];
+        let grouped = blobs.ctx_for_group("dev-tools", Some(&all));
+        assert!(!grouped.container_exists("inbox").await.unwrap());
+        grouped.create_container("workspace").await.unwrap();
+        let peer = blobs.ctx_for_group("dev-tools", Some(&all));
+        assert!(peer.container_exists("workspace").await.unwrap());
+        let stranger = blobs.ctx_for_group("ops", Some(&all));
+        assert!(!stranger.container_exists("workspace").await.unwrap());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_component_blobs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let all = [grant("*", &[AccessType::Read, AccessType::Write])];
+        let blobs = Blobs::open(&BlobstoreConfig::default(), dir.path())?;
+        for component_id in ["local/builder", "local/builder-2"] {
+            let ctx = blobs.ctx_for(component_id, Some(&all));
+            ctx.create_container("artifacts").await.unwrap();
+            ctx.write_data("artifacts", "app.tar", Bytes::from_static(b"tar"))
+                .await
+                .unwrap();
+        }
+        let grouped = blobs.ctx_for_group("dev-tools", Some(&all));
+        grouped.create_container("workspace").await.unwrap();
+
+        blobs.remove_component("local/builder").await?;
+        let removed = blobs.ctx_for("local/builder", Some(&all));
+        assert!(!removed.container_exists("artifacts").await.unwrap());
+        assert!(!dir
+            .path()
+            .join(BLOBS_DIR)
+            .join("objects/local/builder")
+            .exists());
+        let other = blobs.ctx_for("local/builder-2", Some(&all));
+        assert!(other.has_object("artifacts", "app.tar").await.unwrap());
+        assert!(grouped.container_exists("workspace").await.unwrap());
+        Ok(())
+    }
+}
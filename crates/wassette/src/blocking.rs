@@ -0,0 +1,160 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! A synchronous facade over [`crate::LifecycleManager`] for embedders that don't use async Rust.
+//!
+//! The facade owns a Tokio runtime and blocks the calling thread until each operation completes,
+//! like `reqwest::blocking`. It must not be used from within an async runtime, where blocking
+//! would panic; use [`crate::LifecycleManager`] there instead.
+//!
+//! ```no_run
+//! # fn run() -> anyhow::Result<()> {
+//! let manager = wassette::blocking::LifecycleManager::new("./plugins")?;
+//! let (id, _) = manager.load_component("oci://ghcr.io/yoshuawuyts/time:latest")?;
+//! let result = manager.execute_component_call(&id, "get-current-time", "{}")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::{
+    CallOptions, CallOutput, LifecycleConfig, LoadResult, ToolSchema, ToolsDocument, UnloadResult,
+};
+
+/// A lifecycle manager whose operations block until they complete. Clones share the same
+/// components and runtime.
+#[derive(Clone)]
+pub struct LifecycleManager {
+    inner: crate::LifecycleManager,
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl LifecycleManager {
+    /// Creates a lifecycle manager for the given plugin directory
+    pub fn new(plugin_dir: impl AsRef<Path>) -> Result<Self> {
+        Self::new_with_config(plugin_dir, LifecycleConfig::default())
+    }
+
+    /// Creates a lifecycle manager with the given [`LifecycleConfig`]
+    pub fn new_with_config(plugin_dir: impl AsRef<Path>, config: LifecycleConfig) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("wassette-blocking")
+            .build()
+            .context("Failed to start the runtime of the blocking lifecycle manager")?;
+        let inner =
+            runtime.block_on(crate::LifecycleManager::new_with_config(plugin_dir, config))?;
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Returns the async lifecycle manager behind this facade
+    pub fn inner(&self) -> &crate::LifecycleManager {
+        &self.inner
+    }
+
+    /// See [`crate::LifecycleManager::load_component`]
+    pub fn load_component(&self, uri: &str) -> Result<(String, LoadResult)> {
+        self.runtime.block_on(self.inner.load_component(uri))
+    }
+
+    /// See [`crate::LifecycleManager::unload_component`]
+    pub fn unload_component(&self, id: &str) -> Result<UnloadResult> {
+        self.runtime.block_on(self.inner.unload_component(id))
+    }
+
+    /// See [`crate::LifecycleManager::list_components`]
+    pub fn list_components(&self) -> Vec<String> {
+        self.runtime.block_on(self.inner.list_components())
+    }
+
+    /// See [`crate::LifecycleManager::list_tools`]
+    pub fn list_tools(&self) -> Vec<ToolSchema> {
+        self.runtime.block_on(self.inner.list_tools())
+    }
+
+    /// See [`crate::LifecycleManager::get_tool_schema`]
+    pub fn get_tool_schema(&self, tool_name: &str) -> Option<ToolSchema> {
+        self.runtime.block_on(self.inner.get_tool_schema(tool_name))
+    }
+
+    /// See [`crate::LifecycleManager::get_component_schema`]
+    pub fn get_component_schema(&self, component_id: &str) -> Option<ToolsDocument> {
+        self.runtime
+            .block_on(self.inner.get_component_schema(component_id))
+    }
+
+    /// See [`crate::LifecycleManager::get_component_id_for_tool`]
+    pub fn get_component_id_for_tool(&self, tool_name: &str) -> Result<String> {
+        self.runtime
+            .block_on(self.inner.get_component_id_for_tool(tool_name))
+    }
+
+    /// See [`crate::LifecycleManager::execute_component_call`]
+    pub fn execute_component_call(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+    ) -> Result<String> {
+        self.runtime.block_on(self.inner.execute_component_call(
+            component_id,
+            function_name,
+            parameters,
+        ))
+    }
+
+    /// See [`crate::LifecycleManager::execute_component_call_with_options`]
+    pub fn execute_component_call_with_options(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        options: CallOptions,
+    ) -> Result<CallOutput> {
+        self.runtime
+            .block_on(self.inner.execute_component_call_with_options(
+                component_id,
+                function_name,
+                parameters,
+                options,
+            ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn test_blocking_load_and_list() -> Result<()> {
+        let component_path = tokio::runtime::Runtime::new()?.block_on(build_example_component())?;
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(&tempdir)?;
+
+        let (id, result) =
+            manager.load_component(&format!("file://{}", component_path.display()))?;
+        assert_eq!(id, TEST_COMPONENT_ID);
+        assert_eq!(result, LoadResult::New);
+        assert_eq!(
+            manager.list_components(),
+            vec![TEST_COMPONENT_ID.to_string()]
+        );
+        assert!(!manager.list_tools().is_empty());
+        assert_eq!(
+            manager.get_component_id_for_tool("fetch")?,
+            TEST_COMPONENT_ID
+        );
+
+        manager.unload_component(&id)?;
+        assert!(manager.get_component_id_for_tool("fetch").is_err());
+        Ok(())
+    }
+}
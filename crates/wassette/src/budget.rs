@@ -0,0 +1,255 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Per-component usage budgets. Counters are persisted to the plugin directory so that limits
+//! keep applying across restarts.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat};
+use policy::Budget;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::LifecycleManager;
+
+/// Name of the file in the plugin directory that budget usage is persisted to
+pub const BUDGET_USAGE_FILE: &str = "budget-usage.json";
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+/// Usage of a component's budget in the current hourly and daily windows
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetUsage {
+    /// Start of the current hourly window, in seconds since the Unix epoch
+    pub hour_start: u64,
+    /// Number of calls made in the current hourly window
+    pub calls_this_hour: u64,
+    /// Start of the current daily window (midnight UTC), in seconds since the Unix epoch
+    pub day_start: u64,
+    /// Number of calls made in the current daily window
+    pub calls_today: u64,
+    /// Combined wall-clock time of the calls made in the current daily window, in milliseconds
+    pub wall_clock_ms_today: u64,
+}
+
+impl BudgetUsage {
+    /// Resets the counters of windows that have ended by `now`
+    fn roll(&mut self, now: u64) {
+        let hour_start = now - now % HOUR;
+        if self.hour_start != hour_start {
+            self.hour_start = hour_start;
+            self.calls_this_hour = 0;
+        }
+        let day_start = now - now % DAY;
+        if self.day_start != day_start {
+            self.day_start = day_start;
+            self.calls_today = 0;
+            self.wall_clock_ms_today = 0;
+        }
+    }
+
+    /// Returns the exhausted limit and the time it resets at, checking daily limits first since
+    /// they reset last
+    fn exceeded(&self, budget: &Budget) -> Option<(String, u64)> {
+        let day_end = self.day_start + DAY;
+        if let Some(max) = budget.max_calls_per_day {
+            if self.calls_today >= max {
+                return Some((format!("{max} calls per day"), day_end));
+            }
+        }
+        if let Some(max) = budget.max_wall_clock_seconds_per_day {
+            if self.wall_clock_ms_today >= max.saturating_mul(1000) {
+                return Some((format!("{max} seconds of execution time per day"), day_end));
+            }
+        }
+        if let Some(max) = budget.max_calls_per_hour {
+            if self.calls_this_hour >= max {
+                return Some((format!("{max} calls per hour"), self.hour_start + HOUR));
+            }
+        }
+        None
+    }
+}
+
+pub(crate) struct BudgetStore {
+    path: PathBuf,
+    usage: BTreeMap<String, BudgetUsage>,
+}
+
+impl BudgetStore {
+    pub(crate) async fn open(plugin_dir: &Path) -> Self {
+        let path = plugin_dir.join(BUDGET_USAGE_FILE);
+        let usage = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!(error = %e, "Discarding unreadable budget usage");
+                BTreeMap::new()
+            }),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!(error = %e, "Failed to read budget usage");
+                }
+                BTreeMap::new()
+            }
+        };
+        Self { path, usage }
+    }
+
+    /// Counts a call against the component's budget, failing if the budget is exhausted
+    fn reserve(&mut self, component_id: &str, budget: &Budget, now: u64) -> Result<()> {
+        let usage = self.usage.entry(component_id.to_string()).or_default();
+        usage.roll(now);
+        if let Some((limit, resets_at)) = usage.exceeded(budget) {
+            bail!(
+                "Budget exceeded for component '{}': the limit of {} has been reached. Resets at {} ({})",
+                component_id,
+                limit,
+                format_timestamp(resets_at),
+                format_wait(resets_at.saturating_sub(now))
+            );
+        }
+        usage.calls_this_hour += 1;
+        usage.calls_today += 1;
+        Ok(())
+    }
+
+    fn record_duration(&mut self, component_id: &str, duration: Duration, now: u64) {
+        let usage = self.usage.entry(component_id.to_string()).or_default();
+        usage.roll(now);
+        usage.wall_clock_ms_today += duration.as_millis() as u64;
+    }
+
+    async fn persist(&self) -> Result<()> {
+        debug!(path = %self.path.display(), "Persisting budget usage");
+        let data = serde_json::to_vec_pretty(&self.usage)?;
+        tokio::fs::write(&self.path, data)
+            .await
+            .with_context(|| format!("Failed to write budget usage: {}", self.path.display()))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn format_timestamp(secs: u64) -> String {
+    DateTime::from_timestamp(secs as i64, 0)
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_else(|| secs.to_string())
+}
+
+fn format_wait(secs: u64) -> String {
+    let minutes = secs.div_ceil(60);
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("in {m}m"),
+        (h, 0) => format!("in {h}h"),
+        (h, m) => format!("in {h}h {m}m"),
+    }
+}
+
+impl LifecycleManager {
+    async fn component_budget(&self, component_id: &str) -> Option<Budget> {
+        self.policy_registry
+            .read()
+            .await
+            .component_policies
+            .get(component_id)
+            .and_then(|template| template.budget.clone())
+    }
+
+    /// Counts a call against the component's budget, if its policy has one. Returns an error
+    /// saying when the budget resets if it is exhausted.
+    pub(crate) async fn reserve_budget(&self, component_id: &str) -> Result<()> {
+        let Some(budget) = self.component_budget(component_id).await else {
+            return Ok(());
+        };
+        let mut store = self.budgets.write().await;
+        store.reserve(component_id, &budget, unix_now())?;
+        if let Err(e) = store.persist().await {
+            warn!(error = %e, "Failed to persist budget usage");
+        }
+        Ok(())
+    }
+
+    /// Returns the component's usage of its budget in the current windows, or `None` if no call
+    /// has been counted against a budget yet
+    pub async fn get_budget_usage(&self, component_id: &str) -> Option<BudgetUsage> {
+        let mut usage = self.budgets.read().await.usage.get(component_id)?.clone();
+        usage.roll(unix_now());
+        Some(usage)
+    }
+
+    /// Adds the wall-clock time of a call to the component's budget usage
+    pub(crate) async fn record_budget_time(&self, component_id: &str, duration: Duration) {
+        if self.component_budget(component_id).await.is_none() {
+            return;
+        }
+        let mut store = self.budgets.write().await;
+        store.record_duration(component_id, duration, unix_now());
+        if let Err(e) = store.persist().await {
+            warn!(error = %e, "Failed to persist budget usage");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2025-01-01T10:30:00Z
+    const NOW: u64 = 1_735_727_400;
+
+    #[tokio::test]
+    async fn test_hourly_budget() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let mut store = BudgetStore::open(tempdir.path()).await;
+        let budget = Budget {
+            max_calls_per_hour: Some(2),
+            ..Default::default()
+        };
+
+        store.reserve("fetch_rs", &budget, NOW)?;
+        store.reserve("fetch_rs", &budget, NOW + 60)?;
+        let err = store.reserve("fetch_rs", &budget, NOW + 120).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Budget exceeded for component 'fetch_rs': the limit of 2 calls per hour has been \
+             reached. Resets at 2025-01-01T11:00:00Z (in 28m)"
+        );
+
+        // Other components and the next hour are unaffected
+        store.reserve("other", &budget, NOW + 120)?;
+        store.reserve("fetch_rs", &budget, NOW + 30 * 60)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_daily_wall_clock_budget_persists() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let budget = Budget {
+            max_wall_clock_seconds_per_day: Some(10),
+            ..Default::default()
+        };
+
+        let mut store = BudgetStore::open(tempdir.path()).await;
+        store.reserve("fetch_rs", &budget, NOW)?;
+        store.record_duration("fetch_rs", Duration::from_secs(12), NOW);
+        store.persist().await?;
+
+        let mut store = BudgetStore::open(tempdir.path()).await;
+        let err = store.reserve("fetch_rs", &budget, NOW).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Resets at 2025-01-02T00:00:00Z (in 13h 30m)"));
+        store.reserve("fetch_rs", &budget, NOW + DAY)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,526 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Export and import of the full server state as a portable `.tar.zst` bundle.
+//!
+//! A bundle contains every component, co-located policy and metadata file found in the namespace
+//! directories of the plugin directory, plus the server configuration file if one exists. A `manifest.json` at the
+//! root of the archive records the SHA-256 digest and size of every file so that an import can
+//! verify the bundle before anything is written to the destination.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Component as PathComponent, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info};
+
+use crate::component_id::is_reserved_dir;
+use crate::content;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const COMPONENTS_PREFIX: &str = "components";
+const CONFIG_ENTRY: &str = "config/config.toml";
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+/// Suffixes of the bundled files. `.meta.json` covers both component and policy metadata
+const BUNDLED_SUFFIXES: &[&str] = &[".wasm", ".policy.yaml", ".meta.json"];
+
+/// The manifest stored at the root of every bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    /// Version of the bundle layout
+    pub format_version: u32,
+    /// Version of wassette that produced the bundle
+    pub wassette_version: String,
+    /// Creation time in seconds since the Unix epoch
+    pub created_at: u64,
+    /// Every file contained in the bundle, excluding the manifest itself
+    pub entries: Vec<BundleEntry>,
+}
+
+/// A single file recorded in a [`BundleManifest`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleEntry {
+    /// Path of the file inside the archive
+    pub path: String,
+    /// Hex encoded SHA-256 digest of the file contents
+    pub sha256: String,
+    /// Size of the file in bytes
+    pub size: u64,
+}
+
+impl BundleManifest {
+    /// Returns the IDs of all components contained in the bundle
+    pub fn component_ids(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.path.strip_prefix(&format!("{COMPONENTS_PREFIX}/")))
+            .filter_map(|name| name.strip_suffix(".wasm"))
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    /// Returns true if the bundle contains a configuration file
+    pub fn has_config(&self) -> bool {
+        self.entries.iter().any(|entry| entry.path == CONFIG_ENTRY)
+    }
+}
+
+/// Writes a bundle of everything in `plugin_dir` (and `config_file`, if it exists) to `dest`.
+pub async fn export_bundle(
+    plugin_dir: impl AsRef<Path>,
+    config_file: Option<&Path>,
+    dest: impl AsRef<Path>,
+) -> Result<BundleManifest> {
+    let plugin_dir = plugin_dir.as_ref().to_path_buf();
+    let config_file = config_file.map(Path::to_path_buf);
+    let dest = dest.as_ref().to_path_buf();
+    tokio::task::spawn_blocking(move || export_bundle_blocking(&plugin_dir, config_file, &dest))
+        .await?
+}
+
+/// Verifies the bundle at `bundle` and installs its contents into `plugin_dir`.
+///
+/// The configuration file is only restored when `config_file` is given. Existing files with
+/// different contents cause the import to fail unless `overwrite` is set. Nothing is written
+/// unless every digest in the manifest matches.
+pub async fn import_bundle(
+    bundle: impl AsRef<Path>,
+    plugin_dir: impl AsRef<Path>,
+    config_file: Option<&Path>,
+    overwrite: bool,
+) -> Result<BundleManifest> {
+    let bundle = bundle.as_ref().to_path_buf();
+    let plugin_dir = plugin_dir.as_ref().to_path_buf();
+    let config_file = config_file.map(Path::to_path_buf);
+    tokio::task::spawn_blocking(move || {
+        import_bundle_blocking(&bundle, &plugin_dir, config_file, overwrite)
+    })
+    .await?
+}
+
+fn export_bundle_blocking(
+    plugin_dir: &Path,
+    config_file: Option<PathBuf>,
+    dest: &Path,
+) -> Result<BundleManifest> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(plugin_dir)
+        .with_context(|| format!("Failed to read plugin directory {}", plugin_dir.display()))?
+    {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(|s| s.to_string()) else {
+            continue;
+        };
+        let file_type = entry.file_type()?;
+        if file_type.is_file() && BUNDLED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+            files.push((format!("{COMPONENTS_PREFIX}/{name}"), entry.path()));
+        } else if file_type.is_dir() && !is_reserved_dir(&name) {
+            for component_file in std::fs::read_dir(entry.path())? {
+                let component_file = component_file?;
+                let Some(file_name) = component_file.file_name().to_str().map(|s| s.to_string())
+                else {
+                    continue;
+                };
+                if component_file.file_type()?.is_file()
+                    && BUNDLED_SUFFIXES
+                        .iter()
+                        .any(|suffix| file_name.ends_with(suffix))
+                {
+                    files.push((
+                        format!("{COMPONENTS_PREFIX}/{name}/{file_name}"),
+                        component_file.path(),
+                    ));
+                }
+            }
+        }
+    }
+    // Keep the archive layout stable so identical states produce identical manifests
+    files.sort();
+
+    if let Some(config_file) = config_file.filter(|path| path.is_file()) {
+        files.push((CONFIG_ENTRY.to_string(), config_file));
+    }
+
+    let mut entries = Vec::with_capacity(files.len());
+    for (archive_path, path) in &files {
+        let (sha256, size) = digest_file(path)?;
+        entries.push(BundleEntry {
+            path: archive_path.clone(),
+            sha256,
+            size,
+        });
+    }
+
+    let manifest = BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        wassette_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        entries,
+    };
+
+    let out = File::create(dest)
+        .with_context(|| format!("Failed to create bundle file {}", dest.display()))?;
+    let encoder = zstd::Encoder::new(BufWriter::new(out), 0)?;
+    let mut builder = tar::Builder::new(encoder);
+
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_FILE, manifest_bytes.as_slice())?;
+
+    for (archive_path, path) in &files {
+        debug!(path = %path.display(), entry = %archive_path, "Adding file to bundle");
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open {} for bundling", path.display()))?;
+        builder.append_file(archive_path, &mut file)?;
+    }
+
+    builder.into_inner()?.finish()?;
+    info!(
+        path = %dest.display(),
+        files = manifest.entries.len(),
+        "Exported server state bundle"
+    );
+    Ok(manifest)
+}
+
+fn import_bundle_blocking(
+    bundle: &Path,
+    plugin_dir: &Path,
+    config_file: Option<PathBuf>,
+    overwrite: bool,
+) -> Result<BundleManifest> {
+    let staging = tempfile::tempdir()?;
+    let file = File::open(bundle)
+        .with_context(|| format!("Failed to open bundle {}", bundle.display()))?;
+    let decoder = zstd::Decoder::new(BufReader::new(file))?;
+    let mut archive = tar::Archive::new(decoder);
+
+    let mut staged = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let archive_path = validate_archive_path(&path)?;
+        if !entry.header().entry_type().is_file() {
+            bail!("Bundle entry {archive_path} is not a regular file");
+        }
+        let target = staging.path().join(&archive_path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&target)?;
+        staged.push(archive_path);
+    }
+
+    let manifest_path = staging.path().join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        bail!("Bundle {} does not contain a manifest", bundle.display());
+    }
+    let manifest: BundleManifest = serde_json::from_reader(File::open(&manifest_path)?)
+        .context("Failed to parse bundle manifest")?;
+    if manifest.format_version != BUNDLE_FORMAT_VERSION {
+        bail!(
+            "Unsupported bundle format version {} (expected {})",
+            manifest.format_version,
+            BUNDLE_FORMAT_VERSION
+        );
+    }
+
+    for archive_path in &staged {
+        if archive_path != MANIFEST_FILE
+            && !manifest.entries.iter().any(|e| &e.path == archive_path)
+        {
+            bail!("Bundle entry {archive_path} is not listed in the manifest");
+        }
+    }
+    for entry in &manifest.entries {
+        let staged_path = staging.path().join(&entry.path);
+        if !staged_path.is_file() {
+            bail!(
+                "Bundle is missing file {} listed in the manifest",
+                entry.path
+            );
+        }
+        let (sha256, size) = digest_file(&staged_path)?;
+        if sha256 != entry.sha256 || size != entry.size {
+            bail!(
+                "Digest mismatch for {}: expected sha256 {}, found {}",
+                entry.path,
+                entry.sha256,
+                sha256
+            );
+        }
+    }
+
+    // Work out every destination up front so a conflict doesn't leave a partial import behind
+    let mut installs = Vec::new();
+    for entry in &manifest.entries {
+        let dest = if entry.path == CONFIG_ENTRY {
+            match &config_file {
+                Some(config_file) => config_file.clone(),
+                None => continue,
+            }
+        } else {
+            let name = entry
+                .path
+                .strip_prefix(&format!("{COMPONENTS_PREFIX}/"))
+                .context("Unexpected bundle entry")?;
+            plugin_dir.join(name)
+        };
+        if dest.exists() && !overwrite {
+            let (existing, _) = digest_file(&dest)?;
+            if existing != entry.sha256 {
+                bail!(
+                    "Refusing to overwrite {} with different contents. Use --overwrite to replace it.",
+                    dest.display()
+                );
+            }
+        }
+        installs.push((staging.path().join(&entry.path), dest));
+    }
+
+    std::fs::create_dir_all(plugin_dir)?;
+    for (src, dest) in installs {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Replaced rather than overwritten, as it may be linked to other components
+        content::replace_with_copy(&src, &dest).with_context(|| {
+            format!("Failed to install {} to {}", src.display(), dest.display())
+        })?;
+    }
+
+    info!(
+        path = %bundle.display(),
+        components = manifest.component_ids().len(),
+        "Imported server state bundle"
+    );
+    Ok(manifest)
+}
+
+/// Only the manifest, files under `components/` or one of its namespace directories and the
+/// config entry are accepted. This also rejects absolute paths and `..` segments coming from a
+/// crafted archive.
+fn validate_archive_path(path: &Path) -> Result<String> {
+    let parts = path
+        .components()
+        .map(|component| match component {
+            PathComponent::Normal(part) => part
+                .to_str()
+                .map(|s| s.to_string())
+                .context("Bundle entry path is not valid UTF-8"),
+            _ => bail!("Invalid path in bundle: {}", path.display()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let joined = parts.join("/");
+    let allowed = match parts.as_slice() {
+        [name] => name == MANIFEST_FILE,
+        [dir, name] if dir == COMPONENTS_PREFIX => {
+            BUNDLED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+        }
+        [dir, namespace, name] if dir == COMPONENTS_PREFIX && !is_reserved_dir(namespace) => {
+            BUNDLED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+        }
+        _ => joined == CONFIG_ENTRY,
+    };
+    if !allowed {
+        bail!("Unexpected entry in bundle: {joined}");
+    }
+    Ok(joined)
+}
+
+pub(crate) fn digest_file(path: &Path) -> Result<(String, u64)> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+    );
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+        size += read as u64;
+    }
+    Ok((format!("{:x}", hasher.finalize()), size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn populate_plugin_dir(dir: &Path) {
+        std::fs::write(dir.join("fetch.wasm"), b"\0asm fake component").unwrap();
+        std::fs::write(dir.join("fetch.policy.yaml"), "version: \"1.0\"\n").unwrap();
+        std::fs::write(dir.join("fetch.policy.meta.json"), "{}").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not bundled").unwrap();
+        std::fs::create_dir(dir.join("local")).unwrap();
+        std::fs::write(dir.join("local/time.wasm"), b"\0asm fake time").unwrap();
+        std::fs::create_dir(dir.join("downloads")).unwrap();
+        std::fs::write(dir.join("downloads/partial.wasm"), b"not bundled").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_import_roundtrip() -> Result<()> {
+        let source = tempfile::tempdir()?;
+        populate_plugin_dir(source.path());
+        let config = source.path().join("config.toml");
+        std::fs::write(&config, "plugin_dir = \"/tmp\"\n")?;
+
+        let bundle_dir = tempfile::tempdir()?;
+        let bundle = bundle_dir.path().join("bundle.tar.zst");
+        let manifest = export_bundle(source.path(), Some(&config), &bundle).await?;
+
+        assert_eq!(manifest.entries.len(), 5);
+        assert_eq!(
+            manifest.component_ids(),
+            vec!["fetch".to_string(), "local/time".to_string()]
+        );
+        assert!(manifest.has_config());
+
+        let target = tempfile::tempdir()?;
+        let target_config = target.path().join("conf").join("config.toml");
+        let imported = import_bundle(
+            &bundle,
+            target.path().join("plugins"),
+            Some(&target_config),
+            false,
+        )
+        .await?;
+        assert_eq!(imported, manifest);
+
+        let plugins = target.path().join("plugins");
+        assert_eq!(
+            std::fs::read(plugins.join("fetch.wasm"))?,
+            b"\0asm fake component"
+        );
+        assert!(plugins.join("fetch.policy.yaml").exists());
+        assert!(plugins.join("fetch.policy.meta.json").exists());
+        assert!(!plugins.join("notes.txt").exists());
+        assert!(plugins.join("local/time.wasm").exists());
+        assert!(!plugins.join("downloads").exists());
+        assert_eq!(
+            std::fs::read_to_string(target_config)?,
+            "plugin_dir = \"/tmp\"\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_refuses_conflicting_files() -> Result<()> {
+        let source = tempfile::tempdir()?;
+        populate_plugin_dir(source.path());
+        let bundle_dir = tempfile::tempdir()?;
+        let bundle = bundle_dir.path().join("bundle.tar.zst");
+        export_bundle(source.path(), None, &bundle).await?;
+
+        let target = tempfile::tempdir()?;
+        std::fs::write(target.path().join("fetch.wasm"), b"something else")?;
+
+        let err = import_bundle(&bundle, target.path(), None, false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Refusing to overwrite"));
+        // Nothing else should have been written
+        assert!(!target.path().join("fetch.policy.yaml").exists());
+
+        import_bundle(&bundle, target.path(), None, true).await?;
+        assert_eq!(
+            std::fs::read(target.path().join("fetch.wasm"))?,
+            b"\0asm fake component"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_overwrite_keeps_linked_components() -> Result<()> {
+        let source = tempfile::tempdir()?;
+        populate_plugin_dir(source.path());
+        let bundle_dir = tempfile::tempdir()?;
+        let bundle = bundle_dir.path().join("bundle.tar.zst");
+        export_bundle(source.path(), None, &bundle).await?;
+
+        // fetch.wasm shares its content with another component, as the content store links them
+        let target = tempfile::tempdir()?;
+        std::fs::write(target.path().join("other.wasm"), b"something else")?;
+        std::fs::hard_link(
+            target.path().join("other.wasm"),
+            target.path().join("fetch.wasm"),
+        )?;
+
+        import_bundle(&bundle, target.path(), None, true).await?;
+        assert_eq!(
+            std::fs::read(target.path().join("fetch.wasm"))?,
+            b"\0asm fake component"
+        );
+        assert_eq!(
+            std::fs::read(target.path().join("other.wasm"))?,
+            b"something else"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_detects_tampered_bundle() -> Result<()> {
+        let bundle_dir = tempfile::tempdir()?;
+        let bundle = bundle_dir.path().join("bundle.tar.zst");
+
+        let manifest = BundleManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            wassette_version: "0.0.0".to_string(),
+            created_at: 0,
+            entries: vec![BundleEntry {
+                path: "components/fetch.wasm".to_string(),
+                sha256: "0".repeat(64),
+                size: 4,
+            }],
+        };
+        let manifest_bytes = serde_json::to_vec(&manifest)?;
+        let encoder = zstd::Encoder::new(File::create(&bundle)?, 0)?;
+        let mut builder = tar::Builder::new(encoder);
+        for (path, data) in [
+            (MANIFEST_FILE, manifest_bytes.as_slice()),
+            ("components/fetch.wasm", b"evil".as_slice()),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, data)?;
+        }
+        builder.into_inner()?.finish()?;
+
+        let target = tempfile::tempdir()?;
+        let err = import_bundle(&bundle, target.path(), None, false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Digest mismatch"));
+        assert!(!target.path().join("fetch.wasm").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_archive_path() {
+        assert!(validate_archive_path(Path::new("manifest.json")).is_ok());
+        assert!(validate_archive_path(Path::new("components/a.wasm")).is_ok());
+        assert!(validate_archive_path(Path::new("config/config.toml")).is_ok());
+        assert!(validate_archive_path(Path::new("../a.wasm")).is_err());
+        assert!(validate_archive_path(Path::new("/etc/passwd")).is_err());
+        assert!(validate_archive_path(Path::new("components/a.sh")).is_err());
+        assert!(validate_archive_path(Path::new("components/x/a.wasm")).is_ok());
+        assert!(validate_archive_path(Path::new("components/downloads/a.wasm")).is_err());
+        assert!(validate_archive_path(Path::new("components/x/y/a.wasm")).is_err());
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Self-checks of a plugin directory and its configuration, for deployment pipelines to find
+//! problems before a server is started with them.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::precompile::PrecompiledArtifacts;
+use crate::{component_id, policy_internal, LayoutMarker, LifecycleConfig, LAYOUT_VERSION};
+
+/// How long a registry may take to answer before it counts as unreachable
+const REGISTRY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    /// Nothing is wrong
+    Pass,
+    /// Something looks off, but the server would still work
+    Warn,
+    /// The server would fail to start or to serve some tools
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "PASS",
+            Self::Warn => "WARN",
+            Self::Fail => "FAIL",
+        })
+    }
+}
+
+/// Result of a single check, e.g. of compiling one component
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckResult {
+    /// What was checked, e.g. `plugin_dir` or `component:local/fetch`
+    pub check: String,
+    /// Whether the check passed
+    pub status: CheckStatus,
+    /// What was found
+    pub message: String,
+}
+
+/// Results of all checks run by [`check_environment`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CheckReport {
+    /// Results in the order the checks ran
+    pub checks: Vec<CheckResult>,
+}
+
+impl CheckReport {
+    /// Returns whether no check failed. Warnings don't count as failures.
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Fail)
+    }
+
+    /// Adds the result of a check
+    pub fn push(
+        &mut self,
+        check: impl Into<String>,
+        status: CheckStatus,
+        message: impl Into<String>,
+    ) {
+        self.checks.push(CheckResult {
+            check: check.into(),
+            status,
+            message: message.into(),
+        });
+    }
+}
+
+/// Checks that the plugin directory is writable, that the configuration makes sense, that every
+/// installed component compiles and its policy parses, and, unless `offline` is set, that the
+/// registries of `sources` and of the configured mirrors can be reached. `sources` are URIs that
+/// components are loaded from, e.g. those of a provisioning manifest.
+pub async fn check_environment(
+    plugin_dir: &Path,
+    config: &LifecycleConfig,
+    sources: &[String],
+    offline: bool,
+) -> CheckReport {
+    let mut report = CheckReport::default();
+    check_plugin_dir(plugin_dir, &mut report).await;
+    check_layout(plugin_dir, &mut report).await;
+    check_config(config, &mut report);
+    let component_ids = check_components(plugin_dir, config, &mut report).await;
+    check_component_references(config, &component_ids, &mut report);
+    if !offline {
+        check_registries(config, sources, &mut report).await;
+    }
+    report
+}
+
+async fn check_layout(plugin_dir: &Path, report: &mut CheckReport) {
+    match LayoutMarker::read(plugin_dir).await {
+        Ok(Some(marker)) if marker.version > LAYOUT_VERSION => report.push(
+            "layout",
+            CheckStatus::Fail,
+            format!(
+                "Layout version {} was written by wassette {}, this version only understands up \
+                 to {LAYOUT_VERSION}",
+                marker.version, marker.wassette_version
+            ),
+        ),
+        Ok(Some(marker)) if marker.version == LAYOUT_VERSION => report.push(
+            "layout",
+            CheckStatus::Pass,
+            format!("Layout version {LAYOUT_VERSION}"),
+        ),
+        Ok(marker) => report.push(
+            "layout",
+            CheckStatus::Warn,
+            format!(
+                "Layout version {} will be backed up and upgraded to {LAYOUT_VERSION} at startup",
+                marker.map_or(0, |marker| marker.version)
+            ),
+        ),
+        Err(e) => report.push("layout", CheckStatus::Fail, format!("{e:#}")),
+    }
+}
+
+async fn check_plugin_dir(plugin_dir: &Path, report: &mut CheckReport) {
+    let probe = plugin_dir.join(format!(".check-{}", std::process::id()));
+    let writable = async {
+        tokio::fs::create_dir_all(plugin_dir).await?;
+        tokio::fs::write(&probe, b"").await?;
+        tokio::fs::remove_file(&probe).await
+    };
+    match writable.await {
+        Ok(()) => report.push(
+            "plugin_dir",
+            CheckStatus::Pass,
+            format!("{} is writable", plugin_dir.display()),
+        ),
+        Err(e) => report.push(
+            "plugin_dir",
+            CheckStatus::Fail,
+            format!("{} is not writable: {e}", plugin_dir.display()),
+        ),
+    }
+}
+
+fn check_config(config: &LifecycleConfig, report: &mut CheckReport) {
+    if let Err(e) = config.tool_naming.sanitizer() {
+        report.push("config.tool_naming", CheckStatus::Fail, format!("{e:#}"));
+    }
+    for model in &config.ai_models {
+        let check = format!("config.ai_models:{}", model.name);
+        if !model.path.exists() {
+            report.push(
+                check,
+                CheckStatus::Fail,
+                format!("Model directory {} doesn't exist", model.path.display()),
+            );
+        } else if cfg!(not(feature = "wasi-nn")) {
+            report.push(
+                check,
+                CheckStatus::Warn,
+                "wassette was built without the wasi-nn feature, so the model isn't loaded",
+            );
+        }
+    }
+}
+
+/// Compiles every component and parses its policy, returning the IDs of the components
+async fn check_components(
+    plugin_dir: &Path,
+    config: &LifecycleConfig,
+    report: &mut CheckReport,
+) -> Vec<String> {
+    let files = match component_id::component_files(plugin_dir).await {
+        Ok(files) => files,
+        Err(e) => {
+            report.push(
+                "components",
+                CheckStatus::Fail,
+                format!("Failed to list components: {e:#}"),
+            );
+            return Vec::new();
+        }
+    };
+    let compiler = crate::create_engine(config)
+        .and_then(|engine| Ok((crate::create_linker(&engine)?, engine)));
+    let (linker, engine) = match compiler {
+        Ok(compiler) => compiler,
+        Err(e) => {
+            report.push(
+                "engine",
+                CheckStatus::Fail,
+                format!("Failed to create the engine: {e:#}"),
+            );
+            return Vec::new();
+        }
+    };
+
+    let mut ids = Vec::with_capacity(files.len());
+    for (id, path) in files {
+        let start_time = Instant::now();
+        let compiled = match crate::read_component_source(id.clone(), &path).await {
+            Ok(source) => {
+                crate::compile_component(
+                    engine.clone(),
+                    &linker,
+                    source,
+                    plugin_dir,
+                    PrecompiledArtifacts::from_config(config),
+                    &Arc::default(),
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        match compiled {
+            Ok(_) => report.push(
+                format!("component:{id}"),
+                CheckStatus::Pass,
+                format!("Compiled in {:?}", start_time.elapsed()),
+            ),
+            Err(e) => report.push(
+                format!("component:{id}"),
+                CheckStatus::Fail,
+                format!("{e:#}"),
+            ),
+        }
+        match policy_internal::read_policy_template(plugin_dir, &id).await {
+            Ok(Some(_)) => {
+                report.push(format!("policy:{id}"), CheckStatus::Pass, "Policy is valid")
+            }
+            Ok(None) => {}
+            Err(e) => report.push(format!("policy:{id}"), CheckStatus::Fail, format!("{e:#}")),
+        }
+        ids.push(id);
+    }
+    ids
+}
+
+/// Warns about components named in the configuration that aren't installed
+fn check_component_references(
+    config: &LifecycleConfig,
+    component_ids: &[String],
+    report: &mut CheckReport,
+) {
+    let settings = [
+        ("hot_components", &config.hot_components),
+        ("profile_components", &config.profile_components),
+        ("record_components", &config.record_components),
+        ("transcript_components", &config.transcript_components),
+    ];
+    for (setting, ids) in settings {
+        for id in ids {
+            if !component_ids
+                .iter()
+                .any(|installed| component_id::id_matches(id, installed))
+            {
+                report.push(
+                    format!("config.{setting}"),
+                    CheckStatus::Warn,
+                    format!("No installed component matches '{id}'"),
+                );
+            }
+        }
+    }
+}
+
+/// Returns the URL that tells whether the registry or server behind a source URI is reachable
+fn probe_url(uri: &str) -> Option<String> {
+    if let Some(reference) = uri.strip_prefix("oci://") {
+        let registry = reference
+            .split('/')
+            .next()
+            .filter(|host| !host.is_empty())?;
+        return Some(format!("https://{registry}/v2/"));
+    }
+    let url = reqwest::Url::parse(uri).ok()?;
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return None;
+    }
+    Some(format!("{}://{}/", url.scheme(), url.host_str()?))
+}
+
+async fn check_registries(config: &LifecycleConfig, sources: &[String], report: &mut CheckReport) {
+    let urls: BTreeSet<String> = sources
+        .iter()
+        .chain(config.mirrors.iter().flat_map(|mirror| &mirror.mirrors))
+        .filter_map(|uri| probe_url(uri))
+        .collect();
+    if urls.is_empty() {
+        return;
+    }
+    let client = reqwest::Client::builder()
+        .timeout(REGISTRY_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    for url in urls {
+        // Registries answer unauthenticated requests with 401, which still shows they're up
+        match client.get(&url).send().await {
+            Ok(response) => report.push(
+                format!("registry:{url}"),
+                CheckStatus::Pass,
+                format!("Reachable ({})", response.status()),
+            ),
+            Err(e) => report.push(
+                format!("registry:{url}"),
+                CheckStatus::Fail,
+                format!("Unreachable: {e}"),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_url() {
+        assert_eq!(
+            probe_url("oci://ghcr.io/microsoft/time-server-js:latest").as_deref(),
+            Some("https://ghcr.io/v2/")
+        );
+        assert_eq!(
+            probe_url("https://example.com/components/fetch.wasm").as_deref(),
+            Some("https://example.com/")
+        );
+        assert_eq!(probe_url("file:///tmp/fetch.wasm"), None);
+    }
+
+    #[tokio::test]
+    async fn test_check_environment() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let local = dir.path().join("local");
+        tokio::fs::create_dir(&local).await?;
+        tokio::fs::write(local.join("broken.wasm"), b"not a component").await?;
+        tokio::fs::write(local.join("broken.policy.yaml"), "version: [").await?;
+        let config = LifecycleConfig {
+            hot_components: vec!["missing".to_string()],
+            ..Default::default()
+        };
+
+        let report = check_environment(dir.path(), &config, &[], true).await;
+        let statuses: Vec<_> = report
+            .checks
+            .iter()
+            .map(|check| (check.check.as_str(), check.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("plugin_dir", CheckStatus::Pass),
+                ("layout", CheckStatus::Warn),
+                ("component:local/broken", CheckStatus::Fail),
+                ("policy:local/broken", CheckStatus::Fail),
+                ("config.hot_components", CheckStatus::Warn),
+            ]
+        );
+        assert!(!report.passed());
+        Ok(())
+    }
+}
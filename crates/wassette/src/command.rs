@@ -0,0 +1,201 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Command components.
+//!
+//! Components exporting `wasi:cli/run` are programs rather than libraries: their `run` function
+//! takes no parameters and reads its arguments from argv. Such a component gets a single tool
+//! named after the component, whose `args` argument becomes argv. The call returns the exit code
+//! and whatever the program wrote to stdout and stderr.
+
+use anyhow::{bail, Context, Result};
+use component2json::{FunctionIdentifier, ToolMetadata, ToolNameSanitizer};
+use serde_json::{json, Value};
+use wasmtime::component::Val;
+use wasmtime_wasi::p2::pipe::MemoryOutputPipe;
+use wasmtime_wasi::I32Exit;
+
+use crate::component_id::component_name;
+
+/// The interface exported by command components
+const RUN_INTERFACE: &str = "wasi:cli/run";
+
+/// The function of [`RUN_INTERFACE`] that runs the command
+const RUN_FUNCTION: &str = "run";
+
+/// The argument holding the command line arguments, not including the program name
+pub const ARGS_ARGUMENT: &str = "args";
+
+/// How many bytes of stdout and of stderr are kept; the command fails to write any more
+const MAX_OUTPUT_SIZE: usize = 1024 * 1024;
+
+/// Returns whether the function is the `run` function of a command component
+pub(crate) fn is_command(identifier: &FunctionIdentifier) -> bool {
+    identifier.function_name == RUN_FUNCTION
+        && identifier
+            .interface()
+            .is_some_and(|iface| iface.unversioned() == RUN_INTERFACE)
+}
+
+/// Turns the `run` tool of a command component into a tool named after the component that takes
+/// its command line arguments
+pub(crate) fn synthesize_command_tools(
+    component_id: &str,
+    tools: &mut [ToolMetadata],
+    sanitizer: &ToolNameSanitizer,
+) {
+    for tool in tools.iter_mut().filter(|tool| is_command(&tool.identifier)) {
+        let name = sanitizer.sanitize_component(component_name(component_id));
+        tool.normalized_name = name.clone();
+        tool.schema.name = name;
+        if tool.schema.description.is_none() {
+            tool.schema.description = Some(format!(
+                "Runs the {} command with the given arguments",
+                component_name(component_id)
+            ));
+        }
+        tool.schema.input_schema = json!({
+            "type": "object",
+            "properties": {
+                ARGS_ARGUMENT: {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "Command line arguments, not including the program name",
+                }
+            },
+        });
+        tool.schema.output_schema = Some(json!({
+            "type": "object",
+            "properties": {
+                "exit_code": {"type": "integer"},
+                "stdout": {"type": "string"},
+                "stderr": {"type": "string"},
+            },
+            "required": ["exit_code", "stdout", "stderr"],
+        }));
+        tool.plan = None;
+        tool.memoization = None;
+    }
+}
+
+/// The argv and captured output of a single call to a command component
+#[derive(Clone)]
+pub(crate) struct CommandIo {
+    /// The program name followed by the arguments of the call
+    pub(crate) argv: Vec<String>,
+    pub(crate) stdout: MemoryOutputPipe,
+    pub(crate) stderr: MemoryOutputPipe,
+}
+
+impl CommandIo {
+    /// Builds argv from the `args` argument of a call, using the component name as the program
+    /// name
+    pub(crate) fn from_parameters(component_id: &str, parameters: &str) -> Result<Self> {
+        let params: Value = serde_json::from_str(parameters)?;
+        let mut argv = vec![component_name(component_id).to_string()];
+        match params.get(ARGS_ARGUMENT) {
+            None | Some(Value::Null) => {}
+            Some(Value::Array(args)) => {
+                for arg in args {
+                    let Some(arg) = arg.as_str() else {
+                        bail!("Every element of '{ARGS_ARGUMENT}' must be a string, got {arg}");
+                    };
+                    argv.push(arg.to_string());
+                }
+            }
+            Some(other) => bail!("'{ARGS_ARGUMENT}' must be an array of strings, got {other}"),
+        }
+        Ok(Self {
+            argv,
+            stdout: MemoryOutputPipe::new(MAX_OUTPUT_SIZE),
+            stderr: MemoryOutputPipe::new(MAX_OUTPUT_SIZE),
+        })
+    }
+
+    /// Turns the outcome of `run` into the JSON result of the tool call. The command exiting
+    /// with a code is a result rather than an error, any other trap is still an error.
+    pub(crate) fn finish(&self, outcome: Result<Vec<Val>>) -> Result<String> {
+        let exit_code = match outcome {
+            Ok(results) => match results.first() {
+                Some(Val::Result(Err(_))) => 1,
+                _ => 0,
+            },
+            Err(e) => match e.downcast_ref::<I32Exit>() {
+                Some(exit) => exit.0,
+                None => return Err(e).context("Command failed"),
+            },
+        };
+        let output = json!({
+            "exit_code": exit_code,
+            "stdout": String::from_utf8_lossy(&self.stdout.contents()),
+            "stderr": String::from_utf8_lossy(&self.stderr.contents()),
+        });
+        Ok(serde_json::to_string(&output)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use component2json::ToolSchema;
+
+    use super::*;
+
+    fn run_tool() -> ToolMetadata {
+        ToolMetadata {
+            identifier: FunctionIdentifier {
+                package_name: None,
+                interface_name: Some("wasi:cli/run@0.2.0".to_string()),
+                function_name: "run".to_string(),
+            },
+            normalized_name: "wasi_cli_run_0_2_0_run".to_string(),
+            schema: ToolSchema {
+                name: "wasi_cli_run_0_2_0_run".to_string(),
+                description: None,
+                input_schema: json!({"type": "object", "properties": {}}),
+                output_schema: None,
+                annotations: None,
+            },
+            deprecation: None,
+            plan: None,
+            memoization: None,
+        }
+    }
+
+    #[test]
+    fn test_synthesize_command_tools() {
+        let mut tools = vec![run_tool()];
+        synthesize_command_tools("acme/word-count", &mut tools, &ToolNameSanitizer::default());
+        let tool = &tools[0];
+        assert_eq!(tool.normalized_name, "word-count");
+        assert_eq!(tool.schema.name, "word-count");
+        assert_eq!(
+            tool.schema.input_schema["properties"][ARGS_ARGUMENT]["type"],
+            "array"
+        );
+        assert!(tool.schema.output_schema.is_some());
+        assert!(is_command(&tool.identifier));
+    }
+
+    #[test]
+    fn test_command_io_argv() -> Result<()> {
+        let io = CommandIo::from_parameters("acme/wc", r#"{"args": ["-l", "file.txt"]}"#)?;
+        assert_eq!(io.argv, ["wc", "-l", "file.txt"]);
+        assert_eq!(CommandIo::from_parameters("wc", "{}")?.argv, ["wc"]);
+        assert!(CommandIo::from_parameters("wc", r#"{"args": [1]}"#).is_err());
+        assert!(CommandIo::from_parameters("wc", r#"{"args": "-l"}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_exit_codes() -> Result<()> {
+        let io = CommandIo::from_parameters("wc", "{}")?;
+        let output: Value = serde_json::from_str(&io.finish(Ok(vec![Val::Result(Ok(None))]))?)?;
+        assert_eq!(output["exit_code"], 0);
+        let output: Value = serde_json::from_str(&io.finish(Ok(vec![Val::Result(Err(None))]))?)?;
+        assert_eq!(output["exit_code"], 1);
+        let output: Value = serde_json::from_str(&io.finish(Err(I32Exit(3).into()))?)?;
+        assert_eq!(output["exit_code"], 3);
+        assert!(io.finish(Err(anyhow::anyhow!("trap"))).is_err());
+        Ok(())
+    }
+}
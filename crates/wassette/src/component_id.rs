@@ -0,0 +1,419 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Namespaced component IDs.
+//!
+//! A component ID is `<namespace>/<name>`. The name comes from the file name of the component,
+//! the namespace from where it was loaded from:
+//!
+//! - `file://` URIs load into the `local` namespace
+//! - `oci://` references use the registry and the repository path without its last segment,
+//!   e.g. `oci://ghcr.io/microsoft/time:latest` is loaded as `ghcr.io-microsoft/time`
+//! - `https://` URLs and `git+https://` repositories use the host, e.g. `example.com/time`
+//! - URIs of schemes with a registered [`SourceLoader`](crate::SourceLoader) use the scheme, e.g.
+//!   `s3://tools/time.wasm` is loaded as `s3/time`
+//!
+//! The plugin directory mirrors the IDs, with a directory per namespace. Components that were
+//! stored under a flat ID by earlier versions are moved into the `local` namespace at startup.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::budget::BUDGET_USAGE_FILE;
+use crate::labels::Labels;
+use crate::profiling::PROFILES_DIR;
+use crate::stats::STATS_FILE;
+use crate::{
+    APPROVALS_DIR, BACKUPS_DIR, BLOBS_DIR, CONTENT_DIR, DIAGNOSTICS_DIR, DOWNLOADS_DIR,
+    PRECOMPILED_DIR, SANDBOXES_DIR, TRACES_DIR, TRANSCRIPTS_DIR,
+};
+
+/// Namespace of components loaded from local files
+pub const LOCAL_NAMESPACE: &str = "local";
+
+/// Directories in the plugin directory that hold server state rather than components
+const RESERVED_DIRS: &[&str] = &[
+    DOWNLOADS_DIR,
+    DIAGNOSTICS_DIR,
+    TRACES_DIR,
+    TRANSCRIPTS_DIR,
+    PROFILES_DIR,
+    BACKUPS_DIR,
+    BLOBS_DIR,
+    CONTENT_DIR,
+    PRECOMPILED_DIR,
+    APPROVALS_DIR,
+    SANDBOXES_DIR,
+    "cache",
+];
+
+/// Suffixes of the files stored next to a component, starting with the component itself
+const COMPONENT_FILE_SUFFIXES: &[&str] =
+    &[".wasm", ".meta.json", ".policy.yaml", ".policy.meta.json"];
+
+/// Returns whether a directory in the plugin directory holds server state rather than a namespace
+pub(crate) fn is_reserved_dir(name: &str) -> bool {
+    RESERVED_DIRS.contains(&name)
+}
+
+/// Returns the ID of a component with the given name loaded from `uri`
+pub(crate) fn component_id_for_uri(uri: &str, name: &str) -> Result<String> {
+    let uri = uri.trim();
+    let (scheme, reference) = uri
+        .split_once("://")
+        .context("Invalid component reference. Should be of the form scheme://reference")?;
+    let namespace = match scheme {
+        "file" => LOCAL_NAMESPACE.to_string(),
+        "oci" => {
+            let reference: oci_client::Reference =
+                reference.parse().context("Failed to parse OCI reference")?;
+            return Ok(oci_component_id(&reference));
+        }
+        "https" => {
+            let url = url::Url::parse(uri).context("Failed to parse component URL")?;
+            sanitize(url.host_str().context("Component URL has no host")?)
+        }
+        crate::GIT_SCHEME => sanitize(
+            &crate::git::GitSource::parse(uri)?
+                .host()
+                .context("Git repository URL has no host")?,
+        ),
+        _ => sanitize(scheme),
+    };
+    let id = format!("{namespace}/{name}");
+    validate_component_id(&id)?;
+    Ok(id)
+}
+
+/// Returns the ID of the component pulled from an OCI reference
+pub(crate) fn oci_component_id(reference: &oci_client::Reference) -> String {
+    let (path, name) = match reference.repository().rsplit_once('/') {
+        Some((path, name)) => (format!("{}-{path}", reference.registry()), name),
+        None => (reference.registry().to_string(), reference.repository()),
+    };
+    format!("{}/{}", sanitize(&path), sanitize(name))
+}
+
+/// Replaces the characters that don't belong in a namespace or file name
+fn sanitize(part: &str) -> String {
+    part.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+/// Checks that an ID has a namespace and a name that are safe to use as path segments
+pub(crate) fn validate_component_id(id: &str) -> Result<()> {
+    let Some((namespace, name)) = id.split_once('/') else {
+        bail!("Invalid component ID '{id}': expected <namespace>/<name>");
+    };
+    for part in [namespace, name] {
+        if part.is_empty() || part == "." || part == ".." || part.contains(['/', '\\']) {
+            bail!("Invalid component ID '{id}': expected <namespace>/<name>");
+        }
+    }
+    if is_reserved_dir(namespace) {
+        bail!("Invalid component ID '{id}': the namespace '{namespace}' is reserved");
+    }
+    Ok(())
+}
+
+/// Returns the name of a component, the last segment of its ID
+pub fn component_name(id: &str) -> &str {
+    id.rsplit_once('/').map_or(id, |(_, name)| name)
+}
+
+/// Returns whether a configured component, given either as a full ID or as a name, refers to
+/// the component with the given ID
+pub(crate) fn id_matches(configured: &str, id: &str) -> bool {
+    configured == id || (!configured.contains('/') && configured == component_name(id))
+}
+
+/// Resolves a component name to the only ID in `ids` with that name. IDs, and names that no ID
+/// has, are returned unchanged. Fails if several IDs have the name.
+pub(crate) fn resolve_name<S: AsRef<str>>(ids: &[S], id: &str) -> Result<String> {
+    if id.contains('/') {
+        return Ok(id.to_string());
+    }
+    let matches: Vec<&str> = ids
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|candidate| *candidate == id || component_name(candidate) == id)
+        .collect();
+    match matches.as_slice() {
+        [] => Ok(id.to_string()),
+        [only] => Ok(only.to_string()),
+        _ => bail!(
+            "Component name '{}' is ambiguous, use one of: {}",
+            id,
+            matches.join(", ")
+        ),
+    }
+}
+
+/// Metadata stored next to a component in `<id>.meta.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentMetadata {
+    /// ID of the component
+    pub id: String,
+    /// URI the component was loaded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_uri: Option<String>,
+    /// When the component was loaded, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loaded_at: Option<u64>,
+    /// Labels the component was tagged with
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+    /// SHA-256 digest of the component, which names its entry in the content store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+}
+
+impl ComponentMetadata {
+    /// Returns metadata of the component with the given ID, with nothing else set
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            source_uri: None,
+            loaded_at: None,
+            labels: Labels::new(),
+            digest: None,
+        }
+    }
+
+    /// Reads a metadata file, returning `None` if it doesn't exist
+    pub async fn read(path: &Path) -> Result<Option<Self>> {
+        match tokio::fs::read(path).await {
+            Ok(data) => crate::parse_component_metadata(&data)
+                .map(Some)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Writes the metadata file
+    pub async fn write(&self, path: &Path) -> Result<()> {
+        tokio::fs::write(path, serde_json::to_string_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Returns the path of the metadata file of a component
+pub(crate) fn metadata_path(plugin_dir: &Path, id: &str) -> PathBuf {
+    plugin_dir.join(format!("{id}.meta.json"))
+}
+
+/// Returns the IDs and paths of the components in the plugin directory
+pub(crate) async fn component_files(plugin_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut namespaces = tokio::fs::read_dir(plugin_dir).await?;
+    while let Some(namespace) = namespaces.next_entry().await? {
+        if !namespace.file_type().await?.is_dir() {
+            continue;
+        }
+        let Some(namespace_name) = namespace.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        if is_reserved_dir(&namespace_name) {
+            continue;
+        }
+        let mut entries = tokio::fs::read_dir(namespace.path()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if !entry.file_type().await?.is_file()
+                || path.extension().is_none_or(|ext| ext != "wasm")
+            {
+                continue;
+            }
+            let name = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .context("wasm file didn't have a valid file name")?;
+            files.push((format!("{namespace_name}/{name}"), path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Moves components stored under flat IDs into the `local` namespace, together with their
+/// policies, and renames them in the persisted usage statistics and budgets. Returns the new IDs.
+pub(crate) async fn migrate_flat_components(plugin_dir: &Path) -> Result<Vec<String>> {
+    let mut renames = BTreeMap::new();
+    let mut entries = tokio::fs::read_dir(plugin_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !entry.file_type().await?.is_file() || path.extension().is_none_or(|ext| ext != "wasm") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()).map(String::from) else {
+            continue;
+        };
+        let id = format!("{LOCAL_NAMESPACE}/{name}");
+        let namespace_dir = plugin_dir.join(LOCAL_NAMESPACE);
+        if namespace_dir.join(format!("{name}.wasm")).exists() {
+            warn!(component_id = %id, path = %path.display(), "Not migrating component, a component with its namespaced ID exists");
+            continue;
+        }
+        tokio::fs::create_dir_all(&namespace_dir).await?;
+        for suffix in COMPONENT_FILE_SUFFIXES {
+            let from = plugin_dir.join(format!("{name}{suffix}"));
+            if from.exists() {
+                tokio::fs::rename(&from, namespace_dir.join(format!("{name}{suffix}")))
+                    .await
+                    .with_context(|| format!("Failed to migrate {}", from.display()))?;
+            }
+        }
+        info!(component_id = %id, "Migrated component to a namespaced ID");
+        renames.insert(name, id);
+    }
+    if !renames.is_empty() {
+        rename_keys(&plugin_dir.join(STATS_FILE), "/components", &renames).await?;
+        rename_keys(&plugin_dir.join(BUDGET_USAGE_FILE), "", &renames).await?;
+    }
+    Ok(renames.into_values().collect())
+}
+
+/// Renames the keys of the JSON object at `pointer` in a state file, if the file exists
+async fn rename_keys(path: &Path, pointer: &str, renames: &BTreeMap<String, String>) -> Result<()> {
+    let data = match tokio::fs::read(path).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let Ok(mut state) = serde_json::from_slice::<Value>(&data) else {
+        warn!(path = %path.display(), "Not migrating unreadable state file");
+        return Ok(());
+    };
+    let Some(object) = state.pointer_mut(pointer).and_then(Value::as_object_mut) else {
+        return Ok(());
+    };
+    for (from, to) in renames {
+        if let Some(value) = object.remove(from) {
+            object.insert(to.clone(), value);
+        }
+    }
+    tokio::fs::write(path, serde_json::to_vec_pretty(&state)?).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_dirs_are_reserved() {
+        let state_dirs = [
+            DOWNLOADS_DIR,
+            DIAGNOSTICS_DIR,
+            TRACES_DIR,
+            TRANSCRIPTS_DIR,
+            PROFILES_DIR,
+            BACKUPS_DIR,
+            BLOBS_DIR,
+            CONTENT_DIR,
+            PRECOMPILED_DIR,
+            APPROVALS_DIR,
+            SANDBOXES_DIR,
+            crate::HTTP_CACHE_DIR,
+            crate::git::CACHE_DIR,
+        ];
+        for dir in state_dirs {
+            // Only the top-level directory can clash with a namespace
+            let top = dir.split('/').next().unwrap();
+            assert!(is_reserved_dir(top), "{dir} is not reserved");
+        }
+    }
+
+    #[test]
+    fn test_component_id_for_uri() -> Result<()> {
+        assert_eq!(
+            component_id_for_uri("file:///home/me/fetch.wasm", "fetch")?,
+            "local/fetch"
+        );
+        assert_eq!(
+            component_id_for_uri("oci://ghcr.io/microsoft/time-server-js:latest", "ignored")?,
+            "ghcr.io-microsoft/time-server-js"
+        );
+        assert_eq!(
+            component_id_for_uri("oci://localhost:5000/time:1.0", "ignored")?,
+            "localhost-5000/time"
+        );
+        assert_eq!(
+            component_id_for_uri("https://example.com/tools/time.wasm", "time")?,
+            "example.com/time"
+        );
+        assert_eq!(
+            component_id_for_uri(
+                "git+https://example.com/tools.git#v1.2.0:dist/time.wasm",
+                "time"
+            )?,
+            "example.com/time"
+        );
+        assert!(component_id_for_uri("example.com/time.wasm", "time").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_component_id() {
+        assert!(validate_component_id("local/fetch").is_ok());
+        assert!(validate_component_id("fetch").is_err());
+        assert!(validate_component_id("local/../fetch").is_err());
+        assert!(validate_component_id("../fetch").is_err());
+        assert!(validate_component_id("downloads/fetch").is_err());
+    }
+
+    #[test]
+    fn test_id_matches() {
+        assert!(id_matches("local/fetch", "local/fetch"));
+        assert!(id_matches("fetch", "local/fetch"));
+        assert!(!id_matches("other/fetch", "local/fetch"));
+        assert!(!id_matches("fetc", "local/fetch"));
+    }
+
+    #[tokio::test]
+    async fn test_migrate_flat_components() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        tokio::fs::write(dir.path().join("fetch.wasm"), b"component").await?;
+        tokio::fs::write(dir.path().join("fetch.policy.yaml"), "version: \"1.0\"").await?;
+        tokio::fs::write(
+            dir.path().join(STATS_FILE),
+            r#"{"components": {"fetch": {"fetch": {"invocations": 3}}}}"#,
+        )
+        .await?;
+        tokio::fs::create_dir(dir.path().join(DOWNLOADS_DIR)).await?;
+
+        assert_eq!(
+            migrate_flat_components(dir.path()).await?,
+            vec!["local/fetch".to_string()]
+        );
+        assert!(!dir.path().join("fetch.wasm").exists());
+        assert!(dir.path().join("local/fetch.policy.yaml").exists());
+        let stats: Value =
+            serde_json::from_slice(&tokio::fs::read(dir.path().join(STATS_FILE)).await?)?;
+        assert_eq!(
+            stats["components"]["local/fetch"]["fetch"]["invocations"],
+            3
+        );
+
+        let files = component_files(dir.path()).await?;
+        assert_eq!(
+            files,
+            vec![(
+                "local/fetch".to_string(),
+                dir.path().join("local/fetch.wasm")
+            )]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Capture of component stderr as log lines.
+//!
+//! Components usually report diagnostics on stderr. When a call is given a
+//! [`ComponentLogSink`](crate::ComponentLogSink), its stderr is split into lines that are sent to
+//! the sink as they are written, so that they can be forwarded to the client instead of ending up
+//! in the server's own stderr.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::AsyncWrite;
+use tokio::sync::mpsc;
+
+/// Longest line that is buffered before it is sent without waiting for its end
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+
+/// Receives the lines a component writes to stderr during a call
+pub type ComponentLogSink = mpsc::UnboundedSender<ComponentLog>;
+
+/// Severity of a component log line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ComponentLogLevel {
+    /// Debugging or tracing output
+    Debug,
+    /// Informational output, the level of lines without a recognized prefix
+    Info,
+    /// A warning
+    Warning,
+    /// An error, including panics
+    Error,
+}
+
+impl ComponentLogLevel {
+    /// Detects the level of a line from a leading level name such as `WARN`, `[error]` or
+    /// `ERROR:`. Panic messages are errors.
+    pub fn detect(line: &str) -> Self {
+        let line = line.trim_start();
+        if line.starts_with("thread '") && line.contains("panicked at") {
+            return Self::Error;
+        }
+        let word: String = line
+            .trim_start_matches('[')
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        match word.to_ascii_lowercase().as_str() {
+            "trace" | "debug" => Self::Debug,
+            "warn" | "warning" => Self::Warning,
+            "error" | "fatal" | "critical" => Self::Error,
+            _ => Self::Info,
+        }
+    }
+}
+
+/// A line a component wrote to stderr
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentLog {
+    /// Severity of the line
+    pub level: ComponentLogLevel,
+    /// The line, without its line ending
+    pub message: String,
+}
+
+/// Splits the bytes written to it into lines that are sent to a [`ComponentLogSink`]
+pub(crate) struct StderrLines {
+    sink: ComponentLogSink,
+    buffer: Vec<u8>,
+}
+
+impl StderrLines {
+    pub(crate) fn new(sink: ComponentLogSink) -> Self {
+        Self {
+            sink,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn send(&self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let message = line.trim_end_matches('\r');
+        if message.is_empty() {
+            return;
+        }
+        // Nobody listening anymore just means the lines are dropped
+        let _ = self.sink.send(ComponentLog {
+            level: ComponentLogLevel::detect(message),
+            message: message.to_string(),
+        });
+    }
+
+    fn write_bytes(&mut self, mut buf: &[u8]) {
+        while let Some(end) = buf.iter().position(|b| *b == b'\n') {
+            self.buffer.extend_from_slice(&buf[..end]);
+            let line = std::mem::take(&mut self.buffer);
+            self.send(&line);
+            buf = &buf[end + 1..];
+        }
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= MAX_LINE_LENGTH {
+            self.flush_partial();
+        }
+    }
+
+    fn flush_partial(&mut self) {
+        let line = std::mem::take(&mut self.buffer);
+        self.send(&line);
+    }
+}
+
+impl AsyncWrite for StderrLines {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().write_bytes(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().flush_partial();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for StderrLines {
+    fn drop(&mut self) {
+        // A last line without a line ending is still worth reporting
+        self.flush_partial();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[test]
+    fn test_detect_level() {
+        assert_eq!(
+            ComponentLogLevel::detect("WARN: retrying request"),
+            ComponentLogLevel::Warning
+        );
+        assert_eq!(
+            ComponentLogLevel::detect("[error] connection refused"),
+            ComponentLogLevel::Error
+        );
+        assert_eq!(
+            ComponentLogLevel::detect("  DEBUG parsed 3 items"),
+            ComponentLogLevel::Debug
+        );
+        assert_eq!(
+            ComponentLogLevel::detect("thread 'main' panicked at src/lib.rs:10:5:"),
+            ComponentLogLevel::Error
+        );
+        assert_eq!(
+            ComponentLogLevel::detect("fetched https://example.com"),
+            ComponentLogLevel::Info
+        );
+        assert_eq!(
+            ComponentLogLevel::detect("warnings are fine"),
+            ComponentLogLevel::Info
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stderr_lines() {
+        let (sink, mut receiver) = mpsc::unbounded_channel();
+        let mut stderr = StderrLines::new(sink);
+        stderr.write_all(b"first li").await.unwrap();
+        stderr
+            .write_all(b"ne\r\n\nWARN: second\nunterminated")
+            .await
+            .unwrap();
+        drop(stderr);
+
+        let mut lines = Vec::new();
+        while let Some(line) = receiver.recv().await {
+            lines.push(line);
+        }
+        assert_eq!(
+            lines,
+            vec![
+                ComponentLog {
+                    level: ComponentLogLevel::Info,
+                    message: "first line".to_string(),
+                },
+                ComponentLog {
+                    level: ComponentLogLevel::Warning,
+                    message: "WARN: second".to_string(),
+                },
+                ComponentLog {
+                    level: ComponentLogLevel::Info,
+                    message: "unterminated".to_string(),
+                },
+            ]
+        );
+    }
+}
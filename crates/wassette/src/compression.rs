@@ -0,0 +1,187 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Compressed components.
+//!
+//! Components can be loaded from zstd or gzip compressed files, e.g. `fetch.wasm.zst`, and from
+//! OCI layers of type [`ZSTD_WASM_MEDIA_TYPE`] or [`GZIP_WASM_MEDIA_TYPE`]. Compression is
+//! recognized by the magic bytes at the start of the data, whatever the file is called, and the
+//! component is decompressed as it is read. With
+//! [`compress_components`](crate::LifecycleConfig::compress_components) set, installed components
+//! are stored zstd compressed and decompressed whenever they are read back.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use tokio::io::AsyncReadExt;
+
+use crate::loader::DownloadedResource;
+
+/// Media type of an OCI layer holding a zstd compressed component
+pub const ZSTD_WASM_MEDIA_TYPE: &str = "application/wasm+zstd";
+
+/// Media type of an OCI layer holding a gzip compressed component
+pub const GZIP_WASM_MEDIA_TYPE: &str = "application/wasm+gzip";
+
+/// Extensions of compressed component files, e.g. `fetch.wasm.zst`
+const COMPRESSED_EXTENSIONS: &[&str] = &["zst", "gz"];
+
+/// Largest size a component may decompress to, so that a small archive can't fill the disk
+const MAX_DECOMPRESSED_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// zstd level of installed components, which favors speed over size
+const STORE_LEVEL: i32 = 3;
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Zstd,
+    Gzip,
+}
+
+fn detect(data: &[u8]) -> Option<Compression> {
+    if data.starts_with(&ZSTD_MAGIC) {
+        Some(Compression::Zstd)
+    } else if data.starts_with(&GZIP_MAGIC) {
+        Some(Compression::Gzip)
+    } else {
+        None
+    }
+}
+
+/// Copies the decompressed `reader` to `writer`, failing if it grows beyond the size limit
+fn decompress_stream(
+    compression: Compression,
+    reader: impl Read,
+    mut writer: impl Write,
+) -> Result<()> {
+    let decoder: Box<dyn Read> = match compression {
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        Compression::Gzip => Box::new(flate2::read::GzDecoder::new(reader)),
+    };
+    let copied = std::io::copy(&mut decoder.take(MAX_DECOMPRESSED_SIZE + 1), &mut writer)
+        .context("Failed to decompress component")?;
+    if copied > MAX_DECOMPRESSED_SIZE {
+        bail!("Component decompresses to more than {MAX_DECOMPRESSED_SIZE} bytes");
+    }
+    Ok(())
+}
+
+/// Returns the decompressed `data`, or `data` itself if it isn't compressed
+pub(crate) fn decompress(data: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(compression) = detect(&data) else {
+        return Ok(data);
+    };
+    let mut decompressed = Vec::new();
+    decompress_stream(compression, data.as_slice(), &mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Reads a component file, decompressing it if it is compressed
+pub(crate) async fn read_component(path: &Path) -> Result<Vec<u8>> {
+    let data = tokio::fs::read(path).await?;
+    tokio::task::spawn_blocking(move || decompress(data)).await?
+}
+
+/// Returns a file name without its compression extension, if it has one
+pub(crate) fn strip_compression_extension(file_name: &str) -> &str {
+    COMPRESSED_EXTENSIONS
+        .iter()
+        .find_map(|extension| file_name.strip_suffix(&format!(".{extension}")))
+        .unwrap_or(file_name)
+}
+
+/// Decompresses the component file at `path` into a temporary file named after the component,
+/// returning `None` if it isn't compressed
+pub(crate) async fn decompress_file(path: &Path, name: &str) -> Result<Option<DownloadedResource>> {
+    let mut magic = [0; 4];
+    let read = tokio::fs::File::open(path).await?.read(&mut magic).await?;
+    let Some(compression) = detect(&magic[..read]) else {
+        return Ok(None);
+    };
+    let (resource, file) = DownloadedResource::new_temp_file(name, "wasm").await?;
+    let file = file.into_std().await;
+    let source = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        decompress_stream(
+            compression,
+            std::io::BufReader::new(std::fs::File::open(source)?),
+            file,
+        )
+    })
+    .await??;
+    Ok(Some(resource))
+}
+
+/// Returns where the compressed copy of an installed component is written before it replaces
+/// the component
+pub(crate) fn staged_path(path: &Path) -> PathBuf {
+    path.with_extension("wasm.zst.tmp")
+}
+
+/// Compresses an installed component file in place, unless it already is compressed
+pub(crate) async fn compress_file(path: &Path) -> Result<()> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let data = std::fs::read(&path)?;
+        if detect(&data).is_some() {
+            return Ok(());
+        }
+        let compressed = zstd::encode_all(data.as_slice(), STORE_LEVEL)?;
+        let staged = staged_path(&path);
+        std::fs::write(&staged, compressed)?;
+        std::fs::rename(&staged, &path)
+            .with_context(|| format!("Failed to store compressed component {}", path.display()))
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPONENT: &[u8] = b"\0asm\x0d\0\x01\0 component bytes";
+
+    #[test]
+    fn test_decompress() -> Result<()> {
+        let zstd = zstd::encode_all(COMPONENT, 3)?;
+        assert_eq!(decompress(zstd)?, COMPONENT);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzip.write_all(COMPONENT)?;
+        assert_eq!(decompress(gzip.finish()?)?, COMPONENT);
+
+        // Uncompressed data is returned as it is
+        assert_eq!(decompress(COMPONENT.to_vec())?, COMPONENT);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_compression_extension() {
+        assert_eq!(strip_compression_extension("fetch.wasm.zst"), "fetch.wasm");
+        assert_eq!(strip_compression_extension("fetch.wasm.gz"), "fetch.wasm");
+        assert_eq!(strip_compression_extension("fetch.wasm"), "fetch.wasm");
+    }
+
+    #[tokio::test]
+    async fn test_compress_file_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("fetch.wasm");
+        tokio::fs::write(&path, COMPONENT).await?;
+
+        compress_file(&path).await?;
+        assert!(tokio::fs::read(&path).await?.starts_with(&ZSTD_MAGIC));
+        assert_eq!(read_component(&path).await?, COMPONENT);
+
+        let decompressed = decompress_file(&path, "fetch").await?.unwrap();
+        assert_eq!(decompressed.name()?, "fetch");
+        assert_eq!(tokio::fs::read(decompressed.as_ref()).await?, COMPONENT);
+        assert!(decompress_file(decompressed.as_ref(), "fetch")
+            .await?
+            .is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,407 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Runtime configuration for the [`LifecycleManager`](crate::LifecycleManager)
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use component2json::{ToolNameSanitizer, DEFAULT_INVALID_CHARS, MAX_TOOL_NAME_LENGTH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{ExecutionClass, LabelSelector, MirrorConfig};
+
+/// Configuration options for a [`LifecycleManager`](crate::LifecycleManager)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifecycleConfig {
+    /// How tool names are derived from component exports
+    pub tool_naming: ToolNamingConfig,
+    /// IDs or names of components that are compiled before all others at startup. A component can
+    /// also mark itself as hot with `"hot": true` in its embedded tool metadata
+    pub hot_components: Vec<String>,
+    /// Instantiate every hot component once at startup, so the first tool call doesn't pay the
+    /// cold-start cost
+    pub warm_up: bool,
+    /// IDs or names of components whose calls are all profiled. Profiles are written to the
+    /// `profiles` directory in the plugin directory
+    pub profile_components: Vec<String>,
+    /// Write a core dump with the guest's stack and memory to the `diagnostics` directory in the
+    /// plugin directory whenever a component traps
+    pub coredump_on_trap: bool,
+    /// Engine settings that make float and SIMD results the same on every host
+    pub determinism: DeterminismConfig,
+    /// IDs or names of components whose calls are all recorded. Traces are written to the
+    /// `traces` directory in the plugin directory and can be replayed with `wassette replay`
+    pub record_components: Vec<String>,
+    /// IDs or names of components whose calls are saved as transcripts with their full arguments
+    /// and results, e.g. to keep an audit trail of a code executor
+    pub transcript_components: Vec<String>,
+    /// Number of days transcripts are kept for. They are kept forever if unset
+    pub transcript_retention_days: Option<u64>,
+    /// Number of seconds a call that needs approval waits for it before it is rejected. Defaults
+    /// to [`DEFAULT_APPROVAL_TIMEOUT_SECS`](crate::DEFAULT_APPROVAL_TIMEOUT_SECS)
+    pub approval_timeout_secs: Option<u64>,
+    /// Give every component the server's command line arguments and the host's wall clock, as
+    /// earlier versions did, whether or not its policy grants them in its `process` section
+    pub legacy_sandbox_defaults: bool,
+    /// Mirrors that components and policies are loaded from before their original source, e.g.
+    /// an internal registry in an air-gapped environment
+    pub mirrors: Vec<MirrorConfig>,
+    /// Label selector limiting `tools/list` to the tools of the matching components, e.g.
+    /// `team=infra,risk!=high`. Tools of other components can still be called
+    pub tool_selector: Option<LabelSelector>,
+    /// Models loaded at startup for local inference through `wasi:nn`. Components may only use
+    /// the models that their policy allows in its `ai` section. Needs the `wasi-nn` feature
+    pub ai_models: Vec<AiModelConfig>,
+    /// Sizes of the worker pools tool calls run on and which components run as batch work
+    pub execution: ExecutionConfig,
+    /// Store installed components zstd compressed in the plugin directory. They are decompressed
+    /// whenever they are read, and components installed before are compressed when next loaded
+    pub compress_components: bool,
+    /// Abbreviate the deeply nested parts of tool schemas in `tools/list` to short descriptions,
+    /// so that components with complex types don't fill the client's context. Argument validation
+    /// and [`get_tool_schema`](crate::LifecycleManager::get_tool_schema) keep the full schemas
+    pub compact_schemas: bool,
+    /// Language of the builtin tool descriptions and error messages, such as `de`, for clients
+    /// that don't declare their own. English if unset or unsupported
+    pub language: Option<String>,
+    /// Where components store the blobs they write through `wasi:blobstore`
+    pub blobstore: BlobstoreConfig,
+    /// URI schemes that components and policies may be loaded from, e.g. `["oci", "https"]`,
+    /// built in or registered with
+    /// [`register_source_loader`](crate::LifecycleManager::register_source_loader). All of them
+    /// are allowed if unset
+    pub allowed_schemes: Option<Vec<String>>,
+    /// Leave out the tools the host implements itself, `fetch-url` and `read-file`, which work
+    /// before any component is installed under the policy of
+    /// [`HOST_TOOLS_ID`](crate::HOST_TOOLS_ID)
+    pub disable_host_tools: bool,
+    /// Sandbox groups, by name, with the IDs or names of their components. The components of a
+    /// group share a scratch directory and their blob containers
+    pub sandbox_groups: BTreeMap<String, Vec<String>>,
+    /// Bound of the queue of notifications waiting to be sent to each MCP client, and what
+    /// happens when a slow client lets it fill up
+    pub notification_queue: NotificationQueueConfig,
+    /// Limits on the MCP sessions served over HTTP
+    pub sessions: SessionConfig,
+    /// Load components from a compatible precompiled form in the
+    /// [`PRECOMPILED_DIR`](crate::PRECOMPILED_DIR) of the plugin directory instead of compiling
+    /// them. Off by default, since precompiled forms are native code that is run without being
+    /// validated
+    pub load_precompiled: bool,
+    /// Refuse to compile components, and only load those with a compatible precompiled form in
+    /// the [`PRECOMPILED_DIR`](crate::PRECOMPILED_DIR) of the plugin directory. Implies
+    /// [`load_precompiled`](Self::load_precompiled)
+    pub require_precompiled: bool,
+    /// Attach the policy bundled in a `.wassette-pkg` package when the package is loaded. Off by
+    /// default, since anyone who can load a package would otherwise grant its component whatever
+    /// the package's policy asks for.
+    pub attach_package_policies: bool,
+}
+
+impl LifecycleConfig {
+    /// Returns whether the component is hot, either because it is listed in
+    /// [`hot_components`](Self::hot_components) or because its tool metadata says so
+    pub fn is_hot(&self, component_id: &str, tool_metadata: Option<&Value>) -> bool {
+        self.hot_components
+            .iter()
+            .any(|id| crate::component_id::id_matches(id, component_id))
+            || tool_metadata
+                .and_then(|metadata| metadata.get("hot"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false)
+    }
+
+    /// Returns the sandbox group the component belongs to, the first by name that lists it
+    pub fn sandbox_group(&self, component_id: &str) -> Option<&str> {
+        self.sandbox_groups
+            .iter()
+            .find(|(_, components)| {
+                components
+                    .iter()
+                    .any(|id| crate::component_id::id_matches(id, component_id))
+            })
+            .map(|(group, _)| group.as_str())
+    }
+
+    /// Returns how long transcripts are kept for
+    pub fn transcript_retention(&self) -> Option<Duration> {
+        self.transcript_retention_days
+            .map(|days| Duration::from_secs(days.saturating_mul(24 * 60 * 60)))
+    }
+
+    /// Returns how long a call that needs approval waits for it
+    pub fn approval_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.approval_timeout_secs
+                .unwrap_or(crate::DEFAULT_APPROVAL_TIMEOUT_SECS),
+        )
+    }
+}
+
+/// Engine settings that make components compute the same results on every host, for deployments
+/// that audit or replay calls across machines. All are off by default, as they cost speed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeterminismConfig {
+    /// Replace every NaN that a float operation produces with the canonical NaN, so that NaN
+    /// bit patterns don't depend on the host's CPU
+    pub canonicalize_nans: bool,
+    /// Give relaxed SIMD instructions the same results on every host, instead of the fastest
+    /// results of the host's CPU
+    pub deterministic_relaxed_simd: bool,
+    /// Refuse to load components that use relaxed SIMD instructions at all
+    pub forbid_relaxed_simd: bool,
+}
+
+/// Limits on the MCP sessions served over HTTP, so that misbehaving clients can't exhaust the
+/// server by opening sessions and abandoning them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Largest number of sessions open at once. Clients that open another one are turned away
+    /// until one closes. Unlimited if unset
+    pub max_sessions: Option<usize>,
+    /// Number of seconds a session may go without exchanging a message with its client, while
+    /// none of its requests is in flight, before it is closed. Sessions are never closed for
+    /// being idle if unset
+    pub idle_timeout_secs: Option<u64>,
+}
+
+impl SessionConfig {
+    /// Returns how long a session may go without a message from its client
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_secs.map(Duration::from_secs)
+    }
+}
+
+/// Number of notifications queued per session unless
+/// [`NotificationQueueConfig::capacity`] is set
+pub const DEFAULT_NOTIFICATION_QUEUE_CAPACITY: usize = 256;
+
+/// Configures the queue of notifications, such as log messages and tool list changes, that each
+/// MCP session sends to its client
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationQueueConfig {
+    /// Largest number of notifications waiting to be sent to a client. Defaults to
+    /// [`DEFAULT_NOTIFICATION_QUEUE_CAPACITY`]
+    pub capacity: Option<usize>,
+    /// What happens to a notification that doesn't fit in the queue
+    pub overflow: NotificationOverflow,
+}
+
+impl NotificationQueueConfig {
+    /// Returns the largest number of notifications waiting to be sent to a client
+    pub fn capacity(&self) -> usize {
+        self.capacity
+            .unwrap_or(DEFAULT_NOTIFICATION_QUEUE_CAPACITY)
+            .max(1)
+    }
+}
+
+/// What happens when the notification queue of a session is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationOverflow {
+    /// The oldest queued log message is dropped to make room
+    #[default]
+    DropOldest,
+    /// The session is closed, so that the client reconnects and lists the tools again. Sessions
+    /// that can't be closed, such as over stdio, drop the oldest message instead
+    Disconnect,
+}
+
+/// Configures the worker pools that tool calls run on, apart from the runtime serving requests
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecutionConfig {
+    /// Number of threads running interactive calls. Defaults to the number of CPUs
+    pub interactive_workers: Option<usize>,
+    /// Number of threads running batch calls. Defaults to half the number of CPUs
+    pub batch_workers: Option<usize>,
+    /// IDs or names of components whose calls run on the batch pool, e.g. long-running
+    /// converters or indexers
+    pub batch_components: Vec<String>,
+}
+
+impl ExecutionConfig {
+    /// Returns the pool that calls to the component run on
+    pub fn class_of(&self, component_id: &str) -> ExecutionClass {
+        if self
+            .batch_components
+            .iter()
+            .any(|id| crate::component_id::id_matches(id, component_id))
+        {
+            ExecutionClass::Batch
+        } else {
+            ExecutionClass::Interactive
+        }
+    }
+}
+
+/// Configures the backend of `wasi:blobstore`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlobstoreConfig {
+    /// Where blobs are stored
+    pub backend: BlobstoreBackend,
+    /// Largest blob a component may write, in bytes. Defaults to
+    /// [`DEFAULT_MAX_BLOB_SIZE`](crate::DEFAULT_MAX_BLOB_SIZE)
+    pub max_object_size: Option<u64>,
+}
+
+/// Storage behind `wasi:blobstore`. Object storage backends read their credentials from the
+/// environment, e.g. `AWS_ACCESS_KEY_ID` or `AZURE_STORAGE_ACCESS_KEY`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlobstoreBackend {
+    /// The `blobs` directory in the plugin directory
+    #[default]
+    Scratch,
+    /// An S3 bucket, or a bucket of an S3-compatible service set with `AWS_ENDPOINT`. Needs the
+    /// `blobstore-s3` feature
+    S3 {
+        /// Name of the bucket
+        bucket: String,
+        /// Prefix of the keys of all blobs
+        #[serde(default)]
+        prefix: Option<String>,
+    },
+    /// A container of an Azure storage account. Needs the `blobstore-azure` feature
+    Azure {
+        /// Name of the storage account
+        account: String,
+        /// Name of the container in the storage account
+        container: String,
+        /// Prefix of the names of all blobs
+        #[serde(default)]
+        prefix: Option<String>,
+    },
+}
+
+/// A model that components can load by name with `wasi:nn`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AiModelConfig {
+    /// Name that components load the model by
+    pub name: String,
+    /// Encoding of the model, which selects the inference backend, e.g. `openvino` or `onnx`
+    pub encoding: String,
+    /// Directory containing the model files in the layout the backend expects
+    pub path: PathBuf,
+}
+
+/// Controls how MCP tool names are derived from the functions a component exports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolNamingConfig {
+    /// Regular expression matching the characters to replace in each part of a tool name
+    pub invalid_chars: String,
+    /// The text that replaces every match of `invalid_chars`
+    pub replacement: String,
+    /// Maximum length of a tool name. Longer names are truncated and given a hash suffix
+    pub max_length: usize,
+    /// Whether tool names are lowercased before sanitization
+    pub lowercase: bool,
+    /// Leave interface package versions out of tool names. Versions are kept when two exports
+    /// would otherwise only differ by version
+    pub strip_versions: bool,
+    /// Give a numeric suffix to tools whose names collide with a tool from another component,
+    /// instead of rejecting calls to the ambiguous name
+    pub dedup_across_components: bool,
+}
+
+impl Default for ToolNamingConfig {
+    fn default() -> Self {
+        Self {
+            invalid_chars: DEFAULT_INVALID_CHARS.to_string(),
+            replacement: "_".to_string(),
+            max_length: MAX_TOOL_NAME_LENGTH,
+            lowercase: true,
+            strip_versions: false,
+            dedup_across_components: false,
+        }
+    }
+}
+
+impl ToolNamingConfig {
+    /// Builds the sanitizer described by this configuration
+    pub fn sanitizer(&self) -> Result<ToolNameSanitizer> {
+        ToolNameSanitizer::new(
+            &self.invalid_chars,
+            &self.replacement,
+            self.max_length,
+            self.lowercase,
+        )
+        .map(|sanitizer| sanitizer.with_strip_versions(self.strip_versions))
+        .context("Invalid tool naming configuration")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_tool_naming_config_is_valid() {
+        let sanitizer = ToolNamingConfig::default().sanitizer().unwrap();
+        assert_eq!(sanitizer.max_length(), MAX_TOOL_NAME_LENGTH);
+    }
+
+    #[test]
+    fn test_tool_naming_config_partial_deserialize() {
+        let config: LifecycleConfig =
+            serde_json::from_str(r#"{"tool_naming": {"max_length": 64}}"#).unwrap();
+        assert_eq!(config.tool_naming.max_length, 64);
+        assert_eq!(config.tool_naming.replacement, "_");
+        assert!(!config.tool_naming.dedup_across_components);
+
+        let invalid = ToolNamingConfig {
+            max_length: 1,
+            ..Default::default()
+        };
+        assert!(invalid.sanitizer().is_err());
+    }
+
+    #[test]
+    fn test_is_hot() {
+        let config = LifecycleConfig {
+            hot_components: vec!["fetch_rs".to_string()],
+            ..Default::default()
+        };
+        assert!(config.is_hot("fetch_rs", None));
+        assert!(!config.is_hot("time_server", None));
+        assert!(config.is_hot("time_server", Some(&serde_json::json!({ "hot": true }))));
+        assert!(!config.is_hot("time_server", Some(&serde_json::json!({ "tools": {} }))));
+    }
+
+    #[test]
+    fn test_blobstore_config() {
+        assert_eq!(
+            LifecycleConfig::default().blobstore.backend,
+            BlobstoreBackend::Scratch
+        );
+        let config: BlobstoreConfig =
+            serde_json::from_str(r#"{"backend": {"type": "s3", "bucket": "blobs"}}"#).unwrap();
+        assert_eq!(
+            config.backend,
+            BlobstoreBackend::S3 {
+                bucket: "blobs".to_string(),
+                prefix: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_execution_class_of() {
+        let config: ExecutionConfig =
+            serde_json::from_str(r#"{"batch_components": ["indexer"]}"#).unwrap();
+        assert_eq!(config.interactive_workers, None);
+        assert_eq!(config.class_of("indexer"), ExecutionClass::Batch);
+        assert_eq!(config.class_of("fetch_rs"), ExecutionClass::Interactive);
+    }
+}
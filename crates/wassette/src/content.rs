@@ -0,0 +1,167 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Content-addressed storage of installed components.
+//!
+//! Every installed component is kept once per content in the [`CONTENT_DIR`] directory of the
+//! plugin directory, as `content/sha256/<digest>` named after the SHA-256 digest of the component,
+//! and the file at the component's ID is a hard link to that entry. A component installed under
+//! several IDs, e.g. from a registry and from a mirror, or as several versions of the same build,
+//! is stored once. The digest is recorded in the component's metadata, and the digest computed
+//! when the component is compiled at startup is checked against it, so a component that changed
+//! on disk is noticed without hashing it a second time. Entries no installed component uses are
+//! removed. Where the file system has no hard links, components stay separate copies.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tracing::debug;
+
+/// Directory in the plugin directory that installed components are stored in by digest
+pub const CONTENT_DIR: &str = "content";
+
+/// Returns the path of the entry of a digest in the content store
+pub(crate) fn content_path(plugin_dir: &Path, digest: &str) -> PathBuf {
+    plugin_dir.join(CONTENT_DIR).join("sha256").join(digest)
+}
+
+/// Adds an installed component to the content store. If the store already has an entry with the
+/// same digest, the component file is replaced by a link to it, otherwise the file becomes the
+/// entry.
+pub(crate) async fn store(plugin_dir: &Path, component_file: &Path, digest: &str) -> Result<()> {
+    let entry = content_path(plugin_dir, digest);
+    if let Some(dir) = entry.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    if tokio::fs::try_exists(&entry).await? {
+        // Linked next to the component and renamed over it, so that it never goes missing
+        let staged = component_file.with_extension("wasm.link");
+        let _ = tokio::fs::remove_file(&staged).await;
+        tokio::fs::hard_link(&entry, &staged)
+            .await
+            .with_context(|| format!("Failed to link {}", entry.display()))?;
+        tokio::fs::rename(&staged, component_file).await?;
+        debug!(digest, "Component already in the content store");
+    } else {
+        tokio::fs::hard_link(component_file, &entry)
+            .await
+            .with_context(|| format!("Failed to link {}", entry.display()))?;
+    }
+    Ok(())
+}
+
+/// Copies `src` to `dest` through a file next to it that is then renamed over `dest`. Writing to
+/// `dest` itself would change the content store entry it may be linked to, and with it every
+/// other component sharing the entry.
+pub(crate) fn replace_with_copy(src: &Path, dest: &Path) -> std::io::Result<()> {
+    let mut staged = dest.as_os_str().to_owned();
+    staged.push(".partial");
+    let staged = PathBuf::from(staged);
+    std::fs::copy(src, &staged)?;
+    std::fs::rename(&staged, dest).inspect_err(|_| {
+        let _ = std::fs::remove_file(&staged);
+    })
+}
+
+/// Removes the entry of a digest, once no installed component uses it
+pub(crate) async fn release(plugin_dir: &Path, digest: &str) -> Result<()> {
+    match tokio::fs::remove_file(content_path(plugin_dir, digest)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Removes the entries whose digests aren't in `in_use`, returning how many were removed
+pub(crate) async fn collect_garbage(plugin_dir: &Path, in_use: &HashSet<&str>) -> Result<usize> {
+    let dir = plugin_dir.join(CONTENT_DIR).join("sha256");
+    let mut entries = match tokio::fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut removed = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if !in_use.contains(name.to_string_lossy().as_ref()) {
+            tokio::fs::remove_file(entry.path()).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_identical_components_are_stored_once() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let first = dir.path().join("a.wasm");
+        let second = dir.path().join("b.wasm");
+        tokio::fs::write(&first, b"component").await?;
+        tokio::fs::write(&second, b"component").await?;
+
+        store(dir.path(), &first, "abc").await?;
+        store(dir.path(), &second, "abc").await?;
+        assert_eq!(
+            tokio::fs::read(content_path(dir.path(), "abc")).await?,
+            b"component"
+        );
+        assert_eq!(tokio::fs::read(&second).await?, b"component");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(tokio::fs::metadata(&first).await?.nlink(), 3);
+        }
+
+        // Removing a component leaves the entry to the other one
+        tokio::fs::remove_file(&first).await?;
+        assert_eq!(tokio::fs::read(&second).await?, b"component");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replacing_a_linked_component_keeps_the_entry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let first = dir.path().join("a.wasm");
+        let second = dir.path().join("b.wasm");
+        let update = dir.path().join("update.wasm");
+        tokio::fs::write(&first, b"component").await?;
+        tokio::fs::write(&second, b"component").await?;
+        tokio::fs::write(&update, b"new component").await?;
+        store(dir.path(), &first, "abc").await?;
+        store(dir.path(), &second, "abc").await?;
+
+        replace_with_copy(&update, &first)?;
+        assert_eq!(tokio::fs::read(&first).await?, b"new component");
+        assert_eq!(tokio::fs::read(&second).await?, b"component");
+        assert_eq!(
+            tokio::fs::read(content_path(dir.path(), "abc")).await?,
+            b"component"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unused_entries_are_collected() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for digest in ["used", "unused"] {
+            let file = dir.path().join(format!("{digest}.wasm"));
+            tokio::fs::write(&file, digest).await?;
+            store(dir.path(), &file, digest).await?;
+        }
+        assert_eq!(
+            collect_garbage(dir.path(), &HashSet::from(["used"])).await?,
+            1
+        );
+        assert!(content_path(dir.path(), "used").exists());
+        assert!(!content_path(dir.path(), "unused").exists());
+
+        release(dir.path(), "used").await?;
+        release(dir.path(), "used").await?;
+        assert!(!content_path(dir.path(), "used").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Capture of Wasm core dumps when a component traps.
+//!
+//! With [`LifecycleConfig::coredump_on_trap`](crate::LifecycleConfig::coredump_on_trap) set,
+//! wasmtime attaches the guest's stack and memory to trap errors. These are written to the
+//! `diagnostics` directory in the plugin directory, where tools such as `wasmgdb` can open them.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+use wasmtime::{Store, WasmCoreDump};
+
+use crate::http::WassetteWasiState;
+use crate::wasistate::WasiState;
+
+/// Directory in the plugin directory that core dumps are written to
+pub const DIAGNOSTICS_DIR: &str = "diagnostics";
+
+/// Error context of a call that trapped and whose core dump was written to disk. Find it with
+/// `error.downcast_ref::<CoreDump>()`.
+#[derive(Debug, Clone)]
+pub struct CoreDump {
+    /// The error the component trapped with
+    pub message: String,
+    /// Where the core dump was written to
+    pub path: PathBuf,
+}
+
+impl fmt::Display for CoreDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (core dump written to {})",
+            self.message,
+            self.path.display()
+        )
+    }
+}
+
+/// Writes the core dump attached to a trap, if there is one, and records its location in the
+/// returned error. Other errors are returned unchanged.
+pub(crate) async fn capture_core_dump(
+    error: anyhow::Error,
+    store: &mut Store<WassetteWasiState<WasiState>>,
+    plugin_dir: &Path,
+    component_id: &str,
+) -> anyhow::Error {
+    let Some(dump) = error.downcast_ref::<WasmCoreDump>() else {
+        return error;
+    };
+    let data = dump.serialize(&mut *store, component_id);
+    let path = core_dump_path(plugin_dir, component_id);
+    match write_core_dump(&path, data).await {
+        Ok(()) => {
+            info!(component_id, path = %path.display(), "Wrote core dump of trapped component");
+            let message = format!("{error:#}");
+            error.context(CoreDump { message, path })
+        }
+        Err(e) => {
+            warn!(component_id, error = %e, "Failed to write core dump");
+            error
+        }
+    }
+}
+
+async fn write_core_dump(path: &Path, data: Vec<u8>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .context("Failed to create diagnostics directory")?;
+    }
+    tokio::fs::write(path, data)
+        .await
+        .with_context(|| format!("Failed to write core dump: {}", path.display()))
+}
+
+fn core_dump_path(plugin_dir: &Path, component_id: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    plugin_dir
+        .join(DIAGNOSTICS_DIR)
+        .join(component_id)
+        .join(format!("{timestamp}.coredump"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_dump_context() {
+        let dump = CoreDump {
+            message: "wasm trap: unreachable".to_string(),
+            path: PathBuf::from("/plugins/diagnostics/fetch_rs-1.coredump"),
+        };
+        let error = anyhow::anyhow!("wasm trap: unreachable").context(dump);
+        assert_eq!(
+            error.to_string(),
+            "wasm trap: unreachable (core dump written to /plugins/diagnostics/fetch_rs-1.coredump)"
+        );
+        let found = error.downcast_ref::<CoreDump>().unwrap();
+        assert!(found.path.ends_with("fetch_rs-1.coredump"));
+        assert!(core_dump_path(Path::new("/plugins"), "local/fetch_rs")
+            .starts_with("/plugins/diagnostics/local/fetch_rs"));
+    }
+}
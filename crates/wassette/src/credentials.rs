@@ -0,0 +1,399 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Credentials for OCI registries, HTTP APIs and secrets providers, kept in the system keyring.
+//!
+//! Secrets never touch the disk in plain text. The plugin directory only holds an index of which
+//! credentials exist, since keyrings can't be enumerated portably.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use base64::Engine as _;
+use oci_client::secrets::RegistryAuth;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// Service name that credentials are stored under in the system keyring
+pub const KEYRING_SERVICE: &str = "wassette";
+
+/// Name of the file in the plugin directory that lists the stored credentials, without secrets
+pub const CREDENTIALS_INDEX_FILE: &str = "credentials.json";
+
+/// What a credential is used for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CredentialKind {
+    /// Username and password of an OCI registry, e.g. `ghcr.io`
+    #[default]
+    Registry,
+    /// Bearer token, or username and password for basic auth, sent to an HTTPS host that
+    /// components or policies are downloaded from
+    HttpToken,
+    /// Key of a secrets provider
+    Secret,
+}
+
+impl CredentialKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Registry => "registry",
+            Self::HttpToken => "http-token",
+            Self::Secret => "secret",
+        }
+    }
+}
+
+impl std::str::FromStr for CredentialKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "registry" => Ok(Self::Registry),
+            "http-token" => Ok(Self::HttpToken),
+            "secret" => Ok(Self::Secret),
+            _ => bail!("Unknown credential kind '{s}', expected registry, http-token or secret"),
+        }
+    }
+}
+
+impl std::fmt::Display for CredentialKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A stored credential, without its secret
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CredentialEntry {
+    /// What the credential is used for
+    pub kind: CredentialKind,
+    /// The registry, host or provider the credential belongs to
+    pub target: String,
+    /// The username, for registry credentials and HTTP basic auth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Credentials sent to an HTTPS host in the `Authorization` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum HttpAuth {
+    Bearer(String),
+    Basic { username: String, password: String },
+}
+
+impl HttpAuth {
+    /// Returns the value of the `Authorization` header
+    pub(crate) fn header_value(&self) -> String {
+        match self {
+            Self::Bearer(token) => format!("Bearer {token}"),
+            Self::Basic { username, password } => format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(format!("{username}:{password}"))
+            ),
+        }
+    }
+}
+
+/// Where secrets are kept. The system keyring outside of tests.
+pub(crate) trait SecretBackend: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<String>>;
+    fn set(&self, key: &str, secret: &str) -> Result<()>;
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+struct SystemKeyring;
+
+impl SecretBackend for SystemKeyring {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        match keyring::Entry::new(KEYRING_SERVICE, key)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e).context("Failed to read from the system keyring"),
+        }
+    }
+
+    fn set(&self, key: &str, secret: &str) -> Result<()> {
+        keyring::Entry::new(KEYRING_SERVICE, key)?
+            .set_password(secret)
+            .context("Failed to write to the system keyring")
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match keyring::Entry::new(KEYRING_SERVICE, key)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e).context("Failed to delete from the system keyring"),
+        }
+    }
+}
+
+/// Stores and looks up credentials. Lookups block on the keyring, so async code should go through
+/// [`CredentialStore::registry_auth`] and [`CredentialStore::http_auth`].
+#[derive(Clone)]
+pub struct CredentialStore {
+    index_path: PathBuf,
+    backend: Arc<dyn SecretBackend>,
+}
+
+impl CredentialStore {
+    /// Opens the credentials of the given plugin directory, backed by the system keyring
+    pub fn open(plugin_dir: impl AsRef<Path>) -> Self {
+        Self::with_backend(plugin_dir.as_ref(), Arc::new(SystemKeyring))
+    }
+
+    pub(crate) fn with_backend(plugin_dir: &Path, backend: Arc<dyn SecretBackend>) -> Self {
+        Self {
+            index_path: plugin_dir.join(CREDENTIALS_INDEX_FILE),
+            backend,
+        }
+    }
+
+    fn key(kind: CredentialKind, target: &str) -> String {
+        format!("{kind}:{target}")
+    }
+
+    /// Returns the stored credentials, without their secrets
+    pub fn list(&self) -> Result<Vec<CredentialEntry>> {
+        match std::fs::read(&self.index_path) {
+            Ok(data) => serde_json::from_slice(&data).with_context(|| {
+                format!("Invalid credentials index: {}", self.index_path.display())
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e).context("Failed to read credentials index"),
+        }
+    }
+
+    fn write_index(&self, mut entries: Vec<CredentialEntry>) -> Result<()> {
+        entries.sort_by(|a, b| (a.kind, &a.target).cmp(&(b.kind, &b.target)));
+        if let Some(dir) = self.index_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.index_path, serde_json::to_vec_pretty(&entries)?)
+            .context("Failed to write credentials index")
+    }
+
+    /// Stores a credential, replacing any earlier one for the same target. Registry credentials
+    /// need a username.
+    pub fn login(
+        &self,
+        kind: CredentialKind,
+        target: &str,
+        username: Option<&str>,
+        secret: &str,
+    ) -> Result<()> {
+        let target = target.trim();
+        if target.is_empty() {
+            bail!("A registry, host or provider name is required");
+        }
+        if kind == CredentialKind::Registry && username.is_none() {
+            bail!("Registry credentials need a username");
+        }
+        if secret.is_empty() {
+            bail!("The secret must not be empty");
+        }
+        self.backend.set(&Self::key(kind, target), secret)?;
+
+        let mut entries = self.list()?;
+        entries.retain(|entry| !(entry.kind == kind && entry.target == target));
+        entries.push(CredentialEntry {
+            kind,
+            target: target.to_string(),
+            username: username.map(String::from),
+        });
+        self.write_index(entries)
+    }
+
+    /// Removes a credential. Returns whether it existed.
+    pub fn logout(&self, kind: CredentialKind, target: &str) -> Result<bool> {
+        self.backend.delete(&Self::key(kind, target))?;
+        let mut entries = self.list()?;
+        let before = entries.len();
+        entries.retain(|entry| !(entry.kind == kind && entry.target == target));
+        let removed = entries.len() != before;
+        if removed {
+            self.write_index(entries)?;
+        }
+        Ok(removed)
+    }
+
+    /// Returns the username and secret of a credential, if one is stored
+    pub fn get(
+        &self,
+        kind: CredentialKind,
+        target: &str,
+    ) -> Result<Option<(Option<String>, String)>> {
+        let Some(entry) = self
+            .list()?
+            .into_iter()
+            .find(|entry| entry.kind == kind && entry.target == target)
+        else {
+            return Ok(None);
+        };
+        let secret = self.backend.get(&Self::key(kind, target))?;
+        Ok(secret.map(|secret| (entry.username, secret)))
+    }
+
+    /// Looks up a credential without blocking the runtime. Failures are logged and treated as
+    /// no credential, so that public resources still load.
+    async fn lookup(
+        &self,
+        kind: CredentialKind,
+        target: String,
+    ) -> Option<(Option<String>, String)> {
+        let store = self.clone();
+        let lookup_target = target.clone();
+        let result = tokio::task::spawn_blocking(move || store.get(kind, &lookup_target)).await;
+        match result {
+            Ok(Ok(credential)) => credential,
+            Ok(Err(e)) => {
+                warn!(kind = %kind, target = %target, error = %e, "Failed to look up credential");
+                None
+            }
+            Err(e) => {
+                warn!(kind = %kind, target = %target, error = %e, "Credential lookup panicked");
+                None
+            }
+        }
+    }
+
+    /// Returns the authentication for pulling the given OCI reference
+    pub(crate) async fn registry_auth(&self, reference: &str) -> RegistryAuth {
+        let Ok(reference) = reference.parse::<oci_client::Reference>() else {
+            return RegistryAuth::Anonymous;
+        };
+        match self
+            .lookup(CredentialKind::Registry, reference.registry().to_string())
+            .await
+        {
+            Some((Some(username), password)) => {
+                debug!(
+                    registry = reference.registry(),
+                    "Using stored registry credentials"
+                );
+                RegistryAuth::Basic(username, password)
+            }
+            _ => RegistryAuth::Anonymous,
+        }
+    }
+
+    /// Returns the credentials for downloading the given HTTPS URL, basic auth if a username is
+    /// stored with the token and a bearer token otherwise
+    pub(crate) async fn http_auth(&self, url: &str) -> Option<HttpAuth> {
+        let host = url::Url::parse(url).ok()?.host_str()?.to_string();
+        match self.lookup(CredentialKind::HttpToken, host).await? {
+            (Some(username), password) => Some(HttpAuth::Basic { username, password }),
+            (None, token) => Some(HttpAuth::Bearer(token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryBackend(Mutex<HashMap<String, String>>);
+
+    impl SecretBackend for MemoryBackend {
+        fn get(&self, key: &str) -> Result<Option<String>> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        fn set(&self, key: &str, secret: &str) -> Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), secret.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, key: &str) -> Result<()> {
+            self.0.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    fn store(dir: &Path) -> (CredentialStore, Arc<MemoryBackend>) {
+        let backend = Arc::new(MemoryBackend::default());
+        (CredentialStore::with_backend(dir, backend.clone()), backend)
+    }
+
+    #[test]
+    fn test_login_list_logout() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let (store, backend) = store(tempdir.path());
+
+        store.login(
+            CredentialKind::Registry,
+            "ghcr.io",
+            Some("octocat"),
+            "s3cret",
+        )?;
+        store.login(CredentialKind::HttpToken, "api.example.com", None, "token")?;
+        assert!(store
+            .login(CredentialKind::Registry, "docker.io", None, "s3cret")
+            .is_err());
+
+        // The index holds no secrets
+        let index = std::fs::read_to_string(tempdir.path().join(CREDENTIALS_INDEX_FILE))?;
+        assert!(!index.contains("s3cret"));
+        assert_eq!(store.list()?.len(), 2);
+        assert_eq!(
+            store.get(CredentialKind::Registry, "ghcr.io")?,
+            Some((Some("octocat".to_string()), "s3cret".to_string()))
+        );
+        assert_eq!(
+            backend.get("http-token:api.example.com")?,
+            Some("token".to_string())
+        );
+
+        assert!(store.logout(CredentialKind::Registry, "ghcr.io")?);
+        assert!(!store.logout(CredentialKind::Registry, "ghcr.io")?);
+        assert_eq!(store.get(CredentialKind::Registry, "ghcr.io")?, None);
+        assert_eq!(store.list()?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_registry_auth() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let (store, _) = store(tempdir.path());
+        store.login(
+            CredentialKind::Registry,
+            "ghcr.io",
+            Some("octocat"),
+            "s3cret",
+        )?;
+        store.login(CredentialKind::HttpToken, "example.com", None, "token")?;
+        store.login(
+            CredentialKind::HttpToken,
+            "artifacts.example.org",
+            Some("deploy"),
+            "s3cret",
+        )?;
+
+        assert!(matches!(
+            store.registry_auth("ghcr.io/microsoft/fetch-rs:latest").await,
+            RegistryAuth::Basic(username, password) if username == "octocat" && password == "s3cret"
+        ));
+        assert!(matches!(
+            store.registry_auth("docker.io/library/hello:latest").await,
+            RegistryAuth::Anonymous
+        ));
+        assert_eq!(
+            store.http_auth("https://example.com/fetch.wasm").await,
+            Some(HttpAuth::Bearer("token".to_string()))
+        );
+        let basic = store
+            .http_auth("https://artifacts.example.org/libs/fetch.wasm")
+            .await
+            .unwrap();
+        assert_eq!(basic.header_value(), "Basic ZGVwbG95OnMzY3JldA==");
+        assert_eq!(store.http_auth("https://other.com/fetch.wasm").await, None);
+        Ok(())
+    }
+}
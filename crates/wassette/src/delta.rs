@@ -0,0 +1,175 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Delta updates of components pulled from OCI registries.
+//!
+//! When a component is already installed and a newer version is pulled by tag, the registry is
+//! asked for a delta from the installed version before the full component is downloaded. A delta
+//! for `<repository>:<tag>` is published as the artifact `<repository>:<tag>-delta-<base>`, where
+//! `<base>` is the first 16 hex digits of the SHA-256 digest of the old component, with a single
+//! layer of type [`DELTA_MEDIA_TYPE`]. The layer is a zstd frame compressed with the old component
+//! as reference prefix, which is what `zstd --patch-from=old.wasm new.wasm` produces. The patched
+//! component must match the digest of the component layer of `<repository>:<tag>`; anything else
+//! falls back to a full pull.
+
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use oci_client::manifest::OciDescriptor;
+use oci_client::secrets::RegistryAuth;
+use oci_client::Reference;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info};
+
+/// Media type of the layer of a delta artifact
+pub const DELTA_MEDIA_TYPE: &str = "application/vnd.wassette.component.delta.v1+zstd";
+
+/// Number of hex digits of the base digest in the tag of a delta
+const DELTA_TAG_DIGITS: usize = 16;
+
+/// Largest back-reference distance a delta may use, as a power of two
+const MAX_WINDOW_LOG: u32 = 31;
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+/// Returns the tag of the delta from the component with the given digest to the given tag
+pub fn delta_tag(tag: &str, base_digest: &str) -> String {
+    let hex = base_digest.strip_prefix("sha256:").unwrap_or(base_digest);
+    format!("{tag}-delta-{}", &hex[..hex.len().min(DELTA_TAG_DIGITS)])
+}
+
+/// Creates a delta that turns `base` into `target`, e.g. to publish along with a new version
+pub fn create_delta(base: &[u8], target: &[u8]) -> Result<Vec<u8>> {
+    let size = base.len().max(target.len()).max(1);
+    let window_log = (usize::BITS - (size - 1).leading_zeros()).clamp(10, 30);
+    let mut encoder = zstd::stream::write::Encoder::with_ref_prefix(Vec::new(), 19, base)?;
+    encoder.window_log(window_log)?;
+    encoder.long_distance_matching(true)?;
+    std::io::Write::write_all(&mut encoder, target)?;
+    Ok(encoder.finish()?)
+}
+
+/// Applies a delta created from `base`
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = zstd::stream::read::Decoder::with_ref_prefix(delta, base)?;
+    decoder.window_log_max(MAX_WINDOW_LOG)?;
+    let mut target = Vec::new();
+    decoder
+        .read_to_end(&mut target)
+        .context("Failed to apply delta")?;
+    Ok(target)
+}
+
+/// Pulls the component of `reference` as a delta from the installed one. Returns `None` when
+/// nothing is installed, the reference has no tag, or the registry has no matching delta.
+pub(crate) async fn pull_with_delta(
+    client: &oci_client::Client,
+    reference: &Reference,
+    auth: &RegistryAuth,
+    installed: &Path,
+) -> Result<Option<Vec<u8>>> {
+    let (Some(tag), None) = (reference.tag(), reference.digest()) else {
+        return Ok(None);
+    };
+    let Ok(base) = crate::compression::read_component(installed).await else {
+        return Ok(None);
+    };
+
+    let (manifest, _) =
+        crate::oci_manifest::pull_component_manifest(client, reference, auth).await?;
+    let layer = crate::oci_manifest::component_layer(&manifest)?;
+    let base_digest = sha256_digest(&base);
+    if base_digest == layer.digest {
+        debug!(reference = %reference, "Installed component is up to date");
+        return Ok(Some(base));
+    }
+
+    let delta_reference = Reference::with_tag(
+        reference.registry().to_string(),
+        reference.repository().to_string(),
+        delta_tag(tag, &base_digest),
+    );
+    let delta_manifest = match client.pull_image_manifest(&delta_reference, auth).await {
+        Ok((manifest, _)) => manifest,
+        Err(e) => {
+            debug!(reference = %delta_reference, error = %e, "No delta available");
+            return Ok(None);
+        }
+    };
+    let Some(delta_layer) = delta_manifest
+        .layers
+        .iter()
+        .find(|layer| layer.media_type == DELTA_MEDIA_TYPE)
+    else {
+        return Ok(None);
+    };
+
+    let delta = pull_verified_blob(client, &delta_reference, delta_layer).await?;
+    let delta_size = delta.len();
+    let target = tokio::task::spawn_blocking(move || apply_delta(&base, &delta)).await??;
+    if sha256_digest(&target) != layer.digest {
+        bail!(
+            "Delta {} does not produce component {}",
+            delta_reference,
+            layer.digest
+        );
+    }
+    info!(
+        reference = %reference,
+        delta_size,
+        component_size = target.len(),
+        "Updated component from delta"
+    );
+    Ok(Some(target))
+}
+
+/// Pulls a layer of `reference`, failing if it doesn't match the layer's digest
+pub(crate) async fn pull_verified_blob(
+    client: &oci_client::Client,
+    reference: &Reference,
+    layer: &OciDescriptor,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    client.pull_blob(reference, layer, &mut data).await?;
+    if sha256_digest(&data) != layer.digest {
+        bail!("Digest mismatch for layer {}", layer.digest);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_roundtrip() -> Result<()> {
+        let base: Vec<u8> = (0..200_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let mut target = base.clone();
+        target[1000..1010].copy_from_slice(b"new export");
+        target.extend_from_slice(b"appended section");
+
+        let delta = create_delta(&base, &target)?;
+        assert!(
+            delta.len() < target.len() / 100,
+            "delta is {} bytes",
+            delta.len()
+        );
+        assert_eq!(apply_delta(&base, &delta)?, target);
+        // A delta only applies to the base it was created from
+        assert_ne!(apply_delta(&target, &delta).ok(), Some(target));
+        Ok(())
+    }
+
+    #[test]
+    fn test_delta_tag() {
+        assert_eq!(
+            delta_tag("1.2.0", "sha256:0123456789abcdef0123456789abcdef"),
+            "1.2.0-delta-0123456789abcdef"
+        );
+    }
+}
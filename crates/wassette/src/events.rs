@@ -0,0 +1,240 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Events about components, policies and tool calls that embedders can subscribe to instead of
+//! polling the lifecycle manager.
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::LifecycleManager;
+
+/// Number of events a subscriber can fall behind before it misses the oldest ones
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Something that happened in a [`LifecycleManager`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LifecycleEvent {
+    /// A component was loaded, or replaced a loaded component with the same ID
+    ComponentLoaded {
+        /// ID of the component
+        component_id: String,
+        /// Whether a loaded component was replaced
+        replaced: bool,
+        /// URI the component was loaded from, unless it was picked up from the plugin directory
+        #[serde(skip_serializing_if = "Option::is_none")]
+        source_uri: Option<String>,
+    },
+    /// A component was unloaded
+    ComponentUnloaded {
+        /// ID of the component
+        component_id: String,
+    },
+    /// The policy of a component was attached, changed or detached
+    PolicyChanged {
+        /// ID of the component
+        component_id: String,
+    },
+    /// A tool call is waiting for approval before it starts
+    ApprovalRequested {
+        /// ID that the call is approved or denied by
+        approval_id: String,
+        /// ID of the component
+        component_id: String,
+        /// Name of the tool
+        tool: String,
+        /// ID of the request that made the call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+    },
+    /// A tool call started
+    ExecutionStarted {
+        /// ID of the component
+        component_id: String,
+        /// Name of the tool
+        tool: String,
+        /// ID of the request that made the call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        /// ID of the session that made the call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+    },
+    /// A tool call returned a result
+    ExecutionFinished {
+        /// ID of the component
+        component_id: String,
+        /// Name of the tool
+        tool: String,
+        /// ID of the request that made the call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        /// How long the call took, in milliseconds
+        duration_ms: u64,
+    },
+    /// A tool call failed
+    ExecutionFailed {
+        /// ID of the component
+        component_id: String,
+        /// Name of the tool
+        tool: String,
+        /// ID of the request that made the call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        /// How long the call took, in milliseconds
+        duration_ms: u64,
+        /// Why the call failed
+        error: String,
+    },
+    /// A component sent an HTTP request, or tried to and was stopped by its policy. Only sent to
+    /// subscribers of [`LifecycleManager::subscribe_network`], since components can send many
+    NetworkRequested {
+        /// ID of the component
+        component_id: String,
+        /// Host the request was sent to
+        host: String,
+        /// Whether the policy allowed the request
+        allowed: bool,
+        /// ID of the request that made the call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        /// ID of the session that made the call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+    },
+    /// A component published an event through `wassette:events/publisher` during a tool call
+    EventPublished {
+        /// ID of the component
+        component_id: String,
+        /// Topic of the event
+        topic: String,
+        /// Data of the event, as JSON if the component published JSON and as a string otherwise
+        data: serde_json::Value,
+        /// ID of the request that made the call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        /// ID of the session that made the call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+    },
+}
+
+impl LifecycleManager {
+    /// Subscribes to the events of this lifecycle manager. Only events sent after subscribing are
+    /// received, and a subscriber that falls more than [`EVENT_CHANNEL_CAPACITY`] events behind
+    /// gets [`broadcast::error::RecvError::Lagged`] and misses the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<LifecycleEvent> {
+        self.events.subscribe()
+    }
+
+    /// Subscribes to the [`LifecycleEvent::NetworkRequested`] events of this lifecycle manager,
+    /// which [`subscribe`](Self::subscribe) doesn't receive. Requests are only reported to
+    /// calls that start while there is a subscriber. Falling behind works as for `subscribe`.
+    pub fn subscribe_network(&self) -> broadcast::Receiver<LifecycleEvent> {
+        self.network_events.subscribe()
+    }
+
+    pub(crate) fn emit(&self, event: LifecycleEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[tokio::test]
+    async fn test_subscribe_receives_events() -> anyhow::Result<()> {
+        let manager = create_test_manager().await?;
+        let mut events = manager.subscribe();
+
+        manager.load_test_component().await?;
+        assert_eq!(
+            events.recv().await?,
+            LifecycleEvent::ComponentLoaded {
+                component_id: TEST_COMPONENT_ID.to_string(),
+                replaced: false,
+                source_uri: Some(format!(
+                    "file://{}",
+                    build_example_component().await?.display()
+                )),
+            }
+        );
+
+        // The call may fail without network access, but it is reported either way
+        let _ = manager
+            .execute_component_call(
+                TEST_COMPONENT_ID,
+                "fetch",
+                r#"{"url": "https://example.com"}"#,
+            )
+            .await;
+        assert!(matches!(
+            events.recv().await?,
+            LifecycleEvent::ExecutionStarted { tool, .. } if tool == "fetch"
+        ));
+        assert!(matches!(
+            events.recv().await?,
+            LifecycleEvent::ExecutionFinished { .. } | LifecycleEvent::ExecutionFailed { .. }
+        ));
+
+        manager.unload_component(TEST_COMPONENT_ID).await?;
+        assert_eq!(
+            events.recv().await?,
+            LifecycleEvent::ComponentUnloaded {
+                component_id: TEST_COMPONENT_ID.to_string(),
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_network_requests_have_their_own_channel() -> anyhow::Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let mut events = manager.subscribe();
+        let mut network = manager.subscribe_network();
+
+        let _ = manager
+            .execute_component_call(
+                TEST_COMPONENT_ID,
+                "fetch",
+                r#"{"url": "https://example.com"}"#,
+            )
+            .await;
+        assert!(matches!(
+            network.try_recv()?,
+            LifecycleEvent::NetworkRequested { component_id, host, .. }
+                if component_id == TEST_COMPONENT_ID && host == "example.com"
+        ));
+        while let Ok(event) = events.try_recv() {
+            assert!(
+                !matches!(event, LifecycleEvent::NetworkRequested { .. }),
+                "network request on the shared channel"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = LifecycleEvent::ExecutionStarted {
+            component_id: "fetch_rs".to_string(),
+            tool: "fetch".to_string(),
+            request_id: Some("42".to_string()),
+            session_id: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "type": "execution_started",
+                "component_id": "fetch_rs",
+                "tool": "fetch",
+                "request_id": "42",
+            })
+        );
+    }
+}
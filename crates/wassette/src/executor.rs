@@ -0,0 +1,180 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Dedicated worker pools that tool calls run on.
+//!
+//! Wasm code runs until it returns or awaits I/O, so a long computation occupies a runtime thread
+//! for as long as it runs. Calls are therefore kept off the runtime serving MCP requests and run
+//! on one of two pools instead: `interactive` for calls an agent is waiting on and `batch` for the
+//! components listed in [`ExecutionConfig::batch_components`], so that a burst of batch work
+//! can't delay interactive calls, and neither can delay listing or loading components.
+
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::config::ExecutionConfig;
+
+/// The pool a tool call runs on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionClass {
+    /// Calls a client is waiting on
+    #[default]
+    Interactive,
+    /// Long-running or bulk calls, which get fewer threads
+    Batch,
+}
+
+impl std::fmt::Display for ExecutionClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Interactive => "interactive",
+            Self::Batch => "batch",
+        })
+    }
+}
+
+/// Returns the number of threads a pool gets unless configured
+pub(crate) fn default_workers(class: ExecutionClass) -> usize {
+    let cpus = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    match class {
+        ExecutionClass::Interactive => cpus,
+        ExecutionClass::Batch => (cpus / 2).max(1),
+    }
+}
+
+/// A runtime that is started on first use, so that a manager that never calls a tool doesn't
+/// start any threads
+struct Pool {
+    class: ExecutionClass,
+    workers: usize,
+    runtime: OnceLock<Runtime>,
+}
+
+impl Pool {
+    fn new(class: ExecutionClass, workers: Option<usize>) -> Self {
+        Self {
+            class,
+            workers: workers.unwrap_or_else(|| default_workers(class)).max(1),
+            runtime: OnceLock::new(),
+        }
+    }
+
+    fn runtime(&self) -> &Runtime {
+        self.runtime.get_or_init(|| {
+            info!(class = %self.class, workers = self.workers, "Starting execution pool");
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(self.workers)
+                .thread_name(format!("wassette-{}", self.class))
+                .enable_all()
+                .build()
+                .expect("failed to start execution pool")
+        })
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which panics on the async runtime the manager is dropped on
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// The interactive and batch pools of a [`LifecycleManager`](crate::LifecycleManager)
+pub(crate) struct ExecutionPools {
+    interactive: Pool,
+    batch: Pool,
+}
+
+impl ExecutionPools {
+    pub(crate) fn new(config: &ExecutionConfig) -> Self {
+        Self {
+            interactive: Pool::new(ExecutionClass::Interactive, config.interactive_workers),
+            batch: Pool::new(ExecutionClass::Batch, config.batch_workers),
+        }
+    }
+
+    /// Runs `future` on the pool of `class` and waits for its output. The future is aborted if
+    /// the returned future is dropped, as when a client cancels the request, and a panic is
+    /// passed on to the caller.
+    pub(crate) async fn run<F>(&self, class: ExecutionClass, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let pool = match class {
+            ExecutionClass::Interactive => &self.interactive,
+            ExecutionClass::Batch => &self.batch,
+        };
+        let mut task = AbortOnDrop(pool.runtime().spawn(future));
+        match (&mut task.0).await {
+            Ok(output) => output,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => panic!("execution pool task was cancelled: {e}"),
+        }
+    }
+}
+
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_on_pool() {
+        let pools = ExecutionPools::new(&ExecutionConfig {
+            interactive_workers: Some(1),
+            batch_workers: Some(1),
+            ..Default::default()
+        });
+        for class in [ExecutionClass::Interactive, ExecutionClass::Batch] {
+            let thread = pools
+                .run(class, async {
+                    std::thread::current().name().map(str::to_string)
+                })
+                .await;
+            assert_eq!(thread, Some(format!("wassette-{class}")));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_aborts_when_dropped() {
+        let pools = ExecutionPools::new(&ExecutionConfig::default());
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let call = pools.run(ExecutionClass::Batch, async move {
+            let _tx = tx;
+            std::future::pending::<()>().await
+        });
+        // The task holds the sender until it is aborted
+        assert!(
+            tokio::time::timeout(std::time::Duration::from_millis(50), call)
+                .await
+                .is_err()
+        );
+        assert!(rx.await.is_err());
+    }
+
+    #[test]
+    fn test_default_workers() {
+        assert!(default_workers(ExecutionClass::Interactive) >= 1);
+        assert!(default_workers(ExecutionClass::Batch) >= 1);
+        assert!(
+            default_workers(ExecutionClass::Batch) <= default_workers(ExecutionClass::Interactive)
+        );
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Components and policies loaded from git repositories.
+//!
+//! `git+https://github.com/org/tools.git#v1.2.0:dist/time.wasm` loads `dist/time.wasm` as of the
+//! tag, branch or commit `v1.2.0`. The ref is resolved to a commit with `git ls-remote`, then that
+//! commit alone is fetched, without history, and the file is read from it without checking the
+//! repository out. The fetched commit has to be the one the ref was resolved to. Files are cached
+//! by commit in the `cache/git` directory of the plugin directory, so a ref that still points to
+//! the same commit is loaded without fetching again. The token or username and password stored for
+//! the host with `wassette auth login --kind http-token` are sent along. Needs `git` on the `PATH`.
+
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+
+use anyhow::{bail, Context, Result};
+use tracing::{debug, info};
+
+use crate::credentials::HttpAuth;
+
+/// Scheme of git URIs
+pub const GIT_SCHEME: &str = "git+https";
+
+/// Directory in the plugin directory that fetched files are cached in, by commit
+pub(crate) const CACHE_DIR: &str = "cache/git";
+
+const FORMAT_HINT: &str = "Git URIs have the form git+https://host/repository#ref:path/to/file";
+
+/// A file in a git repository as of a ref
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GitSource {
+    /// HTTPS URL of the repository
+    pub(crate) repository: String,
+    /// Tag, branch or commit
+    pub(crate) reference: String,
+    /// Path of the file in the repository
+    pub(crate) path: String,
+}
+
+impl GitSource {
+    pub(crate) fn parse(uri: &str) -> Result<Self> {
+        let repository_and_fragment = uri
+            .trim()
+            .strip_prefix("git+")
+            .filter(|rest| rest.starts_with("https://"))
+            .context(FORMAT_HINT)?;
+        let (repository, fragment) = repository_and_fragment
+            .split_once('#')
+            .context(FORMAT_HINT)?;
+        let (reference, path) = fragment.split_once(':').context(FORMAT_HINT)?;
+
+        let url = url::Url::parse(repository).context("Invalid git repository URL")?;
+        if url.host_str().is_none() {
+            bail!("Git repository URL has no host: {repository}");
+        }
+        // The ref is passed to git, so it must not be mistaken for an option
+        if reference.is_empty()
+            || reference.starts_with('-')
+            || reference
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control())
+        {
+            bail!("Invalid git ref: {reference:?}");
+        }
+        let relative = Path::new(path);
+        if path.is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            bail!("Invalid path in git repository: {path:?}");
+        }
+        Ok(Self {
+            repository: repository.to_string(),
+            reference: reference.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// Returns the host of the repository
+    pub(crate) fn host(&self) -> Option<String> {
+        Some(
+            url::Url::parse(&self.repository)
+                .ok()?
+                .host_str()?
+                .to_string(),
+        )
+    }
+}
+
+/// Returns the file of a git source, fetching it unless it is cached for the commit its ref
+/// points to
+pub(crate) async fn fetch(
+    source: &GitSource,
+    auth: Option<&HttpAuth>,
+    plugin_dir: &Path,
+) -> Result<PathBuf> {
+    let commit = resolve(source, auth).await?;
+    let cached = plugin_dir.join(CACHE_DIR).join(&commit).join(&source.path);
+    if tokio::fs::try_exists(&cached).await.unwrap_or(false) {
+        debug!(repository = %source.repository, commit, path = %source.path, "Using cached git file");
+        return Ok(cached);
+    }
+
+    let checkout = tokio::task::spawn_blocking(tempfile::tempdir).await??;
+    git(&["init", "--quiet"], Some(checkout.path()), None).await?;
+    // A commit is fetched as it is, other refs by name so that servers that don't serve
+    // arbitrary commits work too
+    let target = if is_commit(&source.reference) {
+        &commit
+    } else {
+        &source.reference
+    };
+    git(
+        &[
+            "fetch",
+            "--quiet",
+            "--depth",
+            "1",
+            "--no-tags",
+            &source.repository,
+            target,
+        ],
+        Some(checkout.path()),
+        auth,
+    )
+    .await
+    .with_context(|| format!("Failed to fetch {} from {}", target, source.repository))?;
+    let fetched = git(
+        &["rev-parse", "FETCH_HEAD^{commit}"],
+        Some(checkout.path()),
+        None,
+    )
+    .await?;
+    let fetched = String::from_utf8_lossy(&fetched).trim().to_string();
+    if fetched != commit {
+        bail!(
+            "{} pointed to {commit} but {fetched} was fetched; the ref moved while loading",
+            source.reference
+        );
+    }
+    let data = git(
+        &["cat-file", "blob", &format!("{commit}:{}", source.path)],
+        Some(checkout.path()),
+        None,
+    )
+    .await
+    .with_context(|| format!("{} does not exist at {}", source.path, source.reference))?;
+
+    let parent = cached.parent().context("Cached git file has no parent")?;
+    tokio::fs::create_dir_all(parent).await?;
+    let partial = cached.with_extension("partial");
+    tokio::fs::write(&partial, &data).await?;
+    tokio::fs::rename(&partial, &cached).await?;
+    info!(repository = %source.repository, reference = %source.reference, commit, path = %source.path, "Fetched file from git");
+    Ok(cached)
+}
+
+/// Resolves the ref of a source to the commit it points to
+async fn resolve(source: &GitSource, auth: Option<&HttpAuth>) -> Result<String> {
+    if is_commit(&source.reference) {
+        return Ok(source.reference.to_ascii_lowercase());
+    }
+    let refs = git(
+        &["ls-remote", &source.repository, &source.reference],
+        None,
+        auth,
+    )
+    .await
+    .with_context(|| format!("Failed to list the refs of {}", source.repository))?;
+    pick_ref(&String::from_utf8_lossy(&refs), &source.reference).with_context(|| {
+        format!(
+            "{} has no tag or branch {}",
+            source.repository, source.reference
+        )
+    })
+}
+
+/// Picks the commit of a ref from the output of `git ls-remote`, preferring tags to branches.
+/// Annotated tags are listed twice, the second time peeled to their commit.
+fn pick_ref(ls_remote: &str, reference: &str) -> Option<String> {
+    let refs: Vec<(&str, &str)> = ls_remote
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .collect();
+    [
+        format!("refs/tags/{reference}^{{}}"),
+        format!("refs/tags/{reference}"),
+        format!("refs/heads/{reference}"),
+        reference.to_string(),
+    ]
+    .iter()
+    .find_map(|name| {
+        refs.iter()
+            .find(|(_, listed)| *listed == name.as_str())
+            .map(|(commit, _)| commit.to_string())
+    })
+}
+
+/// Returns whether a ref is a full commit ID
+fn is_commit(reference: &str) -> bool {
+    reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Runs git and returns what it printed, failing with its error output if it fails
+async fn git(args: &[&str], dir: Option<&Path>, auth: Option<&HttpAuth>) -> Result<Vec<u8>> {
+    let mut command = tokio::process::Command::new("git");
+    if let Some(auth) = auth {
+        // Passed through the environment rather than `-c`, which would show it in the process list
+        command
+            .env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", "http.extraHeader")
+            .env(
+                "GIT_CONFIG_VALUE_0",
+                format!("Authorization: {}", auth.header_value()),
+            );
+    }
+    command
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command
+        .output()
+        .await
+        .context("Failed to run git, which loading from git repositories needs")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_git_uri() -> Result<()> {
+        let source =
+            GitSource::parse("git+https://github.com/org/tools.git#v1.2.0:dist/time.wasm")?;
+        assert_eq!(
+            source,
+            GitSource {
+                repository: "https://github.com/org/tools.git".to_string(),
+                reference: "v1.2.0".to_string(),
+                path: "dist/time.wasm".to_string(),
+            }
+        );
+        assert_eq!(source.host().as_deref(), Some("github.com"));
+
+        for invalid in [
+            "git+https://github.com/org/tools.git",
+            "git+https://github.com/org/tools.git#v1.2.0",
+            "git+http://github.com/org/tools.git#main:time.wasm",
+            "git+https://github.com/org/tools.git#--upload-pack=x:time.wasm",
+            "git+https://github.com/org/tools.git#main:../time.wasm",
+            "git+https://github.com/org/tools.git#main:/etc/time.wasm",
+            "git+https://github.com/org/tools.git#main:",
+        ] {
+            assert!(GitSource::parse(invalid).is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_pick_ref() {
+        let ls_remote = "\
+1111111111111111111111111111111111111111\trefs/heads/v1
+2222222222222222222222222222222222222222\trefs/tags/v1
+3333333333333333333333333333333333333333\trefs/tags/v1^{}
+4444444444444444444444444444444444444444\trefs/heads/main
+";
+        assert_eq!(
+            pick_ref(ls_remote, "v1").as_deref(),
+            Some("3333333333333333333333333333333333333333")
+        );
+        assert_eq!(
+            pick_ref(ls_remote, "main").as_deref(),
+            Some("4444444444444444444444444444444444444444")
+        );
+        assert_eq!(
+            pick_ref(ls_remote, "refs/heads/v1").as_deref(),
+            Some("1111111111111111111111111111111111111111")
+        );
+        assert_eq!(pick_ref(ls_remote, "release"), None);
+    }
+
+    #[test]
+    fn test_is_commit() {
+        assert!(is_commit("0123456789abcdef0123456789ABCDEF01234567"));
+        assert!(!is_commit("0123456"));
+        assert!(!is_commit("main"));
+    }
+}
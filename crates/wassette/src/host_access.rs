@@ -0,0 +1,129 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The host directories and TCP ports that the policies of all components in a plugin directory
+//! grant, taken together, so that an OS sandbox around the whole server can be limited to them.
+//! The policy of the host tools counts too, as they read files and fetch URLs in the server
+//! process itself.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use url::Url;
+
+use crate::component_id;
+use crate::policy_internal::read_policy_template;
+
+/// Host directories and ports granted to at least one component of a plugin directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GrantedAccess {
+    /// Directories that some component may only read
+    pub read: BTreeSet<PathBuf>,
+    /// Directories that some component may write to
+    pub write: BTreeSet<PathBuf>,
+    /// TCP ports of the hosts that some component may connect to, besides 443 and 80, which
+    /// hosts granted without a port or scheme use
+    pub ports: BTreeSet<u16>,
+}
+
+/// Reads the policies of the components in the plugin directory and returns the union of the
+/// directories and ports they grant. Fails if a policy can't be read, as the result would be
+/// incomplete.
+pub async fn granted_access(plugin_dir: &Path) -> Result<GrantedAccess> {
+    let mut granted = GrantedAccess::default();
+    if !tokio::fs::try_exists(plugin_dir).await? {
+        return Ok(granted);
+    }
+    let ids = component_id::component_files(plugin_dir)
+        .await?
+        .into_iter()
+        .map(|(id, _)| id)
+        .chain([crate::HOST_TOOLS_ID.to_string()]);
+    for id in ids {
+        let Some(template) = read_policy_template(plugin_dir, &id)
+            .await
+            .with_context(|| format!("Failed to read the policy of {id}"))?
+        else {
+            continue;
+        };
+        for dir in template.preopened_dirs {
+            if dir.file_perms.contains(wasmtime_wasi::FilePerms::WRITE)
+                || dir.dir_perms.contains(wasmtime_wasi::DirPerms::MUTATE)
+            {
+                granted.write.insert(dir.host_path);
+            } else {
+                granted.read.insert(dir.host_path);
+            }
+        }
+        granted.ports.extend(
+            template
+                .allowed_hosts
+                .iter()
+                .filter_map(|host| granted_port(host)),
+        );
+    }
+    // Directories that some component may write to don't need to be listed as readable too
+    granted.read.retain(|path| !granted.write.contains(path));
+    Ok(granted)
+}
+
+/// Returns the port that a host of a network grant names, either explicitly or through its
+/// scheme, e.g. 8443 for `api.example.com:8443` and 443 for `https://api.example.com`
+fn granted_port(host: &str) -> Option<u16> {
+    if host.contains("://") {
+        Url::parse(host).ok()?.port_or_known_default()
+    } else {
+        Url::parse(&format!("http://{host}")).ok()?.port()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_granted_access() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let local = dir.path().join("local");
+        tokio::fs::create_dir(&local).await?;
+        tokio::fs::write(local.join("fetch.wasm"), b"component").await?;
+        tokio::fs::write(
+            local.join("fetch.policy.yaml"),
+            r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs:///srv/reports"
+        access: ["read"]
+      - uri: "fs://workspace"
+        access: ["read", "write"]
+  network:
+    allow:
+      - host: "api.example.com"
+      - host: "https://registry.example.com:8443"
+      - host: "metrics.example.com:9090"
+"#,
+        )
+        .await?;
+        tokio::fs::write(local.join("echo.wasm"), b"component").await?;
+
+        let paths = granted_access(dir.path()).await?;
+        assert_eq!(
+            paths.read.into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/srv/reports")]
+        );
+        assert_eq!(
+            paths.write.into_iter().collect::<Vec<_>>(),
+            vec![dir.path().join("workspace")]
+        );
+        assert_eq!(paths.ports, BTreeSet::from([8443, 9090]));
+
+        assert_eq!(
+            granted_access(&dir.path().join("missing")).await?,
+            GrantedAccess::default()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Tools implemented by the host rather than by a component.
+//!
+//! So that agents can fetch a web page or read a file before any component is installed, the
+//! server offers `fetch-url` and `read-file` as the tools of the [`HOST_TOOLS_ID`] component. They
+//! are listed and called like the tools of any component and follow the same policy model: their
+//! policy is stored as `builtin/host.policy.yaml` in the plugin directory and is managed with the
+//! usual permission tools. `fetch-url` only reaches the hosts the network section allows, also
+//! when following redirects, and `read-file` only reads files below the directories the storage
+//! section grants read access to. Budgets, approvals and time windows apply as well. Without a
+//! policy, both tools are denied everything. Setting `disable_host_tools` in the configuration
+//! leaves them out.
+
+use std::path::{Component, Path};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use component2json::{FunctionIdentifier, ToolMetadata, ToolNameSanitizer};
+use serde_json::{json, Value};
+use tracing::debug;
+use url::Url;
+
+use crate::wasistate::CallState;
+use crate::{http, schedule, LifecycleManager, ToolAnnotations, ToolSchema, WasiStateTemplate};
+
+/// ID of the component the host tools belong to, which their policy is attached to
+pub const HOST_TOOLS_ID: &str = "builtin/host";
+
+/// Largest response body `fetch-url` returns and largest file `read-file` reads, in bytes
+pub const MAX_HOST_TOOL_OUTPUT: usize = 1024 * 1024;
+
+/// Package of the identifiers of the host tools
+const PACKAGE: &str = "wassette:host";
+
+const FETCH_URL: &str = "fetch-url";
+const READ_FILE: &str = "read-file";
+
+/// Most redirects `fetch-url` follows
+const MAX_REDIRECTS: usize = 10;
+
+/// Time `fetch-url` waits for a response
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns the tools of [`HOST_TOOLS_ID`], named by `sanitizer`
+pub(crate) fn tools(sanitizer: &ToolNameSanitizer) -> Vec<ToolMetadata> {
+    [
+        (
+            FETCH_URL,
+            "Fetches a URL with an HTTP GET request and returns the response body as text. Only \
+             hosts that the network permissions of the builtin/host component allow can be \
+             fetched.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "url": {"type": "string", "description": "The http or https URL to fetch"},
+                },
+                "required": ["url"],
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "url": {"type": "string"},
+                    "status": {"type": "integer"},
+                    "content_type": {"type": ["string", "null"]},
+                    "body": {"type": "string"},
+                },
+                "required": ["url", "status", "body"],
+            }),
+            ToolAnnotations {
+                read_only_hint: Some(true),
+                open_world_hint: Some(true),
+                ..Default::default()
+            },
+        ),
+        (
+            READ_FILE,
+            "Reads a text file. Only files in directories that the storage permissions of the \
+             builtin/host component grant read access to can be read.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "Path of the file to read"},
+                },
+                "required": ["path"],
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string"},
+                    "content": {"type": "string"},
+                },
+                "required": ["path", "content"],
+            }),
+            ToolAnnotations {
+                read_only_hint: Some(true),
+                idempotent_hint: Some(true),
+                open_world_hint: Some(false),
+                ..Default::default()
+            },
+        ),
+    ]
+    .into_iter()
+    .map(
+        |(function_name, description, input_schema, output_schema, annotations)| {
+            let name = sanitizer.sanitize_component(function_name);
+            ToolMetadata {
+                identifier: FunctionIdentifier {
+                    package_name: Some(PACKAGE.to_string()),
+                    interface_name: None,
+                    function_name: function_name.to_string(),
+                },
+                normalized_name: name.clone(),
+                schema: ToolSchema {
+                    name,
+                    description: Some(description.to_string()),
+                    input_schema,
+                    output_schema: Some(output_schema),
+                    annotations: Some(annotations),
+                },
+                deprecation: None,
+                plan: None,
+                memoization: None,
+            }
+        },
+    )
+    .collect()
+}
+
+impl LifecycleManager {
+    /// Returns whether a component is loaded, counting [`HOST_TOOLS_ID`] unless the host tools
+    /// are disabled
+    pub(crate) async fn has_component(&self, component_id: &str) -> bool {
+        if component_id == HOST_TOOLS_ID {
+            return self
+                .registry
+                .read()
+                .await
+                .component_map
+                .contains_key(HOST_TOOLS_ID);
+        }
+        self.components.read().await.contains_key(component_id)
+    }
+
+    /// Calls a host tool under the policy of [`HOST_TOOLS_ID`]
+    pub(crate) async fn call_host_tool(
+        &self,
+        function_name: &str,
+        parameters: &str,
+        call: &CallState,
+    ) -> Result<String> {
+        let function = self
+            .registry
+            .read()
+            .await
+            .get_tool(function_name)
+            .filter(|tool| tool.component_id == HOST_TOOLS_ID)
+            .map(|tool| tool.identifier.function_name.clone())
+            .ok_or_else(|| anyhow!("Unknown tool name: {}", function_name))?;
+        let (template, deadline) = self.call_policy_template(HOST_TOOLS_ID, call).await?;
+        let arguments = crate::parse_tool_arguments(parameters.as_bytes())?;
+        let argument = |name: &str| {
+            arguments
+                .get(name)
+                .and_then(Value::as_str)
+                .with_context(|| format!("Missing string argument '{name}'"))
+        };
+
+        let output = schedule::until_deadline(deadline, async {
+            match function.as_str() {
+                FETCH_URL => fetch_url(&template, argument("url")?).await,
+                READ_FILE => read_file(&template, argument("path")?).await,
+                other => bail!("Unknown host tool: {other}"),
+            }
+        })
+        .await?;
+        Ok(output.to_string())
+    }
+}
+
+/// Fetches a URL whose host the policy allows, following redirects only to allowed hosts
+async fn fetch_url(template: &WasiStateTemplate, url: &str) -> Result<Value> {
+    let url = Url::parse(url).context("Invalid URL")?;
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("Only http and https URLs can be fetched");
+    }
+    if !http::is_url_allowed(&template.allowed_hosts, &url) {
+        bail!(
+            "Access to {} is not allowed by the policy of {HOST_TOOLS_ID}",
+            url.host_str().unwrap_or_default()
+        );
+    }
+
+    let allowed_hosts = template.allowed_hosts.clone();
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if http::is_url_allowed(&allowed_hosts, attempt.url()) {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        }))
+        .timeout(FETCH_TIMEOUT)
+        .build()?;
+    let mut response = client
+        .get(url.clone())
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch {url}"))?;
+    let status = response.status();
+    if status.is_redirection() {
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .unwrap_or_default();
+        bail!("Redirect to {location} is not allowed by the policy of {HOST_TOOLS_ID}");
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(str::to_string);
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > MAX_HOST_TOOL_OUTPUT {
+            bail!("The response is larger than {MAX_HOST_TOOL_OUTPUT} bytes");
+        }
+        body.extend_from_slice(&chunk);
+    }
+    debug!(url = %response.url(), status = status.as_u16(), "Fetched URL for host tool");
+    Ok(json!({
+        "url": response.url().as_str(),
+        "status": status.as_u16(),
+        "content_type": content_type,
+        "body": String::from_utf8_lossy(&body),
+    }))
+}
+
+/// Reads a text file in a directory the policy grants read access to
+async fn read_file(template: &WasiStateTemplate, path: &str) -> Result<Value> {
+    let requested = Path::new(path);
+    if requested
+        .components()
+        .any(|component| matches!(component, Component::ParentDir))
+    {
+        bail!("Paths can't contain '..': {path}");
+    }
+    let denied = || anyhow!("Reading {path} is not allowed by the policy of {HOST_TOOLS_ID}");
+    let (dir, relative) = template
+        .preopened_dirs
+        .iter()
+        .filter(|dir| dir.file_perms.contains(wasmtime_wasi::FilePerms::READ))
+        .find_map(|dir| Some((dir, requested.strip_prefix(&dir.guest_path).ok()?)))
+        .ok_or_else(denied)?;
+    let root = tokio::fs::canonicalize(&dir.host_path)
+        .await
+        .with_context(|| format!("Failed to open {}", dir.guest_path))?;
+    let file = tokio::fs::canonicalize(root.join(relative))
+        .await
+        .with_context(|| format!("Failed to open {path}"))?;
+    // Symbolic links must not lead out of the granted directory
+    if !file.starts_with(&root) {
+        return Err(denied());
+    }
+    let metadata = tokio::fs::metadata(&file).await?;
+    if !metadata.is_file() {
+        bail!("{path} is not a file");
+    }
+    if metadata.len() > MAX_HOST_TOOL_OUTPUT as u64 {
+        bail!("{path} is larger than {MAX_HOST_TOOL_OUTPUT} bytes");
+    }
+    let content = String::from_utf8(tokio::fs::read(&file).await?)
+        .map_err(|_| anyhow!("{path} is not a text file"))?;
+    Ok(json!({ "path": path, "content": content }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_manager;
+
+    #[tokio::test]
+    async fn test_host_tools_are_listed() -> Result<()> {
+        let manager = create_test_manager().await?;
+        assert_eq!(
+            manager.get_component_id_for_tool("fetch-url").await?,
+            HOST_TOOLS_ID
+        );
+        assert_eq!(
+            manager.get_component_id_for_tool("read-file").await?,
+            HOST_TOOLS_ID
+        );
+        assert!(manager.has_component(HOST_TOOLS_ID).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_file_within_granted_paths() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let granted = tempfile::tempdir()?;
+        tokio::fs::write(granted.path().join("notes.txt"), "hello").await?;
+        let path = |name: &str| granted.path().join(name).to_string_lossy().to_string();
+
+        let denied = manager
+            .execute_component_call(
+                HOST_TOOLS_ID,
+                "read-file",
+                &json!({ "path": path("notes.txt") }).to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert!(denied.to_string().contains("not allowed"), "{denied:#}");
+
+        manager
+            .grant_permission(
+                HOST_TOOLS_ID,
+                "storage",
+                &json!({
+                    "uri": format!("fs://{}", granted.path().display()),
+                    "access": ["read"],
+                }),
+            )
+            .await?;
+        let output = manager
+            .execute_component_call(
+                HOST_TOOLS_ID,
+                "read-file",
+                &json!({ "path": path("notes.txt") }).to_string(),
+            )
+            .await?;
+        let output: Value = serde_json::from_str(&output)?;
+        assert_eq!(output["content"], "hello");
+
+        let escape = format!("{}/../outside.txt", granted.path().display());
+        assert!(manager
+            .execute_component_call(
+                HOST_TOOLS_ID,
+                "read-file",
+                &json!({ "path": escape }).to_string(),
+            )
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_url_needs_allowed_host() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let error = manager
+            .execute_component_call(
+                HOST_TOOLS_ID,
+                "fetch-url",
+                &json!({ "url": "https://example.com/" }).to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("example.com"), "{error:#}");
+
+        let template = WasiStateTemplate {
+            allowed_hosts: ["example.com".to_string()].into(),
+            ..Default::default()
+        };
+        assert!(fetch_url(&template, "file:///etc/passwd").await.is_err());
+        assert!(fetch_url(&template, "https://example.org/").await.is_err());
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@
 use std::collections::HashSet;
 
 use anyhow::Result;
+use tokio::sync::broadcast;
 use tracing::{debug, warn};
 use url::Url;
 use wasmtime::component::Resource;
@@ -12,6 +13,8 @@ use wasmtime_wasi_http::bindings::http::types;
 use wasmtime_wasi_http::types::{HostFutureIncomingResponse, OutgoingRequestConfig};
 use wasmtime_wasi_http::{HttpResult, WasiHttpView};
 
+use crate::{CorrelationIds, LifecycleEvent};
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct AllowedHost {
     scheme: Option<String>,
@@ -47,6 +50,39 @@ impl AllowedHost {
     }
 }
 
+/// Returns whether a URL may be requested under a policy's allowed hosts, the way requests of
+/// components are checked
+pub(crate) fn is_url_allowed(allowed_hosts: &HashSet<String>, url: &Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.to_ascii_lowercase();
+    allowed_hosts
+        .iter()
+        .filter_map(|allowed| AllowedHost::from_str(allowed).ok())
+        .any(|allowed| allowed.matches(&host, Some(url.scheme())))
+}
+
+/// Reports the HTTP requests of a tool call as [`LifecycleEvent::NetworkRequested`]
+pub(crate) struct RequestMonitor {
+    pub(crate) component_id: String,
+    pub(crate) events: broadcast::Sender<LifecycleEvent>,
+    pub(crate) correlation: CorrelationIds,
+}
+
+impl RequestMonitor {
+    fn report(&self, host: &str, allowed: bool) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(LifecycleEvent::NetworkRequested {
+            component_id: self.component_id.clone(),
+            host: host.to_string(),
+            allowed,
+            request_id: self.correlation.request_id.clone(),
+            session_id: self.correlation.session_id.clone(),
+        });
+    }
+}
+
 /// WassetteWasiState is a wrapper around a WASI state that enforces network policies by filtering
 /// outgoing HTTP requests based on a list of allowed hosts from the component's policy document.
 pub struct WassetteWasiState<T> {
@@ -55,6 +91,9 @@ pub struct WassetteWasiState<T> {
 
     /// Set of allowed hosts for network requests (extracted from policy document)
     allowed_hosts: HashSet<AllowedHost>,
+
+    /// Reports the requests that are allowed or blocked
+    monitor: Option<RequestMonitor>,
 }
 
 impl<T> WassetteWasiState<T> {
@@ -77,9 +116,16 @@ impl<T> WassetteWasiState<T> {
         Ok(Self {
             inner,
             allowed_hosts: parsed_hosts,
+            monitor: None,
         })
     }
 
+    /// Reports every request that is allowed or blocked to `monitor`
+    pub(crate) fn with_monitor(mut self, monitor: RequestMonitor) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
     /// Check if a host is allowed by the policy
     fn is_host_allowed(&self, uri: &hyper::Uri) -> bool {
         let request_host = if let Some(host) = uri.host() {
@@ -139,7 +185,11 @@ impl<T: WasiHttpView> WasiHttpView for WassetteWasiState<T> {
             return Err(types::ErrorCode::HttpRequestUriInvalid.into());
         }
 
-        if !self.is_host_allowed(uri) {
+        let allowed = self.is_host_allowed(uri);
+        if let (Some(monitor), Some(host)) = (&self.monitor, uri.host()) {
+            monitor.report(&host.to_ascii_lowercase(), allowed);
+        }
+        if !allowed {
             warn!(
                 uri = %uri,
                 allowed_hosts = ?self.allowed_hosts,
@@ -0,0 +1,246 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! An on-disk cache of resources downloaded over HTTPS.
+//!
+//! Responses that carry an `ETag` or `Last-Modified` header are kept in the `cache/http`
+//! directory of the plugin directory, keyed by the SHA-256 digest of their URL. Later downloads of
+//! the same URL are revalidated with a conditional request, so an unchanged resource isn't
+//! downloaded again.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use futures::TryStreamExt;
+use reqwest::header::{AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, warn};
+
+use crate::credentials::HttpAuth;
+
+/// Directory in the plugin directory that downloads are cached in
+pub const HTTP_CACHE_DIR: &str = "cache/http";
+
+/// What is stored next to a cached response to revalidate it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CacheMetadata {
+    /// The URL the response came from, after redirects
+    final_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+/// A downloaded response body
+#[derive(Debug)]
+pub(crate) struct HttpDownload {
+    /// The URL the body came from, after redirects
+    pub(crate) url: Url,
+    /// Path of the body, in the cache or in a temporary file
+    pub(crate) body: PathBuf,
+    /// Holds a body that wasn't cached, which is deleted once the download is dropped
+    _uncached: Option<tempfile::TempPath>,
+}
+
+pub(crate) struct HttpCache {
+    dir: PathBuf,
+}
+
+impl HttpCache {
+    pub(crate) fn new(plugin_dir: &Path) -> Self {
+        Self {
+            dir: plugin_dir.join(HTTP_CACHE_DIR),
+        }
+    }
+
+    fn paths(&self, url: &str) -> (PathBuf, PathBuf) {
+        let key = format!("{:x}", Sha256::digest(url.as_bytes()));
+        (
+            self.dir.join(format!("{key}.body")),
+            self.dir.join(format!("{key}.json")),
+        )
+    }
+
+    async fn cached(&self, url: &str) -> Option<(CacheMetadata, PathBuf)> {
+        let (body, meta) = self.paths(url);
+        let data = tokio::fs::read(&meta).await.ok()?;
+        let metadata = match serde_json::from_slice(&data) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!(error = %e, url, "Ignoring unreadable cache metadata");
+                return None;
+            }
+        };
+        tokio::fs::try_exists(&body)
+            .await
+            .unwrap_or(false)
+            .then_some((metadata, body))
+    }
+
+    /// Downloads the URL, or revalidates and returns the cached copy. The credentials are sent if
+    /// given.
+    pub(crate) async fn fetch(
+        &self,
+        http_client: &reqwest::Client,
+        url: &str,
+        auth: Option<&HttpAuth>,
+        resource_type: &str,
+    ) -> Result<HttpDownload> {
+        let cached = self.cached(url).await;
+        let mut request = http_client.get(url);
+        if let Some(auth) = auth {
+            request = request.header(AUTHORIZATION, auth.header_value());
+        }
+        if let Some((metadata, _)) = &cached {
+            if let Some(etag) = &metadata.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &metadata.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let resp = request.send().await?;
+        let status = resp.status();
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some((metadata, body)) = cached {
+                debug!(url, "Using cached download");
+                return Ok(HttpDownload {
+                    url: Url::parse(&metadata.final_url)?,
+                    body,
+                    _uncached: None,
+                });
+            }
+        }
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            bail!(
+                "Failed to download {} from URL: {}. Status code: {}\nBody: {}",
+                resource_type,
+                url,
+                status,
+                body
+            );
+        }
+
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        let metadata = CacheMetadata {
+            final_url: resp.url().to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        let final_url = resp.url().clone();
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .context("Failed to create download cache directory")?;
+        let (body_path, meta_path) = self.paths(url);
+        // Write to a unique file first so that concurrent downloads of the same URL don't read a
+        // partial body
+        let dir = self.dir.clone();
+        let partial = tokio::task::spawn_blocking(move || {
+            tempfile::Builder::new()
+                .suffix(".partial")
+                .tempfile_in(dir)
+                .map(|file| file.into_temp_path())
+        })
+        .await??;
+        let mut file = tokio::fs::File::create(&partial).await?;
+        let mut reader =
+            tokio_util::io::StreamReader::new(resp.bytes_stream().map_err(std::io::Error::other));
+        tokio::io::copy(&mut reader, &mut file)
+            .await
+            .with_context(|| format!("Failed to write downloaded {resource_type}"))?;
+        file.flush().await?;
+        file.sync_all().await?;
+        drop(file);
+
+        // Without a validator the response can't be revalidated, so it isn't worth keeping
+        if metadata.etag.is_none() && metadata.last_modified.is_none() {
+            let _ = tokio::fs::remove_file(&meta_path).await;
+            return Ok(HttpDownload {
+                url: final_url,
+                body: partial.to_path_buf(),
+                _uncached: Some(partial),
+            });
+        }
+        partial.persist(&body_path)?;
+        tokio::fs::write(&meta_path, serde_json::to_vec_pretty(&metadata)?).await?;
+        Ok(HttpDownload {
+            url: final_url,
+            body: body_path,
+            _uncached: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Serves `body` with an ETag, answering requests that send the ETag back with a 304.
+    /// Returns the base URL and the number of full responses sent.
+    async fn serve(body: &'static str) -> Result<(String, Arc<AtomicUsize>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/component.wasm", listener.local_addr()?);
+        let full_responses = Arc::new(AtomicUsize::new(0));
+        let counter = full_responses.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let n = stream.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]).to_ascii_lowercase();
+                let response = if request.contains("if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                } else {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        Ok((url, full_responses))
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_download_is_served_from_cache() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let cache = HttpCache::new(tempdir.path());
+        let client = reqwest::Client::new();
+        let (url, full_responses) = serve("component bytes").await?;
+
+        let first = cache.fetch(&client, &url, None, "component").await?;
+        assert_eq!(
+            tokio::fs::read_to_string(&first.body).await?,
+            "component bytes"
+        );
+        let second = cache.fetch(&client, &url, None, "component").await?;
+        assert_eq!(second.body, first.body);
+        assert_eq!(second.url.as_str(), url);
+        assert_eq!(
+            tokio::fs::read_to_string(&second.body).await?,
+            "component bytes"
+        );
+        assert_eq!(full_responses.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+}
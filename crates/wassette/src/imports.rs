@@ -0,0 +1,366 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Checking component imports against the interfaces wassette provides.
+//!
+//! A component that imports an interface the linker doesn't define can't be instantiated. Rather
+//! than letting that surface as a linker error, components are checked when they are loaded and
+//! rejected with an [`UnsupportedImports`] error that names every missing interface, with a
+//! suggestion where there is a supported alternative. Each import is looked up in the linker
+//! itself, so the check follows whatever the linker defines, including the versions it accepts.
+//!
+//! The supported imports are also mapped to the policy permissions they need, so that components
+//! whose imports exceed what their policy grants can be flagged before they are called.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+use wasmtime::component::{Component, Linker};
+use wasmtime::Engine;
+
+use crate::{WasiState, WasiStateTemplate, WassetteWasiState};
+
+/// Prefix of the WASI versions wassette implements
+const SUPPORTED_VERSION_PREFIX: &str = "0.2.";
+
+/// An import of a component that wassette doesn't provide
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnsupportedImport {
+    /// Name of the import, such as `wasi:keyvalue/store@0.2.0-draft`
+    pub name: String,
+    /// A supported alternative, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+/// Error of loading a component that imports interfaces wassette doesn't provide. Find it with
+/// `error.downcast_ref::<UnsupportedImports>()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnsupportedImports {
+    /// The imports that can't be satisfied, in the order the component declares them
+    pub imports: Vec<UnsupportedImport>,
+}
+
+impl fmt::Display for UnsupportedImports {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Component imports interfaces that wassette doesn't provide:"
+        )?;
+        for import in &self.imports {
+            write!(f, "\n  - {}", import.name)?;
+            if let Some(suggestion) = &import.suggestion {
+                write!(f, " ({suggestion})")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnsupportedImports {}
+
+/// Checks that wassette provides every import of a component
+pub(crate) fn check_imports(
+    component: &Component,
+    linker: &Linker<WassetteWasiState<WasiState>>,
+) -> Result<(), UnsupportedImports> {
+    let imports: Vec<_> = component
+        .component_type()
+        .imports(linker.engine())
+        .map(|(name, _)| name)
+        .filter(|name| !is_supported(linker, name))
+        .map(|name| UnsupportedImport {
+            name: name.to_string(),
+            suggestion: suggestion(linker, name).map(String::from),
+        })
+        .collect();
+    if imports.is_empty() {
+        Ok(())
+    } else {
+        Err(UnsupportedImports { imports })
+    }
+}
+
+/// A policy permission that some imports of a component only work with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Directories granted by `storage`, needed to open files through `wasi:filesystem`
+    Storage,
+    /// Hosts granted by `network`, needed for sockets and outgoing HTTP requests
+    Network,
+    /// Variables granted by `environment`, read through `wasi:config`
+    Environment,
+    /// Models granted by `ai`, needed for inference through `wasi:nn`
+    Ai,
+    /// Containers granted by `blobstore`, needed to store blobs through `wasi:blobstore`
+    Blobstore,
+    /// Topics granted by `events`, needed to publish events through `wassette:events`
+    Events,
+}
+
+impl Capability {
+    /// Returns whether a component's policy grants the capability
+    fn is_granted(self, template: &WasiStateTemplate) -> bool {
+        match self {
+            Self::Storage => !template.preopened_dirs.is_empty(),
+            Self::Network => {
+                !template.allowed_hosts.is_empty()
+                    || template.network_perms.allow_tcp
+                    || template.network_perms.allow_udp
+            }
+            Self::Environment => {
+                !template.config_vars.is_empty() || !template.component_config.is_empty()
+            }
+            Self::Ai => template.ai_models.is_some(),
+            Self::Blobstore => template
+                .blob_containers
+                .as_ref()
+                .is_some_and(|containers| !containers.is_empty()),
+            Self::Events => template
+                .event_topics
+                .as_ref()
+                .is_some_and(|topics| !topics.is_empty()),
+        }
+    }
+}
+
+/// A capability a component needs, with the imports that need it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RequiredCapability {
+    /// The policy permission
+    pub capability: Capability,
+    /// The imports of the component that need it
+    pub imports: Vec<String>,
+}
+
+/// Returns the capabilities the imports of a component need, in the order of [`Capability`]
+pub(crate) fn required_capabilities(
+    component: &Component,
+    engine: &Engine,
+) -> Vec<RequiredCapability> {
+    let mut required: BTreeMap<Capability, Vec<String>> = BTreeMap::new();
+    for (name, _) in component.component_type().imports(engine) {
+        if let Some(capability) = capability_of(name) {
+            required
+                .entry(capability)
+                .or_default()
+                .push(name.to_string());
+        }
+    }
+    required
+        .into_iter()
+        .map(|(capability, imports)| RequiredCapability {
+            capability,
+            imports,
+        })
+        .collect()
+}
+
+/// Returns the required capabilities that a component's policy doesn't grant
+pub(crate) fn capability_gaps(
+    required: &[RequiredCapability],
+    template: &WasiStateTemplate,
+) -> Vec<RequiredCapability> {
+    required
+        .iter()
+        .filter(|required| !required.capability.is_granted(template))
+        .cloned()
+        .collect()
+}
+
+fn capability_of(name: &str) -> Option<Capability> {
+    let (package, interface, _) = parse_import(name)?;
+    match (package, interface) {
+        ("wasi:filesystem", "preopens") => Some(Capability::Storage),
+        ("wasi:sockets", "tcp-create-socket" | "udp-create-socket" | "ip-name-lookup")
+        | ("wasi:http", "outgoing-handler") => Some(Capability::Network),
+        ("wasi:config", "store") => Some(Capability::Environment),
+        ("wasi:nn", _) => Some(Capability::Ai),
+        ("wasi:blobstore", "blobstore") => Some(Capability::Blobstore),
+        ("wassette:events", "publisher") => Some(Capability::Events),
+        _ => None,
+    }
+}
+
+/// Splits an import name such as `wasi:io/streams@0.2.0` into its package, interface and version
+fn parse_import(name: &str) -> Option<(&str, &str, Option<&str>)> {
+    let (path, version) = match name.split_once('@') {
+        Some((path, version)) => (path, Some(version)),
+        None => (name, None),
+    };
+    let (package, interface) = path.split_once('/')?;
+    package
+        .contains(':')
+        .then_some((package, interface, version))
+}
+
+/// Returns whether the linker defines something that an import named `name` resolves to.
+/// The linker is asked with a component that only imports a function of that name, so the name
+/// is matched exactly as when instantiating, including semver-compatible versions. An empty
+/// instance wouldn't do, since the linker accepts those even when nothing is defined. A copy of
+/// the linker stubs the import if it is missing, after which only a name the linker already
+/// defined, as an instance, fails to instantiate.
+fn linker_provides(linker: &Linker<WassetteWasiState<WasiState>>, name: &str) -> bool {
+    let mut types = wasm_encoder::ComponentTypeSection::new();
+    types
+        .function()
+        .params::<_, wasm_encoder::ComponentValType>([])
+        .result(None);
+    let mut imports = wasm_encoder::ComponentImportSection::new();
+    imports.import(name, wasm_encoder::ComponentTypeRef::Func(0));
+    let mut probe = wasm_encoder::Component::new();
+    probe.section(&types).section(&imports);
+    let Ok(probe) = Component::new(linker.engine(), probe.finish()) else {
+        return false;
+    };
+    let mut stubbed = linker.clone();
+    stubbed.define_unknown_imports_as_traps(&probe).is_ok()
+        && stubbed.instantiate_pre(&probe).is_err()
+}
+
+fn is_supported(linker: &Linker<WassetteWasiState<WasiState>>, name: &str) -> bool {
+    linker_provides(linker, name)
+}
+
+fn suggestion(linker: &Linker<WassetteWasiState<WasiState>>, name: &str) -> Option<&'static str> {
+    let Some((package, interface, version)) = parse_import(name) else {
+        return Some(
+            "provide it by composing the component with one that exports it, for example with `wac plug`",
+        );
+    };
+    if version.is_some_and(|version| !version.starts_with(SUPPORTED_VERSION_PREFIX))
+        && linker_provides(
+            linker,
+            &format!("{package}/{interface}@{SUPPORTED_VERSION_PREFIX}0"),
+        )
+    {
+        return Some("wassette implements WASI 0.2, rebuild the component against a 0.2 release");
+    }
+    match package {
+        "wasi:nn" if cfg!(feature = "wasi-nn") => {
+            Some("wassette implements a different version of wasi:nn")
+        }
+        "wasi:nn" => Some(
+            "run a wassette built with the `wasi-nn` feature and allow the model in the `ai` section of the policy",
+        ),
+        "wasi:webgpu" | "wasi:graphics-context" | "wasi:frame-buffer" | "wasi:surface" => {
+            Some("GPU access isn't supported, use wasi:nn for model inference")
+        }
+        "wasi:keyvalue" => Some(
+            "store data with wasi:blobstore in a container granted with a `blobstore` permission, or in a directory granted with `storage`",
+        ),
+        "wasi:messaging" => Some(
+            "publish events with wassette:events/publisher to a topic granted with an `events` permission, or send messages with wasi:http to an allowed host",
+        ),
+        "wassette:events" => Some("wassette implements wassette:events/publisher@0.1.0"),
+        "wasi:logging" => Some("write log lines to stderr, which wassette forwards to the client"),
+        _ if package.starts_with("wasi:") => None,
+        _ => Some(
+            "provide it by composing the component with one that exports it, for example with `wac plug`",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_linker() -> Linker<WassetteWasiState<WasiState>> {
+        let engine = crate::create_engine(&crate::LifecycleConfig::default()).unwrap();
+        crate::create_linker(&engine).unwrap()
+    }
+
+    #[test]
+    fn test_supported_imports() {
+        let linker = test_linker();
+        let is_supported = |name| is_supported(&linker, name);
+        let suggestion = |name| suggestion(&linker, name);
+        assert!(is_supported("wasi:io/streams@0.2.0"));
+        assert!(is_supported("wasi:http/outgoing-handler@0.2.3"));
+        assert!(is_supported("wasi:config/store@0.2.0-draft"));
+        assert!(is_supported("wasi:blobstore/container@0.2.0-draft"));
+        assert!(is_supported("wassette:events/publisher@0.1.0"));
+        assert!(!is_supported("wassette:events/publisher@0.2.0"));
+        assert!(!is_supported("wasi:io/streams@0.3.0"));
+        assert!(is_supported("wasi:clocks/monotonic-clock@0.2.0"));
+        assert!(is_supported("wasi:clocks/wall-clock@0.2.9"));
+        assert!(is_supported("wasi:clocks/monotonic-clock@0.2.99"));
+        assert!(!is_supported("wasi:io/streams"));
+        assert!(!is_supported("wasi:http/incoming-handler@0.2.0"));
+        assert!(!is_supported("wasi:keyvalue/store@0.2.0-draft"));
+        assert!(!is_supported("host-log"));
+        assert!(suggestion("wasi:io/streams@0.3.0")
+            .unwrap()
+            .contains("rebuild the component against a 0.2 release"));
+    }
+
+    #[test]
+    fn test_unsupported_imports_are_reported() -> anyhow::Result<()> {
+        let linker = test_linker();
+        let component = Component::new(
+            linker.engine(),
+            wat::parse_str(
+                r#"(component
+                    (import "wasi:keyvalue/store@0.2.0-draft" (instance))
+                    (import "wasi:io/poll@0.2.0" (instance))
+                    (import "acme:billing/invoices@1.0.0" (instance))
+                )"#,
+            )?,
+        )?;
+        let error = check_imports(&component, &linker).unwrap_err();
+        let names: Vec<_> = error.imports.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "wasi:keyvalue/store@0.2.0-draft",
+                "acme:billing/invoices@1.0.0"
+            ]
+        );
+        assert!(error.imports.iter().all(|i| i.suggestion.is_some()));
+        assert!(error.to_string().contains("wasi:keyvalue/store"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_capability_gaps() -> anyhow::Result<()> {
+        let engine = Engine::default();
+        let component = Component::new(
+            &engine,
+            wat::parse_str(
+                r#"(component
+                    (import "wasi:io/poll@0.2.0" (instance))
+                    (import "wasi:http/outgoing-handler@0.2.0" (instance))
+                    (import "wasi:filesystem/preopens@0.2.0" (instance))
+                    (import "wasi:sockets/ip-name-lookup@0.2.0" (instance))
+                )"#,
+            )?,
+        )?;
+        let required = required_capabilities(&component, &engine);
+        assert_eq!(
+            required,
+            [
+                RequiredCapability {
+                    capability: Capability::Storage,
+                    imports: vec!["wasi:filesystem/preopens@0.2.0".to_string()],
+                },
+                RequiredCapability {
+                    capability: Capability::Network,
+                    imports: vec![
+                        "wasi:http/outgoing-handler@0.2.0".to_string(),
+                        "wasi:sockets/ip-name-lookup@0.2.0".to_string(),
+                    ],
+                },
+            ]
+        );
+
+        let mut template = WasiStateTemplate::default();
+        assert_eq!(capability_gaps(&required, &template), required);
+        template.allowed_hosts.insert("api.example.com".to_string());
+        let gaps = capability_gaps(&required, &template);
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].capability, Capability::Storage);
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Listing the tools of a component that isn't installed.
+//!
+//! Components published to an OCI registry can carry their tool schema next to the component, as
+//! a [`ToolsDocument`] in the manifest annotation [`TOOLS_ANNOTATION`] or in a layer of type
+//! [`TOOLS_MEDIA_TYPE`]. Either is a few kilobytes, so the tools of such a component are shown
+//! without downloading and compiling the component. Other components are downloaded and
+//! compiled, but not installed.
+
+use anyhow::{Context, Result};
+use component2json::{apply_tool_metadata, component_exports_to_tools_with_sanitizer};
+use serde::Serialize;
+use tracing::{debug, instrument};
+use wasmtime::component::Component;
+
+use crate::command;
+use crate::delta::pull_verified_blob;
+use crate::loader::ComponentResource;
+use crate::sources::check_scheme_allowed;
+use crate::{LifecycleManager, ToolsDocument};
+
+/// Manifest annotation holding the [`ToolsDocument`] of a component as JSON
+pub const TOOLS_ANNOTATION: &str = "io.microsoft.wassette.tools";
+
+/// Media type of an image layer holding the [`ToolsDocument`] of the component as JSON
+pub const TOOLS_MEDIA_TYPE: &str = "application/vnd.wassette.tools.v1+json";
+
+/// Largest tool schema layer that is prefetched, larger ones are ignored
+const MAX_TOOLS_LAYER_SIZE: i64 = 1024 * 1024;
+
+/// Where the tools of an inspected component were read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolsSource {
+    /// The tool schema published with the component, without downloading the component
+    Prefetched,
+    /// The component itself, which was downloaded and compiled
+    Downloaded,
+}
+
+/// The tools of a component that isn't necessarily installed
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectedComponent {
+    /// Where the tools were read from
+    pub source: ToolsSource,
+    /// The component's tools and WIT world
+    #[serde(flatten)]
+    pub document: ToolsDocument,
+}
+
+/// Reads the tool schema published with an OCI image. Returns `None` if the image has none.
+async fn prefetch_tools(
+    client: &oci_client::Client,
+    reference: &str,
+    auth: &oci_client::secrets::RegistryAuth,
+) -> Result<Option<ToolsDocument>> {
+    let reference: oci_client::Reference =
+        reference.parse().context("Failed to parse OCI reference")?;
+    let (manifest, reference) =
+        crate::oci_manifest::pull_component_manifest(client, &reference, auth).await?;
+    if let Some(tools) = manifest
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(TOOLS_ANNOTATION))
+    {
+        return serde_json::from_str(tools)
+            .map(Some)
+            .context("Invalid tool schema annotation");
+    }
+    let Some(layer) = manifest
+        .layers
+        .iter()
+        .find(|layer| layer.media_type == TOOLS_MEDIA_TYPE && layer.size <= MAX_TOOLS_LAYER_SIZE)
+    else {
+        return Ok(None);
+    };
+    let data = pull_verified_blob(client, &reference, layer).await?;
+    serde_json::from_slice(&data)
+        .map(Some)
+        .context("Invalid tool schema layer")
+}
+
+impl LifecycleManager {
+    /// Returns the tools of the component at `uri` without installing it. The tool schema
+    /// published with an OCI image is used when there is one, otherwise the component is
+    /// downloaded and compiled. Tools aren't annotated with a policy, since the component has
+    /// none.
+    #[instrument(skip(self))]
+    pub async fn inspect_component(&self, uri: &str) -> Result<InspectedComponent> {
+        let oci_allowed =
+            check_scheme_allowed(self.config.read().await.allowed_schemes.as_deref(), "oci")
+                .is_ok();
+        for candidate in self.mirrors.candidates(uri.trim()) {
+            let Some(reference) = candidate.uri.strip_prefix("oci://") else {
+                continue;
+            };
+            if !oci_allowed {
+                break;
+            }
+            let prefetched = self
+                .with_registry_auth(reference, |auth| async move {
+                    prefetch_tools(&self.oci_client, reference, &auth).await
+                })
+                .await;
+            match prefetched {
+                Ok(Some(document)) => {
+                    return Ok(InspectedComponent {
+                        source: ToolsSource::Prefetched,
+                        document,
+                    })
+                }
+                Ok(None) => debug!(uri = %candidate.uri, "Image has no tool schema"),
+                Err(e) => {
+                    debug!(uri = %candidate.uri, error = %e, "Failed to prefetch tool schema")
+                }
+            }
+        }
+
+        let resource = self.load_resource::<ComponentResource>(uri).await?;
+        let source = crate::read_component_source(resource.name()?, resource.as_ref()).await?;
+        let component = tokio::task::spawn_blocking({
+            let engine = self.engine.clone();
+            move || Component::new(&engine, source.wasm_bytes)
+        })
+        .await??;
+        let mut tools = component_exports_to_tools_with_sanitizer(
+            &component,
+            &self.engine,
+            true,
+            &self.tool_naming,
+        );
+        command::synthesize_command_tools(&source.name, &mut tools, &self.tool_naming);
+        if let Some(metadata) = &source.tool_metadata {
+            apply_tool_metadata(&mut tools, metadata);
+        }
+        Ok(InspectedComponent {
+            source: ToolsSource::Downloaded,
+            document: ToolsDocument {
+                tools: tools.into_iter().map(|tool| tool.schema).collect(),
+                world: source.world_name,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_inspect_local_component() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let component_path = tempdir.path().join("greeter.wasm");
+        let wat = r#"(component
+            (core module $m
+                (func (export "hello") (param i32 i32) (result i32) unreachable)
+                (memory (export "memory") 1)
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32) unreachable)
+            )
+            (core instance $i (instantiate $m))
+            (func $hello (param "name" string) (result string)
+                (canon lift (core func $i "hello") (memory $i "memory")
+                    (realloc (func $i "realloc"))))
+            (instance $greet (export "hello" (func $hello)))
+            (export "foo:bar/greet" (instance $greet))
+        )"#;
+        std::fs::write(&component_path, wat::parse_str(wat)?)?;
+
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+        let inspected = manager
+            .inspect_component(&format!("file://{}", component_path.display()))
+            .await?;
+        assert_eq!(inspected.source, ToolsSource::Downloaded);
+        assert_eq!(inspected.document.tools.len(), 1);
+        assert!(inspected.document.tools[0].name.contains("hello"));
+        // Nothing was installed
+        assert!(manager.list_components().await.is_empty());
+        Ok(())
+    }
+}
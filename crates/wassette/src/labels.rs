@@ -0,0 +1,269 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Labels attached to components, such as `team=infra` or `risk=high`, and selectors that pick
+//! components by their labels so that large installations can be segmented.
+//!
+//! Labels are kept in the `<id>.meta.json` file next to the component, so they survive restarts
+//! and reloading the component from its source.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::component_id::{self, ComponentMetadata};
+
+/// Labels of a component, by key
+pub type Labels = BTreeMap<String, String>;
+
+/// Checks that a label key or value only contains letters, digits, `.`, `_`, `-` and, in keys, `/`
+fn validate_label_part(part: &str, what: &str, allow_slash: bool) -> Result<()> {
+    if let Some(c) = part.chars().find(|c| {
+        !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') || (allow_slash && *c == '/'))
+    }) {
+        bail!("Invalid label {what} '{part}': unexpected character '{c}'");
+    }
+    Ok(())
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    if key.is_empty() {
+        bail!("Label keys can't be empty");
+    }
+    validate_label_part(key, "key", true)
+}
+
+/// Checks that all keys and values of the labels are valid
+pub fn validate_labels(labels: &Labels) -> Result<()> {
+    for (key, value) in labels {
+        validate_key(key)?;
+        validate_label_part(value, "value", false)?;
+    }
+    Ok(())
+}
+
+/// Parses a label given as `key=value`
+pub fn parse_label(label: &str) -> Result<(String, String)> {
+    let (key, value) = label
+        .split_once('=')
+        .with_context(|| format!("Expected key=value, got '{label}'"))?;
+    let (key, value) = (key.trim(), value.trim());
+    validate_key(key)?;
+    validate_label_part(value, "value", false)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// A condition on one label of a [`LabelSelector`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    Exists(String),
+    NotExists(String),
+}
+
+impl Requirement {
+    fn matches(&self, labels: &Labels) -> bool {
+        match self {
+            Self::Equals(key, value) => labels.get(key) == Some(value),
+            Self::NotEquals(key, value) => labels.get(key) != Some(value),
+            Self::Exists(key) => labels.contains_key(key),
+            Self::NotExists(key) => !labels.contains_key(key),
+        }
+    }
+}
+
+/// Selects components by their labels. A selector is a comma-separated list of conditions that
+/// must all hold: `key=value`, `key!=value`, `key` (the label is set) or `!key` (it isn't), e.g.
+/// `team=infra,risk!=high`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LabelSelector {
+    requirements: Vec<Requirement>,
+}
+
+impl LabelSelector {
+    /// Returns whether a component with the given labels is selected
+    pub fn matches(&self, labels: &Labels) -> bool {
+        self.requirements.iter().all(|r| r.matches(labels))
+    }
+}
+
+impl FromStr for LabelSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(selector: &str) -> Result<Self> {
+        let mut requirements = Vec::new();
+        for condition in selector.split(',').map(str::trim) {
+            if condition.is_empty() {
+                continue;
+            }
+            let requirement = if let Some((key, value)) = condition.split_once("!=") {
+                let (key, value) = parse_label(&format!("{key}={value}"))?;
+                Requirement::NotEquals(key, value)
+            } else if condition.contains('=') {
+                let (key, value) = parse_label(condition)?;
+                Requirement::Equals(key, value)
+            } else if let Some(key) = condition.strip_prefix('!') {
+                validate_key(key.trim())?;
+                Requirement::NotExists(key.trim().to_string())
+            } else {
+                validate_key(condition)?;
+                Requirement::Exists(condition.to_string())
+            };
+            requirements.push(requirement);
+        }
+        Ok(Self { requirements })
+    }
+}
+
+impl fmt::Display for LabelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, requirement) in self.requirements.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            match requirement {
+                Requirement::Equals(key, value) => write!(f, "{key}={value}")?,
+                Requirement::NotEquals(key, value) => write!(f, "{key}!={value}")?,
+                Requirement::Exists(key) => write!(f, "{key}")?,
+                Requirement::NotExists(key) => write!(f, "!{key}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for LabelSelector {
+    type Error = anyhow::Error;
+
+    fn try_from(selector: String) -> Result<Self> {
+        selector.parse()
+    }
+}
+
+impl From<LabelSelector> for String {
+    fn from(selector: LabelSelector) -> Self {
+        selector.to_string()
+    }
+}
+
+/// Sets and removes labels of a component in the plugin directory, given by ID or by name.
+/// Returns the ID of the component and its labels after the change. A running server picks the
+/// change up when it is reloaded.
+pub async fn label_component(
+    plugin_dir: &Path,
+    id: &str,
+    set: &Labels,
+    remove: &[String],
+) -> Result<(String, Labels)> {
+    let ids: Vec<String> = component_id::component_files(plugin_dir)
+        .await?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    let id = component_id::resolve_name(&ids, id)?;
+    if !ids.contains(&id) {
+        bail!("Component not found: {id}");
+    }
+    let labels = update_labels(plugin_dir, &id, set, remove).await?;
+    Ok((id, labels))
+}
+
+/// Applies label changes to the metadata file of a component and returns its new labels
+pub(crate) async fn update_labels(
+    plugin_dir: &Path,
+    id: &str,
+    set: &Labels,
+    remove: &[String],
+) -> Result<Labels> {
+    for (key, value) in set {
+        validate_key(key)?;
+        validate_label_part(value, "value", false)?;
+    }
+    let path = component_id::metadata_path(plugin_dir, id);
+    let mut metadata = ComponentMetadata::read(&path)
+        .await?
+        .unwrap_or_else(|| ComponentMetadata::new(id));
+    for key in remove {
+        metadata.labels.remove(key);
+    }
+    metadata
+        .labels
+        .extend(set.iter().map(|(k, v)| (k.clone(), v.clone())));
+    metadata.write(&path).await?;
+    Ok(metadata.labels)
+}
+
+/// Reads the labels of a component from its metadata file
+pub(crate) async fn read_labels(plugin_dir: &Path, id: &str) -> Result<Labels> {
+    Ok(
+        ComponentMetadata::read(&component_id::metadata_path(plugin_dir, id))
+            .await?
+            .map(|metadata| metadata.labels)
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_label_selector() -> Result<()> {
+        let selector: LabelSelector = "team=infra, risk!=high,!deprecated".parse()?;
+        assert_eq!(selector.to_string(), "team=infra,risk!=high,!deprecated");
+        assert!(selector.matches(&labels(&[("team", "infra")])));
+        assert!(selector.matches(&labels(&[("team", "infra"), ("risk", "low")])));
+        assert!(!selector.matches(&labels(&[("team", "infra"), ("risk", "high")])));
+        assert!(!selector.matches(&labels(&[("team", "data")])));
+        assert!(!selector.matches(&labels(&[("team", "infra"), ("deprecated", "")])));
+
+        let selector: LabelSelector = "example.com/owner".parse()?;
+        assert!(selector.matches(&labels(&[("example.com/owner", "me")])));
+        assert!(!selector.matches(&Labels::new()));
+
+        assert!("".parse::<LabelSelector>()?.matches(&Labels::new()));
+        assert!("team=in fra".parse::<LabelSelector>().is_err());
+        assert!("=infra".parse::<LabelSelector>().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_label_component() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        tokio::fs::create_dir(dir.path().join("local")).await?;
+        tokio::fs::write(dir.path().join("local/fetch.wasm"), b"component").await?;
+
+        let (id, current) = label_component(
+            dir.path(),
+            "fetch",
+            &labels(&[("team", "infra"), ("risk", "high")]),
+            &[],
+        )
+        .await?;
+        assert_eq!(id, "local/fetch");
+        assert_eq!(current, labels(&[("risk", "high"), ("team", "infra")]));
+
+        let (_, current) =
+            label_component(dir.path(), "local/fetch", &Labels::new(), &["risk".into()]).await?;
+        assert_eq!(current, labels(&[("team", "infra")]));
+        assert_eq!(read_labels(dir.path(), "local/fetch").await?, current);
+
+        assert!(label_component(dir.path(), "missing", &Labels::new(), &[])
+            .await
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Versioning of the plugin directory's layout.
+//!
+//! The plugin directory carries a [`LAYOUT_FILE`] recording the version of its layout. At startup
+//! a directory with an older layout is backed up to [`BACKUPS_DIR`] and upgraded by running the
+//! migrations between its version and [`LAYOUT_VERSION`] in order. Directories from before the
+//! marker existed count as version 0. A directory written by a newer wassette is refused rather
+//! than misread.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use crate::component_id;
+
+/// Name of the file in the plugin directory that records the version of its layout
+pub const LAYOUT_FILE: &str = "layout.json";
+
+/// Directory in the plugin directory that the state of upgraded layouts is backed up to
+pub const BACKUPS_DIR: &str = "backups";
+
+/// Version of the layout this version of wassette reads and writes
+pub const LAYOUT_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Directories that are not backed up before a migration, because they only hold caches,
+/// scratch data or can be recreated
+const UNSAVED_DIRS: &[&str] = &[
+    BACKUPS_DIR,
+    crate::DOWNLOADS_DIR,
+    crate::BLOBS_DIR,
+    crate::SANDBOXES_DIR,
+    crate::PRECOMPILED_DIR,
+    "cache",
+];
+
+/// A step upgrading the layout by one version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Migration {
+    /// Components stored directly in the plugin directory move into the `local` namespace
+    NamespaceComponents,
+}
+
+/// The migrations, where the one at index `n` upgrades version `n` to `n + 1`
+const MIGRATIONS: &[Migration] = &[Migration::NamespaceComponents];
+
+impl Migration {
+    fn description(self) -> &'static str {
+        match self {
+            Self::NamespaceComponents => "move components into the local namespace",
+        }
+    }
+
+    async fn run(self, plugin_dir: &Path) -> Result<()> {
+        match self {
+            Self::NamespaceComponents => {
+                component_id::migrate_flat_components(plugin_dir).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Contents of the [`LAYOUT_FILE`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayoutMarker {
+    /// Version of the layout
+    pub version: u32,
+    /// Version of wassette that last wrote the layout
+    pub wassette_version: String,
+}
+
+impl LayoutMarker {
+    fn current() -> Self {
+        Self {
+            version: LAYOUT_VERSION,
+            wassette_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Reads the marker of a plugin directory, returning `None` if it has none
+    pub async fn read(plugin_dir: &Path) -> Result<Option<Self>> {
+        let path = plugin_dir.join(LAYOUT_FILE);
+        match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .with_context(|| format!("Invalid layout marker {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    async fn write(&self, plugin_dir: &Path) -> Result<()> {
+        let path = plugin_dir.join(LAYOUT_FILE);
+        tokio::fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// What [`upgrade_layout`] did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayoutUpgrade {
+    /// Version of the layout before the upgrade
+    pub from: u32,
+    /// Descriptions of the migrations that ran, in order
+    pub migrations: Vec<&'static str>,
+    /// Where the state was backed up to before migrating, if any migration ran
+    pub backup: Option<PathBuf>,
+}
+
+/// Brings the layout of a plugin directory to [`LAYOUT_VERSION`], backing it up first if any
+/// migration has to run. An empty directory is marked with the current version right away.
+#[instrument]
+pub async fn upgrade_layout(plugin_dir: &Path) -> Result<LayoutUpgrade> {
+    let from = match LayoutMarker::read(plugin_dir).await? {
+        Some(marker) if marker.version > LAYOUT_VERSION => bail!(
+            "The plugin directory {} has layout version {}, written by wassette {}, but this \
+             version of wassette only understands layouts up to version {LAYOUT_VERSION}. \
+             Upgrade wassette or use another plugin directory",
+            plugin_dir.display(),
+            marker.version,
+            marker.wassette_version
+        ),
+        Some(marker) => marker.version,
+        None if is_empty(plugin_dir).await? => LAYOUT_VERSION,
+        None => 0,
+    };
+    let mut upgrade = LayoutUpgrade {
+        from,
+        ..Default::default()
+    };
+    if from < LAYOUT_VERSION {
+        let backup = backup_dir(plugin_dir, from);
+        let (source, destination) = (plugin_dir.to_path_buf(), backup.clone());
+        tokio::task::spawn_blocking(move || copy_state(&source, &destination))
+            .await?
+            .with_context(|| {
+                format!("Failed to back up plugin directory to {}", backup.display())
+            })?;
+        info!(backup = %backup.display(), from, to = LAYOUT_VERSION, "Backed up plugin directory before upgrading its layout");
+        for (version, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+            migration.run(plugin_dir).await.with_context(|| {
+                format!(
+                    "Failed to upgrade the plugin directory layout from version {version}; its \
+                     previous state is in {}",
+                    backup.display()
+                )
+            })?;
+            info!(
+                version,
+                migration = migration.description(),
+                "Upgraded plugin directory layout"
+            );
+            upgrade.migrations.push(migration.description());
+        }
+        upgrade.backup = Some(backup);
+    }
+    // Also records the version of wassette, so that a refused downgrade can name it
+    if LayoutMarker::read(plugin_dir).await? != Some(LayoutMarker::current()) {
+        LayoutMarker::current().write(plugin_dir).await?;
+    }
+    Ok(upgrade)
+}
+
+async fn is_empty(dir: &Path) -> Result<bool> {
+    Ok(tokio::fs::read_dir(dir)
+        .await?
+        .next_entry()
+        .await?
+        .is_none())
+}
+
+fn backup_dir(plugin_dir: &Path, version: u32) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    plugin_dir
+        .join(BACKUPS_DIR)
+        .join(format!("layout-v{version}-{timestamp}"))
+}
+
+/// Copies the regular files and directories of the plugin directory into `destination`, leaving
+/// out caches and earlier backups
+fn copy_state(plugin_dir: &Path, destination: &Path) -> Result<()> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(plugin_dir)? {
+        let entry = entry?;
+        if UNSAVED_DIRS.iter().any(|dir| entry.file_name() == *dir) {
+            continue;
+        }
+        copy_entry(&entry.path(), &destination.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+fn copy_entry(source: &Path, destination: &Path) -> Result<()> {
+    let file_type = std::fs::symlink_metadata(source)?.file_type();
+    if file_type.is_dir() {
+        std::fs::create_dir_all(destination)?;
+        for entry in std::fs::read_dir(source)? {
+            let entry = entry?;
+            copy_entry(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+    } else if file_type.is_file() {
+        std::fs::copy(source, destination)
+            .with_context(|| format!("Failed to copy {}", source.display()))?;
+    }
+    // Sockets, such as the control socket, and symlinks are left out
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_empty_dir_is_marked_current() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let upgrade = upgrade_layout(dir.path()).await?;
+        assert_eq!(upgrade.from, LAYOUT_VERSION);
+        assert!(upgrade.migrations.is_empty() && upgrade.backup.is_none());
+        assert_eq!(
+            LayoutMarker::read(dir.path()).await?,
+            Some(LayoutMarker::current())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unmarked_dir_is_backed_up_and_migrated() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        tokio::fs::write(dir.path().join("fetch.wasm"), b"component").await?;
+        tokio::fs::create_dir_all(dir.path().join("cache/http")).await?;
+        tokio::fs::write(dir.path().join("cache/http/entry"), b"cached").await?;
+
+        let upgrade = upgrade_layout(dir.path()).await?;
+        assert_eq!(upgrade.from, 0);
+        assert_eq!(upgrade.migrations.len(), MIGRATIONS.len());
+        assert!(dir.path().join("local/fetch.wasm").exists());
+        let backup = upgrade.backup.unwrap();
+        assert!(backup.join("fetch.wasm").exists());
+        assert!(!backup.join("cache").exists());
+
+        // Running again finds nothing to do
+        let again = upgrade_layout(dir.path()).await?;
+        assert_eq!(again.from, LAYOUT_VERSION);
+        assert!(again.backup.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_newer_layout_is_refused() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        LayoutMarker {
+            version: LAYOUT_VERSION + 1,
+            wassette_version: "99.0.0".to_string(),
+        }
+        .write(dir.path())
+        .await?;
+        let error = upgrade_layout(dir.path()).await.unwrap_err();
+        assert!(error.to_string().contains("99.0.0"));
+        Ok(())
+    }
+}
@@ -5,38 +5,185 @@
 
 #![warn(missing_docs)]
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 use std::time::Instant;
 
 use anyhow::{anyhow, bail, Context, Result};
 use component2json::{
-    component_exports_to_json_schema, component_exports_to_tools, create_placeholder_results,
-    json_to_vals, vals_to_json, FunctionIdentifier, ToolMetadata,
+    apply_schema_defaults, apply_tool_metadata, compact_schema,
+    component_exports_to_tools_with_sanitizer, component_tool_metadata, component_world_name,
+    create_placeholder_results, json_to_vals, vals_to_json, FunctionIdentifier, Memoization,
+    ToolMetadata, ToolNameSanitizer, COMPACT_SCHEMA_DEPTH, PREVIEW_ARGUMENT,
 };
-use futures::TryStreamExt;
-use policy::PolicyParser;
+use futures::StreamExt;
 use serde_json::Value;
-use tokio::fs::DirEntry;
+use sha2::{Digest, Sha256};
 use tokio::sync::RwLock;
-use tracing::{debug, info, instrument, warn};
+use tracing::{debug, info, instrument, warn, Instrument};
 use wasmtime::component::{Component, InstancePre, Linker};
 use wasmtime::{Engine, Store};
 use wasmtime_wasi_config::WasiConfig;
 
+mod adapter;
+mod annotations;
+mod approval;
+mod audit;
+mod blobstore;
+pub mod blocking;
+mod budget;
+mod bundle;
+mod check;
+mod command;
+mod component_id;
+mod component_log;
+mod compression;
+mod config;
+mod content;
+mod coredump;
+mod credentials;
+mod delta;
+mod events;
+mod executor;
+mod git;
+mod host_access;
+mod host_tools;
 mod http;
+mod http_cache;
+mod imports;
+mod inspect;
+mod labels;
+mod layout;
 mod loader;
+mod locks;
+mod memo;
+mod memory;
+mod mirrors;
+#[cfg(feature = "wasi-nn")]
+mod nn;
+mod oci_manifest;
+mod package;
+mod parse;
 mod policy_internal;
+mod policy_migration;
+mod precompile;
+mod profiling;
+mod provision;
+mod publish;
+mod registry_auth;
+mod reload;
+mod replay;
+mod sandbox_groups;
+mod schedule;
+mod schema_diff;
+mod sources;
+mod stats;
+mod status;
+mod timers;
+mod transcripts;
+mod upload;
 mod wasistate;
 
+pub use approval::{
+    approval_log, decide_approval, pending_approvals, ApprovalDecision, ApprovalOutcome,
+    ApprovalRecord, ApprovalRequest, APPROVALS_DIR, DEFAULT_APPROVAL_TIMEOUT_SECS,
+};
+pub use audit::{
+    format_audit_time, parse_audit_time, query_audit_log, AuditPage, AuditQuery,
+    DEFAULT_AUDIT_PAGE_SIZE, MAX_AUDIT_PAGE_SIZE,
+};
+use blobstore::Blobs;
+pub use blobstore::{BLOBS_DIR, DEFAULT_MAX_BLOB_SIZE};
+use budget::BudgetStore;
+pub use budget::{BudgetUsage, BUDGET_USAGE_FILE};
+pub use bundle::{export_bundle, import_bundle, BundleEntry, BundleManifest};
+pub use check::{check_environment, CheckReport, CheckResult, CheckStatus};
+use command::CommandIo;
+pub use command::ARGS_ARGUMENT;
+pub use component2json::{Deprecation, ToolAnnotations, ToolSchema, ToolsDocument};
+pub use component_id::{component_name, ComponentMetadata, LOCAL_NAMESPACE};
+pub use component_log::{ComponentLog, ComponentLogLevel, ComponentLogSink};
+pub use compression::{GZIP_WASM_MEDIA_TYPE, ZSTD_WASM_MEDIA_TYPE};
+pub use config::{
+    AiModelConfig, BlobstoreBackend, BlobstoreConfig, DeterminismConfig, ExecutionConfig,
+    LifecycleConfig, NotificationOverflow, NotificationQueueConfig, SessionConfig,
+    ToolNamingConfig, DEFAULT_NOTIFICATION_QUEUE_CAPACITY,
+};
+pub use content::CONTENT_DIR;
+pub use coredump::{CoreDump, DIAGNOSTICS_DIR};
+pub use credentials::{
+    CredentialEntry, CredentialKind, CredentialStore, CREDENTIALS_INDEX_FILE, KEYRING_SERVICE,
+};
+pub use delta::{apply_delta, create_delta, delta_tag, DELTA_MEDIA_TYPE};
+pub use events::{LifecycleEvent, EVENT_CHANNEL_CAPACITY};
+pub use executor::ExecutionClass;
+use executor::ExecutionPools;
+pub use git::GIT_SCHEME;
+pub use host_access::{granted_access, GrantedAccess};
+pub use host_tools::{HOST_TOOLS_ID, MAX_HOST_TOOL_OUTPUT};
+use http::RequestMonitor;
 pub use http::WassetteWasiState;
+use http_cache::HttpCache;
+pub use http_cache::HTTP_CACHE_DIR;
+pub use imports::{Capability, RequiredCapability, UnsupportedImport, UnsupportedImports};
+pub use inspect::{InspectedComponent, ToolsSource, TOOLS_ANNOTATION, TOOLS_MEDIA_TYPE};
+pub use labels::{label_component, parse_label, validate_labels, LabelSelector, Labels};
+pub use layout::{
+    upgrade_layout, LayoutMarker, LayoutUpgrade, BACKUPS_DIR, LAYOUT_FILE, LAYOUT_VERSION,
+};
 use loader::{ComponentResource, PolicyResource};
+use locks::{LockName, TrackedRwLock};
+use memo::MemoCache;
+pub use memory::{MemoryLimitExceeded, MemoryUsage};
+pub use mirrors::MirrorConfig;
+use mirrors::MirrorSet;
+pub use package::{pack_component, PackOptions, PackageManifest, PACKAGE_EXTENSION};
+#[cfg(fuzzing)]
+pub use parse::fuzz;
+pub use parse::{
+    parse_component_metadata, parse_policy, parse_tool_arguments, ToolArguments,
+    MAX_PARSE_INPUT_SIZE,
+};
+pub use policy::Permissions;
 use policy_internal::PolicyRegistry;
 pub use policy_internal::{PermissionGrantRequest, PermissionRule, PolicyInfo};
-use wasistate::WasiState;
-pub use wasistate::{create_wasi_state_template_from_policy, WasiStateTemplate};
+pub use policy_migration::{migrate_policies, PolicyMigration};
+use precompile::PrecompiledArtifacts;
+pub use precompile::{
+    precompile_component, precompiled_path, target_triple, PrecompiledComponent, PRECOMPILED_DIR,
+    PRECOMPILED_EXTENSION,
+};
+pub use profiling::PROFILE_ARGUMENT_KEY;
+pub use provision::{ProvisionFailure, ProvisionManifest, ProvisionReport, ProvisionedComponent};
+use publish::CallPublisher;
+pub use publish::{
+    PublishedEvent, PublishedEventSink, EVENTS_INTERFACE, MAX_EVENTS_PER_CALL, MAX_EVENT_SIZE,
+};
+pub use registry_auth::{REGISTRY_ENV, REGISTRY_PASSWORD_ENV, REGISTRY_USERNAME_ENV};
+pub use reload::{ReloadFailure, ReloadReport};
+use replay::TraceSession;
+pub use replay::{HttpExchange, RecordedTrace, Trace, RECORD_ARGUMENT_KEY, TRACES_DIR};
+pub use sandbox_groups::{SANDBOXES_DIR, SCRATCH_GUEST_PATH};
+use schedule::ScheduleDeadline;
+pub use schema_diff::{ParameterChange, ParameterChangeKind, SchemaDiff, ToolChange};
+use sources::SourceLoaders;
+pub use sources::{SourceLoader, SourceRequest, BUILTIN_SCHEMES};
+use stats::StatsStore;
+pub use stats::{RecentError, ToolStats, UsageStats, STATS_FILE};
+use status::{CacheCounters, LiveStoreGuard, NotificationCounters};
+pub use status::{CacheStats, ComponentMemory, ServerStatus, SessionGuard};
+use transcripts::TranscriptStore;
+pub use transcripts::{Transcript, TranscriptEntry, TRANSCRIPTS_DIR, TRANSCRIPT_URI_PREFIX};
+use upload::UploadStore;
+pub use upload::{MAX_UPLOAD_SIZE, UPLOAD_REFERENCE_KEY};
+pub use wasistate::{
+    create_wasi_state_template_from_policy, WasiStateTemplate, CAPABILITIES_ARGUMENT_KEY,
+    REQUEST_ID_CONFIG_KEY, SESSION_ID_CONFIG_KEY,
+};
+use wasistate::{CallState, WasiState};
 
 const DOWNLOADS_DIR: &str = "downloads";
 
@@ -44,44 +191,177 @@ const DOWNLOADS_DIR: &str = "downloads";
 struct ToolInfo {
     component_id: String,
     identifier: FunctionIdentifier,
-    schema: Value,
+    schema: ToolSchema,
+    deprecation: Option<Deprecation>,
+    /// The plan variant of the function, called instead of it to preview a call
+    plan: Option<FunctionIdentifier>,
+    /// Set if the tool is pure, so that its results are reused
+    memoization: Option<Memoization>,
 }
 
 #[derive(Debug, Default)]
 struct ComponentRegistry {
-    tool_map: HashMap<String, Vec<ToolInfo>>,
+    /// Kept sorted by tool name, so tools are always listed in the same order
+    tool_map: BTreeMap<String, Vec<ToolInfo>>,
     component_map: HashMap<String, Vec<String>>,
+    /// Maps the unsanitized, qualified function names back to the registered tool names
+    aliases: HashMap<String, Vec<(String, String)>>,
+    naming: Arc<ToolNameSanitizer>,
+    dedup_across_components: bool,
+}
+
+/// Options for a single tool call
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    /// Record a guest profile of the call
+    pub profile: bool,
+    /// Record a trace of the call's WASI inputs that [`LifecycleManager::replay_trace`] can replay
+    pub record: bool,
+    /// Send the lines the component writes to stderr here instead of to the server's stderr
+    pub stderr: Option<ComponentLogSink>,
+    /// Send the events the component publishes here as well as to the subscribers of the
+    /// lifecycle manager
+    pub events: Option<PublishedEventSink>,
+    /// Identifiers of the call that are passed to the component
+    pub correlation: CorrelationIds,
+    /// Permissions the call needs, which it gets as far as the component's policy grants them
+    /// instead of everything the policy grants, see [`CAPABILITIES_ARGUMENT_KEY`]
+    pub capabilities: Option<Permissions>,
+    /// Pool to run the call on instead of the one configured for the component in
+    /// [`ExecutionConfig::batch_components`]
+    pub class: Option<ExecutionClass>,
 }
 
+/// Identifiers of a call that are passed to the component as `wasi:config` variables, so that its
+/// logs can be correlated with the server's traces
+#[derive(Debug, Clone, Default)]
+pub struct CorrelationIds {
+    /// ID of the request that made the call, available as [`REQUEST_ID_CONFIG_KEY`]
+    pub request_id: Option<String>,
+    /// ID of the session that made the call, available as [`SESSION_ID_CONFIG_KEY`]
+    pub session_id: Option<String>,
+}
+
+/// The output of a tool call made with [`LifecycleManager::execute_component_call_with_options`]
+#[derive(Debug, Clone)]
+pub struct CallOutput {
+    /// The result of the call, as JSON or a plain string
+    pub result: String,
+    /// Where the profile of the call was written to, if it was profiled
+    pub profile_path: Option<PathBuf>,
+    /// Where the trace of the call was written to, if it was recorded
+    pub trace_path: Option<PathBuf>,
+    /// Peak memory and table use of the call
+    pub memory: MemoryUsage,
+}
+
+/// The returned status when unloading a component. Unloading a component that is neither loaded
+/// nor installed isn't an error, so unloading is idempotent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnloadResult {
+    /// Indicates that the component was loaded or installed and is now removed
+    Removed,
+    /// Indicates that there was no component with the ID, so nothing changed
+    NotFound,
+}
+
+/// Options for [`LifecycleManager::unload_component_with_options`]
+#[derive(Debug, Clone, Default)]
+pub struct UnloadOptions {
+    /// Keep the component's policy file, so that it applies again when the component is loaded
+    /// again
+    pub keep_policy: bool,
+    /// Keep the blob containers of the component and the objects in them
+    pub keep_data: bool,
+}
+
+/// Number of components [`LifecycleManager::load_components`] loads at once by default
+pub const DEFAULT_LOAD_CONCURRENCY: usize = 4;
+
 /// The returned status when loading a component
 #[derive(Debug, PartialEq)]
 pub enum LoadResult {
-    /// Indicates that the component was loaded but replaced a currently loaded component
-    Replaced,
+    /// Indicates that the component was loaded but replaced a currently loaded component, whose
+    /// tools changed as described by the diff
+    Replaced(SchemaDiff),
     /// Indicates that the component did not exist and is now loaded
     New,
 }
 
 impl ComponentRegistry {
-    fn new() -> Self {
-        Self::default()
+    fn new(naming: Arc<ToolNameSanitizer>, dedup_across_components: bool) -> Self {
+        Self {
+            naming,
+            dedup_across_components,
+            ..Default::default()
+        }
     }
 
     fn register_tools(&mut self, component_id: &str, tools: Vec<ToolMetadata>) -> Result<()> {
-        let mut tool_names = Vec::new();
+        let tool_names = tools
+            .into_iter()
+            .map(|tool_metadata| self.insert_tool(component_id, tool_metadata))
+            .collect();
+        self.component_map
+            .insert(component_id.to_string(), tool_names);
+        Ok(())
+    }
+
+    /// Replaces the tools of a component with `tools`, changing only what differs. Tools the
+    /// component still exports keep the name they were registered under and their place among
+    /// tools of other components sharing it, removed tools are dropped and new tools are added.
+    /// Unlike unregistering and registering the component again, a tool that was renamed to
+    /// avoid a collision isn't renamed back when the tool it collided with has since gone.
+    fn update_tools(&mut self, component_id: &str, tools: Vec<ToolMetadata>) -> Result<()> {
+        let Some(previous_names) = self.component_map.remove(component_id) else {
+            return self.register_tools(component_id, tools);
+        };
+        let mut previous: HashMap<String, String> = HashMap::new();
+        for tool_name in previous_names {
+            for info in self.tool_map.get(&tool_name).into_iter().flatten() {
+                if info.component_id == component_id {
+                    previous.insert(info.identifier.qualified_name(), tool_name.clone());
+                }
+            }
+        }
+
+        // Drop removed tools first, so that their names are free for the new ones
+        let exported: HashSet<String> = tools
+            .iter()
+            .map(|tool| tool.identifier.qualified_name())
+            .collect();
+        for (qualified_name, tool_name) in &previous {
+            if !exported.contains(qualified_name) {
+                self.remove_tool(component_id, qualified_name, tool_name);
+            }
+        }
 
+        let mut tool_names = Vec::new();
         for tool_metadata in tools {
-            let tool_info = ToolInfo {
-                component_id: component_id.to_string(),
-                identifier: tool_metadata.identifier,
-                schema: tool_metadata.schema,
+            let qualified_name = tool_metadata.identifier.qualified_name();
+            let Some(tool_name) = previous.get(&qualified_name) else {
+                tool_names.push(self.insert_tool(component_id, tool_metadata));
+                continue;
             };
-
-            self.tool_map
-                .entry(tool_metadata.normalized_name.clone())
-                .or_default()
-                .push(tool_info);
-            tool_names.push(tool_metadata.normalized_name);
+            let mut schema = tool_metadata.schema;
+            schema.name = tool_name.clone();
+            if let Some(info) = self
+                .tool_map
+                .get_mut(tool_name)
+                .into_iter()
+                .flatten()
+                .find(|info| info.component_id == component_id)
+            {
+                *info = ToolInfo {
+                    component_id: component_id.to_string(),
+                    identifier: tool_metadata.identifier,
+                    schema,
+                    deprecation: tool_metadata.deprecation,
+                    plan: tool_metadata.plan,
+                    memoization: tool_metadata.memoization,
+                };
+            }
+            tool_names.push(tool_name.clone());
         }
 
         self.component_map
@@ -89,11 +369,78 @@ impl ComponentRegistry {
         Ok(())
     }
 
-    fn get_function_identifier(&self, tool_name: &str) -> Option<&FunctionIdentifier> {
-        self.tool_map
-            .get(tool_name)
+    /// Adds a tool of a component under a name that doesn't collide with the tools of other
+    /// components if so configured, and returns that name
+    fn insert_tool(&mut self, component_id: &str, tool_metadata: ToolMetadata) -> String {
+        let mut tool_name = tool_metadata.normalized_name;
+        let mut schema = tool_metadata.schema;
+        if self.dedup_across_components {
+            let base_name = tool_name.clone();
+            let mut suffix = 2;
+            while self.tool_map.contains_key(&tool_name) {
+                tool_name = self.naming.dedup_name(&base_name, suffix);
+                suffix += 1;
+            }
+            if tool_name != base_name {
+                debug!(component_id, from = %base_name, to = %tool_name, "Renamed colliding tool");
+                schema.name = tool_name.clone();
+            }
+        }
+
+        self.aliases
+            .entry(tool_metadata.identifier.qualified_name())
+            .or_default()
+            .push((component_id.to_string(), tool_name.clone()));
+
+        let tool_info = ToolInfo {
+            component_id: component_id.to_string(),
+            identifier: tool_metadata.identifier,
+            schema,
+            deprecation: tool_metadata.deprecation,
+            plan: tool_metadata.plan,
+            memoization: tool_metadata.memoization,
+        };
+
+        // Tools sharing a name are ordered by component ID, whatever order they were loaded in
+        let tool_infos = self.tool_map.entry(tool_name.clone()).or_default();
+        let position =
+            tool_infos.partition_point(|info| info.component_id < tool_info.component_id);
+        tool_infos.insert(position, tool_info);
+        tool_name
+    }
+
+    /// Removes a single tool of a component registered as `tool_name`
+    fn remove_tool(&mut self, component_id: &str, qualified_name: &str, tool_name: &str) {
+        if let Some(tool_infos) = self.tool_map.get_mut(tool_name) {
+            tool_infos.retain(|info| info.component_id != component_id);
+            if tool_infos.is_empty() {
+                self.tool_map.remove(tool_name);
+            }
+        }
+        if let Some(entries) = self.aliases.get_mut(qualified_name) {
+            entries.retain(|(id, name)| id != component_id || name != tool_name);
+            if entries.is_empty() {
+                self.aliases.remove(qualified_name);
+            }
+        }
+    }
+
+    /// Maps a requested tool name to the registered one. Registered names are returned as is,
+    /// while unsanitized qualified names (e.g. `wasi:cli/run@0.2.0.run`) are mapped to the
+    /// sanitized name they were registered under, as long as that mapping is unambiguous.
+    fn resolve_tool_name<'a>(&'a self, tool_name: &'a str) -> Option<&'a str> {
+        if self.tool_map.contains_key(tool_name) {
+            return Some(tool_name);
+        }
+        let (first, rest) = self.aliases.get(tool_name)?.split_first()?;
+        rest.iter()
+            .all(|(_, name)| name == &first.1)
+            .then_some(first.1.as_str())
+    }
+
+    fn get_tool(&self, tool_name: &str) -> Option<&ToolInfo> {
+        self.get_tool_info(tool_name)
             .and_then(|tool_infos| tool_infos.first())
-            .map(|tool_info| &tool_info.identifier)
     }
 
     fn unregister_component(&mut self, component_id: &str) {
@@ -107,16 +454,24 @@ impl ComponentRegistry {
                 }
             }
         }
+        self.aliases.retain(|_, entries| {
+            entries.retain(|(id, _)| id != component_id);
+            !entries.is_empty()
+        });
     }
 
     fn get_tool_info(&self, tool_name: &str) -> Option<&Vec<ToolInfo>> {
-        self.tool_map.get(tool_name)
+        self.tool_map.get(self.resolve_tool_name(tool_name)?)
     }
 
-    fn list_tools(&self) -> Vec<Value> {
-        self.tool_map
-            .values()
-            .flat_map(|tools| tools.iter().map(|t| t.schema.clone()))
+    fn component_schemas(&self, component_id: &str) -> Vec<ToolSchema> {
+        self.component_map
+            .get(component_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|name| self.tool_map.get(name))
+            .flat_map(|tools| tools.iter().filter(|t| t.component_id == component_id))
+            .map(|t| t.schema.clone())
             .collect()
     }
 }
@@ -126,12 +481,44 @@ impl ComponentRegistry {
 pub struct LifecycleManager {
     engine: Arc<Engine>,
     linker: Arc<Linker<WassetteWasiState<WasiState>>>,
-    components: Arc<RwLock<HashMap<String, ComponentInstance>>>,
-    registry: Arc<RwLock<ComponentRegistry>>,
-    policy_registry: Arc<RwLock<PolicyRegistry>>,
+    components: Arc<TrackedRwLock<BTreeMap<String, ComponentInstance>>>,
+    /// Labels of the loaded components, as stored in their metadata files
+    labels: Arc<RwLock<BTreeMap<String, Labels>>>,
+    registry: Arc<TrackedRwLock<ComponentRegistry>>,
+    policy_registry: Arc<TrackedRwLock<PolicyRegistry>>,
     oci_client: Arc<oci_wasm::WasmClient>,
     http_client: reqwest::Client,
+    http_cache: Arc<HttpCache>,
+    credentials: CredentialStore,
+    mirrors: Arc<MirrorSet>,
     plugin_dir: PathBuf,
+    tool_naming: Arc<ToolNameSanitizer>,
+    uploads: Arc<RwLock<UploadStore>>,
+    stats: Arc<RwLock<StatsStore>>,
+    budgets: Arc<RwLock<BudgetStore>>,
+    live_stores: Arc<AtomicUsize>,
+    active_sessions: Arc<AtomicUsize>,
+    /// Notifications queued for and dropped by the clients of all sessions
+    notifications: Arc<NotificationCounters>,
+    /// The configuration in effect, which [`LifecycleManager::reload`] replaces
+    config: Arc<RwLock<LifecycleConfig>>,
+    transcripts: Arc<RwLock<TranscriptStore>>,
+    events: tokio::sync::broadcast::Sender<LifecycleEvent>,
+    /// Channel of [`LifecycleEvent::NetworkRequested`], which are only sent while it has
+    /// subscribers
+    network_events: tokio::sync::broadcast::Sender<LifecycleEvent>,
+    /// Worker pools tool calls run on, sized when the manager is created
+    executor: Arc<ExecutionPools>,
+    /// Results of pure tools that identical calls get without running the tool
+    memo: Arc<RwLock<MemoCache>>,
+    /// Hits and misses of precompiled components when loading components
+    compilation_cache: Arc<CacheCounters>,
+    /// Where components store blobs through `wasi:blobstore`
+    blobs: Arc<Blobs>,
+    /// Loaders of the URI schemes embedders registered
+    sources: Arc<RwLock<SourceLoaders>>,
+    #[cfg(feature = "wasi-nn")]
+    nn_models: Arc<nn::NnModels>,
 }
 
 /// A representation of a loaded component instance. It contains both the base component info and a
@@ -140,6 +527,12 @@ pub struct LifecycleManager {
 pub struct ComponentInstance {
     component: Arc<Component>,
     instance_pre: Arc<InstancePre<WassetteWasiState<WasiState>>>,
+    world_name: Option<String>,
+    tool_metadata: Option<Value>,
+    /// SHA-256 digest of the component binary
+    digest: String,
+    /// Capabilities the component's imports need
+    required_capabilities: Vec<RequiredCapability>,
 }
 
 impl LifecycleManager {
@@ -147,10 +540,20 @@ impl LifecycleManager {
     /// This is the primary way to create a LifecycleManager for most use cases
     #[instrument(skip_all, fields(plugin_dir = %plugin_dir.as_ref().display()))]
     pub async fn new(plugin_dir: impl AsRef<Path>) -> Result<Self> {
-        Self::new_with_clients(
+        Self::new_with_config(plugin_dir, LifecycleConfig::default()).await
+    }
+
+    /// Creates a lifecycle manager with the given [`LifecycleConfig`]
+    #[instrument(skip_all, fields(plugin_dir = %plugin_dir.as_ref().display()))]
+    pub async fn new_with_config(
+        plugin_dir: impl AsRef<Path>,
+        config: LifecycleConfig,
+    ) -> Result<Self> {
+        Self::new_with_clients_and_config(
             plugin_dir,
             oci_client::Client::default(),
             reqwest::Client::default(),
+            config,
         )
         .await
     }
@@ -161,6 +564,23 @@ impl LifecycleManager {
         plugin_dir: impl AsRef<Path>,
         oci_client: oci_client::Client,
         http_client: reqwest::Client,
+    ) -> Result<Self> {
+        Self::new_with_clients_and_config(
+            plugin_dir,
+            oci_client,
+            http_client,
+            LifecycleConfig::default(),
+        )
+        .await
+    }
+
+    /// Creates a lifecycle manager with custom clients and the given [`LifecycleConfig`]
+    #[instrument(skip_all)]
+    pub async fn new_with_clients_and_config(
+        plugin_dir: impl AsRef<Path>,
+        oci_client: oci_client::Client,
+        http_client: reqwest::Client,
+        config: LifecycleConfig,
     ) -> Result<Self> {
         let components_dir = plugin_dir.as_ref();
 
@@ -168,13 +588,10 @@ impl LifecycleManager {
             fs::create_dir_all(components_dir)?;
         }
 
-        let mut config = wasmtime::Config::new();
-        config.wasm_component_model(true);
-        config.async_support(true);
-        let engine = Arc::new(wasmtime::Engine::new(&config)?);
+        let engine = create_engine(&config)?;
 
         // Create the lifecycle manager
-        Self::new_with_policy(engine, components_dir, oci_client, http_client).await
+        Self::new_with_policy(engine, components_dir, oci_client, http_client, config).await
     }
 
     /// Creates a lifecycle manager with custom clients and WASI state template
@@ -184,77 +601,130 @@ impl LifecycleManager {
         plugin_dir: impl AsRef<Path>,
         oci_client: oci_client::Client,
         http_client: reqwest::Client,
+        config: LifecycleConfig,
     ) -> Result<Self> {
         info!("Creating new LifecycleManager");
 
-        let mut registry = ComponentRegistry::new();
-        let mut components = HashMap::new();
+        let tool_naming = Arc::new(config.tool_naming.sanitizer()?);
+        let mut registry = ComponentRegistry::new(
+            tool_naming.clone(),
+            config.tool_naming.dedup_across_components,
+        );
+        let mut components = BTreeMap::new();
+        let mut component_labels = BTreeMap::new();
         let mut policy_registry = PolicyRegistry::default();
 
-        let mut linker = Linker::new(engine.as_ref());
-        wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
-
-        // Use the standard HTTP linker - filtering happens at WasiHttpView level
-        wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker)?;
-
-        wasmtime_wasi_config::add_to_linker(
-            &mut linker,
-            |h: &mut WassetteWasiState<WasiState>| WasiConfig::from(&h.inner.wasi_config_vars),
-        )?;
+        let linker = create_linker(&engine)?;
+        #[cfg(feature = "wasi-nn")]
+        let nn_models = Arc::new(nn::NnModels::load(&config.ai_models)?);
+        #[cfg(not(feature = "wasi-nn"))]
+        if !config.ai_models.is_empty() {
+            warn!("AI models are configured, but wassette was built without the wasi-nn feature");
+        }
 
         let linker = Arc::new(linker);
 
-        let loaded_components =
-            tokio_stream::wrappers::ReadDirStream::new(tokio::fs::read_dir(&plugin_dir).await?)
-                .map_err(anyhow::Error::from)
-                .try_filter_map(|entry| {
-                    let value = engine.clone();
-                    let link = linker.clone();
-                    async move { load_component_from_entry(value, &link, entry).await }
-                })
-                .try_collect::<Vec<_>>()
-                .await?;
+        layout::upgrade_layout(plugin_dir.as_ref()).await?;
+        // Components copied into the plugin directory by hand may still use flat IDs
+        component_id::migrate_flat_components(plugin_dir.as_ref()).await?;
+        let mut sources = Vec::new();
+        for (id, path) in component_id::component_files(plugin_dir.as_ref()).await? {
+            sources.push(read_component_source(id, &path).await?);
+        }
+        // Compile hot components first, the ones that are expected to be called right away
+        sources.sort_by_key(|source| !config.is_hot(&source.name, source.tool_metadata.as_ref()));
+        let hot_components: Vec<String> = sources
+            .iter()
+            .filter(|source| config.is_hot(&source.name, source.tool_metadata.as_ref()))
+            .map(|source| source.name.clone())
+            .collect();
+
+        // Registered before any component, so that their names don't depend on what's installed
+        if !config.disable_host_tools {
+            registry
+                .register_tools(HOST_TOOLS_ID, host_tools::tools(&tool_naming))
+                .context("unable to register host tools")?;
+            if let Some(template) =
+                policy_internal::read_policy_template(plugin_dir.as_ref(), HOST_TOOLS_ID).await?
+            {
+                policy_registry.insert(HOST_TOOLS_ID.to_string(), template);
+            }
+        }
+
+        let compilation_cache = Arc::new(CacheCounters::default());
+        let mut loaded_components = Vec::with_capacity(sources.len());
+        for source in sources {
+            loaded_components.push(
+                compile_component(
+                    engine.clone(),
+                    &linker,
+                    source,
+                    plugin_dir.as_ref(),
+                    PrecompiledArtifacts::from_config(&config),
+                    &compilation_cache,
+                )
+                .await?,
+            );
+        }
+        // Register in a stable order so deduplicated tool names don't change between restarts
+        loaded_components.sort_by(|(_, a), (_, b)| a.cmp(b));
 
         for (component_instance, name) in loaded_components.into_iter() {
-            let tool_metadata =
-                component_exports_to_tools(&component_instance.component, &engine, true);
+            let mut tool_metadata = component_exports_to_tools_with_sanitizer(
+                &component_instance.component,
+                &engine,
+                true,
+                &tool_naming,
+            );
+            command::synthesize_command_tools(&name, &mut tool_metadata, &tool_naming);
+            if let Some(metadata) = &component_instance.tool_metadata {
+                apply_tool_metadata(&mut tool_metadata, metadata);
+            }
             registry
                 .register_tools(&name, tool_metadata)
                 .context("unable to insert component into registry")?;
+            let metadata_path = component_id::metadata_path(plugin_dir.as_ref(), &name);
+            match ComponentMetadata::read(&metadata_path).await {
+                Ok(metadata) => {
+                    let metadata = metadata.unwrap_or_else(|| ComponentMetadata::new(&name));
+                    if metadata
+                        .digest
+                        .as_ref()
+                        .is_some_and(|digest| *digest != component_instance.digest)
+                    {
+                        warn!(component_id = %name, "Component changed on disk since it was installed, its digest doesn't match the recorded one");
+                    }
+                    component_labels.insert(name.clone(), metadata.labels);
+                }
+                Err(e) => {
+                    warn!(component_id = %name, error = %e, "Failed to read component metadata");
+                }
+            }
             components.insert(name.clone(), component_instance);
 
             // Check for co-located policy file and restore policy association
-            let policy_path = plugin_dir.as_ref().join(format!("{name}.policy.yaml"));
-            if policy_path.exists() {
-                match tokio::fs::read_to_string(&policy_path).await {
-                    Ok(policy_content) => match PolicyParser::parse_str(&policy_content) {
-                        Ok(policy) => {
-                            match wasistate::create_wasi_state_template_from_policy(
-                                &policy,
-                                plugin_dir.as_ref(),
-                            ) {
-                                Ok(wasi_template) => {
-                                    policy_registry
-                                        .component_policies
-                                        .insert(name.clone(), Arc::new(wasi_template));
-                                    info!(component_id = %name, "Restored policy association from co-located file");
-                                }
-                                Err(e) => {
-                                    warn!(component_id = %name, error = %e, "Failed to create WASI template from policy");
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            warn!(component_id = %name, error = %e, "Failed to parse co-located policy file");
-                        }
-                    },
-                    Err(e) => {
-                        warn!(component_id = %name, error = %e, "Failed to read co-located policy file");
-                    }
+            match policy_internal::read_policy_template(plugin_dir.as_ref(), &name).await {
+                Ok(Some(wasi_template)) => {
+                    policy_registry.insert(name.clone(), wasi_template);
+                    info!(component_id = %name, "Restored policy association from co-located file");
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(component_id = %name, error = %e, "Failed to restore co-located policy");
                 }
             }
         }
 
+        let in_use: HashSet<&str> = components
+            .values()
+            .map(|instance| instance.digest.as_str())
+            .collect();
+        match content::collect_garbage(plugin_dir.as_ref(), &in_use).await {
+            Ok(0) => {}
+            Ok(removed) => info!(removed, "Removed unused content store entries"),
+            Err(e) => warn!(error = %e, "Failed to clean up the content store"),
+        }
+
         // Make sure the plugin dir exists and also create a subdirectory for temporary staging of downloaded files
         tokio::fs::create_dir_all(&plugin_dir)
             .await
@@ -262,86 +732,279 @@ impl LifecycleManager {
         tokio::fs::create_dir_all(plugin_dir.as_ref().join(DOWNLOADS_DIR))
             .await
             .context("Failed to create downloads directory")?;
+        if !config.disable_host_tools {
+            // Where the policy of the host tools is saved
+            if let Some((namespace, _)) = HOST_TOOLS_ID.split_once('/') {
+                tokio::fs::create_dir_all(plugin_dir.as_ref().join(namespace)).await?;
+            }
+        }
+        let blobs = Arc::new(Blobs::open(&config.blobstore, plugin_dir.as_ref())?);
 
-        info!("LifecycleManager initialized successfully");
-        Ok(Self {
+        let manager = Self {
             engine,
             linker,
-            components: Arc::new(RwLock::new(components)),
-            registry: Arc::new(RwLock::new(registry)),
-            policy_registry: Arc::new(RwLock::new(policy_registry)),
+            components: Arc::new(locks::tracked(LockName::Components, components)),
+            labels: Arc::new(RwLock::new(component_labels)),
+            registry: Arc::new(locks::tracked(LockName::Registry, registry)),
+            policy_registry: Arc::new(locks::tracked(LockName::PolicyRegistry, policy_registry)),
             oci_client: Arc::new(oci_wasm::WasmClient::new(oci_client)),
             http_client,
+            http_cache: Arc::new(HttpCache::new(plugin_dir.as_ref())),
+            credentials: CredentialStore::open(plugin_dir.as_ref()),
+            mirrors: Arc::new(MirrorSet::new(config.mirrors.clone())),
             plugin_dir: plugin_dir.as_ref().to_path_buf(),
-        })
+            tool_naming,
+            uploads: Arc::new(RwLock::new(UploadStore::default())),
+            stats: Arc::new(RwLock::new(StatsStore::open(plugin_dir.as_ref()).await)),
+            budgets: Arc::new(RwLock::new(BudgetStore::open(plugin_dir.as_ref()).await)),
+            live_stores: Arc::new(AtomicUsize::new(0)),
+            active_sessions: Arc::new(AtomicUsize::new(0)),
+            notifications: Arc::default(),
+            transcripts: Arc::new(RwLock::new(
+                TranscriptStore::open(plugin_dir.as_ref(), config.transcript_retention()).await,
+            )),
+            executor: Arc::new(ExecutionPools::new(&config.execution)),
+            memo: Arc::new(RwLock::new(MemoCache::default())),
+            compilation_cache,
+            config: Arc::new(RwLock::new(config.clone())),
+            events: tokio::sync::broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            network_events: tokio::sync::broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            blobs,
+            sources: Arc::new(RwLock::new(SourceLoaders::default())),
+            #[cfg(feature = "wasi-nn")]
+            nn_models,
+        };
+
+        if config.warm_up {
+            for component_id in &hot_components {
+                let start_time = Instant::now();
+                if let Err(e) = manager.warm_up_component(component_id).await {
+                    warn!(component_id = %component_id, error = %e, "Failed to warm up component");
+                    continue;
+                }
+                info!(component_id = %component_id, elapsed = ?start_time.elapsed(), "Warmed up component");
+            }
+        }
+
+        info!("LifecycleManager initialized successfully");
+        Ok(manager)
+    }
+
+    /// Instantiates the component once and throws the instance away, so that lazily initialized
+    /// engine state is set up before the first tool call
+    async fn warm_up_component(&self, component_id: &str) -> Result<()> {
+        let component = self
+            .get_component(component_id)
+            .await
+            .ok_or_else(|| anyhow!("Component not found: {}", component_id))?;
+        let state = self
+            .get_wasi_state_for_component(component_id, &CallState::default())
+            .await?;
+        let _live_store = LiveStoreGuard::new(&self.live_stores);
+        let mut store = self.new_store(state);
+        component
+            .instance_pre
+            .instantiate_async(&mut store)
+            .await
+            .context("Failed to instantiate component")?;
+        Ok(())
     }
 
     /// Loads a new component from the given URI. This URI can be a file path, an OCI reference, or a URL.
+    /// A `file://` URI of a `.wassette-pkg` package installs the component it contains, and its
+    /// policy if [`LifecycleConfig::attach_package_policies`] is set.
     ///
     /// If a component with the given id already exists, it will be updated with the new component.
     /// Returns the new ID and whether or not this component was replaced.
     #[instrument(skip(self))]
     pub async fn load_component(&self, uri: &str) -> Result<(String, LoadResult)> {
+        self.load_component_with_digest(uri, None).await
+    }
+
+    /// Loads a component like [`LifecycleManager::load_component`], but only if the SHA-256
+    /// digest of the fetched component is `expected_digest`. On a mismatch, nothing is installed
+    /// and a component already installed under the same ID is left as it is.
+    #[instrument(skip(self))]
+    pub(crate) async fn load_component_with_digest(
+        &self,
+        uri: &str,
+        expected_digest: Option<&str>,
+    ) -> Result<(String, LoadResult)> {
         debug!(uri, "Loading component");
+        if let Some(package) = package::package_path(uri) {
+            return self.load_package(package, expected_digest).await;
+        }
 
-        let downloaded_resource =
-            loader::load_resource::<ComponentResource>(uri, &self.oci_client, &self.http_client)
-                .await?;
+        let downloaded_resource = self.load_resource::<ComponentResource>(uri).await?;
 
-        let wasm_bytes = tokio::fs::read(downloaded_resource.as_ref())
+        let wasm_bytes = compression::read_component(downloaded_resource.as_ref())
             .await
             .context("Failed to read component file")?;
 
-        let component = Component::new(&self.engine, wasm_bytes).map_err(|e| anyhow::anyhow!("Failed to compile component from path: {}. Error: {}. Please ensure the file is a valid WebAssembly component.", downloaded_resource.as_ref().display(), e))?;
+        let world_name = component_world_name(&wasm_bytes);
+        let declared_metadata = component_tool_metadata(&wasm_bytes);
+        let digest = component_digest(&wasm_bytes);
+        if let Some(expected) = expected_digest {
+            if digest != expected {
+                bail!("Component {uri} has digest sha256:{digest}, expected sha256:{expected}");
+            }
+        }
+        let adapter::Adapted {
+            wasm: wasm_bytes,
+            preview1_capabilities,
+        } = adapter::adapt_if_module(wasm_bytes)?;
+        let artifacts = PrecompiledArtifacts::from_config(&*self.config.read().await);
+        // Compiling takes long enough to stall the runtime's other tasks
+        let component = tokio::task::spawn_blocking({
+            let engine = self.engine.clone();
+            let plugin_dir = self.plugin_dir.clone();
+            let digest = digest.clone();
+            let compilation_cache = self.compilation_cache.clone();
+            move || {
+                precompile::load_component(
+                    &engine,
+                    &plugin_dir,
+                    &digest,
+                    wasm_bytes,
+                    artifacts,
+                    &compilation_cache,
+                )
+            }
+        })
+        .await?
+        .map_err(|e| anyhow::anyhow!("Failed to compile component from path: {}. Error: {}. Please ensure the file is a valid WebAssembly component or WASI preview 1 module.", downloaded_resource.as_ref().display(), e))?;
+        imports::check_imports(&component, &self.linker)?;
+        let required_capabilities = preview1_capabilities
+            .unwrap_or_else(|| imports::required_capabilities(&component, &self.engine));
         // Pre-instantiate the component
         let instance_pre = self
             .linker
             .instantiate_pre(&component)
             .context("failed to instantiate component")?;
-        let id = downloaded_resource.id()?;
-        let tool_metadata = component_exports_to_tools(&component, &self.engine, true);
+        let id = component_id::component_id_for_uri(uri, &downloaded_resource.name()?)?;
+        if id == HOST_TOOLS_ID {
+            bail!("The component ID {HOST_TOOLS_ID} is reserved for the host tools");
+        }
+        self.warn_on_name_collision(&id).await;
+        let mut tool_metadata = component_exports_to_tools_with_sanitizer(
+            &component,
+            &self.engine,
+            true,
+            &self.tool_naming,
+        );
+        command::synthesize_command_tools(&id, &mut tool_metadata, &self.tool_naming);
+        if let Some(metadata) = &declared_metadata {
+            apply_tool_metadata(&mut tool_metadata, metadata);
+        }
 
-        {
+        let schema_diff = {
             let mut registry_write = self.registry.write().await;
-            registry_write.unregister_component(&id);
-            registry_write.register_tools(&id, tool_metadata)?;
-        }
+            let previous = registry_write.component_schemas(&id);
+            registry_write.update_tools(&id, tool_metadata)?;
+            SchemaDiff::between(&previous, &registry_write.component_schemas(&id))
+        };
 
-        if let Err(e) = downloaded_resource.copy_to(&self.plugin_dir).await {
+        let component_path = self.component_path(&id);
+        if let Err(e) = downloaded_resource.copy_to(&component_path).await {
             let mut registry_write = self.registry.write().await;
             registry_write.unregister_component(&id);
             bail!(
                 "Failed to copy component to destination: {}. Error: {}",
-                self.plugin_dir.display(),
+                component_path.display(),
                 e
             );
         }
-
-        let res = self
-            .components
+        if self.config.read().await.compress_components {
+            if let Err(e) = compression::compress_file(&component_path).await {
+                warn!(component_id = %id, error = %e, "Failed to compress installed component");
+            }
+        }
+        if let Err(e) = content::store(&self.plugin_dir, &component_path, &digest).await {
+            warn!(component_id = %id, error = %e, "Failed to add component to the content store");
+        }
+        // Record where the component came from next to it, keeping the labels it already has
+        let metadata_path = component_id::metadata_path(&self.plugin_dir, &id);
+        let mut metadata = component_id::ComponentMetadata::read(&metadata_path)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| component_id::ComponentMetadata::new(&id));
+        metadata.source_uri = Some(uri.to_string());
+        metadata.digest = Some(digest.clone());
+        metadata.loaded_at = Some(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        );
+        if let Err(e) = metadata.write(&metadata_path).await {
+            warn!(component_id = %id, error = %e, "Failed to write component metadata");
+        }
+        self.labels
             .write()
             .await
-            .insert(
-                id.clone(),
-                ComponentInstance {
-                    component: Arc::new(component),
-                    instance_pre: Arc::new(instance_pre),
-                },
-            )
-            .map(|_| LoadResult::Replaced)
-            .unwrap_or(LoadResult::New);
+            .insert(id.clone(), metadata.labels);
+
+        let previous = self.components.write().await.insert(
+            id.clone(),
+            ComponentInstance {
+                component: Arc::new(component),
+                instance_pre: Arc::new(instance_pre),
+                world_name,
+                tool_metadata: declared_metadata,
+                digest,
+                required_capabilities,
+            },
+        );
+        let res = match previous {
+            Some(previous) => {
+                self.release_content(&previous.digest).await;
+                LoadResult::Replaced(schema_diff)
+            }
+            None => LoadResult::New,
+        };
+
+        self.restore_policy(&id).await;
 
         info!("Successfully loaded component");
+        if let LoadResult::Replaced(diff) = &res {
+            log_schema_diff(&id, diff);
+        }
+        self.emit(LifecycleEvent::ComponentLoaded {
+            component_id: id.clone(),
+            replaced: matches!(res, LoadResult::Replaced(_)),
+            source_uri: Some(uri.to_string()),
+        });
         Ok((id, res))
     }
 
-    /// Helper function to remove a file with consistent logging and error handling
+    /// Loads several components concurrently, downloading and compiling at most `concurrency` at
+    /// a time. Returns the result for every URI, in the order they were given.
+    #[instrument(skip(self))]
+    pub async fn load_components(
+        &self,
+        uris: &[String],
+        concurrency: usize,
+    ) -> Vec<(String, Result<(String, LoadResult)>)> {
+        // Owned URIs keep the future `Send` for callers that box it
+        futures::stream::iter(uris.iter().cloned())
+            .map(|uri| async move {
+                let result = self.load_component(&uri).await;
+                (uri, result)
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Helper function to remove a file with consistent logging and error handling. Returns
+    /// whether there was a file to remove.
     async fn remove_file_if_exists(
         &self,
         file_path: &std::path::Path,
         file_type: &str,
         component_id: &str,
-    ) -> Result<()> {
+    ) -> Result<bool> {
         match tokio::fs::remove_file(file_path).await {
             Ok(()) => {
                 debug!(
@@ -349,6 +1012,7 @@ impl LifecycleManager {
                     path = %file_path.display(),
                     "Removed {}", file_type
                 );
+                Ok(true)
             }
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
                 debug!(
@@ -356,49 +1020,135 @@ impl LifecycleManager {
                     path = %file_path.display(),
                     "{} already absent", file_type
                 );
+                Ok(false)
             }
+            Err(e) => Err(anyhow::anyhow!(
+                "Failed to remove {} at {}: {}",
+                file_type,
+                file_path.display(),
+                e
+            )),
+        }
+    }
+
+    /// Removes a directory of files that belong to a component, and its namespace directory once
+    /// that is empty
+    async fn remove_dir_if_exists(
+        &self,
+        dir: &std::path::Path,
+        dir_type: &str,
+        component_id: &str,
+    ) -> Result<()> {
+        match tokio::fs::remove_dir_all(dir).await {
+            Ok(()) => {
+                debug!(component_id = %component_id, path = %dir.display(), "Removed {}", dir_type);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
             Err(e) => {
-                return Err(anyhow::anyhow!(
-                    "Failed to remove {} at {}: {}",
-                    file_type,
-                    file_path.display(),
-                    e
-                ));
+                bail!("Failed to remove {} at {}: {}", dir_type, dir.display(), e)
             }
         }
+        if let Some(namespace_dir) = dir.parent() {
+            let _ = tokio::fs::remove_dir(namespace_dir).await;
+        }
         Ok(())
     }
 
     /// Unloads the component with the specified id. This removes the component from the runtime
     /// and removes all associated files from disk, making it the reverse operation of load_component.
-    /// This function fails if any files cannot be removed (except when they don't exist).
+    /// This function fails if any files cannot be removed (except when they don't exist). Unloading
+    /// a component that doesn't exist returns [`UnloadResult::NotFound`].
     #[instrument(skip(self))]
-    pub async fn unload_component(&self, id: &str) -> Result<()> {
+    pub async fn unload_component(&self, id: &str) -> Result<UnloadResult> {
+        self.unload_component_with_options(id, &UnloadOptions::default())
+            .await
+    }
+
+    /// Unloads the component with the specified id like [`LifecycleManager::unload_component`].
+    /// Besides the component file, its policy and metadata files, a half-written compressed copy,
+    /// its blob containers, its traces, profiles, core dumps and transcripts and its usage
+    /// statistics and memoized results are removed, unless `options` keep the policy or the
+    /// blob containers.
+    #[instrument(skip(self))]
+    pub async fn unload_component_with_options(
+        &self,
+        id: &str,
+        options: &UnloadOptions,
+    ) -> Result<UnloadResult> {
         debug!("Unloading component and removing files from disk");
+        if id == HOST_TOOLS_ID {
+            bail!("The host tools can't be unloaded, set disable_host_tools in the configuration to turn them off");
+        }
 
         // Remove files first, then clean up memory on success
         let component_file = self.component_path(id);
-        self.remove_file_if_exists(&component_file, "component file", id)
+        let mut removed = self
+            .remove_file_if_exists(&component_file, "component file", id)
             .await?;
-
-        let policy_path = self.get_component_policy_path(id);
-        self.remove_file_if_exists(&policy_path, "policy file", id)
+        removed |= self
+            .remove_file_if_exists(
+                &compression::staged_path(&component_file),
+                "staged component file",
+                id,
+            )
             .await?;
 
-        let metadata_path = self.get_component_metadata_path(id);
-        self.remove_file_if_exists(&metadata_path, "policy metadata file", id)
+        if !options.keep_policy {
+            let policy_path = self.get_component_policy_path(id);
+            removed |= self
+                .remove_file_if_exists(&policy_path, "policy file", id)
+                .await?;
+
+            let metadata_path = self.get_component_metadata_path(id);
+            removed |= self
+                .remove_file_if_exists(&metadata_path, "policy metadata file", id)
+                .await?;
+        }
+
+        let source_path = component_id::metadata_path(&self.plugin_dir, id);
+        removed |= self
+            .remove_file_if_exists(&source_path, "component metadata file", id)
             .await?;
+        if !options.keep_data {
+            self.blobs.remove_component(id).await?;
+        }
+        for dir in [TRACES_DIR, profiling::PROFILES_DIR, DIAGNOSTICS_DIR] {
+            self.remove_dir_if_exists(&self.plugin_dir.join(dir).join(id), dir, id)
+                .await?;
+        }
+        self.transcripts.write().await.remove_component(id).await?;
+        // Drop the namespace directory once its last component is gone
+        if let Some(namespace_dir) = component_file.parent() {
+            if namespace_dir != self.plugin_dir {
+                let _ = tokio::fs::remove_dir(namespace_dir).await;
+            }
+        }
 
         // Only cleanup memory after all files are successfully removed
-        self.components.write().await.remove(id);
+        let instance = self.components.write().await.remove(id);
+        if let Some(instance) = &instance {
+            self.release_content(&instance.digest).await;
+        }
+        removed |= instance.is_some();
+        self.labels.write().await.remove(id);
         self.registry.write().await.unregister_component(id);
+        self.memo.write().await.remove_component(id);
+        self.remove_tool_stats(id).await;
         self.cleanup_policy_registry(id).await;
 
+        if !removed {
+            info!(component_id = %id, "No component to unload");
+            return Ok(UnloadResult::NotFound);
+        }
         info!(component_id = %id, "Component unloaded successfully");
-        Ok(())
+        self.emit(LifecycleEvent::ComponentUnloaded {
+            component_id: id.to_string(),
+        });
+        Ok(UnloadResult::Removed)
     }
 
-    /// Returns the component ID for a given tool name.
+    /// Returns the component ID for a given tool name. Besides the registered (sanitized) name,
+    /// the unsanitized qualified function name is accepted as well.
     /// If there are multiple components with the same tool name, returns an error.
     #[instrument(skip(self))]
     pub async fn get_component_id_for_tool(&self, tool_name: &str) -> Result<String> {
@@ -422,10 +1172,122 @@ impl LifecycleManager {
         Ok(tool_infos[0].component_id.clone())
     }
 
-    /// Lists all available tools across all components
+    /// Lists all available tools across all components, ordered by tool name and then by component
+    /// ID
+    #[instrument(skip(self))]
+    pub async fn list_tools(&self) -> Vec<ToolSchema> {
+        self.annotated_tools(|_| true).await
+    }
+
+    /// Returns the configured language of builtin tool descriptions and error messages
+    pub async fn language(&self) -> Option<String> {
+        self.config.read().await.language.clone()
+    }
+
+    /// Returns how the notifications of MCP sessions are queued
+    pub async fn notification_queue_config(&self) -> NotificationQueueConfig {
+        self.config.read().await.notification_queue.clone()
+    }
+
+    /// Returns the limits on MCP sessions served over HTTP
+    pub async fn session_config(&self) -> SessionConfig {
+        self.config.read().await.sessions.clone()
+    }
+
+    /// Lists the tools to advertise to clients: those of the components matched by the
+    /// configured [`tool_selector`](LifecycleConfig::tool_selector), or all tools if it is unset.
+    /// Tools that aren't listed can still be called. With
+    /// [`compact_schemas`](LifecycleConfig::compact_schemas) set, their deeply nested types are
+    /// abbreviated.
     #[instrument(skip(self))]
-    pub async fn list_tools(&self) -> Vec<Value> {
-        self.registry.read().await.list_tools()
+    pub async fn list_visible_tools(&self) -> Vec<ToolSchema> {
+        let (selector, compact) = {
+            let config = self.config.read().await;
+            (config.tool_selector.clone(), config.compact_schemas)
+        };
+        let mut tools = match selector {
+            Some(selector) => {
+                let selected = self.list_components_matching(&selector).await;
+                self.annotated_tools(|info| selected.contains(&info.component_id))
+                    .await
+            }
+            None => self.list_tools().await,
+        };
+        if compact {
+            tools.iter_mut().for_each(compact_tool_schema);
+        }
+        tools
+    }
+
+    /// Returns the schemas of the registered tools that pass the filter, ordered like
+    /// [`list_tools`](Self::list_tools), with the annotations that their component's metadata
+    /// leaves unset inferred from its policy
+    async fn annotated_tools(&self, filter: impl Fn(&ToolInfo) -> bool) -> Vec<ToolSchema> {
+        let policies = self.policy_registry.read().await.component_policies.clone();
+        let default_policy = Self::create_default_policy_template();
+        self.registry
+            .read()
+            .await
+            .tool_map
+            .values()
+            .flatten()
+            .filter(|info| filter(info))
+            .map(|info| {
+                let policy = policies.get(&info.component_id).unwrap_or(&default_policy);
+                annotations::annotate(&info.schema, policy)
+            })
+            .collect()
+    }
+
+    /// Returns the policy template that applies to a component
+    async fn policy_template(&self, component_id: &str) -> Arc<WasiStateTemplate> {
+        self.policy_registry
+            .read()
+            .await
+            .component_policies
+            .get(component_id)
+            .cloned()
+            .unwrap_or_else(Self::create_default_policy_template)
+    }
+
+    /// Returns the schema of a tool, including its `inputSchema`. Returns `None` if the tool is
+    /// unknown.
+    #[instrument(skip(self))]
+    pub async fn get_tool_schema(&self, tool_name: &str) -> Option<ToolSchema> {
+        let (component_id, schema) = self
+            .registry
+            .read()
+            .await
+            .get_tool(tool_name)
+            .map(|tool_info| (tool_info.component_id.clone(), tool_info.schema.clone()))?;
+        let policy = self.policy_template(&component_id).await;
+        Some(annotations::annotate(&schema, &policy))
+    }
+
+    /// Returns how a tool is deprecated, or `None` if the tool is unknown or not deprecated
+    #[instrument(skip(self))]
+    pub async fn get_tool_deprecation(&self, tool_name: &str) -> Option<Deprecation> {
+        self.registry
+            .read()
+            .await
+            .get_tool(tool_name)
+            .and_then(|tool_info| tool_info.deprecation.clone())
+    }
+
+    /// Returns whether a call asks the tool for a preview of what it would do, which the tool's
+    /// plan variant answers instead of the tool itself
+    pub async fn is_preview_call(&self, tool_name: &str, parameters: &str) -> bool {
+        let has_plan = self
+            .registry
+            .read()
+            .await
+            .get_tool(tool_name)
+            .is_some_and(|tool_info| tool_info.plan.is_some());
+        has_plan
+            && parse_tool_arguments(parameters.as_bytes())
+                .ok()
+                .and_then(|arguments| arguments.get(PREVIEW_ARGUMENT)?.as_bool())
+                .unwrap_or(false)
     }
 
     /// Returns the requested component. Returns `None` if the component is not found.
@@ -434,62 +1296,609 @@ impl LifecycleManager {
         self.components.read().await.get(component_id).cloned()
     }
 
-    /// Lists all loaded components by their IDs
+    /// Lists all loaded components by their IDs, in sorted order
     #[instrument(skip(self))]
     pub async fn list_components(&self) -> Vec<String> {
         self.components.read().await.keys().cloned().collect()
     }
 
-    /// Gets the schema for a specific component
+    /// Lists the loaded components whose labels match the selector by their IDs, in sorted order
+    #[instrument(skip(self))]
+    pub async fn list_components_matching(&self, selector: &LabelSelector) -> Vec<String> {
+        let labels = self.labels.read().await;
+        self.list_components()
+            .await
+            .into_iter()
+            .filter(|id| selector.matches(labels.get(id).unwrap_or(&Labels::new())))
+            .collect()
+    }
+
+    /// Returns the URI a component was loaded from, or `None` if it isn't known, such as for
+    /// components copied into the plugin directory by hand
+    #[instrument(skip(self))]
+    pub async fn get_component_source(&self, component_id: &str) -> Option<String> {
+        let metadata_path = component_id::metadata_path(&self.plugin_dir, component_id);
+        ComponentMetadata::read(&metadata_path)
+            .await
+            .ok()
+            .flatten()?
+            .source_uri
+    }
+
+    /// Returns the labels of a component, or `None` if the component isn't loaded
+    #[instrument(skip(self))]
+    pub async fn get_component_labels(&self, component_id: &str) -> Option<Labels> {
+        self.get_component(component_id).await?;
+        Some(
+            self.labels
+                .read()
+                .await
+                .get(component_id)
+                .cloned()
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Sets and removes labels of a loaded component and stores them in its metadata file.
+    /// Returns the labels after the change.
+    #[instrument(skip(self))]
+    pub async fn set_component_labels(
+        &self,
+        component_id: &str,
+        set: &Labels,
+        remove: &[String],
+    ) -> Result<Labels> {
+        if self.get_component(component_id).await.is_none() {
+            bail!("Component not found: {component_id}");
+        }
+        let labels = labels::update_labels(&self.plugin_dir, component_id, set, remove).await?;
+        self.labels
+            .write()
+            .await
+            .insert(component_id.to_string(), labels.clone());
+        Ok(labels)
+    }
+
+    /// Gets the schema for a specific component. Alongside the tools, the schema contains the
+    /// name of the component's WIT world when the binary records it.
     #[instrument(skip(self))]
-    pub async fn get_component_schema(&self, component_id: &str) -> Option<Value> {
+    pub async fn get_component_schema(&self, component_id: &str) -> Option<ToolsDocument> {
         let component_instance = self.get_component(component_id).await?;
-        Some(component_exports_to_json_schema(
-            &component_instance.component,
-            self.engine.as_ref(),
-            true,
+        let policy = self.policy_template(component_id).await;
+        let tools = self
+            .registry
+            .read()
+            .await
+            .component_schemas(component_id)
+            .iter()
+            .map(|schema| annotations::annotate(schema, &policy))
+            .collect();
+        Some(ToolsDocument {
+            tools,
+            world: component_instance.world_name,
+        })
+    }
+
+    /// Returns the capabilities a component's imports need, whether or not its policy grants them,
+    /// or `None` if the component isn't loaded. For a WASI preview 1 module, they are mapped from
+    /// the preview 1 functions it imports.
+    #[instrument(skip(self))]
+    pub async fn get_required_capabilities(
+        &self,
+        component_id: &str,
+    ) -> Option<Vec<RequiredCapability>> {
+        Some(
+            self.get_component(component_id)
+                .await?
+                .required_capabilities,
+        )
+    }
+
+    /// Returns the capabilities a component's imports need that its current policy doesn't
+    /// grant, or `None` if the component isn't loaded. Calls using those imports fail until the
+    /// policy grants them.
+    #[instrument(skip(self))]
+    pub async fn get_capability_gaps(&self, component_id: &str) -> Option<Vec<RequiredCapability>> {
+        let component_instance = self.get_component(component_id).await?;
+        let policy_template = self.policy_template(component_id).await;
+        Some(imports::capability_gaps(
+            &component_instance.required_capabilities,
+            &policy_template,
         ))
     }
 
+    /// Maps a tool name to the name it is registered under, accepting either the registered name
+    /// or the unsanitized qualified function name. Returns `None` for unknown or ambiguous names.
+    #[instrument(skip(self))]
+    pub async fn resolve_tool_name(&self, tool_name: &str) -> Option<String> {
+        self.registry
+            .read()
+            .await
+            .resolve_tool_name(tool_name)
+            .map(String::from)
+    }
+
+    /// Removes the content store entry of a digest unless a loaded component still uses it
+    async fn release_content(&self, digest: &str) {
+        if self
+            .components
+            .read()
+            .await
+            .values()
+            .any(|instance| instance.digest == digest)
+        {
+            return;
+        }
+        if let Err(e) = content::release(&self.plugin_dir, digest).await {
+            warn!(digest, error = %e, "Failed to remove unused content store entry");
+        }
+    }
+
     fn component_path(&self, component_id: &str) -> PathBuf {
         self.plugin_dir.join(format!("{component_id}.wasm"))
     }
 
+    /// Resolves a component name to the ID of the only loaded component with that name, so that
+    /// `fetch` can be used for `local/fetch`. IDs of loaded components, and names no loaded
+    /// component has, are returned unchanged. Fails if several loaded components have the name.
+    #[instrument(skip(self))]
+    pub async fn resolve_component_id(&self, id: &str) -> Result<String> {
+        component_id::resolve_name(&self.list_components().await, id)
+    }
+
+    /// Warns when a component is loaded whose name is already used by a component from another
+    /// source. Both stay loaded under their own IDs, but can no longer be referred to by name.
+    async fn warn_on_name_collision(&self, id: &str) {
+        let name = component_name(id);
+        let components = self.components.read().await;
+        if let Some(other) = components
+            .keys()
+            .find(|other| *other != id && component_name(other) == name)
+        {
+            warn!(
+                component_id = %id,
+                other = %other,
+                "Another component with the same name is loaded, refer to both by their full ID"
+            );
+        }
+    }
+
+    /// Returns the policy template of a call, narrowed to the capabilities the call asks for and
+    /// without the grants whose time windows are closed, and when the first window of the
+    /// scheduled grants it keeps closes
+    async fn call_policy_template(
+        &self,
+        component_id: &str,
+        call: &CallState,
+    ) -> Result<(Arc<WasiStateTemplate>, Option<ScheduleDeadline>)> {
+        let policy_template = self.policy_template(component_id).await;
+        // The legacy defaults stand in for grants of the policy, so calls can narrow them too
+        let policy_template = if self.config.read().await.legacy_sandbox_defaults {
+            Arc::new(WasiStateTemplate {
+                allow_args: true,
+                allow_wall_clock: true,
+                ..(*policy_template).clone()
+            })
+        } else {
+            policy_template
+        };
+        // Closed grants are left out before narrowing, so that a call can't get them back by
+        // asking for a part of them
+        let (policy_template, schedule_deadline) = if policy_template.schedule.is_empty() {
+            (policy_template, None)
+        } else {
+            let (template, deadline) =
+                policy_template.scheduled_at(chrono::DateTime::from(std::time::SystemTime::now()));
+            (Arc::new(template), deadline)
+        };
+        let policy_template = match &call.capabilities {
+            Some(capabilities) => Arc::new(
+                policy_template
+                    .narrow(capabilities, &self.plugin_dir)
+                    .context("Invalid capabilities for the call")?,
+            ),
+            None => policy_template,
+        };
+        Ok((policy_template, schedule_deadline))
+    }
+
     async fn get_wasi_state_for_component(
         &self,
         component_id: &str,
+        call: &CallState,
     ) -> Result<WassetteWasiState<WasiState>> {
-        let policy_registry = self.policy_registry.read().await;
-
-        let policy_template = policy_registry
-            .component_policies
-            .get(component_id)
-            .cloned()
-            .unwrap_or_else(Self::create_default_policy_template);
+        let (policy_template, schedule_deadline) =
+            self.call_policy_template(component_id, call).await?;
+        let (group, policy_template) = self
+            .with_sandbox_group(component_id, policy_template)
+            .await?;
+        // Previews skip approval, so they must not change anything
+        let policy_template = if call.preview {
+            Arc::new(policy_template.read_only())
+        } else {
+            policy_template
+        };
+        let blobstore = match &group {
+            Some(group) => self
+                .blobs
+                .ctx_for_group(group, policy_template.blob_containers.as_deref()),
+            None => self
+                .blobs
+                .ctx_for(component_id, policy_template.blob_containers.as_deref()),
+        };
 
-        let wasi_state = policy_template.build()?;
+        let wasi_state = WasiState {
+            blobstore,
+            publisher: CallPublisher::new(
+                component_id,
+                policy_template.event_topics.as_deref(),
+                self.events.clone(),
+                call.events.clone(),
+                call.correlation.clone(),
+            ),
+            schedule_deadline,
+            ..policy_template.build_for_call(call)?
+        };
+        #[cfg(feature = "wasi-nn")]
+        let wasi_state = WasiState {
+            nn: self.nn_models.ctx_for(policy_template.ai_models.as_deref()),
+            ..wasi_state
+        };
         let allowed_hosts = policy_template.allowed_hosts.clone();
+        let wasi_state = WassetteWasiState::new(wasi_state, allowed_hosts)?;
+        // Requests are only reported while someone listens, so busy components don't flood a
+        // channel nobody reads
+        if self.network_events.receiver_count() == 0 {
+            return Ok(wasi_state);
+        }
+        Ok(wasi_state.with_monitor(RequestMonitor {
+            component_id: component_id.to_string(),
+            events: self.network_events.clone(),
+            correlation: call.correlation.clone(),
+        }))
+    }
+
+    /// Executes a function call on a WebAssembly component. Each call is recorded in the tool
+    /// usage statistics and counted against the component's budget, if its policy sets one.
+    /// Calls are rejected once the budget is exhausted. Calls that
+    /// [require approval](LifecycleManager::requires_approval) wait for it before they start.
+    #[instrument(skip(self))]
+    pub async fn execute_component_call(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+    ) -> Result<String> {
+        self.execute_component_call_with_options(
+            component_id,
+            function_name,
+            parameters,
+            CallOptions::default(),
+        )
+        .await
+        .map(|output| output.result)
+    }
+
+    /// Same as [`LifecycleManager::execute_component_call`], but with [`CallOptions`]. Calls to
+    /// components listed in [`LifecycleConfig::profile_components`] are always profiled, and calls
+    /// to components listed in [`LifecycleConfig::record_components`] are always recorded.
+    #[instrument(skip(self, options), fields(
+        request_id = options.correlation.request_id.as_deref(),
+        session_id = options.correlation.session_id.as_deref(),
+    ))]
+    pub async fn execute_component_call_with_options(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        options: CallOptions,
+    ) -> Result<CallOutput> {
+        let tool_name = self
+            .resolve_tool_name(function_name)
+            .await
+            .unwrap_or_else(|| function_name.to_string());
+        self.await_approval(component_id, &tool_name, parameters, &options.correlation)
+            .await?;
+        let preview = self.is_preview_call(&tool_name, parameters).await;
+        let (profile, record, class) = {
+            let config = self.config.read().await;
+            (
+                options.profile
+                    || config
+                        .profile_components
+                        .iter()
+                        .any(|id| component_id::id_matches(id, component_id)),
+                options.record
+                    || config
+                        .record_components
+                        .iter()
+                        .any(|id| component_id::id_matches(id, component_id)),
+                options
+                    .class
+                    .unwrap_or_else(|| config.execution.class_of(component_id)),
+            )
+        };
+        // Profiled, recorded and narrowed calls have to run the component
+        let memo_key = if profile || record || options.capabilities.is_some() {
+            None
+        } else {
+            self.memo_key(component_id, &tool_name, parameters).await
+        };
+        if let Some((key, _)) = &memo_key {
+            let start_time = Instant::now();
+            if let Some(result) = self.memo.write().await.get(key) {
+                debug!(component_id, tool_name = %tool_name, "Returning memoized result");
+                self.record_memoized_call(component_id, &tool_name, start_time.elapsed())
+                    .await;
+                return Ok(CallOutput {
+                    result,
+                    profile_path: None,
+                    trace_path: None,
+                    memory: MemoryUsage::default(),
+                });
+            }
+        }
+        self.reserve_budget(component_id).await?;
+        let profile_path =
+            profile.then(|| profiling::profile_path(&self.plugin_dir, component_id, &tool_name));
+        let request_id = options.correlation.request_id.clone();
+        self.emit(LifecycleEvent::ExecutionStarted {
+            component_id: component_id.to_string(),
+            tool: tool_name.clone(),
+            request_id: request_id.clone(),
+            session_id: options.correlation.session_id.clone(),
+        });
+        let call = CallState {
+            trace: record.then(|| TraceSession::record(component_id, &tool_name, parameters)),
+            stderr: options.stderr,
+            events: options.events,
+            correlation: options.correlation,
+            capabilities: options.capabilities,
+            command: None,
+            preview,
+        };
 
-        WassetteWasiState::new(wasi_state, allowed_hosts)
+        let start_time = Instant::now();
+        let call_function = {
+            let manager = self.clone();
+            let component_id = component_id.to_string();
+            let function_name = function_name.to_string();
+            let parameters = parameters.to_string();
+            let profile_path = profile_path.clone();
+            async move {
+                let mut memory = MemoryUsage::default();
+                let result = manager
+                    .call_component_function(
+                        &component_id,
+                        &function_name,
+                        &parameters,
+                        profile_path.as_deref(),
+                        &call,
+                        &mut memory,
+                    )
+                    .await;
+                (result, memory, call)
+            }
+        };
+        let (result, memory, call) = self
+            .executor
+            .run(class, call_function.in_current_span())
+            .await;
+        self.record_budget_time(component_id, start_time.elapsed())
+            .await;
+        self.record_tool_call(
+            component_id,
+            &tool_name,
+            start_time.elapsed(),
+            result.as_ref().err(),
+            memory,
+        )
+        .await;
+        if let (Some((key, ttl)), Ok(output)) = (memo_key, &result) {
+            self.memo.write().await.insert(key, output.clone(), ttl);
+        }
+        self.save_transcript(
+            component_id,
+            &tool_name,
+            parameters,
+            start_time.elapsed(),
+            &result,
+            memory,
+        )
+        .await;
+        let duration_ms = start_time.elapsed().as_millis() as u64;
+        self.emit(match &result {
+            Ok(_) => LifecycleEvent::ExecutionFinished {
+                component_id: component_id.to_string(),
+                tool: tool_name.clone(),
+                request_id,
+                duration_ms,
+            },
+            Err(e) => LifecycleEvent::ExecutionFailed {
+                component_id: component_id.to_string(),
+                tool: tool_name.clone(),
+                request_id,
+                duration_ms,
+                error: format!("{e:#}"),
+            },
+        });
+
+        if let Some(path) = &profile_path {
+            info!(component_id, tool_name = %tool_name, path = %path.display(), "Profiled tool call");
+        }
+        let trace_path = match call.trace {
+            Some(trace) => {
+                self.save_trace(trace, &result, component_id, &tool_name)
+                    .await
+            }
+            None => None,
+        };
+
+        let error = match result {
+            Ok(result) => {
+                return Ok(CallOutput {
+                    result,
+                    profile_path,
+                    trace_path,
+                    memory,
+                })
+            }
+            Err(e) => e,
+        };
+        match trace_path {
+            Some(path) => {
+                let message = format!("{error:#}");
+                Err(error.context(RecordedTrace { message, path }))
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Writes the recorded trace of a call together with its outcome, returning where it was
+    /// written to
+    async fn save_trace(
+        &self,
+        session: TraceSession,
+        result: &Result<String>,
+        component_id: &str,
+        tool_name: &str,
+    ) -> Option<PathBuf> {
+        let mut trace = session.trace();
+        match result {
+            Ok(output) => trace.result = Some(output.clone()),
+            Err(e) => trace.error = Some(format!("{e:#}")),
+        }
+        let path = replay::trace_path(&self.plugin_dir, component_id, tool_name);
+        if let Err(e) = trace.save(&path).await {
+            warn!(error = %e, "Failed to write trace");
+            return None;
+        }
+        info!(component_id, tool_name, path = %path.display(), "Recorded tool call");
+        Some(path)
+    }
+
+    /// Calls the tool of a recorded [`Trace`] again, feeding it the recorded clock readings,
+    /// random bytes, configuration variables and HTTP responses. Replayed calls are not counted
+    /// against budgets or in the usage statistics.
+    #[instrument(skip(self, trace), fields(component_id = %trace.component_id, tool = %trace.tool))]
+    pub async fn replay_trace(&self, trace: Trace) -> Result<String> {
+        let component_id = trace.component_id.clone();
+        let tool = trace.tool.clone();
+        let parameters = trace.parameters.clone();
+        let call = CallState {
+            trace: Some(TraceSession::replay(trace)),
+            ..Default::default()
+        };
+        self.call_component_function(
+            &component_id,
+            &tool,
+            &parameters,
+            None,
+            &call,
+            &mut MemoryUsage::default(),
+        )
+        .await
+    }
+
+    /// Creates a store for a single call. Epoch interruption is only used to take profiling
+    /// samples, so the deadline is set far enough out that unprofiled calls never reach it.
+    fn new_store(
+        &self,
+        state: WassetteWasiState<WasiState>,
+    ) -> Store<WassetteWasiState<WasiState>> {
+        let mut store = Store::new(self.engine.as_ref(), state);
+        store.set_epoch_deadline(u64::MAX / 2);
+        store.limiter(|state| &mut state.inner.limiter);
+        store
     }
 
-    /// Executes a function call on a WebAssembly component
-    #[instrument(skip(self))]
-    pub async fn execute_component_call(
+    /// Calls a tool in a new store, setting `memory` to the peak memory use of the call whether
+    /// or not it succeeds
+    async fn call_component_function(
         &self,
         component_id: &str,
         function_name: &str,
         parameters: &str,
+        profile_path: Option<&Path>,
+        call: &CallState,
+        memory: &mut MemoryUsage,
     ) -> Result<String> {
+        if component_id == HOST_TOOLS_ID {
+            return self.call_host_tool(function_name, parameters, call).await;
+        }
         let component = self
             .get_component(component_id)
             .await
             .ok_or_else(|| anyhow!("Component not found: {}", component_id))?;
 
-        let state = self.get_wasi_state_for_component(component_id).await?;
+        let command = self
+            .command_io(component_id, function_name, parameters)
+            .await?;
+        let call = &CallState {
+            command: command.clone(),
+            ..call.clone()
+        };
+        let state = self
+            .get_wasi_state_for_component(component_id, call)
+            .await?;
 
-        let mut store = Store::new(self.engine.as_ref(), state);
+        let _live_store = LiveStoreGuard::new(&self.live_stores);
+        let mut store = self.new_store(state);
+
+        let ticker = profile_path
+            .map(|_| profiling::start_profiling(&mut store, component_id, &component.component));
+        let schedule_deadline = store.data().inner.schedule_deadline.clone();
+        let invocation = self.invoke_tool(
+            &mut store,
+            &component,
+            function_name,
+            parameters,
+            command.as_ref(),
+        );
+        let result = schedule::until_deadline(schedule_deadline, invocation).await;
+        drop(ticker);
+        *memory = store.data().inner.limiter.usage();
+        // A profile of a failed call is just as useful, so it is written either way
+        if let Some(profile_path) = profile_path {
+            if let Err(e) = profiling::finish_profiling(&mut store, profile_path).await {
+                warn!(error = %e, "Failed to write profile");
+            }
+        }
+
+        let error = match result {
+            Ok(output) => return Ok(output),
+            Err(e) => e,
+        };
+        Err(coredump::capture_core_dump(error, &mut store, &self.plugin_dir, component_id).await)
+    }
+
+    /// Returns the argv and output pipes of the call if the tool runs a command component
+    async fn command_io(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+    ) -> Result<Option<CommandIo>> {
+        let is_command = self
+            .registry
+            .read()
+            .await
+            .get_tool(function_name)
+            .is_some_and(|tool| command::is_command(&tool.identifier));
+        if !is_command {
+            return Ok(None);
+        }
+        CommandIo::from_parameters(component_id, parameters).map(Some)
+    }
 
+    async fn invoke_tool(
+        &self,
+        mut store: &mut Store<WassetteWasiState<WasiState>>,
+        component: &ComponentInstance,
+        function_name: &str,
+        parameters: &str,
+        command: Option<&CommandIo>,
+    ) -> Result<String> {
         let instance = component
             .instance_pre
             .instantiate_async(&mut store)
@@ -497,13 +1906,27 @@ impl LifecycleManager {
             .context("Failed to instantiate component")?;
 
         // Use the new function identifier lookup instead of dot-splitting
-        let function_id = self
-            .registry
-            .read()
-            .await
-            .get_function_identifier(function_name)
-            .ok_or_else(|| anyhow!("Unknown tool name: {}", function_name))?
-            .clone();
+        let (function_id, input_schema, plan) = {
+            let registry = self.registry.read().await;
+            let tool_info = registry
+                .get_tool(function_name)
+                .ok_or_else(|| anyhow!("Unknown tool name: {}", function_name))?;
+            (
+                tool_info.identifier.clone(),
+                tool_info.schema.input_schema.clone(),
+                tool_info.plan.clone(),
+            )
+        };
+
+        let mut params = Value::Object(parse_tool_arguments(parameters.as_bytes())?);
+        // Previews go to the plan variant of the function, which takes the same parameters
+        let function_id = match (plan, params.as_object_mut()) {
+            (Some(plan), Some(arguments)) => match arguments.remove(PREVIEW_ARGUMENT) {
+                Some(Value::Bool(true)) => plan,
+                _ => function_id,
+            },
+            _ => function_id,
+        };
 
         let (interface_name, func_name) = (
             function_id.interface_name.as_deref().unwrap_or(""),
@@ -543,7 +1966,19 @@ impl LifecycleManager {
                 .ok_or_else(|| anyhow!("Function not found: {}", func_name))?
         };
 
-        let params: serde_json::Value = serde_json::from_str(parameters)?;
+        // Commands take their arguments from argv, which the call state already holds
+        if let Some(command) = command {
+            let mut results = create_placeholder_results(&func.results(&store));
+            let outcome = func
+                .call_async(&mut store, &[], &mut results)
+                .await
+                .map(|()| results);
+            return command.finish(outcome);
+        }
+
+        if let Some(arguments) = params.as_object_mut() {
+            apply_schema_defaults(&input_schema, arguments);
+        }
         let argument_vals = json_to_vals(&params, &func.params(&store))?;
 
         let mut results = create_placeholder_results(&func.results(&store));
@@ -563,45 +1998,180 @@ impl LifecycleManager {
     // Granular permission system methods
 }
 
-async fn load_component_from_entry(
+/// Abbreviates the deeply nested types of a tool's schemas, noting in its description where the
+/// full schema can be found
+fn compact_tool_schema(schema: &mut ToolSchema) {
+    let input = compact_schema(&schema.input_schema, COMPACT_SCHEMA_DEPTH);
+    let output = schema
+        .output_schema
+        .as_ref()
+        .and_then(|output| compact_schema(output, COMPACT_SCHEMA_DEPTH));
+    if input.is_none() && output.is_none() {
+        return;
+    }
+    if let Some(input) = input {
+        schema.input_schema = input;
+    }
+    if output.is_some() {
+        schema.output_schema = output;
+    }
+    let note = "Nested types are abbreviated, get-tool-schema returns the full schema.";
+    schema.description = Some(match schema.description.take() {
+        Some(description) => format!("{description} {note}"),
+        None => note.to_string(),
+    });
+}
+
+/// Logs how the tools of a replaced component changed, as a warning if callers may break
+pub(crate) fn log_schema_diff(component_id: &str, diff: &SchemaDiff) {
+    if diff.breaking {
+        let broken: Vec<&str> = diff
+            .removed
+            .iter()
+            .map(String::as_str)
+            .chain(
+                diff.changed
+                    .iter()
+                    .filter(|c| c.breaking)
+                    .map(|c| c.tool.as_str()),
+            )
+            .collect();
+        warn!(component_id, %diff, ?broken, "Replaced component with breaking tool changes");
+    } else if !diff.is_empty() {
+        info!(component_id, %diff, "Replaced component with changed tools");
+    }
+}
+
+/// A component file read from the plugin directory that hasn't been compiled yet
+struct ComponentSource {
+    name: String,
+    digest: String,
+    wasm_bytes: Vec<u8>,
+    world_name: Option<String>,
+    tool_metadata: Option<Value>,
+    /// Capabilities of a preview 1 module, see [`adapter::Adapted`]
+    preview1_capabilities: Option<Vec<RequiredCapability>>,
+}
+
+/// Creates the engine that components are compiled and run with
+fn create_engine(config: &LifecycleConfig) -> Result<Arc<Engine>> {
+    Ok(Arc::new(wasmtime::Engine::new(&engine_config(config))?))
+}
+
+/// Returns the settings of the engine, which components compiled ahead of time must share
+fn engine_config(config: &LifecycleConfig) -> wasmtime::Config {
+    let mut engine_config = wasmtime::Config::new();
+    engine_config.wasm_component_model(true);
+    engine_config.async_support(true);
+    // Drives the sampling of profiled calls
+    engine_config.epoch_interruption(true);
+    engine_config.coredump_on_trap(config.coredump_on_trap);
+    engine_config.cranelift_nan_canonicalization(config.determinism.canonicalize_nans);
+    engine_config.relaxed_simd_deterministic(config.determinism.deterministic_relaxed_simd);
+    engine_config.wasm_relaxed_simd(!config.determinism.forbid_relaxed_simd);
+    engine_config
+}
+
+/// Creates a linker providing every interface that components may import
+fn create_linker(engine: &Engine) -> Result<Linker<WassetteWasiState<WasiState>>> {
+    let mut linker = Linker::new(engine);
+    wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
+    timers::add_to_linker(&mut linker)?;
+    blobstore::add_to_linker(&mut linker)?;
+    publish::add_to_linker(&mut linker)?;
+
+    // Use the standard HTTP linker - filtering happens at WasiHttpView level
+    wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker)?;
+
+    wasmtime_wasi_config::add_to_linker(&mut linker, |h: &mut WassetteWasiState<WasiState>| {
+        WasiConfig::from(&h.inner.wasi_config_vars)
+    })?;
+
+    #[cfg(feature = "wasi-nn")]
+    wasmtime_wasi_nn::wit::add_to_linker(&mut linker, |h: &mut WassetteWasiState<WasiState>| {
+        wasmtime_wasi_nn::wit::WasiNnView::new(&mut h.inner.table, &mut h.inner.nn)
+    })?;
+    Ok(linker)
+}
+
+async fn read_component_source(id: String, path: &Path) -> Result<ComponentSource> {
+    let wasm_bytes = compression::read_component(path)
+        .await
+        .context("unable to read component file")?;
+    let digest = component_digest(&wasm_bytes);
+    let world_name = component_world_name(&wasm_bytes);
+    let tool_metadata = component_tool_metadata(&wasm_bytes);
+    let adapted = adapter::adapt_if_module(wasm_bytes)?;
+    Ok(ComponentSource {
+        name: id,
+        digest,
+        world_name,
+        tool_metadata,
+        wasm_bytes: adapted.wasm,
+        preview1_capabilities: adapted.preview1_capabilities,
+    })
+}
+
+/// Compiles a component read from the plugin directory, or loads its precompiled form from
+/// there. See [`precompile`].
+async fn compile_component(
     engine: Arc<Engine>,
     linker: &Linker<WassetteWasiState<WasiState>>,
-    entry: DirEntry,
-) -> Result<Option<(ComponentInstance, String)>> {
+    source: ComponentSource,
+    plugin_dir: &Path,
+    artifacts: PrecompiledArtifacts,
+    compilation_cache: &Arc<CacheCounters>,
+) -> Result<(ComponentInstance, String)> {
     let start_time = Instant::now();
-    let is_file = entry
-        .metadata()
-        .await
-        .map(|m| m.is_file())
-        .context("unable to read file metadata")?;
-    let is_wasm = entry
-        .path()
-        .extension()
-        .map(|ext| ext == "wasm")
-        .unwrap_or(false);
-    if !(is_file && is_wasm) {
-        return Ok(None);
-    }
-    let entry_path = entry.path();
-    let component =
-        tokio::task::spawn_blocking(move || Component::from_file(&engine, entry_path)).await??;
-    let name = entry
-        .path()
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .map(String::from)
-        .context("wasm file didn't have a valid file name")?;
+    let ComponentSource {
+        name,
+        digest,
+        wasm_bytes,
+        world_name,
+        tool_metadata,
+        preview1_capabilities,
+    } = source;
+    let component = tokio::task::spawn_blocking({
+        let engine = engine.clone();
+        let plugin_dir = plugin_dir.to_path_buf();
+        let digest = digest.clone();
+        let compilation_cache = compilation_cache.clone();
+        move || {
+            precompile::load_component(
+                &engine,
+                &plugin_dir,
+                &digest,
+                wasm_bytes,
+                artifacts,
+                &compilation_cache,
+            )
+        }
+    })
+    .await?
+    .with_context(|| format!("Failed to compile component {name}"))?;
     info!(component_id = %name, elapsed = ?start_time.elapsed(), "component loaded");
+    imports::check_imports(&component, linker)?;
+    let required_capabilities = preview1_capabilities
+        .unwrap_or_else(|| imports::required_capabilities(&component, &engine));
     let instance_pre = linker
         .instantiate_pre(&component)
         .context("failed to instantiate component")?;
-    Ok(Some((
+    Ok((
         ComponentInstance {
             component: Arc::new(component),
             instance_pre: Arc::new(instance_pre),
+            world_name,
+            tool_metadata,
+            digest,
+            required_capabilities,
         },
         name,
-    )))
+    ))
+}
+
+/// Returns the hex-encoded SHA-256 digest of a component binary
+fn component_digest(wasm_bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(wasm_bytes))
 }
 
 #[cfg(test)]
@@ -610,11 +2180,13 @@ mod tests {
     use std::path::PathBuf;
     use std::process::Command;
 
+    use policy::PolicyParser;
+    use serde_json::json;
     use test_log::test;
 
     use super::*;
 
-    pub(crate) const TEST_COMPONENT_ID: &str = "fetch_rs";
+    pub(crate) const TEST_COMPONENT_ID: &str = "local/fetch_rs";
 
     /// Helper struct for keeping a reference to the temporary directory used for testing the
     /// lifecycle manager
@@ -705,6 +2277,80 @@ mod tests {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_capabilities_narrow_legacy_sandbox_defaults() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.config.write().await.legacy_sandbox_defaults = true;
+
+        let (template, _) = manager
+            .call_policy_template("unknown", &CallState::default())
+            .await?;
+        assert!(template.allow_args);
+        assert!(template.allow_wall_clock);
+
+        let call = CallState {
+            capabilities: Some(serde_json::from_value(json!({
+                "process": {"wall_clock": true}
+            }))?),
+            ..Default::default()
+        };
+        let (template, _) = manager.call_policy_template("unknown", &call).await?;
+        assert!(!template.allow_args);
+        assert!(template.allow_wall_clock);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_capabilities_cant_narrow_to_closed_storage() -> Result<()> {
+        use chrono::Datelike;
+
+        let manager = create_test_manager().await?;
+        std::fs::create_dir_all(manager.plugin_dir.join("reports/2024"))?;
+        // A window that only opens two days from now
+        let day = chrono::Utc::now().weekday().succ().succ();
+        let policy = PolicyParser::parse_str(format!(
+            r#"
+version: "1.4"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://reports"
+        access: ["read"]
+  schedule:
+    windows:
+      - days: ["{}"]
+        start: "00:00"
+        end: "01:00"
+        storage: ["fs://reports"]
+"#,
+            day.to_string().to_lowercase()
+        ))?;
+        let template = create_wasi_state_template_from_policy(&policy, &manager.plugin_dir)?;
+        manager
+            .policy_registry
+            .write()
+            .await
+            .insert(TEST_COMPONENT_ID.to_string(), template);
+
+        let (template, deadline) = manager
+            .call_policy_template(TEST_COMPONENT_ID, &CallState::default())
+            .await?;
+        assert!(template.preopened_dirs.is_empty());
+        assert_eq!(deadline, None);
+
+        let call = CallState {
+            capabilities: Some(serde_json::from_value(json!({
+                "storage": {"allow": [{"uri": "fs://reports/2024", "access": ["read"]}]}
+            }))?),
+            ..Default::default()
+        };
+        let (template, _) = manager
+            .call_policy_template(TEST_COMPONENT_ID, &call)
+            .await?;
+        assert!(template.preopened_dirs.is_empty());
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_load_and_unload_component() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -717,11 +2363,214 @@ mod tests {
         let loaded_components = manager.list_components().await;
         assert_eq!(loaded_components.len(), 1);
 
-        manager.unload_component(TEST_COMPONENT_ID).await?;
+        assert_eq!(
+            manager.unload_component(TEST_COMPONENT_ID).await?,
+            UnloadResult::Removed
+        );
 
         let loaded_components = manager.list_components().await;
         assert!(loaded_components.is_empty());
 
+        // Unloading again changes nothing
+        assert_eq!(
+            manager.unload_component(TEST_COMPONENT_ID).await?,
+            UnloadResult::NotFound
+        );
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_identical_components_share_content() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let copy_dir = tempfile::tempdir()?;
+        let copy = copy_dir.path().join("fetch_copy.wasm");
+        tokio::fs::copy(build_example_component().await?, &copy).await?;
+        let (copy_id, _) = manager
+            .load_component(&format!("file://{}", copy.display()))
+            .await?;
+
+        let metadata = ComponentMetadata::read(&component_id::metadata_path(
+            &manager.plugin_dir,
+            TEST_COMPONENT_ID,
+        ))
+        .await?
+        .unwrap();
+        let digest = metadata.digest.unwrap();
+        let entry = content::content_path(&manager.plugin_dir, &digest);
+        assert!(entry.exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(tokio::fs::metadata(&entry).await?.nlink(), 3);
+        }
+
+        // Reinstalling one of them with other contents leaves the other one and the entry as
+        // they were. A trailing custom section changes the bytes but not the component.
+        let original = tokio::fs::read(&entry).await?;
+        let mut changed = original.clone();
+        changed.extend([0, 5, 4, b'n', b'o', b't', b'e']);
+        tokio::fs::write(&copy, &changed).await?;
+        manager
+            .load_component(&format!("file://{}", copy.display()))
+            .await?;
+        assert_eq!(
+            tokio::fs::read(manager.component_path(&copy_id)).await?,
+            changed
+        );
+        assert_eq!(
+            tokio::fs::read(manager.component_path(TEST_COMPONENT_ID)).await?,
+            original
+        );
+        assert_eq!(tokio::fs::read(&entry).await?, original);
+
+        // The entry goes with the last component using it
+        manager.unload_component(TEST_COMPONENT_ID).await?;
+        assert!(!entry.exists());
+        manager.unload_component(&copy_id).await?;
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_unload_component_keeping_policy() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let policy_path = manager.plugin_dir.join("test-policy.yaml");
+        tokio::fs::write(&policy_path, "version: \"1.0\"\npermissions: {}\n").await?;
+        manager
+            .attach_policy(
+                TEST_COMPONENT_ID,
+                &format!("file://{}", policy_path.display()),
+            )
+            .await?;
+        async fn has_policy(manager: &LifecycleManager) -> bool {
+            manager
+                .policy_registry
+                .read()
+                .await
+                .component_policies
+                .contains_key(TEST_COMPONENT_ID)
+        }
+
+        manager
+            .unload_component_with_options(
+                TEST_COMPONENT_ID,
+                &UnloadOptions {
+                    keep_policy: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        assert!(!manager.component_path(TEST_COMPONENT_ID).exists());
+        assert!(!component_id::metadata_path(&manager.plugin_dir, TEST_COMPONENT_ID).exists());
+        assert!(manager
+            .get_component_policy_path(TEST_COMPONENT_ID)
+            .exists());
+        assert!(!has_policy(&manager).await);
+
+        // The kept policy applies again once the component is loaded again
+        manager.load_test_component().await?;
+        assert!(has_policy(&manager).await);
+
+        manager.unload_component(TEST_COMPONENT_ID).await?;
+        assert!(!manager
+            .get_component_policy_path(TEST_COMPONENT_ID)
+            .exists());
+        assert!(!manager
+            .get_component_metadata_path(TEST_COMPONENT_ID)
+            .exists());
+        assert!(manager
+            .get_tool_stats(Some(TEST_COMPONENT_ID))
+            .await
+            .components
+            .is_empty());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_unload_component_removes_blobs() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let blobs = manager.plugin_dir.join(BLOBS_DIR);
+        let object = blobs.join(format!("objects/{TEST_COMPONENT_ID}/artifacts/app.tar"));
+        let store_blob = || async {
+            let marker = blobs.join(format!("containers/{TEST_COMPONENT_ID}/artifacts"));
+            tokio::fs::create_dir_all(marker.parent().unwrap()).await?;
+            tokio::fs::write(&marker, b"").await?;
+            tokio::fs::create_dir_all(object.parent().unwrap()).await?;
+            tokio::fs::write(&object, b"tar").await
+        };
+
+        manager.load_test_component().await?;
+        store_blob().await?;
+        manager
+            .unload_component_with_options(
+                TEST_COMPONENT_ID,
+                &UnloadOptions {
+                    keep_data: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        assert!(object.exists());
+
+        manager.load_test_component().await?;
+        manager.unload_component(TEST_COMPONENT_ID).await?;
+        assert!(!object.exists());
+        assert!(!blobs
+            .join(format!("containers/{TEST_COMPONENT_ID}/artifacts"))
+            .exists());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_unload_component_removes_diagnostics() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let files = [
+            replay::trace_path(&manager.plugin_dir, TEST_COMPONENT_ID, "fetch"),
+            profiling::profile_path(&manager.plugin_dir, TEST_COMPONENT_ID, "fetch"),
+            manager
+                .plugin_dir
+                .join(DIAGNOSTICS_DIR)
+                .join(TEST_COMPONENT_ID)
+                .join("1.coredump"),
+        ];
+        for file in &files {
+            tokio::fs::create_dir_all(file.parent().unwrap()).await?;
+            tokio::fs::write(file, b"{}").await?;
+        }
+        let other = replay::trace_path(&manager.plugin_dir, "local/other", "fetch");
+        tokio::fs::create_dir_all(other.parent().unwrap()).await?;
+        tokio::fs::write(&other, b"{}").await?;
+
+        manager.unload_component(TEST_COMPONENT_ID).await?;
+        for file in &files {
+            assert!(!file.exists(), "{} was kept", file.display());
+        }
+        assert!(other.exists());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_load_components_reports_each_uri() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let component_path = build_example_component().await?;
+        let uris = vec![
+            format!("file://{}", component_path.display()),
+            "file:///path/to/nonexistent.wasm".to_string(),
+        ];
+
+        let results = manager.load_components(&uris, 2).await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, uris[0]);
+        assert_eq!(
+            results[0].1.as_ref().map(|(id, _)| id.as_str()).ok(),
+            Some(TEST_COMPONENT_ID)
+        );
+        assert_eq!(results[1].0, uris[1]);
+        assert!(results[1].1.is_err());
+        assert_eq!(manager.list_components().await, vec![TEST_COMPONENT_ID]);
         Ok(())
     }
 
@@ -785,8 +2634,8 @@ mod tests {
     async fn test_component_path_update() -> Result<()> {
         let manager = create_test_manager().await?;
 
-        let component_id = "test-component";
-        let expected_path = manager.plugin_dir.join("test-component.wasm");
+        let component_id = "local/test-component";
+        let expected_path = manager.plugin_dir.join("local").join("test-component.wasm");
         let actual_path = manager.component_path(component_id);
 
         assert_eq!(actual_path, expected_path);
@@ -817,9 +2666,130 @@ permissions:
 
         // Test getting WASI state for component with attached policy
         let _wasi_state = manager
-            .get_wasi_state_for_component(TEST_COMPONENT_ID)
+            .get_wasi_state_for_component(TEST_COMPONENT_ID, &CallState::default())
+            .await?;
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_capability_gaps_follow_policy() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        // The fetch component sends HTTP requests, which its missing policy doesn't allow
+        let gaps = manager
+            .get_capability_gaps(TEST_COMPONENT_ID)
+            .await
+            .unwrap();
+        assert!(gaps.iter().any(|gap| gap.capability == Capability::Network));
+
+        let policy_path = manager.plugin_dir.join("test-policy.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.0\"\npermissions:\n  network:\n    allow:\n      - host: \"example.com\"\n",
+        )
+        .await?;
+        manager
+            .attach_policy(
+                TEST_COMPONENT_ID,
+                &format!("file://{}", policy_path.display()),
+            )
+            .await?;
+        let gaps = manager
+            .get_capability_gaps(TEST_COMPONENT_ID)
+            .await
+            .unwrap();
+        assert!(gaps.iter().all(|gap| gap.capability != Capability::Network));
+
+        assert!(manager.get_capability_gaps("missing").await.is_none());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_resolve_component_id() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        assert_eq!(
+            manager.resolve_component_id("fetch_rs").await?,
+            TEST_COMPONENT_ID
+        );
+        assert_eq!(
+            manager.resolve_component_id(TEST_COMPONENT_ID).await?,
+            TEST_COMPONENT_ID
+        );
+        assert_eq!(manager.resolve_component_id("missing").await?, "missing");
+
+        // The same component loaded from another source gets its own ID, which makes the name
+        // ambiguous
+        let other = manager.plugin_dir.join("other").join("fetch_rs.wasm");
+        tokio::fs::create_dir_all(other.parent().unwrap()).await?;
+        tokio::fs::copy(manager.component_path(TEST_COMPONENT_ID), &other).await?;
+        manager.reload(LifecycleConfig::default()).await;
+        assert_eq!(manager.list_components().await.len(), 2);
+        let error = manager.resolve_component_id("fetch_rs").await.unwrap_err();
+        assert!(error.to_string().contains("ambiguous"));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_replacing_component_reports_schema_diff() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let uri = format!("file://{}", build_example_component().await?.display());
+        let (_, result) = manager.load_component(&uri).await?;
+        assert_eq!(result, LoadResult::New);
+
+        let (_, result) = manager.load_component(&uri).await?;
+        let LoadResult::Replaced(diff) = result else {
+            panic!("expected the component to be replaced");
+        };
+        assert!(diff.is_empty());
+        assert!(!diff.breaking);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_component_labels() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        assert_eq!(
+            manager.get_component_labels(TEST_COMPONENT_ID).await,
+            Some(Labels::new())
+        );
+
+        let set = Labels::from([("team".to_string(), "infra".to_string())]);
+        manager
+            .set_component_labels(TEST_COMPONENT_ID, &set, &[])
             .await?;
+        let infra: LabelSelector = "team=infra".parse()?;
+        let data: LabelSelector = "team=data".parse()?;
+        assert_eq!(
+            manager.list_components_matching(&infra).await,
+            vec![TEST_COMPONENT_ID.to_string()]
+        );
+        assert!(manager.list_components_matching(&data).await.is_empty());
+
+        // Labels are kept when the component is loaded again
+        manager.load_test_component().await?;
+        assert_eq!(
+            manager.get_component_labels(TEST_COMPONENT_ID).await,
+            Some(set)
+        );
+
+        // Only the tools of selected components are listed, but all can be called
+        let config = LifecycleConfig {
+            tool_selector: Some(data),
+            ..Default::default()
+        };
+        manager.reload(config).await;
+        assert!(manager.list_visible_tools().await.is_empty());
+        assert!(!manager.list_tools().await.is_empty());
 
+        assert!(manager
+            .set_component_labels("local/missing", &Labels::new(), &[])
+            .await
+            .is_err());
         Ok(())
     }
 
@@ -851,12 +2821,23 @@ permissions:
         let policy_path = tempdir.path().join("test-component.policy.yaml");
         std::fs::write(&policy_path, policy_content)?;
 
-        // Create a new LifecycleManager to test policy restoration
+        // Create a new LifecycleManager to test policy restoration. The component was stored
+        // under a flat ID, so it is migrated into the local namespace first.
         let manager = LifecycleManager::new(&tempdir).await?;
+        assert_eq!(
+            manager.list_components().await,
+            vec!["local/test-component".to_string()]
+        );
 
         // Check if policy was restored
-        let policy_info = manager.get_policy_info("test-component").await;
+        let policy_info = manager.get_policy_info("local/test-component").await;
         assert!(policy_info.is_some());
+        assert!(manager
+            .policy_registry
+            .read()
+            .await
+            .component_policies
+            .contains_key("local/test-component"));
 
         Ok(())
     }
@@ -947,4 +2928,173 @@ permissions:
 
         Ok(())
     }
+
+    fn tool_metadata(interface: &str, function: &str) -> ToolMetadata {
+        let identifier = FunctionIdentifier {
+            package_name: None,
+            interface_name: Some(interface.to_string()),
+            function_name: function.to_string(),
+        };
+        let normalized_name = component2json::normalize_tool_name(&identifier);
+        ToolMetadata {
+            schema: ToolSchema {
+                name: normalized_name.clone(),
+                description: None,
+                input_schema: json!({ "type": "object" }),
+                output_schema: None,
+                annotations: None,
+            },
+            identifier,
+            normalized_name,
+            deprecation: None,
+            plan: None,
+            memoization: None,
+        }
+    }
+
+    #[test]
+    fn test_registry_resolves_qualified_names() -> Result<()> {
+        let mut registry = ComponentRegistry::new(Arc::default(), false);
+        registry.register_tools("cli", vec![tool_metadata("wasi:cli/run@0.2.0", "run")])?;
+
+        assert_eq!(
+            registry.resolve_tool_name("wasi_cli_run_0_2_0_run"),
+            Some("wasi_cli_run_0_2_0_run")
+        );
+        assert_eq!(
+            registry.resolve_tool_name("wasi:cli/run@0.2.0.run"),
+            Some("wasi_cli_run_0_2_0_run")
+        );
+        assert_eq!(
+            registry
+                .get_tool("wasi:cli/run@0.2.0.run")
+                .map(|tool| tool.identifier.function_name.as_str()),
+            Some("run")
+        );
+
+        registry.unregister_component("cli");
+        assert!(registry
+            .resolve_tool_name("wasi:cli/run@0.2.0.run")
+            .is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_lists_tools_in_stable_order() -> Result<()> {
+        let mut registry = ComponentRegistry::new(Arc::default(), false);
+        registry.register_tools(
+            "zeta",
+            vec![
+                tool_metadata("foo:bar/baz", "run"),
+                tool_metadata("a:b/c", "go"),
+            ],
+        )?;
+        registry.register_tools("alpha", vec![tool_metadata("foo:bar/baz", "run")])?;
+
+        let names: Vec<_> = registry
+            .tool_map
+            .values()
+            .flatten()
+            .map(|t| t.schema.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["a_b_c_go", "foo_bar_baz_run", "foo_bar_baz_run"]
+        );
+        let owners: Vec<_> = registry
+            .get_tool_info("foo_bar_baz_run")
+            .unwrap()
+            .iter()
+            .map(|t| t.component_id.as_str())
+            .collect();
+        assert_eq!(owners, vec!["alpha", "zeta"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_dedups_across_components() -> Result<()> {
+        let mut registry = ComponentRegistry::new(Arc::default(), true);
+        registry.register_tools("first", vec![tool_metadata("foo:bar/baz", "run")])?;
+        registry.register_tools("second", vec![tool_metadata("foo:bar/baz", "run")])?;
+
+        assert_eq!(registry.get_tool_info("foo_bar_baz_run").unwrap().len(), 1);
+        let renamed = registry.get_tool_info("foo_bar_baz_run-2").unwrap();
+        assert_eq!(renamed[0].component_id, "second");
+        assert_eq!(renamed[0].schema.name, "foo_bar_baz_run-2");
+
+        // The qualified name now maps to two different tools, so it is ambiguous
+        assert!(registry.resolve_tool_name("foo:bar/baz.run").is_none());
+
+        // Without dedup both components share the name
+        let mut registry = ComponentRegistry::new(Arc::default(), false);
+        registry.register_tools("first", vec![tool_metadata("foo:bar/baz", "run")])?;
+        registry.register_tools("second", vec![tool_metadata("foo:bar/baz", "run")])?;
+        assert_eq!(registry.get_tool_info("foo_bar_baz_run").unwrap().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_updates_only_changed_tools() -> Result<()> {
+        let mut registry = ComponentRegistry::new(Arc::default(), true);
+        registry.register_tools("first", vec![tool_metadata("foo:bar/baz", "run")])?;
+        registry.register_tools(
+            "second",
+            vec![
+                tool_metadata("foo:bar/baz", "run"),
+                tool_metadata("a:b/c", "go"),
+            ],
+        )?;
+        registry.unregister_component("first");
+
+        let mut changed = tool_metadata("foo:bar/baz", "run");
+        changed.schema.description = Some("Runs".to_string());
+        registry.update_tools("second", vec![changed, tool_metadata("x:y/z", "stop")])?;
+
+        // The renamed tool keeps its name although the name it collided with is free now
+        assert!(registry.get_tool_info("foo_bar_baz_run").is_none());
+        let kept = &registry.get_tool_info("foo_bar_baz_run-2").unwrap()[0];
+        assert_eq!(kept.schema.name, "foo_bar_baz_run-2");
+        assert_eq!(kept.schema.description.as_deref(), Some("Runs"));
+        assert_eq!(
+            registry.resolve_tool_name("foo:bar/baz.run"),
+            Some("foo_bar_baz_run-2")
+        );
+
+        assert!(registry.get_tool_info("a_b_c_go").is_none());
+        assert!(registry.resolve_tool_name("a:b/c.go").is_none());
+        assert!(registry.get_tool_info("x_y_z_stop").is_some());
+        assert_eq!(
+            registry
+                .component_schemas("second")
+                .iter()
+                .map(|schema| schema.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["foo_bar_baz_run-2", "x_y_z_stop"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_forbid_relaxed_simd() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"(component
+                (core module
+                    (func (param v128 v128) (result v128)
+                        local.get 0
+                        local.get 1
+                        f32x4.relaxed_min))
+            )"#,
+        )?;
+        Component::new(&*create_engine(&LifecycleConfig::default())?, &wasm)?;
+
+        let config = LifecycleConfig {
+            determinism: DeterminismConfig {
+                forbid_relaxed_simd: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(Component::new(&*create_engine(&config)?, &wasm).is_err());
+        Ok(())
+    }
 }
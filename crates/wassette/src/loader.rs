@@ -5,11 +5,21 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
-use futures::TryStreamExt;
+use oci_client::secrets::RegistryAuth;
 use tokio::fs::metadata;
 use tokio::io::AsyncWriteExt;
 use tracing::{debug, warn};
 
+use crate::component_id::{component_name, oci_component_id};
+use crate::compression;
+use crate::content;
+use crate::delta::{pull_verified_blob, pull_with_delta};
+use crate::git::{self, GitSource, GIT_SCHEME};
+use crate::http_cache::HttpDownload;
+use crate::oci_manifest;
+use crate::sources::{check_scheme_allowed, SourceRequest};
+use crate::LifecycleManager;
+
 /// Represents a downloaded resource, either from a local file or a temporary one.
 pub enum DownloadedResource {
     Local(PathBuf),
@@ -43,53 +53,38 @@ impl DownloadedResource {
         Ok((DownloadedResource::Temp((tempdir, file_path)), temp_file))
     }
 
-    pub fn id(&self) -> Result<String> {
+    /// Returns the name of the resource, taken from its file name. The name of a component is
+    /// the last segment of its ID.
+    pub fn name(&self) -> Result<String> {
         // NOTE(thomastaylor312): Unfortunately the rust tooling (and I think some of the others),
         // doesn't preserve the package ID from the wit world defined for the component. It just
         // ends up as "root-component". So for now we rely on the file name to give us a unique ID
         // for the component.
-        // let decoded = wit_parser::decoding::decode(&wasm_bytes)
-        //     .map_err(|e| anyhow::anyhow!("Failed to decode component from path: {}. Error: {}. Please ensure the file is a valid WebAssembly component.", file.as_ref().display(), e))?;
-
-        // let pkg_id = decoded.package();
-        // // SAFETY: The package ID is guaranteed to be valid because we just decoded it
-        // let pkg = decoded.resolve().packages.get(pkg_id).unwrap();
-        // // Format the package name without the colon so it is valid on all systems. We are using the
-        // // package name as a unique key on the filesystem as well
-        // let id = format!("{}-{}", pkg.name.namespace, pkg.name.name);
-
-        // Load the component to see if it is valid
-        let maybe_id = match self {
+        let maybe_name = match self {
             DownloadedResource::Local(path) => path.file_stem().and_then(|s| s.to_str()),
             DownloadedResource::Temp((_, path)) => path.file_stem().and_then(|s| s.to_str()),
         };
 
-        maybe_id
+        maybe_name
             .map(|s| s.to_string())
-            .ok_or_else(|| anyhow::anyhow!("Failed to extract resource ID from path"))
+            .ok_or_else(|| anyhow::anyhow!("Failed to extract resource name from path"))
     }
 
+    /// Moves or copies the resource to the file `dest`, creating its parent directory. An
+    /// existing `dest` is replaced rather than written to, as it may be linked to other
+    /// components through the content store.
     pub async fn copy_to(self, dest: impl AsRef<Path>) -> Result<()> {
-        let meta = tokio::fs::metadata(&dest).await?;
-        if !meta.is_dir() {
-            bail!(
-                "Destination path must be a directory: {}",
-                dest.as_ref().display()
-            );
+        let dest = dest.as_ref();
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
         }
         match self {
             DownloadedResource::Local(path) => {
-                let dest = dest.as_ref().join(
-                    path.file_name()
-                        .context("Path to copy is missing filename")?,
-                );
-                tokio::fs::copy(path, dest).await?;
+                let dest = dest.to_path_buf();
+                tokio::task::spawn_blocking(move || content::replace_with_copy(&path, &dest))
+                    .await??;
             }
             DownloadedResource::Temp((tempdir, file)) => {
-                let dest = dest.as_ref().join(
-                    file.file_name()
-                        .context("Path to copy is missing filename")?,
-                );
                 match tokio::fs::rename(&file, &dest).await {
                     Ok(()) => {}
                     Err(e) if e.raw_os_error() == Some(18) => {
@@ -100,7 +95,13 @@ impl DownloadedResource {
                             to = %dest.display(),
                             "Cross-device rename detected; falling back to copy"
                         );
-                        tokio::fs::copy(&file, &dest).await.with_context(|| {
+                        tokio::task::spawn_blocking({
+                            let file = file.clone();
+                            let dest = dest.to_path_buf();
+                            move || content::replace_with_copy(&file, &dest)
+                        })
+                        .await?
+                        .with_context(|| {
                             format!(
                                 "Failed to copy component from {} to {} during EXDEV fallback",
                                 file.display(),
@@ -133,11 +134,16 @@ pub trait Loadable: Sized {
     const RESOURCE_TYPE: &'static str;
 
     async fn from_local_file(path: &Path) -> Result<DownloadedResource>;
+    /// Pulls the resource. `plugin_dir` holds the installed version of the resource, if any,
+    /// which allows a delta update
     async fn from_oci_reference(
         reference: &str,
         oci_client: &oci_client::Client,
+        auth: &RegistryAuth,
+        plugin_dir: &Path,
     ) -> Result<DownloadedResource>;
-    async fn from_url(url: &str, http_client: &reqwest::Client) -> Result<DownloadedResource>;
+    /// Turns a body downloaded over HTTPS into the resource
+    async fn from_http_download(download: &HttpDownload) -> Result<DownloadedResource>;
 }
 
 /// Loadable implementation for WebAssembly components
@@ -156,32 +162,48 @@ impl Loadable for ComponentResource {
             bail!("Component path does not exist: {}. Please provide a valid path to a WebAssembly component file.", path.display());
         }
 
-        if path.extension().unwrap_or_default() != Self::FILE_EXTENSION {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let Some(name) = compression::strip_compression_extension(file_name)
+            .strip_suffix(&format!(".{}", Self::FILE_EXTENSION))
+        else {
             bail!(
-                "Invalid file extension for component: {}. Component file must have .{} extension.",
+                "Invalid file extension for component: {}. Component file must have .{} extension, optionally followed by .zst or .gz.",
                 path.display(),
                 Self::FILE_EXTENSION
             );
-        }
+        };
 
-        Ok(DownloadedResource::Local(path.to_path_buf()))
+        match compression::decompress_file(path, name).await? {
+            Some(decompressed) => Ok(decompressed),
+            None => Ok(DownloadedResource::Local(path.to_path_buf())),
+        }
     }
 
     async fn from_oci_reference(
         reference: &str,
         oci_client: &oci_client::Client,
+        auth: &RegistryAuth,
+        plugin_dir: &Path,
     ) -> Result<DownloadedResource> {
         let reference: oci_client::Reference =
             reference.parse().context("Failed to parse OCI reference")?;
-        let data = oci_wasm::WasmClient::from(oci_client.clone())
-            .pull(&reference, &oci_client::secrets::RegistryAuth::Anonymous)
-            .await?;
-        let (downloaded_resource, mut file) = DownloadedResource::new_temp_file(
-            reference.repository().replace('/', "_"),
-            Self::FILE_EXTENSION,
-        )
-        .await?;
-        file.write_all(&data.layers[0].data).await?;
+        let id = oci_component_id(&reference);
+        let installed = plugin_dir.join(format!("{id}.{}", Self::FILE_EXTENSION));
+        let name = component_name(&id).to_string();
+        let data = match pull_with_delta(oci_client, &reference, auth, &installed).await {
+            Ok(Some(data)) => data,
+            Ok(None) => Self::pull_full(oci_client, &reference, auth).await?,
+            Err(e) => {
+                warn!(reference = %reference, error = %e, "Delta update failed, pulling the full component");
+                Self::pull_full(oci_client, &reference, auth).await?
+            }
+        };
+        let (downloaded_resource, mut file) =
+            DownloadedResource::new_temp_file(name, Self::FILE_EXTENSION).await?;
+        file.write_all(&data).await?;
 
         file.flush().await?;
         file.sync_all().await?;
@@ -189,38 +211,45 @@ impl Loadable for ComponentResource {
         Ok(downloaded_resource)
     }
 
-    async fn from_url(url: &str, http_client: &reqwest::Client) -> Result<DownloadedResource> {
-        let resp = http_client.get(url).send().await?;
-        let status = resp.status();
-        if !status.is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            bail!(
-                "Failed to download component from URL: {}. Status code: {}\nBody: {}",
-                url,
-                status,
-                body
-            );
+    async fn from_http_download(download: &HttpDownload) -> Result<DownloadedResource> {
+        let name = compression::strip_compression_extension(
+            download
+                .url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .context("Failed to discover name from URL")?,
+        )
+        .trim_end_matches(&format!(".{}", Self::FILE_EXTENSION));
+        if let Some(decompressed) = compression::decompress_file(&download.body, name).await? {
+            return Ok(decompressed);
         }
-        let name = resp
-            .url()
-            .path_segments()
-            .and_then(|mut segments| segments.next_back())
-            .context("Failed to discover name from URL")?
-            .trim_end_matches(&format!(".{}", Self::FILE_EXTENSION));
-        let (downloaded_resource, mut file) =
+        let (downloaded_resource, file) =
             DownloadedResource::new_temp_file(name, Self::FILE_EXTENSION).await?;
-        let stream = resp.bytes_stream();
-        let mut reader = tokio_util::io::StreamReader::new(stream.map_err(std::io::Error::other));
-        tokio::io::copy(&mut reader, &mut file)
+        drop(file);
+        tokio::fs::copy(&download.body, downloaded_resource.as_ref())
             .await
             .context("Failed to write downloaded component to temp file")?;
-        file.flush().await?;
-        file.sync_all().await?;
-        drop(file);
         Ok(downloaded_resource)
     }
 }
 
+impl ComponentResource {
+    /// Pulls the component layer of an image, skipping other layers such as a prefetchable
+    /// tool schema. An image index is resolved to its wasm entry, and a compressed component
+    /// layer is decompressed.
+    async fn pull_full(
+        oci_client: &oci_client::Client,
+        reference: &oci_client::Reference,
+        auth: &RegistryAuth,
+    ) -> Result<Vec<u8>> {
+        let (manifest, resolved) =
+            oci_manifest::pull_component_manifest(oci_client, reference, auth).await?;
+        let layer = oci_manifest::component_layer(&manifest)?;
+        let data = pull_verified_blob(oci_client, &resolved, layer).await?;
+        tokio::task::spawn_blocking(move || compression::decompress(data)).await?
+    }
+}
+
 /// Loadable implementation for policies
 pub struct PolicyResource;
 
@@ -244,13 +273,15 @@ impl Loadable for PolicyResource {
     async fn from_oci_reference(
         _reference: &str,
         _oci_client: &oci_client::Client,
+        _auth: &RegistryAuth,
+        _plugin_dir: &Path,
     ) -> Result<DownloadedResource> {
         bail!("OCI references are not supported for policy resources. Use 'file://' or 'https://' schemes instead.")
     }
 
-    async fn from_url(url: &str, http_client: &reqwest::Client) -> Result<DownloadedResource> {
-        let url_obj = reqwest::Url::parse(url)?;
-        let filename = url_obj
+    async fn from_http_download(download: &HttpDownload) -> Result<DownloadedResource> {
+        let filename = download
+            .url
             .path_segments()
             .and_then(|mut segments| segments.next_back())
             .unwrap_or("policy")
@@ -258,46 +289,104 @@ impl Loadable for PolicyResource {
             .trim_end_matches(".yml");
 
         let temp_file_name = format!("policy-{filename}");
-        let (downloaded_resource, mut temp_file) =
+        let (downloaded_resource, temp_file) =
             DownloadedResource::new_temp_file(&temp_file_name, Self::FILE_EXTENSION).await?;
-
-        let response = http_client.get(url).send().await?;
-        if !response.status().is_success() {
-            bail!(
-                "Failed to download policy from {}: {}",
-                url,
-                response.status()
-            );
-        }
-
-        let policy_bytes = response.bytes().await?;
-        tokio::io::copy(&mut policy_bytes.as_ref(), &mut temp_file).await?;
-
-        temp_file.flush().await?;
-        temp_file.sync_all().await?;
         drop(temp_file);
+        tokio::fs::copy(&download.body, downloaded_resource.as_ref()).await?;
 
         Ok(downloaded_resource)
     }
 }
 
-/// Generic resource loading function
-pub(crate) async fn load_resource<T: Loadable>(
-    uri: &str,
-    oci_client: &oci_wasm::WasmClient,
-    http_client: &reqwest::Client,
-) -> Result<DownloadedResource> {
-    let uri = uri.trim();
-    let error_message = format!(
-        "Invalid {} reference. Should be of the form scheme://reference",
-        T::RESOURCE_TYPE
-    );
-    let (scheme, reference) = uri.split_once("://").context(error_message)?;
-
-    match scheme {
-        "file" => T::from_local_file(Path::new(reference)).await,
-        "oci" => T::from_oci_reference(reference, oci_client).await,
-        "https" => T::from_url(uri, http_client).await,
-        _ => bail!("Unsupported {} scheme: {}", T::RESOURCE_TYPE, scheme),
+impl LifecycleManager {
+    /// Generic resource loading function. Mirrors of the URI are tried first, and stored
+    /// credentials are used for the registry or host the resource is downloaded from.
+    pub(crate) async fn load_resource<T: Loadable>(&self, uri: &str) -> Result<DownloadedResource> {
+        let candidates = self.mirrors.candidates(uri.trim());
+        let mut last_error = None;
+        for candidate in &candidates {
+            match self.load_resource_from::<T>(&candidate.uri).await {
+                Ok(resource) => {
+                    self.mirrors.record(candidate, true);
+                    return Ok(resource);
+                }
+                Err(e) => {
+                    if candidates.len() > 1 {
+                        warn!(uri = %candidate.uri, error = %e, "Failed to load {} from source", T::RESOURCE_TYPE);
+                    }
+                    self.mirrors.record(candidate, false);
+                    last_error = Some(e);
+                }
+            }
+        }
+        let error = last_error.context("No source to load from")?;
+        if candidates.len() > 1 {
+            return Err(error.context(format!(
+                "Failed to load {} from any of {} sources",
+                T::RESOURCE_TYPE,
+                candidates.len()
+            )));
+        }
+        Err(error)
+    }
+
+    async fn load_resource_from<T: Loadable>(&self, uri: &str) -> Result<DownloadedResource> {
+        let uri = uri.trim();
+        let error_message = format!(
+            "Invalid {} reference. Should be of the form scheme://reference",
+            T::RESOURCE_TYPE
+        );
+        let (scheme, reference) = uri.split_once("://").context(error_message)?;
+        check_scheme_allowed(self.config.read().await.allowed_schemes.as_deref(), scheme)?;
+
+        match scheme {
+            "file" => T::from_local_file(Path::new(reference)).await,
+            "oci" => {
+                self.with_registry_auth(reference, |auth| async move {
+                    T::from_oci_reference(reference, &self.oci_client, &auth, &self.plugin_dir)
+                        .await
+                })
+                .await
+            }
+            GIT_SCHEME => {
+                let source = GitSource::parse(uri)?;
+                let auth = self.credentials.http_auth(&source.repository).await;
+                let path = git::fetch(&source, auth.as_ref(), &self.plugin_dir).await?;
+                T::from_local_file(&path).await
+            }
+            "https" => {
+                let auth = self.credentials.http_auth(uri).await;
+                let download = self
+                    .http_cache
+                    .fetch(&self.http_client, uri, auth.as_ref(), T::RESOURCE_TYPE)
+                    .await?;
+                T::from_http_download(&download).await
+            }
+            _ => {
+                let loader = self.sources.read().await.get(scheme).with_context(|| {
+                    format!("Unsupported {} scheme: {}", T::RESOURCE_TYPE, scheme)
+                })?;
+                let dir = tokio::task::spawn_blocking(tempfile::tempdir).await??;
+                let path = loader
+                    .fetch(SourceRequest {
+                        uri,
+                        resource_type: T::RESOURCE_TYPE,
+                        extension: T::FILE_EXTENSION,
+                        dir: dir.path(),
+                    })
+                    .await
+                    .with_context(|| format!("Failed to fetch {uri}"))?;
+                if !path.starts_with(dir.path()) {
+                    bail!("The {scheme} loader returned a file outside of its directory");
+                }
+                // The fetched file is only kept if it is loaded as it is, not decompressed
+                match T::from_local_file(&path).await? {
+                    DownloadedResource::Local(local) if local == path => {
+                        Ok(DownloadedResource::Temp((dir, path)))
+                    }
+                    resource => Ok(resource),
+                }
+            }
+        }
     }
 }
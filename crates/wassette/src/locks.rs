@@ -0,0 +1,286 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Diagnostics of the locks of the lifecycle manager, which are taken in the order of
+//! [`LockName`]. Without the `lock-diagnostics` feature, [`TrackedRwLock`] is tokio's `RwLock`
+//! and costs nothing.
+
+/// The tracked locks, in the order they must be taken in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LockName {
+    Components,
+    Registry,
+    PolicyRegistry,
+}
+
+impl LockName {
+    #[cfg_attr(not(feature = "lock-diagnostics"), allow(dead_code))]
+    fn as_str(self) -> &'static str {
+        match self {
+            LockName::Components => "components",
+            LockName::Registry => "registry",
+            LockName::PolicyRegistry => "policy_registry",
+        }
+    }
+}
+
+#[cfg(not(feature = "lock-diagnostics"))]
+pub(crate) type TrackedRwLock<T> = tokio::sync::RwLock<T>;
+
+/// Creates the lock named `name`
+#[cfg(not(feature = "lock-diagnostics"))]
+pub(crate) fn tracked<T>(_name: LockName, value: T) -> TrackedRwLock<T> {
+    tokio::sync::RwLock::new(value)
+}
+
+#[cfg(feature = "lock-diagnostics")]
+pub(crate) use diagnostics::{tracked, TrackedRwLock};
+
+#[cfg(feature = "lock-diagnostics")]
+mod diagnostics {
+    use std::future::Future;
+    use std::ops::{Deref, DerefMut};
+    use std::panic::Location;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+    use tokio::task;
+    use tracing::warn;
+
+    use super::LockName;
+
+    /// Time a lock may be held for before it is reported
+    pub(crate) const LONG_HOLD: Duration = Duration::from_millis(500);
+
+    /// Time a task may wait for a lock before the holders are reported
+    pub(crate) const LONG_WAIT: Duration = Duration::from_secs(5);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Access {
+        Read,
+        Write,
+    }
+
+    impl Access {
+        fn as_str(self) -> &'static str {
+            match self {
+                Access::Read => "read",
+                Access::Write => "write",
+            }
+        }
+    }
+
+    /// A lock that is currently held
+    struct Hold {
+        id: u64,
+        task: Option<task::Id>,
+        lock: LockName,
+        access: Access,
+        since: Instant,
+        location: &'static Location<'static>,
+    }
+
+    /// Every lock held right now, across all tasks
+    static HOLDS: Mutex<Vec<Hold>> = Mutex::new(Vec::new());
+    static NEXT_HOLD_ID: AtomicU64 = AtomicU64::new(0);
+
+    fn holds() -> std::sync::MutexGuard<'static, Vec<Hold>> {
+        HOLDS.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A read-write lock whose acquisitions are tracked
+    pub(crate) struct TrackedRwLock<T> {
+        name: LockName,
+        inner: RwLock<T>,
+    }
+
+    /// Creates the lock named `name`
+    pub(crate) fn tracked<T>(name: LockName, value: T) -> TrackedRwLock<T> {
+        TrackedRwLock {
+            name,
+            inner: RwLock::new(value),
+        }
+    }
+
+    impl<T> TrackedRwLock<T> {
+        #[track_caller]
+        pub(crate) fn read(&self) -> impl Future<Output = TrackedGuard<RwLockReadGuard<'_, T>>> {
+            let location = Location::caller();
+            self.acquire(Access::Read, location, self.inner.read())
+        }
+
+        #[track_caller]
+        pub(crate) fn write(&self) -> impl Future<Output = TrackedGuard<RwLockWriteGuard<'_, T>>> {
+            let location = Location::caller();
+            self.acquire(Access::Write, location, self.inner.write())
+        }
+
+        async fn acquire<G>(
+            &self,
+            access: Access,
+            location: &'static Location<'static>,
+            acquisition: impl Future<Output = G>,
+        ) -> TrackedGuard<G> {
+            let task = task::try_id();
+            if let Some(task) = task {
+                check_order(self.name, task, location);
+            }
+
+            let start = Instant::now();
+            tokio::pin!(acquisition);
+            let guard = match tokio::time::timeout(LONG_WAIT, &mut acquisition).await {
+                Ok(guard) => guard,
+                Err(_) => {
+                    warn!(
+                        lock = self.name.as_str(),
+                        access = access.as_str(),
+                        %location,
+                        holders = %describe_holders(self.name),
+                        "Waiting long for a lock, this may be a deadlock"
+                    );
+                    let guard = acquisition.await;
+                    warn!(
+                        lock = self.name.as_str(),
+                        %location,
+                        waited_ms = start.elapsed().as_millis() as u64,
+                        "Got the lock after a long wait"
+                    );
+                    guard
+                }
+            };
+
+            let id = NEXT_HOLD_ID.fetch_add(1, Ordering::Relaxed);
+            holds().push(Hold {
+                id,
+                task,
+                lock: self.name,
+                access,
+                since: Instant::now(),
+                location,
+            });
+            TrackedGuard { guard, id }
+        }
+    }
+
+    /// Warns if taking `lock` now goes against the lock order, given what `task` already holds
+    fn check_order(lock: LockName, task: task::Id, location: &'static Location<'static>) {
+        for hold in holds().iter().filter(|hold| hold.task == Some(task)) {
+            if hold.lock == lock {
+                warn!(
+                    lock = lock.as_str(),
+                    %location,
+                    held_at = %hold.location,
+                    "Task takes a lock it already holds, which deadlocks once a writer waits for it"
+                );
+            } else if hold.lock > lock {
+                warn!(
+                    lock = lock.as_str(),
+                    %location,
+                    holding = hold.lock.as_str(),
+                    held_at = %hold.location,
+                    "Locks taken out of order, take components, then registry, then policy_registry"
+                );
+            }
+        }
+    }
+
+    /// Lists where the current holders of a lock took it and for how long they have held it
+    fn describe_holders(lock: LockName) -> String {
+        holds()
+            .iter()
+            .filter(|hold| hold.lock == lock)
+            .map(|hold| {
+                format!(
+                    "{} at {} for {}ms",
+                    hold.access.as_str(),
+                    hold.location,
+                    hold.since.elapsed().as_millis()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The guard of a tracked lock, which reports the lock if it was held long when dropped
+    pub(crate) struct TrackedGuard<G> {
+        guard: G,
+        id: u64,
+    }
+
+    impl<G> Drop for TrackedGuard<G> {
+        fn drop(&mut self) {
+            let mut holds = holds();
+            let Some(position) = holds.iter().position(|hold| hold.id == self.id) else {
+                return;
+            };
+            let hold = holds.swap_remove(position);
+            drop(holds);
+            let held = hold.since.elapsed();
+            if held > LONG_HOLD {
+                warn!(
+                    lock = hold.lock.as_str(),
+                    access = hold.access.as_str(),
+                    location = %hold.location,
+                    held_ms = held.as_millis() as u64,
+                    "Lock was held long, stalling other requests"
+                );
+            }
+        }
+    }
+
+    impl<G: Deref> Deref for TrackedGuard<G> {
+        type Target = G::Target;
+
+        fn deref(&self) -> &Self::Target {
+            &self.guard
+        }
+    }
+
+    impl<G: DerefMut> DerefMut for TrackedGuard<G> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.guard
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        #[tracing_test::traced_test]
+        async fn test_out_of_order_locks_are_reported() {
+            let components = tracked(LockName::Components, ());
+            let policies = tracked(LockName::PolicyRegistry, ());
+
+            {
+                let _components = components.read().await;
+                let _policies = policies.read().await;
+            }
+            assert!(!logs_contain("out of order"));
+
+            let _policies = policies.write().await;
+            let _components = components.read().await;
+            assert!(logs_contain("out of order"));
+        }
+
+        #[tokio::test]
+        #[tracing_test::traced_test]
+        async fn test_taking_a_held_lock_again_is_reported() {
+            let registry = tracked(LockName::Registry, 0);
+            *registry.write().await += 1;
+            assert_eq!(*registry.read().await, 1);
+            assert!(!logs_contain("already holds"));
+
+            let guard = registry.write().await;
+            let waiting = tokio::time::timeout(Duration::from_millis(10), registry.read()).await;
+            assert!(waiting.is_err());
+            assert!(logs_contain("already holds"));
+
+            // The read that was given up left nothing behind
+            drop(guard);
+            assert_eq!(*registry.read().await, 1);
+        }
+    }
+}
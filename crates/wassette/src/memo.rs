@@ -0,0 +1,215 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Reuse of the results of pure tools.
+//!
+//! Agents often call the same tool with the same arguments several times in a conversation. A
+//! component can declare a tool as `pure` in its tool metadata when its result only depends on its
+//! arguments, and successful results of such a tool are then kept for the declared TTL and
+//! returned for identical calls without running the component. Results are keyed by the digest of
+//! the component binary and the generation of the policies, so neither replacing the component
+//! nor changing a policy ever returns results computed before.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::status::{CacheCounters, CacheStats};
+use crate::LifecycleManager;
+
+/// Maximum number of results kept. Once reached, the result that expires first is dropped.
+const MAX_ENTRIES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct MemoKey {
+    component_id: String,
+    digest: String,
+    /// [`PolicyRegistry::generation`](crate::policy_internal::PolicyRegistry::generation) when
+    /// the call was made
+    policy_generation: u64,
+    tool: String,
+    /// The arguments as canonical JSON, so that the order of their keys doesn't matter
+    arguments: String,
+}
+
+struct MemoEntry {
+    result: String,
+    expires_at: Instant,
+}
+
+/// Results of pure tools, with the time they expire
+#[derive(Default)]
+pub(crate) struct MemoCache {
+    entries: HashMap<MemoKey, MemoEntry>,
+    counters: CacheCounters,
+}
+
+impl MemoCache {
+    /// Returns the result stored for `key` unless it expired
+    pub(crate) fn get(&mut self, key: &MemoKey) -> Option<String> {
+        let result = match self.entries.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.result.clone()),
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        self.counters.record(result.is_some());
+        result
+    }
+
+    /// Returns the number of results kept and how often calls found one
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.counters.stats(self.entries.len())
+    }
+
+    pub(crate) fn insert(&mut self, key: MemoKey, result: String, ttl: Duration) {
+        if self.entries.len() >= MAX_ENTRIES && !self.entries.contains_key(&key) {
+            let now = Instant::now();
+            self.entries.retain(|_, entry| entry.expires_at > now);
+            if self.entries.len() >= MAX_ENTRIES {
+                let first_to_expire = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(first_to_expire) = first_to_expire {
+                    self.entries.remove(&first_to_expire);
+                }
+            }
+        }
+        self.entries.insert(
+            key,
+            MemoEntry {
+                result,
+                expires_at: Instant::now() + ttl,
+            },
+        );
+    }
+
+    /// Drops the results of a component, e.g. when it is unloaded
+    pub(crate) fn remove_component(&mut self, component_id: &str) {
+        self.entries
+            .retain(|key, _| key.component_id != component_id);
+    }
+}
+
+/// Returns `value` as JSON with the keys of every object sorted
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            let entries: Vec<String> = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    format!("{}:{}", Value::from(key.as_str()), canonical_json(value))
+                })
+                .collect();
+            format!("{{{}}}", entries.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        _ => value.to_string(),
+    }
+}
+
+impl LifecycleManager {
+    /// Returns the key that a call's result is stored under and how long it is kept, or `None`
+    /// if the tool isn't pure
+    pub(crate) async fn memo_key(
+        &self,
+        component_id: &str,
+        tool_name: &str,
+        parameters: &str,
+    ) -> Option<(MemoKey, Duration)> {
+        let ttl = {
+            let registry = self.registry.read().await;
+            registry
+                .get_tool_info(tool_name)?
+                .iter()
+                .find(|info| info.component_id == component_id)?
+                .memoization
+                .as_ref()?
+                .ttl()
+        };
+        let digest = self
+            .components
+            .read()
+            .await
+            .get(component_id)?
+            .digest
+            .clone();
+        let policy_generation = self.policy_registry.read().await.generation;
+        let arguments: Value = serde_json::from_str(parameters).ok()?;
+        Some((
+            MemoKey {
+                component_id: component_id.to_string(),
+                digest,
+                policy_generation,
+                tool: tool_name.to_string(),
+                arguments: canonical_json(&arguments),
+            },
+            ttl,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn key(arguments: &str) -> MemoKey {
+        MemoKey {
+            component_id: "local/time".to_string(),
+            digest: "abc".to_string(),
+            policy_generation: 0,
+            tool: "format".to_string(),
+            arguments: arguments.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        assert_eq!(
+            canonical_json(&json!({"b": [1, {"d": null, "c": "x"}], "a": true})),
+            r#"{"a":true,"b":[1,{"c":"x","d":null}]}"#
+        );
+    }
+
+    #[test]
+    fn test_memo_cache() {
+        let mut cache = MemoCache::default();
+        cache.insert(key("{}"), "kept".to_string(), Duration::from_secs(60));
+        cache.insert(key("[]"), "expired".to_string(), Duration::ZERO);
+        assert_eq!(cache.get(&key("{}")).as_deref(), Some("kept"));
+        assert_eq!(cache.get(&key("[]")), None);
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+
+        cache.remove_component("local/time");
+        assert_eq!(cache.get(&key("{}")), None);
+    }
+
+    #[test]
+    fn test_memo_cache_is_bounded() {
+        let mut cache = MemoCache::default();
+        for i in 0..MAX_ENTRIES + 1 {
+            cache.insert(
+                key(&i.to_string()),
+                i.to_string(),
+                Duration::from_secs(60 + i as u64),
+            );
+        }
+        assert_eq!(cache.entries.len(), MAX_ENTRIES);
+        // The result that expires first made room for the last one
+        assert_eq!(cache.get(&key("0")), None);
+        assert!(cache.get(&key(&MAX_ENTRIES.to_string())).is_some());
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Tracking of the linear memories and tables that the component instance of a call allocates,
+//! so that their peak size can be reported and capped with `resources.memory` in the policy.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use wasmtime::ResourceLimiter;
+
+/// Peak memory and table use of a single call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryUsage {
+    /// Largest combined size of the instance's linear memories during the call, in bytes
+    pub peak_memory_bytes: u64,
+    /// Largest combined number of elements of the instance's tables during the call
+    pub peak_table_elements: u64,
+}
+
+/// Error of a call that grew its linear memories beyond the ceiling of the component's policy.
+/// Find it with `error.downcast_ref::<MemoryLimitExceeded>()`.
+#[derive(Debug, Clone)]
+pub struct MemoryLimitExceeded {
+    /// Combined size of the linear memories that the component asked for, in bytes
+    pub requested_bytes: u64,
+    /// Ceiling set by the policy, in bytes
+    pub limit_bytes: u64,
+}
+
+impl fmt::Display for MemoryLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The component exceeded its memory limit: it asked for {} bytes, but its policy allows \
+             at most {} bytes (resources.memory)",
+            self.requested_bytes, self.limit_bytes
+        )
+    }
+}
+
+impl std::error::Error for MemoryLimitExceeded {}
+
+/// The [`ResourceLimiter`] of a call's store, which tracks peak usage and enforces the memory
+/// ceiling of the component's policy
+#[derive(Debug, Default)]
+pub(crate) struct CallLimiter {
+    max_memory_bytes: Option<u64>,
+    memory_bytes: u64,
+    table_elements: u64,
+    usage: MemoryUsage,
+}
+
+impl CallLimiter {
+    pub(crate) fn new(max_memory_bytes: Option<u64>) -> Self {
+        Self {
+            max_memory_bytes,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the peak usage of the call so far
+    pub(crate) fn usage(&self) -> MemoryUsage {
+        self.usage
+    }
+}
+
+impl ResourceLimiter for CallLimiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        // New memories start at a current size of zero, so this sums up all of the instance's
+        let memory_bytes = self
+            .memory_bytes
+            .saturating_add(desired.saturating_sub(current) as u64);
+        if let Some(limit_bytes) = self.max_memory_bytes {
+            if memory_bytes > limit_bytes {
+                return Err(MemoryLimitExceeded {
+                    requested_bytes: memory_bytes,
+                    limit_bytes,
+                }
+                .into());
+            }
+        }
+        self.memory_bytes = memory_bytes;
+        self.usage.peak_memory_bytes = self.usage.peak_memory_bytes.max(memory_bytes);
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        self.table_elements = self
+            .table_elements
+            .saturating_add(desired.saturating_sub(current) as u64);
+        self.usage.peak_table_elements = self.usage.peak_table_elements.max(self.table_elements);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_limiter() -> anyhow::Result<()> {
+        let mut limiter = CallLimiter::new(Some(3 * 65536));
+        assert!(limiter.memory_growing(0, 65536, None)?);
+        assert!(limiter.memory_growing(0, 65536, None)?);
+        assert!(limiter.table_growing(0, 10, None)?);
+        assert!(limiter.table_growing(10, 12, None)?);
+        assert_eq!(
+            limiter.usage(),
+            MemoryUsage {
+                peak_memory_bytes: 2 * 65536,
+                peak_table_elements: 12,
+            }
+        );
+
+        let error = limiter.memory_growing(65536, 3 * 65536, None).unwrap_err();
+        let exceeded = error.downcast_ref::<MemoryLimitExceeded>().unwrap();
+        assert_eq!(exceeded.requested_bytes, 4 * 65536);
+        assert_eq!(exceeded.limit_bytes, 3 * 65536);
+        // The failed growth doesn't count towards the peak
+        assert_eq!(limiter.usage().peak_memory_bytes, 2 * 65536);
+        Ok(())
+    }
+}
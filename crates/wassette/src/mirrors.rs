@@ -0,0 +1,201 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Mirrors and fallback sources for component and policy URIs.
+//!
+//! A [`MirrorConfig`] maps a URI prefix to a list of prefixes to try first, e.g. an internal
+//! registry before `ghcr.io`. Sources that fail are tried last for a while, so a mirror that is
+//! down doesn't slow down every load.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// How long a source that failed once is tried last. Doubles with every further failure.
+const BASE_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Longest time a failing source is tried last
+const MAX_COOLDOWN: Duration = Duration::from_secs(10 * 60);
+
+/// Sources to try in place of a URI prefix
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorConfig {
+    /// Prefix of the URIs that are mirrored, e.g. `oci://ghcr.io/` or
+    /// `https://example.com/components/`
+    pub source: String,
+    /// Prefixes that replace `source`, tried in order before the original URI. A mirror may use a
+    /// different scheme than the source, but should keep the rest of the path, since the ID of a
+    /// component is derived from it
+    pub mirrors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Health {
+    consecutive_failures: u32,
+    last_failure: Instant,
+}
+
+impl Health {
+    fn is_cooling_down(&self, now: Instant) -> bool {
+        let cooldown = BASE_COOLDOWN
+            .saturating_mul(1 << self.consecutive_failures.saturating_sub(1).min(16))
+            .min(MAX_COOLDOWN);
+        now.duration_since(self.last_failure) < cooldown
+    }
+}
+
+/// A URI to try for a resource, and the source prefix its health is tracked under if it is
+/// mirrored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Candidate {
+    pub(crate) uri: String,
+    source: Option<String>,
+}
+
+/// The configured mirrors and the health of every source
+#[derive(Debug, Default)]
+pub(crate) struct MirrorSet {
+    rules: RwLock<Vec<MirrorConfig>>,
+    health: Mutex<HashMap<String, Health>>,
+}
+
+impl MirrorSet {
+    pub(crate) fn new(rules: Vec<MirrorConfig>) -> Self {
+        Self {
+            rules: RwLock::new(rules),
+            health: Mutex::default(),
+        }
+    }
+
+    /// Replaces the configured mirrors. The health of sources is kept.
+    pub(crate) fn set_rules(&self, rules: Vec<MirrorConfig>) {
+        *self.rules.write().unwrap() = rules;
+    }
+
+    /// Returns the URIs to try for the given URI: the mirrors of the longest matching source in
+    /// order, then the URI itself. Sources that failed recently come last.
+    pub(crate) fn candidates(&self, uri: &str) -> Vec<Candidate> {
+        let rules = self.rules.read().unwrap();
+        let Some(rule) = rules
+            .iter()
+            .filter(|rule| !rule.source.is_empty() && uri.starts_with(&rule.source))
+            .max_by_key(|rule| rule.source.len())
+        else {
+            return vec![Candidate {
+                uri: uri.to_string(),
+                source: None,
+            }];
+        };
+        let rest = &uri[rule.source.len()..];
+        let mut candidates: Vec<Candidate> = rule
+            .mirrors
+            .iter()
+            .chain(std::iter::once(&rule.source))
+            .map(|prefix| Candidate {
+                uri: format!("{prefix}{rest}"),
+                source: Some(prefix.clone()),
+            })
+            .collect();
+
+        let now = Instant::now();
+        let health = self.health.lock().unwrap();
+        // A stable sort keeps the configured order among healthy and among failing sources
+        candidates.sort_by_key(|candidate| {
+            candidate
+                .source
+                .as_ref()
+                .and_then(|source| health.get(source))
+                .is_some_and(|health| health.is_cooling_down(now))
+        });
+        candidates
+    }
+
+    /// Records whether loading from a candidate worked
+    pub(crate) fn record(&self, candidate: &Candidate, success: bool) {
+        let Some(source) = &candidate.source else {
+            return;
+        };
+        let mut health = self.health.lock().unwrap();
+        if success {
+            health.remove(source);
+            return;
+        }
+        let entry = health.entry(source.clone()).or_insert(Health {
+            consecutive_failures: 0,
+            last_failure: Instant::now(),
+        });
+        entry.consecutive_failures = entry.consecutive_failures.saturating_add(1);
+        entry.last_failure = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirrors() -> MirrorSet {
+        MirrorSet::new(vec![
+            MirrorConfig {
+                source: "oci://ghcr.io/".to_string(),
+                mirrors: vec![
+                    "oci://registry.internal:5000/".to_string(),
+                    "https://mirror.internal/oci/".to_string(),
+                ],
+            },
+            MirrorConfig {
+                source: "oci://ghcr.io/microsoft/".to_string(),
+                mirrors: vec!["oci://microsoft.internal/microsoft/".to_string()],
+            },
+        ])
+    }
+
+    fn uris(candidates: &[Candidate]) -> Vec<&str> {
+        candidates.iter().map(|c| c.uri.as_str()).collect()
+    }
+
+    #[test]
+    fn test_candidates() {
+        let mirrors = mirrors();
+        assert_eq!(
+            uris(&mirrors.candidates("oci://ghcr.io/yoshuawuyts/time:latest")),
+            vec![
+                "oci://registry.internal:5000/yoshuawuyts/time:latest",
+                "https://mirror.internal/oci/yoshuawuyts/time:latest",
+                "oci://ghcr.io/yoshuawuyts/time:latest",
+            ]
+        );
+        // The longest matching source wins
+        assert_eq!(
+            uris(&mirrors.candidates("oci://ghcr.io/microsoft/fetch-rs:latest")),
+            vec![
+                "oci://microsoft.internal/microsoft/fetch-rs:latest",
+                "oci://ghcr.io/microsoft/fetch-rs:latest",
+            ]
+        );
+        assert_eq!(
+            uris(&mirrors.candidates("file:///tmp/fetch.wasm")),
+            vec!["file:///tmp/fetch.wasm"]
+        );
+    }
+
+    #[test]
+    fn test_failing_sources_are_tried_last() {
+        let mirrors = mirrors();
+        let uri = "oci://ghcr.io/yoshuawuyts/time:latest";
+        let candidates = mirrors.candidates(uri);
+        mirrors.record(&candidates[0], false);
+        assert_eq!(
+            uris(&mirrors.candidates(uri)),
+            vec![
+                "https://mirror.internal/oci/yoshuawuyts/time:latest",
+                "oci://ghcr.io/yoshuawuyts/time:latest",
+                "oci://registry.internal:5000/yoshuawuyts/time:latest",
+            ]
+        );
+
+        mirrors.record(&candidates[0], true);
+        assert_eq!(uris(&mirrors.candidates(uri)), uris(&candidates));
+    }
+}
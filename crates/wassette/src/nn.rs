@@ -0,0 +1,100 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Local model inference through `wasi:nn`.
+//!
+//! The models in [`LifecycleConfig::ai_models`](crate::LifecycleConfig::ai_models) are loaded
+//! once at startup. Every call gets its own `wasi:nn` context that only contains the models the
+//! component's policy allows, and has no backends at all if the policy has no `ai` section, so
+//! components can't load models from bytes either.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use tracing::info;
+use wasmtime_wasi_nn::wit::{ExecutionTarget, GraphEncoding, WasiNnCtx};
+use wasmtime_wasi_nn::{backend, Graph, GraphRegistry, Registry};
+
+use crate::AiModelConfig;
+
+/// The `ai` policy entry that allows every model
+const ANY_MODEL: &str = "*";
+
+/// The models available to components
+#[derive(Default)]
+pub(crate) struct NnModels {
+    graphs: HashMap<String, Graph>,
+}
+
+impl NnModels {
+    /// Loads the configured models with the backend for their encoding
+    pub(crate) fn load(models: &[AiModelConfig]) -> Result<Self> {
+        let mut backends: HashMap<GraphEncoding, _> = backend::list()
+            .into_iter()
+            .map(|backend| (backend.encoding(), backend))
+            .collect();
+        let mut graphs = HashMap::new();
+        for model in models {
+            let encoding: GraphEncoding = model
+                .encoding
+                .parse()
+                .map_err(|e| anyhow!("Unknown encoding of AI model {}: {e}", model.name))?;
+            let backend = backends
+                .get_mut(&encoding)
+                .and_then(|backend| backend.as_dir_loadable())
+                .with_context(|| {
+                    format!(
+                        "No inference backend can load {} models from a directory",
+                        model.encoding
+                    )
+                })?;
+            let graph = backend
+                .load_from_dir(Path::new(&model.path), ExecutionTarget::Cpu)
+                .map_err(|e| anyhow!("{e}"))
+                .with_context(|| {
+                    format!(
+                        "Failed to load AI model {} from {}",
+                        model.name,
+                        model.path.display()
+                    )
+                })?;
+            info!(model = %model.name, encoding = %model.encoding, "Loaded AI model");
+            graphs.insert(model.name.clone(), graph);
+        }
+        Ok(Self { graphs })
+    }
+
+    /// Builds the `wasi:nn` context of a call. `allowed` lists the models the component's policy
+    /// allows, and is `None` if the policy doesn't allow inference.
+    pub(crate) fn ctx_for(&self, allowed: Option<&[String]>) -> WasiNnCtx {
+        let Some(allowed) = allowed else {
+            return WasiNnCtx::new(Vec::new(), Registry::from(AllowedGraphs::default()));
+        };
+        let graphs = self
+            .graphs
+            .iter()
+            .filter(|(name, _)| {
+                allowed
+                    .iter()
+                    .any(|model| model == ANY_MODEL || model == *name)
+            })
+            .map(|(name, graph)| (name.clone(), graph.clone()))
+            .collect();
+        WasiNnCtx::new(backend::list(), Registry::from(AllowedGraphs(graphs)))
+    }
+}
+
+/// The models a single call may load by name
+#[derive(Default)]
+struct AllowedGraphs(HashMap<String, Graph>);
+
+impl GraphRegistry for AllowedGraphs {
+    fn get(&self, name: &str) -> Option<&Graph> {
+        self.0.get(name)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Graph> {
+        self.0.get_mut(name)
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Selection of the component in an OCI image.
+//!
+//! An OCI reference points either to an image manifest or to an image index that lists the
+//! manifests of several platforms, e.g. a component published next to container images of the
+//! same tool. For an index, the entry with the `wasm` architecture is used, preferring `wasip2`
+//! to older WASI versions, instead of the entry for the platform the server runs on. The
+//! component is the layer with a wasm media type. When there is no such entry or layer, the error
+//! lists what the image has instead, so that a reference to the wrong artifact is easy to spot.
+
+use anyhow::{bail, Context, Result};
+use oci_client::manifest::{
+    ImageIndexEntry, OciDescriptor, OciImageIndex, OciImageManifest, OciManifest,
+    IMAGE_MANIFEST_MEDIA_TYPE, OCI_IMAGE_MEDIA_TYPE,
+};
+use oci_client::secrets::RegistryAuth;
+use oci_client::Reference;
+use tracing::debug;
+
+use crate::compression::{GZIP_WASM_MEDIA_TYPE, ZSTD_WASM_MEDIA_TYPE};
+
+/// Architecture of wasm entries in an image index
+const WASM_ARCHITECTURE: &str = "wasm";
+
+/// Operating systems of wasm entries, in the order they are preferred in
+const WASM_OS_PREFERENCE: &[&str] = &["wasip2", "wasi", "wasip1"];
+
+/// Media types of layers that hold a component, compressed or not
+pub(crate) const COMPONENT_LAYER_MEDIA_TYPES: &[&str] = &[
+    oci_wasm::WASM_LAYER_MEDIA_TYPE,
+    ZSTD_WASM_MEDIA_TYPE,
+    GZIP_WASM_MEDIA_TYPE,
+];
+
+/// Pulls the manifest of the component that `reference` points to, resolving an image index to
+/// its wasm entry. Returns the manifest with the reference it was pulled from, which names the
+/// entry's digest if it came from an index.
+pub(crate) async fn pull_component_manifest(
+    client: &oci_client::Client,
+    reference: &Reference,
+    auth: &RegistryAuth,
+) -> Result<(OciImageManifest, Reference)> {
+    let index = match client.pull_manifest(reference, auth).await?.0 {
+        OciManifest::Image(manifest) => return Ok((manifest, reference.clone())),
+        OciManifest::ImageIndex(index) => index,
+    };
+    let entry = select_wasm_entry(&index)
+        .with_context(|| format!("{reference} points to an image index without a component"))?;
+    debug!(reference = %reference, digest = %entry.digest, "Selected wasm entry of image index");
+    let resolved = reference.clone_with_digest(entry.digest.clone());
+    match client.pull_manifest(&resolved, auth).await?.0 {
+        OciManifest::Image(manifest) => Ok((manifest, resolved)),
+        OciManifest::ImageIndex(_) => bail!(
+            "The wasm entry {} of {reference} is another image index",
+            entry.digest
+        ),
+    }
+}
+
+/// Returns the entry of an image index for the wasm platform
+fn select_wasm_entry(index: &OciImageIndex) -> Result<&ImageIndexEntry> {
+    index
+        .manifests
+        .iter()
+        .filter(|entry| {
+            [IMAGE_MANIFEST_MEDIA_TYPE, OCI_IMAGE_MEDIA_TYPE].contains(&entry.media_type.as_str())
+        })
+        .filter_map(|entry| {
+            let platform = entry.platform.as_ref()?;
+            (platform.architecture == WASM_ARCHITECTURE).then(|| {
+                let preference = WASM_OS_PREFERENCE
+                    .iter()
+                    .position(|os| *os == platform.os)
+                    .unwrap_or(WASM_OS_PREFERENCE.len());
+                (preference, entry)
+            })
+        })
+        .min_by_key(|(preference, _)| *preference)
+        .map(|(_, entry)| entry)
+        .with_context(|| {
+            let platforms: Vec<String> = index
+                .manifests
+                .iter()
+                .map(|entry| match &entry.platform {
+                    Some(platform) => format!("{}/{}", platform.os, platform.architecture),
+                    None => format!("{} without platform", entry.media_type),
+                })
+                .collect();
+            format!(
+                "No entry has the {WASM_ARCHITECTURE} architecture. Available: {}",
+                if platforms.is_empty() {
+                    "nothing".to_string()
+                } else {
+                    platforms.join(", ")
+                }
+            )
+        })
+}
+
+/// Returns the layer of a manifest that holds the component
+pub(crate) fn component_layer(manifest: &OciImageManifest) -> Result<&OciDescriptor> {
+    manifest
+        .layers
+        .iter()
+        .find(|layer| COMPONENT_LAYER_MEDIA_TYPES.contains(&layer.media_type.as_str()))
+        .with_context(|| {
+            let mut types: Vec<&str> = manifest
+                .layers
+                .iter()
+                .map(|layer| layer.media_type.as_str())
+                .collect();
+            types.dedup();
+            let artifact = manifest
+                .artifact_type
+                .as_deref()
+                .unwrap_or(&manifest.config.media_type);
+            format!(
+                "Image has no wasm layer. Its artifact type is {artifact} and it has layers of type {}",
+                if types.is_empty() {
+                    "nothing".to_string()
+                } else {
+                    types.join(", ")
+                }
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn entry(os: &str, architecture: &str, digest: &str) -> serde_json::Value {
+        json!({
+            "mediaType": OCI_IMAGE_MEDIA_TYPE,
+            "digest": digest,
+            "size": 100,
+            "platform": {"os": os, "architecture": architecture},
+        })
+    }
+
+    #[test]
+    fn test_select_wasm_entry() -> Result<()> {
+        let index: OciImageIndex = serde_json::from_value(json!({
+            "schemaVersion": 2,
+            "manifests": [
+                entry("linux", "amd64", "sha256:linux"),
+                entry("wasip1", "wasm", "sha256:p1"),
+                entry("wasip2", "wasm", "sha256:p2"),
+            ],
+        }))?;
+        assert_eq!(select_wasm_entry(&index)?.digest, "sha256:p2");
+
+        let containers: OciImageIndex = serde_json::from_value(json!({
+            "schemaVersion": 2,
+            "manifests": [
+                entry("linux", "amd64", "sha256:amd64"),
+                entry("linux", "arm64", "sha256:arm64"),
+            ],
+        }))?;
+        let error = select_wasm_entry(&containers).unwrap_err().to_string();
+        assert!(error.contains("linux/amd64, linux/arm64"), "{error}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_layer() -> Result<()> {
+        let manifest: OciImageManifest = serde_json::from_value(json!({
+            "schemaVersion": 2,
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "digest": "sha256:config",
+                "size": 10,
+            },
+            "layers": [{
+                "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                "digest": "sha256:rootfs",
+                "size": 1000,
+            }],
+        }))?;
+        let error = component_layer(&manifest).unwrap_err().to_string();
+        assert!(
+            error.contains("application/vnd.oci.image.layer.v1.tar+gzip"),
+            "{error}"
+        );
+
+        let mut manifest = manifest;
+        manifest.layers.push(OciDescriptor {
+            media_type: oci_wasm::WASM_LAYER_MEDIA_TYPE.to_string(),
+            digest: "sha256:component".to_string(),
+            size: 100,
+            ..Default::default()
+        });
+        assert_eq!(component_layer(&manifest)?.digest, "sha256:component");
+        Ok(())
+    }
+}
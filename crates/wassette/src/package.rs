@@ -0,0 +1,420 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Self-contained `.wassette-pkg` packages for installing a component without registry access.
+//!
+//! A package is a zstd compressed tar archive holding a single component, optionally its policy,
+//! and any detached signatures for it (e.g. cosign or notation signatures), which are carried along
+//! for verification with external tools. A `package.json` manifest at the root records the
+//! component ID, free-form metadata and the SHA-256 digest of every file, which are checked before
+//! the package is installed with `load-component file:///path/to/name.wassette-pkg`.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Component as PathComponent, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use policy::PolicyParser;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument, warn};
+
+use crate::bundle::digest_file;
+use crate::{BundleEntry, LifecycleManager, LoadResult};
+
+/// File extension of packages
+pub const PACKAGE_EXTENSION: &str = "wassette-pkg";
+
+const PACKAGE_MANIFEST: &str = "package.json";
+const COMPONENT_ENTRY: &str = "component.wasm";
+const POLICY_ENTRY: &str = "policy.yaml";
+const SIGNATURES_PREFIX: &str = "signatures";
+const PACKAGE_FORMAT_VERSION: u32 = 1;
+
+/// The manifest stored at the root of every package
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageManifest {
+    /// Version of the package layout
+    pub format_version: u32,
+    /// Version of wassette that produced the package
+    pub wassette_version: String,
+    /// Creation time in seconds since the Unix epoch
+    pub created_at: u64,
+    /// ID the component is loaded under
+    pub component_id: String,
+    /// Free-form metadata, such as the version or origin of the component
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// Every file contained in the package, excluding the manifest itself
+    pub entries: Vec<BundleEntry>,
+}
+
+impl PackageManifest {
+    /// Returns true if the package contains a policy
+    pub fn has_policy(&self) -> bool {
+        self.entries.iter().any(|entry| entry.path == POLICY_ENTRY)
+    }
+
+    /// Returns the paths of the signatures in the package
+    pub fn signatures(&self) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.path.starts_with(&format!("{SIGNATURES_PREFIX}/")))
+            .map(|entry| entry.path.as_str())
+            .collect()
+    }
+}
+
+/// What goes into a package
+#[derive(Debug, Clone, Default)]
+pub struct PackOptions {
+    /// The component. Its file name without the `.wasm` extension becomes the component ID
+    pub component: PathBuf,
+    /// The policy of the component
+    pub policy: Option<PathBuf>,
+    /// Detached signatures of the component
+    pub signatures: Vec<PathBuf>,
+    /// Free-form metadata recorded in the manifest
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Writes a package to `dest`
+pub async fn pack_component(
+    options: PackOptions,
+    dest: impl AsRef<Path>,
+) -> Result<PackageManifest> {
+    let dest = dest.as_ref().to_path_buf();
+    tokio::task::spawn_blocking(move || pack_blocking(&options, &dest)).await?
+}
+
+fn pack_blocking(options: &PackOptions, dest: &Path) -> Result<PackageManifest> {
+    let component_id = match (
+        options.component.file_stem().and_then(|s| s.to_str()),
+        options.component.extension(),
+    ) {
+        (Some(stem), Some(ext)) if ext == "wasm" => stem.to_string(),
+        _ => bail!(
+            "Component must be a .wasm file: {}",
+            options.component.display()
+        ),
+    };
+
+    let mut files = vec![(COMPONENT_ENTRY.to_string(), options.component.clone())];
+    if let Some(policy) = &options.policy {
+        // Catch a broken policy now rather than on the air-gapped machine
+        PolicyParser::parse_file(policy)
+            .with_context(|| format!("Invalid policy {}", policy.display()))?;
+        files.push((POLICY_ENTRY.to_string(), policy.clone()));
+    }
+    for signature in &options.signatures {
+        let name = signature
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("Invalid signature path {}", signature.display()))?;
+        let entry = format!("{SIGNATURES_PREFIX}/{name}");
+        if files.iter().any(|(path, _)| *path == entry) {
+            bail!("Two signatures are named {name}");
+        }
+        files.push((entry, signature.clone()));
+    }
+
+    let mut entries = Vec::with_capacity(files.len());
+    for (archive_path, path) in &files {
+        let (sha256, size) = digest_file(path)?;
+        entries.push(BundleEntry {
+            path: archive_path.clone(),
+            sha256,
+            size,
+        });
+    }
+    let manifest = PackageManifest {
+        format_version: PACKAGE_FORMAT_VERSION,
+        wassette_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        component_id,
+        metadata: options.metadata.clone(),
+        entries,
+    };
+
+    let out = File::create(dest)
+        .with_context(|| format!("Failed to create package {}", dest.display()))?;
+    let encoder = zstd::Encoder::new(BufWriter::new(out), 0)?;
+    let mut builder = tar::Builder::new(encoder);
+
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at);
+    header.set_cksum();
+    builder.append_data(&mut header, PACKAGE_MANIFEST, manifest_bytes.as_slice())?;
+
+    for (archive_path, path) in &files {
+        debug!(path = %path.display(), entry = %archive_path, "Adding file to package");
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open {} for packing", path.display()))?;
+        builder.append_file(archive_path, &mut file)?;
+    }
+    builder.into_inner()?.finish()?;
+
+    info!(path = %dest.display(), component_id = %manifest.component_id, "Packed component");
+    Ok(manifest)
+}
+
+/// A verified package, extracted to a temporary directory that lives as long as this does
+pub(crate) struct UnpackedPackage {
+    _staging: tempfile::TempDir,
+    pub(crate) manifest: PackageManifest,
+    /// The component, named after its ID
+    pub(crate) component: PathBuf,
+    pub(crate) policy: Option<PathBuf>,
+}
+
+/// Returns the path of the package if the URI refers to a local package
+pub(crate) fn package_path(uri: &str) -> Option<&Path> {
+    let path = uri.trim().strip_prefix("file://")?;
+    path.ends_with(&format!(".{PACKAGE_EXTENSION}"))
+        .then(|| Path::new(path))
+}
+
+/// Extracts the package and verifies every digest in its manifest
+pub(crate) async fn unpack_package(package: &Path) -> Result<UnpackedPackage> {
+    let package = package.to_path_buf();
+    tokio::task::spawn_blocking(move || unpack_blocking(&package)).await?
+}
+
+fn unpack_blocking(package: &Path) -> Result<UnpackedPackage> {
+    let staging = tempfile::tempdir()?;
+    let file = File::open(package)
+        .with_context(|| format!("Failed to open package {}", package.display()))?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(BufReader::new(file))?);
+
+    let mut staged = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let archive_path = validate_archive_path(&entry.path()?)?;
+        if !entry.header().entry_type().is_file() {
+            bail!("Package entry {archive_path} is not a regular file");
+        }
+        let target = staging.path().join(&archive_path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&target)?;
+        staged.push(archive_path);
+    }
+
+    let manifest_path = staging.path().join(PACKAGE_MANIFEST);
+    if !manifest_path.exists() {
+        bail!("Package {} does not contain a manifest", package.display());
+    }
+    let manifest: PackageManifest = serde_json::from_reader(File::open(&manifest_path)?)
+        .context("Failed to parse package manifest")?;
+    if manifest.format_version != PACKAGE_FORMAT_VERSION {
+        bail!(
+            "Unsupported package format version {} (expected {})",
+            manifest.format_version,
+            PACKAGE_FORMAT_VERSION
+        );
+    }
+    // The ID becomes a file name in the plugin directory
+    if manifest.component_id.is_empty()
+        || !manifest
+            .component_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        || manifest.component_id.starts_with('.')
+    {
+        bail!("Invalid component ID in package: {}", manifest.component_id);
+    }
+
+    for archive_path in &staged {
+        if archive_path != PACKAGE_MANIFEST
+            && !manifest.entries.iter().any(|e| &e.path == archive_path)
+        {
+            bail!("Package entry {archive_path} is not listed in the manifest");
+        }
+    }
+    for entry in &manifest.entries {
+        let staged_path = staging.path().join(&entry.path);
+        if !staged_path.is_file() {
+            bail!(
+                "Package is missing file {} listed in the manifest",
+                entry.path
+            );
+        }
+        let (sha256, size) = digest_file(&staged_path)?;
+        if sha256 != entry.sha256 || size != entry.size {
+            bail!(
+                "Digest mismatch for {}: expected sha256 {}, found {}",
+                entry.path,
+                entry.sha256,
+                sha256
+            );
+        }
+    }
+    if !manifest.entries.iter().any(|e| e.path == COMPONENT_ENTRY) {
+        bail!("Package {} does not contain a component", package.display());
+    }
+
+    // The component ID is derived from the file name when the component is loaded
+    let component = staging
+        .path()
+        .join(format!("{}.wasm", manifest.component_id));
+    std::fs::rename(staging.path().join(COMPONENT_ENTRY), &component)?;
+    let policy = manifest
+        .has_policy()
+        .then(|| staging.path().join(POLICY_ENTRY));
+    Ok(UnpackedPackage {
+        _staging: staging,
+        manifest,
+        component,
+        policy,
+    })
+}
+
+/// Only the manifest, the component, the policy and flat files under `signatures/` are accepted.
+/// This also rejects absolute paths and `..` segments coming from a crafted archive.
+fn validate_archive_path(path: &Path) -> Result<String> {
+    let parts = path
+        .components()
+        .map(|component| match component {
+            PathComponent::Normal(part) => part
+                .to_str()
+                .map(|s| s.to_string())
+                .context("Package entry path is not valid UTF-8"),
+            _ => bail!("Invalid path in package: {}", path.display()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let allowed = match parts.as_slice() {
+        [name] => [PACKAGE_MANIFEST, COMPONENT_ENTRY, POLICY_ENTRY].contains(&name.as_str()),
+        [dir, _] => dir == SIGNATURES_PREFIX,
+        _ => false,
+    };
+    let joined = parts.join("/");
+    if !allowed {
+        bail!("Unexpected entry in package: {joined}");
+    }
+    Ok(joined)
+}
+
+impl LifecycleManager {
+    /// Installs the component of a package. Its policy, if it has one, is only attached when
+    /// [`attach_package_policies`](crate::LifecycleConfig::attach_package_policies) is set.
+    #[instrument(skip(self))]
+    pub(crate) async fn load_package(
+        &self,
+        package: &Path,
+        expected_digest: Option<&str>,
+    ) -> Result<(String, LoadResult)> {
+        let unpacked = unpack_package(package).await?;
+        let (id, result) = Box::pin(self.load_component_with_digest(
+            &format!("file://{}", unpacked.component.display()),
+            expected_digest,
+        ))
+        .await?;
+        if let Some(policy) = &unpacked.policy {
+            if self.config.read().await.attach_package_policies {
+                self.install_policy(&id, policy, &format!("file://{}", package.display()))
+                    .await
+                    .context("Failed to install the policy of the package")?;
+            } else {
+                warn!(
+                    component_id = %id,
+                    "Not attaching the policy bundled in the package, since attach_package_policies is off"
+                );
+            }
+        }
+        info!(
+            component_id = %id,
+            signatures = unpacked.manifest.signatures().len(),
+            "Installed component package"
+        );
+        Ok((id, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str =
+        "version: \"1.0\"\npermissions:\n  network:\n    allow:\n      - host: \"example.com\"\n";
+
+    fn write_inputs(dir: &Path) -> PackOptions {
+        std::fs::write(dir.join("fetch.wasm"), b"\0asm fake component").unwrap();
+        std::fs::write(dir.join("fetch.policy.yaml"), POLICY).unwrap();
+        std::fs::write(dir.join("fetch.wasm.sig"), b"signature").unwrap();
+        PackOptions {
+            component: dir.join("fetch.wasm"),
+            policy: Some(dir.join("fetch.policy.yaml")),
+            signatures: vec![dir.join("fetch.wasm.sig")],
+            metadata: BTreeMap::from([("version".to_string(), "1.2.0".to_string())]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pack_unpack_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let package = dir.path().join(format!("fetch.{PACKAGE_EXTENSION}"));
+        let manifest = pack_component(write_inputs(dir.path()), &package).await?;
+        assert_eq!(manifest.component_id, "fetch");
+        assert!(manifest.has_policy());
+        assert_eq!(manifest.signatures(), vec!["signatures/fetch.wasm.sig"]);
+
+        let uri = format!("file://{}", package.display());
+        let unpacked = unpack_package(package_path(&uri).unwrap()).await?;
+        assert_eq!(unpacked.manifest, manifest);
+        assert_eq!(unpacked.component.file_name().unwrap(), "fetch.wasm");
+        assert_eq!(std::fs::read(&unpacked.component)?, b"\0asm fake component");
+        assert_eq!(
+            std::fs::read_to_string(unpacked.policy.as_ref().unwrap())?,
+            POLICY
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unpack_detects_tampered_package() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let package = dir.path().join(format!("fetch.{PACKAGE_EXTENSION}"));
+        let mut manifest = pack_component(write_inputs(dir.path()), &package).await?;
+        manifest.entries[0].sha256 = "0".repeat(64);
+
+        let manifest_bytes = serde_json::to_vec(&manifest)?;
+        let encoder = zstd::Encoder::new(File::create(&package)?, 0)?;
+        let mut builder = tar::Builder::new(encoder);
+        for (path, data) in [
+            (PACKAGE_MANIFEST, manifest_bytes.as_slice()),
+            (COMPONENT_ENTRY, b"\0asm fake component".as_slice()),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, data)?;
+        }
+        builder.into_inner()?.finish()?;
+
+        let err = unpack_package(&package).await.err().unwrap();
+        assert!(err.to_string().contains("Digest mismatch"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_package_path() {
+        assert_eq!(
+            package_path("file:///tmp/fetch.wassette-pkg"),
+            Some(Path::new("/tmp/fetch.wassette-pkg"))
+        );
+        assert_eq!(package_path("file:///tmp/fetch.wasm"), None);
+        assert_eq!(package_path("https://example.com/fetch.wassette-pkg"), None);
+        assert!(validate_archive_path(Path::new("signatures/a.sig")).is_ok());
+        assert!(validate_archive_path(Path::new("signatures/../a.sig")).is_err());
+        assert!(validate_archive_path(Path::new("other.wasm")).is_err());
+    }
+}
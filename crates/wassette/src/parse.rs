@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Parsing of the untrusted input wassette reads: the arguments of tool calls, policies and the
+//! metadata stored next to components. Wassette parses such input only through these functions,
+//! which are public so that they can be fuzzed and so that embedders can reuse the same
+//! validation. They return an error for any input they reject and never panic.
+
+use anyhow::{bail, Context, Result};
+use policy::{PolicyDocument, PolicyParser};
+use serde_json::{Map, Value};
+
+use crate::component_id::{validate_component_id, ComponentMetadata};
+use crate::labels::validate_labels;
+
+/// Largest input the parse functions accept, in bytes
+pub const MAX_PARSE_INPUT_SIZE: usize = 16 * 1024 * 1024;
+
+/// The arguments of a tool call, keyed by parameter name
+pub type ToolArguments = Map<String, Value>;
+
+fn check_size(bytes: &[u8], what: &str) -> Result<()> {
+    if bytes.len() > MAX_PARSE_INPUT_SIZE {
+        bail!(
+            "{what} is {} bytes, more than the limit of {MAX_PARSE_INPUT_SIZE}",
+            bytes.len()
+        );
+    }
+    Ok(())
+}
+
+/// Parses the arguments of a tool call, which must be a JSON object
+pub fn parse_tool_arguments(bytes: &[u8]) -> Result<ToolArguments> {
+    check_size(bytes, "Tool arguments")?;
+    match serde_json::from_slice(bytes).context("Tool arguments are not valid JSON")? {
+        Value::Object(arguments) => Ok(arguments),
+        other => bail!(
+            "Tool arguments must be a JSON object, got {}",
+            json_type(&other)
+        ),
+    }
+}
+
+/// Parses and validates a policy document
+pub fn parse_policy(bytes: &[u8]) -> Result<PolicyDocument> {
+    check_size(bytes, "Policy")?;
+    PolicyParser::parse_bytes(bytes)
+}
+
+/// Parses the metadata stored next to a component in `<id>.meta.json`, validating its component
+/// ID and labels
+pub fn parse_component_metadata(bytes: &[u8]) -> Result<ComponentMetadata> {
+    check_size(bytes, "Component metadata")?;
+    let metadata: ComponentMetadata =
+        serde_json::from_slice(bytes).context("Component metadata is not valid")?;
+    validate_component_id(&metadata.id)?;
+    validate_labels(&metadata.labels)?;
+    Ok(metadata)
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Entry points for fuzz targets, built with `--cfg fuzzing` as `cargo fuzz` does. Every target
+/// feeds arbitrary bytes to a parse function; a panic or a hang is a bug.
+#[cfg(fuzzing)]
+pub mod fuzz {
+    /// Fuzzes [`parse_tool_arguments`](super::parse_tool_arguments)
+    pub fn tool_arguments(data: &[u8]) {
+        let _ = super::parse_tool_arguments(data);
+    }
+
+    /// Fuzzes [`parse_policy`](super::parse_policy)
+    pub fn policy(data: &[u8]) {
+        let _ = super::parse_policy(data);
+    }
+
+    /// Fuzzes [`parse_component_metadata`](super::parse_component_metadata)
+    pub fn component_metadata(data: &[u8]) {
+        let _ = super::parse_component_metadata(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_tool_arguments() {
+        let arguments = parse_tool_arguments(br#"{"url": "https://example.com"}"#).unwrap();
+        assert_eq!(arguments["url"], "https://example.com");
+        assert!(parse_tool_arguments(b"[1, 2]").is_err());
+        assert!(parse_tool_arguments(b"{\"url\":").is_err());
+    }
+
+    #[test]
+    fn test_parse_component_metadata() {
+        let metadata =
+            parse_component_metadata(br#"{"id": "local/fetch", "labels": {"team": "infra"}}"#)
+                .unwrap();
+        assert_eq!(metadata.id, "local/fetch");
+        assert!(parse_component_metadata(br#"{"id": "../../etc/passwd"}"#).is_err());
+        assert!(
+            parse_component_metadata(br#"{"id": "local/fetch", "labels": {"a b": "c"}}"#).is_err()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_functions_never_panic(data in prop::collection::vec(any::<u8>(), 0..512)) {
+            let _ = parse_tool_arguments(&data);
+            let _ = parse_policy(&data);
+            let _ = parse_component_metadata(&data);
+        }
+    }
+}
@@ -3,19 +3,19 @@
 
 //! Policy management structures and types
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use policy::{
     AccessType, EnvironmentPermission, NetworkHostPermission, NetworkPermission, PolicyDocument,
     PolicyParser, StoragePermission,
 };
 use serde::{Deserialize, Serialize};
-use tracing::{info, instrument};
+use tracing::{info, instrument, warn};
 
-use crate::WasiStateTemplate;
+use crate::{LifecycleEvent, WasiStateTemplate};
 
 /// Granular permission rule types
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +50,27 @@ pub struct PermissionGrantRequest {
 pub(crate) struct PolicyRegistry {
     /// Maps component IDs to their associated policy templates
     pub(crate) component_policies: HashMap<String, Arc<WasiStateTemplate>>,
+    /// Counts the changes to `component_policies`, so that results computed under an earlier
+    /// policy can be told apart
+    pub(crate) generation: u64,
+}
+
+impl PolicyRegistry {
+    /// Associates a policy template with a component, replacing its current one
+    pub(crate) fn insert(&mut self, component_id: String, template: WasiStateTemplate) {
+        self.component_policies
+            .insert(component_id, Arc::new(template));
+        self.generation += 1;
+    }
+
+    /// Removes the policy template of a component, returning whether it had one
+    pub(crate) fn remove(&mut self, component_id: &str) -> bool {
+        let removed = self.component_policies.remove(component_id).is_some();
+        if removed {
+            self.generation += 1;
+        }
+        removed
+    }
 }
 
 /// Information about a policy attached to a component
@@ -67,6 +88,46 @@ pub struct PolicyInfo {
     pub created_at: std::time::SystemTime,
 }
 
+/// Reads the policy stored next to a component in the plugin directory, if it has one
+pub(crate) async fn read_policy_template(
+    plugin_dir: &Path,
+    component_id: &str,
+) -> Result<Option<WasiStateTemplate>> {
+    let policy_path = plugin_dir.join(format!("{component_id}.policy.yaml"));
+    let policy_content = match tokio::fs::read(&policy_path).await {
+        Ok(policy_content) => policy_content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read co-located policy file"),
+    };
+    let policy =
+        crate::parse_policy(&policy_content).context("Failed to parse co-located policy file")?;
+    warn_deprecated(component_id, &policy);
+    let mut policy = resolve_extends(policy, &policy_path)?;
+    policy.migrate();
+    crate::create_wasi_state_template_from_policy(&policy, plugin_dir)
+        .map(Some)
+        .context("Failed to create WASI template from policy")
+}
+
+/// Merges the bases a policy extends into it, relative to the file it was read from, and logs
+/// which bases were read
+fn resolve_extends(policy: PolicyDocument, policy_path: &Path) -> Result<PolicyDocument> {
+    let (policy, bases) = policy
+        .resolve_extends_with_bases(policy_path.parent().unwrap_or(Path::new(".")))
+        .context("Failed to resolve the policies the policy extends")?;
+    if !bases.is_empty() {
+        info!(policy = %policy_path.display(), ?bases, "Merged base policies");
+    }
+    Ok(policy)
+}
+
+/// Logs the deprecated settings of a component's policy
+fn warn_deprecated(component_id: &str, policy: &PolicyDocument) {
+    for warning in policy.warnings() {
+        warn!(component_id, %warning, "Policy uses a deprecated setting");
+    }
+}
+
 impl crate::LifecycleManager {
     /// Attaches a policy to a component. The policy can be a local file or a URL.
     /// This function will download the policy from the given URI and store it
@@ -76,39 +137,63 @@ impl crate::LifecycleManager {
     pub async fn attach_policy(&self, component_id: &str, policy_uri: &str) -> Result<()> {
         info!(component_id, policy_uri, "Attaching policy to component");
 
-        if !self.components.read().await.contains_key(component_id) {
+        if !self.has_component(component_id).await {
             return Err(anyhow!("Component not found: {}", component_id));
         }
 
-        let downloaded_policy = crate::loader::load_resource::<crate::PolicyResource>(
-            policy_uri,
-            &self.oci_client,
-            &self.http_client,
-        )
-        .await?;
+        let downloaded_policy = self
+            .load_resource::<crate::PolicyResource>(policy_uri)
+            .await?;
 
-        let policy = PolicyParser::parse_file(downloaded_policy.as_ref())?;
+        self.install_policy(component_id, downloaded_policy.as_ref(), policy_uri)
+            .await?;
+
+        info!(component_id, policy_uri, "Policy attached successfully");
+        Ok(())
+    }
+
+    /// Copies a policy file next to the component, records where it came from and applies it
+    pub(crate) async fn install_policy(
+        &self,
+        component_id: &str,
+        policy_file: &Path,
+        source_uri: &str,
+    ) -> Result<()> {
+        let mut policy = PolicyParser::parse_file(policy_file)?;
+        warn_deprecated(component_id, &policy);
+        // Bases are files on this host, which only a policy that is itself a local file may name
+        if !policy.extends.is_empty() && !source_uri.starts_with("file://") {
+            bail!("Policies loaded from {source_uri} can't extend other policies");
+        }
+        // The bases are read from where they are whenever the policy is applied, so relative
+        // paths are made absolute before the policy is stored next to the component
+        policy.absolutize_extends(policy_file.parent().unwrap_or(Path::new(".")));
+        let resolved = resolve_extends(policy.clone(), policy_file)?;
 
         let policy_path = self.get_component_policy_path(component_id);
-        tokio::fs::copy(downloaded_policy.as_ref(), &policy_path).await?;
+        if policy.extends.is_empty() {
+            tokio::fs::copy(policy_file, &policy_path).await?;
+        } else {
+            tokio::fs::write(&policy_path, serde_yaml::to_string(&policy)?).await?;
+        }
 
         // Store metadata about the policy source
         let metadata = serde_json::json!({
-            "source_uri": policy_uri,
+            "source_uri": source_uri,
             "attached_at": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
         });
         let metadata_path = self.get_component_metadata_path(component_id);
         tokio::fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?).await?;
 
         let wasi_template =
-            crate::create_wasi_state_template_from_policy(&policy, &self.plugin_dir)?;
+            crate::create_wasi_state_template_from_policy(&resolved, &self.plugin_dir)?;
         self.policy_registry
             .write()
             .await
-            .component_policies
-            .insert(component_id.to_string(), Arc::new(wasi_template));
-
-        info!(component_id, policy_uri, "Policy attached successfully");
+            .insert(component_id.to_string(), wasi_template);
+        self.emit(LifecycleEvent::PolicyChanged {
+            component_id: component_id.to_string(),
+        });
         Ok(())
     }
 
@@ -130,6 +215,9 @@ impl crate::LifecycleManager {
         self.cleanup_policy_registry(component_id).await;
 
         info!(component_id, "Policy detached successfully");
+        self.emit(LifecycleEvent::PolicyChanged {
+            component_id: component_id.to_string(),
+        });
         Ok(())
     }
 
@@ -174,6 +262,24 @@ impl crate::LifecycleManager {
         })
     }
 
+    /// Returns the permissions that the policy attached to a component grants, with the policies
+    /// it extends merged in. Returns `None` if no policy is attached to the component.
+    pub async fn get_policy_permissions(
+        &self,
+        component_id: &str,
+    ) -> Result<Option<policy::Permissions>> {
+        let policy_path = self.get_component_policy_path(component_id);
+        let policy_content = match tokio::fs::read(&policy_path).await {
+            Ok(policy_content) => policy_content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read policy file"),
+        };
+        let policy = crate::parse_policy(&policy_content).context("Failed to parse policy file")?;
+        let mut policy = resolve_extends(policy, &policy_path)?;
+        policy.migrate();
+        Ok(Some(policy.permissions))
+    }
+
     pub(crate) fn get_component_policy_path(&self, component_id: &str) -> PathBuf {
         self.plugin_dir.join(format!("{component_id}.policy.yaml"))
     }
@@ -187,13 +293,39 @@ impl crate::LifecycleManager {
         Arc::new(WasiStateTemplate::default())
     }
 
-    /// Helper function to clean up policy registry for a component
-    pub(crate) async fn cleanup_policy_registry(&self, component_id: &str) {
-        self.policy_registry
-            .write()
+    /// Applies the policy file next to a component if no policy is attached to it, e.g. one that
+    /// was kept when the component was unloaded
+    pub(crate) async fn restore_policy(&self, component_id: &str) {
+        if self
+            .policy_registry
+            .read()
             .await
             .component_policies
-            .remove(component_id);
+            .contains_key(component_id)
+        {
+            return;
+        }
+        match read_policy_template(&self.plugin_dir, component_id).await {
+            Ok(Some(wasi_template)) => {
+                self.policy_registry
+                    .write()
+                    .await
+                    .insert(component_id.to_string(), wasi_template);
+                info!(
+                    component_id,
+                    "Restored policy association from co-located file"
+                );
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!(component_id, error = %e, "Failed to restore co-located policy");
+            }
+        }
+    }
+
+    /// Helper function to clean up policy registry for a component
+    pub(crate) async fn cleanup_policy_registry(&self, component_id: &str) {
+        self.policy_registry.write().await.remove(component_id);
     }
 
     /// Grant a specific permission rule to a component
@@ -208,7 +340,7 @@ impl crate::LifecycleManager {
             component_id,
             permission_type, "Granting permission to component"
         );
-        if !self.components.read().await.contains_key(component_id) {
+        if !self.has_component(component_id).await {
             return Err(anyhow!("Component not found: {}", component_id));
         }
 
@@ -226,6 +358,61 @@ impl crate::LifecycleManager {
         Ok(())
     }
 
+    /// Returns the settings in the `config` section of a component's policy, which it reads
+    /// through `wasi:config`
+    pub async fn get_component_config(
+        &self,
+        component_id: &str,
+    ) -> Result<BTreeMap<String, String>> {
+        if !self.has_component(component_id).await {
+            return Err(anyhow!("Component not found: {}", component_id));
+        }
+        Ok(self
+            .policy_registry
+            .read()
+            .await
+            .component_policies
+            .get(component_id)
+            .map(|template| template.component_config.clone())
+            .unwrap_or_default())
+    }
+
+    /// Sets a setting in the `config` section of a component's policy, or removes it if `value`
+    /// is `None`. The component reads the new value from its next call on.
+    #[instrument(skip(self, value))]
+    pub async fn set_component_config(
+        &self,
+        component_id: &str,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<()> {
+        if !self.has_component(component_id).await {
+            return Err(anyhow!("Component not found: {}", component_id));
+        }
+
+        let mut policy = self.load_or_create_component_policy(component_id).await?;
+        // Older formats have no `config` section
+        policy.migrate();
+        let config = policy.permissions.config.get_or_insert_with(BTreeMap::new);
+        match value {
+            Some(value) => {
+                config.insert(key.to_string(), value.to_string());
+            }
+            None => {
+                config.remove(key);
+            }
+        }
+        if config.is_empty() {
+            policy.permissions.config = None;
+        }
+        policy.permissions.validate()?;
+        self.save_component_policy(component_id, &policy).await?;
+        self.update_policy_registry(component_id, &policy).await?;
+
+        info!(component_id, key, "Component config updated");
+        Ok(())
+    }
+
     /// Parse a permission rule from the request details
     fn parse_permission_rule(
         &self,
@@ -293,15 +480,16 @@ impl crate::LifecycleManager {
         let policy_path = self.get_component_policy_path(component_id);
 
         if policy_path.exists() {
-            let policy_content = tokio::fs::read_to_string(&policy_path).await?;
-            Ok(PolicyParser::parse_str(&policy_content)?)
+            let policy_content = tokio::fs::read(&policy_path).await?;
+            crate::parse_policy(&policy_content)
         } else {
             // Create minimal policy document
             Ok(policy::PolicyDocument {
-                version: "1.0".to_string(),
+                version: policy::CURRENT_VERSION.to_string(),
                 description: Some(format!(
                     "Auto-generated policy for component: {component_id}"
                 )),
+                extends: Vec::new(),
                 permissions: Default::default(),
             })
         }
@@ -420,23 +608,29 @@ impl crate::LifecycleManager {
         component_id: &str,
         policy: &PolicyDocument,
     ) -> Result<()> {
+        let policy = resolve_extends(
+            policy.clone(),
+            &self.get_component_policy_path(component_id),
+        )?;
         let wasi_template =
-            crate::create_wasi_state_template_from_policy(policy, &self.plugin_dir)?;
+            crate::create_wasi_state_template_from_policy(&policy, &self.plugin_dir)?;
         self.policy_registry
             .write()
             .await
-            .component_policies
-            .insert(component_id.to_string(), Arc::new(wasi_template));
+            .insert(component_id.to_string(), wasi_template);
+        self.emit(LifecycleEvent::PolicyChanged {
+            component_id: component_id.to_string(),
+        });
         Ok(())
     }
 
     /// Validate permission rule
     fn validate_permission_rule(&self, rule: &PermissionRule) -> Result<()> {
         match rule {
-            PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission { host })) => {
-                if host.is_empty() {
-                    return Err(anyhow!("Network host cannot be empty"));
-                }
+            PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission { host }))
+                if host.is_empty() =>
+            {
+                return Err(anyhow!("Network host cannot be empty"));
             }
             PermissionRule::Storage(storage) => {
                 // TODO: the validation should verify if the uri is actually valid or not
@@ -447,10 +641,8 @@ impl crate::LifecycleManager {
                     return Err(anyhow!("Storage access cannot be empty"));
                 }
             }
-            PermissionRule::Environment(env) => {
-                if env.key.is_empty() {
-                    return Err(anyhow!("Environment variable key cannot be empty"));
-                }
+            PermissionRule::Environment(env) if env.key.is_empty() => {
+                return Err(anyhow!("Environment variable key cannot be empty"));
             }
             _ => {}
         }
@@ -463,6 +655,17 @@ mod tests {
     use super::*;
     use crate::tests::*;
 
+    #[test]
+    fn test_policy_changes_advance_the_generation() {
+        let mut registry = PolicyRegistry::default();
+        registry.insert("local/fetch".to_string(), WasiStateTemplate::default());
+        assert_eq!(registry.generation, 1);
+        assert!(!registry.remove("local/time"));
+        assert_eq!(registry.generation, 1);
+        assert!(registry.remove("local/fetch"));
+        assert_eq!(registry.generation, 2);
+    }
+
     #[tokio::test]
     async fn test_policy_attachment_and_detachment() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -514,6 +717,66 @@ permissions:
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_policy_extending_a_base() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let policies = tempfile::tempdir()?;
+        tokio::fs::write(
+            policies.path().join("org.yaml"),
+            "version: \"1.6\"\npermissions:\n  network:\n    allow:\n      - host: \"org.example.com\"\n",
+        )
+        .await?;
+        let policy_path = policies.path().join("component.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.6\"\nextends: [org.yaml]\npermissions: {}\n",
+        )
+        .await?;
+
+        manager
+            .attach_policy(
+                TEST_COMPONENT_ID,
+                &format!("file://{}", policy_path.display()),
+            )
+            .await?;
+        let allowed_hosts = || async {
+            manager.policy_registry.read().await.component_policies[TEST_COMPONENT_ID]
+                .allowed_hosts
+                .clone()
+        };
+        assert!(allowed_hosts().await.contains("org.example.com"));
+        // The stored policy names the base by its absolute path
+        let stored =
+            tokio::fs::read_to_string(manager.get_component_policy_path(TEST_COMPONENT_ID)).await?;
+        assert!(
+            stored.contains(&*policies.path().to_string_lossy()),
+            "{stored}"
+        );
+
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &serde_json::json!({"host": "api.example.com"}),
+            )
+            .await?;
+        let hosts = allowed_hosts().await;
+        assert!(hosts.contains("org.example.com") && hosts.contains("api.example.com"));
+
+        // A downloaded policy can't name files on this host
+        let err = manager
+            .install_policy(
+                TEST_COMPONENT_ID,
+                &policy_path,
+                "https://example.com/component.yaml",
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("can't extend"), "{err}");
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_policy_attachment_component_not_found() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -836,6 +1099,46 @@ permissions: {}
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_set_component_config() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        manager
+            .set_component_config(TEST_COMPONENT_ID, "beta-search", Some("on"))
+            .await?;
+        manager
+            .set_component_config(TEST_COMPONENT_ID, "LOG_LEVEL", Some("debug"))
+            .await?;
+        let config = manager.get_component_config(TEST_COMPONENT_ID).await?;
+        assert_eq!(config["beta-search"], "on");
+        assert_eq!(config["LOG_LEVEL"], "debug");
+
+        let policy_path = manager.get_component_policy_path(TEST_COMPONENT_ID);
+        let policy = crate::parse_policy(&tokio::fs::read(&policy_path).await?)?;
+        assert_eq!(policy.version, policy::CURRENT_VERSION);
+        assert_eq!(policy.permissions.config.unwrap().len(), 2);
+
+        manager
+            .set_component_config(TEST_COMPONENT_ID, "beta-search", None)
+            .await?;
+        manager
+            .set_component_config(TEST_COMPONENT_ID, "LOG_LEVEL", None)
+            .await?;
+        assert!(manager
+            .get_component_config(TEST_COMPONENT_ID)
+            .await?
+            .is_empty());
+
+        // Keys wassette sets itself are refused
+        assert!(manager
+            .set_component_config(TEST_COMPONENT_ID, "WASSETTE_SESSION_ID", Some("x"))
+            .await
+            .is_err());
+        assert!(manager.get_component_config("non-existent").await.is_err());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_grant_permission_updates_policy_registry() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -849,7 +1152,7 @@ permissions: {}
 
         // Verify policy registry was updated by attempting to get WASI state
         let _wasi_state = manager
-            .get_wasi_state_for_component(TEST_COMPONENT_ID)
+            .get_wasi_state_for_component(TEST_COMPONENT_ID, &crate::CallState::default())
             .await?;
 
         // If we get here without error, the policy registry was updated successfully
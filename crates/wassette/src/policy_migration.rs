@@ -0,0 +1,164 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Rewriting policy files in the current policy format, for `wassette policy migrate`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use policy::{PolicyDocument, PolicyParser, CURRENT_VERSION};
+use serde::Serialize;
+use tracing::{info, instrument};
+
+use crate::component_id;
+
+/// What migrating a single policy file did, or would do on a dry run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyMigration {
+    /// Path of the policy file
+    pub path: PathBuf,
+    /// Format version of the file before the migration
+    pub from_version: String,
+    /// Format version of the file after the migration
+    pub to_version: String,
+    /// Whether the file was, or on a dry run would be, rewritten
+    pub migrated: bool,
+    /// Deprecated settings that remain after the migration and need to be changed by hand
+    pub warnings: Vec<String>,
+}
+
+/// Upgrades policy files to the current policy format in place. With no `paths`, the policies of
+/// every component in the plugin directory are migrated. On a dry run nothing is written.
+///
+/// Where only the version changes, only the `version` line of the file is rewritten, so that its
+/// comments and formatting stay. Otherwise the file is written anew from the migrated document.
+#[instrument(skip(paths))]
+pub async fn migrate_policies(
+    plugin_dir: &Path,
+    paths: &[PathBuf],
+    dry_run: bool,
+) -> Result<Vec<PolicyMigration>> {
+    let paths = if paths.is_empty() {
+        let mut paths = Vec::new();
+        for (id, _) in component_id::component_files(plugin_dir).await? {
+            let path = plugin_dir.join(format!("{id}.policy.yaml"));
+            if path.exists() {
+                paths.push(path);
+            }
+        }
+        paths
+    } else {
+        paths.to_vec()
+    };
+
+    let mut migrations = Vec::with_capacity(paths.len());
+    for path in paths {
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let original = PolicyParser::parse_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let mut document = original.clone();
+        let migrated = document.migrate();
+        if migrated && !dry_run {
+            let rewritten = rewrite(&content, &original, &document)?;
+            tokio::fs::write(&path, rewritten)
+                .await
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            info!(path = %path.display(), from = %original.version, to = CURRENT_VERSION, "Migrated policy");
+        }
+        migrations.push(PolicyMigration {
+            path,
+            from_version: original.version,
+            to_version: document.version.clone(),
+            migrated,
+            warnings: document
+                .warnings()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        });
+    }
+    Ok(migrations)
+}
+
+/// Returns the text of the migrated document, keeping the original text apart from its version
+/// line if that is all that changed
+fn rewrite(content: &str, original: &PolicyDocument, migrated: &PolicyDocument) -> Result<String> {
+    let version_only = PolicyDocument {
+        version: migrated.version.clone(),
+        ..original.clone()
+    } == *migrated;
+    if version_only {
+        if let Some(rewritten) = replace_version_line(content, &migrated.version) {
+            if PolicyParser::parse_str(&rewritten).ok().as_ref() == Some(migrated) {
+                return Ok(rewritten);
+            }
+        }
+    }
+    PolicyParser::to_yaml(migrated)
+}
+
+/// Replaces the top-level `version:` line, if there is exactly one
+fn replace_version_line(content: &str, version: &str) -> Option<String> {
+    let is_version_line = |line: &&str| line.starts_with("version:");
+    if content.lines().filter(is_version_line).count() != 1 {
+        return None;
+    }
+    let mut rewritten = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        if is_version_line(&line) {
+            rewritten.push_str(&format!("version: \"{version}\""));
+            if line.ends_with('\n') {
+                rewritten.push('\n');
+            }
+        } else {
+            rewritten.push_str(line);
+        }
+    }
+    Some(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD_POLICY: &str = r#"# Policy of the fetch component
+version: "1.0"
+description: "Fetch"
+permissions:
+  network:
+    allow:
+      - host: "api.example.com" # the only API it needs
+"#;
+
+    #[tokio::test]
+    async fn test_migrate_policies_keeps_comments() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        tokio::fs::create_dir(dir.path().join("local")).await?;
+        tokio::fs::write(dir.path().join("local/fetch.wasm"), b"component").await?;
+        let path = dir.path().join("local/fetch.policy.yaml");
+        tokio::fs::write(&path, OLD_POLICY).await?;
+
+        let dry_run = migrate_policies(dir.path(), &[], true).await?;
+        assert_eq!(dry_run.len(), 1);
+        assert!(dry_run[0].migrated);
+        assert_eq!(tokio::fs::read_to_string(&path).await?, OLD_POLICY);
+
+        let migrations = migrate_policies(dir.path(), &[], false).await?;
+        assert_eq!(migrations[0].from_version, "1.0");
+        assert_eq!(migrations[0].to_version, CURRENT_VERSION);
+        let rewritten = tokio::fs::read_to_string(&path).await?;
+        assert_eq!(
+            rewritten,
+            OLD_POLICY.replace(
+                "version: \"1.0\"",
+                &format!("version: \"{CURRENT_VERSION}\"")
+            )
+        );
+
+        let again = migrate_policies(dir.path(), &[path], false).await?;
+        assert!(!again[0].migrated);
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Ahead-of-time compilation of components, for hosts where compiling at startup is too slow or
+//! not allowed.
+//!
+//! [`precompile_component`] compiles an installed component to native code for a target, which
+//! need not be the one wassette runs on, with the same engine settings the server uses. The
+//! artifact is named after the digest of the component it was compiled from. Copied into the
+//! [`PRECOMPILED_DIR`] of a plugin directory of a server with
+//! [`load_precompiled`](crate::LifecycleConfig::load_precompiled) set, it is loaded instead of
+//! compiling that component, as long as it was compiled by the same version of wassette for the
+//! host's target. Otherwise the component is compiled as usual, unless
+//! [`require_precompiled`](crate::LifecycleConfig::require_precompiled) is set.
+//!
+//! Artifacts are native code that wasmtime deserializes without validating it, so they are only
+//! loaded when the operator opts in. Without either setting, the directory isn't even looked at.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use tracing::{debug, instrument, warn};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+use crate::status::CacheCounters;
+use crate::{adapter, component_id, compression, LifecycleConfig};
+
+/// Directory in the plugin directory holding precompiled components
+pub const PRECOMPILED_DIR: &str = "precompiled";
+
+/// Extension of precompiled components
+pub const PRECOMPILED_EXTENSION: &str = "cwasm";
+
+/// Short names of common targets, and the triples they stand for
+const TARGET_ALIASES: &[(&str, &str)] = &[
+    ("x86_64-linux", "x86_64-unknown-linux-gnu"),
+    ("aarch64-linux", "aarch64-unknown-linux-gnu"),
+    ("x86_64-macos", "x86_64-apple-darwin"),
+    ("aarch64-macos", "aarch64-apple-darwin"),
+    ("x86_64-windows", "x86_64-pc-windows-msvc"),
+];
+
+/// Whether components are loaded from their precompiled form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PrecompiledArtifacts {
+    /// Always compile components
+    Ignore,
+    /// Load a precompiled form if there is one, and compile the component otherwise
+    Load,
+    /// Only load precompiled forms, and never compile a component
+    Require,
+}
+
+impl PrecompiledArtifacts {
+    pub(crate) fn from_config(config: &LifecycleConfig) -> Self {
+        if config.require_precompiled {
+            Self::Require
+        } else if config.load_precompiled {
+            Self::Load
+        } else {
+            Self::Ignore
+        }
+    }
+}
+
+/// A component compiled ahead of time
+#[derive(Debug, Clone)]
+pub struct PrecompiledComponent {
+    /// ID of the component
+    pub component_id: String,
+    /// Digest of the component binary, which names the artifact
+    pub digest: String,
+    /// Target triple the component was compiled for, or `None` for the host
+    pub target: Option<String>,
+    /// The serialized native code
+    pub artifact: Vec<u8>,
+}
+
+impl PrecompiledComponent {
+    /// Returns the file name the artifact is looked up by in [`PRECOMPILED_DIR`]
+    pub fn file_name(&self) -> String {
+        format!("{}.{PRECOMPILED_EXTENSION}", self.digest)
+    }
+}
+
+/// Returns the path that the precompiled form of the component with `digest` is loaded from
+pub fn precompiled_path(plugin_dir: &Path, digest: &str) -> PathBuf {
+    plugin_dir
+        .join(PRECOMPILED_DIR)
+        .join(format!("{digest}.{PRECOMPILED_EXTENSION}"))
+}
+
+/// Returns the target triple a target name stands for: one of the short names such as
+/// `x86_64-linux`, or a full triple
+pub fn target_triple(target: &str) -> &str {
+    TARGET_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == target)
+        .map_or(target, |(_, triple)| triple)
+}
+
+/// Compiles the installed component `id` for `target`, or for the host if it is `None`, with the
+/// engine settings of `config`. `id` may also be the name of a single installed component.
+#[instrument(skip(config))]
+pub async fn precompile_component(
+    plugin_dir: &Path,
+    id: &str,
+    target: Option<&str>,
+    config: &LifecycleConfig,
+) -> Result<PrecompiledComponent> {
+    let files = component_id::component_files(plugin_dir).await?;
+    let ids: Vec<String> = files.iter().map(|(id, _)| id.clone()).collect();
+    let component_id = component_id::resolve_name(&ids, id)?;
+    let Some((_, path)) = files.into_iter().find(|(id, _)| *id == component_id) else {
+        bail!("Component not found: {component_id}");
+    };
+    let wasm_bytes = compression::read_component(&path)
+        .await
+        .context("Failed to read component file")?;
+    let digest = crate::component_digest(&wasm_bytes);
+    let wasm_bytes = adapter::adapt_if_module(wasm_bytes)?.wasm;
+
+    let mut engine_config = crate::engine_config(config);
+    let target = target.map(target_triple);
+    if let Some(target) = target {
+        engine_config
+            .target(target)
+            .with_context(|| format!("Unsupported target: {target}"))?;
+    }
+    let engine = Engine::new(&engine_config).context("Failed to create the engine")?;
+    let artifact =
+        tokio::task::spawn_blocking(move || engine.precompile_component(&wasm_bytes)).await??;
+    Ok(PrecompiledComponent {
+        component_id,
+        digest,
+        target: target.map(String::from),
+        artifact,
+    })
+}
+
+/// Returns the component compiled from `wasm_bytes`, deserialized from its precompiled form in
+/// `plugin_dir` if `artifacts` allows it and there is one that `engine` can run. Fails without
+/// compiling the component if precompiled forms are required and there is none.
+pub(crate) fn load_component(
+    engine: &Engine,
+    plugin_dir: &Path,
+    digest: &str,
+    wasm_bytes: Vec<u8>,
+    artifacts: PrecompiledArtifacts,
+    cache: &CacheCounters,
+) -> Result<Component> {
+    if artifacts == PrecompiledArtifacts::Ignore {
+        cache.record(false);
+        return Component::new(engine, wasm_bytes);
+    }
+    let require_precompiled = artifacts == PrecompiledArtifacts::Require;
+    let path = precompiled_path(plugin_dir, digest);
+    if path.exists() {
+        // SAFETY: the operator opted in to loading artifacts from the plugin directory, which is
+        // then trusted like the components in it. Wasmtime checks that it was compiled for this
+        // engine's target and settings, and fails otherwise.
+        match unsafe { Component::deserialize_file(engine, &path) } {
+            Ok(component) => {
+                debug!(digest, "Loaded precompiled component");
+                cache.record(true);
+                return Ok(component);
+            }
+            Err(e) if require_precompiled => {
+                return Err(e).with_context(|| {
+                    format!("Failed to load precompiled component {}", path.display())
+                });
+            }
+            Err(e) => {
+                warn!(
+                    path = %path.display(),
+                    error = %e,
+                    "Ignoring precompiled component that can't be loaded"
+                );
+            }
+        }
+    } else if require_precompiled {
+        bail!(
+            "No precompiled component at {}, and components may not be compiled on this host",
+            path.display()
+        );
+    }
+    cache.record(false);
+    Component::new(engine, wasm_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_triple() {
+        assert_eq!(target_triple("x86_64-linux"), "x86_64-unknown-linux-gnu");
+        assert_eq!(target_triple("aarch64-macos"), "aarch64-apple-darwin");
+        assert_eq!(
+            target_triple("riscv64gc-unknown-linux-gnu"),
+            "riscv64gc-unknown-linux-gnu"
+        );
+    }
+
+    #[test]
+    fn test_require_precompiled() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let engine = crate::create_engine(&LifecycleConfig::default()).unwrap();
+        let Err(error) = load_component(
+            &engine,
+            tempdir.path(),
+            "abc",
+            Vec::new(),
+            PrecompiledArtifacts::Require,
+            &CacheCounters::default(),
+        ) else {
+            panic!("component compiled although precompiled components are required");
+        };
+        let error = error.to_string();
+        assert!(
+            error.contains("may not be compiled on this host"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_precompiled_artifacts_are_opt_in() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let engine = crate::create_engine(&LifecycleConfig::default()).unwrap();
+        let wasm_bytes = wat::parse_str("(component)").unwrap();
+        let digest = crate::component_digest(&wasm_bytes);
+        let path = precompiled_path(tempdir.path(), &digest);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"not native code").unwrap();
+        let cache = CacheCounters::default();
+
+        // The artifact isn't touched unless the operator opted in
+        assert_eq!(
+            PrecompiledArtifacts::from_config(&LifecycleConfig::default()),
+            PrecompiledArtifacts::Ignore
+        );
+        load_component(
+            &engine,
+            tempdir.path(),
+            &digest,
+            wasm_bytes.clone(),
+            PrecompiledArtifacts::Ignore,
+            &cache,
+        )
+        .unwrap();
+
+        let config = LifecycleConfig {
+            require_precompiled: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            PrecompiledArtifacts::from_config(&config),
+            PrecompiledArtifacts::Require
+        );
+        let Err(error) = load_component(
+            &engine,
+            tempdir.path(),
+            &digest,
+            wasm_bytes,
+            PrecompiledArtifacts::Require,
+            &cache,
+        ) else {
+            panic!("invalid precompiled component was loaded");
+        };
+        assert!(
+            format!("{error:#}").contains("Failed to load precompiled component"),
+            "{error:#}"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Opt-in guest profiling of tool calls.
+//!
+//! A profiled call samples the guest stack on every epoch tick and at each transition between the
+//! guest and the host. The profile is written in the Firefox profiler format, which
+//! <https://profiler.firefox.com> shows as a flame graph.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tracing::debug;
+use wasmtime::component::Component;
+use wasmtime::{Engine, GuestProfiler, Store, StoreContextMut, UpdateDeadline};
+
+use crate::http::WassetteWasiState;
+use crate::wasistate::WasiState;
+
+/// The argument that requests a profile of a single tool call, e.g. `{"$profile": true}`. It is
+/// removed from the arguments before they are passed to the component.
+pub const PROFILE_ARGUMENT_KEY: &str = "$profile";
+
+/// Directory in the plugin directory that profiles are written to
+pub(crate) const PROFILES_DIR: &str = "profiles";
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+
+type WassetteStore = Store<WassetteWasiState<WasiState>>;
+
+/// Increments the engine's epoch at the sample interval until dropped
+pub(crate) struct EpochTicker {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EpochTicker {
+    fn start(engine: Engine) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(SAMPLE_INTERVAL);
+                    engine.increment_epoch();
+                }
+            }
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for EpochTicker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Runs `f` with the store's profiler, if it has one
+fn with_profiler(
+    mut ctx: StoreContextMut<'_, WassetteWasiState<WasiState>>,
+    f: impl FnOnce(&mut GuestProfiler, &StoreContextMut<'_, WassetteWasiState<WasiState>>),
+) {
+    // The profiler needs to read the store while it is borrowed, so take it out temporarily
+    if let Some(mut profiler) = ctx.data_mut().inner.profiler.take() {
+        f(&mut profiler, &ctx);
+        ctx.data_mut().inner.profiler = Some(profiler);
+    }
+}
+
+/// Attaches a guest profiler to the store. Samples are taken until the returned ticker is dropped.
+pub(crate) fn start_profiling(
+    store: &mut WassetteStore,
+    component_id: &str,
+    component: &Component,
+) -> EpochTicker {
+    let profiler = GuestProfiler::new_component(
+        component_id,
+        SAMPLE_INTERVAL,
+        component.clone(),
+        std::iter::empty(),
+    );
+    store.data_mut().inner.profiler = Some(profiler);
+    store.call_hook(|ctx, kind| {
+        with_profiler(ctx, |profiler, ctx| profiler.call_hook(ctx, kind));
+        Ok(())
+    });
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(|ctx| {
+        with_profiler(ctx, |profiler, ctx| profiler.sample(ctx, Duration::ZERO));
+        Ok(UpdateDeadline::Continue(1))
+    });
+    EpochTicker::start(store.engine().clone())
+}
+
+/// Writes the profile collected by the store's profiler to `path`
+pub(crate) async fn finish_profiling(store: &mut WassetteStore, path: &Path) -> Result<()> {
+    let profiler = store
+        .data_mut()
+        .inner
+        .profiler
+        .take()
+        .context("Profiling was not started for this call")?;
+    let mut profile = Vec::new();
+    profiler
+        .finish(&mut profile)
+        .context("Failed to collect profile")?;
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .context("Failed to create profiles directory")?;
+    }
+    tokio::fs::write(path, profile)
+        .await
+        .with_context(|| format!("Failed to write profile: {}", path.display()))?;
+    debug!(path = %path.display(), "Wrote guest profile");
+    Ok(())
+}
+
+/// Returns the path of a new profile of a call to the given tool
+pub(crate) fn profile_path(plugin_dir: &Path, component_id: &str, tool_name: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    plugin_dir
+        .join(PROFILES_DIR)
+        .join(component_id)
+        .join(format!("{tool_name}-{timestamp}.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_path() {
+        let path = profile_path(Path::new("/plugins"), "local/fetch_rs", "fetch");
+        assert!(path.starts_with("/plugins/profiles/local/fetch_rs"));
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        assert!(file_name.starts_with("fetch-"));
+        assert!(file_name.ends_with(".json"));
+    }
+}
@@ -0,0 +1,574 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Desired-state provisioning of components from a manifest, written as a list of components or
+//! as Kubernetes-style `Component` and `Policy` resources.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use policy::{PolicyDocument, PolicyParser};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{compression, Labels, LifecycleManager, LoadResult};
+
+/// `apiVersion` of the Kubernetes-style resources a manifest can be written as
+pub const RESOURCE_API_VERSION: &str = "wassette.microsoft.com/v1alpha1";
+
+/// Source recorded for policies given inline rather than by URI
+const INLINE_POLICY_SOURCE: &str = "inline";
+
+/// The components a server should run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProvisionManifest {
+    /// Components to install, in order
+    #[serde(default)]
+    pub components: Vec<ProvisionedComponent>,
+    /// Remove installed components that aren't listed
+    #[serde(default)]
+    pub prune: bool,
+}
+
+/// A component listed in a [`ProvisionManifest`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProvisionedComponent {
+    /// URI to load the component from, as accepted by `load-component`
+    pub uri: String,
+    /// Expected SHA-256 digest of the component, with or without a `sha256:` prefix. A pinned
+    /// component that is already installed isn't downloaded again, and one that doesn't match is
+    /// not kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// URI of the policy to attach to the component
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    /// Policy to attach to the component, given inline instead of by URI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline_policy: Option<PolicyDocument>,
+    /// Labels to set on the component. Labels it has but that aren't listed are kept.
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+}
+
+impl ProvisionManifest {
+    /// Reads and validates a manifest from a YAML file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read provisioning manifest {}", path.display()))?;
+        Self::parse(&data)
+            .with_context(|| format!("Invalid provisioning manifest {}", path.display()))
+    }
+
+    /// Parses and validates a manifest, written either as a single document listing the
+    /// components or as Kubernetes-style resources. Digests are normalized to lowercase hex
+    /// without prefix.
+    pub fn parse(data: &str) -> Result<Self> {
+        let documents = serde_yaml::Deserializer::from_str(data)
+            .map(serde_yaml::Value::deserialize)
+            .collect::<Result<Vec<_>, _>>()?;
+        let is_resource = |document: &serde_yaml::Value| document.get("apiVersion").is_some();
+        let mut manifest: Self = match &documents[..] {
+            [document] if !is_resource(document) => serde_yaml::from_value(document.clone())?,
+            _ if documents.iter().any(is_resource) => from_resources(documents)?,
+            [] => Self::default(),
+            _ => bail!("A manifest that isn't made of resources must be a single YAML document"),
+        };
+        let mut uris = HashSet::new();
+        for component in &mut manifest.components {
+            component.uri = component.uri.trim().to_string();
+            if component.uri.is_empty() {
+                bail!("Component URIs must not be empty");
+            }
+            if !uris.insert(component.uri.clone()) {
+                bail!("Component {} is listed more than once", component.uri);
+            }
+            if component.policy.is_some() && component.inline_policy.is_some() {
+                bail!(
+                    "Component {} has both a policy URI and an inline policy",
+                    component.uri
+                );
+            }
+            if let Some(policy) = &component.inline_policy {
+                policy
+                    .validate()
+                    .with_context(|| format!("Invalid policy for component {}", component.uri))?;
+            }
+            if let Some(digest) = &mut component.digest {
+                let hex = digest.trim();
+                let hex = hex
+                    .strip_prefix("sha256:")
+                    .unwrap_or(hex)
+                    .to_ascii_lowercase();
+                if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    bail!(
+                        "Invalid digest for component {}: expected a SHA-256 digest",
+                        component.uri
+                    );
+                }
+                *digest = hex;
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// Metadata of a Kubernetes-style resource
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ResourceMetadata {
+    name: String,
+    #[serde(default)]
+    labels: Labels,
+    /// Accepted so that resources exported from a cluster parse, but not used
+    #[serde(default)]
+    #[allow(dead_code)]
+    annotations: BTreeMap<String, String>,
+}
+
+/// Spec of a `Component` resource
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ComponentSpec {
+    uri: String,
+    #[serde(default)]
+    digest: Option<String>,
+    /// URI of the policy
+    #[serde(default)]
+    policy: Option<String>,
+    /// Name of a `Policy` resource in the same manifest
+    #[serde(default)]
+    policy_ref: Option<String>,
+}
+
+/// A Kubernetes-style resource, whose `spec` depends on its `kind`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Resource {
+    api_version: String,
+    kind: String,
+    metadata: ResourceMetadata,
+    spec: serde_yaml::Value,
+}
+
+/// Builds a manifest from `Component` and `Policy` resources. Components are provisioned in the
+/// order they are listed, and never pruned.
+fn from_resources(documents: Vec<serde_yaml::Value>) -> Result<ProvisionManifest> {
+    let mut components = Vec::new();
+    let mut policies = HashMap::new();
+    for (i, document) in documents.into_iter().enumerate() {
+        let resource: Resource = serde_yaml::from_value(document)
+            .with_context(|| format!("Invalid resource in YAML document {}", i + 1))?;
+        let name = resource.metadata.name.clone();
+        if resource.api_version != RESOURCE_API_VERSION {
+            bail!(
+                "Resource {name} has apiVersion {}, expected {RESOURCE_API_VERSION}",
+                resource.api_version
+            );
+        }
+        match resource.kind.as_str() {
+            "Component" => {
+                let spec: ComponentSpec = serde_yaml::from_value(resource.spec)
+                    .with_context(|| format!("Invalid spec of component {name}"))?;
+                components.push((resource.metadata, spec));
+            }
+            "Policy" => {
+                let spec: PolicyDocument = serde_yaml::from_value(resource.spec)
+                    .with_context(|| format!("Invalid spec of policy {name}"))?;
+                if policies.insert(name.clone(), spec).is_some() {
+                    bail!("Policy {name} is defined more than once");
+                }
+            }
+            kind => bail!("Resource {name} has unknown kind {kind}, expected Component or Policy"),
+        }
+    }
+
+    let mut names = HashSet::new();
+    let components = components
+        .into_iter()
+        .map(|(metadata, spec)| {
+            if !names.insert(metadata.name.clone()) {
+                bail!("Component {} is defined more than once", metadata.name);
+            }
+            let inline_policy = match spec.policy_ref {
+                Some(name) if spec.policy.is_some() => bail!(
+                    "Component {} has both policy and policyRef {name}",
+                    metadata.name
+                ),
+                Some(name) => Some(policies.get(&name).cloned().with_context(|| {
+                    format!(
+                        "Component {} refers to policy {name}, which isn't defined",
+                        metadata.name
+                    )
+                })?),
+                None => None,
+            };
+            Ok(ProvisionedComponent {
+                uri: spec.uri,
+                digest: spec.digest,
+                policy: spec.policy,
+                inline_policy,
+                labels: metadata.labels,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(ProvisionManifest {
+        components,
+        prune: false,
+    })
+}
+
+/// A component that could not be reconciled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProvisionFailure {
+    /// URI of the component, or its ID when removing it failed
+    pub component: String,
+    /// Why reconciling the component failed
+    pub error: String,
+}
+
+/// What reconciling a manifest changed. Components are listed by ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ProvisionReport {
+    /// Components that weren't installed before
+    pub installed: Vec<String>,
+    /// Components whose binary or policy changed
+    pub updated: Vec<String>,
+    /// Components that already matched the manifest
+    pub unchanged: Vec<String>,
+    /// Components removed because the manifest doesn't list them
+    pub removed: Vec<String>,
+    /// Components that could not be reconciled
+    pub failed: Vec<ProvisionFailure>,
+}
+
+impl ProvisionReport {
+    /// Whether the reconcile changed anything
+    pub fn has_changes(&self) -> bool {
+        !(self.installed.is_empty() && self.updated.is_empty() && self.removed.is_empty())
+    }
+}
+
+impl std::fmt::Display for ProvisionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} installed, {} updated, {} unchanged, {} removed, {} failed",
+            self.installed.len(),
+            self.updated.len(),
+            self.unchanged.len(),
+            self.removed.len(),
+            self.failed.len()
+        )
+    }
+}
+
+enum Outcome {
+    Installed,
+    Updated,
+    Unchanged,
+}
+
+/// Returns the digest of an installed component, which is decompressed if it is stored compressed
+async fn digest(path: PathBuf) -> Option<String> {
+    let wasm_bytes = compression::read_component(&path).await.ok()?;
+    Some(crate::component_digest(&wasm_bytes))
+}
+
+impl LifecycleManager {
+    /// Reconciles the installed components with a manifest. Components are reconciled one at a
+    /// time and failures don't stop the others. Nothing is pruned if any component failed, since
+    /// its ID isn't known then.
+    pub async fn provision(&self, manifest: &ProvisionManifest) -> ProvisionReport {
+        let mut installed = HashMap::new();
+        for id in self.list_components().await {
+            if let Some(digest) = digest(self.component_path(&id)).await {
+                installed.insert(id, digest);
+            }
+        }
+
+        let mut report = ProvisionReport::default();
+        let mut desired = HashSet::new();
+        for component in &manifest.components {
+            match self.provision_component(component, &installed).await {
+                Ok((id, outcome)) => {
+                    match outcome {
+                        Outcome::Installed => report.installed.push(id.clone()),
+                        Outcome::Updated => report.updated.push(id.clone()),
+                        Outcome::Unchanged => report.unchanged.push(id.clone()),
+                    }
+                    desired.insert(id);
+                }
+                Err(e) => {
+                    warn!(uri = %component.uri, error = %e, "Failed to provision component");
+                    report.failed.push(ProvisionFailure {
+                        component: component.uri.clone(),
+                        error: format!("{e:#}"),
+                    });
+                }
+            }
+        }
+
+        if manifest.prune {
+            if report.failed.is_empty() {
+                let mut unlisted: Vec<_> = installed
+                    .keys()
+                    .filter(|id| !desired.contains(*id))
+                    .collect();
+                unlisted.sort();
+                for id in unlisted {
+                    match self.unload_component(id).await {
+                        Ok(_) => report.removed.push(id.clone()),
+                        Err(e) => report.failed.push(ProvisionFailure {
+                            component: id.clone(),
+                            error: format!("{e:#}"),
+                        }),
+                    }
+                }
+            } else {
+                warn!("Not pruning components since some components failed to provision");
+            }
+        }
+
+        info!(summary = %report, "Provisioned components");
+        report
+    }
+
+    async fn provision_component(
+        &self,
+        component: &ProvisionedComponent,
+        installed: &HashMap<String, String>,
+    ) -> Result<(String, Outcome)> {
+        let pinned = component.digest.as_ref().and_then(|expected| {
+            installed
+                .iter()
+                .find(|(_, digest)| *digest == expected)
+                .map(|(id, _)| id.clone())
+        });
+        let (id, mut outcome) = match pinned {
+            Some(id) => (id, Outcome::Unchanged),
+            None => {
+                // The digest is checked before the component is installed, so a mismatch leaves
+                // what is installed alone
+                let (id, result) = self
+                    .load_component_with_digest(&component.uri, component.digest.as_deref())
+                    .await?;
+                let actual = digest(self.component_path(&id))
+                    .await
+                    .context("Failed to compute digest of installed component")?;
+                let outcome = match (result, installed.get(&id)) {
+                    (LoadResult::New, _) | (_, None) => Outcome::Installed,
+                    (LoadResult::Replaced(_), Some(previous)) if *previous == actual => {
+                        Outcome::Unchanged
+                    }
+                    (LoadResult::Replaced(_), Some(_)) => Outcome::Updated,
+                };
+                (id, outcome)
+            }
+        };
+
+        let policy_path = self.get_component_policy_path(&id);
+        let previous = digest(policy_path.clone()).await;
+        if let Some(policy) = &component.policy {
+            self.attach_policy(&id, policy).await?;
+        } else if let Some(policy) = &component.inline_policy {
+            let file = tempfile::NamedTempFile::new()?;
+            PolicyParser::write_file(policy, file.path())?;
+            self.install_policy(&id, file.path(), INLINE_POLICY_SOURCE)
+                .await?;
+        }
+        if matches!(outcome, Outcome::Unchanged) && digest(policy_path).await != previous {
+            outcome = Outcome::Updated;
+        }
+
+        if !component.labels.is_empty() {
+            let previous = self.get_component_labels(&id).await.unwrap_or_default();
+            let labels = self
+                .set_component_labels(&id, &component.labels, &[])
+                .await?;
+            if matches!(outcome, Outcome::Unchanged) && labels != previous {
+                outcome = Outcome::Updated;
+            }
+        }
+        Ok((id, outcome))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::digest_file;
+    use crate::tests::*;
+
+    #[test]
+    fn test_parse_manifest() -> Result<()> {
+        let manifest = ProvisionManifest::parse(
+            r#"
+prune: true
+components:
+  - uri: oci://ghcr.io/microsoft/fetch-rs:latest
+    digest: sha256:0123456789ABCDEF0123456789abcdef0123456789abcdef0123456789abcdef
+    policy: https://example.com/fetch.policy.yaml
+  - uri: " file:///opt/components/time.wasm "
+"#,
+        )?;
+        assert!(manifest.prune);
+        assert_eq!(manifest.components.len(), 2);
+        assert_eq!(
+            manifest.components[0].digest.as_deref(),
+            Some("0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef")
+        );
+        assert_eq!(
+            manifest.components[1].uri,
+            "file:///opt/components/time.wasm"
+        );
+
+        assert!(ProvisionManifest::parse(
+            "components:\n  - uri: file:///a.wasm\n    digest: abc\n"
+        )
+        .is_err());
+        assert!(ProvisionManifest::parse(
+            "components:\n  - uri: file:///a.wasm\n  - uri: file:///a.wasm\n"
+        )
+        .is_err());
+        assert!(ProvisionManifest::parse("components: []\nprun: true\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_resources() -> Result<()> {
+        let manifest = ProvisionManifest::parse(
+            r#"
+apiVersion: wassette.microsoft.com/v1alpha1
+kind: Component
+metadata:
+  name: fetch
+  labels:
+    team: infra
+spec:
+  uri: oci://ghcr.io/microsoft/fetch-rs:latest
+  policyRef: fetch-policy
+---
+apiVersion: wassette.microsoft.com/v1alpha1
+kind: Component
+metadata:
+  name: time
+spec:
+  uri: file:///opt/components/time.wasm
+  policy: https://example.com/time.policy.yaml
+---
+apiVersion: wassette.microsoft.com/v1alpha1
+kind: Policy
+metadata:
+  name: fetch-policy
+spec:
+  version: "1.0"
+  permissions:
+    network:
+      allow:
+        - host: api.example.com
+"#,
+        )?;
+        assert!(!manifest.prune);
+        assert_eq!(manifest.components.len(), 2);
+        let fetch = &manifest.components[0];
+        assert_eq!(fetch.uri, "oci://ghcr.io/microsoft/fetch-rs:latest");
+        assert_eq!(fetch.labels.get("team").map(String::as_str), Some("infra"));
+        let policy = fetch.inline_policy.as_ref().unwrap();
+        assert_eq!(policy.version, "1.0");
+        assert_eq!(
+            manifest.components[1].policy.as_deref(),
+            Some("https://example.com/time.policy.yaml")
+        );
+
+        let component = |spec: &str| {
+            format!(
+                "apiVersion: {RESOURCE_API_VERSION}\nkind: Component\n\
+                 metadata:\n  name: a\nspec:\n{spec}"
+            )
+        };
+        assert!(ProvisionManifest::parse(&component("  uri: file:///a.wasm\n")).is_ok());
+        // Unknown policy, unknown field, wrong API version and unknown kind
+        assert!(ProvisionManifest::parse(&component(
+            "  uri: file:///a.wasm\n  policyRef: missing\n"
+        ))
+        .is_err());
+        assert!(
+            ProvisionManifest::parse(&component("  uri: file:///a.wasm\n  replicas: 2\n")).is_err()
+        );
+        assert!(ProvisionManifest::parse(
+            &component("  uri: file:///a.wasm\n").replace("v1alpha1", "v1")
+        )
+        .is_err());
+        assert!(ProvisionManifest::parse(
+            &component("  uri: file:///a.wasm\n").replace("kind: Component", "kind: Deployment")
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_provision_reconciles_components() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let component_path = build_example_component().await?;
+        let uri = format!("file://{}", component_path.display());
+        let (expected_digest, _) = digest_file(&component_path)?;
+
+        let manifest = ProvisionManifest {
+            components: vec![ProvisionedComponent {
+                uri: uri.clone(),
+                digest: Some(expected_digest.clone()),
+                ..Default::default()
+            }],
+            prune: true,
+        };
+        let report = manager.provision(&manifest).await;
+        assert_eq!(report.installed, vec![TEST_COMPONENT_ID.to_string()]);
+        assert!(report.failed.is_empty());
+
+        // A pinned component that is installed is left alone
+        let report = manager.provision(&manifest).await;
+        assert_eq!(report.unchanged, vec![TEST_COMPONENT_ID.to_string()]);
+        assert!(!report.has_changes());
+
+        // A digest that doesn't match is not installed, the installed component is kept, and
+        // nothing is pruned after a failure
+        let mismatched = ProvisionManifest {
+            components: vec![ProvisionedComponent {
+                uri,
+                digest: Some("0".repeat(64)),
+                ..Default::default()
+            }],
+            prune: true,
+        };
+        let report = manager.provision(&mismatched).await;
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].error.contains("expected sha256:"));
+        assert!(report.removed.is_empty());
+        assert_eq!(
+            manager.list_components().await,
+            vec![TEST_COMPONENT_ID.to_string()]
+        );
+        assert_eq!(
+            digest(manager.component_path(TEST_COMPONENT_ID)).await,
+            Some(expected_digest.clone())
+        );
+
+        manager.provision(&manifest).await;
+        let report = manager.provision(&ProvisionManifest::default()).await;
+        assert!(report.removed.is_empty(), "pruning is opt-in");
+        let report = manager
+            .provision(&ProvisionManifest {
+                components: Vec::new(),
+                prune: true,
+            })
+            .await;
+        assert_eq!(report.removed, vec![TEST_COMPONENT_ID.to_string()]);
+        assert!(manager.list_components().await.is_empty());
+        Ok(())
+    }
+}
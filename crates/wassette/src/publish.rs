@@ -0,0 +1,236 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Events that components publish through `wassette:events/publisher`.
+//!
+//! A component whose policy has an `events` section may publish events to the topics it allows
+//! while it is called, e.g. the findings of a scan as it makes them. Every event is sent as a
+//! [`LifecycleEvent::EventPublished`] to the subscribers of the lifecycle manager and, if the call
+//! was given a [`PublishedEventSink`](crate::PublishedEventSink), to the sink, so that the MCP
+//! server can forward it to the client that made the call. The data of an event is kept as JSON
+//! if it parses as JSON, and as a string otherwise.
+
+use anyhow::Result;
+use policy::EventTopicPermission;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{broadcast, mpsc};
+use wasmtime::component::Linker;
+use wasmtime::StoreContextMut;
+
+use crate::{CorrelationIds, LifecycleEvent, WasiState, WassetteWasiState};
+
+/// The interface components import to publish events
+pub const EVENTS_INTERFACE: &str = "wassette:events/publisher@0.1.0";
+
+/// Largest data of a single event, in bytes
+pub const MAX_EVENT_SIZE: usize = 64 * 1024;
+
+/// Most events a single call may publish
+pub const MAX_EVENTS_PER_CALL: usize = 1000;
+
+/// Longest topic name, in bytes
+const MAX_TOPIC_LENGTH: usize = 256;
+
+/// Receives the events a component publishes during a call
+pub type PublishedEventSink = mpsc::UnboundedSender<PublishedEvent>;
+
+/// An event a component published
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PublishedEvent {
+    /// ID of the component
+    pub component_id: String,
+    /// Topic the event was published to
+    pub topic: String,
+    /// Data of the event
+    pub data: Value,
+}
+
+/// Where the events of a call go, with the topics the call may publish to
+#[derive(Default)]
+pub(crate) struct CallPublisher {
+    component_id: String,
+    topics: Option<Vec<EventTopicPermission>>,
+    events: Option<broadcast::Sender<LifecycleEvent>>,
+    sink: Option<PublishedEventSink>,
+    correlation: CorrelationIds,
+    published: usize,
+}
+
+impl CallPublisher {
+    /// Creates the publisher of a call of `component_id` that may publish to `topics`, or nothing
+    /// if `None`
+    pub(crate) fn new(
+        component_id: &str,
+        topics: Option<&[EventTopicPermission]>,
+        events: broadcast::Sender<LifecycleEvent>,
+        sink: Option<PublishedEventSink>,
+        correlation: CorrelationIds,
+    ) -> Self {
+        Self {
+            component_id: component_id.to_string(),
+            topics: topics.map(<[_]>::to_vec),
+            events: Some(events),
+            sink,
+            correlation,
+            published: 0,
+        }
+    }
+
+    /// Publishes an event, returning the error the component gets if it may not
+    fn publish(&mut self, topic: String, data: String) -> Result<(), String> {
+        let Some(topics) = &self.topics else {
+            return Err("publishing events is not allowed by the component's policy".to_string());
+        };
+        if topic.len() > MAX_TOPIC_LENGTH || !topics.iter().any(|grant| grant.allows(&topic)) {
+            return Err(format!(
+                "publishing to topic '{topic}' is not allowed by the component's policy"
+            ));
+        }
+        if data.len() > MAX_EVENT_SIZE {
+            return Err(format!(
+                "event data is {} bytes, more than the limit of {MAX_EVENT_SIZE}",
+                data.len()
+            ));
+        }
+        if self.published >= MAX_EVENTS_PER_CALL {
+            return Err(format!(
+                "a call may publish at most {MAX_EVENTS_PER_CALL} events"
+            ));
+        }
+        self.published += 1;
+
+        let data = serde_json::from_str(&data).unwrap_or(Value::String(data));
+        let event = PublishedEvent {
+            component_id: self.component_id.clone(),
+            topic,
+            data,
+        };
+        tracing::debug!(
+            component_id = %event.component_id,
+            topic = %event.topic,
+            "Component published an event"
+        );
+        if let Some(sink) = &self.sink {
+            // The receiver is gone once the client stopped waiting for the call
+            let _ = sink.send(event.clone());
+        }
+        if let Some(events) = &self.events {
+            let _ = events.send(LifecycleEvent::EventPublished {
+                component_id: event.component_id,
+                topic: event.topic,
+                data: event.data,
+                request_id: self.correlation.request_id.clone(),
+                session_id: self.correlation.session_id.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Adds `wassette:events/publisher` to the linker
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    linker.instance(EVENTS_INTERFACE)?.func_wrap(
+        "publish",
+        |mut store: StoreContextMut<'_, WassetteWasiState<WasiState>>,
+         (topic, data): (String, String)| {
+            Ok((store.data_mut().inner.publisher.publish(topic, data),))
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publisher(
+        topics: &[&str],
+    ) -> (
+        CallPublisher,
+        mpsc::UnboundedReceiver<PublishedEvent>,
+        broadcast::Receiver<LifecycleEvent>,
+    ) {
+        let topics: Vec<_> = topics
+            .iter()
+            .map(|topic| EventTopicPermission {
+                topic: topic.to_string(),
+            })
+            .collect();
+        let (events, subscriber) = broadcast::channel(16);
+        let (sink, receiver) = mpsc::unbounded_channel();
+        let publisher = CallPublisher::new(
+            "local/scanner",
+            Some(&topics),
+            events,
+            Some(sink),
+            CorrelationIds {
+                request_id: Some("7".to_string()),
+                session_id: None,
+            },
+        );
+        (publisher, receiver, subscriber)
+    }
+
+    #[test]
+    fn test_publish_delivers_events() {
+        let (mut publisher, mut receiver, mut subscriber) = publisher(&["scan.*"]);
+        publisher
+            .publish(
+                "scan.finding".to_string(),
+                r#"{"severity": "high"}"#.to_string(),
+            )
+            .unwrap();
+        publisher
+            .publish("scan.progress".to_string(), "half way".to_string())
+            .unwrap();
+
+        let finding = receiver.try_recv().unwrap();
+        assert_eq!(finding.data, serde_json::json!({"severity": "high"}));
+        assert_eq!(
+            receiver.try_recv().unwrap().data,
+            Value::String("half way".to_string())
+        );
+        assert_eq!(
+            subscriber.try_recv().unwrap(),
+            LifecycleEvent::EventPublished {
+                component_id: "local/scanner".to_string(),
+                topic: "scan.finding".to_string(),
+                data: finding.data,
+                request_id: Some("7".to_string()),
+                session_id: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_publish_is_policy_gated() {
+        let (mut publisher, mut receiver, _subscriber) = publisher(&["scan.*"]);
+        let error = publisher
+            .publish("deploy.start".to_string(), "{}".to_string())
+            .unwrap_err();
+        assert!(error.contains("deploy.start"));
+        assert!(publisher
+            .publish("scan.big".to_string(), "x".repeat(MAX_EVENT_SIZE + 1))
+            .is_err());
+        assert!(receiver.try_recv().is_err());
+
+        let mut denied = CallPublisher::default();
+        assert!(denied
+            .publish("scan.finding".to_string(), "{}".to_string())
+            .is_err());
+    }
+
+    #[test]
+    fn test_publish_limit_per_call() {
+        let (mut publisher, _receiver, _subscriber) = publisher(&["*"]);
+        for _ in 0..MAX_EVENTS_PER_CALL {
+            publisher
+                .publish("tick".to_string(), "{}".to_string())
+                .unwrap();
+        }
+        assert!(publisher
+            .publish("tick".to_string(), "{}".to_string())
+            .is_err());
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Retrying OCI pulls that a registry refuses with other credentials.
+//!
+//! A pull first uses the credentials stored for the registry with `wassette auth login`, or no
+//! credentials if none are stored. If the registry refuses it with a 401 or 403, the pull is
+//! retried with the credentials found for the registry elsewhere, in this order:
+//!
+//! - the `auths` of the Docker configuration, `$DOCKER_CONFIG/config.json` or
+//!   `~/.docker/config.json`. Credential helpers aren't run
+//! - `WASSETTE_REGISTRY_USERNAME` and `WASSETTE_REGISTRY_PASSWORD`, if `WASSETTE_REGISTRY` names
+//!   the registry
+//!
+//! If every attempt is refused, the error lists the methods that were tried.
+
+use std::future::Future;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use base64::Engine as _;
+use oci_client::errors::{OciDistributionError, OciErrorCode};
+use oci_client::secrets::RegistryAuth;
+use oci_client::RegistryOperation;
+use serde::Deserialize;
+use tracing::{debug, info};
+
+use crate::LifecycleManager;
+
+/// Registry that the environment credentials belong to
+pub const REGISTRY_ENV: &str = "WASSETTE_REGISTRY";
+/// Username for the registry named by [`REGISTRY_ENV`]
+pub const REGISTRY_USERNAME_ENV: &str = "WASSETTE_REGISTRY_USERNAME";
+/// Password or token for the registry named by [`REGISTRY_ENV`]
+pub const REGISTRY_PASSWORD_ENV: &str = "WASSETTE_REGISTRY_PASSWORD";
+
+/// Where the credentials of an attempt came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum AuthMethod {
+    Anonymous,
+    Stored,
+    DockerConfig(PathBuf),
+    Environment,
+}
+
+impl std::fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Anonymous => f.write_str("anonymous access"),
+            Self::Stored => f.write_str("stored credentials"),
+            Self::DockerConfig(path) => write!(f, "Docker config {}", path.display()),
+            Self::Environment => write!(f, "{REGISTRY_USERNAME_ENV}/{REGISTRY_PASSWORD_ENV}"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: std::collections::HashMap<String, DockerAuth>,
+}
+
+#[derive(Deserialize)]
+struct DockerAuth {
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// Returns the name a registry is known by, so that the Docker Hub aliases and URLs like
+/// `https://index.docker.io/v1/` match `docker.io`
+fn normalize_registry(registry: &str) -> &str {
+    let host = registry
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    match host {
+        "index.docker.io" | "registry-1.docker.io" => "docker.io",
+        host => host,
+    }
+}
+
+/// Reads the username and password of a registry from the contents of a Docker configuration
+fn docker_config_credentials(config: &str, registry: &str) -> Option<(String, String)> {
+    let config: DockerConfig = serde_json::from_str(config).ok()?;
+    let registry = normalize_registry(registry);
+    let auth = config
+        .auths
+        .iter()
+        .find(|(key, _)| normalize_registry(key) == registry)
+        .map(|(_, auth)| auth)?;
+    if let (Some(username), Some(password)) = (&auth.username, &auth.password) {
+        return Some((username.clone(), password.clone()));
+    }
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(auth.auth.as_ref()?)
+        .ok()?;
+    let (username, password) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Returns the path of the Docker configuration
+fn docker_config_path() -> Option<PathBuf> {
+    match std::env::var_os("DOCKER_CONFIG") {
+        Some(dir) => Some(PathBuf::from(dir).join("config.json")),
+        None => std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(".docker").join("config.json")),
+    }
+}
+
+/// Returns the credentials for a registry found outside the credential store
+async fn discovered_auths(registry: &str) -> Vec<(AuthMethod, RegistryAuth)> {
+    let mut auths = Vec::new();
+    if let Some(path) = docker_config_path() {
+        if let Ok(config) = tokio::fs::read_to_string(&path).await {
+            if let Some((username, password)) = docker_config_credentials(&config, registry) {
+                auths.push((
+                    AuthMethod::DockerConfig(path),
+                    RegistryAuth::Basic(username, password),
+                ));
+            }
+        }
+    }
+    let environment = (
+        std::env::var(REGISTRY_ENV),
+        std::env::var(REGISTRY_USERNAME_ENV),
+        std::env::var(REGISTRY_PASSWORD_ENV),
+    );
+    if let (Ok(named), Ok(username), Ok(password)) = environment {
+        if normalize_registry(&named) == normalize_registry(registry) {
+            auths.push((
+                AuthMethod::Environment,
+                RegistryAuth::Basic(username, password),
+            ));
+        }
+    }
+    auths
+}
+
+/// Returns whether an error is the registry refusing access
+fn is_auth_error(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| match cause.downcast_ref::<OciDistributionError>() {
+            Some(OciDistributionError::AuthenticationFailure(_))
+            | Some(OciDistributionError::UnauthorizedError { .. }) => true,
+            Some(OciDistributionError::ServerError { code, .. }) => matches!(code, 401 | 403),
+            Some(OciDistributionError::RegistryError { envelope, .. }) => {
+                envelope.errors.iter().any(|error| {
+                    matches!(
+                        error.code,
+                        OciErrorCode::Unauthorized | OciErrorCode::Denied
+                    )
+                })
+            }
+            _ => false,
+        })
+}
+
+impl LifecycleManager {
+    /// Runs `pull` for an OCI reference with the stored credentials of its registry, or without
+    /// credentials, and again with each set of discovered credentials while the registry refuses
+    /// access
+    pub(crate) async fn with_registry_auth<T, F, Fut>(
+        &self,
+        reference: &str,
+        mut pull: F,
+    ) -> Result<T>
+    where
+        F: FnMut(RegistryAuth) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let stored = self.credentials.registry_auth(reference).await;
+        let first = match stored {
+            RegistryAuth::Anonymous => AuthMethod::Anonymous,
+            _ => AuthMethod::Stored,
+        };
+        let error = match pull(stored.clone()).await {
+            Err(e) if is_auth_error(&e) => e,
+            result => return result,
+        };
+        let Ok(parsed) = reference.parse::<oci_client::Reference>() else {
+            return Err(error);
+        };
+
+        let mut attempted = vec![first];
+        let mut error = error;
+        for (method, auth) in discovered_auths(parsed.registry()).await {
+            if auth == stored {
+                continue;
+            }
+            debug!(registry = parsed.registry(), method = %method, "Registry refused access, retrying");
+            // The client keeps the token of the refused attempt, so the new credentials have to be
+            // exchanged for a token explicitly
+            let result = match self
+                .oci_client
+                .auth(&parsed, &auth, RegistryOperation::Pull)
+                .await
+            {
+                Ok(_) => pull(auth).await,
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(value) => {
+                    info!(registry = parsed.registry(), method = %method, "Pulled with discovered credentials");
+                    return Ok(value);
+                }
+                Err(e) if is_auth_error(&e) => {
+                    attempted.push(method);
+                    error = e;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let attempted: Vec<String> = attempted.iter().map(ToString::to_string).collect();
+        Err(error.context(anyhow!(
+            "{} refused access to {reference}. Tried: {}. Store credentials with `wassette auth login {}`",
+            parsed.registry(),
+            attempted.join(", "),
+            parsed.registry()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_docker_config_credentials() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode("me:s3cret");
+        let config = format!(
+            r#"{{
+                "auths": {{
+                    "https://index.docker.io/v1/": {{"auth": "{encoded}"}},
+                    "ghcr.io": {{"username": "bot", "password": "token"}}
+                }},
+                "credsStore": "desktop"
+            }}"#
+        );
+        assert_eq!(
+            docker_config_credentials(&config, "docker.io"),
+            Some(("me".to_string(), "s3cret".to_string()))
+        );
+        assert_eq!(
+            docker_config_credentials(&config, "ghcr.io"),
+            Some(("bot".to_string(), "token".to_string()))
+        );
+        assert_eq!(docker_config_credentials(&config, "quay.io"), None);
+        assert_eq!(docker_config_credentials("not json", "ghcr.io"), None);
+    }
+
+    #[test]
+    fn test_is_auth_error() {
+        let refused = anyhow::Error::new(OciDistributionError::ServerError {
+            code: 401,
+            url: "https://ghcr.io/v2/".to_string(),
+            message: "unauthorized".to_string(),
+        })
+        .context("Failed to pull");
+        assert!(is_auth_error(&refused));
+
+        let missing = anyhow::Error::new(OciDistributionError::ServerError {
+            code: 404,
+            url: "https://ghcr.io/v2/".to_string(),
+            message: "not found".to_string(),
+        });
+        assert!(!is_auth_error(&missing));
+        assert!(!is_auth_error(&anyhow!("connection reset")));
+    }
+
+    #[tokio::test]
+    async fn test_environment_credentials_are_limited_to_their_registry() {
+        temp_env::async_with_vars(
+            [
+                (REGISTRY_ENV, Some("ghcr.io")),
+                (REGISTRY_USERNAME_ENV, Some("bot")),
+                (REGISTRY_PASSWORD_ENV, Some("token")),
+                ("DOCKER_CONFIG", Some("/nonexistent")),
+            ],
+            async {
+                assert_eq!(
+                    discovered_auths("ghcr.io").await,
+                    vec![(
+                        AuthMethod::Environment,
+                        RegistryAuth::Basic("bot".to_string(), "token".to_string())
+                    )]
+                );
+                assert!(discovered_auths("quay.io").await.is_empty());
+            },
+        )
+        .await;
+    }
+}
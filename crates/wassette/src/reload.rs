@@ -0,0 +1,528 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Reloading the configuration, policies and plugin directory of a running server.
+//!
+//! A reload applies the settings that can change at runtime, picks up components that were
+//! added, replaced or removed in the plugin directory, and re-reads the policy files next to
+//! them. Loaded components are swapped in place, so sessions that are connected keep working.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use component2json::{apply_tool_metadata, component_exports_to_tools_with_sanitizer};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::labels::read_labels;
+use crate::policy_internal::read_policy_template;
+use crate::{
+    command, compile_component, component_id, log_schema_diff, read_component_source,
+    LifecycleConfig, LifecycleEvent, LifecycleManager, PrecompiledArtifacts, SchemaDiff,
+    HOST_TOOLS_ID,
+};
+
+/// A component or policy that could not be reloaded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReloadFailure {
+    /// ID of the component
+    pub component: String,
+    /// Why reloading failed
+    pub error: String,
+}
+
+/// What a reload changed. Components are listed by ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReloadReport {
+    /// Settings that changed and were applied
+    pub settings: Vec<String>,
+    /// Settings that changed but only take effect after a restart
+    pub restart_required: Vec<String>,
+    /// Components that appeared in the plugin directory
+    pub added: Vec<String>,
+    /// Components whose file in the plugin directory changed
+    pub replaced: Vec<String>,
+    /// Components whose file was removed from the plugin directory
+    pub removed: Vec<String>,
+    /// Components whose policy changed, was added or was removed
+    pub policies: Vec<String>,
+    /// Components whose labels changed
+    pub labels: Vec<String>,
+    /// Components that could not be reloaded. They keep running as before.
+    pub failed: Vec<ReloadFailure>,
+}
+
+impl ReloadReport {
+    /// Whether the reload changed anything
+    pub fn has_changes(&self) -> bool {
+        !(self.settings.is_empty()
+            && self.added.is_empty()
+            && self.replaced.is_empty()
+            && self.removed.is_empty()
+            && self.policies.is_empty()
+            && self.labels.is_empty())
+    }
+}
+
+impl std::fmt::Display for ReloadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} settings changed, {} components added, {} replaced, {} removed, {} policies changed, {} labels changed, {} failed",
+            self.settings.len(),
+            self.added.len(),
+            self.replaced.len(),
+            self.removed.len(),
+            self.policies.len(),
+            self.labels.len(),
+            self.failed.len()
+        )?;
+        if !self.restart_required.is_empty() {
+            write!(f, "; restart to apply {}", self.restart_required.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl LifecycleManager {
+    /// Applies a new configuration and re-scans the plugin directory. Settings that are read when
+    /// the engine is created, like the tool naming rules, keep their old value until a restart.
+    pub async fn reload(&self, config: LifecycleConfig) -> ReloadReport {
+        let mut report = ReloadReport::default();
+        self.reload_config(config, &mut report).await;
+        if let Err(e) = self.rescan_plugin_dir(&mut report).await {
+            warn!(error = %e, "Failed to re-scan the plugin directory");
+            report.failed.push(ReloadFailure {
+                component: String::new(),
+                error: format!("{e:#}"),
+            });
+        }
+        self.reload_policies(&mut report).await;
+        self.reload_labels(&mut report).await;
+
+        for (change, components) in [
+            ("added", &report.added),
+            ("replaced", &report.replaced),
+            ("removed", &report.removed),
+            ("policy changed", &report.policies),
+            ("labels changed", &report.labels),
+        ] {
+            for component_id in components {
+                info!(component_id = %component_id, change, "Reloaded component");
+            }
+        }
+        for component_id in &report.added {
+            self.emit(LifecycleEvent::ComponentLoaded {
+                component_id: component_id.clone(),
+                replaced: false,
+                source_uri: None,
+            });
+        }
+        for component_id in &report.replaced {
+            self.emit(LifecycleEvent::ComponentLoaded {
+                component_id: component_id.clone(),
+                replaced: true,
+                source_uri: None,
+            });
+        }
+        for component_id in &report.removed {
+            self.emit(LifecycleEvent::ComponentUnloaded {
+                component_id: component_id.clone(),
+            });
+        }
+        for component_id in &report.policies {
+            self.emit(LifecycleEvent::PolicyChanged {
+                component_id: component_id.clone(),
+            });
+        }
+        info!(summary = %report, "Reloaded configuration and plugin directory");
+        report
+    }
+
+    async fn reload_config(&self, mut config: LifecycleConfig, report: &mut ReloadReport) {
+        let mut current = self.config.write().await;
+        let restart_required = [
+            ("tool_naming", current.tool_naming != config.tool_naming),
+            (
+                "coredump_on_trap",
+                current.coredump_on_trap != config.coredump_on_trap,
+            ),
+            ("determinism", current.determinism != config.determinism),
+            (
+                "hot_components",
+                current.hot_components != config.hot_components,
+            ),
+            ("warm_up", current.warm_up != config.warm_up),
+            ("ai_models", current.ai_models != config.ai_models),
+            ("blobstore", current.blobstore != config.blobstore),
+            (
+                "disable_host_tools",
+                current.disable_host_tools != config.disable_host_tools,
+            ),
+            (
+                "execution.interactive_workers",
+                current.execution.interactive_workers != config.execution.interactive_workers,
+            ),
+            (
+                "execution.batch_workers",
+                current.execution.batch_workers != config.execution.batch_workers,
+            ),
+        ];
+        let applied = [
+            (
+                "profile_components",
+                current.profile_components != config.profile_components,
+            ),
+            (
+                "record_components",
+                current.record_components != config.record_components,
+            ),
+            (
+                "transcript_components",
+                current.transcript_components != config.transcript_components,
+            ),
+            (
+                "transcript_retention_days",
+                current.transcript_retention_days != config.transcript_retention_days,
+            ),
+            (
+                "approval_timeout_secs",
+                current.approval_timeout_secs != config.approval_timeout_secs,
+            ),
+            (
+                "legacy_sandbox_defaults",
+                current.legacy_sandbox_defaults != config.legacy_sandbox_defaults,
+            ),
+            ("mirrors", current.mirrors != config.mirrors),
+            (
+                "tool_selector",
+                current.tool_selector != config.tool_selector,
+            ),
+            (
+                "execution.batch_components",
+                current.execution.batch_components != config.execution.batch_components,
+            ),
+            (
+                "compress_components",
+                current.compress_components != config.compress_components,
+            ),
+            (
+                "compact_schemas",
+                current.compact_schemas != config.compact_schemas,
+            ),
+            ("language", current.language != config.language),
+            (
+                "allowed_schemes",
+                current.allowed_schemes != config.allowed_schemes,
+            ),
+            (
+                "sandbox_groups",
+                current.sandbox_groups != config.sandbox_groups,
+            ),
+            (
+                "notification_queue",
+                current.notification_queue != config.notification_queue,
+            ),
+            ("sessions", current.sessions != config.sessions),
+            (
+                "load_precompiled",
+                current.load_precompiled != config.load_precompiled,
+            ),
+            (
+                "require_precompiled",
+                current.require_precompiled != config.require_precompiled,
+            ),
+            (
+                "attach_package_policies",
+                current.attach_package_policies != config.attach_package_policies,
+            ),
+        ];
+        for (setting, changed) in restart_required {
+            if changed {
+                report.restart_required.push(setting.to_string());
+            }
+        }
+        for (setting, changed) in applied {
+            if changed {
+                report.settings.push(setting.to_string());
+            }
+        }
+
+        // The engine, the blob storage backend and the tool names of loaded components were built
+        // with these
+        config.tool_naming = current.tool_naming.clone();
+        config.coredump_on_trap = current.coredump_on_trap;
+        config.determinism = current.determinism.clone();
+        config.ai_models = current.ai_models.clone();
+        config.blobstore = current.blobstore.clone();
+        // The host tools were registered, or not, at startup
+        config.disable_host_tools = current.disable_host_tools;
+        // The execution pools were sized with these
+        config.execution.interactive_workers = current.execution.interactive_workers;
+        config.execution.batch_workers = current.execution.batch_workers;
+        if current.mirrors != config.mirrors {
+            self.mirrors.set_rules(config.mirrors.clone());
+        }
+        if current.transcript_retention_days != config.transcript_retention_days {
+            self.transcripts
+                .write()
+                .await
+                .set_retention(config.transcript_retention());
+        }
+        *current = config;
+    }
+
+    /// Compiles the components in the plugin directory that aren't loaded or whose file changed,
+    /// and drops the loaded components whose file is gone
+    async fn rescan_plugin_dir(&self, report: &mut ReloadReport) -> Result<()> {
+        component_id::migrate_flat_components(&self.plugin_dir).await?;
+        let mut sources = Vec::new();
+        for (id, path) in component_id::component_files(&self.plugin_dir).await? {
+            sources.push(read_component_source(id, &path).await?);
+        }
+        let on_disk: HashSet<String> = sources.iter().map(|source| source.name.clone()).collect();
+        let artifacts = PrecompiledArtifacts::from_config(&*self.config.read().await);
+
+        for source in sources {
+            let id = source.name.clone();
+            let loaded_digest = self
+                .components
+                .read()
+                .await
+                .get(&id)
+                .map(|instance| instance.digest.clone());
+            if loaded_digest.as_ref() == Some(&source.digest) {
+                continue;
+            }
+
+            let compiled = compile_component(
+                self.engine.clone(),
+                &self.linker,
+                source,
+                &self.plugin_dir,
+                artifacts,
+                &self.compilation_cache,
+            )
+            .await;
+            let (instance, id) = match compiled {
+                Ok(compiled) => compiled,
+                Err(e) => {
+                    warn!(component_id = %id, error = %e, "Failed to reload component");
+                    report.failed.push(ReloadFailure {
+                        component: id,
+                        error: format!("{e:#}"),
+                    });
+                    continue;
+                }
+            };
+            let mut tool_metadata = component_exports_to_tools_with_sanitizer(
+                &instance.component,
+                &self.engine,
+                true,
+                &self.tool_naming,
+            );
+            command::synthesize_command_tools(&id, &mut tool_metadata, &self.tool_naming);
+            if let Some(metadata) = &instance.tool_metadata {
+                apply_tool_metadata(&mut tool_metadata, metadata);
+            }
+            let schema_diff = {
+                let mut registry = self.registry.write().await;
+                let previous = registry.component_schemas(&id);
+                if let Err(e) = registry.update_tools(&id, tool_metadata) {
+                    report.failed.push(ReloadFailure {
+                        component: id,
+                        error: format!("{e:#}"),
+                    });
+                    continue;
+                }
+                SchemaDiff::between(&previous, &registry.component_schemas(&id))
+            };
+            let previous = self.components.write().await.insert(id.clone(), instance);
+            if let Some(previous) = previous {
+                self.release_content(&previous.digest).await;
+            }
+            if loaded_digest.is_some() {
+                log_schema_diff(&id, &schema_diff);
+                report.replaced.push(id);
+            } else {
+                report.added.push(id);
+            }
+        }
+
+        let gone: Vec<String> = self
+            .components
+            .read()
+            .await
+            .keys()
+            .filter(|id| !on_disk.contains(*id))
+            .cloned()
+            .collect();
+        for id in gone {
+            let instance = self.components.write().await.remove(&id);
+            if let Some(instance) = instance {
+                self.release_content(&instance.digest).await;
+            }
+            self.labels.write().await.remove(&id);
+            self.registry.write().await.unregister_component(&id);
+            self.cleanup_policy_registry(&id).await;
+            report.removed.push(id);
+        }
+        Ok(())
+    }
+
+    /// Re-reads the policy file of every loaded component
+    async fn reload_policies(&self, report: &mut ReloadReport) {
+        let mut ids = self.list_components().await;
+        if self.has_component(HOST_TOOLS_ID).await {
+            ids.push(HOST_TOOLS_ID.to_string());
+        }
+        for id in ids {
+            match read_policy_template(&self.plugin_dir, &id).await {
+                Ok(Some(template)) => {
+                    let mut policy_registry = self.policy_registry.write().await;
+                    let changed = policy_registry
+                        .component_policies
+                        .get(&id)
+                        .is_none_or(|current| **current != template);
+                    if changed {
+                        policy_registry.insert(id.clone(), template);
+                        report.policies.push(id);
+                    }
+                }
+                Ok(None) => {
+                    let removed = self.policy_registry.write().await.remove(&id);
+                    if removed {
+                        report.policies.push(id);
+                    }
+                }
+                Err(e) => {
+                    warn!(component_id = %id, error = %e, "Keeping the previous policy of component");
+                    report.failed.push(ReloadFailure {
+                        component: id,
+                        error: format!("{e:#}"),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Re-reads the labels of every loaded component, e.g. after `wassette component label`
+    async fn reload_labels(&self, report: &mut ReloadReport) {
+        for id in self.list_components().await {
+            match read_labels(&self.plugin_dir, &id).await {
+                Ok(labels) => {
+                    let mut current = self.labels.write().await;
+                    if current.get(&id).cloned().unwrap_or_default() != labels {
+                        current.insert(id.clone(), labels);
+                        report.labels.push(id);
+                    }
+                }
+                Err(e) => {
+                    warn!(component_id = %id, error = %e, "Keeping the previous labels of component");
+                    report.failed.push(ReloadFailure {
+                        component: id,
+                        error: format!("{e:#}"),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+    use crate::MirrorConfig;
+
+    #[tokio::test]
+    async fn test_reload_applies_settings() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let config = LifecycleConfig {
+            profile_components: vec![TEST_COMPONENT_ID.to_string()],
+            coredump_on_trap: true,
+            mirrors: vec![MirrorConfig {
+                source: "oci://ghcr.io/".to_string(),
+                mirrors: vec!["oci://registry.internal/".to_string()],
+            }],
+            ..Default::default()
+        };
+
+        let report = manager.reload(config.clone()).await;
+        assert_eq!(report.settings, vec!["profile_components", "mirrors"]);
+        assert_eq!(report.restart_required, vec!["coredump_on_trap"]);
+        assert!(!manager.config.read().await.coredump_on_trap);
+        assert_eq!(
+            manager.mirrors.candidates("oci://ghcr.io/a/b:1")[0].uri,
+            "oci://registry.internal/a/b:1"
+        );
+
+        // The setting that needs a restart is still reported as pending
+        let report = manager.reload(config).await;
+        assert!(report.settings.is_empty());
+        assert_eq!(report.restart_required, vec!["coredump_on_trap"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reload_rescans_plugin_dir() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let component_path = build_example_component().await?;
+        let installed = manager.plugin_dir.join(format!("{TEST_COMPONENT_ID}.wasm"));
+        tokio::fs::create_dir_all(installed.parent().unwrap()).await?;
+        tokio::fs::copy(&component_path, &installed).await?;
+
+        let report = manager.reload(LifecycleConfig::default()).await;
+        assert_eq!(report.added, vec![TEST_COMPONENT_ID.to_string()]);
+        assert!(!manager.list_tools().await.is_empty());
+
+        tokio::fs::write(
+            manager
+                .plugin_dir
+                .join(format!("{TEST_COMPONENT_ID}.policy.yaml")),
+            "version: \"1.0\"\npermissions:\n  network:\n    allow:\n      - host: example.com\n",
+        )
+        .await?;
+        let report = manager.reload(LifecycleConfig::default()).await;
+        assert!(report.added.is_empty() && report.replaced.is_empty());
+        assert_eq!(report.policies, vec![TEST_COMPONENT_ID.to_string()]);
+        assert!(!manager
+            .reload(LifecycleConfig::default())
+            .await
+            .has_changes());
+
+        tokio::fs::remove_file(&installed).await?;
+        let report = manager.reload(LifecycleConfig::default()).await;
+        assert_eq!(report.removed, vec![TEST_COMPONENT_ID.to_string()]);
+        assert!(manager.list_components().await.is_empty());
+        assert!(manager.get_component_id_for_tool("fetch").await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reload_recompiles_changed_components() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let component_path = build_example_component().await?;
+        let installed = manager.plugin_dir.join(format!("{TEST_COMPONENT_ID}.wasm"));
+        tokio::fs::create_dir_all(installed.parent().unwrap()).await?;
+        tokio::fs::copy(&component_path, &installed).await?;
+        manager.reload(LifecycleConfig::default()).await;
+        let before = manager.components.read().await[TEST_COMPONENT_ID]
+            .digest
+            .clone();
+
+        // A trailing custom section changes the digest without changing the exports
+        let mut wasm = tokio::fs::read(&installed).await?;
+        wasm.extend_from_slice(&[0, 5, 4, b'n', b'o', b't', b'e']);
+        tokio::fs::write(&installed, wasm).await?;
+        let report = manager.reload(LifecycleConfig::default()).await;
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(report.replaced, vec![TEST_COMPONENT_ID.to_string()]);
+        assert_ne!(
+            manager.components.read().await[TEST_COMPONENT_ID].digest,
+            before
+        );
+        assert!(!manager.list_tools().await.is_empty());
+        Ok(())
+    }
+}
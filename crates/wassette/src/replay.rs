@@ -0,0 +1,570 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Record and replay of the WASI inputs of a tool call.
+//!
+//! A recorded call captures every value the component reads from the outside world: wall and
+//! monotonic clock readings, random bytes, configuration variables and HTTP responses. Replaying
+//! the [`Trace`] feeds the same values back in the same order, so a user-reported failure can be
+//! reproduced exactly and without network access. Note that traces contain the configuration
+//! variables and HTTP responses the component saw, which may include secrets.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+use wasmtime_wasi_http::body::{HyperIncomingBody, HyperOutgoingBody};
+use wasmtime_wasi_http::types::{
+    default_send_request_handler, HostFutureIncomingResponse, IncomingResponse,
+    OutgoingRequestConfig,
+};
+use wasmtime_wasi_http::HttpResult;
+
+/// The argument that requests a trace of a single tool call, e.g. `{"$record": true}`. It is
+/// removed from the arguments before they are passed to the component.
+pub const RECORD_ARGUMENT_KEY: &str = "$record";
+
+/// Directory in the plugin directory that traces are written to
+pub const TRACES_DIR: &str = "traces";
+
+/// The recorded WASI inputs of a single tool call
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Trace {
+    /// ID of the called component
+    pub component_id: String,
+    /// Name of the called tool
+    pub tool: String,
+    /// The call arguments, as a JSON object
+    pub parameters: String,
+    /// The `wasi:config` variables the component was given
+    pub config_vars: BTreeMap<String, String>,
+    /// Seed of the component's insecure random number generator
+    pub insecure_random_seed: u128,
+    /// Every reading of the wall clock, as time since the Unix epoch
+    pub wall_clock: Vec<Duration>,
+    /// Every reading of the monotonic clock, in nanoseconds
+    pub monotonic_clock: Vec<u64>,
+    /// All bytes read from the secure random number generator
+    #[serde(with = "base64_bytes")]
+    pub secure_random: Vec<u8>,
+    /// All bytes read from the insecure random number generator
+    #[serde(with = "base64_bytes")]
+    pub insecure_random: Vec<u8>,
+    /// Every outgoing HTTP request and its response, in the order they were sent
+    pub http: Vec<HttpExchange>,
+    /// The result of the recorded call, if it succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    /// The error of the recorded call, if it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An outgoing HTTP request and the response it received
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpExchange {
+    /// Method of the request
+    pub method: String,
+    /// URI of the request
+    pub uri: String,
+    /// Status code of the response
+    pub status: u16,
+    /// Headers of the response
+    pub headers: Vec<(String, String)>,
+    /// Body of the response
+    #[serde(with = "base64_bytes")]
+    pub body: Vec<u8>,
+    /// The error the request failed with instead of receiving a response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Trace {
+    /// Reads a trace written by a recorded call
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Failed to read trace: {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("Invalid trace file: {}", path.display()))
+    }
+
+    pub(crate) async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .context("Failed to create traces directory")?;
+        }
+        tokio::fs::write(path, serde_json::to_vec_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write trace: {}", path.display()))
+    }
+}
+
+/// Error context of a failed call whose trace was recorded. Find it with
+/// `error.downcast_ref::<RecordedTrace>()`.
+#[derive(Debug, Clone)]
+pub struct RecordedTrace {
+    /// The error the call failed with
+    pub message: String,
+    /// Where the trace was written to
+    pub path: PathBuf,
+}
+
+impl fmt::Display for RecordedTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (trace recorded to {})",
+            self.message,
+            self.path.display()
+        )
+    }
+}
+
+/// Positions of the next values to replay
+#[derive(Default)]
+struct Cursors {
+    wall_clock: usize,
+    monotonic_clock: usize,
+    secure_random: usize,
+    insecure_random: usize,
+    http: usize,
+}
+
+struct SessionState {
+    trace: Trace,
+    /// Only set when replaying
+    cursors: Option<Cursors>,
+}
+
+/// A trace that is being recorded or replayed by a call. Clones share the same trace.
+#[derive(Clone)]
+pub(crate) struct TraceSession(Arc<Mutex<SessionState>>);
+
+impl TraceSession {
+    /// Starts recording a call to the given tool
+    pub(crate) fn record(component_id: &str, tool: &str, parameters: &str) -> Self {
+        let trace = Trace {
+            component_id: component_id.to_string(),
+            tool: tool.to_string(),
+            parameters: parameters.to_string(),
+            insecure_random_seed: u128::from(OsRng.next_u64()) << 64 | u128::from(OsRng.next_u64()),
+            ..Default::default()
+        };
+        Self::new(trace, None)
+    }
+
+    /// Replays a recorded trace
+    pub(crate) fn replay(trace: Trace) -> Self {
+        Self::new(trace, Some(Cursors::default()))
+    }
+
+    fn new(trace: Trace, cursors: Option<Cursors>) -> Self {
+        Self(Arc::new(Mutex::new(SessionState { trace, cursors })))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SessionState> {
+        // The state stays consistent even if a holder panicked, so a poisoned lock is fine
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn is_replay(&self) -> bool {
+        self.lock().cursors.is_some()
+    }
+
+    /// Returns the trace as recorded so far
+    pub(crate) fn trace(&self) -> Trace {
+        self.lock().trace.clone()
+    }
+
+    pub(crate) fn insecure_random_seed(&self) -> u128 {
+        self.lock().trace.insecure_random_seed
+    }
+
+    /// Records the configuration variables when recording, and returns the recorded ones when
+    /// replaying
+    pub(crate) fn config_vars(
+        &self,
+        live: impl IntoIterator<Item = (String, String)>,
+    ) -> BTreeMap<String, String> {
+        let mut state = self.lock();
+        if state.cursors.is_none() {
+            state.trace.config_vars = live.into_iter().collect();
+        }
+        state.trace.config_vars.clone()
+    }
+
+    fn wall_clock(&self) -> Duration {
+        let state = &mut *self.lock();
+        next_value(
+            &mut state.trace.wall_clock,
+            state.cursors.as_mut().map(|c| &mut c.wall_clock),
+            || {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+            },
+        )
+    }
+
+    fn monotonic_clock(&self, start: Instant) -> u64 {
+        let state = &mut *self.lock();
+        next_value(
+            &mut state.trace.monotonic_clock,
+            state.cursors.as_mut().map(|c| &mut c.monotonic_clock),
+            || start.elapsed().as_nanos() as u64,
+        )
+    }
+
+    fn random(&self, secure: bool, dest: &mut [u8]) {
+        let state = &mut *self.lock();
+        let (bytes, cursor) = if secure {
+            (
+                &mut state.trace.secure_random,
+                state.cursors.as_mut().map(|c| &mut c.secure_random),
+            )
+        } else {
+            (
+                &mut state.trace.insecure_random,
+                state.cursors.as_mut().map(|c| &mut c.insecure_random),
+            )
+        };
+        match cursor {
+            Some(position) => {
+                // Once the recorded bytes run out, zeros keep the replay deterministic
+                for byte in dest.iter_mut() {
+                    *byte = bytes.get(*position).copied().unwrap_or_default();
+                    *position += 1;
+                }
+            }
+            None => {
+                OsRng.fill_bytes(dest);
+                bytes.extend_from_slice(dest);
+            }
+        }
+    }
+
+    fn next_http_exchange(&self) -> Option<HttpExchange> {
+        let state = &mut *self.lock();
+        let cursors = state.cursors.as_mut()?;
+        let exchange = state.trace.http.get(cursors.http).cloned();
+        cursors.http += 1;
+        exchange
+    }
+
+    /// Sends the request and records its response, or answers it from the trace when replaying
+    pub(crate) fn send_request(
+        &self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        let method = request.method().to_string();
+        let uri = request.uri().to_string();
+
+        if self.is_replay() {
+            let response = match self.next_http_exchange() {
+                Some(exchange) if exchange.method == method && exchange.uri == uri => {
+                    exchange.into_response(config.between_bytes_timeout)
+                }
+                Some(exchange) => Err(ErrorCode::InternalError(Some(format!(
+                    "The trace expected {} {}, but the component sent {method} {uri}",
+                    exchange.method, exchange.uri
+                )))),
+                None => Err(ErrorCode::InternalError(Some(format!(
+                    "The trace has no response for {method} {uri}"
+                )))),
+            };
+            return Ok(HostFutureIncomingResponse::ready(Ok(response)));
+        }
+
+        let session = self.clone();
+        let handle = wasmtime_wasi::runtime::spawn(async move {
+            let response = default_send_request_handler(request, config).await;
+            Ok::<_, anyhow::Error>(session.record_response(method, uri, response).await)
+        });
+        Ok(HostFutureIncomingResponse::pending(handle))
+    }
+
+    /// Buffers the response body so that it can be recorded, and hands the component a response
+    /// with the same contents
+    async fn record_response(
+        &self,
+        method: String,
+        uri: String,
+        response: Result<IncomingResponse, ErrorCode>,
+    ) -> Result<IncomingResponse, ErrorCode> {
+        let mut exchange = HttpExchange {
+            method,
+            uri,
+            ..Default::default()
+        };
+        let result: Result<IncomingResponse, ErrorCode> = async {
+            let response = response?;
+            // The worker drives the connection, so it has to live until the body is read
+            let worker = response.worker;
+            let (parts, body) = response.resp.into_parts();
+            let body = body.collect().await?.to_bytes();
+            drop(worker);
+
+            exchange.status = parts.status.as_u16();
+            exchange.headers = parts
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect();
+            exchange.body = body.to_vec();
+            Ok(IncomingResponse {
+                resp: hyper::Response::from_parts(parts, full_body(body)),
+                worker: None,
+                between_bytes_timeout: response.between_bytes_timeout,
+            })
+        }
+        .await;
+
+        if let Err(code) = &result {
+            exchange.error = Some(format!("{code:?}"));
+        }
+        self.lock().trace.http.push(exchange);
+        result
+    }
+}
+
+impl HttpExchange {
+    fn into_response(self, between_bytes_timeout: Duration) -> Result<IncomingResponse, ErrorCode> {
+        if let Some(error) = self.error {
+            return Err(ErrorCode::InternalError(Some(error)));
+        }
+        let mut builder = hyper::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let resp = builder
+            .body(full_body(Bytes::from(self.body)))
+            .map_err(|e| ErrorCode::InternalError(Some(e.to_string())))?;
+        Ok(IncomingResponse {
+            resp,
+            worker: None,
+            between_bytes_timeout,
+        })
+    }
+}
+
+fn full_body(body: Bytes) -> HyperIncomingBody {
+    Full::new(body).map_err(|never| match never {}).boxed()
+}
+
+/// Returns the next recorded value when replaying, or records a live value otherwise
+fn next_value<T: Clone>(
+    values: &mut Vec<T>,
+    cursor: Option<&mut usize>,
+    live: impl FnOnce() -> T,
+) -> T {
+    match cursor {
+        Some(position) => {
+            // Once the recorded values run out, the last one is repeated
+            let value = values.get(*position).or(values.last()).cloned();
+            *position += 1;
+            value.unwrap_or_else(live)
+        }
+        None => {
+            let value = live();
+            values.push(value.clone());
+            value
+        }
+    }
+}
+
+/// Wall clock that reads through a [`TraceSession`]
+pub(crate) struct TracedWallClock(pub(crate) TraceSession);
+
+impl HostWallClock for TracedWallClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        self.0.wall_clock()
+    }
+}
+
+/// Monotonic clock that reads through a [`TraceSession`]
+pub(crate) struct TracedMonotonicClock {
+    session: TraceSession,
+    start: Instant,
+}
+
+impl TracedMonotonicClock {
+    pub(crate) fn new(session: TraceSession) -> Self {
+        Self {
+            session,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl HostMonotonicClock for TracedMonotonicClock {
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn now(&self) -> u64 {
+        self.session.monotonic_clock(self.start)
+    }
+}
+
+/// Random number generator that reads through a [`TraceSession`]
+pub(crate) struct TracedRng {
+    session: TraceSession,
+    secure: bool,
+}
+
+impl TracedRng {
+    pub(crate) fn new(session: TraceSession, secure: bool) -> Self {
+        Self { session, secure }
+    }
+}
+
+impl RngCore for TracedRng {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.session.random(self.secure, dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Returns the path of a new trace of a call to the given tool
+pub(crate) fn trace_path(plugin_dir: &Path, component_id: &str, tool_name: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    plugin_dir
+        .join(TRACES_DIR)
+        .join(component_id)
+        .join(format!("{tool_name}-{timestamp}.json"))
+}
+
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_returns_recorded_values() {
+        let recorder =
+            TraceSession::record("fetch_rs", "fetch", r#"{"url":"https://example.com"}"#);
+        let wall_clock = TracedWallClock(recorder.clone());
+        let first = wall_clock.now();
+        let second = wall_clock.now();
+        let mut rng = TracedRng::new(recorder.clone(), true);
+        let mut random = [0u8; 16];
+        rng.fill_bytes(&mut random);
+        recorder.config_vars([("API_URL".to_string(), "https://example.com".to_string())]);
+
+        let trace = recorder.trace();
+        assert_eq!(trace.wall_clock, vec![first, second]);
+        assert_eq!(trace.secure_random, random);
+
+        let replayer = TraceSession::replay(trace);
+        let wall_clock = TracedWallClock(replayer.clone());
+        assert_eq!(wall_clock.now(), first);
+        assert_eq!(wall_clock.now(), second);
+        // The last reading repeats once the recorded ones run out
+        assert_eq!(wall_clock.now(), second);
+
+        let mut rng = TracedRng::new(replayer.clone(), true);
+        let mut replayed = [0u8; 16];
+        rng.fill_bytes(&mut replayed);
+        assert_eq!(replayed, random);
+
+        let config_vars = replayer.config_vars([("API_URL".to_string(), "changed".to_string())]);
+        assert_eq!(config_vars["API_URL"], "https://example.com");
+    }
+
+    #[test]
+    fn test_trace_serialization() -> Result<()> {
+        let trace = Trace {
+            component_id: "fetch_rs".to_string(),
+            tool: "fetch".to_string(),
+            secure_random: vec![1, 2, 3],
+            http: vec![HttpExchange {
+                method: "GET".to_string(),
+                uri: "https://example.com/".to_string(),
+                status: 200,
+                body: b"hello".to_vec(),
+                ..Default::default()
+            }],
+            result: Some("hello".to_string()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&trace)?;
+        assert_eq!(json["secure_random"], "AQID");
+        assert_eq!(json["http"][0]["body"], "aGVsbG8=");
+        assert_eq!(serde_json::from_value::<Trace>(json)?, trace);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replayed_http_exchange() {
+        let exchange = HttpExchange {
+            method: "GET".to_string(),
+            uri: "https://example.com/".to_string(),
+            status: 404,
+            headers: vec![("content-type".to_string(), "text/plain".to_string())],
+            body: b"not found".to_vec(),
+            error: None,
+        };
+        let response = exchange.into_response(Duration::from_secs(1)).unwrap();
+        assert_eq!(response.resp.status(), 404);
+        assert_eq!(response.resp.headers()["content-type"], "text/plain");
+
+        let failed = HttpExchange {
+            error: Some("DNS error".to_string()),
+            ..Default::default()
+        };
+        assert!(failed.into_response(Duration::from_secs(1)).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Sandbox groups, sets of components that share a scratch directory and their blob containers.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+
+use crate::wasistate::PreopenedDir;
+use crate::{LifecycleManager, WasiStateTemplate};
+
+/// Directory in the plugin directory that holds the scratch directories of sandbox groups
+pub const SANDBOXES_DIR: &str = "sandboxes";
+
+/// Path at which the components of a sandbox group see the group's scratch directory
+pub const SCRATCH_GUEST_PATH: &str = "/scratch";
+
+/// Checks that a group name can name a directory and a blob storage prefix
+pub(crate) fn validate_group_name(group: &str) -> Result<()> {
+    if group.is_empty()
+        || group.starts_with('.')
+        || !group
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        bail!("Invalid sandbox group name: {group:?}");
+    }
+    Ok(())
+}
+
+/// Returns the scratch directory of a group, creating it if needed
+pub(crate) async fn scratch_dir(plugin_dir: &Path, group: &str) -> Result<PreopenedDir> {
+    validate_group_name(group)?;
+    let host_path = plugin_dir.join(SANDBOXES_DIR).join(group);
+    tokio::fs::create_dir_all(&host_path)
+        .await
+        .with_context(|| format!("Failed to create the scratch directory of group {group}"))?;
+    Ok(PreopenedDir {
+        host_path,
+        guest_path: SCRATCH_GUEST_PATH.to_string(),
+        dir_perms: wasmtime_wasi::DirPerms::all(),
+        file_perms: wasmtime_wasi::FilePerms::all(),
+    })
+}
+
+impl LifecycleManager {
+    /// Returns the sandbox group of a component, if it belongs to one, and `template` with the
+    /// group's scratch directory added
+    pub(crate) async fn with_sandbox_group(
+        &self,
+        component_id: &str,
+        template: Arc<WasiStateTemplate>,
+    ) -> Result<(Option<String>, Arc<WasiStateTemplate>)> {
+        let group = self
+            .config
+            .read()
+            .await
+            .sandbox_group(component_id)
+            .map(str::to_string);
+        let Some(group) = group else {
+            return Ok((None, template));
+        };
+        let mut template = (*template).clone();
+        template
+            .preopened_dirs
+            .push(scratch_dir(&self.plugin_dir, &group).await?);
+        Ok((Some(group), Arc::new(template)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_manager;
+
+    #[test]
+    fn test_validate_group_name() {
+        assert!(validate_group_name("dev-tools").is_ok());
+        assert!(validate_group_name("").is_err());
+        assert!(validate_group_name("..").is_err());
+        assert!(validate_group_name("a/b").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_grouped_components_get_the_scratch_directory() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let template = Arc::new(WasiStateTemplate::default());
+        let (group, _) = manager
+            .with_sandbox_group("local/git", template.clone())
+            .await?;
+        assert_eq!(group, None);
+        assert!(!manager.plugin_dir.join(SANDBOXES_DIR).exists());
+
+        manager.config.write().await.sandbox_groups = [(
+            "dev-tools".to_string(),
+            vec!["local/git".to_string(), "build".to_string()],
+        )]
+        .into();
+        let (git_group, git) = manager
+            .with_sandbox_group("local/git", template.clone())
+            .await?;
+        let (build_group, build) = manager
+            .with_sandbox_group("local/build", template.clone())
+            .await?;
+        let (fetch_group, fetch) = manager
+            .with_sandbox_group("local/fetch", template.clone())
+            .await?;
+
+        // Both members see the same directory, and the component outside the group sees none
+        assert_eq!(git_group.as_deref(), Some("dev-tools"));
+        assert_eq!(build_group.as_deref(), Some("dev-tools"));
+        assert_eq!(git.preopened_dirs.len(), 1);
+        assert!(git.preopened_dirs == build.preopened_dirs);
+        assert_eq!(
+            git.preopened_dirs[0].host_path,
+            manager.plugin_dir.join(SANDBOXES_DIR).join("dev-tools")
+        );
+        assert_eq!(git.preopened_dirs[0].guest_path, SCRATCH_GUEST_PATH);
+        assert!(git.preopened_dirs[0].host_path.is_dir());
+        assert_eq!(fetch_group, None);
+        assert!(fetch.preopened_dirs.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Time windows of grants.
+//!
+//! The `schedule` section of a policy restricts some of its network and storage grants to times
+//! of day, e.g. a production API to business hours. The windows are evaluated when the state of a
+//! call is built: grants whose windows are all closed are left out. A call that is still running
+//! when the first window of the grants it got closes is stopped, so that a long execution can't
+//! keep using a grant past its window.
+
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use chrono_tz::Tz;
+use policy::{PolicyDocument, TimeWindow, Weekday};
+
+use crate::WasiStateTemplate;
+
+/// The time windows of a policy, with their time zones resolved
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GrantSchedule {
+    windows: Vec<(TimeWindow, Tz)>,
+}
+
+/// When the first window of the scheduled grants of a call closes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ScheduleDeadline {
+    /// Time until the window closes
+    pub(crate) remaining: Duration,
+    /// Name of the window
+    pub(crate) window: String,
+}
+
+/// Whether a grant applies at some time
+enum GrantState {
+    /// No window lists the grant, so it always applies
+    Unscheduled,
+    /// Every window that lists the grant is closed
+    Closed,
+    /// A window that lists the grant is open, for the given time
+    Open(ScheduleDeadline),
+}
+
+impl GrantSchedule {
+    /// Reads the windows of a policy, failing if one has a time zone that doesn't exist
+    pub(crate) fn from_policy(policy: &PolicyDocument) -> Result<Self> {
+        let windows = policy
+            .permissions
+            .schedule
+            .iter()
+            .flat_map(|schedule| &schedule.windows)
+            .map(|window| {
+                let timezone = match &window.timezone {
+                    Some(name) => name
+                        .parse()
+                        .map_err(|_| anyhow!("Unknown time zone in schedule: {name}"))?,
+                    None => Tz::UTC,
+                };
+                Ok((window.clone(), timezone))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { windows })
+    }
+
+    /// Returns whether the policy has no windows
+    pub(crate) fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// Returns whether the grant the windows matching `lists` restrict applies at `now`
+    fn state(&self, now: DateTime<Utc>, lists: impl Fn(&TimeWindow) -> bool) -> GrantState {
+        let mut state = GrantState::Unscheduled;
+        for (window, timezone) in self.windows.iter().filter(|(window, _)| lists(window)) {
+            let local = now.with_timezone(timezone);
+            let day = Weekday::ALL[local.weekday().num_days_from_monday() as usize];
+            let open_for = window.open_for(day, local.hour() * 60 + local.minute());
+            state = match (state, open_for) {
+                (GrantState::Open(open), None) => GrantState::Open(open),
+                (_, None) => GrantState::Closed,
+                (state, Some(minutes)) => {
+                    let remaining =
+                        Duration::from_secs(u64::from(minutes) * 60 - u64::from(local.second()));
+                    match state {
+                        GrantState::Open(open) if open.remaining >= remaining => {
+                            GrantState::Open(open)
+                        }
+                        _ => GrantState::Open(ScheduleDeadline {
+                            remaining,
+                            window: window.label(),
+                        }),
+                    }
+                }
+            };
+        }
+        state
+    }
+}
+
+/// Runs a call, stopping it if the window of its deadline closes before it finishes
+pub(crate) async fn until_deadline<T>(
+    deadline: Option<ScheduleDeadline>,
+    call: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout(deadline.remaining, call)
+            .await
+            .unwrap_or_else(|_| {
+                Err(anyhow!(
+                    "Tool call stopped because the time window '{}' of its grants closed",
+                    deadline.window
+                ))
+            }),
+        None => call.await,
+    }
+}
+
+impl WasiStateTemplate {
+    /// Returns the template for a call made at `now`, without the grants whose windows are all
+    /// closed, and when the first window of the scheduled grants it keeps closes
+    pub(crate) fn scheduled_at(
+        &self,
+        now: DateTime<Utc>,
+    ) -> (WasiStateTemplate, Option<ScheduleDeadline>) {
+        let mut deadline: Option<ScheduleDeadline> = None;
+        let mut applies = |state: GrantState| match state {
+            GrantState::Unscheduled => true,
+            GrantState::Closed => false,
+            GrantState::Open(open) => {
+                if deadline
+                    .as_ref()
+                    .is_none_or(|first| open.remaining < first.remaining)
+                {
+                    deadline = Some(open);
+                }
+                true
+            }
+        };
+        let allowed_hosts = self
+            .allowed_hosts
+            .iter()
+            .filter(|host| {
+                applies(
+                    self.schedule
+                        .state(now, |window| window.hosts.contains(*host)),
+                )
+            })
+            .cloned()
+            .collect();
+        let preopened_dirs = self
+            .preopened_dirs
+            .iter()
+            .filter(|dir| {
+                applies(self.schedule.state(now, |window| {
+                    window
+                        .storage
+                        .iter()
+                        .any(|uri| restricts_storage(uri, &dir.guest_path))
+                }))
+            })
+            .cloned()
+            .collect();
+        let template = WasiStateTemplate {
+            allowed_hosts,
+            preopened_dirs,
+            ..self.clone()
+        };
+        (template, deadline)
+    }
+}
+
+/// Returns whether a window listing the storage `uri` restricts the directory opened at
+/// `guest_path`. Paths are compared by their components, so `fs://reports/` lists `reports`
+/// too. A directory inside the listed one or containing it is restricted as well, since it
+/// reaches the scheduled files.
+fn restricts_storage(uri: &str, guest_path: &str) -> bool {
+    let Some(listed) = uri.strip_prefix("fs://") else {
+        return false;
+    };
+    let normalize = |path: &str| -> PathBuf {
+        Path::new(path)
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .collect()
+    };
+    let (listed, opened) = (normalize(listed), normalize(guest_path));
+    opened.starts_with(&listed) || listed.starts_with(&opened)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::TimeZone;
+    use policy::PolicyParser;
+
+    use super::*;
+    use crate::create_wasi_state_template_from_policy;
+
+    const POLICY: &str = r#"
+version: "1.4"
+permissions:
+  network:
+    allow:
+      - host: "api.prod.example.com"
+      - host: "api.staging.example.com"
+  storage:
+    allow:
+      - uri: "fs://reports"
+        access: ["read"]
+  schedule:
+    windows:
+      - name: "business-hours"
+        timezone: "America/New_York"
+        days: ["mon", "tue", "wed", "thu", "fri"]
+        start: "09:00"
+        end: "17:00"
+        hosts: ["api.prod.example.com"]
+        storage: ["fs://reports"]
+"#;
+
+    #[test]
+    fn test_grants_follow_their_window() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let policy = PolicyParser::parse_str(POLICY)?;
+        let template = create_wasi_state_template_from_policy(&policy, dir.path())?;
+
+        // Wednesday 16:30 in New York
+        let (during, deadline) =
+            template.scheduled_at(Utc.with_ymd_and_hms(2025, 6, 4, 20, 30, 0).unwrap());
+        assert_eq!(during.allowed_hosts.len(), 2);
+        assert_eq!(during.preopened_dirs.len(), 1);
+        assert_eq!(
+            deadline,
+            Some(ScheduleDeadline {
+                remaining: Duration::from_secs(30 * 60),
+                window: "business-hours".to_string(),
+            })
+        );
+
+        // Saturday noon in New York
+        let (weekend, deadline) =
+            template.scheduled_at(Utc.with_ymd_and_hms(2025, 6, 7, 16, 0, 0).unwrap());
+        assert_eq!(
+            weekend.allowed_hosts,
+            HashSet::from(["api.staging.example.com".to_string()])
+        );
+        assert!(weekend.preopened_dirs.is_empty());
+        assert_eq!(deadline, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_windows_match_by_path() {
+        assert!(restricts_storage("fs://reports", "reports"));
+        assert!(restricts_storage("fs://reports/", "./reports"));
+        assert!(restricts_storage("fs://reports", "reports/2024"));
+        assert!(restricts_storage("fs://reports/2024", "reports"));
+        assert!(!restricts_storage("fs://reports", "reports-archive"));
+        assert!(!restricts_storage("https://reports", "reports"));
+    }
+
+    #[test]
+    fn test_unknown_time_zone() -> Result<()> {
+        let policy = PolicyParser::parse_str(POLICY.replace("America/New_York", "Mars/Olympus"))?;
+        assert!(GrantSchedule::from_policy(&policy).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Differences between the tool schemas of two versions of a component.
+//!
+//! When a component is replaced, its old and new tools are compared by name and by parameter, so
+//! that agents and operators can tell when the contract of a tool changed in a way that breaks
+//! existing callers.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::ToolSchema;
+
+/// How the tools of a component changed when it was replaced
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SchemaDiff {
+    /// Tools that only the new version has
+    pub added: Vec<String>,
+    /// Tools that only the old version had
+    pub removed: Vec<String>,
+    /// Tools whose parameters or result changed
+    pub changed: Vec<ToolChange>,
+    /// Whether callers of the old version may fail with the new one
+    pub breaking: bool,
+}
+
+/// How the schema of a tool changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ToolChange {
+    /// Name of the tool
+    pub tool: String,
+    /// Changes of the tool's parameters, by parameter name
+    pub parameters: Vec<ParameterChange>,
+    /// Whether the schema of the tool's result changed
+    pub output_changed: bool,
+    /// Whether callers of the old version of the tool may fail with the new one
+    pub breaking: bool,
+}
+
+/// A change of one parameter of a tool
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParameterChange {
+    /// Name of the parameter
+    pub parameter: String,
+    /// What changed
+    pub change: ParameterChangeKind,
+}
+
+/// What changed about a parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterChangeKind {
+    /// The parameter is new and must be given
+    AddedRequired,
+    /// The parameter is new and may be left out
+    AddedOptional,
+    /// The parameter no longer exists
+    Removed,
+    /// The parameter used to be optional and must now be given
+    BecameRequired,
+    /// The parameter used to be required and may now be left out
+    BecameOptional,
+    /// The type of the parameter changed
+    TypeChanged,
+}
+
+impl ParameterChangeKind {
+    /// Whether calls that were valid before the change may be rejected after it
+    pub fn is_breaking(self) -> bool {
+        !matches!(self, Self::AddedOptional | Self::BecameOptional)
+    }
+}
+
+impl SchemaDiff {
+    /// Compares the tools of the old and the new version of a component
+    pub fn between(old: &[ToolSchema], new: &[ToolSchema]) -> Self {
+        let old: BTreeMap<&str, &ToolSchema> = old.iter().map(|t| (t.name.as_str(), t)).collect();
+        let new: BTreeMap<&str, &ToolSchema> = new.iter().map(|t| (t.name.as_str(), t)).collect();
+
+        let added: Vec<String> = new
+            .keys()
+            .filter(|name| !old.contains_key(*name))
+            .map(|name| name.to_string())
+            .collect();
+        let removed: Vec<String> = old
+            .keys()
+            .filter(|name| !new.contains_key(*name))
+            .map(|name| name.to_string())
+            .collect();
+        let changed: Vec<ToolChange> = old
+            .iter()
+            .filter_map(|(name, old)| diff_tool(old, new.get(name)?))
+            .collect();
+        let breaking = !removed.is_empty() || changed.iter().any(|change| change.breaking);
+        Self {
+            added,
+            removed,
+            changed,
+            breaking,
+        }
+    }
+
+    /// Whether the tools of the component are unchanged
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} tools added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )?;
+        if self.breaking {
+            write!(f, " (breaking)")?;
+        }
+        Ok(())
+    }
+}
+
+fn diff_tool(old: &ToolSchema, new: &ToolSchema) -> Option<ToolChange> {
+    let (old_params, old_required) = parameters(&old.input_schema);
+    let (new_params, new_required) = parameters(&new.input_schema);
+
+    let mut parameters = Vec::new();
+    for (name, new_schema) in &new_params {
+        let change = match old_params.get(name) {
+            None if new_required.contains(name) => Some(ParameterChangeKind::AddedRequired),
+            None => Some(ParameterChangeKind::AddedOptional),
+            Some(old_schema)
+                if without_descriptions(old_schema) != without_descriptions(new_schema) =>
+            {
+                Some(ParameterChangeKind::TypeChanged)
+            }
+            Some(_) => match (old_required.contains(name), new_required.contains(name)) {
+                (false, true) => Some(ParameterChangeKind::BecameRequired),
+                (true, false) => Some(ParameterChangeKind::BecameOptional),
+                _ => None,
+            },
+        };
+        if let Some(change) = change {
+            parameters.push(ParameterChange {
+                parameter: name.to_string(),
+                change,
+            });
+        }
+    }
+    for name in old_params
+        .keys()
+        .filter(|name| !new_params.contains_key(*name))
+    {
+        parameters.push(ParameterChange {
+            parameter: name.to_string(),
+            change: ParameterChangeKind::Removed,
+        });
+    }
+    parameters.sort_by(|a, b| a.parameter.cmp(&b.parameter));
+
+    let output_changed = old.output_schema.as_ref().map(without_descriptions)
+        != new.output_schema.as_ref().map(without_descriptions);
+    if parameters.is_empty() && !output_changed {
+        return None;
+    }
+    let breaking = output_changed || parameters.iter().any(|p| p.change.is_breaking());
+    Some(ToolChange {
+        tool: new.name.clone(),
+        parameters,
+        output_changed,
+        breaking,
+    })
+}
+
+/// Returns the parameters of a tool's input schema and the names of the required ones
+fn parameters(input_schema: &Value) -> (BTreeMap<&str, &Value>, BTreeSet<&str>) {
+    let properties = input_schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|properties| properties.iter().map(|(k, v)| (k.as_str(), v)).collect())
+        .unwrap_or_default();
+    let required = input_schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    (properties, required)
+}
+
+/// Returns a schema without its `description` fields, which don't change what values it accepts
+fn without_descriptions(schema: &Value) -> Value {
+    match schema {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .filter(|(key, value)| !(*key == "description" && value.is_string()))
+                .map(|(key, value)| (key.clone(), without_descriptions(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(without_descriptions).collect()),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn tool(name: &str, input_schema: Value) -> ToolSchema {
+        ToolSchema {
+            name: name.to_string(),
+            description: None,
+            input_schema,
+            output_schema: None,
+            annotations: None,
+        }
+    }
+
+    #[test]
+    fn test_schema_diff() {
+        let old = [
+            tool(
+                "fetch",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "url": {"type": "string", "description": "URL to fetch"},
+                        "timeout": {"type": "number"},
+                    },
+                    "required": ["url"],
+                }),
+            ),
+            tool("ping", json!({"type": "object", "properties": {}})),
+        ];
+        let new = [
+            tool(
+                "fetch",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "url": {"type": "string", "description": "The URL"},
+                        "timeout": {"type": "number"},
+                        "headers": {"type": "object"},
+                    },
+                    "required": ["url"],
+                }),
+            ),
+            tool("ping", json!({"type": "object", "properties": {}})),
+            tool("head", json!({"type": "object", "properties": {}})),
+        ];
+
+        let diff = SchemaDiff::between(&old, &new);
+        assert_eq!(diff.added, vec!["head"]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(
+            diff.changed[0].parameters,
+            vec![ParameterChange {
+                parameter: "headers".to_string(),
+                change: ParameterChangeKind::AddedOptional,
+            }]
+        );
+        assert!(!diff.breaking);
+        assert!(SchemaDiff::between(&new, &new).is_empty());
+
+        // Going back drops a tool, which breaks its callers
+        let diff = SchemaDiff::between(&new, &old);
+        assert_eq!(diff.removed, vec!["head"]);
+        assert!(diff.breaking);
+    }
+
+    #[test]
+    fn test_breaking_parameter_changes() {
+        let old = [tool(
+            "fetch",
+            json!({
+                "type": "object",
+                "properties": {
+                    "url": {"type": "string"},
+                    "timeout": {"type": "number"},
+                    "method": {"type": "string"},
+                },
+                "required": ["url"],
+            }),
+        )];
+        let new = [tool(
+            "fetch",
+            json!({
+                "type": "object",
+                "properties": {
+                    "url": {"type": "string"},
+                    "timeout": {"type": "string"},
+                    "body": {"type": "string"},
+                },
+                "required": ["url", "body"],
+            }),
+        )];
+
+        let diff = SchemaDiff::between(&old, &new);
+        let changes: Vec<_> = diff.changed[0]
+            .parameters
+            .iter()
+            .map(|p| (p.parameter.as_str(), p.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("body", ParameterChangeKind::AddedRequired),
+                ("method", ParameterChangeKind::Removed),
+                ("timeout", ParameterChangeKind::TypeChanged),
+            ]
+        );
+        assert!(diff.changed[0].breaking);
+        assert!(diff.breaking);
+    }
+}
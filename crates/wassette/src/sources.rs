@@ -0,0 +1,188 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Loaders for URI schemes beyond the built-in ones.
+//!
+//! Components and policies are loaded from `file://`, `oci://`, `https://` and `git+https://` URIs
+//! out of the box.
+//! An embedder can register a [`SourceLoader`] for another scheme, e.g. `s3://` or `ipfs://`, with
+//! [`LifecycleManager::register_source_loader`]. The loader fetches the resource into a local file,
+//! which is then read like a `file://` URI. The `allowed_schemes` setting of the
+//! [`LifecycleConfig`](crate::LifecycleConfig) limits the schemes that may be loaded from, built-in
+//! or registered.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use futures::future::BoxFuture;
+use tracing::info;
+
+use crate::LifecycleManager;
+
+/// Schemes wassette loads from itself, which can't be registered
+pub const BUILTIN_SCHEMES: &[&str] = &["file", "oci", "https", crate::GIT_SCHEME];
+
+/// What a [`SourceLoader`] is asked to fetch
+#[derive(Debug, Clone, Copy)]
+pub struct SourceRequest<'a> {
+    /// The URI to fetch, including its scheme
+    pub uri: &'a str,
+    /// Type of the resource, `component` or `policy`
+    pub resource_type: &'a str,
+    /// Extension the fetched file must have, `wasm` for components and `yaml` for policies.
+    /// Components may also be fetched compressed, as `.wasm.zst` or `.wasm.gz`
+    pub extension: &'a str,
+    /// Empty directory to write the fetched file to. It is deleted once the resource is loaded
+    pub dir: &'a Path,
+}
+
+/// Fetches resources for a URI scheme
+pub trait SourceLoader: Send + Sync {
+    /// Fetches the resource into a file in `request.dir` and returns the path of the file. The
+    /// file name without its extension becomes the name of the resource, and with it the ID of a
+    /// component.
+    fn fetch<'a>(&'a self, request: SourceRequest<'a>) -> BoxFuture<'a, Result<PathBuf>>;
+}
+
+/// The registered loaders by scheme
+#[derive(Default)]
+pub(crate) struct SourceLoaders {
+    loaders: BTreeMap<String, Arc<dyn SourceLoader>>,
+}
+
+impl SourceLoaders {
+    fn register(&mut self, scheme: &str, loader: Arc<dyn SourceLoader>) -> Result<()> {
+        validate_scheme(scheme)?;
+        if BUILTIN_SCHEMES.contains(&scheme) {
+            bail!("The {scheme} scheme is built in and can't be replaced");
+        }
+        self.loaders.insert(scheme.to_string(), loader);
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, scheme: &str) -> Option<Arc<dyn SourceLoader>> {
+        self.loaders.get(scheme).cloned()
+    }
+
+    fn schemes(&self) -> Vec<String> {
+        self.loaders.keys().cloned().collect()
+    }
+}
+
+/// Checks that a scheme is made of what RFC 3986 allows: a letter followed by letters, digits,
+/// `+`, `-` and `.`. Schemes are registered and matched in lower case.
+fn validate_scheme(scheme: &str) -> Result<()> {
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c));
+    if !valid {
+        bail!("Invalid URI scheme: {scheme:?}");
+    }
+    Ok(())
+}
+
+/// Fails unless `scheme` is one of `allowed`, if the schemes are limited
+pub(crate) fn check_scheme_allowed(allowed: Option<&[String]>, scheme: &str) -> Result<()> {
+    match allowed {
+        Some(allowed) if !allowed.iter().any(|allowed| allowed == scheme) => bail!(
+            "Loading from {scheme}:// URIs is not allowed by the configuration. Allowed schemes: {}",
+            allowed.join(", ")
+        ),
+        _ => Ok(()),
+    }
+}
+
+impl LifecycleManager {
+    /// Registers the loader of a URI scheme, e.g. `s3`, replacing the one registered before. The
+    /// [built-in schemes](BUILTIN_SCHEMES) can't be replaced.
+    pub async fn register_source_loader(
+        &self,
+        scheme: &str,
+        loader: impl SourceLoader + 'static,
+    ) -> Result<()> {
+        self.sources
+            .write()
+            .await
+            .register(scheme, Arc::new(loader))?;
+        info!(scheme, "Registered source loader");
+        Ok(())
+    }
+
+    /// Returns the schemes that loaders are registered for, besides the built-in ones
+    pub async fn registered_schemes(&self) -> Vec<String> {
+        self.sources.read().await.schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_example_component, create_test_manager};
+
+    struct NoopLoader;
+
+    impl SourceLoader for NoopLoader {
+        fn fetch<'a>(&'a self, request: SourceRequest<'a>) -> BoxFuture<'a, Result<PathBuf>> {
+            Box::pin(async move { Ok(request.dir.join(format!("noop.{}", request.extension))) })
+        }
+    }
+
+    #[test]
+    fn test_register_schemes() {
+        let mut loaders = SourceLoaders::default();
+        loaders.register("s3", Arc::new(NoopLoader)).unwrap();
+        loaders.register("git+ssh", Arc::new(NoopLoader)).unwrap();
+        assert!(loaders.get("s3").is_some());
+        assert!(loaders.get("ipfs").is_none());
+        assert_eq!(loaders.schemes(), vec!["git+ssh", "s3"]);
+
+        assert!(loaders.register("oci", Arc::new(NoopLoader)).is_err());
+        assert!(loaders.register("S3", Arc::new(NoopLoader)).is_err());
+        assert!(loaders.register("3d", Arc::new(NoopLoader)).is_err());
+        assert!(loaders.register("", Arc::new(NoopLoader)).is_err());
+    }
+
+    /// Serves the example component for any URI
+    struct ExampleLoader(PathBuf);
+
+    impl SourceLoader for ExampleLoader {
+        fn fetch<'a>(&'a self, request: SourceRequest<'a>) -> BoxFuture<'a, Result<PathBuf>> {
+            Box::pin(async move {
+                let path = request.dir.join(format!("fetch_rs.{}", request.extension));
+                tokio::fs::copy(&self.0, &path).await?;
+                Ok(path)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load_from_registered_scheme() -> Result<()> {
+        let manager = create_test_manager().await?;
+        assert!(manager
+            .load_component("mem://fetch_rs")
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported component scheme: mem"));
+
+        let component = build_example_component().await?;
+        manager
+            .register_source_loader("mem", ExampleLoader(component))
+            .await?;
+        assert_eq!(manager.registered_schemes().await, vec!["mem"]);
+        let (id, _) = manager.load_component("mem://fetch_rs").await?;
+        assert_eq!(id, "mem/fetch_rs");
+        Ok(())
+    }
+
+    #[test]
+    fn test_allowed_schemes() {
+        let allowed = vec!["oci".to_string(), "s3".to_string()];
+        assert!(check_scheme_allowed(None, "file").is_ok());
+        assert!(check_scheme_allowed(Some(&allowed), "s3").is_ok());
+        let error = check_scheme_allowed(Some(&allowed), "file").unwrap_err();
+        assert!(error.to_string().contains("oci, s3"));
+    }
+}
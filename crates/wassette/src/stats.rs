@@ -0,0 +1,418 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Per-tool usage statistics, persisted to the plugin directory so they survive restarts. The
+//! most recent errors of each component are kept too, but only in memory.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument, warn};
+
+use crate::{LifecycleManager, MemoryUsage};
+
+/// Name of the file in the plugin directory that usage statistics are persisted to
+pub const STATS_FILE: &str = "tool-stats.json";
+
+const PERSIST_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Number of errors kept per component
+const RECENT_ERRORS: usize = 10;
+
+/// Usage statistics of a single tool
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolStats {
+    /// Number of times the tool was called
+    pub invocations: u64,
+    /// Number of calls that completed successfully
+    pub successes: u64,
+    /// Number of calls that failed
+    pub failures: u64,
+    /// Combined duration of all calls, in milliseconds
+    pub total_duration_ms: u64,
+    /// Time of the most recent call, in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_called: Option<u64>,
+    /// Largest peak size of the linear memories of any call, in bytes
+    pub peak_memory_bytes: u64,
+    /// Largest peak number of table elements of any call
+    pub peak_table_elements: u64,
+    /// Number of successful calls answered with a memoized result of a pure tool
+    pub memoized: u64,
+}
+
+impl ToolStats {
+    /// Returns the fraction of calls that succeeded, or `None` if the tool was never called
+    pub fn success_rate(&self) -> Option<f64> {
+        (self.invocations > 0).then(|| self.successes as f64 / self.invocations as f64)
+    }
+
+    /// Returns the average duration of a call in milliseconds, or `None` if the tool was never
+    /// called
+    pub fn average_latency_ms(&self) -> Option<f64> {
+        (self.invocations > 0).then(|| self.total_duration_ms as f64 / self.invocations as f64)
+    }
+
+    fn record(&mut self, duration: Duration, success: bool, memory: MemoryUsage) {
+        self.invocations += 1;
+        self.peak_memory_bytes = self.peak_memory_bytes.max(memory.peak_memory_bytes);
+        self.peak_table_elements = self.peak_table_elements.max(memory.peak_table_elements);
+        if success {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+        self.total_duration_ms += duration.as_millis() as u64;
+        self.touch();
+    }
+
+    fn record_memoized(&mut self, duration: Duration) {
+        self.invocations += 1;
+        self.successes += 1;
+        self.memoized += 1;
+        self.total_duration_ms += duration.as_millis() as u64;
+        self.touch();
+    }
+
+    fn touch(&mut self) {
+        self.last_called = now();
+    }
+}
+
+fn now() -> Option<u64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// A failed call to a tool
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecentError {
+    /// Name of the tool that was called
+    pub tool: String,
+    /// The error the call failed with
+    pub message: String,
+    /// Time of the call, in seconds since the Unix epoch
+    pub time: Option<u64>,
+}
+
+/// Usage statistics of every tool, grouped by component ID and then by tool name
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageStats {
+    /// Statistics per component, keyed by component ID and then by tool name
+    pub components: BTreeMap<String, BTreeMap<String, ToolStats>>,
+}
+
+impl UsageStats {
+    /// Reads the statistics persisted in the given plugin directory. Returns empty statistics if
+    /// nothing was persisted yet.
+    pub async fn load(plugin_dir: impl AsRef<Path>) -> Result<Self> {
+        let path = plugin_dir.as_ref().join(STATS_FILE);
+        match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid tool statistics file: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read tool statistics: {}", path.display())),
+        }
+    }
+
+    /// Returns the statistics of every tool as `(component ID, tool name, stats)`
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &ToolStats)> {
+        self.components.iter().flat_map(|(component_id, tools)| {
+            tools
+                .iter()
+                .map(move |(tool, stats)| (component_id.as_str(), tool.as_str(), stats))
+        })
+    }
+}
+
+pub(crate) struct StatsStore {
+    path: PathBuf,
+    stats: UsageStats,
+    recent_errors: BTreeMap<String, VecDeque<RecentError>>,
+    dirty: bool,
+    last_persisted: Instant,
+}
+
+impl StatsStore {
+    pub(crate) async fn open(plugin_dir: &Path) -> Self {
+        let stats = UsageStats::load(plugin_dir).await.unwrap_or_else(|e| {
+            warn!(error = %e, "Discarding unreadable tool statistics");
+            UsageStats::default()
+        });
+        Self {
+            path: plugin_dir.join(STATS_FILE),
+            stats,
+            recent_errors: BTreeMap::new(),
+            dirty: false,
+            last_persisted: Instant::now(),
+        }
+    }
+
+    fn record(
+        &mut self,
+        component_id: &str,
+        tool_name: &str,
+        duration: Duration,
+        error: Option<&anyhow::Error>,
+        memory: MemoryUsage,
+    ) {
+        self.tool(component_id, tool_name)
+            .record(duration, error.is_none(), memory);
+        if let Some(error) = error {
+            let errors = self
+                .recent_errors
+                .entry(component_id.to_string())
+                .or_default();
+            if errors.len() == RECENT_ERRORS {
+                errors.pop_front();
+            }
+            errors.push_back(RecentError {
+                tool: tool_name.to_string(),
+                message: format!("{error:#}"),
+                time: now(),
+            });
+        }
+    }
+
+    fn record_memoized(&mut self, component_id: &str, tool_name: &str, duration: Duration) {
+        self.tool(component_id, tool_name).record_memoized(duration);
+    }
+
+    /// Returns the statistics of a tool to update
+    fn tool(&mut self, component_id: &str, tool_name: &str) -> &mut ToolStats {
+        self.dirty = true;
+        self.stats
+            .components
+            .entry(component_id.to_string())
+            .or_default()
+            .entry(tool_name.to_string())
+            .or_default()
+    }
+
+    /// Returns the serialized statistics if there are unsaved changes and either `force` is set
+    /// or the persist interval has passed
+    fn take_snapshot(&mut self, force: bool) -> Result<Option<(PathBuf, Vec<u8>)>> {
+        if !self.dirty || (!force && self.last_persisted.elapsed() < PERSIST_INTERVAL) {
+            return Ok(None);
+        }
+        let data = serde_json::to_vec_pretty(&self.stats)?;
+        self.dirty = false;
+        self.last_persisted = Instant::now();
+        Ok(Some((self.path.clone(), data)))
+    }
+}
+
+async fn persist(snapshot: Result<Option<(PathBuf, Vec<u8>)>>) {
+    let persisted = match snapshot {
+        Ok(snapshot) => write_snapshot(snapshot).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = persisted {
+        warn!(error = %e, "Failed to persist tool statistics");
+    }
+}
+
+async fn write_snapshot(snapshot: Option<(PathBuf, Vec<u8>)>) -> Result<()> {
+    if let Some((path, data)) = snapshot {
+        debug!(path = %path.display(), "Persisting tool statistics");
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("Failed to write tool statistics: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+impl LifecycleManager {
+    /// Records a call to a tool. Statistics are persisted at most every 30 seconds; use
+    /// [`LifecycleManager::flush_tool_stats`] to persist them right away.
+    pub(crate) async fn record_tool_call(
+        &self,
+        component_id: &str,
+        tool_name: &str,
+        duration: Duration,
+        error: Option<&anyhow::Error>,
+        memory: MemoryUsage,
+    ) {
+        let snapshot = {
+            let mut store = self.stats.write().await;
+            store.record(component_id, tool_name, duration, error, memory);
+            store.take_snapshot(false)
+        };
+        persist(snapshot).await;
+    }
+
+    /// Records a call to a pure tool that was answered with a memoized result
+    pub(crate) async fn record_memoized_call(
+        &self,
+        component_id: &str,
+        tool_name: &str,
+        duration: Duration,
+    ) {
+        let snapshot = {
+            let mut store = self.stats.write().await;
+            store.record_memoized(component_id, tool_name, duration);
+            store.take_snapshot(false)
+        };
+        persist(snapshot).await;
+    }
+
+    /// Returns the usage statistics of all tools, or only those of the given component
+    #[instrument(skip(self))]
+    pub async fn get_tool_stats(&self, component_id: Option<&str>) -> UsageStats {
+        let store = self.stats.read().await;
+        let mut stats = store.stats.clone();
+        if let Some(component_id) = component_id {
+            stats.components.retain(|id, _| id == component_id);
+        }
+        stats
+    }
+
+    /// Returns the most recent failed calls to the tools of a component, oldest first. Errors
+    /// aren't persisted, so only those since the server started are returned.
+    pub async fn get_recent_errors(&self, component_id: &str) -> Vec<RecentError> {
+        self.stats
+            .read()
+            .await
+            .recent_errors
+            .get(component_id)
+            .map(|errors| errors.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Drops the statistics of a component, e.g. when it is unloaded
+    pub(crate) async fn remove_tool_stats(&self, component_id: &str) {
+        let snapshot = {
+            let mut store = self.stats.write().await;
+            store.recent_errors.remove(component_id);
+            if store.stats.components.remove(component_id).is_none() {
+                return;
+            }
+            store.dirty = true;
+            store.take_snapshot(true)
+        };
+        persist(snapshot).await;
+    }
+
+    /// Persists any usage statistics that haven't been written yet
+    #[instrument(skip(self))]
+    pub async fn flush_tool_stats(&self) -> Result<()> {
+        let snapshot = self.stats.write().await.take_snapshot(true)?;
+        write_snapshot(snapshot).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_stats_record() {
+        let mut stats = ToolStats::default();
+        assert_eq!(stats.success_rate(), None);
+        assert_eq!(stats.average_latency_ms(), None);
+
+        stats.record(
+            Duration::from_millis(10),
+            true,
+            MemoryUsage {
+                peak_memory_bytes: 131072,
+                peak_table_elements: 4,
+            },
+        );
+        stats.record(
+            Duration::from_millis(30),
+            false,
+            MemoryUsage {
+                peak_memory_bytes: 65536,
+                peak_table_elements: 8,
+            },
+        );
+        assert_eq!(stats.invocations, 2);
+        assert_eq!(stats.successes, 1);
+        assert_eq!(stats.failures, 1);
+        assert_eq!(stats.success_rate(), Some(0.5));
+        assert_eq!(stats.average_latency_ms(), Some(20.0));
+        assert_eq!(stats.peak_memory_bytes, 131072);
+        assert_eq!(stats.peak_table_elements, 8);
+        assert!(stats.last_called.is_some());
+
+        stats.record_memoized(Duration::ZERO);
+        assert_eq!(stats.invocations, 3);
+        assert_eq!(stats.successes, 2);
+        assert_eq!(stats.memoized, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stats_store_persists() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let mut store = StatsStore::open(tempdir.path()).await;
+        store.record(
+            "fetch_rs",
+            "fetch",
+            Duration::from_millis(5),
+            None,
+            MemoryUsage::default(),
+        );
+
+        // Nothing is written before the persist interval passes unless forced
+        assert!(store.take_snapshot(false)?.is_none());
+        write_snapshot(store.take_snapshot(true)?).await?;
+        assert!(store.take_snapshot(true)?.is_none());
+
+        let loaded = UsageStats::load(tempdir.path()).await?;
+        assert_eq!(loaded, store.stats);
+        let entries: Vec<_> = loaded
+            .iter()
+            .map(|(c, t, s)| (c, t, s.invocations))
+            .collect();
+        assert_eq!(entries, vec![("fetch_rs", "fetch", 1)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recent_errors_are_bounded() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let mut store = StatsStore::open(tempdir.path()).await;
+        for i in 0..RECENT_ERRORS + 2 {
+            let error = anyhow::anyhow!("failure {i}");
+            store.record(
+                "fetch_rs",
+                "fetch",
+                Duration::ZERO,
+                Some(&error),
+                MemoryUsage::default(),
+            );
+        }
+        let errors = &store.recent_errors["fetch_rs"];
+        assert_eq!(errors.len(), RECENT_ERRORS);
+        assert_eq!(
+            errors.front().map(|e| e.message.as_str()),
+            Some("failure 2")
+        );
+        assert_eq!(store.stats.components["fetch_rs"]["fetch"].failures, 12);
+
+        // Errors are only kept in memory
+        write_snapshot(store.take_snapshot(true)?).await?;
+        let persisted = tokio::fs::read_to_string(tempdir.path().join(STATS_FILE)).await?;
+        assert!(!persisted.contains("failure 2"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missing_stats_file() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        assert_eq!(
+            UsageStats::load(tempdir.path()).await?,
+            UsageStats::default()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Introspection of the runtime's resource usage, to help diagnose a growing wassette process.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::precompile::{PRECOMPILED_DIR, PRECOMPILED_EXTENSION};
+use crate::LifecycleManager;
+
+/// A snapshot of the resources used by a [`LifecycleManager`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerStatus {
+    /// Resident set size of the process in bytes, if the platform reports it
+    pub resident_memory_bytes: Option<u64>,
+    /// Number of stores, each holding one component instance, that are currently alive
+    pub live_stores: usize,
+    /// Number of MCP sessions currently open
+    #[serde(default)]
+    pub active_sessions: usize,
+    /// Number of loaded components
+    pub loaded_components: usize,
+    /// Number of registered tools across all components
+    pub registered_tools: usize,
+    /// Number of staged uploads that haven't been used or discarded yet
+    pub pending_uploads: usize,
+    /// Combined size of all staged uploads, in bytes
+    pub pending_upload_bytes: usize,
+    /// Number of notifications waiting to be sent to the clients of all sessions
+    #[serde(default)]
+    pub queued_notifications: usize,
+    /// Number of notifications dropped because the queue of their session was full, since the
+    /// server started
+    #[serde(default)]
+    pub dropped_notifications: u64,
+    /// Precompiled components found in the plugin directory, and how often loading a component
+    /// could use one instead of compiling it
+    #[serde(default)]
+    pub compilation_cache: CacheStats,
+    /// Results of pure tools kept for identical calls, and how often a call could reuse one
+    #[serde(default)]
+    pub result_cache: CacheStats,
+    /// Estimated memory use of each component, keyed by component ID
+    pub components: BTreeMap<String, ComponentMemory>,
+}
+
+/// Size and effectiveness of a cache
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Number of entries in the cache
+    pub entries: usize,
+    /// Lookups that found an entry, since the server started
+    pub hits: u64,
+    /// Lookups that found no entry, since the server started
+    pub misses: u64,
+    /// Share of lookups that found an entry, or `None` before the first lookup
+    pub hit_rate: Option<f64>,
+}
+
+/// Counts the hits and misses of a cache
+#[derive(Default)]
+pub(crate) struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheCounters {
+    pub(crate) fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self, entries: usize) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        CacheStats {
+            entries,
+            hits,
+            misses,
+            hit_rate: (lookups > 0).then(|| hits as f64 / lookups as f64),
+        }
+    }
+}
+
+/// Estimated memory use of a loaded component
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentMemory {
+    /// Size of the compiled code and data image kept in memory, in bytes
+    pub compiled_image_bytes: usize,
+    /// Number of tools the component exports
+    pub tools: usize,
+}
+
+/// Counts a store as live for as long as the guard exists
+pub(crate) struct LiveStoreGuard(Arc<AtomicUsize>);
+
+impl LiveStoreGuard {
+    pub(crate) fn new(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter.clone())
+    }
+}
+
+impl Drop for LiveStoreGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Notifications queued for and dropped by the clients of all sessions
+#[derive(Default)]
+pub(crate) struct NotificationCounters {
+    queued: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+/// Counts an MCP session as active in [`ServerStatus::active_sessions`] for as long as the guard
+/// exists, along with the notifications queued for its client. Get one with
+/// [`LifecycleManager::session_guard`].
+pub struct SessionGuard {
+    _guard: LiveStoreGuard,
+    notifications: Arc<NotificationCounters>,
+    queue_depth: AtomicUsize,
+}
+
+impl SessionGuard {
+    /// Records the number of notifications currently waiting to be sent to the session's client
+    pub fn set_queue_depth(&self, depth: usize) {
+        let previous = self.queue_depth.swap(depth, Ordering::Relaxed);
+        let queued = &self.notifications.queued;
+        if depth >= previous {
+            queued.fetch_add(depth - previous, Ordering::Relaxed);
+        } else {
+            queued.fetch_sub(previous - depth, Ordering::Relaxed);
+        }
+    }
+
+    /// Records notifications that were dropped because the session's queue was full
+    pub fn record_dropped_notifications(&self, count: u64) {
+        self.notifications
+            .dropped
+            .fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.set_queue_depth(0);
+    }
+}
+
+/// Reads the `VmRSS` line of a `/proc/<pid>/status` file
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Counts the precompiled components in the plugin directory
+async fn precompiled_artifacts(plugin_dir: &std::path::Path) -> usize {
+    let Ok(mut entries) = tokio::fs::read_dir(plugin_dir.join(PRECOMPILED_DIR)).await else {
+        return 0;
+    };
+    let mut count = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry
+            .path()
+            .extension()
+            .is_some_and(|ext| ext == PRECOMPILED_EXTENSION)
+        {
+            count += 1;
+        }
+    }
+    count
+}
+
+fn resident_memory_bytes() -> Option<u64> {
+    // Only Linux has procfs, elsewhere the RSS is simply not reported
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .as_deref()
+        .and_then(parse_vm_rss)
+}
+
+impl LifecycleManager {
+    /// Returns a guard that counts a session as active until it is dropped
+    pub fn session_guard(&self) -> SessionGuard {
+        SessionGuard {
+            _guard: LiveStoreGuard::new(&self.active_sessions),
+            notifications: self.notifications.clone(),
+            queue_depth: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a snapshot of the resources the runtime currently uses
+    #[instrument(skip(self))]
+    pub async fn server_status(&self) -> ServerStatus {
+        let (tool_counts, registered_tools) = {
+            let registry = self.registry.read().await;
+            let tool_counts: BTreeMap<String, usize> = registry
+                .component_map
+                .iter()
+                .map(|(id, tools)| (id.clone(), tools.len()))
+                .collect();
+            (tool_counts, registry.tool_map.values().map(Vec::len).sum())
+        };
+
+        let components: BTreeMap<String, ComponentMemory> = self
+            .components
+            .read()
+            .await
+            .iter()
+            .map(|(id, instance)| {
+                let image = instance.component.image_range();
+                let memory = ComponentMemory {
+                    compiled_image_bytes: image.end as usize - image.start as usize,
+                    tools: tool_counts.get(id).copied().unwrap_or_default(),
+                };
+                (id.clone(), memory)
+            })
+            .collect();
+
+        let (pending_uploads, pending_upload_bytes) = self.uploads.read().await.usage();
+        let compilation_cache = self
+            .compilation_cache
+            .stats(precompiled_artifacts(&self.plugin_dir).await);
+        let result_cache = self.memo.read().await.stats();
+
+        ServerStatus {
+            resident_memory_bytes: resident_memory_bytes(),
+            live_stores: self.live_stores.load(Ordering::Relaxed),
+            active_sessions: self.active_sessions.load(Ordering::Relaxed),
+            loaded_components: components.len(),
+            registered_tools,
+            pending_uploads,
+            pending_upload_bytes,
+            queued_notifications: self.notifications.queued.load(Ordering::Relaxed),
+            dropped_notifications: self.notifications.dropped.load(Ordering::Relaxed),
+            compilation_cache,
+            result_cache,
+            components,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\twassette\nVmPeak:\t  204800 kB\nVmRSS:\t   51200 kB\nThreads:\t8\n";
+        assert_eq!(parse_vm_rss(status), Some(51200 * 1024));
+        assert_eq!(parse_vm_rss("Name:\twassette\n"), None);
+    }
+
+    #[test]
+    fn test_live_store_guard() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let first = LiveStoreGuard::new(&counter);
+        let second = LiveStoreGuard::new(&counter);
+        assert_eq!(counter.load(Ordering::Relaxed), 2);
+        drop(first);
+        drop(second);
+        assert_eq!(counter.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_cache_counters() {
+        let counters = CacheCounters::default();
+        assert_eq!(counters.stats(0).hit_rate, None);
+        counters.record(true);
+        counters.record(true);
+        counters.record(true);
+        counters.record(false);
+        let stats = counters.stats(7);
+        assert_eq!((stats.entries, stats.hits, stats.misses), (7, 3, 1));
+        assert_eq!(stats.hit_rate, Some(0.75));
+    }
+
+    #[tokio::test]
+    async fn test_session_queue_depth() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path()).await?;
+        let first = manager.session_guard();
+        let second = manager.session_guard();
+        first.set_queue_depth(5);
+        second.set_queue_depth(3);
+        first.set_queue_depth(2);
+        second.record_dropped_notifications(4);
+        let status = manager.server_status().await;
+        assert_eq!(status.queued_notifications, 5);
+        assert_eq!(status.dropped_notifications, 4);
+
+        // A closed session no longer counts what it had queued
+        drop(second);
+        let status = manager.server_status().await;
+        assert_eq!(status.queued_notifications, 2);
+        assert_eq!(status.active_sessions, 1);
+        Ok(())
+    }
+}
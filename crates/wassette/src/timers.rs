@@ -0,0 +1,173 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Timers with a per-call sleep budget.
+//!
+//! Components sleep, poll and back off by waiting on the pollables of `wasi:clocks/monotonic-clock`.
+//! This module replaces that interface with one that counts the duration of every timer a call
+//! creates against [`max_sleep_seconds_per_call`](policy::Budget::max_sleep_seconds_per_call),
+//! so a tool can't keep a call, and the client waiting for it, busy by sleeping. Timers count
+//! when they are created, whether or not the component waits for them, and creating a timer that
+//! exceeds the budget fails the call.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use wasmtime::component::{Linker, Resource};
+use wasmtime::StoreContextMut;
+use wasmtime_wasi::p2::{DynPollable, Pollable};
+use wasmtime_wasi::HostMonotonicClock;
+
+use crate::{WasiState, WassetteWasiState};
+
+/// Versions of `wasi:clocks` whose monotonic clock is replaced. The linker resolves imports of a
+/// later 0.2 version to the newest of these, so the last one must not be older than the
+/// `wasi:clocks` of `wasmtime_wasi`, or those imports would get its clock without a sleep budget.
+const CLOCK_VERSIONS: [&str; 7] = [
+    "0.2.0", "0.2.1", "0.2.2", "0.2.3", "0.2.4", "0.2.5", "0.2.6",
+];
+
+/// The monotonic clock of a call, with the duration its timers may still add up to
+pub(crate) struct CallClock {
+    clock: Box<dyn HostMonotonicClock + Send>,
+    budget: Option<SleepBudget>,
+}
+
+struct SleepBudget {
+    limit: Duration,
+    remaining: Duration,
+}
+
+impl CallClock {
+    /// Creates the clock of a call whose timers may add up to `max_sleep`
+    pub(crate) fn new(
+        clock: Box<dyn HostMonotonicClock + Send>,
+        max_sleep: Option<Duration>,
+    ) -> Self {
+        Self {
+            clock,
+            budget: max_sleep.map(|limit| SleepBudget {
+                limit,
+                remaining: limit,
+            }),
+        }
+    }
+
+    /// Counts a timer against the budget, failing if the budget doesn't cover it
+    fn charge(&mut self, duration: Duration) -> Result<()> {
+        let Some(budget) = &mut self.budget else {
+            return Ok(());
+        };
+        let Some(remaining) = budget.remaining.checked_sub(duration) else {
+            bail!(
+                "Tool call exceeded its sleep budget of {}s: a timer of {:.3}s was requested with {:.3}s left",
+                budget.limit.as_secs(),
+                duration.as_secs_f64(),
+                budget.remaining.as_secs_f64()
+            );
+        };
+        budget.remaining = remaining;
+        Ok(())
+    }
+}
+
+/// The monotonic clock of calls that aren't recorded or replayed
+pub(crate) struct SystemMonotonicClock(Instant);
+
+impl Default for SystemMonotonicClock {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl HostMonotonicClock for SystemMonotonicClock {
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn now(&self) -> u64 {
+        self.0.elapsed().as_nanos().try_into().unwrap_or(u64::MAX)
+    }
+}
+
+/// A timer that is ready once its deadline has passed, or never if the deadline is too far in
+/// the future to represent
+struct Timer(Option<tokio::time::Instant>);
+
+#[wasmtime_wasi::async_trait]
+impl Pollable for Timer {
+    async fn ready(&mut self) {
+        match self.0 {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+fn subscribe(
+    mut store: StoreContextMut<'_, WassetteWasiState<WasiState>>,
+    duration: Duration,
+) -> Result<(Resource<DynPollable>,)> {
+    let state = &mut store.data_mut().inner;
+    state.clock.charge(duration)?;
+    let deadline = tokio::time::Instant::now().checked_add(duration);
+    let timer = state.table.push(Timer(deadline))?;
+    Ok((wasmtime_wasi::p2::subscribe(&mut state.table, timer)?,))
+}
+
+/// Replaces the monotonic clock that [`wasmtime_wasi::p2::add_to_linker_async`] added with one
+/// that enforces the sleep budget
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    linker.allow_shadowing(true);
+    for version in CLOCK_VERSIONS {
+        let mut clock = linker.instance(&format!("wasi:clocks/monotonic-clock@{version}"))?;
+        clock.func_wrap(
+            "now",
+            |store: StoreContextMut<'_, WassetteWasiState<WasiState>>, (): ()| {
+                Ok((store.data().inner.clock.clock.now(),))
+            },
+        )?;
+        clock.func_wrap(
+            "resolution",
+            |store: StoreContextMut<'_, WassetteWasiState<WasiState>>, (): ()| {
+                Ok((store.data().inner.clock.clock.resolution(),))
+            },
+        )?;
+        clock.func_wrap(
+            "subscribe-instant",
+            |store: StoreContextMut<'_, WassetteWasiState<WasiState>>, (when,): (u64,)| {
+                let now = store.data().inner.clock.clock.now();
+                subscribe(store, Duration::from_nanos(when.saturating_sub(now)))
+            },
+        )?;
+        clock.func_wrap(
+            "subscribe-duration",
+            |store: StoreContextMut<'_, WassetteWasiState<WasiState>>, (duration,): (u64,)| {
+                subscribe(store, Duration::from_nanos(duration))
+            },
+        )?;
+    }
+    linker.allow_shadowing(false);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleep_budget() {
+        let mut clock = CallClock::new(
+            Box::new(SystemMonotonicClock::default()),
+            Some(Duration::from_secs(2)),
+        );
+        clock.charge(Duration::from_millis(1500)).unwrap();
+        clock.charge(Duration::ZERO).unwrap();
+        let error = clock.charge(Duration::from_secs(1)).unwrap_err();
+        assert!(error.to_string().contains("sleep budget of 2s"));
+        clock.charge(Duration::from_millis(500)).unwrap();
+
+        let mut unlimited = CallClock::new(Box::new(SystemMonotonicClock::default()), None);
+        unlimited.charge(Duration::from_secs(86400)).unwrap();
+    }
+}
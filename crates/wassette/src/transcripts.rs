@@ -0,0 +1,359 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Persistence of full tool call transcripts for designated components.
+//!
+//! Transcripts are stored in the `transcripts` directory of the plugin directory, each in a file
+//! named after the SHA-256 digest of its contents. An index of what was recorded when is kept next
+//! to them, and transcripts older than the retention period are removed whenever a new one is
+//! saved. Each transcript is available as the resource `wassette://transcripts/<digest>`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use crate::{LifecycleManager, MemoryUsage};
+
+/// Directory in the plugin directory that transcripts are written to
+pub const TRANSCRIPTS_DIR: &str = "transcripts";
+
+/// Prefix of the resource URIs of transcripts
+pub const TRANSCRIPT_URI_PREFIX: &str = "wassette://transcripts/";
+
+const INDEX_FILE: &str = "index.json";
+
+/// The request and response of a tool call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    /// ID of the called component
+    pub component_id: String,
+    /// Name of the called tool
+    pub tool: String,
+    /// When the call started, in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+    /// How long the call took, in milliseconds
+    pub duration_ms: u64,
+    /// The arguments of the call
+    pub arguments: Value,
+    /// The result of the call, if it succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    /// The error of the call, if it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Peak memory and table use of the call
+    #[serde(default)]
+    pub memory: MemoryUsage,
+}
+
+/// What the index records about a stored transcript
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// SHA-256 digest of the transcript, which is also its file name
+    pub digest: String,
+    /// ID of the called component
+    pub component_id: String,
+    /// Name of the called tool
+    pub tool: String,
+    /// When the call started, in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+    /// Size of the transcript in bytes
+    pub size: u64,
+}
+
+impl TranscriptEntry {
+    /// Returns the resource URI of the transcript
+    pub fn uri(&self) -> String {
+        format!("{TRANSCRIPT_URI_PREFIX}{}", self.digest)
+    }
+}
+
+pub(crate) struct TranscriptStore {
+    dir: PathBuf,
+    retention: Option<Duration>,
+    index: BTreeMap<String, TranscriptEntry>,
+}
+
+impl TranscriptStore {
+    pub(crate) async fn open(plugin_dir: &Path, retention: Option<Duration>) -> Self {
+        let dir = plugin_dir.join(TRANSCRIPTS_DIR);
+        let index = match tokio::fs::read(dir.join(INDEX_FILE)).await {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!(error = %e, "Discarding unreadable transcript index");
+                BTreeMap::new()
+            }),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!(error = %e, "Failed to read transcript index");
+                }
+                BTreeMap::new()
+            }
+        };
+        Self {
+            dir,
+            retention,
+            index,
+        }
+    }
+
+    pub(crate) fn set_retention(&mut self, retention: Option<Duration>) {
+        self.retention = retention;
+    }
+
+    /// Writes the transcript, removes expired ones and returns the index entry of the new one
+    async fn save(&mut self, transcript: &Transcript, now_ms: u64) -> Result<TranscriptEntry> {
+        let data = serde_json::to_vec_pretty(transcript)?;
+        let digest = format!("{:x}", Sha256::digest(&data));
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .context("Failed to create transcripts directory")?;
+        tokio::fs::write(self.dir.join(format!("{digest}.json")), &data)
+            .await
+            .context("Failed to write transcript")?;
+
+        let entry = TranscriptEntry {
+            digest: digest.clone(),
+            component_id: transcript.component_id.clone(),
+            tool: transcript.tool.clone(),
+            started_at_ms: transcript.started_at_ms,
+            size: data.len() as u64,
+        };
+        self.index.insert(digest, entry.clone());
+        self.prune(now_ms).await;
+        self.persist_index().await?;
+        Ok(entry)
+    }
+
+    /// Removes the transcripts that are older than the retention period
+    async fn prune(&mut self, now_ms: u64) {
+        let Some(retention) = self.retention else {
+            return;
+        };
+        let cutoff = now_ms.saturating_sub(retention.as_millis() as u64);
+        self.remove_where(|entry| entry.started_at_ms < cutoff)
+            .await;
+    }
+
+    /// Removes the transcripts of an unloaded component
+    pub(crate) async fn remove_component(&mut self, component_id: &str) -> Result<()> {
+        if self
+            .remove_where(|entry| entry.component_id == component_id)
+            .await
+        {
+            self.persist_index().await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the transcripts whose entries match, returning whether there were any. Entries
+    /// whose file can't be removed stay in the index.
+    async fn remove_where(&mut self, matches: impl Fn(&TranscriptEntry) -> bool) -> bool {
+        let matching: Vec<String> = self
+            .index
+            .values()
+            .filter(|entry| matches(entry))
+            .map(|entry| entry.digest.clone())
+            .collect();
+        for digest in &matching {
+            debug!(digest = %digest, "Removing transcript");
+            let path = self.dir.join(format!("{digest}.json"));
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!(error = %e, path = %path.display(), "Failed to remove transcript");
+                    continue;
+                }
+            }
+            self.index.remove(digest);
+        }
+        !matching.is_empty()
+    }
+
+    async fn persist_index(&self) -> Result<()> {
+        let data = serde_json::to_vec_pretty(&self.index)?;
+        tokio::fs::write(self.dir.join(INDEX_FILE), data)
+            .await
+            .context("Failed to write transcript index")
+    }
+
+    async fn read(&self, digest: &str) -> Result<String> {
+        // Only digests from the index are read, which also keeps the URI from escaping the
+        // transcripts directory
+        if !self.index.contains_key(digest) {
+            bail!("Transcript not found: {}", digest);
+        }
+        tokio::fs::read_to_string(self.dir.join(format!("{digest}.json")))
+            .await
+            .with_context(|| format!("Failed to read transcript {digest}"))
+    }
+}
+
+fn unix_now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl LifecycleManager {
+    /// Saves the transcript of a call if transcripts are enabled for the component
+    pub(crate) async fn save_transcript(
+        &self,
+        component_id: &str,
+        tool_name: &str,
+        parameters: &str,
+        duration: Duration,
+        result: &Result<String>,
+        memory: MemoryUsage,
+    ) {
+        if !self
+            .config
+            .read()
+            .await
+            .transcript_components
+            .iter()
+            .any(|id| crate::component_id::id_matches(id, component_id))
+        {
+            return;
+        }
+        let now_ms = unix_now_ms();
+        let transcript = Transcript {
+            component_id: component_id.to_string(),
+            tool: tool_name.to_string(),
+            started_at_ms: now_ms.saturating_sub(duration.as_millis() as u64),
+            duration_ms: duration.as_millis() as u64,
+            arguments: serde_json::from_str(parameters)
+                .unwrap_or_else(|_| Value::String(parameters.to_string())),
+            result: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+            memory,
+        };
+        match self
+            .transcripts
+            .write()
+            .await
+            .save(&transcript, now_ms)
+            .await
+        {
+            Ok(entry) => debug!(uri = %entry.uri(), "Saved tool call transcript"),
+            Err(e) => warn!(error = %e, "Failed to save tool call transcript"),
+        }
+    }
+
+    /// Returns the stored transcripts, most recent first
+    pub async fn list_transcripts(&self) -> Vec<TranscriptEntry> {
+        let mut entries: Vec<TranscriptEntry> = self
+            .transcripts
+            .read()
+            .await
+            .index
+            .values()
+            .cloned()
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.started_at_ms));
+        entries
+    }
+
+    /// Returns the JSON of the transcript with the given resource URI
+    pub async fn read_transcript(&self, uri: &str) -> Result<String> {
+        let digest = uri
+            .strip_prefix(TRANSCRIPT_URI_PREFIX)
+            .with_context(|| format!("Not a transcript URI: {uri}"))?;
+        self.transcripts.read().await.read(digest).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+    fn transcript(started_at_ms: u64) -> Transcript {
+        Transcript {
+            component_id: "code-executor".to_string(),
+            tool: "run".to_string(),
+            started_at_ms,
+            duration_ms: 12,
+            arguments: serde_json::json!({"code": "print(1)"}),
+            result: Some("1".to_string()),
+            error: None,
+            memory: MemoryUsage::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transcripts_are_content_addressed() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let mut store = TranscriptStore::open(tempdir.path(), None).await;
+        let entry = store.save(&transcript(1_000), 1_000).await?;
+
+        let data = tokio::fs::read(
+            tempdir
+                .path()
+                .join(TRANSCRIPTS_DIR)
+                .join(format!("{}.json", entry.digest)),
+        )
+        .await?;
+        assert_eq!(format!("{:x}", Sha256::digest(&data)), entry.digest);
+        assert_eq!(
+            entry.uri(),
+            format!("wassette://transcripts/{}", entry.digest)
+        );
+
+        let read: Transcript = serde_json::from_str(&store.read(&entry.digest).await?)?;
+        assert_eq!(read, transcript(1_000));
+        assert!(store.read("../index").await.is_err());
+
+        // The index survives a restart
+        let store = TranscriptStore::open(tempdir.path(), None).await;
+        assert!(store.index.contains_key(&entry.digest));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_transcript_retention() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let mut store =
+            TranscriptStore::open(tempdir.path(), Some(Duration::from_secs(86400))).await;
+        let old = store.save(&transcript(0), 0).await?;
+        let new = store.save(&transcript(2 * DAY_MS), 2 * DAY_MS).await?;
+
+        assert!(!store.index.contains_key(&old.digest));
+        assert!(store.index.contains_key(&new.digest));
+        assert!(!tempdir
+            .path()
+            .join(TRANSCRIPTS_DIR)
+            .join(format!("{}.json", old.digest))
+            .exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_component_transcripts() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let mut store = TranscriptStore::open(tempdir.path(), None).await;
+        let removed = store.save(&transcript(1_000), 1_000).await?;
+        let kept = store
+            .save(
+                &Transcript {
+                    component_id: "formatter".to_string(),
+                    ..transcript(1_000)
+                },
+                1_000,
+            )
+            .await?;
+
+        store.remove_component("code-executor").await?;
+        assert!(store.read(&removed.digest).await.is_err());
+        assert!(store.read(&kept.digest).await.is_ok());
+        let store = TranscriptStore::open(tempdir.path(), None).await;
+        assert_eq!(store.index.keys().collect::<Vec<_>>(), [&kept.digest]);
+        Ok(())
+    }
+}
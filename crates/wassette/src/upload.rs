@@ -0,0 +1,381 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Server-side staging of large binary parameters that are uploaded in chunks.
+//!
+//! Clients stage a blob with [`LifecycleManager::begin_upload`] and
+//! [`LifecycleManager::append_upload_chunk`], then reference it in the arguments of a tool call as
+//! `{"$upload": "<upload id>"}`. [`LifecycleManager::resolve_upload_references`] swaps each
+//! reference for the staged bytes (a `list<u8>` in JSON form) and releases the upload.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use rand_core::{OsRng, RngCore};
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::{debug, instrument};
+
+use crate::LifecycleManager;
+
+/// The key used to reference a staged upload from within tool call arguments
+pub const UPLOAD_REFERENCE_KEY: &str = "$upload";
+
+/// Maximum size of a single staged upload
+pub const MAX_UPLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+const MAX_ACTIVE_UPLOADS: usize = 32;
+const UPLOAD_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// An upload staged in a temporary file, which is removed when the upload is dropped
+struct StagedUpload {
+    /// The session that started the upload. Other sessions can't append to or use it.
+    owner: Option<String>,
+    file: tokio::fs::File,
+    _path: tempfile::TempPath,
+    size: usize,
+    last_touched: Instant,
+}
+
+#[derive(Default)]
+pub(crate) struct UploadStore {
+    uploads: HashMap<String, StagedUpload>,
+}
+
+/// Upload IDs are random so that they can't be guessed by other clients
+fn new_upload_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!("upload-{hex}")
+}
+
+impl UploadStore {
+    fn begin(&mut self, owner: Option<&str>) -> Result<String> {
+        self.expire_stale();
+        if self.uploads.len() >= MAX_ACTIVE_UPLOADS {
+            bail!(
+                "Too many active uploads (limit {MAX_ACTIVE_UPLOADS}). Use or discard existing uploads first."
+            );
+        }
+        let (file, path) = tempfile::Builder::new()
+            .prefix("wassette-upload-")
+            .tempfile()
+            .context("Failed to create a staging file for the upload")?
+            .into_parts();
+        let id = new_upload_id();
+        self.uploads.insert(
+            id.clone(),
+            StagedUpload {
+                owner: owner.map(str::to_string),
+                file: tokio::fs::File::from_std(file),
+                _path: path,
+                size: 0,
+                last_touched: Instant::now(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Whether `upload_id` exists and belongs to `owner`
+    fn owns(&self, owner: Option<&str>, upload_id: &str) -> bool {
+        self.uploads
+            .get(upload_id)
+            .is_some_and(|upload| upload.owner.as_deref() == owner)
+    }
+
+    async fn append(
+        &mut self,
+        owner: Option<&str>,
+        upload_id: &str,
+        chunk: &[u8],
+    ) -> Result<usize> {
+        self.expire_stale();
+        // Uploads of other sessions are reported as unknown so their IDs can't be probed
+        if !self.owns(owner, upload_id) {
+            bail!("Unknown or expired upload: {upload_id}");
+        }
+        let upload = self
+            .uploads
+            .get_mut(upload_id)
+            .with_context(|| format!("Unknown or expired upload: {upload_id}"))?;
+        if upload.size + chunk.len() > MAX_UPLOAD_SIZE {
+            bail!("Upload {upload_id} would exceed the maximum upload size of {MAX_UPLOAD_SIZE} bytes");
+        }
+        upload
+            .file
+            .write_all(chunk)
+            .await
+            .with_context(|| format!("Failed to stage chunk for upload {upload_id}"))?;
+        upload.size += chunk.len();
+        upload.last_touched = Instant::now();
+        Ok(upload.size)
+    }
+
+    async fn take(&mut self, owner: Option<&str>, upload_id: &str) -> Result<Vec<u8>> {
+        self.expire_stale();
+        if !self.owns(owner, upload_id) {
+            bail!("Unknown or expired upload: {upload_id}");
+        }
+        let mut upload = self
+            .uploads
+            .remove(upload_id)
+            .with_context(|| format!("Unknown or expired upload: {upload_id}"))?;
+        let mut data = Vec::with_capacity(upload.size);
+        upload.file.flush().await?;
+        upload.file.rewind().await?;
+        upload
+            .file
+            .read_to_end(&mut data)
+            .await
+            .with_context(|| format!("Failed to read staged upload {upload_id}"))?;
+        Ok(data)
+    }
+
+    /// Returns the number of staged uploads and their combined size in bytes
+    pub(crate) fn usage(&self) -> (usize, usize) {
+        let bytes = self.uploads.values().map(|upload| upload.size).sum();
+        (self.uploads.len(), bytes)
+    }
+
+    fn discard(&mut self, owner: Option<&str>, upload_id: &str) -> bool {
+        self.owns(owner, upload_id) && self.uploads.remove(upload_id).is_some()
+    }
+
+    fn expire_stale(&mut self) {
+        self.uploads.retain(|id, upload| {
+            let keep = upload.last_touched.elapsed() < UPLOAD_TTL;
+            if !keep {
+                debug!(upload_id = %id, "Discarding expired upload");
+            }
+            keep
+        });
+    }
+}
+
+/// Returns the upload ID if `value` is an upload reference
+fn upload_reference(value: &Value) -> Option<&str> {
+    match value {
+        Value::Object(obj) if obj.len() == 1 => obj.get(UPLOAD_REFERENCE_KEY)?.as_str(),
+        _ => None,
+    }
+}
+
+fn collect_references<'a>(value: &'a Value, ids: &mut Vec<&'a str>) {
+    if let Some(upload_id) = upload_reference(value) {
+        ids.push(upload_id);
+        return;
+    }
+    match value {
+        Value::Object(obj) => obj.values().for_each(|v| collect_references(v, ids)),
+        Value::Array(items) => items.iter().for_each(|v| collect_references(v, ids)),
+        _ => {}
+    }
+}
+
+async fn replace_references(
+    value: &mut Value,
+    store: &mut UploadStore,
+    owner: Option<&str>,
+) -> Result<()> {
+    // Check every reference up front so a bad one doesn't consume the others
+    let mut ids = Vec::new();
+    collect_references(value, &mut ids);
+    let mut ids: Vec<String> = ids.into_iter().map(str::to_string).collect();
+    ids.sort();
+    ids.dedup();
+    store.expire_stale();
+    if let Some(missing) = ids.iter().find(|id| !store.owns(owner, id)) {
+        bail!("Unknown or expired upload: {missing}");
+    }
+    let mut staged = HashMap::new();
+    for upload_id in ids {
+        let data = store.take(owner, &upload_id).await?;
+        staged.insert(upload_id, data);
+    }
+    replace_checked_references(value, &mut staged)
+}
+
+fn replace_checked_references(
+    value: &mut Value,
+    staged: &mut HashMap<String, Vec<u8>>,
+) -> Result<()> {
+    if let Some(upload_id) = upload_reference(value) {
+        // Uploads can only be used once, even within the same arguments
+        let data = staged
+            .remove(upload_id)
+            .with_context(|| format!("Unknown or expired upload: {upload_id}"))?;
+        *value = Value::Array(data.into_iter().map(Value::from).collect());
+        return Ok(());
+    }
+    match value {
+        Value::Object(obj) => obj
+            .values_mut()
+            .try_for_each(|v| replace_checked_references(v, staged)),
+        Value::Array(items) => items
+            .iter_mut()
+            .try_for_each(|v| replace_checked_references(v, staged)),
+        _ => Ok(()),
+    }
+}
+
+impl LifecycleManager {
+    /// Starts a new chunked upload owned by `session_id` and returns its ID. The upload is
+    /// staged in a temporary file and can only be used by the session that started it.
+    #[instrument(skip(self))]
+    pub async fn begin_upload(&self, session_id: Option<&str>) -> Result<String> {
+        self.uploads.write().await.begin(session_id)
+    }
+
+    /// Appends a chunk of data to a staged upload, returning the total number of bytes staged
+    #[instrument(skip(self, chunk), fields(chunk_len = chunk.len()))]
+    pub async fn append_upload_chunk(
+        &self,
+        session_id: Option<&str>,
+        upload_id: &str,
+        chunk: &[u8],
+    ) -> Result<usize> {
+        self.uploads
+            .write()
+            .await
+            .append(session_id, upload_id, chunk)
+            .await
+    }
+
+    /// Discards a staged upload. Returns `false` if the session has no such upload
+    #[instrument(skip(self))]
+    pub async fn discard_upload(&self, session_id: Option<&str>, upload_id: &str) -> bool {
+        self.uploads.write().await.discard(session_id, upload_id)
+    }
+
+    /// Replaces every `{"$upload": "<id>"}` reference in `arguments` with the staged bytes.
+    /// Referenced uploads are consumed. If any reference is unknown or belongs to another
+    /// session, an error is returned.
+    #[instrument(skip(self, arguments))]
+    pub async fn resolve_upload_references(
+        &self,
+        session_id: Option<&str>,
+        mut arguments: Value,
+    ) -> Result<Value> {
+        let mut store = self.uploads.write().await;
+        replace_references(&mut arguments, &mut store, session_id).await?;
+        Ok(arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const SESSION: Option<&str> = Some("session-1");
+
+    #[tokio::test]
+    async fn test_upload_store_lifecycle() -> Result<()> {
+        let mut store = UploadStore::default();
+        let id = store.begin(SESSION)?;
+        assert_eq!(store.append(SESSION, &id, b"hello ").await?, 6);
+        assert_eq!(store.append(SESSION, &id, b"world").await?, 11);
+        assert_eq!(store.usage(), (1, 11));
+        assert_eq!(store.take(SESSION, &id).await?, b"hello world");
+        // Uploads can only be used once
+        assert!(store.take(SESSION, &id).await.is_err());
+        assert!(store.append(SESSION, "upload-unknown", b"x").await.is_err());
+        assert_eq!(store.usage(), (0, 0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_store_limits() -> Result<()> {
+        let mut store = UploadStore::default();
+        let id = store.begin(SESSION)?;
+        let err = store
+            .append(SESSION, &id, &vec![0; MAX_UPLOAD_SIZE + 1])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("maximum upload size"));
+
+        for _ in 1..MAX_ACTIVE_UPLOADS {
+            store.begin(SESSION)?;
+        }
+        assert!(store.begin(SESSION).is_err());
+        assert!(store.discard(SESSION, &id));
+        assert!(store.begin(SESSION).is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_uploads_are_bound_to_their_session() -> Result<()> {
+        let mut store = UploadStore::default();
+        let id = store.begin(SESSION)?;
+        let other = Some("session-2");
+
+        let err = store.append(other, &id, b"x").await.unwrap_err();
+        assert!(err.to_string().contains("Unknown or expired upload"));
+        assert!(store.append(None, &id, b"x").await.is_err());
+        assert!(store.take(other, &id).await.is_err());
+        assert!(!store.discard(other, &id));
+
+        let mut args = json!({ "data": { "$upload": id } });
+        assert!(replace_references(&mut args, &mut store, other)
+            .await
+            .is_err());
+
+        // The owner can still use it
+        store.append(SESSION, &id, b"x").await?;
+        assert_eq!(store.take(SESSION, &id).await?, b"x");
+        Ok(())
+    }
+
+    #[test]
+    fn test_upload_ids_are_random() -> Result<()> {
+        let mut store = UploadStore::default();
+        let first = store.begin(SESSION)?;
+        let second = store.begin(SESSION)?;
+        assert_ne!(first, second);
+        assert_eq!(first.len(), "upload-".len() + 32);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replace_references() -> Result<()> {
+        let mut store = UploadStore::default();
+        let first = store.begin(SESSION)?;
+        store.append(SESSION, &first, &[1, 2, 3]).await?;
+        let second = store.begin(SESSION)?;
+        store.append(SESSION, &second, &[255]).await?;
+
+        let mut args = json!({
+            "name": "file.bin",
+            "data": { "$upload": first },
+            "nested": [{ "$upload": second }],
+            "not-a-reference": { "$upload": "x", "other": 1 }
+        });
+        replace_references(&mut args, &mut store, SESSION).await?;
+        assert_eq!(
+            args,
+            json!({
+                "name": "file.bin",
+                "data": [1, 2, 3],
+                "nested": [[255]],
+                "not-a-reference": { "$upload": "x", "other": 1 }
+            })
+        );
+
+        let mut args = json!({ "data": { "$upload": first } });
+        assert!(replace_references(&mut args, &mut store, SESSION)
+            .await
+            .is_err());
+
+        // A bad reference leaves the other uploads in place
+        let third = store.begin(SESSION)?;
+        let mut args = json!({ "a": { "$upload": third }, "b": { "$upload": "missing" } });
+        assert!(replace_references(&mut args, &mut store, SESSION)
+            .await
+            .is_err());
+        assert!(store.take(SESSION, &third).await.is_ok());
+        Ok(())
+    }
+}
@@ -1,20 +1,70 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT license.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
-use std::path::{Path, PathBuf};
-
-use policy::{AccessType, PolicyDocument};
-use wasmtime_wasi::p2::WasiCtxBuilder;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use policy::{
+    AccessType, BlobContainerPermission, EventTopicPermission, Permissions, PolicyDocument,
+};
+use wasmtime_wasi::p2::pipe::AsyncWriteStream;
+use wasmtime_wasi::p2::{AsyncStdoutStream, WasiCtxBuilder};
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock};
 use wasmtime_wasi_config::WasiConfigVariables;
-use wasmtime_wasi_http::{WasiHttpCtx, WasiHttpView};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::types::{HostFutureIncomingResponse, OutgoingRequestConfig};
+use wasmtime_wasi_http::{HttpResult, WasiHttpCtx, WasiHttpView};
+
+use crate::blobstore::BlobstoreCtx;
+use crate::command::CommandIo;
+use crate::component_log::{ComponentLogSink, StderrLines};
+use crate::memory::CallLimiter;
+use crate::publish::{CallPublisher, PublishedEventSink};
+use crate::replay::{TraceSession, TracedMonotonicClock, TracedRng, TracedWallClock};
+use crate::schedule::{GrantSchedule, ScheduleDeadline};
+use crate::timers::{CallClock, SystemMonotonicClock};
+use crate::CorrelationIds;
+
+/// The `wasi:config` variable that holds the ID of the request that made a call
+pub const REQUEST_ID_CONFIG_KEY: &str = "WASSETTE_REQUEST_ID";
+
+/// The `wasi:config` variable that holds the ID of the session that made a call
+pub const SESSION_ID_CONFIG_KEY: &str = "WASSETTE_SESSION_ID";
+
+/// The argument that narrows the permissions of a single tool call, e.g.
+/// `{"$capabilities": {"storage": {"allow": [{"uri": "fs://data/reports", "access": ["read"]}]}}}`.
+/// It takes the `permissions` section of a policy and is removed from the arguments before they
+/// are passed to the component.
+pub const CAPABILITIES_ARGUMENT_KEY: &str = "$capabilities";
+
+/// How many bytes of captured stderr may be in flight before the component has to wait
+const STDERR_WRITE_BUDGET: usize = 64 * 1024;
 
 pub struct WasiState {
     pub ctx: wasmtime_wasi::p2::WasiCtx,
     pub table: wasmtime_wasi::ResourceTable,
     pub http: wasmtime_wasi_http::WasiHttpCtx,
     pub wasi_config_vars: WasiConfigVariables,
+    /// Collects samples while a call is being profiled
+    pub profiler: Option<wasmtime::GuestProfiler>,
+    /// The trace that the call's clock, random and HTTP inputs are recorded to or replayed from
+    pub(crate) trace: Option<TraceSession>,
+    /// The monotonic clock of the call, which enforces its sleep budget
+    pub(crate) clock: CallClock,
+    /// Tracks the call's peak memory use and enforces the memory ceiling of the policy
+    pub(crate) limiter: CallLimiter,
+    /// The models the call may run inference with through `wasi:nn`
+    #[cfg(feature = "wasi-nn")]
+    pub nn: wasmtime_wasi_nn::wit::WasiNnCtx,
+    /// The blob containers the call may use through `wasi:blobstore`
+    pub(crate) blobstore: BlobstoreCtx,
+    /// Where the events the call publishes through `wassette:events` go
+    pub(crate) publisher: CallPublisher,
+    /// When the first time window of the call's scheduled grants closes, which stops the call
+    pub(crate) schedule_deadline: Option<ScheduleDeadline>,
 }
 
 impl wasmtime_wasi::p2::IoView for WasiState {
@@ -33,21 +83,62 @@ impl WasiHttpView for WasiState {
     fn ctx(&mut self) -> &mut WasiHttpCtx {
         &mut self.http
     }
+
+    fn send_request(
+        &mut self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        match &self.trace {
+            Some(trace) => trace.send_request(request, config),
+            None => Ok(wasmtime_wasi_http::types::default_send_request(
+                request, config,
+            )),
+        }
+    }
 }
 
 impl WasiStateTemplate {
     /// Creates a new `WasiState` from the template.
     pub fn build(&self) -> anyhow::Result<WasiState> {
+        self.build_for_call(&CallState::default())
+    }
+
+    /// Creates a new `WasiState` from the template for a single call
+    pub(crate) fn build_for_call(&self, call: &CallState) -> anyhow::Result<WasiState> {
         let mut ctx_builder = WasiCtxBuilder::new();
-        if self.allow_stdout {
-            ctx_builder.inherit_stdout();
+        if let Some(command) = &call.command {
+            // The output of a command is the result of the call, so it is always captured
+            ctx_builder.args(&command.argv);
+            ctx_builder.stdout(command.stdout.clone());
+            ctx_builder.stderr(command.stderr.clone());
+        } else {
+            if self.allow_stdout {
+                ctx_builder.inherit_stdout();
+            }
+            if self.allow_stderr {
+                match &call.stderr {
+                    Some(sink) => {
+                        let lines = StderrLines::new(sink.clone());
+                        ctx_builder.stderr(AsyncStdoutStream::new(AsyncWriteStream::new(
+                            STDERR_WRITE_BUDGET,
+                            lines,
+                        )));
+                    }
+                    None => {
+                        ctx_builder.inherit_stderr();
+                    }
+                }
+            }
+            if self.allow_args {
+                ctx_builder.inherit_args();
+            }
         }
-        if self.allow_stderr {
-            ctx_builder.inherit_stderr();
+        if self.allow_stdin {
+            ctx_builder.inherit_stdin();
         }
-        ctx_builder.inherit_args();
-        if self.allow_args {
-            ctx_builder.inherit_args();
+        for (key, value) in &self.env_vars {
+            ctx_builder.env(key, value);
         }
         // Note(mossaka): removed ctx_builder.inherit_network() to implement deny-by-default network policy
         // For HTTP requests to work, we need to allow TCP and DNS lookups when there are network permissions
@@ -69,17 +160,277 @@ impl WasiStateTemplate {
             )?;
         }
 
+        let mut config_vars = self.config_vars.clone();
+        config_vars.extend(self.component_config.clone());
+        config_vars.extend(call.correlation.config_vars());
+        let trace = call.trace.as_ref();
+        let monotonic_clock: Box<dyn HostMonotonicClock + Send> = match trace {
+            Some(trace) => Box::new(TracedMonotonicClock::new(trace.clone())),
+            None => Box::new(SystemMonotonicClock::default()),
+        };
+        let max_sleep = self
+            .budget
+            .as_ref()
+            .and_then(|budget| budget.max_sleep_seconds_per_call)
+            .map(Duration::from_secs);
+        if !self.allow_wall_clock {
+            ctx_builder.wall_clock(EpochWallClock);
+        }
+        let config_vars = match trace {
+            Some(trace) => {
+                // A clock stuck at the epoch reads the same when replayed, so only the host's is traced
+                if self.allow_wall_clock {
+                    ctx_builder.wall_clock(TracedWallClock(trace.clone()));
+                }
+                ctx_builder.monotonic_clock(TracedMonotonicClock::new(trace.clone()));
+                ctx_builder.secure_random(TracedRng::new(trace.clone(), true));
+                ctx_builder.insecure_random(TracedRng::new(trace.clone(), false));
+                ctx_builder.insecure_random_seed(trace.insecure_random_seed());
+                trace.config_vars(config_vars).into_iter().collect()
+            }
+            None => config_vars,
+        };
+
         Ok(WasiState {
             ctx: ctx_builder.build(),
             table: wasmtime_wasi::ResourceTable::default(),
             http: WasiHttpCtx::new(),
-            wasi_config_vars: WasiConfigVariables::from_iter(self.config_vars.clone()),
+            wasi_config_vars: WasiConfigVariables::from_iter(config_vars),
+            profiler: None,
+            trace: trace.cloned(),
+            clock: CallClock::new(monotonic_clock, max_sleep),
+            limiter: CallLimiter::new(self.max_memory_bytes),
+            #[cfg(feature = "wasi-nn")]
+            nn: crate::nn::NnModels::default().ctx_for(None),
+            blobstore: BlobstoreCtx::default(),
+            publisher: CallPublisher::default(),
+            schedule_deadline: None,
         })
     }
 }
 
+impl WasiStateTemplate {
+    /// Returns the template for a call that only asked for `capabilities`: the intersection of
+    /// the requested permissions and the ones this template grants. Sections that the request
+    /// leaves out are granted nothing. Budget and approval settings stay as they are. Requested
+    /// directories are compared with the granted ones after resolving symbolic links, so they
+    /// have to exist.
+    pub(crate) fn narrow(
+        &self,
+        capabilities: &Permissions,
+        plugin_dir: &Path,
+    ) -> anyhow::Result<WasiStateTemplate> {
+        capabilities.validate()?;
+        let requested = PolicyDocument {
+            version: policy::CURRENT_VERSION.to_string(),
+            description: None,
+            extends: Vec::new(),
+            permissions: capabilities.clone(),
+        };
+
+        // Granted directories that don't exist can't be opened, so they cover nothing
+        let grants: Vec<_> = self
+            .preopened_dirs
+            .iter()
+            .filter_map(|grant| Some((std::fs::canonicalize(&grant.host_path).ok()?, grant)))
+            .collect();
+        let mut preopened_dirs = Vec::new();
+        for dir in extract_storage_permissions(&requested, plugin_dir)? {
+            if dir
+                .host_path
+                .components()
+                .any(|c| matches!(c, Component::ParentDir))
+            {
+                bail!("Requested storage can't contain '..': {}", dir.guest_path);
+            }
+            // Symbolic links must not lead out of the granted directories
+            let host_path = std::fs::canonicalize(&dir.host_path)
+                .with_context(|| format!("Failed to open requested storage {}", dir.guest_path))?;
+            // A directory may be covered by several grants, which add up
+            let (dir_perms, file_perms) = grants
+                .iter()
+                .filter(|(grant_path, _)| host_path.starts_with(grant_path))
+                .map(|(_, grant)| grant)
+                .fold(
+                    (
+                        wasmtime_wasi::DirPerms::empty(),
+                        wasmtime_wasi::FilePerms::empty(),
+                    ),
+                    |(dir_perms, file_perms), grant| {
+                        (dir_perms | grant.dir_perms, file_perms | grant.file_perms)
+                    },
+                );
+            let dir = PreopenedDir {
+                host_path,
+                dir_perms: dir.dir_perms & dir_perms,
+                file_perms: dir.file_perms & file_perms,
+                ..dir
+            };
+            if !dir.dir_perms.is_empty() || !dir.file_perms.is_empty() {
+                preopened_dirs.push(dir);
+            }
+        }
+
+        let requested_network = extract_network_perms(&requested);
+        let requested_process = capabilities.process.clone().unwrap_or_default();
+        let requested_env: HashSet<&str> = capabilities
+            .environment
+            .iter()
+            .flat_map(|env| env.allow.iter().flatten())
+            .map(|permission| permission.key.as_str())
+            .collect();
+        let ai_models = match (extract_ai_models(&requested), &self.ai_models) {
+            (Some(requested), Some(granted)) if requested.iter().any(|m| m == "*") => {
+                Some(granted.clone())
+            }
+            (Some(requested), Some(granted)) => Some(
+                requested
+                    .into_iter()
+                    .filter(|model| granted.iter().any(|g| g == "*" || g == model))
+                    .collect(),
+            ),
+            _ => None,
+        };
+        let blob_containers = match (extract_blob_containers(&requested), &self.blob_containers) {
+            (Some(requested), Some(granted)) => Some(
+                requested
+                    .into_iter()
+                    .filter_map(|request| {
+                        let access: Vec<_> = request
+                            .access
+                            .into_iter()
+                            .filter(|access| {
+                                granted.iter().any(|grant| {
+                                    (grant.container == "*" || grant.container == request.container)
+                                        && grant.access.contains(access)
+                                })
+                            })
+                            .collect();
+                        (!access.is_empty()).then_some(BlobContainerPermission {
+                            container: request.container,
+                            access,
+                        })
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        };
+        let event_topics = match (extract_event_topics(&requested), &self.event_topics) {
+            (Some(requested), Some(granted)) => Some(
+                requested
+                    .into_iter()
+                    .filter(|request| granted.iter().any(|grant| grant.covers(request)))
+                    .collect(),
+            ),
+            _ => None,
+        };
+
+        Ok(WasiStateTemplate {
+            network_perms: NetworkPermissions {
+                allow_tcp: self.network_perms.allow_tcp && requested_network.allow_tcp,
+                allow_udp: self.network_perms.allow_udp && requested_network.allow_udp,
+                allow_ip_name_lookup: self.network_perms.allow_ip_name_lookup
+                    && requested_network.allow_ip_name_lookup,
+            },
+            config_vars: self
+                .config_vars
+                .iter()
+                .filter(|(key, _)| requested_env.contains(key.as_str()))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            preopened_dirs,
+            allowed_hosts: extract_allowed_hosts(&requested)
+                .intersection(&self.allowed_hosts)
+                .cloned()
+                .collect(),
+            ai_models,
+            blob_containers,
+            event_topics,
+            allow_args: self.allow_args && requested_process.args,
+            allow_stdin: self.allow_stdin && requested_process.stdin,
+            allow_wall_clock: self.allow_wall_clock && requested_process.wall_clock,
+            ..self.clone()
+        })
+    }
+
+    /// Returns the template for previewing a call: directories can be read but not changed, blob
+    /// containers can be read but not written, and the network and event topics are off limits
+    pub(crate) fn read_only(&self) -> WasiStateTemplate {
+        WasiStateTemplate {
+            network_perms: NetworkPermissions::default(),
+            preopened_dirs: self
+                .preopened_dirs
+                .iter()
+                .map(|dir| PreopenedDir {
+                    dir_perms: dir.dir_perms & wasmtime_wasi::DirPerms::READ,
+                    file_perms: dir.file_perms & wasmtime_wasi::FilePerms::READ,
+                    ..dir.clone()
+                })
+                .filter(|dir| !dir.dir_perms.is_empty() || !dir.file_perms.is_empty())
+                .collect(),
+            allowed_hosts: HashSet::new(),
+            blob_containers: self.blob_containers.as_ref().map(|containers| {
+                containers
+                    .iter()
+                    .filter(|grant| grant.access.contains(&AccessType::Read))
+                    .map(|grant| BlobContainerPermission {
+                        container: grant.container.clone(),
+                        access: vec![AccessType::Read],
+                    })
+                    .collect()
+            }),
+            event_topics: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// The wall clock of components that aren't granted the host's, which always reads as the Unix
+/// epoch so that it reveals nothing about the host
+struct EpochWallClock;
+
+impl HostWallClock for EpochWallClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// Additions to the state built from a template that only apply to a single call
+#[derive(Default, Clone)]
+pub(crate) struct CallState {
+    /// The trace that the call's clock, random and HTTP inputs are recorded to or replayed from
+    pub(crate) trace: Option<TraceSession>,
+    /// Receives the lines the component writes to stderr, instead of the server's stderr
+    pub(crate) stderr: Option<ComponentLogSink>,
+    /// Receives the events the component publishes, besides the lifecycle manager's subscribers
+    pub(crate) events: Option<PublishedEventSink>,
+    /// Identifiers of the call that are added to the `wasi:config` variables
+    pub(crate) correlation: CorrelationIds,
+    /// Permissions the call asked for, which narrow those of the component's policy
+    pub(crate) capabilities: Option<Permissions>,
+    /// The argv and output pipes of a call to a command component
+    pub(crate) command: Option<CommandIo>,
+    /// Whether the call is a preview, which runs with [`WasiStateTemplate::read_only`]
+    pub(crate) preview: bool,
+}
+
+impl CorrelationIds {
+    fn config_vars(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        [
+            (REQUEST_ID_CONFIG_KEY, &self.request_id),
+            (SESSION_ID_CONFIG_KEY, &self.session_id),
+        ]
+        .into_iter()
+        .filter_map(|(key, id)| Some((key.to_string(), id.clone()?)))
+    }
+}
+
 /// A struct that presents the arguments passed to `wasmtime_wasi::WasiCtxBuilder::preopened_dir`
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct PreopenedDir {
     pub host_path: PathBuf,
     pub guest_path: String,
@@ -88,7 +439,7 @@ pub struct PreopenedDir {
 }
 
 /// A struct that presents the network permissions passed to wasmtime_wasi::WasiContextBuilder
-#[derive(Default, Clone)]
+#[derive(Default, Clone, PartialEq)]
 pub struct NetworkPermissions {
     pub allow_tcp: bool,
     pub allow_udp: bool,
@@ -97,22 +448,47 @@ pub struct NetworkPermissions {
 
 /// A template for the wasi state
 /// this includes the wasmtime_wasi, wasmtime_wasi_config and wasmtime_wasi_http states
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct WasiStateTemplate {
     /// Whether to allow stdout access
     pub allow_stdout: bool,
     /// Whether to allow stderr access
     pub allow_stderr: bool,
-    /// Whether to allow command line arguments access
+    /// Whether to pass the server's command line arguments to the component
     pub allow_args: bool,
+    /// Whether to connect the component's stdin to the server's stdin
+    pub allow_stdin: bool,
+    /// Whether the component reads the host's wall clock, rather than one stuck at the Unix epoch
+    pub allow_wall_clock: bool,
+    /// Environment variables set inside the sandbox, such as `TZ` and `LANG` from the policy's
+    /// `locale` section
+    pub env_vars: BTreeMap<String, String>,
     /// Network permissions configuration
     pub network_perms: NetworkPermissions,
     /// Configuration variables for wasmtime_wasi_config
     pub config_vars: HashMap<String, String>,
+    /// Settings from the policy's `config` section, passed to the component through
+    /// `wasi:config` along with `config_vars`
+    pub component_config: BTreeMap<String, String>,
     /// Preopened directories for filesystem access
     pub preopened_dirs: Vec<PreopenedDir>,
     /// Allowed network hosts for HTTP requests
     pub allowed_hosts: HashSet<String>,
+    /// Usage budget of the component's tools
+    pub budget: Option<policy::Budget>,
+    /// Models the component may use through `wasi:nn`, or `None` if it may not use inference
+    pub ai_models: Option<Vec<String>>,
+    /// Containers the component may use through `wasi:blobstore`, or `None` if it may not store
+    /// blobs
+    pub blob_containers: Option<Vec<BlobContainerPermission>>,
+    /// Topics the component may publish events to, or `None` if it may not publish events
+    pub event_topics: Option<Vec<EventTopicPermission>>,
+    /// Time windows that restrict when some of the network and storage grants apply
+    pub(crate) schedule: GrantSchedule,
+    /// Calls of the component's tools that need human approval
+    pub approval: Option<policy::Approval>,
+    /// Largest combined size of the linear memories of a call's instance, in bytes
+    pub max_memory_bytes: Option<u64>,
 }
 
 impl Default for WasiStateTemplate {
@@ -120,11 +496,22 @@ impl Default for WasiStateTemplate {
         Self {
             allow_stdout: true,
             allow_stderr: true,
-            allow_args: true,
+            allow_args: false,
+            allow_stdin: false,
+            allow_wall_clock: false,
+            env_vars: BTreeMap::new(),
             network_perms: NetworkPermissions::default(),
             config_vars: HashMap::new(),
+            component_config: BTreeMap::new(),
             preopened_dirs: Vec::new(),
             allowed_hosts: HashSet::new(),
+            budget: None,
+            ai_models: None,
+            blob_containers: None,
+            event_topics: None,
+            schedule: GrantSchedule::default(),
+            approval: None,
+            max_memory_bytes: None,
         }
     }
 }
@@ -134,16 +521,48 @@ pub fn create_wasi_state_template_from_policy(
     policy: &PolicyDocument,
     plugin_dir: &Path,
 ) -> anyhow::Result<WasiStateTemplate> {
-    let env_vars = extract_env_vars(policy)?;
+    let mut config_vars = extract_env_vars(policy)?;
+    let env_vars = policy
+        .permissions
+        .locale
+        .as_ref()
+        .map(|locale| locale.env_vars())
+        .unwrap_or_default();
+    // A variable the policy sets, e.g. `TZ`, reads the same through `wasi:config` instead of
+    // taking the host's value
+    for (key, value) in config_vars.iter_mut() {
+        if let Some(synthetic) = env_vars.get(key) {
+            value.clone_from(synthetic);
+        }
+    }
     let network_perms = extract_network_perms(policy);
     let preopened_dirs = extract_storage_permissions(policy, plugin_dir)?;
     let allowed_hosts = extract_allowed_hosts(policy);
 
+    let process = policy.permissions.process.clone().unwrap_or_default();
+
     Ok(WasiStateTemplate {
+        allow_args: process.args,
+        allow_stdin: process.stdin,
+        allow_wall_clock: process.wall_clock,
+        env_vars,
         network_perms,
-        config_vars: env_vars,
+        config_vars,
+        component_config: policy.permissions.config.clone().unwrap_or_default(),
         preopened_dirs,
         allowed_hosts,
+        budget: policy.permissions.budget.clone(),
+        ai_models: extract_ai_models(policy),
+        blob_containers: extract_blob_containers(policy),
+        event_topics: extract_event_topics(policy),
+        schedule: GrantSchedule::from_policy(policy)?,
+        approval: policy.permissions.approval.clone(),
+        max_memory_bytes: policy
+            .permissions
+            .resources
+            .as_ref()
+            .and_then(|resources| resources.memory)
+            .map(|mebibytes| mebibytes.saturating_mul(1024 * 1024)),
         ..Default::default()
     })
 }
@@ -176,6 +595,37 @@ pub(crate) fn extract_network_perms(policy: &PolicyDocument) -> NetworkPermissio
     }
 }
 
+/// Extract the models the policy allows for `wasi:nn` inference
+pub(crate) fn extract_ai_models(policy: &PolicyDocument) -> Option<Vec<String>> {
+    policy.permissions.ai.as_ref().map(|ai| {
+        ai.allow
+            .iter()
+            .flatten()
+            .map(|permission| permission.model.clone())
+            .collect()
+    })
+}
+
+/// Extract the containers the policy allows for `wasi:blobstore`
+pub(crate) fn extract_blob_containers(
+    policy: &PolicyDocument,
+) -> Option<Vec<BlobContainerPermission>> {
+    policy
+        .permissions
+        .blobstore
+        .as_ref()
+        .map(|blobstore| blobstore.allow.iter().flatten().cloned().collect())
+}
+
+/// Extract the topics the policy allows components to publish events to
+pub(crate) fn extract_event_topics(policy: &PolicyDocument) -> Option<Vec<EventTopicPermission>> {
+    policy
+        .permissions
+        .events
+        .as_ref()
+        .map(|events| events.allow.iter().flatten().cloned().collect())
+}
+
 /// Extract allowed hosts from the policy document
 pub(crate) fn extract_allowed_hosts(policy: &PolicyDocument) -> HashSet<String> {
     let mut allowed_hosts = HashSet::new();
@@ -424,6 +874,24 @@ permissions:
         assert!(env_vars.is_empty());
     }
 
+    #[test]
+    fn test_extract_ai_models() {
+        assert_eq!(extract_ai_models(&create_zero_permission_policy()), None);
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  ai:
+    allow:
+      - model: "mobilenet"
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        assert_eq!(
+            extract_ai_models(&policy),
+            Some(vec!["mobilenet".to_string()])
+        );
+    }
+
     #[test]
     fn test_extract_network_permissions_with_allow() {
         let policy = create_test_policy();
@@ -578,6 +1046,235 @@ permissions:
         assert_eq!(template.preopened_dirs.len(), 3);
     }
 
+    #[test]
+    fn test_narrowed_template() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        for dir in ["test/path", "write/path", "readwrite/path/reports", "other"] {
+            std::fs::create_dir_all(temp_dir.path().join(dir))?;
+        }
+        let mut template =
+            create_wasi_state_template_from_policy(&create_test_policy(), temp_dir.path())?;
+        template
+            .config_vars
+            .insert("TEST_VAR".to_string(), "value".to_string());
+
+        let capabilities: Permissions = serde_json::from_value(serde_json::json!({
+            "storage": {"allow": [
+                {"uri": "fs://readwrite/path/reports", "access": ["read"]},
+                {"uri": "fs://test/path", "access": ["read", "write"]},
+                {"uri": "fs://other", "access": ["read"]}
+            ]},
+            "network": {"allow": [{"host": "api.example.com"}, {"host": "evil.example.com"}]}
+        }))?;
+        let narrowed = template.narrow(&capabilities, temp_dir.path())?;
+
+        let dirs: Vec<_> = narrowed
+            .preopened_dirs
+            .iter()
+            .map(|dir| (dir.guest_path.as_str(), dir.file_perms))
+            .collect();
+        assert_eq!(
+            dirs,
+            [
+                ("readwrite/path/reports", wasmtime_wasi::FilePerms::READ),
+                ("test/path", wasmtime_wasi::FilePerms::READ),
+            ]
+        );
+        assert_eq!(
+            narrowed.allowed_hosts,
+            HashSet::from(["api.example.com".to_string()])
+        );
+        assert!(narrowed.config_vars.is_empty());
+
+        // Leaving out a section grants nothing of it
+        let narrowed = template.narrow(&Permissions::default(), temp_dir.path())?;
+        assert!(narrowed.preopened_dirs.is_empty());
+        assert!(narrowed.allowed_hosts.is_empty());
+        assert!(!narrowed.network_perms.allow_tcp);
+
+        let escape: Permissions = serde_json::from_value(serde_json::json!({
+            "storage": {"allow": [{"uri": "fs://test/path/../../secrets", "access": ["read"]}]}
+        }))?;
+        assert!(template.narrow(&escape, temp_dir.path()).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_narrowed_template_resolves_symlinks() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::create_dir_all(temp_dir.path().join("test/path"))?;
+        std::fs::create_dir_all(temp_dir.path().join("secrets"))?;
+        std::os::unix::fs::symlink(
+            temp_dir.path().join("secrets"),
+            temp_dir.path().join("test/path/link"),
+        )?;
+        let template =
+            create_wasi_state_template_from_policy(&create_test_policy(), temp_dir.path())?;
+
+        // The link lies inside the granted directory, but what it points to doesn't
+        let capabilities: Permissions = serde_json::from_value(serde_json::json!({
+            "storage": {"allow": [{"uri": "fs://test/path/link", "access": ["read"]}]}
+        }))?;
+        let narrowed = template.narrow(&capabilities, temp_dir.path())?;
+        assert!(narrowed.preopened_dirs.is_empty());
+
+        let missing: Permissions = serde_json::from_value(serde_json::json!({
+            "storage": {"allow": [{"uri": "fs://test/path/missing", "access": ["read"]}]}
+        }))?;
+        assert!(template.narrow(&missing, temp_dir.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_narrowed_blob_containers() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let template = WasiStateTemplate {
+            blob_containers: Some(vec![BlobContainerPermission {
+                container: "artifacts".to_string(),
+                access: vec![AccessType::Read],
+            }]),
+            ..Default::default()
+        };
+        let capabilities: Permissions = serde_json::from_value(serde_json::json!({
+            "blobstore": {"allow": [
+                {"container": "artifacts", "access": ["read", "write"]},
+                {"container": "cache", "access": ["read"]}
+            ]}
+        }))?;
+        let narrowed = template.narrow(&capabilities, temp_dir.path())?;
+        assert_eq!(
+            narrowed.blob_containers,
+            Some(vec![BlobContainerPermission {
+                container: "artifacts".to_string(),
+                access: vec![AccessType::Read],
+            }])
+        );
+
+        let narrowed = template.narrow(&Permissions::default(), temp_dir.path())?;
+        assert_eq!(narrowed.blob_containers, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_template() {
+        let template = WasiStateTemplate {
+            network_perms: NetworkPermissions {
+                allow_tcp: true,
+                allow_udp: true,
+                allow_ip_name_lookup: true,
+            },
+            preopened_dirs: vec![
+                PreopenedDir {
+                    host_path: PathBuf::from("/data"),
+                    guest_path: "/data".to_string(),
+                    dir_perms: wasmtime_wasi::DirPerms::all(),
+                    file_perms: wasmtime_wasi::FilePerms::all(),
+                },
+                PreopenedDir {
+                    host_path: PathBuf::from("/out"),
+                    guest_path: "/out".to_string(),
+                    dir_perms: wasmtime_wasi::DirPerms::MUTATE,
+                    file_perms: wasmtime_wasi::FilePerms::WRITE,
+                },
+            ],
+            allowed_hosts: HashSet::from(["api.example.com".to_string()]),
+            blob_containers: Some(vec![
+                BlobContainerPermission {
+                    container: "artifacts".to_string(),
+                    access: vec![AccessType::Read, AccessType::Write],
+                },
+                BlobContainerPermission {
+                    container: "uploads".to_string(),
+                    access: vec![AccessType::Write],
+                },
+            ]),
+            event_topics: Some(vec![EventTopicPermission {
+                topic: "scan.*".to_string(),
+            }]),
+            ..Default::default()
+        };
+
+        let read_only = template.read_only();
+        assert_eq!(read_only.preopened_dirs.len(), 1);
+        assert_eq!(
+            read_only.preopened_dirs[0].dir_perms,
+            wasmtime_wasi::DirPerms::READ
+        );
+        assert_eq!(
+            read_only.preopened_dirs[0].file_perms,
+            wasmtime_wasi::FilePerms::READ
+        );
+        assert!(!read_only.network_perms.allow_tcp);
+        assert!(!read_only.network_perms.allow_udp);
+        assert!(read_only.allowed_hosts.is_empty());
+        assert_eq!(
+            read_only.blob_containers,
+            Some(vec![BlobContainerPermission {
+                container: "artifacts".to_string(),
+                access: vec![AccessType::Read],
+            }])
+        );
+        assert_eq!(read_only.event_topics, None);
+    }
+
+    #[test]
+    fn test_narrowed_event_topics() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let template = WasiStateTemplate {
+            event_topics: Some(vec![EventTopicPermission {
+                topic: "scan.*".to_string(),
+            }]),
+            ..Default::default()
+        };
+        let capabilities: Permissions = serde_json::from_value(serde_json::json!({
+            "events": {"allow": [{"topic": "scan.finding"}, {"topic": "deploy.*"}]}
+        }))?;
+        let narrowed = template.narrow(&capabilities, temp_dir.path())?;
+        assert_eq!(
+            narrowed.event_topics,
+            Some(vec![EventTopicPermission {
+                topic: "scan.finding".to_string(),
+            }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_correlation_config_vars() {
+        let correlation = CorrelationIds {
+            request_id: Some("7".to_string()),
+            session_id: None,
+        };
+        let vars: Vec<_> = correlation.config_vars().collect();
+        assert_eq!(
+            vars,
+            vec![(REQUEST_ID_CONFIG_KEY.to_string(), "7".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_component_config() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.5"
+permissions:
+  config:
+    beta-search: "on"
+    LOG_LEVEL: "debug"
+"#,
+        )?;
+        let template = create_wasi_state_template_from_policy(&policy, temp_dir.path())?;
+        assert_eq!(template.component_config["beta-search"], "on");
+        assert_eq!(template.component_config["LOG_LEVEL"], "debug");
+
+        // Narrowing leaves the settings in place, since they grant nothing
+        let narrowed = template.narrow(&Permissions::default(), temp_dir.path())?;
+        assert_eq!(narrowed.component_config, template.component_config);
+        Ok(())
+    }
+
     #[test]
     fn test_create_wasi_state_template_from_policy_no_permissions() {
         let temp_dir = TempDir::new().unwrap();
@@ -593,7 +1290,65 @@ permissions:
         assert!(template.preopened_dirs.is_empty());
         assert!(template.allow_stdout);
         assert!(template.allow_stderr);
+        assert!(!template.allow_args);
+        assert!(!template.allow_stdin);
+        assert!(!template.allow_wall_clock);
+    }
+
+    #[test]
+    fn test_create_wasi_state_template_from_policy_process() {
+        let temp_dir = TempDir::new().unwrap();
+        let policy = PolicyDocument {
+            version: "1.0".to_string(),
+            description: None,
+            extends: Vec::new(),
+            permissions: Permissions {
+                process: Some(policy::ProcessPermissions {
+                    args: true,
+                    wall_clock: true,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        };
+
+        let template = create_wasi_state_template_from_policy(&policy, temp_dir.path()).unwrap();
+
         assert!(template.allow_args);
+        assert!(!template.allow_stdin);
+        assert!(template.allow_wall_clock);
+    }
+
+    #[test]
+    fn test_create_wasi_state_template_from_policy_locale() {
+        let temp_dir = TempDir::new().unwrap();
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+permissions:
+  environment:
+    allow:
+      - key: TZ
+  locale:
+    timezone: Europe/Berlin
+    lang: de_DE.UTF-8
+"#,
+        )
+        .unwrap();
+
+        temp_env::with_vars(vec![("TZ", Some("America/New_York"))], || {
+            let template =
+                create_wasi_state_template_from_policy(&policy, temp_dir.path()).unwrap();
+            assert_eq!(
+                template.env_vars,
+                BTreeMap::from([
+                    ("LANG".to_string(), "de_DE.UTF-8".to_string()),
+                    ("TZ".to_string(), "Europe/Berlin".to_string()),
+                ])
+            );
+            // The host's time zone doesn't leak through wasi:config either
+            assert_eq!(template.config_vars["TZ"], "Europe/Berlin");
+        });
     }
 
     proptest! {
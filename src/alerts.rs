@@ -0,0 +1,508 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Alerts on suspicious activity of components, so that security teams learn of an agent doing
+//! something unusual while it happens. Each rule in the `[alerts]` section of the configuration
+//! file watches the lifecycle events for one pattern: repeated requests blocked by policy, a
+//! component contacting a host it didn't contact before, or a component loaded from a registry no
+//! component came from before. A rule that fires logs a warning with the `wassette::alert` target
+//! and POSTs the [`Alert`] as JSON to its webhook, or to `alerts.webhook` if it has none.
+//! Alerts are delivered in the background, so that a slow webhook doesn't hold back the rules.
+//!
+//! The HTTP requests of components are only reported while a rule watches them, since busy
+//! components send far more of them than any other event.
+//!
+//! Hosts are remembered since the server started. Registries are also remembered from the sources
+//! of the components that are loaded when it starts.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use mcp_server::LifecycleManager;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Semaphore};
+use tokio_util::sync::CancellationToken;
+use wassette::LifecycleEvent;
+
+/// How long delivering an alert may take
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of alerts that may be on their way to webhooks at once. Alerts raised beyond that are
+/// only logged
+const MAX_PENDING_DELIVERIES: usize = 64;
+
+/// Number of blocked requests that fire a `permission_denials` rule unless it sets `threshold`
+const DEFAULT_DENIAL_THRESHOLD: usize = 5;
+
+/// Seconds in which blocked requests are counted unless a rule sets `window_secs`
+const DEFAULT_DENIAL_WINDOW_SECS: u64 = 60;
+
+/// The `[alerts]` section of the configuration file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// URL that alerts are POSTed to as JSON, unless their rule has a webhook of its own
+    pub webhook: Option<String>,
+    /// The rules that raise alerts
+    pub rules: Vec<AlertRule>,
+}
+
+/// A pattern of events that raises an alert, an entry of `[[alerts.rules]]`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AlertRule {
+    /// Name of the rule, included in its alerts
+    pub name: String,
+    /// What the rule watches for
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+    /// URL that the alerts of this rule are POSTed to instead of `alerts.webhook`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+}
+
+/// What an [`AlertRule`] watches for, set by its `kind`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// A component had `threshold` requests blocked by its policy within `window_secs`
+    PermissionDenials {
+        /// Number of blocked requests that fire the rule
+        #[serde(default = "default_denial_threshold")]
+        threshold: usize,
+        /// Seconds in which blocked requests are counted
+        #[serde(default = "default_denial_window_secs")]
+        window_secs: u64,
+    },
+    /// A component sent a request to a host it didn't send one to before
+    NewNetworkHost,
+    /// A component was loaded from a registry that no component was loaded from before
+    NewRegistry,
+}
+
+impl AlertCondition {
+    /// Returns whether the condition watches the HTTP requests of components
+    fn watches_network(&self) -> bool {
+        matches!(self, Self::PermissionDenials { .. } | Self::NewNetworkHost)
+    }
+}
+
+fn default_denial_threshold() -> usize {
+    DEFAULT_DENIAL_THRESHOLD
+}
+
+fn default_denial_window_secs() -> u64 {
+    DEFAULT_DENIAL_WINDOW_SECS
+}
+
+/// An alert raised by a rule, as it is POSTed to webhooks
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    /// Name of the rule that raised the alert
+    pub rule: String,
+    /// When the alert was raised, in milliseconds since the Unix epoch
+    pub time_ms: u64,
+    /// ID of the component whose activity raised the alert
+    pub component_id: String,
+    /// What happened
+    pub message: String,
+    /// The event that fired the rule
+    pub event: LifecycleEvent,
+}
+
+/// Matches lifecycle events against the rules
+#[derive(Debug, Default)]
+struct Detector {
+    rules: Vec<AlertRule>,
+    /// Times of the recent blocked requests, by rule and component
+    denials: HashMap<(usize, String), VecDeque<Instant>>,
+    /// Hosts that each component sent requests to
+    hosts: HashSet<(String, String)>,
+    /// Registries that components were loaded from
+    registries: HashSet<String>,
+}
+
+impl Detector {
+    fn new(rules: Vec<AlertRule>) -> Self {
+        Self {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the alerts that `event`, received at `now`, raises
+    fn observe(&mut self, event: &LifecycleEvent, now: Instant) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        match event {
+            LifecycleEvent::NetworkRequested {
+                component_id,
+                host,
+                allowed,
+                ..
+            } => {
+                let new_host = self.hosts.insert((component_id.clone(), host.clone()));
+                for (index, rule) in self.rules.iter().enumerate() {
+                    let message = match rule.condition {
+                        AlertCondition::NewNetworkHost if new_host => {
+                            format!("{component_id} sent a request to {host} for the first time")
+                        }
+                        AlertCondition::PermissionDenials {
+                            threshold,
+                            window_secs,
+                        } if !allowed => {
+                            let denials = self
+                                .denials
+                                .entry((index, component_id.clone()))
+                                .or_default();
+                            denials.push_back(now);
+                            let window = Duration::from_secs(window_secs);
+                            while denials
+                                .front()
+                                .is_some_and(|time| now.duration_since(*time) > window)
+                            {
+                                denials.pop_front();
+                            }
+                            if denials.len() < threshold.max(1) {
+                                continue;
+                            }
+                            // Counting starts over, so that the rule fires once per burst
+                            denials.clear();
+                            format!(
+                                "{component_id} had {threshold} requests blocked by its policy \
+                                 within {window_secs} seconds, the last to {host}"
+                            )
+                        }
+                        _ => continue,
+                    };
+                    alerts.push(alert(rule, component_id, message, event));
+                }
+            }
+            LifecycleEvent::ComponentLoaded {
+                component_id,
+                source_uri: Some(source_uri),
+                ..
+            } => {
+                let Some(registry) = registry(source_uri) else {
+                    return alerts;
+                };
+                if !self.registries.insert(registry.clone()) {
+                    return alerts;
+                }
+                for rule in &self.rules {
+                    if rule.condition == AlertCondition::NewRegistry {
+                        let message = format!(
+                            "{component_id} was loaded from {registry}, which no component was \
+                             loaded from before"
+                        );
+                        alerts.push(alert(rule, component_id, message, event));
+                    }
+                }
+            }
+            _ => {}
+        }
+        alerts
+    }
+}
+
+fn alert(rule: &AlertRule, component_id: &str, message: String, event: &LifecycleEvent) -> Alert {
+    Alert {
+        rule: rule.name.clone(),
+        time_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default(),
+        component_id: component_id.to_string(),
+        message,
+        event: event.clone(),
+    }
+}
+
+/// Returns the registry or host a component was fetched from, or `None` for local files
+fn registry(source_uri: &str) -> Option<String> {
+    let url = reqwest::Url::parse(source_uri).ok()?;
+    if url.scheme() == "file" {
+        return None;
+    }
+    let host = url.host_str()?.to_ascii_lowercase();
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host,
+    })
+}
+
+/// Raises alerts in the background. Alerting stops when dropped.
+pub struct Alerter {
+    ct: CancellationToken,
+}
+
+impl Drop for Alerter {
+    fn drop(&mut self) {
+        self.ct.cancel();
+    }
+}
+
+/// Starts matching the events of `lifecycle_manager` against the rules, if there are any
+pub async fn start(lifecycle_manager: &LifecycleManager, config: &AlertsConfig) -> Option<Alerter> {
+    if config.rules.is_empty() {
+        return None;
+    }
+    tracing::info!(
+        rules = config.rules.len(),
+        "Watching for suspicious activity"
+    );
+
+    let mut events = lifecycle_manager.subscribe();
+    let mut network = config
+        .rules
+        .iter()
+        .any(|rule| rule.condition.watches_network())
+        .then(|| lifecycle_manager.subscribe_network());
+    let mut detector = Detector::new(config.rules.clone());
+    for component_id in lifecycle_manager.list_components().await {
+        if let Some(source_uri) = lifecycle_manager.get_component_source(&component_id).await {
+            detector.registries.extend(registry(&source_uri));
+        }
+    }
+
+    let ct = CancellationToken::new();
+    let cancelled = ct.clone();
+    let default_webhook = config.webhook.clone().filter(|webhook| !webhook.is_empty());
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let deliveries = Arc::new(Semaphore::new(MAX_PENDING_DELIVERIES));
+        loop {
+            let event = tokio::select! {
+                () = cancelled.cancelled() => break,
+                event = events.recv() => event,
+                event = recv_network(&mut network) => event,
+            };
+            match event {
+                Ok(event) => {
+                    for alert in detector.observe(&event, Instant::now()) {
+                        raise(
+                            &client,
+                            &deliveries,
+                            &detector.rules,
+                            default_webhook.as_deref(),
+                            alert,
+                        );
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "Alerting fell behind and missed events");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+    Some(Alerter { ct })
+}
+
+/// Receives the next network event, or waits forever if no rule watches the network
+async fn recv_network(
+    network: &mut Option<broadcast::Receiver<LifecycleEvent>>,
+) -> Result<LifecycleEvent, RecvError> {
+    match network {
+        Some(network) => network.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Logs an alert and sends it to the webhook of its rule in the background
+fn raise(
+    client: &reqwest::Client,
+    deliveries: &Arc<Semaphore>,
+    rules: &[AlertRule],
+    default_webhook: Option<&str>,
+    alert: Alert,
+) {
+    tracing::warn!(
+        target: "wassette::alert",
+        rule = %alert.rule,
+        component_id = %alert.component_id,
+        "ALERT: {}",
+        alert.message
+    );
+    let webhook = rules
+        .iter()
+        .find(|rule| rule.name == alert.rule)
+        .and_then(|rule| rule.webhook.as_deref())
+        .filter(|webhook| !webhook.is_empty())
+        .or(default_webhook);
+    let Some(webhook) = webhook.map(str::to_string) else {
+        return;
+    };
+    let Ok(permit) = deliveries.clone().try_acquire_owned() else {
+        tracing::warn!(
+            rule = %alert.rule,
+            "Not sending alert, too many alerts are still being delivered"
+        );
+        return;
+    };
+    let client = client.clone();
+    tokio::spawn(async move {
+        let _permit = permit;
+        if let Err(e) = send(&client, &webhook, &alert).await {
+            tracing::warn!(error = %format!("{e:#}"), "Failed to send alert");
+        }
+    });
+}
+
+async fn send(client: &reqwest::Client, webhook: &str, alert: &Alert) -> Result<()> {
+    client
+        .post(webhook)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(alert)?)
+        .timeout(SEND_TIMEOUT)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {webhook}"))?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::providers::{Format, Toml};
+
+    use super::*;
+
+    fn request(component_id: &str, host: &str, allowed: bool) -> LifecycleEvent {
+        LifecycleEvent::NetworkRequested {
+            component_id: component_id.to_string(),
+            host: host.to_string(),
+            allowed,
+            request_id: None,
+            session_id: None,
+        }
+    }
+
+    fn loaded(component_id: &str, source_uri: &str) -> LifecycleEvent {
+        LifecycleEvent::ComponentLoaded {
+            component_id: component_id.to_string(),
+            replaced: false,
+            source_uri: Some(source_uri.to_string()),
+        }
+    }
+
+    fn rules(toml: &str) -> Vec<AlertRule> {
+        #[derive(Deserialize)]
+        struct Section {
+            alerts: AlertsConfig,
+        }
+        figment::Figment::from(Toml::string(toml))
+            .extract::<Section>()
+            .unwrap()
+            .alerts
+            .rules
+    }
+
+    #[test]
+    fn test_rules_from_config() {
+        let rules = rules(
+            r#"
+            [[alerts.rules]]
+            name = "denials"
+            kind = "permission_denials"
+            threshold = 3
+
+            [[alerts.rules]]
+            name = "new-host"
+            kind = "new_network_host"
+            webhook = "https://alerts.example.com/hosts"
+            "#,
+        );
+        assert_eq!(
+            rules[0].condition,
+            AlertCondition::PermissionDenials {
+                threshold: 3,
+                window_secs: DEFAULT_DENIAL_WINDOW_SECS,
+            }
+        );
+        assert_eq!(rules[1].condition, AlertCondition::NewNetworkHost);
+        // Only these rules need the requests of components to be reported
+        assert!(rules.iter().all(|rule| rule.condition.watches_network()));
+        assert!(!AlertCondition::NewRegistry.watches_network());
+        assert_eq!(
+            rules[1].webhook.as_deref(),
+            Some("https://alerts.example.com/hosts")
+        );
+    }
+
+    #[test]
+    fn test_permission_denials_fire_once_per_burst() {
+        let mut detector = Detector::new(rules(
+            r#"
+            [[alerts.rules]]
+            name = "denials"
+            kind = "permission_denials"
+            threshold = 3
+            window_secs = 10
+            "#,
+        ));
+        let start = Instant::now();
+        let denied = request("local/fetch", "evil.example.com", false);
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(detector.observe(&denied, at(0)).is_empty());
+        assert!(detector.observe(&denied, at(5)).is_empty());
+        // The first two denials are out of the window by the time of the third
+        assert!(detector.observe(&denied, at(15)).is_empty());
+        assert!(detector.observe(&denied, at(16)).is_empty());
+        let alerts = detector.observe(&denied, at(17));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "denials");
+        assert_eq!(alerts[0].component_id, "local/fetch");
+        assert!(detector.observe(&denied, at(18)).is_empty());
+
+        // Allowed requests and other components don't count
+        let mut detector = Detector::new(detector.rules);
+        for secs in 0..3 {
+            assert!(detector
+                .observe(&request("local/fetch", "api.example.com", true), at(secs))
+                .is_empty());
+        }
+        assert!(detector
+            .observe(&request("local/other", "evil.example.com", false), at(3))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_new_hosts_and_registries() {
+        let mut detector = Detector::new(rules(
+            r#"
+            [[alerts.rules]]
+            name = "new-host"
+            kind = "new_network_host"
+
+            [[alerts.rules]]
+            name = "new-registry"
+            kind = "new_registry"
+            "#,
+        ));
+        let now = Instant::now();
+        let first = request("local/fetch", "api.example.com", true);
+        assert_eq!(detector.observe(&first, now)[0].rule, "new-host");
+        assert!(detector.observe(&first, now).is_empty());
+        // A host is new to each component
+        assert_eq!(
+            detector
+                .observe(&request("local/other", "api.example.com", true), now)
+                .len(),
+            1
+        );
+
+        let alerts = detector.observe(&loaded("fetch", "oci://ghcr.io/acme/fetch:1"), now);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "new-registry");
+        assert!(alerts[0].message.contains("ghcr.io"));
+        assert!(detector
+            .observe(&loaded("time", "oci://GHCR.io/acme/time:1"), now)
+            .is_empty());
+        assert!(detector
+            .observe(&loaded("local", "file:///tmp/local.wasm"), now)
+            .is_empty());
+    }
+}
@@ -1,12 +1,22 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT license.
 
+use std::collections::BTreeMap;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use etcetera::BaseStrategy;
 use figment::providers::{Env, Format, Serialized, Toml};
 use serde::{Deserialize, Serialize};
+use wassette::{
+    AiModelConfig, BlobstoreConfig, DeterminismConfig, ExecutionConfig, LabelSelector,
+    LifecycleConfig, MirrorConfig, NotificationQueueConfig, SessionConfig, ToolNamingConfig,
+};
+
+use crate::alerts::AlertsConfig;
+use crate::event_webhook::EventsConfig;
+use crate::telemetry::TelemetryConfig;
 
 /// Get the default component directory path based on the OS
 pub fn get_component_dir() -> Result<PathBuf, anyhow::Error> {
@@ -14,6 +24,28 @@ pub fn get_component_dir() -> Result<PathBuf, anyhow::Error> {
     Ok(dir_strategy.data_dir().join("wasette").join("components"))
 }
 
+/// Get the path of the configuration file, honoring the `WASETTE_CONFIG_FILE` override
+pub fn get_config_file_path() -> Result<PathBuf, anyhow::Error> {
+    match std::env::var_os("WASETTE_CONFIG_FILE") {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(etcetera::choose_base_strategy()
+            .context("Unable to get home directory")?
+            .config_dir()
+            .join("wasette")
+            .join("config.toml")),
+    }
+}
+
+/// Directory in the working directory that carries a project's own components, policies and
+/// configuration overrides
+pub const PROJECT_DIR: &str = ".wassette";
+
+/// Returns the `.wassette` directory of the current working directory, if there is one
+pub fn find_project_dir() -> Option<PathBuf> {
+    let dir = std::env::current_dir().ok()?.join(PROJECT_DIR);
+    dir.is_dir().then_some(dir)
+}
+
 fn default_plugin_dir() -> PathBuf {
     get_component_dir().unwrap_or_else(|_| {
         eprintln!("WARN: Unable to determine default component directory, using `components` directory in the current working directory");
@@ -27,6 +59,182 @@ pub struct Config {
     /// Directory where plugins are stored
     #[serde(default = "default_plugin_dir")]
     pub plugin_dir: PathBuf,
+
+    /// How tool names are derived from component exports
+    #[serde(default)]
+    pub tool_naming: ToolNamingConfig,
+
+    /// IDs of components that are compiled first at startup
+    #[serde(default)]
+    pub hot_components: Vec<String>,
+
+    /// Instantiate hot components once at startup to avoid cold starts on the first call
+    #[serde(default)]
+    pub warm_up: bool,
+
+    /// IDs of components whose calls are all profiled
+    #[serde(default)]
+    pub profile_components: Vec<String>,
+
+    /// Write a core dump whenever a component traps
+    #[serde(default)]
+    pub coredump_on_trap: bool,
+
+    /// Engine settings that make float and SIMD results the same on every host
+    #[serde(default)]
+    pub determinism: DeterminismConfig,
+
+    /// IDs of components whose calls are all recorded for replay
+    #[serde(default)]
+    pub record_components: Vec<String>,
+
+    /// IDs of components whose calls are saved as transcripts
+    #[serde(default)]
+    pub transcript_components: Vec<String>,
+
+    /// Number of days transcripts are kept for
+    #[serde(default)]
+    pub transcript_retention_days: Option<u64>,
+
+    /// Number of seconds a call that needs approval waits for it
+    #[serde(default)]
+    pub approval_timeout_secs: Option<u64>,
+
+    /// Give every component the server's arguments and wall clock, as earlier versions did
+    #[serde(default)]
+    pub legacy_sandbox_defaults: bool,
+
+    /// Mirrors to load components and policies from before their original source
+    #[serde(default)]
+    pub mirrors: Vec<MirrorConfig>,
+
+    /// Label selector limiting the tools listed to clients to those of the matching components
+    #[serde(default)]
+    pub tool_selector: Option<LabelSelector>,
+
+    /// Models loaded for local inference through `wasi:nn`
+    #[serde(default)]
+    pub ai_models: Vec<AiModelConfig>,
+
+    /// Sizes of the worker pools tool calls run on and which components run as batch work
+    #[serde(default)]
+    pub execution: ExecutionConfig,
+
+    /// Store installed components zstd compressed
+    #[serde(default)]
+    pub compress_components: bool,
+
+    /// Abbreviate deeply nested types in the tool schemas listed to clients
+    #[serde(default)]
+    pub compact_schemas: bool,
+
+    /// Language of the builtin tool descriptions and error messages
+    #[serde(default)]
+    pub language: Option<String>,
+
+    /// Backend that components store blobs in through `wasi:blobstore`
+    #[serde(default)]
+    pub blobstore: BlobstoreConfig,
+
+    /// URI schemes that components and policies may be loaded from. All are allowed if unset
+    #[serde(default)]
+    pub allowed_schemes: Option<Vec<String>>,
+
+    /// Leave out the `fetch-url` and `read-file` tools that the server implements itself
+    #[serde(default)]
+    pub disable_host_tools: bool,
+
+    /// Groups of components, by name, that share a scratch directory and their blob containers
+    #[serde(default)]
+    pub sandbox_groups: BTreeMap<String, Vec<String>>,
+
+    /// Bound of the queue of notifications waiting to be sent to each client, and what happens
+    /// when a slow client lets it fill up
+    #[serde(default)]
+    pub notification_queue: NotificationQueueConfig,
+
+    /// Limits on the number of MCP sessions over HTTP and how long they may stay idle
+    #[serde(default)]
+    pub sessions: SessionConfig,
+
+    /// Load components that were precompiled for this host with `wassette component precompile`
+    /// instead of compiling them
+    #[serde(default)]
+    pub load_precompiled: bool,
+
+    /// Only load components that were precompiled for this host with `wassette component
+    /// precompile`, and never compile any
+    #[serde(default)]
+    pub require_precompiled: bool,
+
+    /// Attach the policies bundled in `.wassette-pkg` packages when they are loaded
+    #[serde(default)]
+    pub attach_package_policies: bool,
+
+    /// YAML manifest of the components to reconcile at startup
+    #[serde(default)]
+    pub provision: Option<PathBuf>,
+
+    /// Reconcile the provisioning manifest again whenever the server receives SIGHUP
+    #[serde(default)]
+    pub provision_on_sighup: bool,
+
+    /// File to write the ID of the server process to while it runs
+    #[serde(default)]
+    pub pid_file: Option<PathBuf>,
+
+    /// Address to serve the REST management API on, e.g. `127.0.0.1:9002`. The API is off unless
+    /// this is set
+    #[serde(default)]
+    pub admin_address: Option<String>,
+
+    /// Bearer token that requests to the management API have to carry, best set through the
+    /// `WASETTE_ADMIN_TOKEN` environment variable
+    #[serde(default)]
+    pub admin_token: Option<String>,
+
+    /// Append every request to the HTTP transport to `approvals/access.jsonl` in the plugin
+    /// directory, next to the approval audit log
+    #[serde(default)]
+    pub access_log: bool,
+
+    /// Addresses of reverse proxies whose `X-Forwarded-For` header names the client in the access
+    /// log
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// Unix socket that `wassette top` reads live statistics from. Defaults to `control.sock` in
+    /// the plugin directory
+    #[serde(default)]
+    pub control_socket: Option<PathBuf>,
+
+    /// Confine the server process with Landlock and seccomp on Linux
+    #[serde(default)]
+    pub os_sandbox: bool,
+
+    /// Directories the OS sandbox lets the server read besides those it needs itself
+    #[serde(default)]
+    pub os_sandbox_read_paths: Vec<PathBuf>,
+
+    /// Directories the OS sandbox lets the server write to besides those it needs itself
+    #[serde(default)]
+    pub os_sandbox_write_paths: Vec<PathBuf>,
+
+    /// TCP ports the OS sandbox lets the server connect to besides 80 and 443
+    #[serde(default)]
+    pub os_sandbox_ports: Vec<u16>,
+
+    /// Anonymous usage reporting, off unless enabled
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+
+    /// Where the events components publish are forwarded to besides MCP clients
+    #[serde(default)]
+    pub events: EventsConfig,
+
+    /// Rules that raise alerts on suspicious activity of components
+    #[serde(default)]
+    pub alerts: AlertsConfig,
 }
 
 impl Config {
@@ -41,15 +249,58 @@ impl Config {
     /// 2. Environment variables prefixed with `WASETTE_`
     /// 3. Configuration file specified by `WASETTE_CONFIG_FILE` or default location
     pub fn new<T: Serialize>(cli_config: &T) -> Result<Self, anyhow::Error> {
-        let config_file_path = match std::env::var_os("WASETTE_CONFIG_FILE") {
-            Some(path) => PathBuf::from(path),
-            None => etcetera::choose_base_strategy()
-                .context("Unable to get home directory")?
-                .config_dir()
-                .join("wasette")
-                .join("config.toml"),
-        };
-        Self::new_from_path(cli_config, config_file_path)
+        Self::new_from_path(cli_config, get_config_file_path()?)
+    }
+
+    /// Returns the [`LifecycleConfig`] used to construct the lifecycle manager
+    pub fn lifecycle_config(&self) -> LifecycleConfig {
+        LifecycleConfig {
+            tool_naming: self.tool_naming.clone(),
+            hot_components: self.hot_components.clone(),
+            warm_up: self.warm_up,
+            profile_components: self.profile_components.clone(),
+            coredump_on_trap: self.coredump_on_trap,
+            determinism: self.determinism.clone(),
+            record_components: self.record_components.clone(),
+            transcript_components: self.transcript_components.clone(),
+            transcript_retention_days: self.transcript_retention_days,
+            approval_timeout_secs: self.approval_timeout_secs,
+            legacy_sandbox_defaults: self.legacy_sandbox_defaults,
+            mirrors: self.mirrors.clone(),
+            tool_selector: self.tool_selector.clone(),
+            ai_models: self.ai_models.clone(),
+            execution: self.execution.clone(),
+            compress_components: self.compress_components,
+            compact_schemas: self.compact_schemas,
+            language: self.language.clone(),
+            blobstore: self.blobstore.clone(),
+            allowed_schemes: self.allowed_schemes.clone(),
+            disable_host_tools: self.disable_host_tools,
+            sandbox_groups: self.sandbox_groups.clone(),
+            notification_queue: self.notification_queue.clone(),
+            sessions: self.sessions.clone(),
+            load_precompiled: self.load_precompiled,
+            require_precompiled: self.require_precompiled,
+            attach_package_policies: self.attach_package_policies,
+        }
+    }
+
+    /// Returns the path of the control socket of a server using this configuration
+    pub fn control_socket_path(&self) -> PathBuf {
+        self.control_socket
+            .clone()
+            .unwrap_or_else(|| self.plugin_dir.join(crate::control::CONTROL_SOCKET))
+    }
+
+    /// Same as [`Config::new`], but also applies the project directory `project_dir`, usually
+    /// found with [`find_project_dir`]. Its `components` subdirectory, if it exists, becomes the
+    /// plugin directory and its `config.toml` overrides the configuration file, while
+    /// environment variables and `cli_config` still take precedence over both.
+    pub fn new_with_project<T: Serialize>(
+        cli_config: &T,
+        project_dir: Option<&Path>,
+    ) -> Result<Self, anyhow::Error> {
+        Self::new_from_paths(cli_config, get_config_file_path()?, project_dir)
     }
 
     /// Same as [`Config::new`], but allows specifying a custom path for the configuration file.
@@ -57,8 +308,23 @@ impl Config {
         cli_config: &T,
         config_file_path: impl AsRef<Path>,
     ) -> Result<Self, anyhow::Error> {
-        figment::Figment::new()
-            .admerge(Toml::file(config_file_path))
+        Self::new_from_paths(cli_config, config_file_path, None)
+    }
+
+    fn new_from_paths<T: Serialize>(
+        cli_config: &T,
+        config_file_path: impl AsRef<Path>,
+        project_dir: Option<&Path>,
+    ) -> Result<Self, anyhow::Error> {
+        let mut figment = figment::Figment::new().admerge(Toml::file(config_file_path));
+        if let Some(project_dir) = project_dir {
+            let components = project_dir.join("components");
+            if components.is_dir() {
+                figment = figment.admerge(Serialized::default("plugin_dir", components));
+            }
+            figment = figment.admerge(Toml::file(project_dir.join("config.toml")));
+        }
+        figment
             .admerge(Env::prefixed("WASETTE_"))
             .admerge(Serialized::defaults(cli_config))
             .extract()
@@ -77,17 +343,31 @@ mod tests {
 
     fn create_test_cli_config() -> crate::Serve {
         crate::Serve {
-            plugin_dir: Some(PathBuf::from("/test/plugin/dir")),
+            plugin_dir: crate::PluginDirArgs {
+                plugin_dir: Some(PathBuf::from("/test/plugin/dir")),
+            },
             stdio: true,
             http: false,
+            provision: None,
+            provision_on_sighup: false,
+            pid_file: None,
+            tool_selector: None,
+            no_project: false,
+            os_sandbox: false,
         }
     }
 
     fn empty_test_cli_config() -> crate::Serve {
         crate::Serve {
-            plugin_dir: None,
+            plugin_dir: crate::PluginDirArgs::default(),
             stdio: false,
             http: false,
+            provision: None,
+            provision_on_sighup: false,
+            pid_file: None,
+            tool_selector: None,
+            no_project: false,
+            os_sandbox: false,
         }
     }
 
@@ -176,6 +456,43 @@ plugin_dir = "/config/plugin/dir"
         assert_eq!(config.plugin_dir, PathBuf::from("/test/plugin/dir"));
     }
 
+    #[test]
+    fn test_project_dir_overrides_config_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_file,
+            "plugin_dir = \"/config/plugin/dir\"\nwarm_up = true\n",
+        )
+        .unwrap();
+        let project_dir = temp_dir.path().join(PROJECT_DIR);
+        fs::create_dir_all(project_dir.join("components")).unwrap();
+
+        // The project's components directory replaces the configured plugin directory
+        let config =
+            Config::new_from_paths(&empty_test_cli_config(), &config_file, Some(&project_dir))
+                .expect("Failed to create config");
+        assert_eq!(config.plugin_dir, project_dir.join("components"));
+        assert!(config.warm_up);
+
+        // The project's config file overrides the user's, and the CLI overrides both
+        fs::write(
+            project_dir.join("config.toml"),
+            "plugin_dir = \"/project/plugin/dir\"\nwarm_up = false\n",
+        )
+        .unwrap();
+        let config =
+            Config::new_from_paths(&empty_test_cli_config(), &config_file, Some(&project_dir))
+                .expect("Failed to create config");
+        assert_eq!(config.plugin_dir, PathBuf::from("/project/plugin/dir"));
+        assert!(!config.warm_up);
+
+        let config =
+            Config::new_from_paths(&create_test_cli_config(), &config_file, Some(&project_dir))
+                .expect("Failed to create config");
+        assert_eq!(config.plugin_dir, PathBuf::from("/test/plugin/dir"));
+    }
+
     #[test]
     fn test_config_file_partial_values() {
         let temp_dir = TempDir::new().unwrap();
@@ -229,6 +546,90 @@ policy_file = unclosed_string"
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_config_file_tool_naming() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+
+        let toml_content = r#"
+[tool_naming]
+max_length = 64
+dedup_across_components = true
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+
+        assert_eq!(config.tool_naming.max_length, 64);
+        assert!(config.tool_naming.dedup_across_components);
+        // Unset values keep their defaults
+        assert_eq!(config.tool_naming.replacement, "_");
+        assert!(config.lifecycle_config().tool_naming.sanitizer().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_execution() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+
+        let toml_content = r#"
+[execution]
+batch_workers = 2
+batch_components = ["indexer"]
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+
+        let execution = config.lifecycle_config().execution;
+        assert_eq!(execution.batch_workers, Some(2));
+        assert_eq!(execution.interactive_workers, None);
+        assert_eq!(execution.batch_components, vec!["indexer".to_string()]);
+    }
+
+    #[test]
+    fn test_config_file_hot_components() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+
+        let toml_content = r#"
+hot_components = ["fetch_rs"]
+warm_up = true
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+
+        let lifecycle_config = config.lifecycle_config();
+        assert_eq!(lifecycle_config.hot_components, vec!["fetch_rs"]);
+        assert!(lifecycle_config.warm_up);
+        assert!(lifecycle_config.is_hot("fetch_rs", None));
+    }
+
+    #[test]
+    fn test_config_file_provision() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+
+        let toml_content = r#"
+provision = "/etc/wassette/components.yaml"
+provision_on_sighup = true
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        // A flag that isn't passed on the command line doesn't override the file
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(
+            config.provision,
+            Some(PathBuf::from("/etc/wassette/components.yaml"))
+        );
+        assert!(config.provision_on_sighup);
+    }
+
     #[test]
     fn test_config_file_path_override_with_env_var() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,164 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The control socket of a running server. Every connection is answered with a [`Snapshot`] of
+//! the server's status and tool statistics as a single line of JSON, which `wassette top` polls.
+//! The socket is only accessible to the user running the server.
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[cfg(not(unix))]
+use anyhow::bail;
+use anyhow::Result;
+use mcp_server::LifecycleManager;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use wassette::{ServerStatus, UsageStats};
+
+/// Name of the control socket in the plugin directory, unless `control_socket` is configured
+pub const CONTROL_SOCKET: &str = "control.sock";
+
+/// What the server reports over the control socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Seconds since the server started
+    pub uptime_secs: u64,
+    /// Resources the server uses and its number of open sessions
+    pub status: ServerStatus,
+    /// Usage statistics of every tool since statistics were first recorded
+    pub stats: UsageStats,
+}
+
+/// A control socket accepting connections in the background. It is closed and removed when
+/// dropped.
+pub struct ControlSocket {
+    path: PathBuf,
+    ct: CancellationToken,
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.ct.cancel();
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!(
+                path = %self.path.display(),
+                error = %e,
+                "Failed to remove control socket"
+            );
+        }
+    }
+}
+
+/// Listens on a Unix socket at `path`, replacing a socket left behind by an earlier server
+#[cfg(unix)]
+pub fn bind(lifecycle_manager: LifecycleManager, path: PathBuf) -> Result<ControlSocket> {
+    use std::os::unix::fs::PermissionsExt;
+
+    use anyhow::Context;
+    use tokio::io::AsyncWriteExt;
+
+    if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+        anyhow::bail!("Another server listens on {}", path.display());
+    }
+    if path.exists() {
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove stale control socket {}", path.display()))?;
+    }
+    let listener = tokio::net::UnixListener::bind(&path)
+        .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+    // Tool statistics name components and tools, which other users have no business seeing
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+    let started = Instant::now();
+    let ct = CancellationToken::new();
+    let cancelled = ct.clone();
+    tokio::spawn(async move {
+        loop {
+            let mut stream = tokio::select! {
+                () = cancelled.cancelled() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Failed to accept control connection");
+                        continue;
+                    }
+                },
+            };
+            let lifecycle_manager = lifecycle_manager.clone();
+            tokio::spawn(async move {
+                let snapshot = snapshot(&lifecycle_manager, started).await;
+                let written = async {
+                    let mut line = serde_json::to_vec(&snapshot)?;
+                    line.push(b'\n');
+                    stream.write_all(&line).await?;
+                    stream.shutdown().await?;
+                    anyhow::Ok(())
+                };
+                if let Err(e) = written.await {
+                    tracing::debug!(error = %e, "Failed to answer control connection");
+                }
+            });
+        }
+    });
+    tracing::info!(path = %path.display(), "Listening on control socket");
+    Ok(ControlSocket { path, ct })
+}
+
+/// Unix sockets aren't available on this platform, so `wassette top` can't monitor the server
+#[cfg(not(unix))]
+pub fn bind(_lifecycle_manager: LifecycleManager, _path: PathBuf) -> Result<ControlSocket> {
+    bail!("The control socket is only available on Unix")
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+async fn snapshot(lifecycle_manager: &LifecycleManager, started: Instant) -> Snapshot {
+    Snapshot {
+        uptime_secs: started.elapsed().as_secs(),
+        status: lifecycle_manager.server_status().await,
+        stats: lifecycle_manager.get_tool_stats(None).await,
+    }
+}
+
+/// Connects to the control socket at `path` and reads a snapshot of the server
+#[cfg(unix)]
+pub fn read_snapshot(path: &Path) -> Result<Snapshot> {
+    use anyhow::Context;
+
+    let stream = std::os::unix::net::UnixStream::connect(path).with_context(|| {
+        format!(
+            "Failed to connect to {}, is the server running?",
+            path.display()
+        )
+    })?;
+    serde_json::from_reader(stream).context("Invalid snapshot from the control socket")
+}
+
+/// Unix sockets aren't available on this platform
+#[cfg(not(unix))]
+pub fn read_snapshot(_path: &Path) -> Result<Snapshot> {
+    bail!("The control socket is only available on Unix")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_control_socket() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let lifecycle_manager = LifecycleManager::new(dir.path()).await?;
+        let path = dir.path().join(CONTROL_SOCKET);
+        let socket = bind(lifecycle_manager, path.clone())?;
+
+        let read = path.clone();
+        let snapshot = tokio::task::spawn_blocking(move || read_snapshot(&read)).await??;
+        assert_eq!(snapshot.status.loaded_components, 0);
+        assert_eq!(snapshot.status.active_sessions, 0);
+        assert!(snapshot.stats.components.is_empty());
+
+        drop(socket);
+        assert!(!path.exists());
+        Ok(())
+    }
+}
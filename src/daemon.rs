@@ -0,0 +1,224 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Support for running as a service: PID files, systemd readiness notifications and socket
+//! activation.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// A PID file that is removed again when dropped
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the ID of this process to `path`. Fails if the file names a process that is still
+    /// running, so that two servers don't share a plugin directory by accident.
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Ok(existing) = std::fs::read_to_string(&path) {
+            if let Ok(pid) = existing.trim().parse::<u32>() {
+                if pid != std::process::id() && is_running(pid) {
+                    bail!(
+                        "Another server is running with PID {pid} according to {}",
+                        path.display()
+                    );
+                }
+            }
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        }
+        let partial = path.with_extension("pid.partial");
+        std::fs::write(&partial, format!("{}\n", std::process::id()))
+            .and_then(|()| std::fs::rename(&partial, &path))
+            .with_context(|| format!("Failed to write PID file {}", path.display()))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!(path = %self.path.display(), error = %e, "Failed to remove PID file");
+        }
+    }
+}
+
+/// Whether a process with the given ID exists. Only known on Linux, where stale PID files are
+/// therefore replaced; elsewhere every PID file is assumed stale.
+fn is_running(pid: u32) -> bool {
+    cfg!(target_os = "linux") && Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Tells systemd about the state of the service, e.g. `READY=1`, if it runs under a unit with
+/// `Type=notify`. Does nothing otherwise.
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Some(socket) = std::env::var_os("NOTIFY_SOCKET") {
+        if let Err(e) = notify_socket(Path::new(&socket), state) {
+            tracing::warn!(error = %e, "Failed to notify systemd");
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+#[cfg(unix)]
+fn notify_socket(socket: &Path, state: &str) -> Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let datagram = UnixDatagram::unbound()?;
+    let name = socket.as_os_str().as_encoded_bytes();
+    // A leading `@` names a socket in the abstract namespace
+    if let Some(abstract_name) = name.strip_prefix(b"@") {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            #[cfg(target_os = "android")]
+            use std::os::android::net::SocketAddrExt;
+            #[cfg(target_os = "linux")]
+            use std::os::linux::net::SocketAddrExt;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(abstract_name)?;
+            datagram.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        bail!(
+            "Abstract notify socket {} is not supported on this platform",
+            String::from_utf8_lossy(abstract_name)
+        );
+    }
+    datagram.send_to(state.as_bytes(), socket)?;
+    Ok(())
+}
+
+/// The first file descriptor passed by systemd socket activation
+#[cfg(unix)]
+const LISTEN_FDS_START: std::os::fd::RawFd = 3;
+
+/// Returns whether the listen environment variables pass sockets to the process with ID `pid`
+#[cfg(unix)]
+fn has_activated_socket(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> bool {
+    listen_pid.and_then(|value| value.trim().parse::<u32>().ok()) == Some(pid)
+        && listen_fds
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .is_some_and(|count| count >= 1)
+}
+
+/// Takes the listening socket passed by systemd socket activation, if the server was started by
+/// a `.socket` unit. Only the first socket is used.
+#[cfg(unix)]
+pub fn activated_listener() -> Result<Option<std::net::TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    let listen_pid = std::env::var("LISTEN_PID").ok();
+    let listen_fds = std::env::var("LISTEN_FDS").ok();
+    if !has_activated_socket(
+        listen_pid.as_deref(),
+        listen_fds.as_deref(),
+        std::process::id(),
+    ) {
+        return Ok(None);
+    }
+    // Child processes must not take the socket as well
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+    // SAFETY: systemd passes the sockets starting at descriptor 3 to the process named by
+    // LISTEN_PID, which was checked above, and nothing else in the process owns that descriptor.
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    listener
+        .set_nonblocking(true)
+        .context("Socket passed by systemd is not a TCP listener")?;
+    Ok(Some(listener))
+}
+
+/// Socket activation needs systemd, so there is never an activated socket
+#[cfg(not(unix))]
+pub fn activated_listener() -> Result<Option<std::net::TcpListener>> {
+    Ok(None)
+}
+
+/// Waits until the server is asked to stop, with Ctrl+C or, on Unix, SIGTERM as sent by service
+/// managers
+pub async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate =
+            signal(SignalKind::terminate()).context("Failed to listen for SIGTERM")?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("run/wassette.pid");
+
+        let pid_file = PidFile::create(&path)?;
+        assert_eq!(
+            std::fs::read_to_string(&path)?,
+            format!("{}\n", std::process::id())
+        );
+        drop(pid_file);
+        assert!(!path.exists());
+
+        // A PID file naming a process that is gone is replaced
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, format!("{}\n", u32::MAX))?;
+        let _pid_file = PidFile::create(&path)?;
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pid_file_of_running_process_is_kept() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("wassette.pid");
+        // PID 1 always runs
+        std::fs::write(&path, "1\n")?;
+        assert!(PidFile::create(&path).is_err());
+        assert_eq!(std::fs::read_to_string(&path)?, "1\n");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notify_socket() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let socket = tempdir.path().join("notify");
+        let receiver = std::os::unix::net::UnixDatagram::bind(&socket)?;
+
+        notify_socket(&socket, "READY=1")?;
+        let mut buf = [0; 64];
+        let n = receiver.recv(&mut buf)?;
+        assert_eq!(&buf[..n], b"READY=1");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_has_activated_socket() {
+        assert!(has_activated_socket(Some("42"), Some("1"), 42));
+        // The sockets were meant for another process, e.g. the parent
+        assert!(!has_activated_socket(Some("41"), Some("1"), 42));
+        assert!(!has_activated_socket(Some("42"), Some("0"), 42));
+        assert!(!has_activated_socket(None, None, 42));
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Forwarding of the events components publish to a webhook. A server with `events.webhook`
+//! configured POSTs every [`LifecycleEvent::EventPublished`] to it as JSON, in the order the
+//! events were published, so that findings reach monitoring systems without an MCP client.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use mcp_server::LifecycleManager;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use wassette::LifecycleEvent;
+
+/// How long delivering an event may take
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The `[events]` section of the configuration file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EventsConfig {
+    /// URL that published events are POSTed to as JSON
+    pub webhook: Option<String>,
+}
+
+/// Forwards events in the background. Forwarding stops when dropped.
+pub struct Forwarder {
+    ct: CancellationToken,
+}
+
+impl Drop for Forwarder {
+    fn drop(&mut self) {
+        self.ct.cancel();
+    }
+}
+
+/// Starts forwarding the events published in `lifecycle_manager` if a webhook is configured
+pub fn start(lifecycle_manager: &LifecycleManager, config: &EventsConfig) -> Option<Forwarder> {
+    let webhook = config
+        .webhook
+        .clone()
+        .filter(|webhook| !webhook.is_empty())?;
+    tracing::info!(%webhook, "Forwarding published events");
+
+    let ct = CancellationToken::new();
+    let cancelled = ct.clone();
+    let mut events = lifecycle_manager.subscribe();
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        loop {
+            tokio::select! {
+                () = cancelled.cancelled() => break,
+                event = events.recv() => match event {
+                    Ok(event @ LifecycleEvent::EventPublished { .. }) => {
+                        if let Err(e) = send(&client, &webhook, &event).await {
+                            tracing::warn!(error = %format!("{e:#}"), "Failed to forward event");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed, "Event forwarding fell behind and dropped events");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+    });
+    Some(Forwarder { ct })
+}
+
+async fn send(client: &reqwest::Client, webhook: &str, event: &LifecycleEvent) -> Result<()> {
+    client
+        .post(webhook)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(event)?)
+        .timeout(SEND_TIMEOUT)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {webhook}"))?
+        .error_for_status()?;
+    Ok(())
+}
@@ -5,28 +5,37 @@
 
 #![warn(missing_docs)]
 
-use std::future::Future;
+use std::io::{BufRead, IsTerminal, Write};
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::pin::Pin;
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use mcp_server::{
-    handle_prompts_list, handle_resources_list, handle_tools_call, handle_tools_list,
-    LifecycleManager,
-};
-use rmcp::model::{
-    CallToolRequestParam, CallToolResult, ErrorData, ListPromptsResult, ListResourcesResult,
-    ListToolsResult, PaginatedRequestParam, ServerCapabilities, ServerInfo, ToolsCapability,
-};
-use rmcp::service::{serve_server, RequestContext, RoleServer};
-use rmcp::transport::{stdio as stdio_transport, SseServer};
-use rmcp::ServerHandler;
+use clap::{Args, Parser, Subcommand};
+use component2json::{tool_schema_for_format, SchemaFormat};
+use mcp_server::access_log::AccessLog;
+use mcp_server::{LifecycleManager, McpServer};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal;
+use rmcp::service::serve_server;
+use rmcp::transport::stdio as stdio_transport;
 use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
 use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::util::SubscriberInitExt as _;
+use wassette::{CheckStatus, CredentialKind, CredentialStore, ProvisionManifest};
 
+use crate::output::OutputFormat;
+
+mod alerts;
 mod config;
+mod control;
+mod daemon;
+mod event_webhook;
+mod os_sandbox;
+mod output;
+mod repl;
+mod telemetry;
+mod top;
 
 const BIND_ADDRESS: &str = "127.0.0.1:9001";
 
@@ -41,14 +50,131 @@ struct Cli {
 enum Commands {
     /// Begin handling requests over the specified protocol.
     Serve(Serve),
+    /// Export all components, policies and configuration into a portable bundle.
+    Export(Export),
+    /// Import a bundle created with `wassette export`, verifying every file's digest.
+    Import(Import),
+    /// Show how often each tool was called, its success rate, its average latency and its peak memory use.
+    Stats(Stats),
+    /// Check the configuration, plugin directory, components, policies and registries before
+    /// deploying. Exits with a non-zero status if any check fails.
+    Check(Check),
+    /// Replay a tool call recorded with `$record`, feeding it the recorded WASI inputs.
+    Replay(Replay),
+    /// Call the tools of the installed components from an interactive prompt.
+    Repl(Repl),
+    /// Monitor a running server: call throughput, errors and latency per component, and sessions.
+    Top(Top),
+    /// Pack a component, its policy and signatures into a `.wassette-pkg` for offline installs.
+    Pack(Pack),
+    /// Store credentials for registries and APIs in the system keyring.
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+    /// Inspect loaded components.
+    Component {
+        #[command(subcommand)]
+        command: ComponentCommands,
+    },
+    /// Approve or deny tool calls that wait for approval, and review past decisions.
+    Approval {
+        #[command(subcommand)]
+        command: ApprovalCommands,
+    },
+    /// Search the audit log of approval decisions.
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+    /// Upgrade policy files to the current policy format.
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommands,
+    },
+    /// Inspect the opt-in anonymous usage reporting.
+    Telemetry {
+        #[command(subcommand)]
+        command: TelemetryCommands,
+    },
 }
 
-#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
-struct Serve {
+#[derive(Subcommand, Debug)]
+enum AuthCommands {
+    /// Store a credential in the system keyring. The password or token is read from stdin.
+    Login(Login),
+    /// Remove a stored credential.
+    Logout(Logout),
+    /// List the stored credentials, without their secrets.
+    List(AuthList),
+}
+
+#[derive(Subcommand, Debug)]
+enum ComponentCommands {
+    /// List the installed components with their labels, number of tools and the capabilities
+    /// their policies lack.
+    List(ComponentList),
+    /// Show the tools, labels, required capabilities and policy of an installed component.
+    Inspect(ComponentInspect),
+    /// Print the tool schemas of a loaded component, e.g. for use with an LLM provider SDK, or of
+    /// a component that isn't installed, given by URI.
+    Schema(Schema),
+    /// Set or remove labels of a component, e.g. `team=infra`, or print them if none are given.
+    /// A running server picks the change up when it is reloaded.
+    Label(Label),
+    /// Compile an installed component ahead of time, for this host or another target, so that
+    /// servers load it without compiling it.
+    Precompile(Precompile),
+}
+
+#[derive(Subcommand, Debug)]
+enum ApprovalCommands {
+    /// List the tool calls waiting for approval.
+    List(ApprovalList),
+    /// Let a waiting tool call run.
+    Approve(Decide),
+    /// Reject a waiting tool call.
+    Deny(Decide),
+    /// Print the audit log of approval decisions.
+    Log(ApprovalList),
+}
+
+#[derive(Subcommand, Debug)]
+enum AuditCommands {
+    /// Print the approval decisions on calls to a component or tool, in a time range or with an
+    /// outcome, oldest first and a page at a time.
+    Query(AuditQueryArgs),
+}
+
+#[derive(Subcommand, Debug)]
+enum PolicyCommands {
+    /// Print the policy attached to a component and where it came from.
+    Show(PolicyShow),
+    /// Rewrite policy files in an older format in the current one, in place. Without paths, the
+    /// policies of all installed components are migrated.
+    Migrate(PolicyMigrate),
+}
+
+#[derive(Subcommand, Debug)]
+enum TelemetryCommands {
+    /// Show whether a server with the current configuration reports usage, where to and what.
+    Status(TelemetryStatus),
+}
+
+/// The `--plugin-dir` option shared by the commands that work on a plugin directory
+#[derive(Args, Debug, Clone, Default, Serialize, Deserialize)]
+struct PluginDirArgs {
     /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wasette/components
     #[arg(long)]
     #[serde(skip_serializing_if = "Option::is_none")]
     plugin_dir: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Serve {
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
 
     /// Enable stdio transport
     #[arg(long)]
@@ -59,186 +185,1526 @@ struct Serve {
     #[arg(long)]
     #[serde(skip)]
     http: bool,
+
+    /// YAML manifest of the components to reconcile at startup
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provision: Option<PathBuf>,
+
+    /// Reconcile the provisioning manifest again whenever the server receives SIGHUP
+    #[arg(long)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    provision_on_sighup: bool,
+
+    /// Write the ID of the server process to this file while it runs
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid_file: Option<PathBuf>,
+
+    /// Only list the tools of components whose labels match this selector, e.g.
+    /// `team=infra,risk!=high`
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_selector: Option<String>,
+
+    /// Ignore the `.wassette` directory in the current working directory
+    #[arg(long)]
+    #[serde(skip)]
+    no_project: bool,
+
+    /// Confine the server process with Landlock and seccomp, limiting it to the files and ports
+    /// it and the policies of its components need. Only available on Linux
+    #[arg(long)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    os_sandbox: bool,
 }
 
-/// A security-oriented runtime that runs WebAssembly Components via MCP.
-#[derive(Clone)]
-pub struct McpServer {
-    lifecycle_manager: LifecycleManager,
+impl Serve {
+    /// Loads the configuration, applying the `.wassette` project directory unless disabled
+    fn project_dir(&self) -> Option<PathBuf> {
+        if self.no_project {
+            None
+        } else {
+            config::find_project_dir()
+        }
+    }
+
+    fn config(&self) -> Result<config::Config> {
+        let project_dir = self.project_dir();
+        if let Some(project_dir) = &project_dir {
+            tracing::info!(project_dir = %project_dir.display(), "Using project configuration");
+        }
+        config::Config::new_with_project(self, project_dir.as_deref())
+    }
 }
 
-impl McpServer {
-    /// Creates a new MCP server instance with the given lifecycle manager.
-    ///
-    /// # Arguments
-    /// * `lifecycle_manager` - The lifecycle manager for handling component operations
-    pub fn new(lifecycle_manager: LifecycleManager) -> Self {
-        Self { lifecycle_manager }
-    }
-}
-
-#[allow(refining_impl_trait_reachable)]
-impl ServerHandler for McpServer {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            capabilities: ServerCapabilities {
-                tools: Some(ToolsCapability {
-                    list_changed: Some(true),
-                }),
-                ..Default::default()
-            },
-            instructions: Some(
-                r#"This server runs tools in sandboxed WebAssembly environments with no default access to host resources.
-
-Key points:
-- Tools must be loaded before use: "Load component from oci://registry/tool:version" or "file:///path/to/tool.wasm"
-- When the server starts, it will load all tools present in the plugin directory.
-- You can list loaded tools with 'list-components' tool.
-- Each tool only accesses resources explicitly granted by a policy file (filesystem paths, network domains, etc.)
-- You MUST never modify the policy file directly, use tools to grant permissions instead.
-- Tools needs permission for that resource
-- If access is denied, suggest alternatives within allowed permissions or propose to grant permission"#.to_string(),
-            ),
-            ..Default::default()
-        }
-    }
-
-    fn call_tool<'a>(
-        &'a self,
-        params: CallToolRequestParam,
-        ctx: RequestContext<RoleServer>,
-    ) -> Pin<Box<dyn Future<Output = Result<CallToolResult, ErrorData>> + Send + 'a>> {
-        let peer_clone = ctx.peer.clone();
-
-        Box::pin(async move {
-            let result = handle_tools_call(params, &self.lifecycle_manager, peer_clone).await;
-            match result {
-                Ok(value) => serde_json::from_value(value).map_err(|e| {
-                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
-                }),
-                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
-            }
-        })
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Export {
+    /// Path of the bundle to write, e.g. `bundle.tar.zst`
+    #[serde(skip)]
+    path: PathBuf,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Import {
+    /// Path of the bundle to import
+    #[serde(skip)]
+    path: PathBuf,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+
+    /// Replace existing files that differ from the ones in the bundle
+    #[arg(long)]
+    #[serde(skip)]
+    overwrite: bool,
+
+    /// Do not restore the configuration file contained in the bundle
+    #[arg(long)]
+    #[serde(skip)]
+    skip_config: bool,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct ComponentList {
+    /// Only list the components whose labels match this selector, e.g. `team=infra,risk!=high`
+    #[arg(long)]
+    #[serde(skip)]
+    labels: Option<String>,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct ComponentInspect {
+    /// ID or name of the component
+    #[serde(skip)]
+    component_id: String,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Schema {
+    /// ID or name of the component, or the URI of a component to inspect without installing it,
+    /// e.g. `oci://ghcr.io/microsoft/fetch-rs:latest`
+    #[serde(skip)]
+    component_id: String,
+
+    /// Format of the emitted tool schemas: mcp, openai or anthropic
+    #[arg(long, default_value_t = SchemaFormat::Mcp)]
+    #[serde(skip)]
+    format: SchemaFormat,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Precompile {
+    /// ID or name of the component
+    #[serde(skip)]
+    component_id: String,
+
+    /// Target to compile for, e.g. `x86_64-linux`, `aarch64-macos` or a full target triple.
+    /// Defaults to this host
+    #[arg(long)]
+    #[serde(skip)]
+    target: Option<String>,
+
+    /// File to write the precompiled component to. Defaults to `<digest>.cwasm` in the
+    /// `precompiled` directory of the plugin directory when compiling for this host, and in the
+    /// current directory otherwise
+    #[arg(long, short)]
+    #[serde(skip)]
+    output: Option<PathBuf>,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Label {
+    /// ID or name of the component
+    #[serde(skip)]
+    component_id: String,
+
+    /// Labels to set, as `key=value`
+    #[arg(value_parser = wassette::parse_label)]
+    #[serde(skip)]
+    labels: Vec<(String, String)>,
+
+    /// Key of a label to remove. Can be given multiple times
+    #[arg(long = "remove")]
+    #[serde(skip)]
+    remove: Vec<String>,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Stats {
+    /// Only show the tools of this component, given by ID or name
+    #[arg(long)]
+    #[serde(skip)]
+    component: Option<String>,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Check {
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+
+    /// Skip the checks that need network access, such as reaching registries
+    #[arg(long)]
+    #[serde(skip)]
+    offline: bool,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Replay {
+    /// Path of the trace to replay, from the `traces` directory in the plugin directory
+    #[serde(skip)]
+    path: PathBuf,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Repl {
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Top {
+    /// Control socket of the server. Defaults to the configured `control_socket`
+    #[arg(long)]
+    #[serde(skip)]
+    socket: Option<PathBuf>,
+
+    /// Seconds between refreshes
+    #[arg(long, default_value_t = 1)]
+    #[serde(skip)]
+    interval: u64,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct Pack {
+    /// Path of the component. Its file name without `.wasm` becomes the component ID
+    component: PathBuf,
+
+    /// Policy to install along with the component
+    #[arg(long)]
+    policy: Option<PathBuf>,
+
+    /// Detached signature of the component to include. Can be given multiple times
+    #[arg(long = "signature")]
+    signatures: Vec<PathBuf>,
+
+    /// Metadata to record in the package, as `key=value`. Can be given multiple times
+    #[arg(long = "metadata", value_parser = parse_key_value)]
+    metadata: Vec<(String, String)>,
+
+    /// Path of the package to write. Defaults to `<component ID>.wassette-pkg`
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .with_context(|| format!("Expected key=value, got '{s}'"))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Login {
+    /// Registry (e.g. `ghcr.io`), HTTPS host or secrets provider the credential is for
+    #[serde(skip)]
+    target: String,
+
+    /// What the credential is used for: registry, http-token or secret
+    #[arg(long, default_value_t = CredentialKind::Registry)]
+    #[serde(skip)]
+    kind: CredentialKind,
+
+    /// Username for the registry, or for basic auth with an HTTPS host
+    #[arg(long, short)]
+    #[serde(skip)]
+    username: Option<String>,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Logout {
+    /// Registry, HTTPS host or secrets provider the credential is for
+    #[serde(skip)]
+    target: String,
+
+    /// What the credential is used for: registry, http-token or secret
+    #[arg(long, default_value_t = CredentialKind::Registry)]
+    #[serde(skip)]
+    kind: CredentialKind,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct AuthList {
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct ApprovalList {
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct AuditQueryArgs {
+    /// Only decisions on calls to this component, by ID or name
+    #[arg(long)]
+    #[serde(skip)]
+    component: Option<String>,
+
+    /// Only decisions on calls to this tool
+    #[arg(long)]
+    #[serde(skip)]
+    tool: Option<String>,
+
+    /// Only decisions made at or after this time: an RFC 3339 timestamp, milliseconds since the
+    /// Unix epoch or a duration before now such as `12h` or `7d`
+    #[arg(long, value_parser = wassette::parse_audit_time)]
+    #[serde(skip)]
+    since: Option<u64>,
+
+    /// Only decisions made before this time, in the same formats as `--since`
+    #[arg(long, value_parser = wassette::parse_audit_time)]
+    #[serde(skip)]
+    until: Option<u64>,
+
+    /// Only decisions with this outcome: approved, denied or expired
+    #[arg(long)]
+    #[serde(skip)]
+    outcome: Option<wassette::ApprovalOutcome>,
+
+    /// Largest number of decisions to print
+    #[arg(long, default_value_t = wassette::DEFAULT_AUDIT_PAGE_SIZE)]
+    #[serde(skip)]
+    limit: usize,
+
+    /// Cursor of the page to print, as printed after the previous page
+    #[arg(long)]
+    #[serde(skip)]
+    cursor: Option<String>,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct PolicyShow {
+    /// ID or name of the component
+    #[serde(skip)]
+    component_id: String,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct PolicyMigrate {
+    /// Policy files to migrate
+    #[serde(skip)]
+    paths: Vec<PathBuf>,
+
+    /// Show what would change without writing anything
+    #[arg(long)]
+    #[serde(skip)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct TelemetryStatus {
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+
+    /// Output format: table, json or yaml
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    output: OutputFormat,
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+struct Decide {
+    /// ID of the waiting call, as shown by `wassette approval list`
+    #[serde(skip)]
+    id: String,
+
+    /// Who made the decision, for the audit log. Defaults to the current user
+    #[arg(long)]
+    #[serde(skip)]
+    by: Option<String>,
+
+    /// Why the call was approved or denied
+    #[arg(long)]
+    #[serde(skip)]
+    reason: Option<String>,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    plugin_dir: PluginDirArgs,
+}
+
+impl Decide {
+    /// Records the decision for the waiting call
+    async fn decide(&self, approve: bool) -> Result<()> {
+        let config = config::Config::new(self).context("Failed to load configuration")?;
+        let by = self
+            .by
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        let request = wassette::decide_approval(
+            &config.plugin_dir,
+            &self.id,
+            approve,
+            &by,
+            self.reason.clone(),
+        )
+        .await?;
+        println!(
+            "{} call to '{}' of {}",
+            if approve { "Approved" } else { "Denied" },
+            request.tool,
+            request.component_id
+        );
+        Ok(())
     }
+}
 
-    fn list_tools<'a>(
-        &'a self,
-        _params: Option<PaginatedRequestParam>,
-        _ctx: RequestContext<RoleServer>,
-    ) -> Pin<Box<dyn Future<Output = Result<ListToolsResult, ErrorData>> + Send + 'a>> {
-        Box::pin(async move {
-            let result = handle_tools_list(&self.lifecycle_manager).await;
-            match result {
-                Ok(value) => serde_json::from_value(value).map_err(|e| {
-                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
-                }),
-                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
-            }
-        })
+/// Reads a password or token from the first line of stdin. On a terminal, it prompts for it and
+/// doesn't echo what is typed.
+fn read_secret(prompt: &str) -> Result<String> {
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        let mut secret = String::new();
+        stdin
+            .lock()
+            .read_line(&mut secret)
+            .context("Failed to read secret from stdin")?;
+        return Ok(secret.trim_end_matches(['\r', '\n']).to_string());
     }
 
-    fn list_prompts<'a>(
-        &'a self,
-        _params: Option<PaginatedRequestParam>,
-        _ctx: RequestContext<RoleServer>,
-    ) -> Pin<Box<dyn Future<Output = Result<ListPromptsResult, ErrorData>> + Send + 'a>> {
-        Box::pin(async move {
-            let result = handle_prompts_list(serde_json::Value::Null).await;
-            match result {
-                Ok(value) => serde_json::from_value(value).map_err(|e| {
-                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
-                }),
-                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
+    eprint!("{prompt}: ");
+    std::io::stderr().flush()?;
+    terminal::enable_raw_mode().context("Failed to turn off echo on the terminal")?;
+    let secret = read_secret_keys();
+    // Restore the terminal before reporting any error
+    terminal::disable_raw_mode().context("Failed to restore the terminal")?;
+    eprintln!();
+    secret
+}
+
+/// Reads typed keys up to Enter while the terminal is in raw mode, so nothing is echoed
+fn read_secret_keys() -> Result<String> {
+    let mut secret = String::new();
+    loop {
+        let Event::Key(key) = event::read().context("Failed to read secret from the terminal")?
+        else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(secret),
+            KeyCode::Char('c' | 'd') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                anyhow::bail!("Cancelled")
             }
-        })
+            KeyCode::Char(c) => secret.push(c),
+            KeyCode::Backspace => {
+                secret.pop();
+            }
+            _ => {}
+        }
     }
+}
 
-    fn list_resources<'a>(
-        &'a self,
-        _params: Option<PaginatedRequestParam>,
-        _ctx: RequestContext<RoleServer>,
-    ) -> Pin<Box<dyn Future<Output = Result<ListResourcesResult, ErrorData>> + Send + 'a>> {
-        Box::pin(async move {
-            let result = handle_resources_list(serde_json::Value::Null).await;
-            match result {
-                Ok(value) => serde_json::from_value(value).map_err(|e| {
-                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
-                }),
-                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
-            }
-        })
+/// Reconciles the components with the provisioning manifest in `config`, if there is one. The
+/// manifest is read again every time, so edits are picked up without a restart.
+async fn provision(lifecycle_manager: &LifecycleManager, config: &config::Config) -> Result<()> {
+    if let Some(manifest_path) = &config.provision {
+        let manifest = ProvisionManifest::from_file(manifest_path)?;
+        lifecycle_manager.provision(&manifest).await;
     }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+/// Serves the REST management API on its own listener at `address` until `ct` is cancelled
+async fn serve_admin_api(
+    lifecycle_manager: LifecycleManager,
+    config: &config::Config,
+    address: &str,
+    ct: CancellationToken,
+) -> Result<()> {
+    let token = config
+        .admin_token
+        .as_deref()
+        .filter(|token| !token.is_empty())
+        .context("admin_address is set, but admin_token isn't; set WASETTE_ADMIN_TOKEN")?;
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Failed to bind management API to {address}"))?;
+    tracing::info!("Serving management API on {}", listener.local_addr()?);
+    let router =
+        axum::Router::new().nest("/v1", mcp_server::admin::router(lifecycle_manager, token));
+    tokio::spawn(async move {
+        let server = axum::serve(listener, router)
+            .with_graceful_shutdown(async move { ct.cancelled().await });
+        if let Err(e) = server.await {
+            tracing::error!(error = %e, "Management API stopped with an error");
+        }
+    });
+    Ok(())
+}
 
-    match &cli.command {
-        Commands::Serve(cfg) => {
-            // Initialize logging based on transport type
-            let use_stdio_transport = match (cfg.stdio, cfg.http) {
-                (false, false) => true, // Default case: use stdio transport
-                (true, false) => true,  // Stdio transport only
-                (false, true) => false, // HTTP transport only
-                (true, true) => {
-                    return Err(anyhow::anyhow!(
-                        "Running both stdio and HTTP transports simultaneously is not supported. Please choose one."
-                    ));
+/// Reloads the configuration file and re-scans the plugin directory whenever the server receives
+/// SIGHUP, and reconciles the provisioning manifest again if `provision_on_sighup` is set.
+/// Components are swapped within the running lifecycle manager, so sessions stay connected.
+#[cfg(unix)]
+fn reload_on_sighup(
+    lifecycle_manager: LifecycleManager,
+    cli_config: Serve,
+    plugin_dir: PathBuf,
+) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup()).context("Failed to listen for SIGHUP")?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            tracing::info!("Received SIGHUP, reloading configuration");
+            let config = match cli_config.config() {
+                Ok(config) => config,
+                Err(e) => {
+                    tracing::error!(error = %format!("{e:#}"), "Failed to reload configuration, keeping the current one");
+                    continue;
                 }
             };
+            if config.plugin_dir != plugin_dir {
+                tracing::warn!(
+                    plugin_dir = %config.plugin_dir.display(),
+                    "Changing the plugin directory requires a restart"
+                );
+            }
+            lifecycle_manager.reload(config.lifecycle_config()).await;
+            if config.provision_on_sighup {
+                if let Err(e) = provision(&lifecycle_manager, &config).await {
+                    tracing::error!(error = %format!("{e:#}"), "Failed to reconcile provisioning manifest");
+                }
+            }
+        }
+    });
+    Ok(())
+}
 
-            // Configure logging - use stderr for stdio transport to avoid interfering with MCP protocol
-            let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| {
-                    "info,cranelift_codegen=warn,cranelift_entity=warn,cranelift_bforest=warn,cranelift_frontend=warn"
-                        .to_string()
-                        .into()
-                });
-
-            let registry = tracing_subscriber::registry().with(env_filter);
-
-            if use_stdio_transport {
-                registry
-                    .with(
-                        tracing_subscriber::fmt::layer()
-                            .with_writer(std::io::stderr)
-                            .with_ansi(false),
-                    )
-                    .init();
+#[cfg(not(unix))]
+fn reload_on_sighup(
+    _lifecycle_manager: LifecycleManager,
+    _cli_config: Serve,
+    _plugin_dir: PathBuf,
+) -> Result<()> {
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // Landlock and seccomp only confine the threads started after them, so the OS sandbox has to
+    // be in place before the runtime starts its workers
+    let os_sandbox = match &cli.command {
+        Commands::Serve(cfg) => {
+            let config = cfg.config().context("Failed to load configuration")?;
+            if config.os_sandbox {
+                let bind_port = BIND_ADDRESS.parse::<std::net::SocketAddr>()?.port();
+                Some(os_sandbox::apply(
+                    &config,
+                    cfg.project_dir().as_deref(),
+                    bind_port,
+                )?)
             } else {
-                registry.with(tracing_subscriber::fmt::layer()).init();
+                None
             }
+        }
+        _ => None,
+    };
 
-            let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(cli, os_sandbox))
+}
 
-            let lifecycle_manager = LifecycleManager::new(&config.plugin_dir).await?;
+async fn run(cli: Cli, os_sandbox: Option<os_sandbox::Enforcement>) -> Result<()> {
+    match &cli.command {
+        Commands::Serve(cfg) => serve(cfg, os_sandbox).await,
+        Commands::Export(cfg) => export(cfg).await,
+        Commands::Import(cfg) => import(cfg).await,
+        Commands::Check(cfg) => check(cfg).await,
+        Commands::Stats(cfg) => stats(cfg).await,
+        Commands::Replay(cfg) => replay(cfg).await,
+        Commands::Repl(cfg) => start_repl(cfg).await,
+        Commands::Top(cfg) => start_top(cfg).await,
+        Commands::Pack(cfg) => pack(cfg).await,
+        Commands::Auth {
+            command: AuthCommands::Login(cfg),
+        } => login(cfg).await,
+        Commands::Auth {
+            command: AuthCommands::Logout(cfg),
+        } => logout(cfg).await,
+        Commands::Auth {
+            command: AuthCommands::List(cfg),
+        } => list_credentials(cfg).await,
+        Commands::Component {
+            command: ComponentCommands::List(cfg),
+        } => list_components(cfg).await,
+        Commands::Component {
+            command: ComponentCommands::Inspect(cfg),
+        } => inspect_component(cfg).await,
+        Commands::Component {
+            command: ComponentCommands::Schema(cfg),
+        } => print_schema(cfg).await,
+        Commands::Component {
+            command: ComponentCommands::Label(cfg),
+        } => label(cfg).await,
+        Commands::Component {
+            command: ComponentCommands::Precompile(cfg),
+        } => precompile(cfg).await,
+        Commands::Approval {
+            command: ApprovalCommands::List(cfg),
+        } => list_approvals(cfg).await,
+        Commands::Approval {
+            command: ApprovalCommands::Approve(cfg),
+        } => cfg.decide(true).await,
+        Commands::Approval {
+            command: ApprovalCommands::Deny(cfg),
+        } => cfg.decide(false).await,
+        Commands::Approval {
+            command: ApprovalCommands::Log(cfg),
+        } => approval_log(cfg).await,
+        Commands::Audit {
+            command: AuditCommands::Query(cfg),
+        } => query_audit_log(cfg).await,
+        Commands::Policy {
+            command: PolicyCommands::Show(cfg),
+        } => show_policy(cfg).await,
+        Commands::Policy {
+            command: PolicyCommands::Migrate(cfg),
+        } => migrate_policies(cfg).await,
+        Commands::Telemetry {
+            command: TelemetryCommands::Status(cfg),
+        } => telemetry_status(cfg).await,
+    }
+}
 
-            let server = McpServer::new(lifecycle_manager);
+/// Runs the MCP server until it receives a shutdown signal
+async fn serve(cfg: &Serve, os_sandbox: Option<os_sandbox::Enforcement>) -> Result<()> {
+    // Initialize logging based on transport type
+    let use_stdio_transport = match (cfg.stdio, cfg.http) {
+        (false, false) => true, // Default case: use stdio transport
+        (true, false) => true,  // Stdio transport only
+        (false, true) => false, // HTTP transport only
+        (true, true) => {
+            return Err(anyhow::anyhow!(
+                "Running both stdio and HTTP transports simultaneously is not supported. Please choose one."
+            ));
+        }
+    };
 
-            if use_stdio_transport {
-                tracing::info!("Starting MCP server with stdio transport");
-                let transport = stdio_transport();
-                let running_service = serve_server(server, transport).await?;
+    // Configure logging - use stderr for stdio transport to avoid interfering with MCP protocol
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| {
+            "info,cranelift_codegen=warn,cranelift_entity=warn,cranelift_bforest=warn,cranelift_frontend=warn"
+                .to_string()
+                .into()
+        });
 
-                tokio::signal::ctrl_c().await?;
-                let _ = running_service.cancel().await;
-            } else {
-                tracing::info!(
-                    "Starting MCP server on {} with HTTP transport",
-                    BIND_ADDRESS
-                );
-                let ct = SseServer::serve(BIND_ADDRESS.parse().unwrap())
-                    .await?
-                    .with_service(move || server.clone());
+    let registry = tracing_subscriber::registry().with(env_filter);
+
+    if use_stdio_transport {
+        registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(std::io::stderr)
+                    .with_ansi(false),
+            )
+            .init();
+    } else {
+        registry.with(tracing_subscriber::fmt::layer()).init();
+    }
+
+    match os_sandbox {
+        Some(os_sandbox::Enforcement::Full) => {
+            tracing::info!("Confined the server with Landlock and seccomp")
+        }
+        Some(enforcement) => tracing::warn!(
+            %enforcement,
+            "The kernel doesn't support every Landlock restriction of the OS sandbox"
+        ),
+        None => {}
+    }
+
+    let config = cfg.config().context("Failed to load configuration")?;
+    let _pid_file = config
+        .pid_file
+        .as_ref()
+        .map(daemon::PidFile::create)
+        .transpose()?;
+
+    let lifecycle_manager =
+        LifecycleManager::new_with_config(&config.plugin_dir, config.lifecycle_config()).await?;
+
+    provision(&lifecycle_manager, &config).await?;
+    // The server works without it, it just can't be monitored with `wassette top`
+    let _control_socket =
+        match control::bind(lifecycle_manager.clone(), config.control_socket_path()) {
+            Ok(socket) => Some(socket),
+            Err(e) => {
+                tracing::warn!(error = %format!("{e:#}"), "Failed to open control socket");
+                None
+            }
+        };
+    let admin = CancellationToken::new();
+    if let Some(address) = &config.admin_address {
+        serve_admin_api(lifecycle_manager.clone(), &config, address, admin.clone()).await?;
+    }
+    reload_on_sighup(
+        lifecycle_manager.clone(),
+        cfg.clone(),
+        config.plugin_dir.clone(),
+    )?;
+    let _telemetry = telemetry::start(lifecycle_manager.clone(), &config.telemetry);
+    let _event_webhook = event_webhook::start(&lifecycle_manager, &config.events);
+    let _alerts = alerts::start(&lifecycle_manager, &config.alerts).await;
+
+    if use_stdio_transport {
+        tracing::info!("Starting MCP server with stdio transport");
+        let server = McpServer::new(lifecycle_manager.clone());
+        let transport = stdio_transport();
+        let running_service = serve_server(server, transport).await?;
+        daemon::notify("READY=1");
+
+        daemon::shutdown_signal().await?;
+        daemon::notify("STOPPING=1");
+        let _ = running_service.cancel().await;
+    } else {
+        let listener = match daemon::activated_listener()? {
+            Some(listener) => {
+                tracing::info!("Using the socket passed by systemd");
+                tokio::net::TcpListener::from_std(listener)?
+            }
+            None => tokio::net::TcpListener::bind(BIND_ADDRESS).await?,
+        };
+        tracing::info!(
+            "Starting MCP server on {} with HTTP transport",
+            listener.local_addr()?
+        );
+        let ct = CancellationToken::new();
+        let mut router = mcp_server::http::router_with_cancellation(
+            McpServer::new(lifecycle_manager.clone()),
+            ct.clone(),
+        );
+        if config.access_log {
+            let path = AccessLog::path_in(&config.plugin_dir);
+            let access_log = AccessLog::open(&path, config.trusted_proxies.clone()).await?;
+            tracing::info!(path = %path.display(), "Writing access log");
+            router = access_log.layer(router);
+        }
+        let shutdown = ct.child_token();
+        tokio::spawn(async move {
+            let service = router.into_make_service_with_connect_info::<SocketAddr>();
+            let server = axum::serve(listener, service)
+                .with_graceful_shutdown(async move { shutdown.cancelled().await });
+            if let Err(e) = server.await {
+                tracing::error!(error = %e, "HTTP server stopped with an error");
+            }
+        });
+        daemon::notify("READY=1");
+
+        daemon::shutdown_signal().await?;
+        daemon::notify("STOPPING=1");
+        ct.cancel();
+    }
+
+    admin.cancel();
+    tracing::info!("MCP server shutting down");
+    if let Err(e) = lifecycle_manager.flush_tool_stats().await {
+        tracing::warn!(error = %e, "Failed to persist tool statistics");
+    }
+    Ok(())
+}
+
+/// Writes the plugin directory and configuration file to a bundle
+async fn export(cfg: &Export) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let config_file = config::get_config_file_path()?;
+    let manifest = wassette::export_bundle(&config.plugin_dir, Some(&config_file), &cfg.path)
+        .await
+        .context("Failed to export bundle")?;
+    println!(
+        "Exported {} component(s) and {} file(s) to {}",
+        manifest.component_ids().len(),
+        manifest.entries.len(),
+        cfg.path.display()
+    );
+    Ok(())
+}
+
+/// Unpacks a bundle into the plugin directory
+async fn import(cfg: &Import) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let config_file = if cfg.skip_config {
+        None
+    } else {
+        Some(config::get_config_file_path()?)
+    };
+    let manifest = wassette::import_bundle(
+        &cfg.path,
+        &config.plugin_dir,
+        config_file.as_deref(),
+        cfg.overwrite,
+    )
+    .await
+    .context("Failed to import bundle")?;
+    println!(
+        "Imported {} component(s) from {} into {}",
+        manifest.component_ids().len(),
+        cfg.path.display(),
+        config.plugin_dir.display()
+    );
+    Ok(())
+}
 
-                tokio::signal::ctrl_c().await?;
-                ct.cancel();
+/// Validates the configuration and environment, exiting with 1 if a check fails
+async fn check(cfg: &Check) -> Result<()> {
+    let mut report = wassette::CheckReport::default();
+    match config::Config::new(cfg) {
+        Ok(config) => {
+            report.push("config", CheckStatus::Pass, "Configuration is valid");
+            let mut sources = Vec::new();
+            if let Some(path) = &config.provision {
+                match ProvisionManifest::from_file(path) {
+                    Ok(manifest) => {
+                        report.push(
+                            "provision",
+                            CheckStatus::Pass,
+                            format!(
+                                "{} lists {} components",
+                                path.display(),
+                                manifest.components.len()
+                            ),
+                        );
+                        sources.extend(manifest.components.into_iter().flat_map(|component| {
+                            std::iter::once(component.uri).chain(component.policy)
+                        }));
+                    }
+                    Err(e) => report.push("provision", CheckStatus::Fail, format!("{e:#}")),
+                }
             }
+            let environment = wassette::check_environment(
+                &config.plugin_dir,
+                &config.lifecycle_config(),
+                &sources,
+                cfg.offline,
+            )
+            .await;
+            report.checks.extend(environment.checks);
+        }
+        Err(e) => report.push("config", CheckStatus::Fail, format!("{e:#}")),
+    }
+
+    cfg.output.print(&report, |report| {
+        for check in &report.checks {
+            println!("{}  {}  {}", check.status, check.check, check.message);
+        }
+    })?;
+    if !report.passed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Prints the recorded usage of every tool
+async fn stats(cfg: &Stats) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let usage = wassette::UsageStats::load(&config.plugin_dir).await?;
+    let mut rows: Vec<_> = usage
+        .iter()
+        .filter(|(component_id, _, _)| {
+            cfg.component.as_deref().is_none_or(|id| {
+                id == *component_id || id == wassette::component_name(component_id)
+            })
+        })
+        .map(|(component_id, tool, stats)| output::StatsRow::new(component_id, tool, stats))
+        .collect();
+    // Most used tools first
+    rows.sort_by_key(|row| std::cmp::Reverse(row.stats.invocations));
+
+    cfg.output.print(&rows, |rows| {
+        if rows.is_empty() {
+            println!("No tool usage recorded yet");
+            return;
+        }
+        let component_width = rows
+            .iter()
+            .map(|r| r.component_id.len())
+            .max()
+            .unwrap_or(0)
+            .max(9);
+        let tool_width = rows.iter().map(|r| r.tool.len()).max().unwrap_or(0).max(4);
+        println!(
+            "{:<component_width$}  {:<tool_width$}  {:>8}  {:>8}  {:>12}  {:>10}",
+            "COMPONENT", "TOOL", "CALLS", "SUCCESS", "AVG LATENCY", "PEAK MEM"
+        );
+        for row in rows {
+            println!(
+                "{:<component_width$}  {:<tool_width$}  {:>8}  {:>7.1}%  {:>10.1}ms  {:>8.1}MiB",
+                row.component_id,
+                row.tool,
+                row.stats.invocations,
+                row.success_rate.unwrap_or_default() * 100.0,
+                row.average_latency_ms.unwrap_or_default(),
+                row.stats.peak_memory_bytes as f64 / (1024.0 * 1024.0)
+            );
+        }
+    })?;
+    Ok(())
+}
+
+/// Replays a recorded trace and compares the outcome with the recorded one
+async fn replay(cfg: &Replay) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let trace = wassette::Trace::load(&cfg.path).await?;
+    let lifecycle_manager =
+        LifecycleManager::new_with_config(&config.plugin_dir, config.lifecycle_config()).await?;
+    let recorded = match (&trace.result, &trace.error) {
+        (Some(result), _) => Ok(result.clone()),
+        (None, Some(error)) => Err(error.clone()),
+        (None, None) => Err("no outcome recorded".to_string()),
+    };
+    let replayed = lifecycle_manager
+        .replay_trace(trace)
+        .await
+        .map_err(|e| format!("{e:#}"));
+    match &replayed {
+        Ok(result) => println!("{result}"),
+        Err(error) => eprintln!("Replayed call failed: {error}"),
+    }
+    if replayed != recorded {
+        eprintln!("The replayed outcome differs from the recorded one:");
+        match recorded {
+            Ok(result) => eprintln!("  recorded result: {result}"),
+            Err(error) => eprintln!("  recorded error: {error}"),
+        }
+    }
+    Ok(())
+}
 
-            tracing::info!("MCP server shutting down");
+/// Starts the interactive shell on the installed components
+async fn start_repl(cfg: &Repl) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let lifecycle_manager =
+        LifecycleManager::new_with_config(&config.plugin_dir, config.lifecycle_config()).await?;
+    repl::run(&lifecycle_manager, &config.plugin_dir).await?;
+    Ok(())
+}
+
+/// Shows the live view of a running server
+async fn start_top(cfg: &Top) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let socket = cfg
+        .socket
+        .clone()
+        .unwrap_or_else(|| config.control_socket_path());
+    let interval = std::time::Duration::from_secs(cfg.interval.max(1));
+    tokio::task::block_in_place(|| top::run(&socket, interval))?;
+    Ok(())
+}
+
+/// Packs a component, its policy and signatures into a package
+async fn pack(cfg: &Pack) -> Result<()> {
+    let output = match &cfg.output {
+        Some(output) => output.clone(),
+        None => {
+            let id = cfg
+                .component
+                .file_stem()
+                .context("Component path has no file name")?;
+            PathBuf::from(format!(
+                "{}.{}",
+                id.to_string_lossy(),
+                wassette::PACKAGE_EXTENSION
+            ))
         }
+    };
+    let manifest = wassette::pack_component(
+        wassette::PackOptions {
+            component: cfg.component.clone(),
+            policy: cfg.policy.clone(),
+            signatures: cfg.signatures.clone(),
+            metadata: cfg.metadata.iter().cloned().collect(),
+        },
+        &output,
+    )
+    .await?;
+    println!(
+        "Packed component '{}' ({} files) into {}",
+        manifest.component_id,
+        manifest.entries.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Stores a credential read from the terminal or stdin
+async fn login(cfg: &Login) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    if cfg.kind == CredentialKind::Registry && cfg.username.is_none() {
+        anyhow::bail!("Registry credentials need a username, pass it with --username");
     }
+    let prompt = match cfg.kind {
+        CredentialKind::Registry => "Password",
+        CredentialKind::HttpToken => "Token",
+        CredentialKind::Secret => "Key",
+    };
+    let secret = read_secret(prompt)?;
+    CredentialStore::open(&config.plugin_dir).login(
+        cfg.kind,
+        &cfg.target,
+        cfg.username.as_deref(),
+        &secret,
+    )?;
+    println!("Stored {} credential for {}", cfg.kind, cfg.target);
+    Ok(())
+}
 
+/// Removes a stored credential
+async fn logout(cfg: &Logout) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    if CredentialStore::open(&config.plugin_dir).logout(cfg.kind, &cfg.target)? {
+        println!("Removed {} credential for {}", cfg.kind, cfg.target);
+    } else {
+        println!("No {} credential stored for {}", cfg.kind, cfg.target);
+    }
+    Ok(())
+}
+
+/// Prints the stored credentials, without their secrets
+async fn list_credentials(cfg: &AuthList) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let entries = CredentialStore::open(&config.plugin_dir).list()?;
+    cfg.output.print(&entries, |entries| {
+        if entries.is_empty() {
+            println!("No credentials stored");
+            return;
+        }
+        let target_width = entries
+            .iter()
+            .map(|e| e.target.len())
+            .max()
+            .unwrap_or(0)
+            .max(6);
+        println!("{:<10}  {:<target_width$}  USERNAME", "KIND", "TARGET");
+        for entry in entries {
+            println!(
+                "{:<10}  {:<target_width$}  {}",
+                entry.kind.to_string(),
+                entry.target,
+                entry.username.as_deref().unwrap_or("-")
+            );
+        }
+    })?;
+    Ok(())
+}
+
+/// Prints the installed components matching the label selector
+async fn list_components(cfg: &ComponentList) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let selector = cfg
+        .labels
+        .as_deref()
+        .map(str::parse::<wassette::LabelSelector>)
+        .transpose()?;
+    let lifecycle_manager =
+        LifecycleManager::new_with_config(&config.plugin_dir, config.lifecycle_config()).await?;
+    let component_ids = match &selector {
+        Some(selector) => lifecycle_manager.list_components_matching(selector).await,
+        None => lifecycle_manager.list_components().await,
+    };
+    let mut rows = Vec::new();
+    for component_id in component_ids {
+        rows.push(output::ComponentRow {
+            labels: lifecycle_manager
+                .get_component_labels(&component_id)
+                .await
+                .unwrap_or_default(),
+            tools: lifecycle_manager
+                .get_component_schema(&component_id)
+                .await
+                .map_or(0, |schema| schema.tools.len()),
+            capability_gaps: lifecycle_manager
+                .get_capability_gaps(&component_id)
+                .await
+                .unwrap_or_default(),
+            component_id,
+        });
+    }
+    cfg.output.print(&rows, |rows| {
+        if rows.is_empty() {
+            println!("No components installed");
+            return;
+        }
+        let width = rows
+            .iter()
+            .map(|row| row.component_id.len())
+            .max()
+            .unwrap_or(0)
+            .max(9);
+        println!(
+            "{:<width$}  {:>5}  {:<24}  LABELS",
+            "COMPONENT", "TOOLS", "MISSING"
+        );
+        for row in rows {
+            println!(
+                "{:<width$}  {:>5}  {:<24}  {}",
+                row.component_id,
+                row.tools,
+                output::capability_names(&row.capability_gaps),
+                output::label_list(&row.labels)
+            );
+        }
+    })?;
+    Ok(())
+}
+
+/// Prints the details of an installed component
+async fn inspect_component(cfg: &ComponentInspect) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let lifecycle_manager =
+        LifecycleManager::new_with_config(&config.plugin_dir, config.lifecycle_config()).await?;
+    let component_id = lifecycle_manager
+        .resolve_component_id(&cfg.component_id)
+        .await?;
+    let schema = lifecycle_manager
+        .get_component_schema(&component_id)
+        .await
+        .with_context(|| format!("Component not found: {}", cfg.component_id))?;
+    let details = output::ComponentDetails {
+        labels: lifecycle_manager
+            .get_component_labels(&component_id)
+            .await
+            .unwrap_or_default(),
+        world: schema.world,
+        tools: schema
+            .tools
+            .into_iter()
+            .map(|tool| output::ToolSummary {
+                name: tool.name,
+                description: tool.description,
+            })
+            .collect(),
+        required_capabilities: lifecycle_manager
+            .get_required_capabilities(&component_id)
+            .await
+            .unwrap_or_default(),
+        capability_gaps: lifecycle_manager
+            .get_capability_gaps(&component_id)
+            .await
+            .unwrap_or_default(),
+        policy: lifecycle_manager
+            .get_policy_info(&component_id)
+            .await
+            .map(output::PolicySource::from),
+        component_id,
+    };
+    cfg.output.print(&details, |details| {
+        println!("Component: {}", details.component_id);
+        if let Some(world) = &details.world {
+            println!("World: {world}");
+        }
+        println!("Labels: {}", output::label_list(&details.labels));
+        match &details.policy {
+            Some(policy) => println!("Policy: {}", policy.local_path.display()),
+            None => println!("Policy: none"),
+        }
+        println!(
+            "Required capabilities: {}",
+            output::capability_names(&details.required_capabilities)
+        );
+        println!(
+            "Missing capabilities: {}",
+            output::capability_names(&details.capability_gaps)
+        );
+        println!("Tools:");
+        for tool in &details.tools {
+            match &tool.description {
+                Some(description) => println!("  {}: {description}", tool.name),
+                None => println!("  {}", tool.name),
+            }
+        }
+    })?;
+    Ok(())
+}
+
+/// Prints the tool schemas of an installed or remote component
+async fn print_schema(cfg: &Schema) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let lifecycle_manager =
+        LifecycleManager::new_with_config(&config.plugin_dir, config.lifecycle_config()).await?;
+    let schema = if cfg.component_id.contains("://") {
+        let inspected = lifecycle_manager
+            .inspect_component(&cfg.component_id)
+            .await?;
+        tracing::info!(source = ?inspected.source, "Inspected component");
+        inspected.document
+    } else {
+        let component_id = lifecycle_manager
+            .resolve_component_id(&cfg.component_id)
+            .await?;
+        lifecycle_manager
+            .get_component_schema(&component_id)
+            .await
+            .with_context(|| format!("Component not found: {}", cfg.component_id))?
+    };
+    let tools: Vec<serde_json::Value> = schema
+        .tools
+        .iter()
+        .map(|tool| tool_schema_for_format(tool, cfg.format))
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&tools)?);
+    Ok(())
+}
+
+/// Sets or removes labels of a component
+async fn label(cfg: &Label) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let set = cfg.labels.iter().cloned().collect();
+    let (component_id, labels) =
+        wassette::label_component(&config.plugin_dir, &cfg.component_id, &set, &cfg.remove).await?;
+    let labels = output::ComponentLabels {
+        component_id,
+        labels,
+    };
+    cfg.output.print(&labels, |labels| {
+        if labels.labels.is_empty() {
+            println!("{} has no labels", labels.component_id);
+        }
+        for (key, value) in &labels.labels {
+            println!("{key}={value}");
+        }
+    })?;
+    Ok(())
+}
+
+/// Compiles a component ahead of time for this host or another target
+async fn precompile(cfg: &Precompile) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let precompiled = wassette::precompile_component(
+        &config.plugin_dir,
+        &cfg.component_id,
+        cfg.target.as_deref(),
+        &config.lifecycle_config(),
+    )
+    .await?;
+    let output = match (&cfg.output, &precompiled.target) {
+        (Some(output), _) => output.clone(),
+        (None, None) => config
+            .plugin_dir
+            .join(wassette::PRECOMPILED_DIR)
+            .join(precompiled.file_name()),
+        (None, Some(_)) => PathBuf::from(precompiled.file_name()),
+    };
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(&output, &precompiled.artifact)
+        .await
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    println!(
+        "Precompiled {} for {} to {}",
+        precompiled.component_id,
+        precompiled.target.as_deref().unwrap_or("this host"),
+        output.display()
+    );
+    if precompiled.target.is_some() || cfg.output.is_some() {
+        println!(
+            "Copy it to {}/{} in the plugin directory of the hosts to run it on",
+            wassette::PRECOMPILED_DIR,
+            precompiled.file_name()
+        );
+    }
+    if !config.load_precompiled && !config.require_precompiled {
+        println!("Servers only load it with load_precompiled = true in their configuration file");
+    }
+    Ok(())
+}
+
+/// Prints the calls waiting for approval
+async fn list_approvals(cfg: &ApprovalList) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let requests = wassette::pending_approvals(&config.plugin_dir).await?;
+    cfg.output.print(&requests, |requests| {
+        if requests.is_empty() {
+            println!("No calls are waiting for approval");
+            return;
+        }
+        println!(
+            "{:<12}  {:<24}  {:<24}  ARGUMENTS",
+            "ID", "COMPONENT", "TOOL"
+        );
+        for request in requests {
+            println!(
+                "{:<12}  {:<24}  {:<24}  {}",
+                request.id, request.component_id, request.tool, request.arguments
+            );
+        }
+    })?;
+    Ok(())
+}
+
+/// Prints the decided approvals
+async fn approval_log(cfg: &ApprovalList) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let records = wassette::approval_log(&config.plugin_dir).await?;
+    cfg.output.print(&records, |records| {
+        println!(
+            "{:<12}  {:<8}  {:<16}  {:<24}  TOOL",
+            "ID", "OUTCOME", "BY", "COMPONENT"
+        );
+        for record in records {
+            println!(
+                "{:<12}  {:<8}  {:<16}  {:<24}  {}",
+                record.request.id,
+                format!("{:?}", record.decision.outcome).to_lowercase(),
+                record.decision.decided_by.as_deref().unwrap_or("-"),
+                record.request.component_id,
+                record.request.tool
+            );
+        }
+    })?;
+    Ok(())
+}
+
+/// Prints a page of the audit log matching the filters
+async fn query_audit_log(cfg: &AuditQueryArgs) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let query = wassette::AuditQuery {
+        component: cfg.component.clone(),
+        tool: cfg.tool.clone(),
+        since_ms: cfg.since,
+        until_ms: cfg.until,
+        outcome: cfg.outcome,
+        limit: Some(cfg.limit),
+        cursor: cfg.cursor.clone(),
+    };
+    let page = wassette::query_audit_log(&config.plugin_dir, &query).await?;
+    cfg.output.print(&page, |page| {
+        println!(
+            "{:<24}  {:<8}  {:<16}  {:<24}  TOOL",
+            "DECIDED", "OUTCOME", "BY", "COMPONENT"
+        );
+        for record in &page.records {
+            println!(
+                "{:<24}  {:<8}  {:<16}  {:<24}  {}",
+                wassette::format_audit_time(record.decision.decided_at_ms),
+                format!("{:?}", record.decision.outcome).to_lowercase(),
+                record.decision.decided_by.as_deref().unwrap_or("-"),
+                record.request.component_id,
+                record.request.tool
+            );
+        }
+        if let Some(cursor) = &page.next_cursor {
+            println!("More decisions match, print them with --cursor {cursor}");
+        }
+    })?;
+    Ok(())
+}
+
+/// Prints the policy attached to a component
+async fn show_policy(cfg: &PolicyShow) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let lifecycle_manager =
+        LifecycleManager::new_with_config(&config.plugin_dir, config.lifecycle_config()).await?;
+    let component_id = lifecycle_manager
+        .resolve_component_id(&cfg.component_id)
+        .await?;
+    let source = lifecycle_manager.get_policy_info(&component_id).await;
+    let document = match &source {
+        Some(info) => {
+            let content = tokio::fs::read_to_string(&info.local_path)
+                .await
+                .with_context(|| format!("Failed to read {}", info.local_path.display()))?;
+            Some(
+                serde_yaml::from_str(&content)
+                    .with_context(|| format!("Failed to parse {}", info.local_path.display()))?,
+            )
+        }
+        None => None,
+    };
+    let policy = output::ComponentPolicy {
+        component_id,
+        source: source.map(output::PolicySource::from),
+        document,
+    };
+    cfg.output.print(&policy, |policy| {
+        let (Some(source), Some(document)) = (&policy.source, &policy.document) else {
+            println!("{} has no policy", policy.component_id);
+            return;
+        };
+        println!(
+            "# {} from {}",
+            source.local_path.display(),
+            source.source_uri
+        );
+        print!("{}", serde_yaml::to_string(document).unwrap_or_default());
+    })?;
+    Ok(())
+}
+
+/// Migrates policies to the current schema version, or only reports it with `--dry-run`
+async fn migrate_policies(cfg: &PolicyMigrate) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let migrations =
+        wassette::migrate_policies(&config.plugin_dir, &cfg.paths, cfg.dry_run).await?;
+    cfg.output.print(&migrations, |migrations| {
+        if migrations.is_empty() {
+            println!("No policies found");
+        }
+        for migration in migrations {
+            let action = match (migration.migrated, cfg.dry_run) {
+                (false, _) => "is current",
+                (true, true) => "would be migrated",
+                (true, false) => "migrated",
+            };
+            println!(
+                "{} {action} ({} -> {})",
+                migration.path.display(),
+                migration.from_version,
+                migration.to_version
+            );
+            for warning in &migration.warnings {
+                println!("  warning: {warning}");
+            }
+        }
+    })?;
+    Ok(())
+}
+
+/// Prints whether telemetry is on, where it reports to and what
+async fn telemetry_status(cfg: &TelemetryStatus) -> Result<()> {
+    let config = config::Config::new(cfg).context("Failed to load configuration")?;
+    let status = telemetry::TelemetryStatus::new(&config.telemetry);
+    cfg.output.print(&status, |status| {
+        match (&status.endpoint, status.reason) {
+            (Some(endpoint), _) => println!("Telemetry is on, reporting to {endpoint}"),
+            (None, reason) => {
+                println!("Telemetry is off: {}", reason.unwrap_or("disabled"))
+            }
+        }
+        println!("Interval: {}s", status.interval_secs);
+        println!("Reported fields: {}", status.fields.join(", "));
+    })?;
     Ok(())
 }
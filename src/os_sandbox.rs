@@ -0,0 +1,289 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! An optional Landlock and seccomp sandbox around the server process on Linux, as a second line
+//! of defense behind the WebAssembly sandbox. [`apply`] has to run before the async runtime
+//! starts its worker threads, since both only confine the threads started after them.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[cfg(not(target_os = "linux"))]
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::config::Config;
+
+/// TCP ports the server may always connect to, for loading components over HTTPS and HTTP
+const DEFAULT_PORTS: &[u16] = &[443, 80];
+
+/// Directories the server reads without being told to, such as shared libraries, TLS certificates,
+/// DNS settings and `/proc`
+const SYSTEM_READ_PATHS: &[&str] = &["/etc", "/usr", "/lib", "/lib64", "/proc", "/sys", "/dev"];
+
+/// Files the server writes to without being told to
+const SYSTEM_WRITE_PATHS: &[&str] = &["/dev/null"];
+
+/// How much of the sandbox the kernel enforces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Enforcement {
+    /// Every Landlock restriction is enforced
+    Full,
+    /// The kernel is too old for some restrictions, e.g. those on TCP ports before Linux 6.7
+    Partial,
+    /// The kernel doesn't support Landlock, so only the seccomp filter applies
+    SeccompOnly,
+}
+
+impl fmt::Display for Enforcement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Full => "fully enforced",
+            Self::Partial => "partially enforced",
+            Self::SeccompOnly => "seccomp only",
+        })
+    }
+}
+
+/// What the sandbox lets the server access
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Allowances {
+    read: BTreeSet<PathBuf>,
+    write: BTreeSet<PathBuf>,
+    connect_ports: BTreeSet<u16>,
+    bind_ports: BTreeSet<u16>,
+}
+
+/// Returns what the server needs: its plugin directory, configuration and PID file, the
+/// directories and ports granted by the policies of its components, the configured extra
+/// directories and ports, and the port it listens on
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn allowances(
+    config: &Config,
+    granted: wassette::GrantedAccess,
+    project_dir: Option<&Path>,
+    config_file: &Path,
+    bind_port: u16,
+) -> Allowances {
+    let mut read: BTreeSet<PathBuf> = SYSTEM_READ_PATHS.iter().map(PathBuf::from).collect();
+    read.extend(granted.read);
+    read.extend(config.os_sandbox_read_paths.iter().cloned());
+    read.extend(config_file.parent().map(Path::to_path_buf));
+    read.extend(project_dir.map(Path::to_path_buf));
+    read.extend(
+        config
+            .provision
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf),
+    );
+
+    let mut write: BTreeSet<PathBuf> = SYSTEM_WRITE_PATHS.iter().map(PathBuf::from).collect();
+    write.insert(config.plugin_dir.clone());
+    write.insert(std::env::temp_dir());
+    write.extend(granted.write);
+    write.extend(config.os_sandbox_write_paths.iter().cloned());
+    write.extend(
+        config
+            .pid_file
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf),
+    );
+    write.extend(config.control_socket_path().parent().map(Path::to_path_buf));
+    read.retain(|path| !write.contains(path));
+
+    let mut connect_ports: BTreeSet<u16> = DEFAULT_PORTS.iter().copied().collect();
+    connect_ports.extend(granted.ports);
+    connect_ports.extend(config.os_sandbox_ports.iter().copied());
+    let mut bind_ports = BTreeSet::from([bind_port]);
+    bind_ports.extend(
+        config
+            .admin_address
+            .as_deref()
+            .and_then(|address| address.parse::<std::net::SocketAddr>().ok())
+            .map(|address| address.port()),
+    );
+    Allowances {
+        read,
+        write,
+        connect_ports,
+        bind_ports,
+    }
+}
+
+/// Confines the current thread, and every thread it starts afterwards, to what the server and
+/// the policies of the components in its plugin directory need
+#[cfg(target_os = "linux")]
+pub fn apply(config: &Config, project_dir: Option<&Path>, bind_port: u16) -> Result<Enforcement> {
+    use anyhow::Context;
+
+    // Reading the policies is async, but the runtime serving requests may only start afterwards
+    let granted = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(wassette::granted_access(&config.plugin_dir))?;
+    // Landlock can only allow directories that exist, and the server creates this one later
+    std::fs::create_dir_all(&config.plugin_dir).with_context(|| {
+        format!(
+            "Failed to create plugin directory {}",
+            config.plugin_dir.display()
+        )
+    })?;
+    let allowances = allowances(
+        config,
+        granted,
+        project_dir,
+        &crate::config::get_config_file_path()?,
+        bind_port,
+    );
+
+    let enforcement = linux::restrict_access(&allowances).context("Failed to apply Landlock")?;
+    linux::deny_syscalls().context("Failed to apply the seccomp filter")?;
+    Ok(enforcement)
+}
+
+/// The OS sandbox relies on Landlock and seccomp, which only Linux has
+#[cfg(not(target_os = "linux"))]
+pub fn apply(
+    _config: &Config,
+    _project_dir: Option<&Path>,
+    _bind_port: u16,
+) -> Result<Enforcement> {
+    bail!("The OS sandbox is only available on Linux")
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, AccessNet, NetPort, Ruleset, RulesetAttr,
+        RulesetCreatedAttr, RulesetError, RulesetStatus, ABI,
+    };
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter};
+
+    use super::{Allowances, Enforcement, Result};
+
+    /// System calls that neither Wasmtime nor the server make, which would let a compromised
+    /// process inspect other processes, change the kernel or leave its namespaces
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_kexec_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_keyctl,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_reboot,
+    ];
+
+    pub(super) fn restrict_access(allowances: &Allowances) -> Result<Enforcement> {
+        let abi = ABI::V4;
+        // Rules can only be added for paths that exist, and missing ones need no access anyway
+        let read = allowances.read.iter().filter(|path| path.exists());
+        let write = allowances.write.iter().filter(|path| path.exists());
+        let status = Ruleset::default()
+            .handle_access(AccessFs::from_all(abi))?
+            .handle_access(AccessNet::from_all(abi))?
+            .create()?
+            .add_rules(path_beneath_rules(read, AccessFs::from_read(abi)))?
+            .add_rules(path_beneath_rules(write, AccessFs::from_all(abi)))?
+            .add_rules(
+                allowances
+                    .connect_ports
+                    .iter()
+                    .map(|port| Ok::<_, RulesetError>(NetPort::new(*port, AccessNet::ConnectTcp))),
+            )?
+            .add_rules(
+                allowances
+                    .bind_ports
+                    .iter()
+                    .map(|port| Ok::<_, RulesetError>(NetPort::new(*port, AccessNet::BindTcp))),
+            )?
+            .restrict_self()?;
+        Ok(match status.ruleset {
+            RulesetStatus::FullyEnforced => Enforcement::Full,
+            RulesetStatus::PartiallyEnforced => Enforcement::Partial,
+            RulesetStatus::NotEnforced => Enforcement::SeccompOnly,
+        })
+    }
+
+    pub(super) fn deny_syscalls() -> Result<()> {
+        let filter = SeccompFilter::new(
+            DENIED_SYSCALLS
+                .iter()
+                .map(|syscall| (*syscall, Vec::new()))
+                .collect(),
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::EPERM as u32),
+            std::env::consts::ARCH.try_into()?,
+        )?;
+        let program: BpfProgram = filter.try_into()?;
+        seccompiler::apply_filter(&program)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowances() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "plugin_dir": "/srv/wassette/components",
+            "pid_file": "/run/wassette/wassette.pid",
+            "os_sandbox_read_paths": ["/srv/models"],
+            "os_sandbox_ports": [8443],
+            "admin_address": "127.0.0.1:9002",
+        }))
+        .unwrap();
+        let granted = wassette::GrantedAccess {
+            read: BTreeSet::from([PathBuf::from("/srv/reports")]),
+            write: BTreeSet::from([PathBuf::from("/srv/wassette/components/workspace")]),
+            ports: BTreeSet::from([9090]),
+        };
+
+        let allowances = allowances(
+            &config,
+            granted,
+            None,
+            Path::new("/home/me/.config/wasette/config.toml"),
+            9001,
+        );
+
+        assert!(allowances.read.contains(Path::new("/srv/reports")));
+        assert!(allowances.read.contains(Path::new("/srv/models")));
+        assert!(allowances
+            .read
+            .contains(Path::new("/home/me/.config/wasette")));
+        assert!(allowances.read.contains(Path::new("/etc")));
+        assert!(allowances
+            .write
+            .contains(Path::new("/srv/wassette/components")));
+        assert!(allowances
+            .write
+            .contains(Path::new("/srv/wassette/components/workspace")));
+        assert!(allowances.write.contains(Path::new("/run/wassette")));
+        assert!(!allowances.read.contains(Path::new("/run/wassette")));
+        assert_eq!(
+            allowances.connect_ports,
+            BTreeSet::from([80, 443, 8443, 9090])
+        );
+        assert_eq!(allowances.bind_ports, BTreeSet::from([9001, 9002]));
+    }
+}
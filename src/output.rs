@@ -0,0 +1,219 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Output formats of the management commands, so that scripts and dashboards can consume their
+//! output as JSON or YAML instead of scraping the tables printed for people.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::Serialize;
+use wassette::{Labels, PolicyInfo, RequiredCapability, ToolStats};
+
+/// Format of the output of a management command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns for people to read
+    #[default]
+    Table,
+    /// Pretty-printed JSON
+    Json,
+    /// YAML
+    Yaml,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Table => "table",
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+        })
+    }
+}
+
+impl OutputFormat {
+    /// Prints `value` as JSON or YAML, or has `table` print it for people
+    pub fn print<T: Serialize>(self, value: &T, table: impl FnOnce(&T)) -> Result<()> {
+        match self {
+            Self::Table => table(value),
+            Self::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            Self::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        }
+        Ok(())
+    }
+}
+
+/// A row of `wassette stats`
+#[derive(Debug, Serialize)]
+pub struct StatsRow<'a> {
+    /// ID of the component
+    pub component_id: &'a str,
+    /// Name of the tool
+    pub tool: &'a str,
+    #[serde(flatten)]
+    pub stats: &'a ToolStats,
+    /// Fraction of calls that succeeded
+    pub success_rate: Option<f64>,
+    /// Average duration of a call, in milliseconds
+    pub average_latency_ms: Option<f64>,
+}
+
+impl<'a> StatsRow<'a> {
+    pub fn new(component_id: &'a str, tool: &'a str, stats: &'a ToolStats) -> Self {
+        Self {
+            component_id,
+            tool,
+            stats,
+            success_rate: stats.success_rate(),
+            average_latency_ms: stats.average_latency_ms(),
+        }
+    }
+}
+
+/// Output of `wassette component label`
+#[derive(Debug, Serialize)]
+pub struct ComponentLabels {
+    /// ID of the component
+    pub component_id: String,
+    /// The labels after the change
+    pub labels: Labels,
+}
+
+/// A row of `wassette component list`
+#[derive(Debug, Serialize)]
+pub struct ComponentRow {
+    /// ID of the component
+    pub component_id: String,
+    /// Labels of the component
+    pub labels: Labels,
+    /// Number of tools the component exports
+    pub tools: usize,
+    /// Capabilities the component's imports need that its policy doesn't grant
+    pub capability_gaps: Vec<RequiredCapability>,
+}
+
+/// Output of `wassette component inspect`
+#[derive(Debug, Serialize)]
+pub struct ComponentDetails {
+    /// ID of the component
+    pub component_id: String,
+    /// Labels of the component
+    pub labels: Labels,
+    /// Name of the WIT world the component was built from, if the binary records it
+    pub world: Option<String>,
+    /// Tools the component exports
+    pub tools: Vec<ToolSummary>,
+    /// Capabilities the component's imports need
+    pub required_capabilities: Vec<RequiredCapability>,
+    /// Capabilities the component's imports need that its policy doesn't grant
+    pub capability_gaps: Vec<RequiredCapability>,
+    /// The component's policy, if it has one
+    pub policy: Option<PolicySource>,
+}
+
+/// A tool of `wassette component inspect`
+#[derive(Debug, Serialize)]
+pub struct ToolSummary {
+    /// Name of the tool
+    pub name: String,
+    /// Description of the tool
+    pub description: Option<String>,
+}
+
+/// Where the policy of a component is stored and came from
+#[derive(Debug, Serialize)]
+pub struct PolicySource {
+    /// URI the policy was attached from
+    pub source_uri: String,
+    /// Path of the policy file in the plugin directory
+    pub local_path: PathBuf,
+    /// When the policy was attached, in seconds since the Unix epoch
+    pub attached_at_secs: u64,
+}
+
+impl From<PolicyInfo> for PolicySource {
+    fn from(info: PolicyInfo) -> Self {
+        Self {
+            source_uri: info.source_uri,
+            local_path: info.local_path,
+            attached_at_secs: info
+                .created_at
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+}
+
+/// Output of `wassette policy show`
+#[derive(Debug, Serialize)]
+pub struct ComponentPolicy {
+    /// ID of the component
+    pub component_id: String,
+    /// Where the policy is stored, or `None` if the component has no policy
+    pub source: Option<PolicySource>,
+    /// The policy document as stored
+    pub document: Option<serde_yaml::Value>,
+}
+
+/// Names capabilities for a table, e.g. `network, storage`, or `-` if there are none
+pub fn capability_names(capabilities: &[RequiredCapability]) -> String {
+    if capabilities.is_empty() {
+        return "-".to_string();
+    }
+    capabilities
+        .iter()
+        .map(|required| format!("{:?}", required.capability).to_lowercase())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Lists labels for a table, e.g. `risk=high, team=infra`, or `-` if there are none
+pub fn label_list(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return "-".to_string();
+    }
+    labels
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::*;
+
+    #[test]
+    fn test_table_lists() {
+        assert_eq!(capability_names(&[]), "-");
+        assert_eq!(
+            capability_names(&[RequiredCapability {
+                capability: wassette::Capability::Network,
+                imports: vec!["wasi:http/outgoing-handler".to_string()],
+            }]),
+            "network"
+        );
+        let labels: Labels = [
+            ("team".to_string(), "infra".to_string()),
+            ("risk".to_string(), "high".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(label_list(&labels), "risk=high, team=infra");
+    }
+
+    #[test]
+    fn test_output_format_names() {
+        for format in OutputFormat::value_variants() {
+            assert_eq!(
+                OutputFormat::from_str(&format.to_string(), false).unwrap(),
+                *format
+            );
+        }
+    }
+}
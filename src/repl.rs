@@ -0,0 +1,374 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! `wassette repl`, an interactive prompt for calling the tools of the installed components
+//! without an MCP client, as a quick manual-testing loop for component authors.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context as _, Result};
+use mcp_server::LifecycleManager;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Context, Editor, Helper, Highlighter, Hinter, Validator};
+use serde_json::{Map, Value};
+
+/// File in the plugin directory that the lines entered at the prompt are kept in
+const HISTORY_FILE: &str = "repl_history";
+
+/// Commands of the prompt, for completion
+const COMMANDS: &[&str] = &["call", "exit", "help", "history", "load", "schema", "tools"];
+
+/// Commands whose argument is the name of a tool
+const TOOL_COMMANDS: &[&str] = &["call", "schema"];
+
+const HELP: &str = "\
+tools                  list the tools of the loaded components
+schema <tool>          print the schema of a tool
+call <tool> [json]     call a tool, prompting for each argument unless they are given as JSON
+load <uri>             load a component from a file, OCI or HTTPS URI
+history                list the calls made in this session
+help                   print this help
+exit                   leave the prompt";
+
+/// Completes command names and, after `call` and `schema`, tool names
+#[derive(Helper, Hinter, Highlighter, Validator)]
+struct ReplHelper {
+    tools: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = completions(&line[..pos], &self.tools);
+        Ok((
+            start,
+            candidates
+                .into_iter()
+                .map(|candidate| Pair {
+                    display: candidate.clone(),
+                    replacement: candidate,
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Returns where the word being completed starts and its candidates
+fn completions(line: &str, tools: &[String]) -> (usize, Vec<String>) {
+    let start = line.rfind(' ').map_or(0, |i| i + 1);
+    let word = &line[start..];
+    let words: Vec<&str> = line[..start].split_whitespace().collect();
+    let candidates: Vec<&str> = match words[..] {
+        [] => COMMANDS.to_vec(),
+        [command] if TOOL_COMMANDS.contains(&command) => tools.iter().map(String::as_str).collect(),
+        _ => Vec::new(),
+    };
+    (
+        start,
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// A call made at the prompt
+struct CallRecord {
+    tool: String,
+    arguments: Value,
+    duration: Duration,
+    succeeded: bool,
+}
+
+struct Repl<'a> {
+    lifecycle_manager: &'a LifecycleManager,
+    editor: Editor<ReplHelper, DefaultHistory>,
+    calls: Vec<CallRecord>,
+}
+
+/// Runs the prompt until it is left with `exit` or Ctrl-D
+pub async fn run(lifecycle_manager: &LifecycleManager, plugin_dir: &Path) -> Result<()> {
+    let mut editor = Editor::new().context("Failed to start the prompt")?;
+    editor.set_helper(Some(ReplHelper { tools: Vec::new() }));
+    let history_path = plugin_dir.join(HISTORY_FILE);
+    // There is no history before the first session
+    let _ = editor.load_history(&history_path);
+
+    let mut repl = Repl {
+        lifecycle_manager,
+        editor,
+        calls: Vec::new(),
+    };
+    repl.refresh_tools().await;
+    println!(
+        "{} tools loaded. Type 'help' for the commands.",
+        repl.tool_names().len()
+    );
+
+    loop {
+        let line = match repl.read_line("wassette> ") {
+            Ok(line) => line,
+            // Ctrl-C discards the line, like in a shell
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e).context("Failed to read from the prompt"),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = repl.editor.add_history_entry(line);
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let result = match command {
+            "exit" | "quit" => break,
+            "help" => {
+                println!("{HELP}");
+                Ok(())
+            }
+            "tools" => repl.print_tools().await,
+            "schema" => repl.print_schema(rest.trim()).await,
+            "call" => repl.call(rest.trim()).await,
+            "load" => repl.load(rest.trim()).await,
+            "history" => {
+                repl.print_history();
+                Ok(())
+            }
+            _ => Err(anyhow::anyhow!(
+                "Unknown command '{command}', type 'help' for the commands"
+            )),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {e:#}");
+        }
+    }
+
+    if let Err(e) = repl.editor.save_history(&history_path) {
+        tracing::warn!(error = %e, "Failed to save the prompt history");
+    }
+    Ok(())
+}
+
+impl Repl<'_> {
+    /// Reads a line without blocking the other tasks of the runtime
+    fn read_line(&mut self, prompt: &str) -> rustyline::Result<String> {
+        tokio::task::block_in_place(|| self.editor.readline(prompt))
+    }
+
+    fn tool_names(&self) -> &[String] {
+        self.editor
+            .helper()
+            .map(|helper| helper.tools.as_slice())
+            .unwrap_or_default()
+    }
+
+    async fn refresh_tools(&mut self) {
+        let tools = self
+            .lifecycle_manager
+            .list_tools()
+            .await
+            .into_iter()
+            .map(|tool| tool.name)
+            .collect();
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.tools = tools;
+        }
+    }
+
+    async fn print_tools(&self) -> Result<()> {
+        let tools = self.lifecycle_manager.list_tools().await;
+        if tools.is_empty() {
+            println!("No tools loaded");
+        }
+        let width = tools.iter().map(|tool| tool.name.len()).max().unwrap_or(0);
+        for tool in tools {
+            println!(
+                "{:<width$}  {}",
+                tool.name,
+                tool.description.as_deref().unwrap_or("")
+            );
+        }
+        Ok(())
+    }
+
+    async fn print_schema(&self, tool: &str) -> Result<()> {
+        if tool.is_empty() {
+            bail!("Usage: schema <tool>");
+        }
+        let schema = self
+            .lifecycle_manager
+            .get_tool_schema(tool)
+            .await
+            .with_context(|| format!("Tool not found: {tool}"))?;
+        println!("{}", serde_json::to_string_pretty(&schema)?);
+        Ok(())
+    }
+
+    async fn load(&mut self, uri: &str) -> Result<()> {
+        if uri.is_empty() {
+            bail!("Usage: load <uri>");
+        }
+        let (id, _) = self.lifecycle_manager.load_component(uri).await?;
+        self.refresh_tools().await;
+        println!("Loaded component {id}");
+        Ok(())
+    }
+
+    async fn call(&mut self, args: &str) -> Result<()> {
+        let (tool, arguments) = args.split_once(' ').unwrap_or((args, ""));
+        if tool.is_empty() {
+            bail!("Usage: call <tool> [json]");
+        }
+        let schema = self
+            .lifecycle_manager
+            .get_tool_schema(tool)
+            .await
+            .with_context(|| format!("Tool not found: {tool}"))?;
+        let arguments = match arguments.trim() {
+            "" => match self.prompt_arguments(&schema.input_schema)? {
+                Some(arguments) => arguments,
+                None => return Ok(()),
+            },
+            json => serde_json::from_str(json).context("Arguments aren't valid JSON")?,
+        };
+
+        let component_id = self
+            .lifecycle_manager
+            .get_component_id_for_tool(tool)
+            .await?;
+        let start_time = Instant::now();
+        let result = self
+            .lifecycle_manager
+            .execute_component_call(&component_id, tool, &arguments.to_string())
+            .await;
+        self.calls.push(CallRecord {
+            tool: tool.to_string(),
+            arguments,
+            duration: start_time.elapsed(),
+            succeeded: result.is_ok(),
+        });
+        let result = result?;
+        match serde_json::from_str::<Value>(&result) {
+            Ok(json) => println!("{}", serde_json::to_string_pretty(&json)?),
+            Err(_) => println!("{result}"),
+        }
+        Ok(())
+    }
+
+    /// Asks for each property of the tool's `inputSchema`, returning `None` if the user cancels
+    /// with Ctrl-C or Ctrl-D
+    fn prompt_arguments(&mut self, input_schema: &Value) -> Result<Option<Value>> {
+        let required: Vec<&str> = input_schema["required"]
+            .as_array()
+            .map(|required| required.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        let mut arguments = Map::new();
+        let Some(properties) = input_schema["properties"].as_object() else {
+            return Ok(Some(Value::Object(arguments)));
+        };
+        for (name, schema) in properties {
+            let is_required = required.contains(&name.as_str());
+            let prompt = format!(
+                "{name} ({}{}): ",
+                schema["type"].as_str().unwrap_or("json"),
+                if is_required { ", required" } else { "" }
+            );
+            loop {
+                let input = match self.read_line(&prompt) {
+                    Ok(input) => input,
+                    Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(None),
+                    Err(e) => return Err(e).context("Failed to read from the prompt"),
+                };
+                if input.is_empty() && !is_required {
+                    break;
+                }
+                match parse_argument(&input, schema) {
+                    Ok(value) => {
+                        arguments.insert(name.clone(), value);
+                        break;
+                    }
+                    Err(e) => eprintln!("{e:#}"),
+                }
+            }
+        }
+        Ok(Some(Value::Object(arguments)))
+    }
+
+    fn print_history(&self) {
+        if self.calls.is_empty() {
+            println!("No calls made yet");
+        }
+        for (i, call) in self.calls.iter().enumerate() {
+            println!(
+                "{:>3}  {:<4}  {:>8.1}ms  {} {}",
+                i + 1,
+                if call.succeeded { "ok" } else { "err" },
+                call.duration.as_secs_f64() * 1000.0,
+                call.tool,
+                call.arguments
+            );
+        }
+    }
+}
+
+/// Parses what was entered for an argument. Strings are taken as they are, everything else has
+/// to be JSON.
+fn parse_argument(input: &str, schema: &Value) -> Result<Value> {
+    if schema["type"] == "string" {
+        return Ok(Value::String(input.to_string()));
+    }
+    serde_json::from_str(input).with_context(|| {
+        format!(
+            "Expected JSON of type {}",
+            schema["type"].as_str().unwrap_or("any")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_completions() {
+        let tools = vec!["fetch".to_string(), "format-date".to_string()];
+        assert_eq!(completions("sc", &tools), (0, vec!["schema".to_string()]));
+        assert_eq!(
+            completions("call f", &tools),
+            (5, vec!["fetch".to_string(), "format-date".to_string()])
+        );
+        assert_eq!(
+            completions("call fe", &tools),
+            (5, vec!["fetch".to_string()])
+        );
+        assert_eq!(completions("load f", &tools), (5, Vec::new()));
+    }
+
+    #[test]
+    fn test_parse_argument() {
+        assert_eq!(
+            parse_argument("42", &json!({"type": "string"})).unwrap(),
+            json!("42")
+        );
+        assert_eq!(
+            parse_argument("42", &json!({"type": "integer"})).unwrap(),
+            json!(42)
+        );
+        assert_eq!(
+            parse_argument("[1, 2]", &json!({"type": "array"})).unwrap(),
+            json!([1, 2])
+        );
+        assert!(parse_argument("yes", &json!({"type": "boolean"})).is_err());
+    }
+}
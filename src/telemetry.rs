@@ -0,0 +1,309 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Opt-in usage reporting. A server with `telemetry.enabled` set and a `telemetry.endpoint`
+//! configured periodically POSTs a [`Report`] of aggregate counters to the endpoint: its version
+//! and platform, how many components are loaded, and how many calls were made and failed, with
+//! failures grouped into coarse classes. Reports never contain component IDs, tool names,
+//! arguments, results, error messages or anything else identifying the deployment. Setting the
+//! `DO_NOT_TRACK` environment variable turns reporting off regardless of the configuration.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use mcp_server::LifecycleManager;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use wassette::LifecycleEvent;
+
+/// Seconds between reports unless `telemetry.interval_secs` is set
+const DEFAULT_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Shortest interval between reports, so that a misconfiguration can't flood the endpoint
+const MIN_INTERVAL_SECS: u64 = 60;
+
+/// How long sending a report may take
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The fields of a [`Report`], as listed by `wassette telemetry status`
+pub const REPORTED_FIELDS: &[&str] = &[
+    "version",
+    "os",
+    "arch",
+    "period_secs",
+    "components",
+    "calls",
+    "failures",
+    "error_classes",
+];
+
+/// The `[telemetry]` section of the configuration file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// Send usage reports. Off unless set
+    pub enabled: bool,
+    /// URL that reports are POSTed to as JSON
+    pub endpoint: Option<String>,
+    /// Seconds between reports, a day unless set
+    pub interval_secs: Option<u64>,
+}
+
+impl TelemetryConfig {
+    /// Returns the time between reports
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(
+            self.interval_secs
+                .unwrap_or(DEFAULT_INTERVAL_SECS)
+                .max(MIN_INTERVAL_SECS),
+        )
+    }
+
+    /// Returns the endpoint that reports are sent to, or why none are sent
+    pub fn endpoint(&self) -> Result<&str, &'static str> {
+        if std::env::var_os("DO_NOT_TRACK").is_some_and(|v| !v.is_empty() && v != "0") {
+            return Err("DO_NOT_TRACK is set");
+        }
+        if !self.enabled {
+            return Err("telemetry.enabled isn't set");
+        }
+        match self.endpoint.as_deref() {
+            Some(endpoint) if !endpoint.is_empty() => Ok(endpoint),
+            _ => Err("telemetry.endpoint isn't set"),
+        }
+    }
+}
+
+/// Output of `wassette telemetry status`
+#[derive(Debug, Serialize)]
+pub struct TelemetryStatus {
+    /// Whether a server with this configuration sends reports
+    pub enabled: bool,
+    /// Why no reports are sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+    /// URL that reports are sent to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Seconds between reports
+    pub interval_secs: u64,
+    /// The fields every report has
+    pub fields: &'static [&'static str],
+}
+
+impl TelemetryStatus {
+    /// Returns the status of reporting with the given configuration
+    pub fn new(config: &TelemetryConfig) -> Self {
+        let endpoint = config.endpoint();
+        Self {
+            enabled: endpoint.is_ok(),
+            reason: endpoint.err(),
+            endpoint: endpoint.ok().map(str::to_string),
+            interval_secs: config.interval().as_secs(),
+            fields: REPORTED_FIELDS,
+        }
+    }
+}
+
+/// What a server reports about one period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// Version of wassette
+    pub version: &'static str,
+    /// Operating system, e.g. `linux`
+    pub os: &'static str,
+    /// CPU architecture, e.g. `x86_64`
+    pub arch: &'static str,
+    /// Length of the period, in seconds
+    pub period_secs: u64,
+    /// Number of components loaded at the end of the period
+    pub components: usize,
+    /// Number of tool calls made in the period
+    pub calls: u64,
+    /// Number of those calls that failed
+    pub failures: u64,
+    /// Number of failures per class, see [`classify_error`]
+    pub error_classes: BTreeMap<&'static str, u64>,
+}
+
+/// Counters of the current period
+#[derive(Debug, Default)]
+struct Counters {
+    calls: u64,
+    failures: u64,
+    error_classes: BTreeMap<&'static str, u64>,
+}
+
+impl Counters {
+    fn record(&mut self, event: &LifecycleEvent) {
+        match event {
+            LifecycleEvent::ExecutionFinished { .. } => self.calls += 1,
+            LifecycleEvent::ExecutionFailed { error, .. } => {
+                self.calls += 1;
+                self.failures += 1;
+                *self.error_classes.entry(classify_error(error)).or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn report(&self, period: Duration, components: usize) -> Report {
+        Report {
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            period_secs: period.as_secs(),
+            components,
+            calls: self.calls,
+            failures: self.failures,
+            error_classes: self.error_classes.clone(),
+        }
+    }
+}
+
+/// Sorts a failed call into a coarse class, so that the report carries no error message
+pub fn classify_error(error: &str) -> &'static str {
+    let error = error.to_lowercase();
+    if error.contains("wasm trap") || error.contains("wasm backtrace") {
+        "trap"
+    } else if error.contains("fuel") || error.contains("interrupt") || error.contains("memory") {
+        "resource_limit"
+    } else if error.contains("denied") || error.contains("not allowed") {
+        "denied"
+    } else if error.contains("invalid") || error.contains("failed to parse") {
+        "invalid_input"
+    } else {
+        "other"
+    }
+}
+
+/// Reports usage in the background. Reporting stops when dropped.
+pub struct Reporter {
+    ct: CancellationToken,
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        self.ct.cancel();
+    }
+}
+
+/// Starts reporting the usage of `lifecycle_manager` if the configuration enables it
+pub fn start(lifecycle_manager: LifecycleManager, config: &TelemetryConfig) -> Option<Reporter> {
+    let endpoint = match config.endpoint() {
+        Ok(endpoint) => endpoint.to_string(),
+        Err(reason) => {
+            tracing::debug!(reason, "Usage reporting is off");
+            return None;
+        }
+    };
+    let interval = config.interval();
+    tracing::info!(%endpoint, interval_secs = interval.as_secs(), "Reporting anonymous usage");
+
+    let ct = CancellationToken::new();
+    let cancelled = ct.clone();
+    let mut events = lifecycle_manager.subscribe();
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut counters = Counters::default();
+        let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        loop {
+            tokio::select! {
+                () = cancelled.cancelled() => break,
+                event = events.recv() => match event {
+                    Ok(event) => counters.record(&event),
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::debug!(missed, "Usage reporting missed events");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = ticks.tick() => {
+                    let components = lifecycle_manager.list_components().await.len();
+                    let report = counters.report(interval, components);
+                    match send(&client, &endpoint, &report).await {
+                        Ok(()) => counters = Counters::default(),
+                        // The counters carry over to the next report
+                        Err(e) => tracing::debug!(error = %format!("{e:#}"), "Failed to send usage report"),
+                    }
+                }
+            }
+        }
+    });
+    Some(Reporter { ct })
+}
+
+async fn send(client: &reqwest::Client, endpoint: &str, report: &Report) -> Result<()> {
+    client
+        .post(endpoint)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(report)?)
+        .timeout(SEND_TIMEOUT)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {endpoint}"))?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_telemetry_is_opt_in() {
+        temp_env::with_var_unset("DO_NOT_TRACK", || {
+            let mut config = TelemetryConfig::default();
+            assert_eq!(config.endpoint(), Err("telemetry.enabled isn't set"));
+            config.enabled = true;
+            assert_eq!(config.endpoint(), Err("telemetry.endpoint isn't set"));
+            config.endpoint = Some("https://telemetry.example.com/v1".to_string());
+            assert_eq!(config.endpoint(), Ok("https://telemetry.example.com/v1"));
+            temp_env::with_var("DO_NOT_TRACK", Some("1"), || {
+                assert_eq!(config.endpoint(), Err("DO_NOT_TRACK is set"));
+            });
+        });
+    }
+
+    #[test]
+    fn test_report_is_anonymous() {
+        let mut counters = Counters::default();
+        counters.record(&LifecycleEvent::ExecutionFinished {
+            component_id: "acme/secret-project".to_string(),
+            tool: "fetch".to_string(),
+            request_id: None,
+            duration_ms: 3,
+        });
+        counters.record(&LifecycleEvent::ExecutionFailed {
+            component_id: "acme/secret-project".to_string(),
+            tool: "fetch".to_string(),
+            request_id: None,
+            duration_ms: 3,
+            error: "error while executing at wasm backtrace: https://internal.example.com"
+                .to_string(),
+        });
+        let report = counters.report(Duration::from_secs(60), 2);
+        assert_eq!(report.calls, 2);
+        assert_eq!(report.failures, 1);
+        assert_eq!(report.error_classes, BTreeMap::from([("trap", 1)]));
+
+        let json = serde_json::to_value(&report).unwrap();
+        let keys: Vec<_> = json.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys.len(), REPORTED_FIELDS.len());
+        assert!(REPORTED_FIELDS
+            .iter()
+            .all(|field| json.get(field).is_some()));
+        let text = json.to_string();
+        assert!(!text.contains("secret-project") && !text.contains("internal.example.com"));
+    }
+
+    #[test]
+    fn test_classify_error() {
+        assert_eq!(classify_error("wasm trap: unreachable"), "trap");
+        assert_eq!(classify_error("all fuel consumed"), "resource_limit");
+        assert_eq!(classify_error("Call to 'x' was denied by alice"), "denied");
+        assert_eq!(classify_error("Invalid access type"), "invalid_input");
+        assert_eq!(classify_error("connection reset"), "other");
+    }
+}
@@ -0,0 +1,361 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! `wassette top`, a terminal dashboard of a running server that polls its control socket and
+//! shows call throughput, errors and latency per component and the open sessions.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use wassette::ToolStats;
+
+use crate::control::{self, Snapshot};
+
+/// Calls, errors and latency of a component, combined over its tools
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Totals {
+    invocations: u64,
+    failures: u64,
+    total_duration_ms: u64,
+}
+
+impl Totals {
+    fn of<'a>(tools: impl IntoIterator<Item = &'a ToolStats>) -> Self {
+        tools
+            .into_iter()
+            .fold(Self::default(), |totals, stats| Self {
+                invocations: totals.invocations + stats.invocations,
+                failures: totals.failures + stats.failures,
+                total_duration_ms: totals.total_duration_ms + stats.total_duration_ms,
+            })
+    }
+
+    /// Returns what happened since `earlier`, which saturates at zero if the statistics were reset
+    fn since(self, earlier: Self) -> Self {
+        Self {
+            invocations: self.invocations.saturating_sub(earlier.invocations),
+            failures: self.failures.saturating_sub(earlier.failures),
+            total_duration_ms: self
+                .total_duration_ms
+                .saturating_sub(earlier.total_duration_ms),
+        }
+    }
+
+    fn average_latency_ms(self) -> Option<f64> {
+        (self.invocations > 0).then(|| self.total_duration_ms as f64 / self.invocations as f64)
+    }
+}
+
+/// A line of the component table
+#[derive(Debug, PartialEq)]
+struct ComponentRow {
+    component_id: String,
+    totals: Totals,
+    /// Calls per second since the previous refresh
+    calls_per_sec: f64,
+    /// Errors per second since the previous refresh
+    errors_per_sec: f64,
+    /// Average latency of the calls since the previous refresh
+    recent_latency_ms: Option<f64>,
+}
+
+fn component_totals(snapshot: &Snapshot) -> BTreeMap<&str, Totals> {
+    // Loaded components that were never called are listed too
+    let mut totals: BTreeMap<&str, Totals> = snapshot
+        .status
+        .components
+        .keys()
+        .map(|id| (id.as_str(), Totals::default()))
+        .collect();
+    for (id, tools) in &snapshot.stats.components {
+        totals.insert(id, Totals::of(tools.values()));
+    }
+    totals
+}
+
+/// Returns a row per component, busiest first, with rates over the `elapsed` time since the
+/// `previous` snapshot
+fn component_rows(
+    current: &Snapshot,
+    previous: Option<&Snapshot>,
+    elapsed: Duration,
+) -> Vec<ComponentRow> {
+    let earlier = previous.map(component_totals).unwrap_or_default();
+    let seconds = elapsed.as_secs_f64();
+    let mut rows: Vec<ComponentRow> = component_totals(current)
+        .into_iter()
+        .map(|(id, totals)| {
+            let recent = previous
+                .map(|_| totals.since(earlier.get(id).copied().unwrap_or_default()))
+                .unwrap_or_default();
+            let per_sec = |count: u64| {
+                if seconds > 0.0 {
+                    count as f64 / seconds
+                } else {
+                    0.0
+                }
+            };
+            ComponentRow {
+                component_id: id.to_string(),
+                totals,
+                calls_per_sec: per_sec(recent.invocations),
+                errors_per_sec: per_sec(recent.failures),
+                recent_latency_ms: recent.average_latency_ms(),
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        b.calls_per_sec
+            .total_cmp(&a.calls_per_sec)
+            .then(b.totals.invocations.cmp(&a.totals.invocations))
+    });
+    rows
+}
+
+fn format_latency(latency_ms: Option<f64>) -> String {
+    latency_ms.map_or_else(|| "-".to_string(), |ms| format!("{ms:.1}ms"))
+}
+
+fn format_uptime(secs: u64) -> String {
+    format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn draw(frame: &mut Frame, snapshot: &Snapshot, rows: &[ComponentRow], error: Option<&str>) {
+    let [header, table, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let status = &snapshot.status;
+    let calls_per_sec: f64 = rows.iter().map(|row| row.calls_per_sec).sum();
+    let errors_per_sec: f64 = rows.iter().map(|row| row.errors_per_sec).sum();
+    let memory = status.resident_memory_bytes.map_or_else(
+        || "-".to_string(),
+        |bytes| format!("{:.1}MiB", bytes as f64 / (1024.0 * 1024.0)),
+    );
+    let mut lines = vec![
+        Line::from(format!(
+            "up {}   sessions {}   components {}   tools {}   live instances {}   memory {}",
+            format_uptime(snapshot.uptime_secs),
+            status.active_sessions,
+            status.loaded_components,
+            status.registered_tools,
+            status.live_stores,
+            memory
+        )),
+        Line::from(format!(
+            "{calls_per_sec:.1} calls/s   {errors_per_sec:.1} errors/s"
+        )),
+    ];
+    if let Some(error) = error {
+        lines.push(Line::from(error.to_string()).style(Style::new().fg(Color::Red)));
+    }
+    frame.render_widget(Paragraph::new(lines), header);
+
+    let widths = [
+        Constraint::Fill(1),
+        Constraint::Length(10),
+        Constraint::Length(9),
+        Constraint::Length(8),
+        Constraint::Length(9),
+        Constraint::Length(12),
+        Constraint::Length(12),
+    ];
+    let rows = rows.iter().map(|row| {
+        let style = if row.errors_per_sec > 0.0 {
+            Style::new().fg(Color::Red)
+        } else {
+            Style::new()
+        };
+        Row::new([
+            row.component_id.clone(),
+            row.totals.invocations.to_string(),
+            format!("{:.1}", row.calls_per_sec),
+            row.totals.failures.to_string(),
+            format!("{:.1}", row.errors_per_sec),
+            format_latency(row.totals.average_latency_ms()),
+            format_latency(row.recent_latency_ms),
+        ])
+        .style(style)
+    });
+    let header_row = Row::new([
+        "COMPONENT",
+        "CALLS",
+        "CALLS/S",
+        "ERRORS",
+        "ERRORS/S",
+        "AVG LATENCY",
+        "NOW LATENCY",
+    ])
+    .bold();
+    frame.render_widget(
+        Table::new(rows, widths)
+            .header(header_row)
+            .block(Block::bordered().title(" Components ")),
+        table,
+    );
+    frame.render_widget(Paragraph::new("q: quit").dim(), footer);
+}
+
+/// Shows the dashboard until the user quits with `q`, Esc or Ctrl-C, refreshing it every
+/// `interval`
+pub fn run(socket: &Path, interval: Duration) -> Result<()> {
+    // Fail before taking over the terminal if no server is listening
+    let snapshot = control::read_snapshot(socket)?;
+    let mut terminal = ratatui::init();
+    let result = show(&mut terminal, socket, interval, snapshot);
+    ratatui::restore();
+    result
+}
+
+fn show(
+    terminal: &mut DefaultTerminal,
+    socket: &Path,
+    interval: Duration,
+    snapshot: Snapshot,
+) -> Result<()> {
+    let mut previous: Option<(Instant, Snapshot)> = None;
+    let mut current = (Instant::now(), snapshot);
+    let mut error = None;
+    loop {
+        let rows = match &previous {
+            Some((taken, snapshot)) => {
+                component_rows(&current.1, Some(snapshot), current.0 - *taken)
+            }
+            None => component_rows(&current.1, None, Duration::ZERO),
+        };
+        terminal.draw(|frame| draw(frame, &current.1, &rows, error.as_deref()))?;
+
+        let refresh = Instant::now() + interval;
+        while let Some(timeout) = refresh.checked_duration_since(Instant::now()) {
+            if !event::poll(timeout)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press
+                    && (matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c)
+                {
+                    return Ok(());
+                }
+            }
+        }
+
+        // Keep showing the last snapshot if the server went away, e.g. while it restarts
+        match control::read_snapshot(socket) {
+            Ok(snapshot) => {
+                previous = Some(std::mem::replace(&mut current, (Instant::now(), snapshot)));
+                error = None;
+            }
+            Err(e) => error = Some(format!("{e:#}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(calls: &[(&str, &str, u64, u64, u64)]) -> Snapshot {
+        let mut snapshot: Snapshot = serde_json::from_value(serde_json::json!({
+            "uptime_secs": 60,
+            "status": {
+                "resident_memory_bytes": null,
+                "live_stores": 0,
+                "loaded_components": 1,
+                "registered_tools": 1,
+                "pending_uploads": 0,
+                "pending_upload_bytes": 0,
+                "components": {"idle": {"compiled_image_bytes": 1024, "tools": 1}},
+            },
+            "stats": {},
+        }))
+        .unwrap();
+        for (component, tool, invocations, failures, total_duration_ms) in calls {
+            snapshot
+                .stats
+                .components
+                .entry(component.to_string())
+                .or_default()
+                .insert(
+                    tool.to_string(),
+                    ToolStats {
+                        invocations: *invocations,
+                        successes: invocations - failures,
+                        failures: *failures,
+                        total_duration_ms: *total_duration_ms,
+                        ..Default::default()
+                    },
+                );
+        }
+        snapshot
+    }
+
+    #[test]
+    fn test_component_rows() {
+        let previous = snapshot(&[("fetch", "get", 10, 1, 100), ("fetch", "head", 5, 0, 50)]);
+        let current = snapshot(&[
+            ("fetch", "get", 18, 3, 260),
+            ("fetch", "head", 7, 0, 70),
+            ("time", "now", 1, 0, 2),
+        ]);
+
+        let rows = component_rows(&current, Some(&previous), Duration::from_secs(2));
+        assert_eq!(
+            rows,
+            vec![
+                ComponentRow {
+                    component_id: "fetch".to_string(),
+                    totals: Totals {
+                        invocations: 25,
+                        failures: 3,
+                        total_duration_ms: 330,
+                    },
+                    calls_per_sec: 5.0,
+                    errors_per_sec: 1.0,
+                    recent_latency_ms: Some(18.0),
+                },
+                ComponentRow {
+                    component_id: "time".to_string(),
+                    totals: Totals {
+                        invocations: 1,
+                        failures: 0,
+                        total_duration_ms: 2,
+                    },
+                    calls_per_sec: 0.5,
+                    errors_per_sec: 0.0,
+                    recent_latency_ms: Some(2.0),
+                },
+                ComponentRow {
+                    component_id: "idle".to_string(),
+                    totals: Totals::default(),
+                    calls_per_sec: 0.0,
+                    errors_per_sec: 0.0,
+                    recent_latency_ms: None,
+                },
+            ]
+        );
+
+        // Without an earlier snapshot there are no rates yet
+        let rows = component_rows(&current, None, Duration::ZERO);
+        assert_eq!(rows[0].component_id, "fetch");
+        assert_eq!(rows[0].calls_per_sec, 0.0);
+        assert_eq!(rows[0].recent_latency_ms, None);
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(3725), "1h02m05s");
+    }
+}
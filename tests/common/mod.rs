@@ -2,8 +2,10 @@
 // Licensed under the MIT license.
 
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
 
 #[allow(dead_code)]
 pub async fn build_fetch_component() -> Result<PathBuf> {
@@ -62,3 +64,29 @@ pub async fn build_filesystem_component() -> Result<PathBuf> {
 
     Ok(component_path)
 }
+
+/// Reads the response to the request with `id` from the stdout of a server, skipping
+/// notifications. Sessions send notifications from a queue of their own, so they can arrive
+/// before or after the response to the request that caused them.
+#[allow(dead_code)]
+pub async fn read_response(
+    stdout: &mut (impl AsyncBufRead + Unpin),
+    id: u64,
+) -> Result<serde_json::Value> {
+    loop {
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(15), stdout.read_line(&mut line))
+            .await
+            .with_context(|| format!("Timeout waiting for the response to request {id}"))?
+            .with_context(|| format!("Failed to read the response to request {id}"))?;
+        let message: serde_json::Value = serde_json::from_str(&line)
+            .with_context(|| format!("Failed to parse the response to request {id}"))?;
+        if message["id"] == id {
+            return Ok(message);
+        }
+        anyhow::ensure!(
+            message["method"].is_string(),
+            "Expected the response to request {id}, got {message}"
+        );
+    }
+}
@@ -12,7 +12,7 @@ use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use wassette::LifecycleManager;
 
 mod common;
-use common::build_filesystem_component;
+use common::{build_filesystem_component, read_response};
 
 async fn cleanup_components(manager: &LifecycleManager) -> Result<()> {
     let component_ids = manager.list_components().await;
@@ -128,49 +128,14 @@ async fn test_filesystem_component_integration() -> Result<()> {
     stdin.write_all(load_component_request.as_bytes()).await?;
     stdin.flush().await?;
 
-    let mut load_response_line = String::new();
-    tokio::time::timeout(
-        Duration::from_secs(15),
-        stdout.read_line(&mut load_response_line),
-    )
-    .await
-    .context("Timeout waiting for load-component response")?
-    .context("Failed to read load-component response")?;
-
-    let load_response: serde_json::Value = serde_json::from_str(&load_response_line)
-        .context("Failed to parse load-component response")?;
-
-    // If we get a notification, read the next line for the actual response
-    let actual_load_response = if load_response["method"] == "notifications/tools/list_changed" {
-        load_response_line.clear();
-        tokio::time::timeout(
-            Duration::from_secs(15),
-            stdout.read_line(&mut load_response_line),
-        )
-        .await
-        .context("Timeout waiting for actual load-component response")?
-        .context("Failed to read actual load-component response")?;
-
-        let response: serde_json::Value = serde_json::from_str(&load_response_line)
-            .context("Failed to parse actual load-component response")?;
-
-        assert_eq!(response["jsonrpc"], "2.0");
-        assert_eq!(response["id"], 2);
-        response
-    } else {
-        assert_eq!(load_response["jsonrpc"], "2.0");
-        assert_eq!(load_response["id"], 2);
-        load_response
-    };
+    let load_response = read_response(&mut stdout, 2).await?;
+    assert_eq!(load_response["jsonrpc"], "2.0");
 
     // Check if the load succeeded
-    if actual_load_response["error"].is_object() {
-        panic!(
-            "Failed to load component: {}",
-            actual_load_response["error"]
-        );
+    if load_response["error"].is_object() {
+        panic!("Failed to load component: {}", load_response["error"]);
     }
-    assert!(actual_load_response["result"].is_object());
+    assert!(load_response["result"].is_object());
 
     let list_components_request = r#"{"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "list-components", "arguments": {}}, "id": 3}
 "#;
@@ -178,20 +143,8 @@ async fn test_filesystem_component_integration() -> Result<()> {
     stdin.write_all(list_components_request.as_bytes()).await?;
     stdin.flush().await?;
 
-    let mut list_response_line = String::new();
-    tokio::time::timeout(
-        Duration::from_secs(10),
-        stdout.read_line(&mut list_response_line),
-    )
-    .await
-    .context("Timeout waiting for list-components response")?
-    .context("Failed to read list-components response")?;
-
-    let list_response: serde_json::Value = serde_json::from_str(&list_response_line)
-        .context("Failed to parse list-components response")?;
-
+    let list_response = read_response(&mut stdout, 3).await?;
     assert_eq!(list_response["jsonrpc"], "2.0");
-    assert_eq!(list_response["id"], 3);
 
     // Parse the components response
     let components_text = list_response["result"]["content"].as_array().unwrap()[0]["text"]
@@ -204,7 +157,7 @@ async fn test_filesystem_component_integration() -> Result<()> {
 
     let components = components_data["components"].as_array().unwrap();
     assert_eq!(components.len(), 1);
-    assert_eq!(components[0]["id"], "filesystem");
+    assert_eq!(components[0]["id"], "local/filesystem");
 
     let project_dir = std::env::var("CARGO_MANIFEST_DIR").context("CARGO_MANIFEST_DIR not set")?;
 
@@ -216,20 +169,8 @@ async fn test_filesystem_component_integration() -> Result<()> {
     stdin.write_all(execute_request.as_bytes()).await?;
     stdin.flush().await?;
 
-    let mut execute_response_line = String::new();
-    tokio::time::timeout(
-        Duration::from_secs(10),
-        stdout.read_line(&mut execute_response_line),
-    )
-    .await
-    .context("Timeout waiting for execute response")?
-    .context("Failed to read execute response")?;
-
-    let execute_response: serde_json::Value =
-        serde_json::from_str(&execute_response_line).context("Failed to parse execute response")?;
-
+    let execute_response = read_response(&mut stdout, 4).await?;
     assert_eq!(execute_response["jsonrpc"], "2.0");
-    assert_eq!(execute_response["id"], 4);
 
     // The component returns a success response but with an error in the content
     assert!(execute_response["result"].is_object());
@@ -256,20 +197,8 @@ async fn test_filesystem_component_integration() -> Result<()> {
     stdin.write_all(grant_permission_request.as_bytes()).await?;
     stdin.flush().await?;
 
-    let mut grant_response_line = String::new();
-    tokio::time::timeout(
-        Duration::from_secs(10),
-        stdout.read_line(&mut grant_response_line),
-    )
-    .await
-    .context("Timeout waiting for grant-permission response")?
-    .context("Failed to read grant-permission response")?;
-
-    let grant_response: serde_json::Value = serde_json::from_str(&grant_response_line)
-        .context("Failed to parse grant-permission response")?;
-
+    let grant_response = read_response(&mut stdout, 5).await?;
     assert_eq!(grant_response["jsonrpc"], "2.0");
-    assert_eq!(grant_response["id"], 5);
     assert!(grant_response["result"].is_object());
 
     let get_policy_request = r#"{"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "get-policy", "arguments": {"component_id": "filesystem"}}, "id": 6}
@@ -278,20 +207,8 @@ async fn test_filesystem_component_integration() -> Result<()> {
     stdin.write_all(get_policy_request.as_bytes()).await?;
     stdin.flush().await?;
 
-    let mut policy_response_line = String::new();
-    tokio::time::timeout(
-        Duration::from_secs(10),
-        stdout.read_line(&mut policy_response_line),
-    )
-    .await
-    .context("Timeout waiting for get-policy response")?
-    .context("Failed to read get-policy response")?;
-
-    let policy_response: serde_json::Value = serde_json::from_str(&policy_response_line)
-        .context("Failed to parse get-policy response")?;
-
+    let policy_response = read_response(&mut stdout, 6).await?;
     assert_eq!(policy_response["jsonrpc"], "2.0");
-    assert_eq!(policy_response["id"], 6);
     assert!(policy_response["result"].is_object());
 
     // The get-policy response contains policy metadata, not the actual policy content
@@ -303,7 +220,7 @@ async fn test_filesystem_component_integration() -> Result<()> {
         serde_json::from_str(policy_info_text).context("Failed to parse policy info as JSON")?;
 
     // Verify the policy was created and has the expected metadata
-    assert_eq!(policy_info["component_id"], "filesystem");
+    assert_eq!(policy_info["component_id"], "local/filesystem");
     assert_eq!(policy_info["status"], "policy found");
     assert!(policy_info["policy_info"]["local_path"].is_string());
     assert!(policy_info["policy_info"]["policy_id"].is_string());
@@ -318,20 +235,8 @@ async fn test_filesystem_component_integration() -> Result<()> {
         .await?;
     stdin.flush().await?;
 
-    let mut final_response_line = String::new();
-    tokio::time::timeout(
-        Duration::from_secs(10),
-        stdout.read_line(&mut final_response_line),
-    )
-    .await
-    .context("Timeout waiting for final execute response")?
-    .context("Failed to read final execute response")?;
-
-    let final_response: serde_json::Value = serde_json::from_str(&final_response_line)
-        .context("Failed to parse final execute response")?;
-
+    let final_response = read_response(&mut stdout, 7).await?;
     assert_eq!(final_response["jsonrpc"], "2.0");
-    assert_eq!(final_response["id"], 7);
     assert!(final_response["result"].is_object());
 
     let content = final_response["result"]["content"].as_array().unwrap();
@@ -383,17 +288,13 @@ async fn test_filesystem_component_lifecycle_manager() -> Result<()> {
 
     let components_after_load = manager.list_components().await;
     assert_eq!(components_after_load.len(), 1);
-    assert_eq!(components_after_load[0], "filesystem");
+    assert_eq!(components_after_load[0], "local/filesystem");
 
     let schema = manager
         .get_component_schema(&id)
         .await
         .context("Component not found")?;
-    assert!(schema["tools"]
-        .as_array()
-        .unwrap()
-        .iter()
-        .any(|t| t["name"] == "list-directory"));
+    assert!(schema.tools.iter().any(|t| t.name == "list-directory"));
 
     let project_dir = std::env::var("CARGO_MANIFEST_DIR").context("CARGO_MANIFEST_DIR not set")?;
 
@@ -118,17 +118,13 @@ async fn test_fetch_component_workflow() -> Result<()> {
 
     let components_after_load = manager.list_components().await;
     assert_eq!(components_after_load.len(), 1);
-    assert_eq!(components_after_load[0], "fetch_rs");
+    assert_eq!(components_after_load[0], "local/fetch_rs");
 
     let schema = manager
         .get_component_schema(&id)
         .await
         .context("Component not found")?;
-    assert!(schema["tools"]
-        .as_array()
-        .unwrap()
-        .iter()
-        .any(|t| t["name"] == "fetch"));
+    assert!(schema.tools.iter().any(|t| t.name == "fetch"));
 
     let grant_result = manager
         .grant_permission(&id, "network", &serde_json::json!({"host": "example.com"}))
@@ -260,7 +256,8 @@ async fn test_load_component_from_https() -> Result<()> {
 
     // Verify component was loaded
     let components = manager.list_components().await;
-    assert!(components.contains(&"fetch_rs".to_string()));
+    assert!(id.ends_with("/fetch_rs"));
+    assert!(components.contains(&id));
 
     // Test calling the component
     let result = manager
@@ -329,11 +326,12 @@ async fn test_load_component_from_oci() -> Result<()> {
 
     // Load from OCI
     let oci_url = format!("oci://{reference}");
-    manager.load_component(&oci_url).await?;
+    let (id, _) = manager.load_component(&oci_url).await?;
 
     // Verify component was loaded
     let components = manager.list_components().await;
-    assert!(components.contains(&"fetch_rs".to_string()));
+    assert!(id.ends_with("/fetch_rs"));
+    assert!(components.contains(&id));
 
     Ok(())
 }
@@ -487,6 +485,7 @@ async fn test_stdio_transport() -> Result<()> {
     assert!(response["result"]["capabilities"]["tools"]["listChanged"]
         .as_bool()
         .unwrap_or(false));
+    assert!(response["result"]["capabilities"]["logging"].is_object());
 
     // Send initialized notification (required by MCP protocol)
     let initialized_notification = r#"{"jsonrpc": "2.0", "method": "notifications/initialized", "params": {}}
@@ -670,37 +669,30 @@ async fn test_tool_list_notification() -> Result<()> {
     stdin.write_all(load_component_request.as_bytes()).await?;
     stdin.flush().await?;
 
-    // Read the tool list change notification first (this is what we're testing!)
-    let mut notification_line = String::new();
-    tokio::time::timeout(
-        Duration::from_secs(15),
-        stdout.read_line(&mut notification_line),
-    )
-    .await
-    .context("Timeout waiting for tool list change notification")?
-    .context("Failed to read tool list change notification")?;
-
-    let notification: serde_json::Value = serde_json::from_str(&notification_line)
-        .context("Failed to parse tool list change notification")?;
+    // The notification is sent from the notification queue of the session, so it can arrive
+    // before or after the load-component response
+    let mut notification = serde_json::Value::Null;
+    let mut load_response = serde_json::Value::Null;
+    while notification.is_null() || load_response.is_null() {
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(15), stdout.read_line(&mut line))
+            .await
+            .context("Timeout waiting for the notification and load-component response")?
+            .context("Failed to read the notification or load-component response")?;
+        let message: serde_json::Value = serde_json::from_str(&line)
+            .context("Failed to parse the notification or load-component response")?;
+        if message["method"].is_string() {
+            notification = message;
+        } else {
+            load_response = message;
+        }
+    }
 
-    // Verify we received a tools/list_changed notification
+    // Verify we received a tools/list_changed notification (this is what we're testing!)
     assert_eq!(notification["jsonrpc"], "2.0");
     assert_eq!(notification["method"], "notifications/tools/list_changed");
     println!("✓ Received tools/list_changed notification as expected");
 
-    // Read the actual load-component response
-    let mut load_response_line = String::new();
-    tokio::time::timeout(
-        Duration::from_secs(15),
-        stdout.read_line(&mut load_response_line),
-    )
-    .await
-    .context("Timeout waiting for load-component response")?
-    .context("Failed to read load-component response")?;
-
-    let load_response: serde_json::Value = serde_json::from_str(&load_response_line)
-        .context("Failed to parse load-component response")?;
-
     assert_eq!(load_response["jsonrpc"], "2.0");
     assert_eq!(load_response["id"], 3);
 